@@ -0,0 +1,330 @@
+//!
+//! Detection of node ID conflicts
+//!
+//! Node IDs are usually configured by hand, and two nodes that are configured with the same ID
+//! cannot be told apart by their transfers. A [`ConflictDetector`] watches `uavcan.node.Heartbeat`
+//! messages and reports a conflict when:
+//!
+//! * A heartbeat arrives from another node that uses this node's ID, or
+//! * The heartbeats from one node ID are not consistent with one node sending them: the uptime
+//!   goes backwards by more than a restart would explain, the uptime jumps forwards by more
+//!   than the time between heartbeats, or the transfer IDs repeatedly do not follow on from each
+//!   other
+//!
+//! Some conflicts cannot be detected. If two nodes with the same ID send identical heartbeats with
+//! the same transfer ID at almost the same time, the receiver discards one of each pair of
+//! heartbeats as a duplicate.
+//!
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use canadensis_core::time::{milliseconds, MicrosecondDuration32, Microseconds32};
+use canadensis_core::transfer::MessageTransfer;
+use canadensis_core::transport::{Receiver, TransferId, Transport};
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_encoding::{DataType, Deserialize};
+
+use crate::health::HealthMonitor;
+use crate::{Node, StartSendError, TransferHandler};
+
+/// The number of seconds that an uptime can differ from the elapsed time before it counts as
+/// evidence of a conflict
+///
+/// Uptimes are rounded down to whole seconds and heartbeats are not published exactly once per
+/// second, so a small difference is normal.
+const UPTIME_TOLERANCE: u32 = 2;
+/// The number of consecutive heartbeats with unexpected transfer IDs that count as a conflict
+///
+/// A single unexpected transfer ID usually means that a heartbeat was lost.
+const DISCONTINUITY_THRESHOLD: u8 = 3;
+/// The transfer-ID timeout for heartbeat subscriptions
+///
+/// Two nodes with the same ID that started at about the same time send heartbeats with the same
+/// transfer IDs. With the usual one-second timeout, the receiver would discard the heartbeats
+/// from one of them as duplicates.
+const HEARTBEAT_TRANSFER_ID_TIMEOUT: MicrosecondDuration32 = milliseconds(100);
+
+/// A boxed function that is called when a conflict is detected
+type Callback<I> = Box<dyn FnMut(&Conflict<I>)>;
+
+/// Watches heartbeats and detects when more than one node uses the same node ID
+///
+/// A conflict stays active until `uavcan.node.Heartbeat.OFFLINE_TIMEOUT` seconds after the
+/// latest heartbeat that showed evidence of it.
+///
+/// To use a detector, pass the detector's [`handler`](#method.handler) to
+/// [`Node::receive`](crate::Node::receive) and call [`poll`](#method.poll) periodically.
+/// The handler does not consume heartbeats, so it should come before any other handlers that
+/// use heartbeats (for example, a [`NodeMonitor`](crate::monitor::NodeMonitor) handler) in a
+/// chain.
+///
+/// An application that already subscribes to heartbeats can create a detector with
+/// [`without_subscription`](#method.without_subscription) and pass each heartbeat to
+/// [`handle_heartbeat`](#method.handle_heartbeat) instead.
+pub struct ConflictDetector<N: Node> {
+    /// The latest heartbeat from each remote node ID
+    sources: BTreeMap<u16, Source<<N::Transport as Transport>::TransferId>>,
+    /// The active conflicts, and the time after which each one expires
+    conflicts: Vec<(
+        Conflict<<N::Transport as Transport>::NodeId>,
+        Microseconds32,
+    )>,
+    /// The function to call when a conflict is detected
+    callback: Option<Callback<<N::Transport as Transport>::NodeId>>,
+}
+
+impl<N> ConflictDetector<N>
+where
+    N: Node,
+    <N::Transport as Transport>::TransferId: PartialEq,
+{
+    /// Creates a conflict detector and subscribes to heartbeat messages
+    ///
+    /// The heartbeat subscription uses a transfer-ID timeout of 100 milliseconds. If something
+    /// else subscribes to heartbeats after this function is called, it should use the same
+    /// timeout.
+    pub fn new(node: &mut N) -> Result<Self, NewError<N>> {
+        node.subscribe_message(
+            heartbeat_1_0::SUBJECT,
+            Heartbeat::EXTENT_BYTES.unwrap() as usize,
+            HEARTBEAT_TRANSFER_ID_TIMEOUT,
+        )?;
+        Ok(Self::without_subscription())
+    }
+
+    /// Creates a conflict detector that does not subscribe to heartbeat messages
+    ///
+    /// The application must subscribe to heartbeats and pass each heartbeat transfer to
+    /// [`handle_heartbeat`](#method.handle_heartbeat). The subscription should use a
+    /// transfer-ID timeout of 100 milliseconds or less. With a longer timeout, the receiver
+    /// discards the heartbeats from one of two nodes with the same ID that started at about the
+    /// same time, and this detector can't detect the conflict from their transfer IDs.
+    pub fn without_subscription() -> Self {
+        ConflictDetector {
+            sources: BTreeMap::new(),
+            conflicts: Vec::new(),
+            callback: None,
+        }
+    }
+
+    /// Sets a function that will be called when a conflict is detected
+    ///
+    /// The function is called once when each conflict becomes active. If a conflict expires and
+    /// is detected again, the function is called again.
+    pub fn with_callback<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&Conflict<<N::Transport as Transport>::NodeId>) + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Returns a handler that updates this detector with incoming heartbeats
+    pub fn handler(&mut self) -> ConflictDetectorHandler<'_, N> {
+        ConflictDetectorHandler { detector: self }
+    }
+
+    /// Removes conflicts and heartbeat sources that have expired, and updates a health monitor
+    ///
+    /// While any conflict is active, the health monitor reports the health from its
+    /// [`TransportChecks::conflict_health`](crate::health::TransportChecks::conflict_health)
+    /// setting (`Health::WARNING` by default).
+    ///
+    /// This function should be called at least once per second.
+    pub fn poll(&mut self, now: Microseconds32, health: &mut HealthMonitor) {
+        self.conflicts.retain(|(_, deadline)| now <= *deadline);
+        self.sources.retain(|_, source| now <= source.deadline);
+        health.set_node_id_conflict(!self.conflicts.is_empty());
+    }
+
+    /// Returns an iterator over the active conflicts, in the order they were detected
+    pub fn conflicts(
+        &self,
+    ) -> impl Iterator<Item = &Conflict<<N::Transport as Transport>::NodeId>> + '_ {
+        self.conflicts.iter().map(|(conflict, _)| conflict)
+    }
+
+    /// Returns true if this node's ID is in use by another node
+    pub fn is_own_node_id_conflict(&self) -> bool {
+        self.conflicts()
+            .any(|conflict| *conflict == Conflict::OwnNodeId)
+    }
+
+    /// Updates this detector with a heartbeat transfer
+    ///
+    /// * `own_id`: The node ID of the local node, if it has one
+    /// * `transfer`: A received transfer on the `uavcan.node.Heartbeat` subject
+    ///
+    /// Transfers on other subjects, anonymous transfers, and transfers that can't be
+    /// deserialized are ignored.
+    pub fn handle_heartbeat(
+        &mut self,
+        own_id: Option<<N::Transport as Transport>::NodeId>,
+        transfer: &MessageTransfer<Vec<u8>, N::Transport>,
+    ) {
+        if transfer.header.subject != heartbeat_1_0::SUBJECT {
+            return;
+        }
+        let source = match &transfer.header.source {
+            Some(source) => source.clone(),
+            // Anonymous nodes do not publish heartbeats
+            None => return,
+        };
+        if let Ok(heartbeat) = Heartbeat::deserialize_from_bytes(&transfer.payload) {
+            self.update_source(
+                own_id,
+                source,
+                transfer.header.timestamp,
+                transfer.header.transfer_id.clone(),
+                heartbeat,
+            );
+        }
+    }
+
+    fn update_source(
+        &mut self,
+        own_id: Option<<N::Transport as Transport>::NodeId>,
+        source: <N::Transport as Transport>::NodeId,
+        timestamp: Microseconds32,
+        transfer_id: <N::Transport as Transport>::TransferId,
+        heartbeat: Heartbeat,
+    ) {
+        // Heartbeats that this node sends are loopback transfers, which do not get here.
+        if own_id.as_ref() == Some(&source) {
+            self.report(Conflict::OwnNodeId, timestamp);
+            return;
+        }
+        let key: u16 = source.clone().into();
+        let evidence = match self.sources.get_mut(&key) {
+            Some(previous) => previous.update(timestamp, transfer_id, heartbeat.uptime),
+            None => {
+                self.sources.insert(
+                    key,
+                    Source {
+                        uptime: heartbeat.uptime,
+                        transfer_id,
+                        timestamp,
+                        deadline: expiry(timestamp),
+                        discontinuities: 0,
+                    },
+                );
+                false
+            }
+        };
+        if evidence {
+            self.report(Conflict::RemoteNodeId(source), timestamp);
+        }
+    }
+
+    /// Records evidence of a conflict and calls the callback if the conflict is new
+    fn report(
+        &mut self,
+        conflict: Conflict<<N::Transport as Transport>::NodeId>,
+        now: Microseconds32,
+    ) {
+        let deadline = expiry(now);
+        if let Some(active) = self
+            .conflicts
+            .iter_mut()
+            .find(|(active, _)| *active == conflict)
+        {
+            active.1 = deadline;
+            return;
+        }
+        l0g::warn!("Node ID conflict detected");
+        if let Some(callback) = &mut self.callback {
+            callback(&conflict);
+        }
+        self.conflicts.push((conflict, deadline));
+    }
+}
+
+/// Returns the time when a heartbeat or conflict at `time` expires
+fn expiry(time: Microseconds32) -> Microseconds32 {
+    time + milliseconds(u32::from(Heartbeat::OFFLINE_TIMEOUT) * 1000)
+}
+
+/// Error type returned by [`ConflictDetector::new`]
+pub type NewError<N> =
+    StartSendError<<<N as Node>::Receiver as Receiver<<N as Node>::Clock>>::Error>;
+
+/// A node ID that more than one node is using
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Conflict<I> {
+    /// Another node is using this node's ID
+    OwnNodeId,
+    /// More than one other node is using this node ID
+    RemoteNodeId(I),
+}
+
+/// The latest heartbeat from a remote node ID
+struct Source<T> {
+    uptime: u32,
+    transfer_id: T,
+    /// The time when the latest heartbeat was received
+    timestamp: Microseconds32,
+    /// The time after which this source is forgotten
+    deadline: Microseconds32,
+    /// The number of consecutive heartbeats with unexpected transfer IDs
+    discontinuities: u8,
+}
+
+impl<T: TransferId + PartialEq> Source<T> {
+    /// Updates this source with a new heartbeat and returns true if the heartbeat is evidence of
+    /// a conflict
+    fn update(&mut self, timestamp: Microseconds32, transfer_id: T, uptime: u32) -> bool {
+        let elapsed_micros = timestamp.ticks().wrapping_sub(self.timestamp.ticks());
+        let elapsed_seconds = elapsed_micros.div_ceil(1_000_000);
+
+        let evidence = if uptime < self.uptime {
+            if uptime > elapsed_seconds + UPTIME_TOLERANCE {
+                // The uptime went backwards, but not far enough for the node to have restarted
+                // since the previous heartbeat
+                true
+            } else {
+                // The node restarted, so its transfer IDs also restarted
+                self.discontinuities = 0;
+                false
+            }
+        } else if uptime - self.uptime > elapsed_seconds + UPTIME_TOLERANCE {
+            // The uptime increased by more than the time since the previous heartbeat
+            true
+        } else if transfer_id != self.transfer_id.clone().increment() {
+            self.discontinuities = self.discontinuities.saturating_add(1);
+            self.discontinuities >= DISCONTINUITY_THRESHOLD
+        } else {
+            self.discontinuities = 0;
+            false
+        };
+
+        self.uptime = uptime;
+        self.transfer_id = transfer_id;
+        self.timestamp = timestamp;
+        self.deadline = expiry(timestamp);
+        evidence
+    }
+}
+
+/// A handler that updates a [`ConflictDetector`] with incoming heartbeats
+pub struct ConflictDetectorHandler<'a, N: Node> {
+    detector: &'a mut ConflictDetector<N>,
+}
+
+impl<N> TransferHandler<N::Transport> for ConflictDetectorHandler<'_, N>
+where
+    N: Node,
+    <N::Transport as Transport>::TransferId: PartialEq,
+{
+    fn handle_message<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        transfer: &MessageTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        self.detector.handle_heartbeat(node.node_id(), transfer);
+        // Other handlers may also need this heartbeat
+        false
+    }
+}
@@ -0,0 +1,316 @@
+//!
+//! Automatic health reporting for the local node
+//!
+//! A [`HealthMonitor`] decides what health and vendor-specific status code the local node reports
+//! in its heartbeats. The health is the most severe of:
+//!
+//! * The health that the application sets with [`HealthMonitor::set_health`]
+//! * The health of each watchdog that has not been kicked within its timeout
+//! * The health for a congested transmitter, when heartbeats could not be published for
+//!   several consecutive seconds (usually because the transmit queue is full)
+//! * The health for transport errors, when the number of transfers that failed to send or
+//!   receive during the last second is above a threshold
+//! * The health for a node ID conflict, when a
+//!   [`ConflictDetector`](crate::conflict::ConflictDetector) has found another node using the
+//!   same node ID
+//!
+//! [`HeartbeatService`](crate::service::heartbeat::HeartbeatService) and
+//! [`MinimalNode`](crate::node::MinimalNode) (and [`BasicNode`](crate::node::BasicNode)) each
+//! contain a health monitor and update it before publishing each heartbeat.
+//!
+
+use alloc::vec::Vec;
+
+use canadensis_core::statistics::TransportStatistics;
+use canadensis_core::time::{MicrosecondDuration32, Microseconds32};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+
+/// Identifies a watchdog in a [`HealthMonitor`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct WatchdogId(usize);
+
+/// A watchdog that a subsystem of the application must kick periodically
+#[derive(Debug)]
+struct Watchdog {
+    timeout: MicrosecondDuration32,
+    /// The health value to report when this watchdog has lapsed
+    health: u8,
+    /// The status code to report when this watchdog has lapsed
+    status_code: u8,
+    last_kick: Microseconds32,
+    lapsed: bool,
+}
+
+/// Settings for the checks that use the transmitter, the transport statistics, and node ID
+/// conflict detection
+#[derive(Debug, Clone)]
+pub struct TransportChecks {
+    /// The number of consecutive heartbeats that must fail to publish before the transmitter is
+    /// considered congested
+    ///
+    /// If this is zero, transmitter congestion is not checked.
+    ///
+    /// Default: 3
+    pub congested_heartbeats: u8,
+    /// The health value (for example, `Health::CAUTION`) to report when the transmitter is
+    /// congested
+    ///
+    /// Default: `Health::CAUTION`
+    pub congested_health: u8,
+    /// The status code to report when the transmitter is congested
+    ///
+    /// Default: 0
+    pub congested_status_code: u8,
+    /// The number of transfer errors during one second that is considered an error spike
+    ///
+    /// This uses the `transfers.errored` counter from
+    /// [`Node::transport_statistics`](crate::Node::transport_statistics), which usually includes
+    /// transfers that could not be sent and transfers that could not be received (for example,
+    /// because of a CRC error or a missing frame).
+    ///
+    /// If this is zero, transport errors are not checked.
+    ///
+    /// Default: 10
+    pub errors_per_second: u64,
+    /// The health value (for example, `Health::ADVISORY`) to report when the error rate is
+    /// above `errors_per_second`
+    ///
+    /// Default: `Health::ADVISORY`
+    pub error_health: u8,
+    /// The status code to report when the error rate is above `errors_per_second`
+    ///
+    /// Default: 0
+    pub error_status_code: u8,
+    /// The health value to report when a node ID conflict has been detected
+    ///
+    /// Default: `Health::WARNING`
+    pub conflict_health: u8,
+    /// The status code to report when a node ID conflict has been detected
+    ///
+    /// Default: 0
+    pub conflict_status_code: u8,
+}
+
+impl Default for TransportChecks {
+    fn default() -> Self {
+        TransportChecks {
+            congested_heartbeats: 3,
+            congested_health: Health::CAUTION,
+            congested_status_code: 0,
+            errors_per_second: 10,
+            error_health: Health::ADVISORY,
+            error_status_code: 0,
+            conflict_health: Health::WARNING,
+            conflict_status_code: 0,
+        }
+    }
+}
+
+/// Combines the health set by the application with watchdogs and transport checks
+#[derive(Debug)]
+pub struct HealthMonitor {
+    /// The health value set by the application
+    health: u8,
+    /// The status code set by the application
+    status_code: u8,
+    watchdogs: Vec<Watchdog>,
+    checks: TransportChecks,
+    /// The number of consecutive heartbeats that could not be published
+    failed_heartbeats: u8,
+    /// The transport error counter at the previous update, and the time of that update
+    previous_errors: Option<(u64, Microseconds32)>,
+    /// True if the error rate during the previous second was too high
+    error_spike: bool,
+    /// True if a node ID conflict has been detected
+    node_id_conflict: bool,
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        HealthMonitor::new()
+    }
+}
+
+impl HealthMonitor {
+    /// Creates a monitor with nominal health, no watchdogs, and the default transport checks
+    pub fn new() -> Self {
+        HealthMonitor {
+            health: Health::NOMINAL,
+            status_code: 0,
+            watchdogs: Vec::new(),
+            checks: TransportChecks::default(),
+            failed_heartbeats: 0,
+            previous_errors: None,
+            error_spike: false,
+            node_id_conflict: false,
+        }
+    }
+
+    /// Sets the health that the application reports
+    ///
+    /// The reported health may be more severe than this because of watchdogs or transport checks.
+    pub fn set_health(&mut self, health: Health) {
+        self.health = health.value;
+    }
+
+    /// Sets the vendor-specific status code that the application reports
+    ///
+    /// This code is reported when the health set by the application is at least as severe as
+    /// the health from all watchdogs and transport checks.
+    pub fn set_status_code(&mut self, status_code: u8) {
+        self.status_code = status_code;
+    }
+
+    /// Changes the settings for the transport checks
+    pub fn set_transport_checks(&mut self, checks: TransportChecks) {
+        self.checks = checks;
+    }
+
+    /// Adds a watchdog and returns its ID
+    ///
+    /// * `now`: The current time. The watchdog counts as kicked at this time.
+    /// * `timeout`: The maximum time between kicks
+    /// * `health`: The health to report when more than `timeout` has passed since the latest kick
+    /// * `status_code`: The vendor-specific status code to report when the watchdog has lapsed
+    pub fn add_watchdog(
+        &mut self,
+        now: Microseconds32,
+        timeout: MicrosecondDuration32,
+        health: Health,
+        status_code: u8,
+    ) -> WatchdogId {
+        self.watchdogs.push(Watchdog {
+            timeout,
+            health: health.value,
+            status_code,
+            last_kick: now,
+            lapsed: false,
+        });
+        WatchdogId(self.watchdogs.len() - 1)
+    }
+
+    /// Records that the subsystem watched by a watchdog is still working
+    ///
+    /// A watchdog that has lapsed stops affecting the reported health at the next update after
+    /// it is kicked.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `watchdog` did not come from this monitor.
+    pub fn kick(&mut self, watchdog: WatchdogId, now: Microseconds32) {
+        self.watchdogs[watchdog.0].last_kick = now;
+    }
+
+    /// Returns true if a watchdog had lapsed at the most recent update
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `watchdog` did not come from this monitor.
+    pub fn is_lapsed(&self, watchdog: WatchdogId) -> bool {
+        self.watchdogs[watchdog.0].lapsed
+    }
+
+    /// Returns true if the most recent heartbeats could not be published, which usually means
+    /// that the transmit queue is full
+    pub fn is_congested(&self) -> bool {
+        self.checks.congested_heartbeats != 0
+            && self.failed_heartbeats >= self.checks.congested_heartbeats
+    }
+
+    /// Returns true if there were too many transport errors during the second before the most
+    /// recent update
+    pub fn is_error_spike(&self) -> bool {
+        self.error_spike
+    }
+
+    /// Sets whether a node ID conflict has been detected
+    ///
+    /// [`ConflictDetector::poll`](crate::conflict::ConflictDetector::poll) calls this function.
+    pub fn set_node_id_conflict(&mut self, conflict: bool) {
+        self.node_id_conflict = conflict;
+    }
+
+    /// Returns true if a node ID conflict has been detected
+    pub fn is_node_id_conflict(&self) -> bool {
+        self.node_id_conflict
+    }
+
+    /// Updates the watchdogs and the error rate
+    ///
+    /// This should be called once per second, just before publishing a heartbeat.
+    pub fn update(&mut self, now: Microseconds32, statistics: &TransportStatistics) {
+        for watchdog in &mut self.watchdogs {
+            let since_kick = now.ticks().wrapping_sub(watchdog.last_kick.ticks());
+            watchdog.lapsed = since_kick > watchdog.timeout.ticks();
+        }
+
+        let errors = statistics.transfers.errored;
+        self.error_spike = match self.previous_errors {
+            // If the error count went down, the statistics have been reset. The new count
+            // becomes the baseline.
+            Some((previous, previous_time))
+                if self.checks.errors_per_second != 0 && errors >= previous =>
+            {
+                let new_errors = errors - previous;
+                let elapsed = u64::from(now.ticks().wrapping_sub(previous_time.ticks())).max(1);
+                new_errors.saturating_mul(1_000_000) / elapsed >= self.checks.errors_per_second
+            }
+            _ => false,
+        };
+        self.previous_errors = Some((errors, now));
+    }
+
+    /// Records whether the most recent heartbeat was published
+    pub fn record_heartbeat(&mut self, published: bool) {
+        if published {
+            self.failed_heartbeats = 0;
+        } else {
+            self.failed_heartbeats = self.failed_heartbeats.saturating_add(1);
+        }
+    }
+
+    /// Returns the health to report, based on the most recent update
+    pub fn health(&self) -> Health {
+        Health {
+            value: self.most_severe().0,
+        }
+    }
+
+    /// Returns the vendor-specific status code to report, based on the most recent update
+    ///
+    /// This is the status code that goes with the most severe health. If more than one source
+    /// has the most severe health, the application's status code has priority, then the
+    /// watchdogs in the order they were added, then the transport checks.
+    pub fn status_code(&self) -> u8 {
+        self.most_severe().1
+    }
+
+    /// Returns the most severe health value and its status code
+    fn most_severe(&self) -> (u8, u8) {
+        let mut most_severe = (self.health, self.status_code);
+        let mut consider = |health: u8, status_code: u8| {
+            if health > most_severe.0 {
+                most_severe = (health, status_code);
+            }
+        };
+        for watchdog in self.watchdogs.iter().filter(|watchdog| watchdog.lapsed) {
+            consider(watchdog.health, watchdog.status_code);
+        }
+        if self.is_congested() {
+            consider(
+                self.checks.congested_health,
+                self.checks.congested_status_code,
+            );
+        }
+        if self.error_spike {
+            consider(self.checks.error_health, self.checks.error_status_code);
+        }
+        if self.node_id_conflict {
+            consider(
+                self.checks.conflict_health,
+                self.checks.conflict_status_code,
+            );
+        }
+        most_severe
+    }
+}
@@ -11,8 +11,12 @@
 extern crate alloc;
 extern crate fallible_collections;
 extern crate heapless;
+#[cfg(feature = "std")]
+extern crate std;
 
 extern crate canadensis_core;
+#[cfg(feature = "app-descriptor")]
+extern crate canadensis_crc;
 extern crate canadensis_encoding;
 
 // Re-exports from other crates
@@ -27,7 +31,11 @@ pub mod encoding {
 pub use canadensis_core::nb;
 
 pub mod anonymous;
+pub mod conflict;
+pub mod health;
+pub mod monitor;
 pub mod node;
+pub mod pending;
 mod publisher;
 pub mod register;
 pub mod requester;
@@ -40,6 +48,7 @@ use alloc::vec::Vec;
 use canadensis_core::{OutOfMemoryError, ServiceSubscribeError};
 
 use crate::core::transport::Transport;
+use canadensis_core::statistics::TransportStatistics;
 use canadensis_core::time::{Clock, MicrosecondDuration32};
 use canadensis_core::transfer::*;
 use canadensis_core::transport::{Receiver, Transmitter};
@@ -529,6 +538,16 @@ pub trait Node {
 
     /// Returns an iterator over the services provided by this node
     fn servers(&self) -> impl Iterator<Item = ServiceId>;
+
+    /// Returns the numbers of transfers and frames that this node has sent, received, and failed
+    /// to send or receive
+    ///
+    /// The frame counts for each network interface come from the driver if it counts frames.
+    ///
+    /// The default implementation returns statistics with all counts set to zero.
+    fn transport_statistics(&self) -> TransportStatistics {
+        TransportStatistics::default()
+    }
 }
 
 /// Errors that may occur when publishing a message
@@ -0,0 +1,423 @@
+//!
+//! Monitoring of the other nodes on a network
+//!
+//! A [`NodeMonitor`] subscribes to `uavcan.node.Heartbeat` and `uavcan.node.port.List` messages
+//! and keeps a table of the nodes that are online. It can also send a `uavcan.node.GetInfo`
+//! request to each node that appears or restarts.
+//!
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use canadensis_core::time::{milliseconds, Clock, MicrosecondDuration32, Microseconds32};
+use canadensis_core::transfer::{MessageTransfer, ServiceTransfer};
+use canadensis_core::transport::{Receiver, Transport};
+use canadensis_core::{Priority, ServiceId, SubjectId};
+use canadensis_data_types::uavcan::node::get_info_1_0::{self, GetInfoRequest, GetInfoResponse};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use canadensis_data_types::uavcan::node::port::list_1_0::{self, List};
+use canadensis_data_types::uavcan::node::port::service_id_list_1_0::ServiceIDList;
+use canadensis_data_types::uavcan::node::port::subject_id_list_1_0::SubjectIDList;
+use canadensis_data_types::uavcan::node::version_1_0::Version;
+use canadensis_encoding::{DataType, Deserialize};
+
+use crate::{Node, ServiceToken, StartSendError, TransferHandler};
+
+/// The maximum size of a `uavcan.node.port.List.1.0` message
+const PORT_LIST_SIZE_MAX: usize = 2194;
+/// The time to wait for a GetInfo response before sending the request again
+const INFO_RETRY_INTERVAL: MicrosecondDuration32 = milliseconds(1000);
+/// The number of GetInfo requests to send to a node before giving up
+const INFO_ATTEMPTS: u8 = 3;
+
+/// Keeps track of the other nodes on the network
+///
+/// A node is online from when the monitor receives its first heartbeat until
+/// `uavcan.node.Heartbeat.OFFLINE_TIMEOUT` seconds after its latest heartbeat.
+///
+/// To use a monitor, pass the monitor's [`handler`](#method.handler) (or a chain that includes it)
+/// to [`Node::receive`](crate::Node::receive), call [`poll`](#method.poll) periodically,
+/// and call [`next_event`](#method.next_event) to find out about nodes that appeared,
+/// restarted, or went offline.
+pub struct NodeMonitor<N: Node> {
+    /// The nodes that are online, indexed by node ID
+    nodes: BTreeMap<u16, NodeStatus<<N::Transport as Transport>::NodeId>>,
+    /// Events that have not yet been returned from next_event()
+    events: VecDeque<MonitorEvent<<N::Transport as Transport>::NodeId>>,
+    /// The token used to send GetInfo requests, if GetInfo queries are enabled
+    get_info: Option<ServiceToken<GetInfoRequest>>,
+}
+
+impl<N: Node> NodeMonitor<N> {
+    /// Creates a node monitor and subscribes to heartbeat and port list messages
+    ///
+    /// * `node`: The node to use for receiving messages and sending requests
+    /// * `query_info`: If this is true, the monitor sends a `uavcan.node.GetInfo` request to
+    ///   each node that appears or restarts. This requires a node that is not anonymous.
+    pub fn new(node: &mut N, query_info: bool) -> Result<Self, NewError<N>> {
+        node.subscribe_message(
+            heartbeat_1_0::SUBJECT,
+            Heartbeat::EXTENT_BYTES.unwrap() as usize,
+            milliseconds(1000),
+        )?;
+        node.subscribe_message(list_1_0::SUBJECT, PORT_LIST_SIZE_MAX, milliseconds(1000))?;
+        let get_info = if query_info {
+            Some(node.start_sending_requests(
+                get_info_1_0::SERVICE,
+                milliseconds(1000),
+                GetInfoResponse::EXTENT_BYTES.unwrap() as usize,
+                Priority::Optional.into(),
+            )?)
+        } else {
+            None
+        };
+
+        Ok(NodeMonitor {
+            nodes: BTreeMap::new(),
+            events: VecDeque::new(),
+            get_info,
+        })
+    }
+
+    /// Returns a handler that updates this monitor with incoming transfers
+    pub fn handler(&mut self) -> NodeMonitorHandler<'_, N> {
+        NodeMonitorHandler { monitor: self }
+    }
+
+    /// Removes nodes that have gone offline and sends any pending GetInfo requests
+    ///
+    /// This function should be called at least once per second.
+    pub fn poll(&mut self, node: &mut N) {
+        let now = node.clock_mut().now();
+
+        let events = &mut self.events;
+        self.nodes.retain(|_, status| {
+            if now > status.offline_deadline {
+                events.push_back(MonitorEvent::Offline(status.node_id.clone()));
+                false
+            } else {
+                true
+            }
+        });
+
+        if let Some(token) = &self.get_info {
+            for status in self.nodes.values_mut() {
+                let due = match status.info_request {
+                    InfoRequest::Due(time) => now >= time,
+                    InfoRequest::Done => false,
+                };
+                if !due {
+                    continue;
+                }
+                status.info_attempts += 1;
+                status.info_request = if status.info_attempts < INFO_ATTEMPTS {
+                    InfoRequest::Due(now + INFO_RETRY_INTERVAL)
+                } else {
+                    InfoRequest::Done
+                };
+                // If the request can't be sent now, it will be sent again later.
+                let _ = node.send_request(token, &GetInfoRequest {}, status.node_id.clone());
+            }
+        }
+    }
+
+    /// Returns and removes the oldest event that has not been returned yet
+    pub fn next_event(&mut self) -> Option<MonitorEvent<<N::Transport as Transport>::NodeId>> {
+        self.events.pop_front()
+    }
+
+    /// Returns the status of a node, if it is online
+    pub fn node(
+        &self,
+        node_id: <N::Transport as Transport>::NodeId,
+    ) -> Option<&NodeStatus<<N::Transport as Transport>::NodeId>> {
+        self.nodes.get(&node_id.into())
+    }
+
+    /// Returns an iterator over the nodes that are online, in order of increasing node ID
+    pub fn nodes(
+        &self,
+    ) -> impl Iterator<Item = &NodeStatus<<N::Transport as Transport>::NodeId>> + '_ {
+        self.nodes.values()
+    }
+
+    fn handle_heartbeat(
+        &mut self,
+        node_id: <N::Transport as Transport>::NodeId,
+        timestamp: Microseconds32,
+        heartbeat: Heartbeat,
+    ) {
+        let offline_deadline =
+            timestamp + milliseconds(u32::from(Heartbeat::OFFLINE_TIMEOUT) * 1000);
+        let info_request = if self.get_info.is_some() {
+            InfoRequest::Due(timestamp)
+        } else {
+            InfoRequest::Done
+        };
+        let key: u16 = node_id.clone().into();
+        if !self.nodes.contains_key(&key) {
+            self.events
+                .push_back(MonitorEvent::Appeared(node_id.clone()));
+            self.nodes.insert(
+                key,
+                NodeStatus {
+                    node_id: node_id.clone(),
+                    uptime: heartbeat.uptime,
+                    health: Health {
+                        value: heartbeat.health.value,
+                    },
+                    mode: Mode {
+                        value: heartbeat.mode.value,
+                    },
+                    vendor_specific_status_code: heartbeat.vendor_specific_status_code,
+                    last_heartbeat: timestamp,
+                    offline_deadline,
+                    info: None,
+                    info_request,
+                    info_attempts: 0,
+                    ports: None,
+                },
+            );
+        }
+        let status = self.nodes.get_mut(&key).expect("Node status not inserted");
+
+        if heartbeat.uptime < status.uptime {
+            // The uptime leaped backwards, so the node has restarted and may be running
+            // different software.
+            self.events.push_back(MonitorEvent::Restarted(node_id));
+            status.info = None;
+            status.info_request = info_request;
+            status.info_attempts = 0;
+            status.ports = None;
+        }
+        status.uptime = heartbeat.uptime;
+        status.health = heartbeat.health;
+        status.mode = heartbeat.mode;
+        status.vendor_specific_status_code = heartbeat.vendor_specific_status_code;
+        status.last_heartbeat = timestamp;
+        status.offline_deadline = offline_deadline;
+    }
+}
+
+/// Error type returned by [`NodeMonitor::new`]
+pub type NewError<N> =
+    StartSendError<<<N as Node>::Receiver as Receiver<<N as Node>::Clock>>::Error>;
+
+/// Something that happened to a node on the network
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MonitorEvent<I> {
+    /// A node sent its first heartbeat, or sent a heartbeat after going offline
+    Appeared(I),
+    /// A node sent a heartbeat with a smaller uptime than its previous heartbeat
+    Restarted(I),
+    /// A node has not sent a heartbeat for `uavcan.node.Heartbeat.OFFLINE_TIMEOUT` seconds
+    /// and has been removed from the table
+    Offline(I),
+}
+
+/// What the monitor knows about a node that is online
+pub struct NodeStatus<I> {
+    node_id: I,
+    uptime: u32,
+    health: Health,
+    mode: Mode,
+    vendor_specific_status_code: u8,
+    /// The time when the latest heartbeat was received
+    last_heartbeat: Microseconds32,
+    /// The time after which the node is considered offline
+    offline_deadline: Microseconds32,
+    info: Option<GetInfoResponse>,
+    info_request: InfoRequest,
+    /// The number of GetInfo requests sent since the node appeared or restarted
+    info_attempts: u8,
+    ports: Option<Ports>,
+}
+
+impl<I> NodeStatus<I> {
+    /// Returns the ID of this node
+    pub fn node_id(&self) -> &I {
+        &self.node_id
+    }
+    /// Returns the uptime from the latest heartbeat, in seconds
+    pub fn uptime(&self) -> u32 {
+        self.uptime
+    }
+    /// Returns the health from the latest heartbeat
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+    /// Returns the mode from the latest heartbeat
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+    /// Returns the vendor-specific status code from the latest heartbeat
+    pub fn vendor_specific_status_code(&self) -> u8 {
+        self.vendor_specific_status_code
+    }
+    /// Returns the time when the latest heartbeat was received
+    pub fn last_heartbeat(&self) -> Microseconds32 {
+        self.last_heartbeat
+    }
+    /// Returns the GetInfo response from this node
+    ///
+    /// This returns None if GetInfo queries are disabled or the node has not responded
+    /// since it appeared or restarted.
+    pub fn info(&self) -> Option<&GetInfoResponse> {
+        self.info.as_ref()
+    }
+    /// Returns the software version from the GetInfo response from this node
+    pub fn software_version(&self) -> Option<&Version> {
+        self.info.as_ref().map(|info| &info.software_version)
+    }
+    /// Returns the ports from the latest port list message from this node
+    ///
+    /// This returns None if the node has not published a port list since it appeared or
+    /// restarted.
+    pub fn ports(&self) -> Option<&Ports> {
+        self.ports.as_ref()
+    }
+}
+
+/// The state of the GetInfo query for a node
+#[derive(Debug, Copy, Clone)]
+enum InfoRequest {
+    /// A request should be sent at or after a time
+    Due(Microseconds32),
+    /// A response has been received, the monitor has given up, or queries are disabled
+    Done,
+}
+
+/// The ports that a node uses, from a `uavcan.node.port.List` message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ports {
+    /// The subjects that the node publishes
+    pub publishers: Subjects,
+    /// The subjects that the node subscribes to
+    pub subscribers: Subjects,
+    /// The services that the node sends requests for
+    pub clients: Vec<ServiceId>,
+    /// The services that the node responds to
+    pub servers: Vec<ServiceId>,
+}
+
+impl From<&List> for Ports {
+    fn from(list: &List) -> Self {
+        Ports {
+            publishers: Subjects::from(&list.publishers),
+            subscribers: Subjects::from(&list.subscribers),
+            clients: services(&list.clients),
+            servers: services(&list.servers),
+        }
+    }
+}
+
+/// A set of subject IDs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subjects {
+    /// Specific subject IDs, in the order that the node listed them
+    List(Vec<SubjectId>),
+    /// All subject IDs
+    All,
+}
+
+impl Subjects {
+    /// Returns true if this set contains a subject ID
+    pub fn contains(&self, subject: SubjectId) -> bool {
+        match self {
+            Subjects::List(subjects) => subjects.contains(&subject),
+            Subjects::All => true,
+        }
+    }
+}
+
+impl From<&SubjectIDList> for Subjects {
+    fn from(list: &SubjectIDList) -> Self {
+        match list {
+            SubjectIDList::Mask(mask) => Subjects::List(
+                mask.iter()
+                    .enumerate()
+                    .filter(|(_, used)| *used)
+                    .filter_map(|(id, _)| SubjectId::try_from(id as u16).ok())
+                    .collect(),
+            ),
+            SubjectIDList::SparseList(subjects) => Subjects::List(
+                subjects
+                    .iter()
+                    .filter_map(|subject| SubjectId::try_from(subject.value).ok())
+                    .collect(),
+            ),
+            SubjectIDList::Total(_) => Subjects::All,
+        }
+    }
+}
+
+fn services(list: &ServiceIDList) -> Vec<ServiceId> {
+    list.mask
+        .iter()
+        .enumerate()
+        .filter(|(_, used)| *used)
+        .filter_map(|(id, _)| ServiceId::try_from(id as u16).ok())
+        .collect()
+}
+
+/// A handler that updates a [`NodeMonitor`] with incoming transfers
+pub struct NodeMonitorHandler<'a, N: Node> {
+    monitor: &'a mut NodeMonitor<N>,
+}
+
+impl<N: Node> TransferHandler<N::Transport> for NodeMonitorHandler<'_, N> {
+    fn handle_message<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        _node: &mut N2,
+        transfer: &MessageTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        let source = match &transfer.header.source {
+            Some(source) => source.clone(),
+            // Anonymous nodes do not publish heartbeats or port lists
+            None => return false,
+        };
+        if transfer.header.subject == heartbeat_1_0::SUBJECT {
+            if let Ok(heartbeat) = Heartbeat::deserialize_from_bytes(&transfer.payload) {
+                self.monitor
+                    .handle_heartbeat(source, transfer.header.timestamp, heartbeat);
+                return true;
+            }
+        } else if transfer.header.subject == list_1_0::SUBJECT {
+            if let Ok(list) = List::deserialize_from_bytes(&transfer.payload) {
+                // Port lists from nodes that have not sent a heartbeat are ignored
+                if let Some(status) = self.monitor.nodes.get_mut(&source.into()) {
+                    status.ports = Some(Ports::from(&list));
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    fn handle_response<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        _node: &mut N2,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        if self.monitor.get_info.is_none() || transfer.header.service != get_info_1_0::SERVICE {
+            return false;
+        }
+        if let Ok(info) = GetInfoResponse::deserialize_from_bytes(&transfer.payload) {
+            if let Some(status) = self
+                .monitor
+                .nodes
+                .get_mut(&transfer.header.source.clone().into())
+            {
+                status.info = Some(info);
+                status.info_request = InfoRequest::Done;
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use crate::core::transport::Transmitter;
+use crate::health::{HealthMonitor, WatchdogId};
 use crate::node::{MinimalNode, NodeError};
 use crate::{Node, PublishError, ResponseToken, ServiceToken, StartSendError, TransferHandler};
 use alloc::vec::Vec;
+use canadensis_core::statistics::TransportStatistics;
 use canadensis_core::time::{milliseconds, MicrosecondDuration32};
 use canadensis_core::transfer::ServiceTransfer;
 use canadensis_core::transport::{Receiver, Transport};
@@ -142,6 +144,30 @@ where
         self.node.set_status_code(status);
     }
 
+    /// Adds a watchdog that must be kicked at least once every `timeout`
+    ///
+    /// See [`MinimalNode::add_watchdog`].
+    pub fn add_watchdog(
+        &mut self,
+        timeout: MicrosecondDuration32,
+        health: Health,
+        status_code: u8,
+    ) -> WatchdogId {
+        self.node.add_watchdog(timeout, health, status_code)
+    }
+    /// Kicks a watchdog, showing that the subsystem it watches is still working
+    pub fn kick_watchdog(&mut self, watchdog: WatchdogId) {
+        self.node.kick_watchdog(watchdog);
+    }
+    /// Returns a reference to the health monitor
+    pub fn health_monitor(&self) -> &HealthMonitor {
+        self.node.health_monitor()
+    }
+    /// Returns a mutable reference to the health monitor
+    pub fn health_monitor_mut(&mut self) -> &mut HealthMonitor {
+        self.node.health_monitor_mut()
+    }
+
     /// Returns a reference to the enclosed node
     pub fn node(&self) -> &N {
         self.node.node()
@@ -397,6 +423,10 @@ where
     fn servers(&self) -> impl Iterator<Item = ServiceId> {
         self.node.node().servers()
     }
+
+    fn transport_statistics(&self) -> TransportStatistics {
+        self.node.node().transport_statistics()
+    }
 }
 
 fn insert_into_list(subject_list: &mut SubjectIDList, subject: SubjectId) {
@@ -2,6 +2,7 @@ use alloc::vec::Vec;
 use core::marker::PhantomData;
 use heapless::index_map::FnvIndexMap;
 
+use canadensis_core::statistics::TransportStatistics;
 use canadensis_core::time::{Clock, MicrosecondDuration32, Microseconds32};
 use canadensis_core::transfer::{
     Header, MessageTransfer, ServiceHeader, ServiceTransfer, Transfer,
@@ -478,4 +479,12 @@ where
     fn servers(&self) -> impl Iterator<Item = ServiceId> {
         self.receiver.servers()
     }
+
+    fn transport_statistics(&self) -> TransportStatistics {
+        let mut statistics = TransportStatistics::default();
+        self.transmitter
+            .add_statistics(&self.driver, &mut statistics);
+        self.receiver.add_statistics(&self.driver, &mut statistics);
+        statistics
+    }
 }
@@ -0,0 +1,608 @@
+//! A node with standard services that are chosen at runtime
+//!
+//! [`NodeBuilder`] enables services one at a time and creates a [`DynamicNode`], which owns the
+//! underlying node and the enabled services. Unlike the individual services in
+//! [`service`](crate::service), a dynamic node does not need a handler type for each combination
+//! of services: its [`poll`](DynamicNode::poll) function dispatches incoming transfers to the
+//! enabled services and passes everything else to the application's handler.
+//!
+//! Services that respond to requests need a node ID. If the node is anonymous and plug-and-play
+//! is enabled, those services start after an allocator assigns a node ID.
+
+use crate::core::transport::Transmitter;
+use crate::node::NodeError;
+use crate::register::RegisterBlock;
+use crate::service::get_info::GetInfoService;
+use crate::service::heartbeat::HeartbeatService;
+use crate::service::pnp::client::PnpClientService;
+use crate::service::pnp::{self, AllocationMessage, RequestTimer};
+use crate::service::port_list::{self, PortListService};
+use crate::service::register_server::RegisterServerService;
+use crate::service::server::{Call, Handler, Reply, ServiceServer};
+use crate::{Node, PublishError, ResponseToken, StartSendError, TransferHandler};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use canadensis_core::time::{milliseconds, Microseconds32};
+use canadensis_core::transfer::{MessageTransfer, ServiceTransfer};
+use canadensis_core::transport::Receiver;
+use canadensis_core::{nb, Priority, ServiceSubscribeError};
+use canadensis_data_types::uavcan::diagnostic::record_1_1::{self, Record};
+use canadensis_data_types::uavcan::diagnostic::severity_1_0::Severity;
+use canadensis_data_types::uavcan::node::execute_command_1_3::{
+    self, ExecuteCommandRequest, ExecuteCommandResponse,
+};
+use canadensis_data_types::uavcan::node::get_info_1_0::{self, GetInfoResponse};
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_1_0::NodeIDAllocationData as Data1;
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_2_0::NodeIDAllocationData as Data2;
+use canadensis_data_types::uavcan::register::{access_1_0, list_1_0};
+use canadensis_data_types::uavcan::time::synchronized_timestamp_1_0::SynchronizedTimestamp;
+use canadensis_encoding::DataType;
+use core::fmt::{Debug, Formatter};
+
+type TxError<N> = <<N as Node>::Transmitter as Transmitter<<N as Node>::Clock>>::Error;
+type RxError<N> = <<N as Node>::Receiver as Receiver<<N as Node>::Clock>>::Error;
+
+/// An error that prevents a [`DynamicNode`] from starting a service
+pub type StartError<N> = NodeError<StartSendError<TxError<N>>, ServiceSubscribeError<RxError<N>>>;
+
+/// A boxed `uavcan.node.ExecuteCommand` handler
+type CommandHandler<T> = Handler<ExecuteCommandRequest, ExecuteCommandResponse, T>;
+
+/// The register block type that a [`DynamicNode`] serves
+type Registers = Box<dyn RegisterBlock>;
+
+/// Sets up a [`DynamicNode`] with a selection of standard services
+///
+/// All services are disabled by default.
+pub struct NodeBuilder<N>
+where
+    N: Node,
+{
+    node: N,
+    heartbeat: bool,
+    diagnostics: bool,
+    unique_id: Option<[u8; 16]>,
+    waiting: WaitingServices<N>,
+}
+
+impl<N> NodeBuilder<N>
+where
+    N: Node,
+{
+    /// Creates a builder
+    ///
+    /// * `node`: The underlying node (this is usually a [`CoreNode`](crate::node::CoreNode))
+    pub fn new(node: N) -> Self {
+        NodeBuilder {
+            node,
+            heartbeat: false,
+            diagnostics: false,
+            unique_id: None,
+            waiting: WaitingServices {
+                node_info: None,
+                port_list: false,
+                registers: None,
+                execute_command: None,
+            },
+        }
+    }
+
+    /// Enables sending a `uavcan.node.Heartbeat` every second
+    ///
+    /// Every node should send heartbeats. This uses one publisher slot.
+    pub fn heartbeat(mut self) -> Self {
+        self.heartbeat = true;
+        self
+    }
+
+    /// Enables responding to `uavcan.node.GetInfo` requests with the provided information
+    pub fn get_info(mut self, node_info: GetInfoResponse) -> Self {
+        self.waiting.node_info = Some(node_info);
+        self
+    }
+
+    /// Enables sending a `uavcan.node.port.List` every 10 seconds
+    ///
+    /// This uses one publisher slot.
+    pub fn port_list(mut self) -> Self {
+        self.waiting.port_list = true;
+        self
+    }
+
+    /// Enables responding to `uavcan.register.List` and `uavcan.register.Access` requests
+    pub fn registers<B>(mut self, registers: B) -> Self
+    where
+        B: RegisterBlock + 'static,
+    {
+        self.waiting.registers = Some(Box::new(registers));
+        self
+    }
+
+    /// Enables plug-and-play node ID allocation
+    ///
+    /// If the node does not have a node ID when it is built, it sends allocation requests until an
+    /// allocator assigns a node ID. The first request is sent after a random delay of up to one
+    /// second, and later requests are sent at random intervals (see [`RequestTimer`]). This uses
+    /// one publisher slot until a node ID is assigned.
+    ///
+    /// The version 2 allocation message is used if it fits into one frame, otherwise version 1.
+    pub fn pnp_client(mut self, unique_id: [u8; 16]) -> Self {
+        self.unique_id = Some(unique_id);
+        self
+    }
+
+    /// Enables responding to `uavcan.node.ExecuteCommand` (version 1.3) requests
+    ///
+    /// `handler` is called with each request. It can reply immediately or defer the response
+    /// and send it later with [`Node::send_response`].
+    pub fn execute_command<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(
+                ExecuteCommandRequest,
+                Call<'_, N::Transport>,
+            ) -> Reply<ExecuteCommandResponse, N::Transport>
+            + 'static,
+    {
+        self.waiting.execute_command = Some(Box::new(handler));
+        self
+    }
+
+    /// Enables publishing `uavcan.diagnostic.Record` messages with
+    /// [`DynamicNode::publish_diagnostic`]
+    ///
+    /// This uses one publisher slot.
+    pub fn diagnostics(mut self) -> Self {
+        self.diagnostics = true;
+        self
+    }
+
+    /// Creates the node and starts the enabled services
+    ///
+    /// The services that respond to requests and the port list can only start when the node
+    /// has a node ID. If the node does not have a node ID yet, they start during the first call
+    /// to [`DynamicNode::poll`] after it gets one.
+    pub fn build(self) -> Result<DynamicNode<N>, StartError<N>> {
+        let NodeBuilder {
+            mut node,
+            heartbeat,
+            diagnostics,
+            unique_id,
+            waiting,
+        } = self;
+
+        let heartbeat = if heartbeat {
+            Some(HeartbeatService::new(&mut node).map_err(NodeError::Transmitter)?)
+        } else {
+            None
+        };
+        if diagnostics {
+            node.start_publishing(
+                record_1_1::SUBJECT,
+                milliseconds(1000),
+                Priority::Low.into(),
+            )
+            .map_err(NodeError::Transmitter)?;
+        }
+        let pnp = match unique_id {
+            Some(unique_id) if node.node_id().is_none() => {
+                Some(PnpClient::new(&mut node, unique_id)?)
+            }
+            _ => None,
+        };
+
+        let mut dynamic = DynamicNode {
+            node,
+            heartbeat,
+            diagnostics,
+            pnp,
+            waiting: Some(waiting),
+            get_info: None,
+            port_list: None,
+            registers: None,
+            execute_command: None,
+            next_second: None,
+            seconds_since_port_list_published: 0,
+        };
+        if dynamic.node.node_id().is_some() {
+            dynamic.start_waiting_services()?;
+        }
+        Ok(dynamic)
+    }
+}
+
+/// Services that need a node ID to start
+struct WaitingServices<N>
+where
+    N: Node,
+{
+    node_info: Option<GetInfoResponse>,
+    port_list: bool,
+    registers: Option<Registers>,
+    execute_command: Option<CommandHandler<N::Transport>>,
+}
+
+/// A node with a selection of standard services chosen at runtime
+///
+/// A dynamic node is created with a [`NodeBuilder`]. The application calls
+/// [`poll`](#method.poll) frequently, which receives and dispatches incoming transfers, runs
+/// the tasks that need to happen every second, and flushes outgoing frames.
+///
+/// The underlying node type `N` is usually a [`CoreNode`](crate::node::CoreNode).
+pub struct DynamicNode<N>
+where
+    N: Node,
+{
+    node: N,
+    heartbeat: Option<HeartbeatService<N>>,
+    diagnostics: bool,
+    /// The plug-and-play client, until the node has a node ID
+    pnp: Option<PnpClient<N>>,
+    /// Services that will start when the node has a node ID
+    waiting: Option<WaitingServices<N>>,
+    get_info: Option<GetInfoService<N>>,
+    port_list: Option<PortListService<N>>,
+    registers: Option<RegisterServerService<N, Registers>>,
+    execute_command: Option<ServiceServer<N, ExecuteCommandRequest, ExecuteCommandResponse>>,
+    /// The time when the per-second tasks should run next
+    next_second: Option<Microseconds32>,
+    seconds_since_port_list_published: u8,
+}
+
+impl<N> DynamicNode<N>
+where
+    N: Node,
+{
+    /// Handles incoming transfers, runs periodic tasks, and sends outgoing frames
+    ///
+    /// * `now`: The current time, used to run the per-second tasks (heartbeats and port lists)
+    ///   once per second and to send plug-and-play requests
+    /// * `handler`: A handler for incoming transfers that none of the enabled services handle
+    ///
+    /// Sending a heartbeat, port list, or plug-and-play request may fail because the transmit
+    /// queue is full. This is not reported as an error, but it affects the health in the
+    /// heartbeats.
+    pub fn poll<H>(&mut self, now: Microseconds32, handler: &mut H) -> Result<(), PollError<N>>
+    where
+        H: TransferHandler<N::Transport>,
+    {
+        let services = ServiceHandlers {
+            pnp: self.pnp.as_mut(),
+            get_info: self.get_info.as_ref(),
+            registers: self.registers.as_mut(),
+            execute_command: self.execute_command.as_mut(),
+        };
+        self.node
+            .receive(&mut services.chain(handler))
+            .map_err(PollError::Receive)?;
+
+        if self.node.node_id().is_some() {
+            // The node may have just received a node ID from the allocator
+            self.pnp = None;
+            self.start_waiting_services().map_err(PollError::Start)?;
+        } else if let Some(pnp) = &mut self.pnp {
+            if pnp.timer.is_due(now) {
+                match pnp.send_request(&mut self.node) {
+                    Ok(()) => pnp.timer.restart(now),
+                    // Try again during the next poll
+                    Err(nb::Error::WouldBlock) => {}
+                    Err(e) => check_publish(Err(e))?,
+                }
+            }
+        }
+
+        let due = match self.next_second {
+            Some(next_second) => now >= next_second,
+            None => true,
+        };
+        if due {
+            self.next_second = Some(match self.next_second {
+                // Keep a steady rate unless the tasks are more than one second late
+                Some(next_second) if now < next_second + milliseconds(1000) => {
+                    next_second + milliseconds(1000)
+                }
+                _ => now + milliseconds(1000),
+            });
+            self.run_per_second_tasks()?;
+        }
+
+        match self.node.flush() {
+            Ok(()) | Err(nb::Error::WouldBlock) => Ok(()),
+            Err(nb::Error::Other(e)) => Err(PollError::Transmit(e)),
+        }
+    }
+
+    fn run_per_second_tasks(&mut self) -> Result<(), PollError<N>> {
+        if self.node.node_id().is_none() {
+            return Ok(());
+        }
+        if let Some(heartbeat) = &mut self.heartbeat {
+            check_publish(heartbeat.publish_heartbeat(&mut self.node))?;
+        }
+        if let Some(port_list) = &mut self.port_list {
+            if self.seconds_since_port_list_published == 10 {
+                self.seconds_since_port_list_published = 1;
+                check_publish(port_list.publish_port_list(&mut self.node))?;
+            } else {
+                self.seconds_since_port_list_published += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts the services that were waiting for a node ID
+    ///
+    /// If a service fails to start, the services after it are not started.
+    fn start_waiting_services(&mut self) -> Result<(), StartError<N>> {
+        let waiting = match self.waiting.take() {
+            Some(waiting) => waiting,
+            None => return Ok(()),
+        };
+        if let Some(node_info) = waiting.node_info {
+            self.get_info =
+                Some(GetInfoService::new(&mut self.node, node_info).map_err(NodeError::Receiver)?);
+        }
+        if waiting.port_list {
+            self.port_list = Some(PortListService::new(&mut self.node).map_err(|e| {
+                NodeError::Transmitter(match e {
+                    port_list::NewError::OutOfMemory => {
+                        StartSendError::Memory(canadensis_core::OutOfMemoryError)
+                    }
+                    port_list::NewError::Duplicate => StartSendError::Duplicate,
+                    port_list::NewError::Other(e) => StartSendError::Transport(e),
+                    port_list::NewError::Anonymous => StartSendError::AnonymousRequest,
+                })
+            })?);
+        }
+        if let Some(registers) = waiting.registers {
+            self.registers = Some(
+                RegisterServerService::new(&mut self.node, registers)
+                    .map_err(NodeError::Receiver)?,
+            );
+        }
+        if let Some(handler) = waiting.execute_command {
+            self.execute_command = Some(
+                ServiceServer::with_boxed_handler(
+                    &mut self.node,
+                    execute_command_1_3::SERVICE,
+                    ExecuteCommandRequest::EXTENT_BYTES.unwrap() as usize,
+                    handler,
+                )
+                .map_err(NodeError::Receiver)?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Publishes a `uavcan.diagnostic.Record` message
+    ///
+    /// Text longer than 255 bytes is truncated. The timestamp in the message is zero (unknown).
+    ///
+    /// This function returns a `NotPublishing` error if diagnostics were not enabled in the
+    /// builder.
+    pub fn publish_diagnostic(
+        &mut self,
+        severity: Severity,
+        text: &str,
+    ) -> nb::Result<(), PublishError<TxError<N>>> {
+        if !self.diagnostics {
+            return Err(nb::Error::Other(PublishError::NotPublishing));
+        }
+        let mut length = text.len().min(255);
+        while !text.is_char_boundary(length) {
+            length -= 1;
+        }
+        let record = Record {
+            timestamp: SynchronizedTimestamp { microsecond: 0 },
+            severity,
+            text: heapless::Vec::from_slice(&text.as_bytes()[..length]).unwrap(),
+        };
+        self.node.publish(record_1_1::SUBJECT, &record)
+    }
+
+    /// Returns the heartbeat service, if heartbeats are enabled
+    ///
+    /// The heartbeat service can set the mode, health, and status code in the heartbeats.
+    pub fn heartbeat(&self) -> Option<&HeartbeatService<N>> {
+        self.heartbeat.as_ref()
+    }
+    /// Returns the heartbeat service, if heartbeats are enabled
+    pub fn heartbeat_mut(&mut self) -> Option<&mut HeartbeatService<N>> {
+        self.heartbeat.as_mut()
+    }
+
+    /// Returns the register block, if registers are enabled
+    pub fn registers(&self) -> Option<&dyn RegisterBlock> {
+        match (&self.registers, &self.waiting) {
+            (Some(server), _) => Some(&**server.registers()),
+            (None, Some(waiting)) => waiting.registers.as_deref(),
+            (None, None) => None,
+        }
+    }
+    /// Returns the register block, if registers are enabled
+    pub fn registers_mut(&mut self) -> Option<&mut dyn RegisterBlock> {
+        match (&mut self.registers, &mut self.waiting) {
+            (Some(server), _) => Some(&mut **server.registers_mut()),
+            (None, Some(waiting)) => match &mut waiting.registers {
+                Some(registers) => Some(&mut **registers),
+                None => None,
+            },
+            (None, None) => None,
+        }
+    }
+
+    /// Returns true if services are still waiting for the node to get a node ID
+    pub fn is_waiting_for_node_id(&self) -> bool {
+        self.waiting.is_some()
+    }
+
+    /// Returns a reference to the enclosed node
+    pub fn node(&self) -> &N {
+        &self.node
+    }
+    /// Returns a mutable reference to the enclosed node
+    ///
+    /// This can be used to publish, subscribe, send requests, and send deferred responses.
+    pub fn node_mut(&mut self) -> &mut N {
+        &mut self.node
+    }
+}
+
+/// Converts the result of publishing something periodically
+///
+/// A full transmit queue is not an error because the same thing will be published again.
+fn check_publish<N: Node>(
+    result: nb::Result<(), PublishError<TxError<N>>>,
+) -> Result<(), PollError<N>> {
+    match result {
+        Ok(()) | Err(nb::Error::WouldBlock) => Ok(()),
+        Err(nb::Error::Other(PublishError::Transport(e))) => Err(PollError::Transmit(e)),
+        Err(nb::Error::Other(PublishError::NotPublishing)) => Ok(()),
+    }
+}
+
+/// An error from [`DynamicNode::poll`]
+pub enum PollError<N: Node> {
+    /// The receiver returned an error
+    Receive(RxError<N>),
+    /// The transmitter returned an error
+    Transmit(TxError<N>),
+    /// A service could not start after the node got a node ID
+    Start(StartError<N>),
+}
+
+impl<N> Debug for PollError<N>
+where
+    N: Node,
+    TxError<N>: Debug,
+    RxError<N>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PollError::Receive(e) => f.debug_tuple("Receive").field(e).finish(),
+            PollError::Transmit(e) => f.debug_tuple("Transmit").field(e).finish(),
+            PollError::Start(e) => f.debug_tuple("Start").field(e).finish(),
+        }
+    }
+}
+
+/// A plug-and-play client and the timer that decides when it sends requests
+struct PnpClient<N>
+where
+    N: Node,
+{
+    service: PnpVersion<N>,
+    timer: RequestTimer,
+}
+
+/// A plug-and-play client with the allocation message version that fits into one frame
+enum PnpVersion<N>
+where
+    N: Node,
+{
+    V1(PnpClientService<N, Data1>),
+    V2(PnpClientService<N, Data2>),
+}
+
+impl<N> PnpClient<N>
+where
+    N: Node,
+{
+    fn new(node: &mut N, unique_id: [u8; 16]) -> Result<Self, StartError<N>> {
+        let service = if <Data2 as AllocationMessage<N::Transport>>::PAYLOAD_SIZE_MAX
+            <= node.transmitter().mtu()
+        {
+            PnpClientService::new(node, unique_id).map(PnpVersion::V2)
+        } else {
+            PnpClientService::new(node, unique_id).map(PnpVersion::V1)
+        };
+        let service = service.map_err(|e| match e {
+            pnp::NewError::Subscribe(e) => NodeError::Receiver(ServiceSubscribeError::Transport(e)),
+            pnp::NewError::OutOfMemory => {
+                NodeError::Transmitter(StartSendError::Memory(canadensis_core::OutOfMemoryError))
+            }
+            pnp::NewError::Duplicate => NodeError::Transmitter(StartSendError::Duplicate),
+            pnp::NewError::Publish(e) => NodeError::Transmitter(StartSendError::Transport(e)),
+        })?;
+        Ok(PnpClient {
+            service,
+            timer: RequestTimer::new(&unique_id),
+        })
+    }
+
+    fn send_request(&mut self, node: &mut N) -> nb::Result<(), PublishError<TxError<N>>> {
+        match &mut self.service {
+            PnpVersion::V1(client) => client.send_request(node),
+            PnpVersion::V2(client) => client.send_request(node),
+        }
+    }
+}
+
+/// Passes incoming transfers to the enabled services
+struct ServiceHandlers<'a, N>
+where
+    N: Node,
+{
+    pnp: Option<&'a mut PnpClient<N>>,
+    get_info: Option<&'a GetInfoService<N>>,
+    registers: Option<&'a mut RegisterServerService<N, Registers>>,
+    execute_command:
+        Option<&'a mut ServiceServer<N, ExecuteCommandRequest, ExecuteCommandResponse>>,
+}
+
+impl<N> TransferHandler<N::Transport> for ServiceHandlers<'_, N>
+where
+    N: Node,
+{
+    fn handle_message<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        transfer: &MessageTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        let subject = transfer.header.subject;
+        let pnp = match &mut self.pnp {
+            Some(pnp) => pnp,
+            None => return false,
+        };
+        match &mut pnp.service {
+            PnpVersion::V1(client)
+                if subject == <Data1 as AllocationMessage<N::Transport>>::SUBJECT =>
+            {
+                // With version 1.0, any allocation message (a request from another node, or a
+                // response to another node) restarts the request timer
+                pnp.timer.restart(transfer.header.timestamp);
+                client.handler().handle_message(node, transfer)
+            }
+            PnpVersion::V2(client)
+                if subject == <Data2 as AllocationMessage<N::Transport>>::SUBJECT =>
+            {
+                client.handler().handle_message(node, transfer)
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_request<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        token: ResponseToken<N2::Transport>,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        let service = transfer.header.service;
+        if service == get_info_1_0::SERVICE {
+            if let Some(get_info) = &self.get_info {
+                return get_info.handler().handle_request(node, token, transfer);
+            }
+        } else if service == list_1_0::SERVICE || service == access_1_0::SERVICE {
+            if let Some(registers) = &mut self.registers {
+                return registers.handler().handle_request(node, token, transfer);
+            }
+        } else if service == execute_command_1_3::SERVICE {
+            if let Some(execute_command) = &mut self.execute_command {
+                return execute_command
+                    .handler()
+                    .handle_request(node, token, transfer);
+            }
+        }
+        false
+    }
+}
@@ -1,5 +1,6 @@
+use crate::health::{HealthMonitor, WatchdogId};
 use crate::{Node, PublishError, StartSendError};
-use canadensis_core::time::MicrosecondDuration32;
+use canadensis_core::time::{Clock, MicrosecondDuration32};
 use canadensis_core::transport::Transmitter;
 use canadensis_core::{nb, Priority};
 use canadensis_data_types::uavcan::node::health_1_0::Health;
@@ -14,6 +15,10 @@ use canadensis_data_types::uavcan::node::mode_1_0::Mode;
 ///
 /// A BasicNode uses up one publisher slot in the underlying Node.
 ///
+/// The health and vendor-specific status code in each heartbeat come from a
+/// [`HealthMonitor`], so they can change automatically when a watchdog lapses or the
+/// transport has problems.
+///
 /// The underlying node type `N` is usually a [`CoreNode`](crate::node::CoreNode).
 pub struct MinimalNode<N>
 where
@@ -23,6 +28,8 @@ where
     node: N,
     /// The heartbeat message that will be periodically sent
     heartbeat: Heartbeat,
+    /// The health monitor that determines the health in each heartbeat
+    health: HealthMonitor,
 }
 
 impl<N> MinimalNode<N>
@@ -54,7 +61,11 @@ where
             Priority::Nominal.into(),
         )?;
 
-        Ok(MinimalNode { node, heartbeat })
+        Ok(MinimalNode {
+            node,
+            heartbeat,
+            health: HealthMonitor::new(),
+        })
     }
 
     /// This function must be called once per second to send heartbeat messages
@@ -73,8 +84,14 @@ where
     fn send_heartbeat(
         &mut self,
     ) -> nb::Result<(), PublishError<<N::Transmitter as Transmitter<N::Clock>>::Error>> {
+        let now = self.node.clock_mut().now();
+        self.health.update(now, &self.node.transport_statistics());
+        self.heartbeat.health = self.health.health();
+        self.heartbeat.vendor_specific_status_code = self.health.status_code();
         self.heartbeat.uptime = self.heartbeat.uptime.saturating_add(1);
-        self.node.publish(heartbeat_1_0::SUBJECT, &self.heartbeat)
+        let result = self.node.publish(heartbeat_1_0::SUBJECT, &self.heartbeat);
+        self.health.record_heartbeat(result.is_ok());
+        result
     }
 
     /// Sets the operating mode that will be reported in the heartbeat messages
@@ -82,12 +99,43 @@ where
         self.heartbeat.mode = mode;
     }
     /// Sets the health status that will be reported in the heartbeat messages
+    ///
+    /// The reported health may be more severe if a watchdog has lapsed or a transport check has
+    /// failed.
     pub fn set_health(&mut self, health: Health) {
-        self.heartbeat.health = health;
+        self.health.set_health(health);
     }
     /// Sets the vendor-specific status code that will be reported in the heartbeat messages
     pub fn set_status_code(&mut self, status: u8) {
-        self.heartbeat.vendor_specific_status_code = status;
+        self.health.set_status_code(status);
+    }
+
+    /// Adds a watchdog that must be kicked at least once every `timeout`
+    ///
+    /// If the watchdog is not kicked in time, the heartbeats report `health` (if it is more severe
+    /// than the other sources of health) and `status_code`.
+    pub fn add_watchdog(
+        &mut self,
+        timeout: MicrosecondDuration32,
+        health: Health,
+        status_code: u8,
+    ) -> WatchdogId {
+        let now = self.node.clock_mut().now();
+        self.health.add_watchdog(now, timeout, health, status_code)
+    }
+    /// Kicks a watchdog, showing that the subsystem it watches is still working
+    pub fn kick_watchdog(&mut self, watchdog: WatchdogId) {
+        let now = self.node.clock_mut().now();
+        self.health.kick(watchdog, now);
+    }
+
+    /// Returns a reference to the health monitor
+    pub fn health_monitor(&self) -> &HealthMonitor {
+        &self.health
+    }
+    /// Returns a mutable reference to the health monitor
+    pub fn health_monitor_mut(&mut self) -> &mut HealthMonitor {
+        &mut self.health
     }
 
     /// Returns a reference to the enclosed node
@@ -1,7 +1,7 @@
 //!
 //! High-level Cyphal node types
 //!
-//! Three different node implementations are provided with different features:
+//! Four different node implementations are provided with different features:
 //!
 //! * [`CoreNode`]: Keeps track of subscriptions and other state, but does not automatically
 //!   send anything
@@ -9,14 +9,18 @@
 //!   application-layer functionality according to the Cyphal specification)
 //! * [`BasicNode`]: Sends heartbeat messages, responds to GetInfo requests, and sends port list
 //!   messages
+//! * [`DynamicNode`]: Provides a selection of standard services chosen at runtime with a
+//!   [`NodeBuilder`], and handles receiving, periodic tasks, and sending in one `poll` function
 //!
 
 mod basic;
 mod core;
+mod dynamic;
 mod minimal;
 
 pub use self::basic::BasicNode;
 pub use self::core::CoreNode;
+pub use self::dynamic::{DynamicNode, NodeBuilder, PollError, StartError};
 pub use self::minimal::MinimalNode;
 
 pub mod data_types {
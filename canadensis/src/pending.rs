@@ -0,0 +1,280 @@
+//!
+//! Matching service responses to the requests that they answer
+//!
+//! A [`PendingRequests`] table sends requests and remembers each one by its service ID,
+//! destination node ID, and transfer ID. When a matching response arrives, or when no response
+//! arrives before the request's timeout, the table removes the request and calls the callback
+//! that was provided when the request was sent.
+//!
+//! To use a table, pass its [`handler`](PendingRequests::handler) (or a chain that includes it)
+//! to [`Node::receive`](crate::Node::receive) and call [`poll`](PendingRequests::poll)
+//! periodically to detect timeouts.
+//!
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+
+use canadensis_core::time::{Clock, MicrosecondDuration32, Microseconds32};
+use canadensis_core::transfer::ServiceTransfer;
+use canadensis_core::transport::{Transmitter, Transport};
+use canadensis_core::{nb, ServiceId};
+use canadensis_encoding::{Deserialize, DeserializeError, Request, Serialize};
+
+use crate::{Node, ServiceToken, TransferHandler};
+
+/// The result passed to a request callback: a response, or a timeout
+pub type RequestResult<'r, T> = Result<ReceivedResponse<'r, T>, RequestTimeout<T>>;
+
+/// A request that has been sent and has not yet been answered
+struct PendingRequest<T: Transport> {
+    service: ServiceId,
+    destination: T::NodeId,
+    transfer_id: T::TransferId,
+    /// The time when the request was sent
+    sent: Microseconds32,
+    timeout: MicrosecondDuration32,
+    callback: Callback<T>,
+}
+
+/// A boxed request callback
+type Callback<T> = Box<dyn FnOnce(RequestResult<'_, T>)>;
+
+/// The error that a node's transmitter can return
+type TxError<N> = <<N as Node>::Transmitter as Transmitter<<N as Node>::Clock>>::Error;
+
+impl<T: Transport> PendingRequest<T> {
+    fn time_out(self) {
+        (self.callback)(Err(RequestTimeout {
+            service: self.service,
+            destination: self.destination,
+            transfer_id: self.transfer_id,
+            sent: self.sent,
+        }))
+    }
+}
+
+/// A table of requests that are waiting for responses
+pub struct PendingRequests<N: Node> {
+    /// Pending requests, in the order they were sent
+    requests: Vec<PendingRequest<N::Transport>>,
+}
+
+impl<N: Node> Default for PendingRequests<N> {
+    fn default() -> Self {
+        PendingRequests {
+            requests: Vec::new(),
+        }
+    }
+}
+
+impl<N> PendingRequests<N>
+where
+    N: Node,
+    <N::Transport as Transport>::TransferId: PartialEq,
+{
+    /// Creates an empty table
+    pub fn new() -> Self {
+        PendingRequests::default()
+    }
+
+    /// Sends a request and adds it to this table
+    ///
+    /// * `node`: The node to send the request from
+    /// * `token`: The token from [`Node::start_sending_requests`]
+    /// * `payload`: The request to send
+    /// * `destination`: The node to send the request to
+    /// * `timeout`: The maximum time to wait for a response
+    /// * `callback`: The function to call with the response, or with a timeout error if no
+    ///   response arrives in time. The callback is called exactly once, from
+    ///   [`handler`](#method.handler) or [`poll`](#method.poll).
+    ///
+    /// On success, this function returns the transfer ID of the request. If sending fails,
+    /// the request is not added to the table and the callback is not called.
+    ///
+    /// If a request with the same service ID, destination, and transfer ID is already pending
+    /// (because the transfer ID wrapped around), that request times out immediately.
+    pub fn send<Q, F>(
+        &mut self,
+        node: &mut N,
+        token: &ServiceToken<Q>,
+        payload: &Q,
+        destination: <N::Transport as Transport>::NodeId,
+        timeout: MicrosecondDuration32,
+        callback: F,
+    ) -> nb::Result<<N::Transport as Transport>::TransferId, TxError<N>>
+    where
+        Q: Request + Serialize,
+        F: FnOnce(RequestResult<'_, N::Transport>) + 'static,
+    {
+        let sent = node.clock_mut().now();
+        let transfer_id = node.send_request(token, payload, destination.clone())?;
+        let service = token.service_id();
+        if let Some(index) = self.position(service, &destination, &transfer_id) {
+            self.requests.remove(index).time_out();
+        }
+        self.requests.push(PendingRequest {
+            service,
+            destination,
+            transfer_id: transfer_id.clone(),
+            sent,
+            timeout,
+            callback: Box::new(callback),
+        });
+        Ok(transfer_id)
+    }
+
+    /// Calls the callbacks of all requests that have timed out, and removes them
+    ///
+    /// This function should be called frequently, because a timeout is only detected when this
+    /// function runs.
+    pub fn poll(&mut self, node: &mut N) {
+        let now = node.clock_mut().now();
+        let mut i = 0;
+        while i < self.requests.len() {
+            let request = &self.requests[i];
+            let elapsed = now.ticks().wrapping_sub(request.sent.ticks());
+            if elapsed > request.timeout.ticks() {
+                self.requests.remove(i).time_out();
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Removes a pending request without calling its callback
+    ///
+    /// This function returns true if a matching request was pending.
+    pub fn cancel(
+        &mut self,
+        service: ServiceId,
+        destination: &<N::Transport as Transport>::NodeId,
+        transfer_id: &<N::Transport as Transport>::TransferId,
+    ) -> bool {
+        match self.position(service, destination, transfer_id) {
+            Some(index) => {
+                self.requests.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the number of requests that are waiting for responses
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Returns true if no requests are waiting for responses
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Returns a handler that delivers responses to the callbacks of their requests
+    ///
+    /// The handler handles only responses that match a pending request.
+    pub fn handler(&mut self) -> PendingRequestsHandler<'_, N> {
+        PendingRequestsHandler { table: self }
+    }
+
+    fn position(
+        &self,
+        service: ServiceId,
+        destination: &<N::Transport as Transport>::NodeId,
+        transfer_id: &<N::Transport as Transport>::TransferId,
+    ) -> Option<usize> {
+        self.requests.iter().position(|request| {
+            request.service == service
+                && request.destination == *destination
+                && request.transfer_id == *transfer_id
+        })
+    }
+}
+
+/// A handler that delivers responses to a [`PendingRequests`] table
+pub struct PendingRequestsHandler<'t, N: Node> {
+    table: &'t mut PendingRequests<N>,
+}
+
+impl<N> TransferHandler<N::Transport> for PendingRequestsHandler<'_, N>
+where
+    N: Node,
+    <N::Transport as Transport>::TransferId: PartialEq,
+{
+    fn handle_response<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        _node: &mut N2,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        let header = &transfer.header;
+        match self
+            .table
+            .position(header.service, &header.source, &header.transfer_id)
+        {
+            Some(index) => {
+                let request = self.table.requests.remove(index);
+                let latency = MicrosecondDuration32::from_ticks(
+                    header.timestamp.ticks().wrapping_sub(request.sent.ticks()),
+                );
+                (request.callback)(Ok(ReceivedResponse { transfer, latency }));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A response that matched a pending request
+pub struct ReceivedResponse<'r, T: Transport> {
+    transfer: &'r ServiceTransfer<Vec<u8>, T>,
+    latency: MicrosecondDuration32,
+}
+
+impl<'r, T: Transport> ReceivedResponse<'r, T> {
+    /// Returns the response transfer
+    pub fn transfer(&self) -> &'r ServiceTransfer<Vec<u8>, T> {
+        self.transfer
+    }
+
+    /// Returns the serialized response payload
+    pub fn payload(&self) -> &'r [u8] {
+        &self.transfer.payload
+    }
+
+    /// Deserializes the response payload
+    pub fn deserialize<R>(&self) -> Result<R, DeserializeError>
+    where
+        R: Deserialize,
+    {
+        R::deserialize_from_bytes(&self.transfer.payload)
+    }
+
+    /// Returns the round-trip latency: the time from sending the request to receiving the
+    /// first frame of the response
+    pub fn latency(&self) -> MicrosecondDuration32 {
+        self.latency
+    }
+}
+
+/// The error passed to a request callback when no response arrived before the timeout
+pub struct RequestTimeout<T: Transport> {
+    /// The service ID of the request
+    pub service: ServiceId,
+    /// The node that the request was sent to
+    pub destination: T::NodeId,
+    /// The transfer ID of the request
+    pub transfer_id: T::TransferId,
+    /// The time when the request was sent
+    pub sent: Microseconds32,
+}
+
+impl<T: Transport> Debug for RequestTimeout<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RequestTimeout")
+            .field("service", &self.service)
+            .field("destination", &self.destination)
+            .field("transfer_id", &self.transfer_id)
+            .field("sent", &self.sent)
+            .finish()
+    }
+}
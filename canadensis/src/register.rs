@@ -3,6 +3,7 @@
 
 pub mod basic;
 
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use canadensis_core::ServiceSubscribeError;
 use core::str;
@@ -57,6 +58,23 @@ pub trait RegisterBlock {
     fn register_by_name_mut(&mut self, name: &str) -> Option<&mut dyn Register>;
 }
 
+impl<B> RegisterBlock for Box<B>
+where
+    B: RegisterBlock + ?Sized,
+{
+    fn register_by_index(&self, index: usize) -> Option<&dyn Register> {
+        (**self).register_by_index(index)
+    }
+
+    fn register_by_index_mut(&mut self, index: usize) -> Option<&mut dyn Register> {
+        (**self).register_by_index_mut(index)
+    }
+
+    fn register_by_name_mut(&mut self, name: &str) -> Option<&mut dyn Register> {
+        (**self).register_by_name_mut(name)
+    }
+}
+
 /// Information about how a register can be accessed
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
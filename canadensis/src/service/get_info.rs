@@ -1,11 +1,21 @@
+use crate::service::server::send_response;
 use crate::{Node, ResponseToken, ServiceTransfer, TransferHandler};
 use alloc::vec::Vec;
 use canadensis_core::time::milliseconds;
 use canadensis_core::transport::Receiver;
 use canadensis_core::ServiceSubscribeError;
+#[cfg(feature = "app-descriptor")]
+pub use canadensis_crc::AppDescriptor;
 use canadensis_data_types::uavcan::node::get_info_1_0::{GetInfoResponse, SERVICE};
+#[cfg(feature = "app-descriptor")]
+use canadensis_data_types::uavcan::node::version_1_0::Version;
 use core::marker::PhantomData;
 
+/// The error that can occur when subscribing to GetInfo requests
+#[cfg(feature = "app-descriptor")]
+type SubscribeError<N> =
+    ServiceSubscribeError<<<N as Node>::Receiver as Receiver<<N as Node>::Clock>>::Error>;
+
 /// A service that responds to `uavcan.node.GetInfo`
 pub struct GetInfoService<N>
 where
@@ -34,6 +44,23 @@ where
         })
     }
 
+    /// Creates a new GetInfo service that reports the software version, version control
+    /// revision, and image CRC from an app descriptor
+    ///
+    /// The descriptor values replace the corresponding fields of `node_info`, as described in
+    /// [`apply_app_descriptor`].
+    ///
+    /// * `node`: The node to use for responding to requests
+    #[cfg(feature = "app-descriptor")]
+    pub fn with_app_descriptor(
+        node: &mut N,
+        mut node_info: GetInfoResponse,
+        descriptor: &AppDescriptor,
+    ) -> Result<Self, SubscribeError<N>> {
+        apply_app_descriptor(&mut node_info, descriptor);
+        Self::new(node, node_info)
+    }
+
     /// Returns the handler for this service
     pub fn handler(&self) -> GetInfoServiceHandler<'_, N> {
         GetInfoServiceHandler { service: self }
@@ -62,7 +89,27 @@ where
             return false;
         }
 
-        let _ = node.send_response(token, milliseconds(1000), &self.service.node_info);
+        send_response(node, token, milliseconds(1000), &self.service.node_info);
         true
     }
 }
+
+/// Copies the software version, version control revision, and image CRC from an app descriptor
+/// into a GetInfo response
+///
+/// If `canadensis_write_crc` has not filled in the descriptor, the software version is still
+/// copied, the version control revision becomes zero, and the image CRC is left empty.
+///
+/// This is useful with node types that take a `GetInfoResponse`, like
+/// [`BasicNode`](crate::node::BasicNode).
+#[cfg(feature = "app-descriptor")]
+pub fn apply_app_descriptor(node_info: &mut GetInfoResponse, descriptor: &AppDescriptor) {
+    let (major, minor) = descriptor.software_version();
+    node_info.software_version = Version { major, minor };
+    node_info.software_vcs_revision_id = descriptor.vcs_revision();
+    node_info.software_image_crc.clear();
+    if let Some(crc) = descriptor.crc() {
+        // The capacity is 1, so this can't fail
+        let _ = node_info.software_image_crc.push(crc);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::service::server::send_response;
+use crate::{Node, ResponseToken, ServiceTransfer, TransferHandler};
+use alloc::vec::Vec;
+use canadensis_core::statistics::IoStatistics;
+use canadensis_core::time::milliseconds;
+use canadensis_core::transport::Receiver;
+use canadensis_core::ServiceSubscribeError;
+use canadensis_data_types::uavcan::node::get_transport_statistics_0_1::{
+    GetTransportStatisticsResponse, SERVICE,
+};
+use canadensis_data_types::uavcan::node::io_statistics_0_1::IOStatistics;
+use core::marker::PhantomData;
+
+/// A service that responds to `uavcan.node.GetTransportStatistics` with the counters from
+/// [`Node::transport_statistics`]
+///
+/// Counters are reported modulo 2<sup>40</sup>.
+pub struct GetTransportStatisticsService<N>
+where
+    N: Node,
+{
+    _node: PhantomData<N>,
+}
+
+impl<N> GetTransportStatisticsService<N>
+where
+    N: Node,
+{
+    /// Creates a new GetTransportStatistics service
+    ///
+    /// * `node`: The node to use for responding to requests
+    pub fn new(node: &mut N) -> Result<Self, NewError<N>> {
+        node.subscribe_request(SERVICE, 0, milliseconds(1000))?;
+
+        Ok(Self { _node: PhantomData })
+    }
+
+    /// Returns the handler for this service
+    pub fn handler(&self) -> GetTransportStatisticsServiceHandler<'_, N> {
+        GetTransportStatisticsServiceHandler { _service: self }
+    }
+}
+
+/// Error type returned by [`GetTransportStatisticsService::new`]
+pub type NewError<N> =
+    ServiceSubscribeError<<<N as Node>::Receiver as Receiver<<N as Node>::Clock>>::Error>;
+
+/// A handler for a `uavcan.node.GetTransportStatistics` request
+pub struct GetTransportStatisticsServiceHandler<'a, N>
+where
+    N: Node,
+{
+    _service: &'a GetTransportStatisticsService<N>,
+}
+
+impl<'a, N> TransferHandler<N::Transport> for GetTransportStatisticsServiceHandler<'a, N>
+where
+    N: Node,
+{
+    fn handle_request<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        token: ResponseToken<N2::Transport>,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        if transfer.header.service != SERVICE {
+            return false;
+        }
+
+        let statistics = node.transport_statistics();
+        let response = GetTransportStatisticsResponse {
+            transfer_statistics: to_io_statistics(&statistics.transfers),
+            network_interface_statistics: statistics
+                .interfaces
+                .iter()
+                .map(to_io_statistics)
+                .collect(),
+        };
+        send_response(node, token, milliseconds(1000), &response);
+        true
+    }
+}
+
+fn to_io_statistics(statistics: &IoStatistics) -> IOStatistics {
+    IOStatistics {
+        num_emitted: statistics.emitted,
+        num_received: statistics.received,
+        num_errored: statistics.errored,
+    }
+}
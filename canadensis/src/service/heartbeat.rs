@@ -1,5 +1,6 @@
-use crate::core::time::milliseconds;
+use crate::core::time::{milliseconds, Clock};
 use crate::core::Priority;
+use crate::health::HealthMonitor;
 use crate::{nb, Node, PublishError, StartSendError, Transmitter};
 use canadensis_data_types::uavcan::node::health_1_0::Health;
 use canadensis_data_types::uavcan::node::heartbeat_1_0::{Heartbeat, SUBJECT};
@@ -7,8 +8,12 @@ use canadensis_data_types::uavcan::node::mode_1_0::Mode;
 use core::marker::PhantomData;
 
 /// Publishes heartbeat messages
+///
+/// The health and vendor-specific status code in each heartbeat come from a
+/// [`HealthMonitor`], which is updated just before each heartbeat is published.
 pub struct HeartbeatService<N> {
     heartbeat: Heartbeat,
+    health: HealthMonitor,
     _node: PhantomData<N>,
 }
 
@@ -37,6 +42,7 @@ where
 
         Ok(Self {
             heartbeat: heatbeat,
+            health: HealthMonitor::new(),
             _node: PhantomData,
         })
     }
@@ -46,12 +52,25 @@ where
         self.heartbeat.mode = mode;
     }
     /// Sets the health status that will be reported in the heartbeat messages
+    ///
+    /// The reported health may be more severe if a watchdog has lapsed or a transport check has
+    /// failed.
     pub fn set_health(&mut self, health: Health) {
-        self.heartbeat.health = health;
+        self.health.set_health(health);
     }
     /// Sets the vendor-specific status code that will be reported in the heartbeat messages
     pub fn set_status_code(&mut self, status: u8) {
-        self.heartbeat.vendor_specific_status_code = status;
+        self.health.set_status_code(status);
+    }
+
+    /// Returns a reference to the health monitor
+    pub fn health_monitor(&self) -> &HealthMonitor {
+        &self.health
+    }
+    /// Returns a mutable reference to the health monitor, which can be used to add and kick
+    /// watchdogs
+    pub fn health_monitor_mut(&mut self) -> &mut HealthMonitor {
+        &mut self.health
     }
 
     /// Publishes a heartbeat message
@@ -61,7 +80,13 @@ where
         &mut self,
         node: &mut N,
     ) -> nb::Result<(), PublishError<<N::Transmitter as Transmitter<N::Clock>>::Error>> {
+        let now = node.clock_mut().now();
+        self.health.update(now, &node.transport_statistics());
+        self.heartbeat.health = self.health.health();
+        self.heartbeat.vendor_specific_status_code = self.health.status_code();
         self.heartbeat.uptime = self.heartbeat.uptime.saturating_add(1);
-        node.publish(SUBJECT, &self.heartbeat)
+        let result = node.publish(SUBJECT, &self.heartbeat);
+        self.health.record_heartbeat(result.is_ok());
+        result
     }
 }
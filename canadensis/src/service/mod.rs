@@ -4,6 +4,9 @@
 /// Handles GetInfo requests
 pub mod get_info;
 
+/// Handles GetTransportStatistics requests
+pub mod get_transport_statistics;
+
 /// Generate heartbeat messages
 pub mod heartbeat;
 
@@ -15,3 +18,6 @@ pub mod port_list;
 
 /// Register server
 pub mod register_server;
+
+/// Generic server for one service
+pub mod server;
@@ -31,10 +31,12 @@ where
     ///
     /// # Panics
     ///
-    /// This function will panic if the message size is larger than the MTU of the node's transmitter.
+    /// This function will panic if the request message size is larger than the MTU of the node's
+    /// transmitter.
     pub fn new(node: &mut N, unique_id: [u8; 16]) -> Result<Self, NewError<N>> {
+        // The request (without a node ID) must fit into one frame. Responses may be longer.
         debug_assert!(
-            M::PAYLOAD_SIZE_MAX <= node.transmitter().mtu(),
+            M::with_unique_id(&unique_id).size_bits().div_ceil(8) <= node.transmitter().mtu(),
             "Can't fit transfer into one frame"
         );
 
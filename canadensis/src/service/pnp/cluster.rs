@@ -0,0 +1,1031 @@
+//!
+//! A plug-and-play node ID allocator that is replicated on a cluster of allocators
+//!
+//! Two to five allocators keep the same allocation table using the Raft consensus algorithm, as
+//! described in the documentation of the `uavcan.pnp.cluster` data types. The allocators find
+//! each other with `uavcan.pnp.cluster.Discovery` messages and replicate the table using the
+//! `uavcan.pnp.cluster.AppendEntries` and `uavcan.pnp.cluster.RequestVote` services.
+//!
+//! The Raft log is the allocation table. Only the leader answers allocation requests, and only
+//! after all entries in its log have been replicated to a majority of the cluster. When the
+//! leader fails, the other allocators elect a new leader, so node ID allocation continues as long
+//! as a majority of the allocators are running.
+//!
+//! An allocation request that uses version 1.0 of the allocation message contains only a 48-bit
+//! hash of the unique ID. The log stores these allocations with a pseudo unique ID: the hash,
+//! padded with zeros on the left.
+//!
+//! To use an allocator, pass its [`handler`](PnpClusterService::handler) (or a chain that
+//! includes it) to [`Node::receive`](crate::Node::receive), and call
+//! [`poll`](PnpClusterService::poll) frequently.
+//!
+
+use crate::core::transport::Transport;
+use crate::service::pnp::server::{publish_error, start_allocator, Assignment, UniqueId};
+use crate::service::pnp::storage::{
+    apply_cluster_record, ClusterRecord, ClusterStorage, NoStorage,
+};
+use crate::service::pnp::{AllocationMessage, NewError, Random};
+use crate::{Node, ResponseToken, ServiceToken, StartSendError, TransferHandler};
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use canadensis_core::time::{milliseconds, Clock, MicrosecondDuration32, Microseconds32};
+use canadensis_core::transfer::{MessageTransfer, ServiceTransfer};
+use canadensis_core::{Priority, ServiceSubscribeError};
+use canadensis_data_types::uavcan::node::heartbeat_1_0;
+use canadensis_data_types::uavcan::node::id_1_0::ID;
+use canadensis_data_types::uavcan::pnp::cluster::append_entries_1_0::{
+    self, AppendEntriesRequest, AppendEntriesResponse,
+};
+use canadensis_data_types::uavcan::pnp::cluster::discovery_1_0::{self, Discovery};
+use canadensis_data_types::uavcan::pnp::cluster::entry_1_0::Entry;
+use canadensis_data_types::uavcan::pnp::cluster::request_vote_1_0::{
+    self, RequestVoteRequest, RequestVoteResponse,
+};
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_1_0::NodeIDAllocationData as Data1;
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_2_0::NodeIDAllocationData as Data2;
+use canadensis_encoding::{DataType, Deserialize};
+use core::cmp;
+use core::convert::TryInto;
+use core::marker::PhantomData;
+use l0g::{debug, warn};
+use num_traits::Bounded;
+
+/// The minimum election timeout (exclusive), in microseconds
+const ELECTION_TIMEOUT_MIN_US: u32 =
+    AppendEntriesRequest::DEFAULT_MIN_ELECTION_TIMEOUT as u32 * 1_000_000;
+/// The maximum election timeout (inclusive), in microseconds
+const ELECTION_TIMEOUT_MAX_US: u32 =
+    AppendEntriesRequest::DEFAULT_MAX_ELECTION_TIMEOUT as u32 * 1_000_000;
+/// The interval between discovery messages, in milliseconds
+const DISCOVERY_PERIOD_MS: u32 = Discovery::BROADCASTING_PERIOD as u32 * 1000;
+
+/// An entry in the replicated log, which is one row of the allocation table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LogEntry {
+    /// The term when the leader added this entry
+    pub term: u32,
+    /// The unique ID of the node, a pseudo unique ID, or zeros if the node was found on the
+    /// bus instead of sending an allocation request
+    pub unique_id: [u8; 16],
+    /// The allocated node ID
+    pub node_id: u16,
+}
+
+impl LogEntry {
+    /// The entry at index 0, which every log starts with
+    ///
+    /// This entry is not an allocation. It is never stored or sent to other allocators.
+    pub const INITIAL: LogEntry = LogEntry {
+        term: 0,
+        unique_id: [0; 16],
+        node_id: 0,
+    };
+
+    /// Returns true if this entry belongs to the node that sent an allocation request
+    fn matches(&self, unique_id: UniqueId) -> bool {
+        self.unique_id == pseudo_unique_id(unique_id) || unique_id.matches(self.assignment())
+    }
+
+    /// Converts this entry into an assignment, interpreting pseudo unique IDs as hashes
+    fn assignment(&self) -> Assignment {
+        if self.unique_id == [0; 16] {
+            Assignment::Reserved
+        } else if self.unique_id[..10] == [0; 10] {
+            let mut hash = [0u8; 8];
+            hash[..6].copy_from_slice(&self.unique_id[10..]);
+            Assignment::AssignedHash(u64::from_le_bytes(hash))
+        } else {
+            Assignment::Assigned(self.unique_id)
+        }
+    }
+}
+
+impl From<&Entry> for LogEntry {
+    fn from(entry: &Entry) -> Self {
+        LogEntry {
+            term: entry.term,
+            unique_id: entry.unique_id,
+            node_id: entry.node_id.value,
+        }
+    }
+}
+
+impl From<LogEntry> for Entry {
+    fn from(entry: LogEntry) -> Self {
+        Entry {
+            term: entry.term,
+            unique_id: entry.unique_id,
+            node_id: ID {
+                value: entry.node_id,
+            },
+        }
+    }
+}
+
+/// The role of an allocator in the cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Role {
+    /// Accepts log entries from the leader
+    Follower,
+    /// Asks the other allocators to vote for it to become the leader
+    Candidate,
+    /// Answers allocation requests and replicates the log to the other allocators
+    Leader,
+}
+
+/// Another allocator in the cluster
+struct Member {
+    node_id: u16,
+    /// The index of the next log entry to send to this allocator (leader only)
+    next_index: u16,
+    /// The highest log index known to be replicated on this allocator (leader only)
+    match_index: u16,
+    /// The previous log index in the last AppendEntries request sent to this allocator, and true
+    /// if the request contained an entry
+    in_flight: Option<(u16, bool)>,
+    /// True if this allocator voted for this allocator in the current election (candidate only)
+    vote_granted: bool,
+}
+
+/// A plug-and-play allocator that replicates its allocation table on a cluster of allocators
+///
+/// Each allocator in the cluster must have a node ID and must be configured with the same
+/// cluster size. The other allocators are found automatically.
+///
+/// Allocators in a cluster of two can only make progress when both are running. A cluster of
+/// three or more tolerates the failure of a minority of its allocators.
+///
+/// By default, the log and voting state are only kept in memory. Use
+/// [`with_storage`](Self::with_storage) to keep them in persistent storage. An allocator that
+/// restarts without storage gets the log again from the leader, but Raft's safety guarantees
+/// only hold if allocators do not forget their votes.
+pub struct PnpClusterService<N, S: ClusterStorage = NoStorage> {
+    /// The node ID of this allocator
+    node_id: u16,
+    /// The number of allocators in the cluster, including this one
+    cluster_size: u8,
+    /// The other allocators that have been discovered
+    members: Vec<Member>,
+    role: Role,
+    /// The node ID of the current leader, if known
+    leader: Option<u16>,
+    /// The current term (persistent)
+    term: u32,
+    /// The allocator that received this allocator's vote in the current term (persistent)
+    voted_for: Option<u16>,
+    /// The log (persistent), starting with LogEntry::INITIAL
+    log: Vec<LogEntry>,
+    /// The index of the highest entry known to be committed
+    commit_index: u16,
+    /// Node IDs that have been seen sending heartbeats
+    online_nodes: BTreeSet<u16>,
+    /// The time when this allocator will start an election, or None before the first poll
+    election_deadline: Option<Microseconds32>,
+    /// The time to send the next AppendEntries request (leader only)
+    next_append: Microseconds32,
+    /// The index in members of the allocator to send the next AppendEntries request to
+    next_member: usize,
+    /// The time to send the next periodic discovery message, or None to send one immediately
+    next_discovery: Option<Microseconds32>,
+    /// True if a discovery message should be sent because another allocator does not know all
+    /// allocators
+    discovery_requested: bool,
+    append_token: ServiceToken<AppendEntriesRequest>,
+    vote_token: ServiceToken<RequestVoteRequest>,
+    random: Random,
+    storage: S,
+    /// The first storage error since the last call to poll
+    storage_error: Option<S::Error>,
+    _node: PhantomData<N>,
+}
+
+impl<N: Node> PnpClusterService<N, NoStorage> {
+    /// Creates an allocator, subscribes to allocation, heartbeat, and discovery messages, and sets
+    /// up the cluster services
+    ///
+    /// * `cluster_size`: The number of allocators in the cluster, including this one. All
+    ///   allocators in the cluster must use the same value.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the node is anonymous, or if `cluster_size` is less than 2 or
+    /// greater than 5.
+    pub fn new(node: &mut N, cluster_size: u8) -> Result<Self, NewError<N>> {
+        assert!(
+            (2..=Discovery::MAX_CLUSTER_SIZE).contains(&cluster_size),
+            "Cluster size must be between 2 and 5"
+        );
+        let node_id: u16 = node
+            .node_id()
+            .expect("An allocator in a cluster must have a node ID")
+            .into();
+
+        start_allocator(node)?;
+
+        node.subscribe_message(
+            discovery_1_0::SUBJECT,
+            Discovery::EXTENT_BYTES.unwrap() as usize,
+            milliseconds(1000),
+        )
+        .map_err(|err| NewError::Subscribe(err))?;
+        node.start_publishing(
+            discovery_1_0::SUBJECT,
+            milliseconds(1000),
+            Priority::Slow.into(),
+        )
+        .map_err(publish_error)?;
+
+        subscribe_request::<N>(
+            node,
+            append_entries_1_0::SERVICE,
+            AppendEntriesRequest::EXTENT_BYTES.unwrap() as usize,
+        )?;
+        subscribe_request::<N>(
+            node,
+            request_vote_1_0::SERVICE,
+            RequestVoteRequest::EXTENT_BYTES.unwrap() as usize,
+        )?;
+        let append_token = start_sending_requests::<N, AppendEntriesRequest>(
+            node,
+            append_entries_1_0::SERVICE,
+            AppendEntriesResponse::EXTENT_BYTES.unwrap() as usize,
+        )?;
+        let vote_token = start_sending_requests::<N, RequestVoteRequest>(
+            node,
+            request_vote_1_0::SERVICE,
+            RequestVoteResponse::EXTENT_BYTES.unwrap() as usize,
+        )?;
+
+        Ok(PnpClusterService {
+            node_id,
+            cluster_size,
+            members: Vec::new(),
+            role: Role::Follower,
+            leader: None,
+            term: 0,
+            voted_for: None,
+            log: alloc::vec![LogEntry::INITIAL],
+            commit_index: 0,
+            online_nodes: BTreeSet::new(),
+            election_deadline: None,
+            next_append: Microseconds32::from_ticks(0),
+            next_member: 0,
+            next_discovery: None,
+            discovery_requested: false,
+            append_token,
+            vote_token,
+            random: Random::new(&node_id.to_le_bytes()),
+            storage: NoStorage,
+            storage_error: None,
+            _node: PhantomData,
+        })
+    }
+
+    /// Loads the log and voting state in `storage` and returns an allocator that stores changes
+    /// there
+    pub fn with_storage<S>(self, mut storage: S) -> Result<PnpClusterService<N, S>, S::Error>
+    where
+        S: ClusterStorage,
+    {
+        let mut term_record = None;
+        let mut log = self.log;
+        storage.load_cluster(|record| apply_cluster_record(record, &mut term_record, &mut log))?;
+        let (term, voted_for) = match term_record {
+            Some(ClusterRecord::Term { term, voted_for }) => (term, voted_for),
+            _ => (0, None),
+        };
+        Ok(PnpClusterService {
+            node_id: self.node_id,
+            cluster_size: self.cluster_size,
+            members: self.members,
+            role: self.role,
+            leader: self.leader,
+            term,
+            voted_for,
+            log,
+            commit_index: self.commit_index,
+            online_nodes: self.online_nodes,
+            election_deadline: self.election_deadline,
+            next_append: self.next_append,
+            next_member: self.next_member,
+            next_discovery: self.next_discovery,
+            discovery_requested: self.discovery_requested,
+            append_token: self.append_token,
+            vote_token: self.vote_token,
+            random: self.random,
+            storage,
+            storage_error: None,
+            _node: PhantomData,
+        })
+    }
+}
+
+impl<N: Node, S: ClusterStorage> PnpClusterService<N, S> {
+    /// Returns a handler for the allocator
+    pub fn handler(&mut self) -> PnpClusterServiceHandler<'_, N, S> {
+        PnpClusterServiceHandler { cluster: self }
+    }
+
+    /// Sends discovery messages, starts elections, and (if this allocator is the leader) sends
+    /// log entries to the other allocators
+    ///
+    /// This function should be called frequently, at least every few milliseconds.
+    ///
+    /// If the storage failed since the last call to this function (including while the handler
+    /// was handling incoming transfers), this function returns the first error. The change that
+    /// could not be stored does not take effect: the allocator does not grant a vote, accept a
+    /// log entry, or move to a new term that it could not store. The allocator keeps running
+    /// and tries again when it gets another request.
+    pub fn poll(&mut self, node: &mut N) -> Result<(), S::Error> {
+        let now = node.clock_mut().now();
+        self.publish_discovery(node, now);
+        match self.role {
+            Role::Leader => {
+                self.add_online_node();
+                self.send_append_entries(node, now);
+            }
+            Role::Follower | Role::Candidate => {
+                let deadline = match self.election_deadline {
+                    Some(deadline) => deadline,
+                    None => {
+                        let deadline = now + election_timeout(&mut self.random);
+                        self.election_deadline = Some(deadline);
+                        deadline
+                    }
+                };
+                if now >= deadline {
+                    self.start_election(node, now);
+                }
+            }
+        }
+        match self.storage_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the current role of this allocator
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Returns the node ID of the current leader, if it is known
+    pub fn leader(&self) -> Option<<N::Transport as Transport>::NodeId> {
+        self.leader.and_then(|id| id.try_into().ok())
+    }
+
+    /// Returns the current term
+    pub fn term(&self) -> u32 {
+        self.term
+    }
+
+    /// Returns the node IDs of the other allocators that have been discovered
+    pub fn known_allocators(
+        &self,
+    ) -> impl Iterator<Item = <N::Transport as Transport>::NodeId> + use<'_, N, S> {
+        self.members
+            .iter()
+            .filter_map(|member| member.node_id.try_into().ok())
+    }
+
+    /// Returns an iterator over the committed log entries, which are the allocations that this
+    /// allocator knows are permanent
+    pub fn allocations(&self) -> impl Iterator<Item = LogEntry> + use<'_, N, S> {
+        self.log[1..=usize::from(self.commit_index)].iter().copied()
+    }
+
+    /// Returns a reference to the storage
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+    /// Returns a mutable reference to the storage
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    fn last_index(&self) -> u16 {
+        (self.log.len() - 1) as u16
+    }
+
+    fn last_term(&self) -> u32 {
+        self.log.last().map(|entry| entry.term).unwrap_or(0)
+    }
+
+    /// The number of allocators, including this one, needed to elect a leader or commit an entry
+    fn majority(&self) -> usize {
+        usize::from(self.cluster_size) / 2 + 1
+    }
+
+    /// Returns true if this allocator is the leader and all entries in its log are committed
+    fn can_allocate(&self) -> bool {
+        self.role == Role::Leader && self.commit_index == self.last_index()
+    }
+
+    /// Stores a new term and vote, and then changes them
+    fn set_term(&mut self, term: u32, voted_for: Option<u16>) -> Result<(), S::Error> {
+        self.storage
+            .store_cluster(ClusterRecord::Term { term, voted_for })?;
+        self.term = term;
+        self.voted_for = voted_for;
+        Ok(())
+    }
+
+    /// Stores an entry at an index, and then puts it in the log, removing any entries after it
+    fn store_entry(&mut self, index: u16, entry: LogEntry) -> Result<(), S::Error> {
+        self.storage
+            .store_cluster(ClusterRecord::Entry { index, entry })?;
+        self.log.truncate(usize::from(index));
+        self.log.push(entry);
+        Ok(())
+    }
+
+    /// Adds an entry for the current term at the end of the log (leader only)
+    fn append(&mut self, node_id: u16, unique_id: [u8; 16]) {
+        let entry = LogEntry {
+            term: self.term,
+            unique_id,
+            node_id,
+        };
+        if let Err(e) = self.store_entry(self.last_index() + 1, entry) {
+            self.storage_failed(e);
+        }
+    }
+
+    /// Logs a storage error and keeps it until the next call to poll
+    fn storage_failed(&mut self, error: S::Error) {
+        warn!("Failed to store cluster state: {:?}", error);
+        if self.storage_error.is_none() {
+            self.storage_error = Some(error);
+        }
+    }
+
+    /// Handles a term number from another allocator, and becomes a follower if it is newer
+    /// than the current term
+    ///
+    /// This function returns false if the term could not be stored.
+    fn observe_term(&mut self, term: u32) -> bool {
+        if term > self.term {
+            if let Err(e) = self.set_term(term, None) {
+                self.storage_failed(e);
+                return false;
+            }
+            self.role = Role::Follower;
+            self.leader = None;
+        }
+        true
+    }
+
+    /// Adds an allocator to the cluster if it is not already known
+    fn add_member(&mut self, node_id: u16) {
+        if node_id == self.node_id || self.members.iter().any(|member| member.node_id == node_id) {
+            return;
+        }
+        if self.members.len() + 1 >= usize::from(self.cluster_size) {
+            warn!("Ignoring an allocator because the cluster is already full");
+            return;
+        }
+        self.members.push(Member {
+            node_id,
+            next_index: self.last_index() + 1,
+            match_index: 0,
+            in_flight: None,
+            vote_granted: false,
+        });
+    }
+
+    fn reset_election_timeout(&mut self, now: Microseconds32) {
+        self.election_deadline = Some(now + election_timeout(&mut self.random));
+    }
+
+    fn publish_discovery(&mut self, node: &mut N, now: Microseconds32) {
+        let complete = self.members.len() + 1 == usize::from(self.cluster_size);
+        let periodic = !complete && self.next_discovery.is_none_or(|next| now >= next);
+        if !(periodic || self.discovery_requested) {
+            return;
+        }
+        let mut known_nodes = heapless::Vec::new();
+        known_nodes
+            .push(ID {
+                value: self.node_id,
+            })
+            .ok();
+        for member in &self.members {
+            known_nodes
+                .push(ID {
+                    value: member.node_id,
+                })
+                .ok();
+        }
+        let message = Discovery {
+            configured_cluster_size: self.cluster_size,
+            known_nodes,
+        };
+        node.publish(discovery_1_0::SUBJECT, &message).ok();
+        self.discovery_requested = false;
+        self.next_discovery = Some(now + milliseconds(DISCOVERY_PERIOD_MS));
+    }
+
+    fn start_election(&mut self, node: &mut N, now: Microseconds32) {
+        self.reset_election_timeout(now);
+        // An election can't succeed until enough allocators are known. Waiting also avoids
+        // storing a new term after every timeout.
+        if self.members.len() + 1 < self.majority() {
+            return;
+        }
+        if let Err(e) = self.set_term(self.term.saturating_add(1), Some(self.node_id)) {
+            self.storage_failed(e);
+            return;
+        }
+        debug!("Starting election for term {}", self.term);
+        self.role = Role::Candidate;
+        self.leader = None;
+        let request = RequestVoteRequest {
+            term: self.term,
+            last_log_term: self.last_term(),
+            last_log_index: self.last_index(),
+        };
+        for member in self.members.iter_mut() {
+            member.vote_granted = false;
+            if let Ok(destination) = member.node_id.try_into() {
+                node.send_request(&self.vote_token, &request, destination)
+                    .ok();
+            }
+        }
+    }
+
+    fn become_leader(&mut self, now: Microseconds32) {
+        debug!("Became leader for term {}", self.term);
+        self.role = Role::Leader;
+        self.leader = Some(self.node_id);
+        let next_index = self.last_index() + 1;
+        for member in self.members.iter_mut() {
+            member.next_index = next_index;
+            member.match_index = 0;
+            member.in_flight = None;
+        }
+        // The log needs an entry for this allocator. Entries from earlier terms can only be
+        // committed along with an entry from the current term, so this also adds an entry if
+        // any are not committed.
+        let has_own_entry = self.log[1..]
+            .iter()
+            .any(|entry| entry.node_id == self.node_id);
+        if !has_own_entry || self.commit_index != self.last_index() {
+            self.append(self.node_id, [0; 16]);
+        }
+        self.next_append = now;
+    }
+
+    /// Adds an entry for a node that has been seen on the bus without an allocation, if the
+    /// log has no uncommitted entries (leader only)
+    fn add_online_node(&mut self) {
+        if !self.can_allocate() {
+            return;
+        }
+        let log = &self.log[1..];
+        let unknown = self
+            .online_nodes
+            .iter()
+            .copied()
+            .find(|&id| !log.iter().any(|entry| entry.node_id == id));
+        if let Some(id) = unknown {
+            self.append(id, [0; 16]);
+        }
+    }
+
+    /// Sends an AppendEntries request to the next allocator, if it is time
+    ///
+    /// The leader sends requests to one allocator at a time, so that each allocator gets a
+    /// request at least twice per minimum election timeout.
+    fn send_append_entries(&mut self, node: &mut N, now: Microseconds32) {
+        if now < self.next_append || self.members.is_empty() {
+            return;
+        }
+        let interval = ELECTION_TIMEOUT_MIN_US / 2 / u32::from(self.cluster_size - 1);
+        self.next_append = now + MicrosecondDuration32::from_ticks(interval);
+
+        self.next_member = (self.next_member + 1) % self.members.len();
+        let last_index = self.last_index();
+        let member = &mut self.members[self.next_member];
+        member.next_index = cmp::min(member.next_index, last_index + 1);
+        let prev_log_index = member.next_index - 1;
+        let mut entries = heapless::Vec::new();
+        if let Some(entry) = self.log.get(usize::from(member.next_index)) {
+            entries.push(Entry::from(*entry)).ok();
+        }
+        member.in_flight = Some((prev_log_index, !entries.is_empty()));
+        let request = AppendEntriesRequest {
+            term: self.term,
+            prev_log_term: self.log[usize::from(prev_log_index)].term,
+            prev_log_index,
+            leader_commit: self.commit_index,
+            entries,
+        };
+        if let Ok(destination) = member.node_id.try_into() {
+            node.send_request(&self.append_token, &request, destination)
+                .ok();
+        }
+    }
+
+    /// Commits the highest entry from the current term that a majority of the cluster has
+    fn update_commit_index(&mut self) {
+        let majority = self.majority();
+        for index in (self.commit_index + 1..=self.last_index()).rev() {
+            if self.log[usize::from(index)].term != self.term {
+                continue;
+            }
+            let replicas = 1 + self
+                .members
+                .iter()
+                .filter(|member| member.match_index >= index)
+                .count();
+            if replicas >= majority {
+                self.commit_index = index;
+                break;
+            }
+        }
+    }
+
+    /// Returns a node ID that is not allocated and has not been seen on the bus
+    ///
+    /// The search starts at the preferred node ID and goes upward, stopping before the two
+    /// highest node IDs (which are reserved for network maintenance tools), then goes downward
+    /// from the preferred node ID.
+    fn free_node_id(&self, preferred: u16) -> Option<u16> {
+        let max: u16 = <<N::Transport as Transport>::NodeId as Bounded>::max_value().into();
+        let tools = max - 1;
+        let preferred = cmp::min(preferred, max);
+        let is_free = |id: &u16| {
+            !self.log[1..].iter().any(|entry| entry.node_id == *id)
+                && !self.online_nodes.contains(id)
+        };
+        (preferred..tools)
+            .find(is_free)
+            .or_else(|| (0..cmp::min(preferred, tools)).rev().find(is_free))
+    }
+}
+
+/// Handler for the allocator
+pub struct PnpClusterServiceHandler<'a, N, S: ClusterStorage = NoStorage> {
+    cluster: &'a mut PnpClusterService<N, S>,
+}
+
+impl<N: Node, S: ClusterStorage> PnpClusterServiceHandler<'_, N, S> {
+    fn handle_allocation_message<
+        N2: Node<Transport = N::Transport>,
+        M: AllocationMessage<N::Transport>,
+    >(
+        &mut self,
+        node: &mut N2,
+        message: M,
+        unique_id: UniqueId,
+        preferred: u16,
+    ) {
+        let cluster = &mut *self.cluster;
+        if !cluster.can_allocate() {
+            return;
+        }
+        let entry = cluster.log[1..]
+            .iter()
+            .find(|entry| entry.matches(unique_id));
+        match entry {
+            Some(entry) => {
+                if let Ok(id) = entry.node_id.try_into() {
+                    let message = message.with_node_id(id);
+                    node.publish(M::SUBJECT, &message).ok();
+                }
+            }
+            None => {
+                let id = match cluster.free_node_id(preferred) {
+                    Some(id) => id,
+                    None => {
+                        warn!("No free node IDs to allocate");
+                        return;
+                    }
+                };
+                // The leader responds when the allocatee sends another request after the entry
+                // has been committed
+                cluster.append(id, pseudo_unique_id(unique_id));
+            }
+        }
+    }
+
+    fn handle_append_entries<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        token: ResponseToken<N::Transport>,
+        source: u16,
+        request: AppendEntriesRequest,
+    ) {
+        let cluster = &mut *self.cluster;
+        if !cluster.observe_term(request.term) {
+            return;
+        }
+        let mut success = false;
+        if request.term == cluster.term {
+            // A candidate that finds the leader of its term becomes a follower
+            cluster.role = Role::Follower;
+            cluster.leader = Some(source);
+            cluster.add_member(source);
+            cluster.reset_election_timeout(node.clock_mut().now());
+
+            let prev_index = usize::from(request.prev_log_index);
+            success = cluster
+                .log
+                .get(prev_index)
+                .is_some_and(|entry| entry.term == request.prev_log_term);
+            if success {
+                for (index, entry) in (request.prev_log_index + 1..).zip(request.entries.iter()) {
+                    let entry = LogEntry::from(entry);
+                    // An existing entry with the same term is the same entry. A different term
+                    // means that the entry and all entries after it need to be replaced.
+                    if cluster
+                        .log
+                        .get(usize::from(index))
+                        .map(|existing| existing.term)
+                        != Some(entry.term)
+                    {
+                        if let Err(e) = cluster.store_entry(index, entry) {
+                            // Without a success response, the leader will send the entry again
+                            cluster.storage_failed(e);
+                            return;
+                        }
+                    }
+                }
+                let last_new_index = request.prev_log_index + request.entries.len() as u16;
+                let leader_commit = cmp::min(request.leader_commit, last_new_index);
+                cluster.commit_index = cmp::max(cluster.commit_index, leader_commit);
+            }
+        }
+        let response = AppendEntriesResponse {
+            term: cluster.term,
+            success,
+        };
+        node.send_response(token, milliseconds(1000), &response)
+            .ok();
+    }
+
+    fn handle_request_vote<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        token: ResponseToken<N::Transport>,
+        source: u16,
+        request: RequestVoteRequest,
+    ) {
+        let cluster = &mut *self.cluster;
+        if !cluster.observe_term(request.term) {
+            return;
+        }
+        let up_to_date = (request.last_log_term, request.last_log_index)
+            >= (cluster.last_term(), cluster.last_index());
+        let mut vote_granted = request.term == cluster.term
+            && cluster.voted_for.is_none_or(|id| id == source)
+            && up_to_date;
+        if vote_granted {
+            cluster.add_member(source);
+            if cluster.voted_for.is_none() {
+                if let Err(e) = cluster.set_term(cluster.term, Some(source)) {
+                    cluster.storage_failed(e);
+                    vote_granted = false;
+                }
+            }
+            if vote_granted {
+                cluster.reset_election_timeout(node.clock_mut().now());
+            }
+        }
+        let response = RequestVoteResponse {
+            term: cluster.term,
+            vote_granted,
+        };
+        node.send_response(token, milliseconds(1000), &response)
+            .ok();
+    }
+
+    fn handle_append_entries_response(&mut self, source: u16, response: AppendEntriesResponse) {
+        let cluster = &mut *self.cluster;
+        if !cluster.observe_term(response.term)
+            || cluster.role != Role::Leader
+            || response.term != cluster.term
+        {
+            return;
+        }
+        let member = match cluster
+            .members
+            .iter_mut()
+            .find(|member| member.node_id == source)
+        {
+            Some(member) => member,
+            None => return,
+        };
+        if let Some((prev_log_index, had_entry)) = member.in_flight.take() {
+            if response.success {
+                let matched = prev_log_index + u16::from(had_entry);
+                member.match_index = cmp::max(member.match_index, matched);
+                member.next_index = member.match_index + 1;
+                cluster.update_commit_index();
+            } else {
+                // The logs differ at the previous index. Try an earlier entry next time.
+                member.next_index = cmp::max(prev_log_index, 1);
+            }
+        }
+    }
+
+    fn handle_request_vote_response(
+        &mut self,
+        now: Microseconds32,
+        source: u16,
+        response: RequestVoteResponse,
+    ) {
+        let cluster = &mut *self.cluster;
+        if !cluster.observe_term(response.term)
+            || cluster.role != Role::Candidate
+            || response.term != cluster.term
+            || !response.vote_granted
+        {
+            return;
+        }
+        if let Some(member) = cluster
+            .members
+            .iter_mut()
+            .find(|member| member.node_id == source)
+        {
+            member.vote_granted = true;
+        }
+        let votes = 1 + cluster
+            .members
+            .iter()
+            .filter(|member| member.vote_granted)
+            .count();
+        if votes >= cluster.majority() {
+            cluster.become_leader(now);
+        }
+    }
+
+    fn handle_discovery(&mut self, source: u16, discovery: Discovery) {
+        let cluster = &mut *self.cluster;
+        if discovery.configured_cluster_size != cluster.cluster_size {
+            warn!(
+                "Ignoring discovery message with cluster size {}",
+                discovery.configured_cluster_size
+            );
+            return;
+        }
+        cluster.add_member(source);
+        for id in discovery.known_nodes.iter() {
+            cluster.add_member(id.value);
+        }
+        // Another allocator that does not know all allocators needs to find out about this one
+        if discovery.known_nodes.len() < usize::from(cluster.cluster_size) {
+            cluster.discovery_requested = true;
+        }
+    }
+}
+
+impl<N: Node, S: ClusterStorage> TransferHandler<N::Transport>
+    for PnpClusterServiceHandler<'_, N, S>
+{
+    fn handle_message<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        transfer: &MessageTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        let max: u16 = <<N::Transport as Transport>::NodeId as Bounded>::max_value().into();
+        if transfer.header.subject == <Data1 as AllocationMessage<N::Transport>>::SUBJECT {
+            // Requests are anonymous. Messages from other nodes are responses from allocators.
+            if transfer.header.source.is_some() {
+                return false;
+            }
+            if let Ok(message) = Data1::deserialize_from_bytes(&transfer.payload) {
+                let unique_id = UniqueId::Hash(message.unique_id_hash);
+                // Version 1.0 requests have no preferred node ID
+                self.handle_allocation_message(node, message, unique_id, max);
+                return true;
+            }
+        } else if transfer.header.subject == <Data2 as AllocationMessage<N::Transport>>::SUBJECT {
+            if transfer.header.source.is_some() {
+                return false;
+            }
+            if let Ok(message) = Data2::deserialize_from_bytes(&transfer.payload) {
+                let unique_id = UniqueId::Full(message.unique_id);
+                let preferred = message.node_id.value;
+                self.handle_allocation_message(node, message, unique_id, preferred);
+                return true;
+            }
+        } else if transfer.header.subject == discovery_1_0::SUBJECT {
+            if let Some(source) = transfer.header.source.clone() {
+                if let Ok(discovery) = Discovery::deserialize_from_bytes(&transfer.payload) {
+                    self.handle_discovery(source.into(), discovery);
+                    return true;
+                }
+            }
+        } else if transfer.header.subject == heartbeat_1_0::SUBJECT {
+            if let Some(id) = transfer.header.source.clone() {
+                if heartbeat_1_0::Heartbeat::deserialize_from_bytes(&transfer.payload).is_ok() {
+                    // The leader adds entries for these nodes
+                    self.cluster.online_nodes.insert(id.into());
+                    return false;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn handle_request<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        token: ResponseToken<N::Transport>,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        let source: u16 = transfer.header.source.clone().into();
+        if transfer.header.service == append_entries_1_0::SERVICE {
+            if let Ok(request) = AppendEntriesRequest::deserialize_from_bytes(&transfer.payload) {
+                self.handle_append_entries(node, token, source, request);
+                return true;
+            }
+        } else if transfer.header.service == request_vote_1_0::SERVICE {
+            if let Ok(request) = RequestVoteRequest::deserialize_from_bytes(&transfer.payload) {
+                self.handle_request_vote(node, token, source, request);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn handle_response<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        let source: u16 = transfer.header.source.clone().into();
+        if transfer.header.service == append_entries_1_0::SERVICE {
+            if let Ok(response) = AppendEntriesResponse::deserialize_from_bytes(&transfer.payload) {
+                self.handle_append_entries_response(source, response);
+                return true;
+            }
+        } else if transfer.header.service == request_vote_1_0::SERVICE {
+            if let Ok(response) = RequestVoteResponse::deserialize_from_bytes(&transfer.payload) {
+                let now = node.clock_mut().now();
+                self.handle_request_vote_response(now, source, response);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Returns the unique ID to store in the log for an allocation request
+///
+/// A 48-bit hash is padded with zeros on the left, as the specification recommends.
+fn pseudo_unique_id(unique_id: UniqueId) -> [u8; 16] {
+    match unique_id {
+        UniqueId::Full(id) => id,
+        UniqueId::Hash(hash) => {
+            let mut id = [0u8; 16];
+            id[10..].copy_from_slice(&hash.to_le_bytes()[..6]);
+            id
+        }
+    }
+}
+
+fn subscribe_request<N: Node>(
+    node: &mut N,
+    service: canadensis_core::ServiceId,
+    payload_size_max: usize,
+) -> Result<(), NewError<N>> {
+    node.subscribe_request(service, payload_size_max, milliseconds(1000))
+        .map_err(|err| match err {
+            ServiceSubscribeError::Transport(err) => NewError::Subscribe(err),
+            ServiceSubscribeError::Anonymous => unreachable!(), // the node has a node ID
+        })
+}
+
+fn start_sending_requests<N: Node, T: canadensis_encoding::Request>(
+    node: &mut N,
+    service: canadensis_core::ServiceId,
+    response_payload_size_max: usize,
+) -> Result<ServiceToken<T>, NewError<N>> {
+    node.start_sending_requests(
+        service,
+        milliseconds(1000),
+        response_payload_size_max,
+        Priority::Slow.into(),
+    )
+    .map_err(|err| match err {
+        StartSendError::Memory(_) => NewError::OutOfMemory,
+        StartSendError::Duplicate => NewError::Duplicate,
+        StartSendError::Transport(err) => NewError::Subscribe(err),
+        StartSendError::AnonymousRequest => unreachable!(), // the node has a node ID
+    })
+}
+
+/// Returns an election timeout that is greater than the minimum and less than or equal to the
+/// maximum
+fn election_timeout(random: &mut Random) -> MicrosecondDuration32 {
+    random.duration(
+        MicrosecondDuration32::from_ticks(ELECTION_TIMEOUT_MIN_US + 1),
+        MicrosecondDuration32::from_ticks(ELECTION_TIMEOUT_MAX_US + 1),
+    )
+}
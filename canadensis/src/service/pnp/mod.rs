@@ -4,7 +4,12 @@ pub mod client;
 /// Cyphal plug-and-play server
 pub mod server;
 
+pub mod cluster;
+
+pub mod storage;
+
 use crate::Node;
+use canadensis_core::time::{milliseconds, MicrosecondDuration32, Microseconds32};
 use canadensis_core::transport::{Receiver, Transmitter, Transport};
 use canadensis_core::SubjectId;
 use canadensis_data_types::uavcan::node::id_1_0;
@@ -115,6 +120,110 @@ where
     }
 }
 
+/// A xorshift pseudorandom number generator for plug-and-play timing
+///
+/// This spreads out the times when different nodes send messages. It is not suitable for
+/// anything that needs unpredictable numbers.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// Creates a generator with a seed derived from some bytes, usually a unique ID or node ID
+    ///
+    /// Generators created from the same bytes produce the same numbers.
+    pub fn new(seed: &[u8]) -> Self {
+        let mut crc = CRCu64::crc64we();
+        crc.digest(seed);
+        // The state must not be zero
+        Random {
+            state: crc.get_crc() | 1,
+        }
+    }
+
+    /// Returns the next pseudorandom number
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Returns a duration that is greater than or equal to `min` and less than `max`
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `max` is not greater than `min`.
+    pub fn duration(
+        &mut self,
+        min: MicrosecondDuration32,
+        max: MicrosecondDuration32,
+    ) -> MicrosecondDuration32 {
+        let range_us = u64::from(max.ticks() - min.ticks());
+        let offset_us = (self.next_u64() % range_us) as u32;
+        min + MicrosecondDuration32::from_ticks(offset_us)
+    }
+}
+
+/// Decides when a plug-and-play client sends allocation requests
+///
+/// The first request is sent after a random delay of up to 1 second. Later requests are sent at
+/// random intervals of 600 to 1000 milliseconds. The random values are derived from the unique
+/// ID, so nodes that start at the same time do not send their requests at the same times.
+#[derive(Debug, Clone)]
+pub struct RequestTimer {
+    random: Random,
+    /// The time to send the next request, or None before the first call to `is_due`
+    next_request: Option<Microseconds32>,
+}
+
+impl RequestTimer {
+    /// The maximum delay before the first request
+    const INITIAL_DELAY_MAX_MS: u32 = 1000;
+    /// The minimum interval between requests
+    const INTERVAL_MIN_MS: u32 = 600;
+    /// The maximum interval between requests
+    const INTERVAL_MAX_MS: u32 = 1000;
+
+    /// Creates a timer for a client with the provided unique ID
+    pub fn new(unique_id: &[u8; 16]) -> Self {
+        RequestTimer {
+            random: Random::new(unique_id),
+            next_request: None,
+        }
+    }
+
+    /// Returns true if a request should be sent at the time `now`
+    ///
+    /// The first call starts the initial delay.
+    pub fn is_due(&mut self, now: Microseconds32) -> bool {
+        let next_request = match self.next_request {
+            Some(next_request) => next_request,
+            None => {
+                let delay = self
+                    .random
+                    .duration(milliseconds(0), milliseconds(Self::INITIAL_DELAY_MAX_MS));
+                *self.next_request.insert(now + delay)
+            }
+        };
+        now >= next_request
+    }
+
+    /// Schedules the next request after a random interval that starts at the time `now`
+    ///
+    /// This should be called after sending a request. With version 1.0 of the allocation
+    /// message, it should also be called after receiving any allocation message (from another
+    /// client, or for another client).
+    pub fn restart(&mut self, now: Microseconds32) {
+        let interval = self.random.duration(
+            milliseconds(Self::INTERVAL_MIN_MS),
+            milliseconds(Self::INTERVAL_MAX_MS),
+        );
+        self.next_request = Some(now + interval);
+    }
+}
+
 /// Error type returned by [`PnpClientService::new`].
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
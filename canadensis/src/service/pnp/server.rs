@@ -1,4 +1,5 @@
-use crate::service::pnp::{AllocationMessage, NewError};
+use crate::service::pnp::storage::{AllocationStorage, NoStorage};
+use crate::service::pnp::{crc_64we_48_bits, AllocationMessage, NewError};
 use crate::{Node, StartSendError, TransferHandler};
 use alloc::vec::Vec;
 use canadensis_core::time::milliseconds;
@@ -8,9 +9,10 @@ use canadensis_core::Priority;
 use canadensis_data_types::uavcan::node::heartbeat_1_0;
 use canadensis_data_types::uavcan::pnp::node_id_allocation_data_1_0::NodeIDAllocationData as Data1;
 use canadensis_data_types::uavcan::pnp::node_id_allocation_data_2_0::NodeIDAllocationData as Data2;
-use canadensis_encoding::Deserialize;
+use canadensis_encoding::{DataType, Deserialize};
 use core::convert::TryInto;
 use core::marker::PhantomData;
+use l0g::warn;
 use num_traits::Bounded;
 
 /// Defines the states of assignment
@@ -23,32 +25,46 @@ pub enum Assignment {
     Reserved,
     /// Fully assigned with unique ID known
     Assigned([u8; 16]),
+    /// Assigned to a node that sent a version 1.0 allocation request, which contains only
+    /// a 48-bit hash of the unique ID
+    AssignedHash(u64),
 }
 
 impl Assignment {
-    /// Returns true if the value is [`Assigned`] or [`Reserved`] and the value matches a predicate
+    /// Returns true if the value is [`Assigned`](Self::Assigned) and the unique ID matches
+    /// a predicate, or if the value is [`Reserved`](Self::Reserved) or
+    /// [`AssignedHash`](Self::AssignedHash)
     pub fn is_assigned_and(self, f: impl FnOnce([u8; 16]) -> bool) -> bool {
         match self {
             Assignment::Assigned(id) => f(id),
-            Assignment::Reserved => true,
+            Assignment::Reserved | Assignment::AssignedHash(_) => true,
             _ => false,
         }
     }
 
-    /// Returns true if the value is [`Assigned`] or [`Reserved`]
+    /// Returns true if the value is [`Assigned`](Self::Assigned),
+    /// [`AssignedHash`](Self::AssignedHash), or [`Reserved`](Self::Reserved)
     pub fn is_assigned(self) -> bool {
         self.is_assigned_and(|_| true)
     }
 }
 
 /// A plug-and-play allocation server
-pub struct PnpServerService<N, const C: usize> {
+///
+/// The server allocates the highest node ID that is not assigned or reserved. It reserves the
+/// node IDs of all nodes that send heartbeats, so it does not allocate node IDs that are
+/// already in use.
+///
+/// By default, the allocations are only kept in memory. Use [`with_storage`](Self::with_storage)
+/// to keep them in persistent storage.
+pub struct PnpServerService<N, const C: usize, S = NoStorage> {
     assignments: [Assignment; C],
+    storage: S,
     _node: PhantomData<N>,
 }
 
-impl<N: Node, const C: usize> PnpServerService<N, C> {
-    /// Creates a new plug-and-play server
+impl<N: Node, const C: usize> PnpServerService<N, C, NoStorage> {
+    /// Creates a new plug-and-play server and subscribes to allocation and heartbeat messages
     ///
     /// # Panics
     ///
@@ -62,66 +78,81 @@ impl<N: Node, const C: usize> PnpServerService<N, C> {
             "C must be the maximum value of the node ID type"
         );
 
-        node.subscribe_message(
-            <Data1 as AllocationMessage<N::Transport>>::SUBJECT,
-            <Data1 as AllocationMessage<N::Transport>>::PAYLOAD_SIZE_MAX,
-            milliseconds(1000),
-        )
-        .map_err(|err| NewError::Subscribe(err))?;
-
-        node.start_publishing(
-            <Data1 as AllocationMessage<N::Transport>>::SUBJECT,
-            milliseconds(1000),
-            Priority::Nominal.into(),
-        )
-        .map_err(|err| match err {
-            StartSendError::Memory(_) => NewError::OutOfMemory,
-            StartSendError::Duplicate => NewError::Duplicate,
-            StartSendError::Transport(err) => NewError::Publish(err),
-            StartSendError::AnonymousRequest => unreachable!(), // we are publishing a message, not a request
-        })?;
-
-        if <Data2 as AllocationMessage<N::Transport>>::PAYLOAD_SIZE_MAX <= node.transmitter().mtu()
-        {
-            node.subscribe_message(
-                <Data2 as AllocationMessage<N::Transport>>::SUBJECT,
-                <Data2 as AllocationMessage<N::Transport>>::PAYLOAD_SIZE_MAX,
-                milliseconds(1000),
-            )
-            .map_err(|err| NewError::Subscribe(err))?;
-
-            node.start_publishing(
-                <Data2 as AllocationMessage<N::Transport>>::SUBJECT,
-                milliseconds(1000),
-                Priority::Nominal.into(),
-            )
-            .map_err(|err| match err {
-                StartSendError::Memory(_) => NewError::OutOfMemory,
-                StartSendError::Duplicate => NewError::Duplicate,
-                StartSendError::Transport(err) => NewError::Publish(err),
-                StartSendError::AnonymousRequest => unreachable!(), // we are publishing a message, not a request
-            })?;
-        }
+        start_allocator(node)?;
 
         Ok(PnpServerService {
             assignments: [Assignment::Unassigned; C],
+            storage: NoStorage,
             _node: PhantomData,
         })
     }
 
+    /// Loads the allocations in `storage` and returns a server that stores new allocations there
+    ///
+    /// Stored assignments replace the assignments of the same node IDs in this server. Stored
+    /// node IDs that are too large for this server are ignored.
+    pub fn with_storage<S>(self, mut storage: S) -> Result<PnpServerService<N, C, S>, S::Error>
+    where
+        S: AllocationStorage,
+    {
+        let mut assignments = self.assignments;
+        storage.load(|node_id, assignment| {
+            if let Some(slot) = assignments.get_mut(usize::from(node_id)) {
+                *slot = assignment;
+            }
+        })?;
+        Ok(PnpServerService {
+            assignments,
+            storage,
+            _node: PhantomData,
+        })
+    }
+
+    /// Assigns a [`NodeId`] to a given unique ID. This will only have an effect if the [`NodeId`] has not yet been assigned or reserved.
+    ///
+    /// A server with storage uses [`try_assign`](#method.try_assign) instead, which reports
+    /// storage errors.
+    pub fn assign(&mut self, node_id: <N::Transport as Transport>::NodeId, unique_id: [u8; 16]) {
+        match self.try_assign(node_id, unique_id) {
+            Ok(()) => {}
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl<N: Node, const C: usize, S: AllocationStorage> PnpServerService<N, C, S> {
     /// Returns a handler for the server
-    pub fn handler(&mut self) -> PnpServerServiceHandler<'_, N, C> {
+    pub fn handler(&mut self) -> PnpServerServiceHandler<'_, N, C, S> {
         PnpServerServiceHandler { server: self }
     }
 
     /// Assigns a [`NodeId`] to a given unique ID. This will only have an effect if the [`NodeId`] has not yet been assigned or reserved.
-    pub fn assign(&mut self, node_id: <N::Transport as Transport>::NodeId, unique_id: [u8; 16]) {
+    ///
+    /// The assignment is stored before it takes effect. This function returns an error if the
+    /// storage fails.
+    pub fn try_assign(
+        &mut self,
+        node_id: <N::Transport as Transport>::NodeId,
+        unique_id: [u8; 16],
+    ) -> Result<(), S::Error> {
         let idx: usize = node_id.into().into();
         if self.assignments[idx].is_assigned() {
-            return;
+            return Ok(());
         }
 
+        self.storage
+            .store(idx as u16, Assignment::Assigned(unique_id))?;
         self.assignments[idx] = Assignment::Assigned(unique_id);
+        Ok(())
+    }
+
+    /// Returns a reference to the storage
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+    /// Returns a mutable reference to the storage
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
     }
 
     /// Get an iterator over the current assignments
@@ -131,7 +162,7 @@ impl<N: Node, const C: usize> PnpServerService<N, C> {
     /// This function should never panic despite the use of [`unwrap`] as the values are known to be sufficiently constrained
     pub fn assignments(
         &self,
-    ) -> impl Iterator<Item = (<N::Transport as Transport>::NodeId, Assignment)> + use<'_, N, C>
+    ) -> impl Iterator<Item = (<N::Transport as Transport>::NodeId, Assignment)> + use<'_, N, C, S>
     {
         self.assignments.iter().copied().enumerate().map(|x| {
             (
@@ -146,11 +177,11 @@ impl<N: Node, const C: usize> PnpServerService<N, C> {
 }
 
 /// Handler for the server
-pub struct PnpServerServiceHandler<'a, N, const C: usize> {
-    server: &'a mut PnpServerService<N, C>,
+pub struct PnpServerServiceHandler<'a, N, const C: usize, S = NoStorage> {
+    server: &'a mut PnpServerService<N, C, S>,
 }
 
-impl<N: Node, const C: usize> PnpServerServiceHandler<'_, N, C> {
+impl<N: Node, const C: usize, S: AllocationStorage> PnpServerServiceHandler<'_, N, C, S> {
     fn handle_allocation_message<
         N2: Node<Transport = N::Transport>,
         M: AllocationMessage<N::Transport>,
@@ -158,54 +189,76 @@ impl<N: Node, const C: usize> PnpServerServiceHandler<'_, N, C> {
         &mut self,
         node: &mut N2,
         message: M,
+        unique_id: UniqueId,
     ) {
-        let id = self
-            .server
-            .assignments
-            .iter()
-            .position(|x| x.is_assigned_and(|x| message.matches_unique_id(&x)));
-
-        // if no assignment is found, find the first unassigned address and assign it
-        let id = id.or_else(|| {
-            self.server
-                .assignments
-                .iter()
-                .rev()
-                .position(|x| !x.is_assigned())
-                .inspect(|id| self.server.assignments[*id] = Assignment::Reserved)
-        });
-
-        let id = id.map(|x| TryInto::<u16>::try_into(x).unwrap().try_into().unwrap());
-
-        if let Some(id) = id {
-            let message = message.with_node_id(id);
-            node.publish(M::SUBJECT, &message).ok();
-        }
+        let assignments = &mut self.server.assignments;
+        let id = match assignments.iter().position(|x| unique_id.matches(*x)) {
+            Some(id) => id,
+            None => {
+                // Assign the highest node ID that is not assigned or reserved
+                let id = match (0..C).rev().find(|&id| !assignments[id].is_assigned()) {
+                    Some(id) => id,
+                    None => {
+                        warn!("No free node IDs to allocate");
+                        return;
+                    }
+                };
+                let assignment = unique_id.assignment();
+                // Store the assignment first, so that the node ID is not allocated to a
+                // different node after a restart
+                #[allow(unused_variables)]
+                if let Err(e) = self.server.storage.store(id as u16, assignment) {
+                    warn!("Failed to store node ID allocation: {:?}", e);
+                    return;
+                }
+                assignments[id] = assignment;
+                id
+            }
+        };
+
+        let id = TryInto::<u16>::try_into(id).unwrap().try_into().unwrap();
+        let message = message.with_node_id(id);
+        node.publish(M::SUBJECT, &message).ok();
     }
 }
 
-impl<N: Node, const C: usize> TransferHandler<N::Transport> for PnpServerServiceHandler<'_, N, C> {
+impl<N: Node, const C: usize, S: AllocationStorage> TransferHandler<N::Transport>
+    for PnpServerServiceHandler<'_, N, C, S>
+{
     fn handle_message<N2: Node<Transport = N::Transport>>(
         &mut self,
         node: &mut N2,
         transfer: &MessageTransfer<Vec<u8>, N2::Transport>,
     ) -> bool {
         if transfer.header.subject == <Data1 as AllocationMessage<N::Transport>>::SUBJECT {
+            // Requests are anonymous. Messages from other nodes are responses from allocators.
+            if transfer.header.source.is_some() {
+                return false;
+            }
             if let Ok(message) = Data1::deserialize_from_bytes(&transfer.payload) {
-                self.handle_allocation_message(node, message);
+                let unique_id = UniqueId::Hash(message.unique_id_hash);
+                self.handle_allocation_message(node, message, unique_id);
                 return true;
             }
         } else if transfer.header.subject == <Data2 as AllocationMessage<N::Transport>>::SUBJECT {
+            if transfer.header.source.is_some() {
+                return false;
+            }
             if let Ok(message) = Data2::deserialize_from_bytes(&transfer.payload) {
-                self.handle_allocation_message(node, message);
+                let unique_id = UniqueId::Full(message.unique_id);
+                self.handle_allocation_message(node, message, unique_id);
                 return true;
             }
         } else if transfer.header.subject == heartbeat_1_0::SUBJECT {
             if let Some(id) = transfer.header.source.clone() {
-                if let Ok(_) = heartbeat_1_0::Heartbeat::deserialize_from_bytes(&transfer.payload) {
+                if heartbeat_1_0::Heartbeat::deserialize_from_bytes(&transfer.payload).is_ok() {
                     let id = Into::<u16>::into(id) as usize;
-                    if !self.server.assignments[id].is_assigned() {
-                        self.server.assignments[id] = Assignment::Reserved;
+                    // Reservations are not stored. The node will send heartbeats again after
+                    // the allocator restarts.
+                    if let Some(assignment) = self.server.assignments.get_mut(id) {
+                        if !assignment.is_assigned() {
+                            *assignment = Assignment::Reserved;
+                        }
                     }
                     return false;
                 }
@@ -215,3 +268,87 @@ impl<N: Node, const C: usize> TransferHandler<N::Transport> for PnpServerService
         false
     }
 }
+
+/// Subscribes to allocation messages and heartbeats, and starts publishing allocation messages
+///
+/// Version 2.0 allocation messages are only used if they fit into one frame.
+pub(super) fn start_allocator<N: Node>(node: &mut N) -> Result<(), NewError<N>> {
+    node.subscribe_message(
+        <Data1 as AllocationMessage<N::Transport>>::SUBJECT,
+        <Data1 as AllocationMessage<N::Transport>>::PAYLOAD_SIZE_MAX,
+        milliseconds(1000),
+    )
+    .map_err(|err| NewError::Subscribe(err))?;
+
+    node.start_publishing(
+        <Data1 as AllocationMessage<N::Transport>>::SUBJECT,
+        milliseconds(1000),
+        Priority::Nominal.into(),
+    )
+    .map_err(publish_error)?;
+
+    if <Data2 as AllocationMessage<N::Transport>>::PAYLOAD_SIZE_MAX <= node.transmitter().mtu() {
+        node.subscribe_message(
+            <Data2 as AllocationMessage<N::Transport>>::SUBJECT,
+            <Data2 as AllocationMessage<N::Transport>>::PAYLOAD_SIZE_MAX,
+            milliseconds(1000),
+        )
+        .map_err(|err| NewError::Subscribe(err))?;
+
+        node.start_publishing(
+            <Data2 as AllocationMessage<N::Transport>>::SUBJECT,
+            milliseconds(1000),
+            Priority::Nominal.into(),
+        )
+        .map_err(publish_error)?;
+    }
+
+    node.subscribe_message(
+        heartbeat_1_0::SUBJECT,
+        heartbeat_1_0::Heartbeat::EXTENT_BYTES.unwrap() as usize,
+        milliseconds(1000),
+    )
+    .map_err(|err| NewError::Subscribe(err))
+}
+
+pub(super) fn publish_error<N: Node>(
+    err: StartSendError<<N::Transmitter as Transmitter<N::Clock>>::Error>,
+) -> NewError<N> {
+    match err {
+        StartSendError::Memory(_) => NewError::OutOfMemory,
+        StartSendError::Duplicate => NewError::Duplicate,
+        StartSendError::Transport(err) => NewError::Publish(err),
+        StartSendError::AnonymousRequest => unreachable!(), // we are publishing a message, not a request
+    }
+}
+
+/// The unique ID from an allocation request
+#[derive(Clone, Copy)]
+pub(super) enum UniqueId {
+    /// The full unique ID from a version 2.0 request
+    Full([u8; 16]),
+    /// The 48-bit unique ID hash from a version 1.0 request
+    Hash(u64),
+}
+
+impl UniqueId {
+    /// Returns true if an assignment belongs to the node with this unique ID
+    pub(super) fn matches(self, assignment: Assignment) -> bool {
+        match (self, assignment) {
+            (UniqueId::Full(id), Assignment::Assigned(assigned)) => id == assigned,
+            (UniqueId::Full(id), Assignment::AssignedHash(hash)) => crc_64we_48_bits(&id) == hash,
+            (UniqueId::Hash(hash), Assignment::Assigned(assigned)) => {
+                crc_64we_48_bits(&assigned) == hash
+            }
+            (UniqueId::Hash(hash), Assignment::AssignedHash(assigned)) => hash == assigned,
+            _ => false,
+        }
+    }
+
+    pub(super) fn assignment(self) -> Assignment {
+        match self {
+            UniqueId::Full(id) => Assignment::Assigned(id),
+            UniqueId::Hash(hash) => Assignment::AssignedHash(hash),
+        }
+    }
+}
@@ -0,0 +1,548 @@
+//!
+//! Persistent storage for plug-and-play node ID allocations
+//!
+//! A [`PnpServerService`](super::server::PnpServerService) with storage writes each allocation
+//! to its [`AllocationStorage`] before responding, and loads the stored allocations when it
+//! starts. This keeps the allocator from giving a node ID that it has already allocated to a
+//! different node after it restarts.
+//!
+//! Storage is a journal of fixed-size records. Records are only appended, and a later record for
+//! a node ID replaces an earlier one.
+//!
+//! The same storage types also implement [`ClusterStorage`], which keeps the replicated log and
+//! voting state of a [`PnpClusterService`](super::cluster::PnpClusterService).
+//!
+
+// The record encoding is only used by the storage types, which are behind features
+#![cfg_attr(not(any(feature = "flash", feature = "std")), allow(dead_code))]
+
+#[cfg(feature = "std")]
+pub use self::file::FileStorage;
+#[cfg(feature = "flash")]
+pub use self::flash::{FlashStorage, FlashStorageError};
+
+use crate::service::pnp::cluster::LogEntry;
+use crate::service::pnp::server::Assignment;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::convert::TryInto;
+use core::fmt::Debug;
+use crc_any::CRCu32;
+
+/// The size of one stored allocation record, in bytes
+pub const RECORD_SIZE: usize = 32;
+
+/// Something that can store node ID assignments so that they persist when an allocator restarts
+pub trait AllocationStorage {
+    /// The error type
+    type Error: Debug;
+
+    /// Calls `f` with each stored node ID and its assignment, oldest first
+    fn load<F>(&mut self, f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(u16, Assignment);
+
+    /// Stores the assignment of a node ID
+    ///
+    /// When this function returns `Ok(())`, the record must survive a restart.
+    fn store(&mut self, node_id: u16, assignment: Assignment) -> Result<(), Self::Error>;
+}
+
+/// Something that can store the state of an allocator in a cluster so that it persists when the
+/// allocator restarts
+pub trait ClusterStorage {
+    /// The error type
+    type Error: Debug;
+
+    /// Calls `f` with each stored record, oldest first
+    fn load_cluster<F>(&mut self, f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(ClusterRecord);
+
+    /// Stores a record
+    ///
+    /// When this function returns `Ok(())`, the record must survive a restart.
+    fn store_cluster(&mut self, record: ClusterRecord) -> Result<(), Self::Error>;
+}
+
+/// A change to the persistent state of an allocator in a cluster
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClusterRecord {
+    /// The current term, and the allocator that this allocator voted for in that term
+    Term {
+        /// The term number
+        term: u32,
+        /// The node ID of the allocator that received this allocator's vote, if any
+        voted_for: Option<u16>,
+    },
+    /// A log entry, which replaces the entry at the same index and removes all entries after it
+    Entry {
+        /// The index of the entry in the log
+        index: u16,
+        /// The entry
+        entry: LogEntry,
+    },
+}
+
+/// Storage that does not store anything
+///
+/// Allocators that use this forget all allocations when they restart.
+#[derive(Debug, Default)]
+pub struct NoStorage;
+
+impl AllocationStorage for NoStorage {
+    type Error = Infallible;
+
+    fn load<F>(&mut self, _f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(u16, Assignment),
+    {
+        Ok(())
+    }
+
+    fn store(&mut self, _node_id: u16, _assignment: Assignment) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ClusterStorage for NoStorage {
+    type Error = Infallible;
+
+    fn load_cluster<F>(&mut self, _f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(ClusterRecord),
+    {
+        Ok(())
+    }
+
+    fn store_cluster(&mut self, _record: ClusterRecord) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "flash")]
+mod flash {
+    use super::{
+        apply_cluster_record, decode, encode, encode_cluster, AllocationStorage, ClusterRecord,
+        ClusterStorage, Record, RECORD_SIZE,
+    };
+    use crate::service::pnp::cluster::LogEntry;
+    use crate::service::pnp::server::Assignment;
+    use embedded_storage::nor_flash::NorFlash;
+
+    /// Allocation storage in a region of NOR flash
+    ///
+    /// Records are written one after another from the beginning of the region. Because an allocator
+    /// stores at most one record for each node ID that it allocates, a region with space for one
+    /// record per node ID never fills up.
+    ///
+    /// A record that was only partly written (for example, because power was lost) is skipped
+    /// when loading.
+    ///
+    /// An allocator in a cluster also stores a record each time its term changes. When the region
+    /// is full, [`store_cluster`](ClusterStorage::store_cluster) erases it and writes back only the
+    /// records that are still needed. If power is lost during that time, the stored log is lost and
+    /// the allocator gets it again from the other allocators.
+    pub struct FlashStorage<F> {
+        flash: F,
+        /// The offset of the region from the beginning of the flash
+        offset: u32,
+        /// The size of the region in bytes
+        size: u32,
+        /// The offset of the first erased record in the region, if it is known
+        end: Option<u32>,
+    }
+
+    impl<F: NorFlash> FlashStorage<F> {
+        /// Creates a storage that uses `size` bytes of `flash`, starting at `offset`
+        ///
+        /// The flash region is not erased. Existing records in the region will be loaded.
+        ///
+        /// # Panics
+        ///
+        /// This function panics if `offset` or `size` is not a multiple of the flash erase size,
+        /// or if [`RECORD_SIZE`] is not a multiple of the flash read and write sizes.
+        pub fn new(flash: F, offset: u32, size: u32) -> Self {
+            assert_eq!(offset as usize % F::ERASE_SIZE, 0, "Offset not aligned");
+            assert_eq!(size as usize % F::ERASE_SIZE, 0, "Size not aligned");
+            assert_eq!(RECORD_SIZE % F::WRITE_SIZE, 0, "Unsupported write size");
+            assert_eq!(RECORD_SIZE % F::READ_SIZE, 0, "Unsupported read size");
+            FlashStorage {
+                flash,
+                offset,
+                size,
+                end: None,
+            }
+        }
+
+        /// Erases the region, removing all stored allocations
+        pub fn erase(&mut self) -> Result<(), FlashStorageError<F::Error>> {
+            self.flash
+                .erase(self.offset, self.offset + self.size)
+                .map_err(FlashStorageError::Flash)?;
+            self.end = Some(0);
+            Ok(())
+        }
+
+        /// Returns the flash that this storage uses
+        pub fn into_inner(self) -> F {
+            self.flash
+        }
+
+        /// Reads each record until the first erased one, calls `f` with the valid records, and
+        /// returns the offset of the erased record
+        fn scan<G>(&mut self, mut f: G) -> Result<u32, FlashStorageError<F::Error>>
+        where
+            G: FnMut(Record),
+        {
+            let mut record = [0u8; RECORD_SIZE];
+            let mut position = 0;
+            while position + RECORD_SIZE as u32 <= self.size {
+                self.flash
+                    .read(self.offset + position, &mut record)
+                    .map_err(FlashStorageError::Flash)?;
+                if record.iter().all(|&byte| byte == 0xff) {
+                    break;
+                }
+                if let Some(record) = decode(&record) {
+                    f(record);
+                }
+                position += RECORD_SIZE as u32;
+            }
+            Ok(position)
+        }
+
+        /// Writes a record after the existing records
+        fn append(
+            &mut self,
+            record: &[u8; RECORD_SIZE],
+        ) -> Result<(), FlashStorageError<F::Error>> {
+            let end = match self.end {
+                Some(end) => end,
+                None => self.scan(|_| {})?,
+            };
+            if end + RECORD_SIZE as u32 > self.size {
+                return Err(FlashStorageError::Full);
+            }
+            self.flash
+                .write(self.offset + end, record)
+                .map_err(FlashStorageError::Flash)?;
+            self.end = Some(end + RECORD_SIZE as u32);
+            Ok(())
+        }
+
+        /// Erases the region and writes back the latest term record and the current log
+        fn compact_cluster(&mut self) -> Result<(), FlashStorageError<F::Error>> {
+            let mut term = None;
+            let mut log = alloc::vec![LogEntry::INITIAL];
+            self.scan(|record| {
+                if let Record::Cluster(record) = record {
+                    apply_cluster_record(record, &mut term, &mut log);
+                }
+            })?;
+            self.erase()?;
+            if let Some(term) = term {
+                self.append(&encode_cluster(term))?;
+            }
+            for (index, entry) in log.into_iter().enumerate().skip(1) {
+                self.append(&encode_cluster(ClusterRecord::Entry {
+                    index: index as u16,
+                    entry,
+                }))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl<F: NorFlash> AllocationStorage for FlashStorage<F> {
+        type Error = FlashStorageError<F::Error>;
+
+        fn load<G>(&mut self, mut f: G) -> Result<(), Self::Error>
+        where
+            G: FnMut(u16, Assignment),
+        {
+            self.end = Some(self.scan(|record| {
+                if let Record::Allocation(node_id, assignment) = record {
+                    f(node_id, assignment);
+                }
+            })?);
+            Ok(())
+        }
+
+        fn store(&mut self, node_id: u16, assignment: Assignment) -> Result<(), Self::Error> {
+            self.append(&encode(node_id, assignment))
+        }
+    }
+
+    impl<F: NorFlash> ClusterStorage for FlashStorage<F> {
+        type Error = FlashStorageError<F::Error>;
+
+        fn load_cluster<G>(&mut self, mut f: G) -> Result<(), Self::Error>
+        where
+            G: FnMut(ClusterRecord),
+        {
+            self.end = Some(self.scan(|record| {
+                if let Record::Cluster(record) = record {
+                    f(record);
+                }
+            })?);
+            Ok(())
+        }
+
+        fn store_cluster(&mut self, record: ClusterRecord) -> Result<(), Self::Error> {
+            let encoded = encode_cluster(record);
+            match self.append(&encoded) {
+                Err(FlashStorageError::Full) => {
+                    self.compact_cluster()?;
+                    self.append(&encoded)
+                }
+                result => result,
+            }
+        }
+    }
+
+    /// Errors that a [`FlashStorage`] can report
+    #[derive(Debug)]
+    #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+    pub enum FlashStorageError<E> {
+        /// The flash returned an error
+        Flash(E),
+        /// The region has no space for another record
+        Full,
+    }
+}
+
+#[cfg(feature = "std")]
+mod file {
+    use super::{
+        decode, encode, encode_cluster, AllocationStorage, ClusterRecord, ClusterStorage, Record,
+        RECORD_SIZE,
+    };
+    use crate::service::pnp::server::Assignment;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+    use std::vec::Vec;
+
+    /// Allocation storage in a file
+    ///
+    /// Each record is written and synchronized to the disk before the allocator responds.
+    /// If the last record was only partly written, it is removed when loading.
+    #[derive(Debug)]
+    pub struct FileStorage {
+        file: File,
+    }
+
+    impl FileStorage {
+        /// Opens a storage file, creating it if it does not exist
+        pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+            let file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(path)?;
+            Ok(FileStorage { file })
+        }
+
+        /// Reads the file and calls `f` with each valid record
+        fn scan<F>(&mut self, mut f: F) -> io::Result<()>
+        where
+            F: FnMut(Record),
+        {
+            let mut contents = Vec::new();
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.read_to_end(&mut contents)?;
+            let records = contents.chunks_exact(RECORD_SIZE);
+            if !records.remainder().is_empty() {
+                // Remove the partial record so that the next record starts at the right place
+                let complete_length = contents.len() - records.remainder().len();
+                self.file.set_len(complete_length as u64)?;
+            }
+            for record in records {
+                if let Some(record) = decode(record) {
+                    f(record);
+                }
+            }
+            Ok(())
+        }
+
+        /// Writes a record at the end of the file and synchronizes it to the disk
+        fn append(&mut self, record: &[u8; RECORD_SIZE]) -> io::Result<()> {
+            self.file.write_all(record)?;
+            self.file.sync_data()
+        }
+    }
+
+    impl AllocationStorage for FileStorage {
+        type Error = io::Error;
+
+        fn load<F>(&mut self, mut f: F) -> Result<(), Self::Error>
+        where
+            F: FnMut(u16, Assignment),
+        {
+            self.scan(|record| {
+                if let Record::Allocation(node_id, assignment) = record {
+                    f(node_id, assignment);
+                }
+            })
+        }
+
+        fn store(&mut self, node_id: u16, assignment: Assignment) -> Result<(), Self::Error> {
+            self.append(&encode(node_id, assignment))
+        }
+    }
+
+    impl ClusterStorage for FileStorage {
+        type Error = io::Error;
+
+        fn load_cluster<F>(&mut self, mut f: F) -> Result<(), Self::Error>
+        where
+            F: FnMut(ClusterRecord),
+        {
+            self.scan(|record| {
+                if let Record::Cluster(record) = record {
+                    f(record);
+                }
+            })
+        }
+
+        fn store_cluster(&mut self, record: ClusterRecord) -> Result<(), Self::Error> {
+            self.append(&encode_cluster(record))
+        }
+    }
+}
+
+// Record layout:
+// * Bytes 0-1: node ID, little-endian. For term records, the node ID that received this
+//   allocator's vote, or 0xffff if none.
+// * Byte 2: record kind
+// * Byte 3: zero
+// * Bytes 4-19: unique ID, or the unique ID hash (little-endian) followed by zeros
+// * Bytes 20-23: for term records and log entries, the term number, little-endian
+// * Bytes 24-25: for log entries, the index in the log, little-endian
+// * Bytes 26-27: zero
+// * Bytes 28-31: CRC-32 of bytes 0-27, little-endian
+
+const KIND_UNASSIGNED: u8 = 0;
+const KIND_RESERVED: u8 = 1;
+const KIND_ASSIGNED: u8 = 2;
+const KIND_ASSIGNED_HASH: u8 = 3;
+const KIND_TERM: u8 = 4;
+const KIND_LOG_ENTRY: u8 = 5;
+
+/// The stored voted-for node ID that means no vote
+const NO_VOTE: u16 = 0xffff;
+
+/// A decoded record of either kind
+enum Record {
+    Allocation(u16, Assignment),
+    Cluster(ClusterRecord),
+}
+
+fn encode(node_id: u16, assignment: Assignment) -> [u8; RECORD_SIZE] {
+    let mut record = [0u8; RECORD_SIZE];
+    record[0..2].copy_from_slice(&node_id.to_le_bytes());
+    record[2] = match assignment {
+        Assignment::Unassigned => KIND_UNASSIGNED,
+        Assignment::Reserved => KIND_RESERVED,
+        Assignment::Assigned(unique_id) => {
+            record[4..20].copy_from_slice(&unique_id);
+            KIND_ASSIGNED
+        }
+        Assignment::AssignedHash(hash) => {
+            record[4..12].copy_from_slice(&hash.to_le_bytes());
+            KIND_ASSIGNED_HASH
+        }
+    };
+    add_crc(&mut record);
+    record
+}
+
+fn encode_cluster(cluster_record: ClusterRecord) -> [u8; RECORD_SIZE] {
+    let mut record = [0u8; RECORD_SIZE];
+    match cluster_record {
+        ClusterRecord::Term { term, voted_for } => {
+            record[0..2].copy_from_slice(&voted_for.unwrap_or(NO_VOTE).to_le_bytes());
+            record[2] = KIND_TERM;
+            record[20..24].copy_from_slice(&term.to_le_bytes());
+        }
+        ClusterRecord::Entry { index, entry } => {
+            record[0..2].copy_from_slice(&entry.node_id.to_le_bytes());
+            record[2] = KIND_LOG_ENTRY;
+            record[4..20].copy_from_slice(&entry.unique_id);
+            record[20..24].copy_from_slice(&entry.term.to_le_bytes());
+            record[24..26].copy_from_slice(&index.to_le_bytes());
+        }
+    }
+    add_crc(&mut record);
+    record
+}
+
+/// Decodes a record, or returns None if it is corrupt
+fn decode(record: &[u8]) -> Option<Record> {
+    let crc = u32::from_le_bytes(record[28..32].try_into().unwrap());
+    if crc != record_crc(&record[..28]) {
+        return None;
+    }
+    let node_id = u16::from_le_bytes([record[0], record[1]]);
+    let term = u32::from_le_bytes(record[20..24].try_into().unwrap());
+    let assignment = match record[2] {
+        KIND_UNASSIGNED => Assignment::Unassigned,
+        KIND_RESERVED => Assignment::Reserved,
+        KIND_ASSIGNED => Assignment::Assigned(record[4..20].try_into().unwrap()),
+        KIND_ASSIGNED_HASH => {
+            Assignment::AssignedHash(u64::from_le_bytes(record[4..12].try_into().unwrap()))
+        }
+        KIND_TERM => {
+            return Some(Record::Cluster(ClusterRecord::Term {
+                term,
+                voted_for: Some(node_id).filter(|&id| id != NO_VOTE),
+            }))
+        }
+        KIND_LOG_ENTRY => {
+            return Some(Record::Cluster(ClusterRecord::Entry {
+                index: u16::from_le_bytes([record[24], record[25]]),
+                entry: LogEntry {
+                    term,
+                    unique_id: record[4..20].try_into().unwrap(),
+                    node_id,
+                },
+            }))
+        }
+        _ => return None,
+    };
+    Some(Record::Allocation(node_id, assignment))
+}
+
+/// Applies a cluster record to a term record and a log that starts with
+/// [`LogEntry::INITIAL`], in the same way that an allocator applies it when loading
+pub(crate) fn apply_cluster_record(
+    record: ClusterRecord,
+    term: &mut Option<ClusterRecord>,
+    log: &mut Vec<LogEntry>,
+) {
+    match record {
+        ClusterRecord::Term { .. } => *term = Some(record),
+        ClusterRecord::Entry { index, entry } => {
+            let index = usize::from(index);
+            // Entries are always stored in order, so an index past the end would mean that an
+            // earlier record was corrupt. Keep the log without gaps.
+            if index != 0 && index <= log.len() {
+                log.truncate(index);
+                log.push(entry);
+            }
+        }
+    }
+}
+
+fn add_crc(record: &mut [u8; RECORD_SIZE]) {
+    let crc = record_crc(&record[..28]);
+    record[28..].copy_from_slice(&crc.to_le_bytes());
+}
+
+fn record_crc(bytes: &[u8]) -> u32 {
+    let mut crc = CRCu32::crc32();
+    crc.digest(bytes);
+    crc.get_crc()
+}
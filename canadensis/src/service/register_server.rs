@@ -1,4 +1,5 @@
 use crate::register::RegisterBlock;
+use crate::service::server::send_response;
 use crate::{Node, ResponseToken, TransferHandler};
 use alloc::vec::Vec;
 use canadensis_core::transfer::ServiceTransfer;
@@ -14,7 +15,7 @@ use canadensis_data_types::uavcan::time::synchronized_timestamp_1_0::Synchronize
 use canadensis_encoding::Deserialize;
 use core::marker::PhantomData;
 use core::str;
-use l0g::{debug, warn};
+use l0g::debug;
 
 /// A service that responds to `uavcan.register.List` and `uavcan.register.Access`
 pub struct RegisterServerService<N: Node, B: RegisterBlock> {
@@ -101,10 +102,7 @@ where
                             }
                         }
                     };
-                    #[allow(unused_variables)]
-                    if let Err(err) = node.send_response(token, milliseconds(1000), &response) {
-                        warn!("Failed to send response: {:?}", err);
-                    }
+                    send_response(node, token, milliseconds(1000), &response);
                     true
                 } else {
                     false
@@ -133,10 +131,7 @@ where
                             }
                         }
                     };
-                    #[allow(unused_variables)]
-                    if let Err(err) = node.send_response(token, milliseconds(1000), &response) {
-                        warn!("Failed to send response: {:?}", err);
-                    }
+                    send_response(node, token, milliseconds(1000), &response);
                     true
                 } else {
                     false
@@ -0,0 +1,224 @@
+//!
+//! A generic server that answers requests for one service
+//!
+//! [`ServiceServer`] subscribes to a service, deserializes each request, and passes it to a
+//! function that returns the response (or defers it). The function is boxed and `'static`, and
+//! it gets only the request and a [`Call`]. Some of the built-in services need more than that,
+//! so they have their own handlers (which send responses the same way as `ServiceServer`):
+//!
+//! * [`GetInfoService`](crate::service::get_info::GetInfoService) sends the same response
+//!   every time. The generated data types do not implement `Clone`, so a `'static` function
+//!   cannot return a new copy of the response for each request.
+//! * [`GetTransportStatisticsService`](crate::service::get_transport_statistics::GetTransportStatisticsService)
+//!   reads the statistics from the node that received the request, and the function has no
+//!   access to the node.
+//! * [`RegisterServerService`](crate::service::register_server::RegisterServerService) answers
+//!   two services from one register block, and the application can access the block between
+//!   requests. A `'static` function would have to own the block.
+//! * [`PnpServerService`](crate::service::pnp::server::PnpServerService) handles allocation
+//!   messages, not service requests.
+//!
+
+use crate::{Node, ResponseToken, TransferHandler};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use canadensis_core::time::{milliseconds, MicrosecondDuration32};
+use canadensis_core::transfer::{ServiceHeader, ServiceTransfer};
+use canadensis_core::transport::{Receiver, Transport};
+use canadensis_core::{ServiceId, ServiceSubscribeError};
+use canadensis_encoding::{Deserialize, Request, Response, Serialize};
+use core::marker::PhantomData;
+use l0g::{debug, warn};
+
+/// A boxed request handler
+pub(crate) type Handler<Req, Resp, T> = Box<dyn FnMut(Req, Call<'_, T>) -> Reply<Resp, T>>;
+
+/// A server for one service that deserializes requests, passes them to a function, and sends
+/// the responses that the function returns
+///
+/// Requests that cannot be deserialized are counted and ignored, without sending a response.
+pub struct ServiceServer<N, Req, Resp>
+where
+    N: Node,
+{
+    service: ServiceId,
+    response_timeout: MicrosecondDuration32,
+    handler: Handler<Req, Resp, N::Transport>,
+    deserialize_errors: u64,
+    _node: PhantomData<N>,
+}
+
+impl<N, Req, Resp> ServiceServer<N, Req, Resp>
+where
+    N: Node,
+    Req: Request + Deserialize,
+    Resp: Response + Serialize,
+{
+    /// Subscribes to requests for a service and creates a server
+    ///
+    /// * `node`: The node to use for responding to requests
+    /// * `service`: The service ID to respond to
+    /// * `payload_size_max`: The maximum number of payload bytes in a request
+    /// * `handler`: The function to call with each request. It returns either a response to send
+    ///   immediately ([`Call::reply`]) or a deferral ([`Call::defer`]).
+    pub fn new<F>(
+        node: &mut N,
+        service: ServiceId,
+        payload_size_max: usize,
+        handler: F,
+    ) -> Result<Self, NewError<N>>
+    where
+        F: FnMut(Req, Call<'_, N::Transport>) -> Reply<Resp, N::Transport> + 'static,
+    {
+        Self::with_boxed_handler(node, service, payload_size_max, Box::new(handler))
+    }
+
+    /// Subscribes to requests for a service and creates a server with a handler that is already
+    /// boxed
+    pub(crate) fn with_boxed_handler(
+        node: &mut N,
+        service: ServiceId,
+        payload_size_max: usize,
+        handler: Handler<Req, Resp, N::Transport>,
+    ) -> Result<Self, NewError<N>> {
+        node.subscribe_request(service, payload_size_max, milliseconds(1000))?;
+
+        Ok(ServiceServer {
+            service,
+            response_timeout: milliseconds(1000),
+            handler,
+            deserialize_errors: 0,
+            _node: PhantomData,
+        })
+    }
+
+    /// Returns the service ID that this server responds to
+    pub fn service(&self) -> ServiceId {
+        self.service
+    }
+
+    /// Sets the timeout for sending responses
+    ///
+    /// Default: 1 second
+    pub fn set_response_timeout(&mut self, timeout: MicrosecondDuration32) {
+        self.response_timeout = timeout;
+    }
+
+    /// Returns the number of requests that could not be deserialized
+    pub fn deserialize_errors(&self) -> u64 {
+        self.deserialize_errors
+    }
+
+    /// Unsubscribes from requests and consumes this server
+    pub fn stop(self, node: &mut N) {
+        node.unsubscribe_request(self.service);
+    }
+
+    /// Returns the handler for this server
+    pub fn handler(&mut self) -> ServiceServerHandler<'_, N, Req, Resp> {
+        ServiceServerHandler { server: self }
+    }
+}
+
+/// Error type returned by [`ServiceServer::new`]
+pub type NewError<N> =
+    ServiceSubscribeError<<<N as Node>::Receiver as Receiver<<N as Node>::Clock>>::Error>;
+
+/// The [`TransferHandler`] for a [`ServiceServer`]
+pub struct ServiceServerHandler<'a, N, Req, Resp>
+where
+    N: Node,
+{
+    server: &'a mut ServiceServer<N, Req, Resp>,
+}
+
+impl<N, Req, Resp> TransferHandler<N::Transport> for ServiceServerHandler<'_, N, Req, Resp>
+where
+    N: Node,
+    Req: Request + Deserialize,
+    Resp: Response + Serialize,
+{
+    fn handle_request<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        token: ResponseToken<N2::Transport>,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        if transfer.header.service != self.server.service {
+            return false;
+        }
+
+        match Req::deserialize_from_bytes(&transfer.payload) {
+            Ok(request) => {
+                let call = Call {
+                    header: &transfer.header,
+                    token,
+                };
+                if let ReplyKind::Now(token, response) = (self.server.handler)(request, call).0 {
+                    send_response(node, token, self.server.response_timeout, &response);
+                }
+            }
+            Err(_) => {
+                // A request that is not valid gets no response, and no other handler should
+                // respond to it either
+                debug!("Ignoring a request that could not be deserialized");
+                self.server.deserialize_errors = self.server.deserialize_errors.wrapping_add(1);
+            }
+        }
+        true
+    }
+}
+
+/// Sends a response and logs a warning if it could not be sent
+pub(crate) fn send_response<N, Resp>(
+    node: &mut N,
+    token: ResponseToken<N::Transport>,
+    timeout: MicrosecondDuration32,
+    response: &Resp,
+) where
+    N: Node,
+    Resp: Response + Serialize,
+{
+    #[allow(unused_variables)]
+    if let Err(err) = node.send_response(token, timeout, response) {
+        warn!("Failed to send response: {:?}", err);
+    }
+}
+
+/// Information about a request that a [`ServiceServer`] has received
+///
+/// The request handler uses this to produce a [`Reply`].
+pub struct Call<'r, T: Transport> {
+    header: &'r ServiceHeader<T>,
+    token: ResponseToken<T>,
+}
+
+impl<'r, T: Transport> Call<'r, T> {
+    /// Returns the header of the request transfer, which contains the client node ID, transfer
+    /// ID, priority, and timestamp
+    pub fn header(&self) -> &'r ServiceHeader<T> {
+        self.header
+    }
+
+    /// Returns a reply that sends a response immediately
+    pub fn reply<Resp>(self, response: Resp) -> Reply<Resp, T> {
+        Reply(ReplyKind::Now(self.token, response))
+    }
+
+    /// Returns a reply that does not send a response, and the token needed to send a response
+    /// later with [`Node::send_response`]
+    pub fn defer<Resp>(self) -> (Reply<Resp, T>, ResponseToken<T>) {
+        (Reply(ReplyKind::Deferred), self.token)
+    }
+}
+
+/// What a [`ServiceServer`] does after its handler returns: send a response, or nothing if the
+/// response was deferred
+///
+/// Replies are created by [`Call::reply`] and [`Call::defer`].
+pub struct Reply<Resp, T: Transport>(ReplyKind<Resp, T>);
+
+enum ReplyKind<Resp, T: Transport> {
+    Now(ResponseToken<T>, Resp),
+    Deferred,
+}
@@ -0,0 +1,90 @@
+//! A CAN node with a manually controlled clock and a driver that records outgoing frames, shared
+//! by several tests
+
+use canadensis::node::CoreNode;
+use canadensis::requester::TransferIdFixedMap;
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanNodeId, CanReceiver, CanTransmitter, CanTransport, Frame, Mtu};
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{Clock, Microseconds32};
+use canadensis_core::OutOfMemoryError;
+use std::cell::Cell;
+use std::convert::Infallible;
+
+pub type TestNode<'c> = CoreNode<
+    StubClock<'c>,
+    CanTransmitter<StubClock<'c>, RecordingDriver>,
+    CanReceiver<StubClock<'c>, RecordingDriver>,
+    TransferIdFixedMap<CanTransport, 4>,
+    RecordingDriver,
+    4,
+    4,
+>;
+
+/// Creates a node with the provided ID that reads the current time in microseconds from `time`
+pub fn test_node(time: &Cell<u32>, id: CanNodeId) -> TestNode<'_> {
+    CoreNode::new(
+        StubClock { time },
+        id,
+        CanTransmitter::new(Mtu::Can8),
+        CanReceiver::new(id),
+        RecordingDriver::default(),
+    )
+}
+
+/// A CAN driver that records outgoing frames and never receives anything
+#[derive(Default)]
+pub struct RecordingDriver {
+    pub frames: Vec<Frame>,
+}
+
+impl TransmitDriver<StubClock<'_>> for RecordingDriver {
+    type Error = Infallible;
+
+    fn try_reserve(&mut self, _frames: usize) -> Result<(), OutOfMemoryError> {
+        Ok(())
+    }
+
+    fn transmit(
+        &mut self,
+        frame: Frame,
+        _clock: &mut StubClock<'_>,
+    ) -> canadensis::nb::Result<Option<Frame>, Self::Error> {
+        self.frames.push(frame);
+        Ok(None)
+    }
+
+    fn flush(&mut self, _clock: &mut StubClock<'_>) -> canadensis::nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ReceiveDriver<StubClock<'_>> for RecordingDriver {
+    type Error = Infallible;
+
+    fn receive(
+        &mut self,
+        _clock: &mut StubClock<'_>,
+    ) -> canadensis::nb::Result<Frame, Self::Error> {
+        Err(canadensis::nb::Error::WouldBlock)
+    }
+
+    fn apply_filters<S>(&mut self, _local_node: Option<CanNodeId>, _subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+    }
+
+    fn apply_accept_all(&mut self) {}
+}
+
+/// A clock that returns the time in a shared cell
+pub struct StubClock<'t> {
+    time: &'t Cell<u32>,
+}
+
+impl Clock for StubClock<'_> {
+    fn now(&mut self) -> Microseconds32 {
+        Microseconds32::from_ticks(self.time.get())
+    }
+}
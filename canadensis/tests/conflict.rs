@@ -0,0 +1,267 @@
+//!
+//! Tests of node ID conflict detection on a simulated CAN bus
+//!
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+extern crate canadensis_sim;
+
+use canadensis::conflict::{Conflict, ConflictDetector};
+use canadensis::core::time::{milliseconds, Microseconds32};
+use canadensis::core::transfer::MessageTransfer;
+use canadensis::core::Priority;
+use canadensis::encoding::DataType;
+use canadensis::health::HealthMonitor;
+use canadensis::{Node, TransferHandler};
+use canadensis_can::{CanNodeId, CanTransport};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use canadensis_sim::can::{CanBus, CanBusConfig, SimCanNode};
+use canadensis_sim::Simulation;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// A node that runs a conflict detector and records the conflicts that it reports
+struct Local {
+    node: SimCanNode,
+    detector: ConflictDetector<SimCanNode>,
+    health: HealthMonitor,
+    reported: Rc<RefCell<Vec<Conflict<CanNodeId>>>>,
+}
+
+impl Local {
+    fn new(bus: &CanBus, id: u8) -> Self {
+        let mut node = bus.node(node_id(id));
+        let reported = Rc::new(RefCell::new(Vec::new()));
+        let callback_reported = reported.clone();
+        // NewError is only Debug if the node is
+        let detector = ConflictDetector::new(&mut node)
+            .unwrap_or_else(|_| panic!("Failed to start conflict detector"))
+            .with_callback(move |conflict| callback_reported.borrow_mut().push(conflict.clone()));
+        Local {
+            node,
+            detector,
+            health: HealthMonitor::new(),
+            reported,
+        }
+    }
+
+    fn poll(&mut self, now: Microseconds32) {
+        self.node.receive(&mut self.detector.handler()).unwrap();
+        self.detector.poll(now, &mut self.health);
+    }
+
+    fn conflicts(&self) -> Vec<Conflict<CanNodeId>> {
+        self.detector.conflicts().cloned().collect()
+    }
+}
+
+/// A node that publishes a heartbeat every second
+struct HeartbeatSender {
+    node: SimCanNode,
+    /// The time when the uptime was `initial_uptime`
+    start: Microseconds32,
+    initial_uptime: u32,
+    next_heartbeat: Microseconds32,
+}
+
+impl HeartbeatSender {
+    fn new(simulation: &Simulation, bus: &CanBus, id: u8, initial_uptime: u32) -> Self {
+        let mut node = bus.node(node_id(id));
+        node.start_publishing(
+            heartbeat_1_0::SUBJECT,
+            milliseconds(1000),
+            Priority::Nominal,
+        )
+        .unwrap();
+        HeartbeatSender {
+            node,
+            start: simulation.now(),
+            initial_uptime,
+            next_heartbeat: simulation.now(),
+        }
+    }
+
+    fn poll(&mut self, now: Microseconds32) {
+        if now >= self.next_heartbeat {
+            let uptime =
+                self.initial_uptime + now.ticks().wrapping_sub(self.start.ticks()) / 1_000_000;
+            let heartbeat = Heartbeat {
+                uptime,
+                health: Health {
+                    value: Health::NOMINAL,
+                },
+                mode: Mode {
+                    value: Mode::OPERATIONAL,
+                },
+                vendor_specific_status_code: 0,
+            };
+            self.node
+                .publish(heartbeat_1_0::SUBJECT, &heartbeat)
+                .unwrap();
+            self.next_heartbeat += milliseconds(1000);
+        }
+        self.node.flush().unwrap();
+    }
+}
+
+fn new_network() -> (Simulation, CanBus) {
+    let mut simulation = Simulation::new(44);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    (simulation, bus)
+}
+
+fn node_id(id: u8) -> CanNodeId {
+    CanNodeId::try_from(id).unwrap()
+}
+
+#[test]
+fn own_node_id_conflict_sets_warning_until_timeout() {
+    let (mut simulation, bus) = new_network();
+    let mut local = Local::new(&bus, 10);
+    let mut impostor = HeartbeatSender::new(&simulation, &bus, 10, 0);
+
+    simulation.run_for(milliseconds(3000), milliseconds(10), |now| {
+        impostor.poll(now);
+        local.poll(now);
+    });
+    assert_eq!(local.conflicts(), vec![Conflict::OwnNodeId]);
+    assert!(local.detector.is_own_node_id_conflict());
+    assert!(local.health.is_node_id_conflict());
+    assert_eq!(local.health.health().value, Health::WARNING);
+    // The callback is called only when the conflict first appears
+    assert_eq!(*local.reported.borrow(), vec![Conflict::OwnNodeId]);
+
+    // The other node has been reconfigured
+    simulation.run_for(milliseconds(4000), milliseconds(10), |now| local.poll(now));
+    assert_eq!(local.conflicts(), vec![]);
+    assert!(!local.health.is_node_id_conflict());
+    assert_eq!(local.health.health().value, Health::NOMINAL);
+}
+
+#[test]
+fn remote_nodes_with_different_uptimes() {
+    let (mut simulation, bus) = new_network();
+    let mut local = Local::new(&bus, 10);
+    let mut sender_a = HeartbeatSender::new(&simulation, &bus, 20, 100);
+    let mut sender_b = HeartbeatSender::new(&simulation, &bus, 20, 5000);
+    sender_b.next_heartbeat += milliseconds(500);
+
+    simulation.run_for(milliseconds(3000), milliseconds(10), |now| {
+        sender_a.poll(now);
+        sender_b.poll(now);
+        local.poll(now);
+    });
+    assert_eq!(local.conflicts(), vec![Conflict::RemoteNodeId(node_id(20))]);
+    assert!(!local.detector.is_own_node_id_conflict());
+    assert_eq!(local.health.health().value, Health::WARNING);
+    assert_eq!(
+        *local.reported.borrow(),
+        vec![Conflict::RemoteNodeId(node_id(20))]
+    );
+}
+
+#[test]
+fn remote_nodes_with_the_same_uptime() {
+    let (mut simulation, bus) = new_network();
+    let mut local = Local::new(&bus, 10);
+    let mut sender_a = HeartbeatSender::new(&simulation, &bus, 20, 100);
+
+    // Start the second node later, so that its transfer IDs are different but its uptime is
+    // the same
+    simulation.run_for(milliseconds(3000), milliseconds(10), |now| {
+        sender_a.poll(now);
+        local.poll(now);
+    });
+    assert_eq!(local.conflicts(), vec![]);
+    let mut sender_b = HeartbeatSender::new(&simulation, &bus, 20, 103);
+    sender_b.next_heartbeat += milliseconds(500);
+
+    simulation.run_for(milliseconds(4000), milliseconds(10), |now| {
+        sender_a.poll(now);
+        sender_b.poll(now);
+        local.poll(now);
+    });
+    assert_eq!(local.conflicts(), vec![Conflict::RemoteNodeId(node_id(20))]);
+}
+
+#[test]
+fn restart_is_not_a_conflict() {
+    let (mut simulation, bus) = new_network();
+    let mut local = Local::new(&bus, 10);
+    let mut sender = HeartbeatSender::new(&simulation, &bus, 30, 1000);
+
+    simulation.run_for(milliseconds(3000), milliseconds(10), |now| {
+        sender.poll(now);
+        local.poll(now);
+    });
+    // The node restarts, with its uptime and transfer IDs back at zero
+    let mut sender = HeartbeatSender::new(&simulation, &bus, 30, 0);
+    simulation.run_for(milliseconds(5000), milliseconds(10), |now| {
+        sender.poll(now);
+        local.poll(now);
+    });
+    assert_eq!(local.conflicts(), vec![]);
+    assert!(local.reported.borrow().is_empty());
+    assert_eq!(local.health.health().value, Health::NOMINAL);
+}
+
+/// Passes heartbeats from an application's own subscription to a detector
+struct ExistingSubscription<'a> {
+    detector: &'a mut ConflictDetector<SimCanNode>,
+    heartbeats: usize,
+}
+
+impl TransferHandler<CanTransport> for ExistingSubscription<'_> {
+    fn handle_message<N: Node<Transport = CanTransport>>(
+        &mut self,
+        node: &mut N,
+        transfer: &MessageTransfer<Vec<u8>, CanTransport>,
+    ) -> bool {
+        if transfer.header.subject != heartbeat_1_0::SUBJECT {
+            return false;
+        }
+        self.heartbeats += 1;
+        self.detector.handle_heartbeat(node.node_id(), transfer);
+        true
+    }
+}
+
+#[test]
+fn detector_without_subscription() {
+    let (mut simulation, bus) = new_network();
+    let mut node = bus.node(node_id(10));
+    node.subscribe_message(
+        heartbeat_1_0::SUBJECT,
+        Heartbeat::EXTENT_BYTES.unwrap() as usize,
+        milliseconds(100),
+    )
+    .unwrap();
+    let mut detector = ConflictDetector::without_subscription();
+    let mut health = HealthMonitor::new();
+    let mut sender_a = HeartbeatSender::new(&simulation, &bus, 20, 100);
+    let mut sender_b = HeartbeatSender::new(&simulation, &bus, 20, 5000);
+    sender_b.next_heartbeat += milliseconds(500);
+    let mut heartbeats = 0;
+
+    simulation.run_for(milliseconds(3000), milliseconds(10), |now| {
+        sender_a.poll(now);
+        sender_b.poll(now);
+        let mut handler = ExistingSubscription {
+            detector: &mut detector,
+            heartbeats: 0,
+        };
+        node.receive(&mut handler).unwrap();
+        heartbeats += handler.heartbeats;
+        detector.poll(now, &mut health);
+    });
+    assert!(heartbeats >= 4);
+    assert_eq!(
+        detector.conflicts().cloned().collect::<Vec<_>>(),
+        vec![Conflict::RemoteNodeId(node_id(20))]
+    );
+    assert_eq!(health.health().value, Health::WARNING);
+}
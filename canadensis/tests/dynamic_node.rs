@@ -0,0 +1,333 @@
+//! Tests a dynamic node with services chosen at runtime
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+
+use canadensis::node::data_types::{GetInfoResponse, Version};
+use canadensis::node::{CoreNode, DynamicNode, NodeBuilder};
+use canadensis::requester::TransferIdFixedMap;
+use canadensis::service::pnp::AllocationMessage;
+use canadensis::Node;
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanId, CanNodeId, CanReceiver, CanTransmitter, CanTransport, Frame, Mtu};
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{milliseconds, Clock, Microseconds32};
+use canadensis_core::{OutOfMemoryError, Priority, ServiceId, SubjectId};
+use canadensis_data_types::uavcan::diagnostic::record_1_1;
+use canadensis_data_types::uavcan::diagnostic::severity_1_0::Severity;
+use canadensis_data_types::uavcan::node::execute_command_1_3::{self, ExecuteCommandResponse};
+use canadensis_data_types::uavcan::node::get_info_1_0;
+use canadensis_data_types::uavcan::node::heartbeat_1_0;
+use canadensis_data_types::uavcan::node::port::list_1_0;
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_1_0::{self, NodeIDAllocationData};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::convert::{Infallible, TryFrom};
+
+type TestNode<'c> = CoreNode<
+    StubClock<'c>,
+    CanTransmitter<StubClock<'c>, QueueDriver>,
+    CanReceiver<StubClock<'c>, QueueDriver>,
+    TransferIdFixedMap<CanTransport, 4>,
+    QueueDriver,
+    8,
+    8,
+>;
+
+#[test]
+fn services_with_node_id() {
+    let time = Cell::new(0);
+    let mut node = NodeBuilder::new(make_node(&time, Some(1)))
+        .heartbeat()
+        .get_info(node_info())
+        .port_list()
+        .diagnostics()
+        .execute_command(|request, call| {
+            assert_eq!(
+                execute_command_1_3::ExecuteCommandRequest::COMMAND_IDENTIFY,
+                request.command
+            );
+            call.reply(ExecuteCommandResponse {
+                status: ExecuteCommandResponse::STATUS_SUCCESS,
+                output: heapless::Vec::new(),
+            })
+        })
+        .build()
+        .unwrap();
+    assert!(!node.is_waiting_for_node_id());
+
+    // The first poll sends a heartbeat
+    poll(&mut node, &time, 0);
+    assert_eq!(
+        vec![message_subject(heartbeat_1_0::SUBJECT)],
+        sent_ids(&mut node)
+    );
+    // No more heartbeats until one second later
+    poll(&mut node, &time, 999_999);
+    assert!(sent_ids(&mut node).is_empty());
+    poll(&mut node, &time, 1_000_000);
+    assert_eq!(
+        vec![message_subject(heartbeat_1_0::SUBJECT)],
+        sent_ids(&mut node)
+    );
+
+    // Requests are answered
+    push_request(&mut node, get_info_1_0::SERVICE, 0, &[]);
+    // Command 65529 (identify) with an empty parameter
+    push_request(
+        &mut node,
+        execute_command_1_3::SERVICE,
+        1,
+        &[0xf9, 0xff, 0x00],
+    );
+    poll(&mut node, &time, 1_500_000);
+    let ids = sent_ids(&mut node);
+    assert!(ids.contains(&response_service(get_info_1_0::SERVICE)));
+    assert!(ids.contains(&response_service(execute_command_1_3::SERVICE)));
+
+    // The port list is sent every 10 seconds
+    for second in 2..=11 {
+        poll(&mut node, &time, second * 1_000_000);
+    }
+    let ids = sent_ids(&mut node);
+    assert_eq!(
+        1,
+        ids.iter()
+            .filter(|&&id| id == message_subject(list_1_0::SUBJECT))
+            .count()
+    );
+    assert_eq!(
+        10,
+        ids.iter()
+            .filter(|&&id| id == message_subject(heartbeat_1_0::SUBJECT))
+            .count()
+    );
+
+    node.publish_diagnostic(
+        Severity {
+            value: Severity::NOTICE,
+        },
+        "hello",
+    )
+    .unwrap();
+    assert_eq!(
+        vec![message_subject(record_1_1::SUBJECT)],
+        sent_ids(&mut node)
+    );
+}
+
+#[test]
+fn plug_and_play() {
+    let time = Cell::new(0);
+    let unique_id = [7; 16];
+    let mut node = NodeBuilder::new(make_node(&time, None))
+        .heartbeat()
+        .get_info(node_info())
+        .pnp_client(unique_id)
+        .build()
+        .unwrap();
+    assert!(node.is_waiting_for_node_id());
+
+    // An anonymous node sends allocation requests at random intervals and no heartbeats.
+    // The first request is sent within one second, and the interval between requests is
+    // 0.6 to 1 second.
+    for time_us in (0..=3_000_000).step_by(100_000) {
+        poll(&mut node, &time, time_us);
+    }
+    let frames = std::mem::take(&mut node.node_mut().driver_mut().outgoing);
+    assert!((3..=6).contains(&frames.len()), "{} requests", frames.len());
+    for frame in &frames {
+        let id = u32::from(frame.id());
+        // Anonymous message on the allocation subject
+        assert_ne!(0, id & (1 << 24));
+        assert_eq!(
+            u32::from(u16::from(node_id_allocation_data_1_0::SUBJECT)),
+            (id >> 8) & 0x1fff
+        );
+    }
+
+    // An allocator assigns node ID 5
+    let mut allocator = make_node(&time, Some(10));
+    allocator
+        .start_publishing(
+            node_id_allocation_data_1_0::SUBJECT,
+            milliseconds(1000),
+            Priority::Nominal,
+        )
+        .unwrap();
+    let assigned = <NodeIDAllocationData as AllocationMessage<CanTransport>>::with_node_id(
+        AllocationMessage::<CanTransport>::with_unique_id(&unique_id),
+        CanNodeId::try_from(5_u8).unwrap(),
+    );
+    allocator
+        .publish(node_id_allocation_data_1_0::SUBJECT, &assigned)
+        .unwrap();
+    let allocation_frames = std::mem::take(&mut allocator.driver_mut().outgoing);
+    node.node_mut()
+        .driver_mut()
+        .incoming
+        .extend(allocation_frames);
+    poll(&mut node, &time, 3_500_000);
+    assert_eq!(
+        Some(CanNodeId::try_from(5_u8).unwrap()),
+        node.node().node_id()
+    );
+    assert!(!node.is_waiting_for_node_id());
+
+    // Now the GetInfo service is running and heartbeats are sent
+    push_request(&mut node, get_info_1_0::SERVICE, 0, &[]);
+    poll(&mut node, &time, 4_000_000);
+    let ids = sent_ids(&mut node);
+    assert!(ids.contains(&response_service(get_info_1_0::SERVICE)));
+    assert!(ids.contains(&message_subject(heartbeat_1_0::SUBJECT)));
+}
+
+fn node_info() -> GetInfoResponse {
+    GetInfoResponse {
+        protocol_version: Version { major: 1, minor: 0 },
+        hardware_version: Version { major: 0, minor: 0 },
+        software_version: Version { major: 0, minor: 1 },
+        software_vcs_revision_id: 0,
+        unique_id: [7; 16],
+        name: heapless::Vec::from_slice(b"org.example.dynamic").unwrap(),
+        software_image_crc: heapless::Vec::new(),
+        certificate_of_authenticity: heapless::Vec::new(),
+    }
+}
+
+fn make_node(time: &Cell<u32>, node_id: Option<u8>) -> TestNode<'_> {
+    match node_id {
+        Some(node_id) => {
+            let node_id = CanNodeId::try_from(node_id).unwrap();
+            CoreNode::new(
+                StubClock { time },
+                node_id,
+                CanTransmitter::new(Mtu::Can8),
+                CanReceiver::new(node_id),
+                QueueDriver::default(),
+            )
+        }
+        None => CoreNode::new_anonymous(
+            StubClock { time },
+            CanTransmitter::new(Mtu::Can8),
+            CanReceiver::new_anonymous(),
+            QueueDriver::default(),
+        ),
+    }
+}
+
+fn poll(node: &mut DynamicNode<TestNode<'_>>, time: &Cell<u32>, now: u32) {
+    time.set(now);
+    node.poll(Microseconds32::from_ticks(now), &mut NoHandler)
+        .unwrap();
+}
+
+/// Removes the frames that the node has sent and returns the CAN ID of the first frame of each
+/// transfer, without the source node ID and priority
+fn sent_ids(node: &mut DynamicNode<TestNode<'_>>) -> Vec<u32> {
+    node.node_mut()
+        .driver_mut()
+        .outgoing
+        .drain(..)
+        // Start of transfer bit in the tail byte
+        .filter(|frame| frame.data().last().unwrap() & 0x80 != 0)
+        .map(|frame| u32::from(frame.id()) & 0x03ff_ff80)
+        .collect()
+}
+
+fn message_subject(subject: SubjectId) -> u32 {
+    // Including the two reserved bits that are always 1
+    (3 << 21) | (u32::from(u16::from(subject)) << 8)
+}
+
+fn response_service(service: ServiceId) -> u32 {
+    // Response to node 42
+    (1 << 25) | (u32::from(u16::from(service)) << 14) | (42 << 7)
+}
+
+/// Adds a single-frame request from node 42
+fn push_request(
+    node: &mut DynamicNode<TestNode<'_>>,
+    service: ServiceId,
+    transfer_id: u8,
+    payload: &[u8],
+) {
+    let destination = u32::from(u16::from(node.node().node_id().unwrap()));
+    let request_id = (4 << 26)
+        | (1 << 25)
+        | (1 << 24)
+        | (u32::from(u16::from(service)) << 14)
+        | (destination << 7)
+        | 42;
+    let mut data = payload.to_vec();
+    data.push(0xe0 | transfer_id);
+    node.node_mut().driver_mut().incoming.push_back(Frame::new(
+        Microseconds32::from_ticks(0),
+        CanId::try_from(request_id).unwrap(),
+        &data,
+    ));
+}
+
+struct NoHandler;
+
+impl canadensis::TransferHandler<CanTransport> for NoHandler {}
+
+/// A CAN driver that records outgoing frames and receives frames from a queue
+#[derive(Default)]
+struct QueueDriver {
+    outgoing: Vec<Frame>,
+    incoming: VecDeque<Frame>,
+}
+
+impl TransmitDriver<StubClock<'_>> for QueueDriver {
+    type Error = Infallible;
+
+    fn try_reserve(&mut self, _frames: usize) -> Result<(), OutOfMemoryError> {
+        Ok(())
+    }
+
+    fn transmit(
+        &mut self,
+        frame: Frame,
+        _clock: &mut StubClock<'_>,
+    ) -> canadensis::nb::Result<Option<Frame>, Self::Error> {
+        self.outgoing.push(frame);
+        Ok(None)
+    }
+
+    fn flush(&mut self, _clock: &mut StubClock<'_>) -> canadensis::nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ReceiveDriver<StubClock<'_>> for QueueDriver {
+    type Error = Infallible;
+
+    fn receive(
+        &mut self,
+        _clock: &mut StubClock<'_>,
+    ) -> canadensis::nb::Result<Frame, Self::Error> {
+        self.incoming
+            .pop_front()
+            .ok_or(canadensis::nb::Error::WouldBlock)
+    }
+
+    fn apply_filters<S>(&mut self, _local_node: Option<CanNodeId>, _subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+    }
+
+    fn apply_accept_all(&mut self) {}
+}
+
+struct StubClock<'t> {
+    time: &'t Cell<u32>,
+}
+
+impl Clock for StubClock<'_> {
+    fn now(&mut self) -> Microseconds32 {
+        Microseconds32::from_ticks(self.time.get())
+    }
+}
@@ -0,0 +1,162 @@
+//! Tests the GetTransportStatistics service with counters from a CAN transmitter and receiver
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+extern crate canadensis_encoding;
+
+use canadensis::node::CoreNode;
+use canadensis::requester::TransferIdFixedMap;
+use canadensis::service::get_transport_statistics::GetTransportStatisticsService;
+use canadensis::Node;
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanId, CanNodeId, CanReceiver, CanTransmitter, CanTransport, Frame, Mtu};
+use canadensis_core::statistics::IoStatistics;
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{milliseconds, Clock, Microseconds32};
+use canadensis_core::{OutOfMemoryError, Priority};
+use canadensis_data_types::uavcan::node::get_transport_statistics_0_1::{
+    GetTransportStatisticsResponse, SERVICE,
+};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use canadensis_encoding::Deserialize;
+use std::collections::VecDeque;
+use std::convert::{Infallible, TryFrom};
+
+type TestNode = CoreNode<
+    ZeroClock,
+    CanTransmitter<ZeroClock, QueueDriver>,
+    CanReceiver<ZeroClock, QueueDriver>,
+    TransferIdFixedMap<CanTransport, 4>,
+    QueueDriver,
+    4,
+    4,
+>;
+
+#[test]
+fn serve_transport_statistics() {
+    let local_id = CanNodeId::try_from(1_u8).unwrap();
+    let mut node: TestNode = CoreNode::new(
+        ZeroClock,
+        local_id,
+        CanTransmitter::new(Mtu::Can8),
+        CanReceiver::new(local_id),
+        QueueDriver::default(),
+    );
+    let service = GetTransportStatisticsService::new(&mut node).unwrap();
+
+    node.start_publishing(
+        heartbeat_1_0::SUBJECT,
+        milliseconds(1000),
+        Priority::Nominal,
+    )
+    .unwrap();
+    node.publish(
+        heartbeat_1_0::SUBJECT,
+        &Heartbeat {
+            uptime: 0,
+            health: Health {
+                value: Health::NOMINAL,
+            },
+            mode: Mode {
+                value: Mode::OPERATIONAL,
+            },
+            vendor_specific_status_code: 0,
+        },
+    )
+    .unwrap();
+
+    // Request from node 42 to node 1, with an empty payload
+    let request_id =
+        (4 << 26) | (1 << 25) | (1 << 24) | (u32::from(u16::from(SERVICE)) << 14) | (1 << 7) | 42;
+    node.driver_mut().incoming.push_back(Frame::new(
+        Microseconds32::from_ticks(0),
+        CanId::try_from(request_id).unwrap(),
+        &[0xe0],
+    ));
+    node.receive(&mut service.handler()).unwrap();
+
+    // The response takes up all frames after the heartbeat. Remove the tail bytes and the
+    // transfer CRC to get the payload.
+    let mut payload: Vec<u8> = node.driver().outgoing[1..]
+        .iter()
+        .flat_map(|frame| frame.data()[..frame.data().len() - 1].iter().copied())
+        .collect();
+    payload.truncate(payload.len() - 2);
+    let response = GetTransportStatisticsResponse::deserialize_from_bytes(&payload).unwrap();
+    // One heartbeat sent, one request received
+    let expected = IoStatistics {
+        emitted: 1,
+        received: 1,
+        errored: 0,
+    };
+    assert_io_statistics(&expected, &response.transfer_statistics);
+    assert_eq!(1, response.network_interface_statistics.len());
+    assert_io_statistics(&expected, &response.network_interface_statistics[0]);
+}
+
+fn assert_io_statistics(
+    expected: &IoStatistics,
+    actual: &canadensis_data_types::uavcan::node::io_statistics_0_1::IOStatistics,
+) {
+    assert_eq!(
+        (expected.emitted, expected.received, expected.errored),
+        (actual.num_emitted, actual.num_received, actual.num_errored)
+    );
+}
+
+/// A CAN driver that records outgoing frames and receives frames from a queue
+#[derive(Default)]
+struct QueueDriver {
+    outgoing: Vec<Frame>,
+    incoming: VecDeque<Frame>,
+}
+
+impl TransmitDriver<ZeroClock> for QueueDriver {
+    type Error = Infallible;
+
+    fn try_reserve(&mut self, _frames: usize) -> Result<(), OutOfMemoryError> {
+        Ok(())
+    }
+
+    fn transmit(
+        &mut self,
+        frame: Frame,
+        _clock: &mut ZeroClock,
+    ) -> canadensis::nb::Result<Option<Frame>, Self::Error> {
+        self.outgoing.push(frame);
+        Ok(None)
+    }
+
+    fn flush(&mut self, _clock: &mut ZeroClock) -> canadensis::nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ReceiveDriver<ZeroClock> for QueueDriver {
+    type Error = Infallible;
+
+    fn receive(&mut self, _clock: &mut ZeroClock) -> canadensis::nb::Result<Frame, Self::Error> {
+        self.incoming
+            .pop_front()
+            .ok_or(canadensis::nb::Error::WouldBlock)
+    }
+
+    fn apply_filters<S>(&mut self, _local_node: Option<CanNodeId>, _subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+    }
+
+    fn apply_accept_all(&mut self) {}
+}
+
+struct ZeroClock;
+
+impl Clock for ZeroClock {
+    fn now(&mut self) -> Microseconds32 {
+        Microseconds32::from_ticks(0)
+    }
+}
@@ -0,0 +1,171 @@
+//! Tests the health monitor and the health reported in heartbeats
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+
+mod common;
+
+use canadensis::health::{HealthMonitor, TransportChecks};
+use canadensis::node::MinimalNode;
+use canadensis_can::CanNodeId;
+use canadensis_core::statistics::TransportStatistics;
+use canadensis_core::time::{milliseconds, Microseconds32};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use common::{test_node, TestNode};
+use std::cell::Cell;
+use std::convert::TryFrom;
+
+#[test]
+fn watchdog_lapses_and_recovers() {
+    let time = Cell::new(0);
+    let local_id = CanNodeId::try_from(1_u8).unwrap();
+    let core_node = test_node(&time, local_id);
+    let mut node = MinimalNode::new(core_node).unwrap();
+    let watchdog = node.add_watchdog(
+        milliseconds(2000),
+        Health {
+            value: Health::WARNING,
+        },
+        42,
+    );
+    node.set_status_code(7);
+
+    time.set(1_000_000);
+    node.run_per_second_tasks().unwrap();
+    assert_eq!((Health::NOMINAL, 7), last_heartbeat_health(&node));
+
+    // More than 2 seconds since the watchdog was added
+    time.set(2_500_000);
+    node.run_per_second_tasks().unwrap();
+    assert_eq!((Health::WARNING, 42), last_heartbeat_health(&node));
+    assert!(node.health_monitor().is_lapsed(watchdog));
+
+    node.kick_watchdog(watchdog);
+    time.set(3_500_000);
+    node.run_per_second_tasks().unwrap();
+    assert_eq!((Health::NOMINAL, 7), last_heartbeat_health(&node));
+    assert!(!node.health_monitor().is_lapsed(watchdog));
+}
+
+#[test]
+fn most_severe_health_wins() {
+    let mut monitor = HealthMonitor::new();
+    let start = Microseconds32::from_ticks(0);
+    let caution = monitor.add_watchdog(
+        start,
+        milliseconds(100),
+        Health {
+            value: Health::CAUTION,
+        },
+        1,
+    );
+    let advisory = monitor.add_watchdog(
+        start,
+        milliseconds(100),
+        Health {
+            value: Health::ADVISORY,
+        },
+        2,
+    );
+    monitor.set_health(Health {
+        value: Health::ADVISORY,
+    });
+    monitor.set_status_code(3);
+
+    monitor.update(
+        Microseconds32::from_ticks(1_000_000),
+        &TransportStatistics::default(),
+    );
+    assert!(monitor.is_lapsed(caution));
+    assert!(monitor.is_lapsed(advisory));
+    assert_eq!(Health::CAUTION, monitor.health().value);
+    assert_eq!(1, monitor.status_code());
+
+    // With the same severity, the application's status code has priority
+    monitor.kick(caution, Microseconds32::from_ticks(1_000_000));
+    monitor.update(
+        Microseconds32::from_ticks(1_000_001),
+        &TransportStatistics::default(),
+    );
+    assert_eq!(Health::ADVISORY, monitor.health().value);
+    assert_eq!(3, monitor.status_code());
+}
+
+#[test]
+fn transport_checks() {
+    let mut monitor = HealthMonitor::new();
+    monitor.set_transport_checks(TransportChecks {
+        congested_status_code: 10,
+        error_status_code: 20,
+        conflict_status_code: 30,
+        ..TransportChecks::default()
+    });
+    let mut statistics = TransportStatistics::default();
+    monitor.update(Microseconds32::from_ticks(0), &statistics);
+    assert_eq!(Health::NOMINAL, monitor.health().value);
+
+    // Errors during one second
+    statistics.transfers.errored = 9;
+    monitor.update(Microseconds32::from_ticks(1_000_000), &statistics);
+    assert!(!monitor.is_error_spike());
+    statistics.transfers.errored = 19;
+    monitor.update(Microseconds32::from_ticks(2_000_000), &statistics);
+    assert!(monitor.is_error_spike());
+    assert_eq!(Health::ADVISORY, monitor.health().value);
+    assert_eq!(20, monitor.status_code());
+    monitor.update(Microseconds32::from_ticks(3_000_000), &statistics);
+    assert!(!monitor.is_error_spike());
+    assert_eq!(Health::NOMINAL, monitor.health().value);
+
+    // Heartbeats that could not be published
+    monitor.record_heartbeat(false);
+    monitor.record_heartbeat(false);
+    assert!(!monitor.is_congested());
+    monitor.record_heartbeat(false);
+    assert!(monitor.is_congested());
+    assert_eq!(Health::CAUTION, monitor.health().value);
+    assert_eq!(10, monitor.status_code());
+    monitor.record_heartbeat(true);
+    assert!(!monitor.is_congested());
+    assert_eq!(Health::NOMINAL, monitor.health().value);
+
+    // Node ID conflict
+    monitor.set_node_id_conflict(true);
+    assert!(monitor.is_node_id_conflict());
+    assert_eq!(Health::WARNING, monitor.health().value);
+    assert_eq!(30, monitor.status_code());
+    monitor.set_node_id_conflict(false);
+    assert_eq!(Health::NOMINAL, monitor.health().value);
+}
+
+#[test]
+fn error_count_reset() {
+    let mut monitor = HealthMonitor::new();
+    let mut statistics = TransportStatistics::default();
+    statistics.transfers.errored = 100;
+    monitor.update(Microseconds32::from_ticks(0), &statistics);
+    assert!(!monitor.is_error_spike());
+
+    // After the statistics are reset, the error count is lower than before
+    statistics = TransportStatistics::default();
+    monitor.update(Microseconds32::from_ticks(1_000_000), &statistics);
+    assert!(!monitor.is_error_spike());
+    assert_eq!(Health::NOMINAL, monitor.health().value);
+
+    // The errors after the reset are counted from zero
+    statistics.transfers.errored = 9;
+    monitor.update(Microseconds32::from_ticks(2_000_000), &statistics);
+    assert!(!monitor.is_error_spike());
+    statistics.transfers.errored = 19;
+    monitor.update(Microseconds32::from_ticks(3_000_000), &statistics);
+    assert!(monitor.is_error_spike());
+}
+
+/// Returns the health and vendor-specific status code from the most recently sent heartbeat
+fn last_heartbeat_health(node: &MinimalNode<TestNode<'_>>) -> (u8, u8) {
+    let frame = node.node().driver().frames.last().unwrap();
+    // Uptime (4 bytes), health, mode, status code, tail byte
+    assert_eq!(8, frame.data().len());
+    (frame.data()[4], frame.data()[6])
+}
@@ -0,0 +1,259 @@
+//! Tests the node monitor with heartbeats, port lists, and GetInfo responses from another node
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+extern crate canadensis_encoding;
+extern crate heapless;
+
+mod common;
+
+use canadensis::monitor::{MonitorEvent, NodeMonitor, Subjects};
+use canadensis::TransferHandler;
+use canadensis_can::{CanNodeId, CanTransferId, CanTransport};
+use canadensis_core::time::Microseconds32;
+use canadensis_core::transfer::{MessageHeader, MessageTransfer, ServiceHeader, ServiceTransfer};
+use canadensis_core::{Priority, SubjectId};
+use canadensis_data_types::uavcan::node::get_info_1_0::{self, GetInfoResponse};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use canadensis_data_types::uavcan::node::port::list_1_0::{self, List};
+use canadensis_data_types::uavcan::node::port::service_id_list_1_0::ServiceIDList;
+use canadensis_data_types::uavcan::node::port::subject_id_1_0::SubjectID;
+use canadensis_data_types::uavcan::node::port::subject_id_list_1_0::SubjectIDList;
+use canadensis_data_types::uavcan::node::version_1_0::Version;
+use canadensis_data_types::uavcan::primitive::empty_1_0::Empty;
+use canadensis_encoding::bits::BitArray;
+use canadensis_encoding::Serialize;
+use common::test_node;
+use std::cell::Cell;
+use std::convert::TryFrom;
+
+#[test]
+fn monitor_node_lifecycle() {
+    let time = Cell::new(0);
+    let local_id = CanNodeId::try_from(1_u8).unwrap();
+    let remote_id = CanNodeId::try_from(42_u8).unwrap();
+    let mut node = test_node(&time, local_id);
+    let mut monitor = NodeMonitor::new(&mut node, true).unwrap();
+
+    // First heartbeat
+    monitor
+        .handler()
+        .handle_message(&mut node, &heartbeat(remote_id, 0, 100));
+    assert_eq!(
+        Some(MonitorEvent::Appeared(remote_id)),
+        monitor.next_event()
+    );
+    assert_eq!(None, monitor.next_event());
+    let status = monitor.node(remote_id).unwrap();
+    assert_eq!(100, status.uptime());
+    assert_eq!(Health::NOMINAL, status.health().value);
+    assert_eq!(Mode::OPERATIONAL, status.mode().value);
+    assert!(status.info().is_none());
+
+    // Polling sends a GetInfo request to the new node
+    monitor.poll(&mut node);
+    let frames = &node.driver().frames;
+    assert_eq!(1, frames.len());
+    // The destination node ID is in bits 7 through 13 of a service frame ID
+    assert_eq!(42, (u32::from(frames[0].id()) >> 7) & 0x7f);
+
+    monitor
+        .handler()
+        .handle_response(&mut node, &info_response(remote_id, local_id, 1_000));
+    assert_eq!(
+        Some((3, 7)),
+        monitor
+            .node(remote_id)
+            .unwrap()
+            .software_version()
+            .map(|version| (version.major, version.minor))
+    );
+    // No more requests after the response
+    time.set(2_000_000);
+    monitor.poll(&mut node);
+    assert_eq!(1, node.driver().frames.len());
+
+    // Port list
+    monitor
+        .handler()
+        .handle_message(&mut node, &port_list(remote_id, 2_000_000));
+    let ports = monitor.node(remote_id).unwrap().ports().unwrap();
+    assert_eq!(
+        Subjects::List(vec![
+            heartbeat_1_0::SUBJECT,
+            SubjectId::try_from(100).unwrap()
+        ]),
+        ports.publishers
+    );
+    assert_eq!(Subjects::All, ports.subscribers);
+    assert!(ports.clients.is_empty());
+    assert_eq!(vec![get_info_1_0::SERVICE], ports.servers);
+
+    // Uptime decreasing means the node restarted
+    monitor
+        .handler()
+        .handle_message(&mut node, &heartbeat(remote_id, 2_500_000, 1));
+    assert_eq!(
+        Some(MonitorEvent::Restarted(remote_id)),
+        monitor.next_event()
+    );
+    let status = monitor.node(remote_id).unwrap();
+    assert_eq!(1, status.uptime());
+    assert!(status.info().is_none());
+    assert!(status.ports().is_none());
+
+    // Still online 3 seconds after the last heartbeat
+    time.set(5_500_000);
+    monitor.poll(&mut node);
+    assert_eq!(None, monitor.next_event());
+    assert_eq!(1, monitor.nodes().count());
+    // Offline after that
+    time.set(5_500_001);
+    monitor.poll(&mut node);
+    assert_eq!(Some(MonitorEvent::Offline(remote_id)), monitor.next_event());
+    assert!(monitor.node(remote_id).is_none());
+    assert_eq!(0, monitor.nodes().count());
+}
+
+#[test]
+fn monitor_info_retry() {
+    let time = Cell::new(0);
+    let local_id = CanNodeId::try_from(1_u8).unwrap();
+    let remote_id = CanNodeId::try_from(9_u8).unwrap();
+    let mut node = test_node(&time, local_id);
+    let mut monitor = NodeMonitor::new(&mut node, true).unwrap();
+
+    for i in 0..5 {
+        time.set(i * 1_000_000);
+        monitor
+            .handler()
+            .handle_message(&mut node, &heartbeat(remote_id, i * 1_000_000, i));
+        monitor.poll(&mut node);
+    }
+    // The monitor gives up after three requests
+    assert_eq!(3, node.driver().frames.len());
+}
+
+#[test]
+fn monitor_without_info() {
+    let time = Cell::new(0);
+    let local_id = CanNodeId::try_from(1_u8).unwrap();
+    let remote_id = CanNodeId::try_from(9_u8).unwrap();
+    let mut node = test_node(&time, local_id);
+    let mut monitor = NodeMonitor::new(&mut node, false).unwrap();
+
+    // A port list from an unknown node is ignored
+    monitor
+        .handler()
+        .handle_message(&mut node, &port_list(remote_id, 0));
+    assert_eq!(0, monitor.nodes().count());
+
+    monitor
+        .handler()
+        .handle_message(&mut node, &heartbeat(remote_id, 0, 0));
+    assert_eq!(
+        Some(MonitorEvent::Appeared(remote_id)),
+        monitor.next_event()
+    );
+    monitor.poll(&mut node);
+    assert!(node.driver().frames.is_empty());
+}
+
+fn serialize<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![0u8; value.size_bits().div_ceil(8)];
+    value.serialize_to_bytes(&mut bytes);
+    bytes
+}
+
+fn message<T: Serialize>(
+    source: CanNodeId,
+    subject: SubjectId,
+    time: u32,
+    payload: &T,
+) -> MessageTransfer<Vec<u8>, CanTransport> {
+    MessageTransfer {
+        header: MessageHeader {
+            timestamp: Microseconds32::from_ticks(time),
+            transfer_id: CanTransferId::default(),
+            priority: Priority::Nominal,
+            subject,
+            source: Some(source),
+        },
+        loopback: false,
+        payload: serialize(payload),
+    }
+}
+
+fn heartbeat(source: CanNodeId, time: u32, uptime: u32) -> MessageTransfer<Vec<u8>, CanTransport> {
+    message(
+        source,
+        heartbeat_1_0::SUBJECT,
+        time,
+        &Heartbeat {
+            uptime,
+            health: Health {
+                value: Health::NOMINAL,
+            },
+            mode: Mode {
+                value: Mode::OPERATIONAL,
+            },
+            vendor_specific_status_code: 0,
+        },
+    )
+}
+
+fn port_list(source: CanNodeId, time: u32) -> MessageTransfer<Vec<u8>, CanTransport> {
+    let mut servers = BitArray::new(512);
+    servers.set(get_info_1_0::SERVICE.into(), true);
+    message(
+        source,
+        list_1_0::SUBJECT,
+        time,
+        &List {
+            publishers: SubjectIDList::SparseList({
+                let mut publishers = heapless::Vec::new();
+                let _ = publishers.push(SubjectID {
+                    value: heartbeat_1_0::SUBJECT.into(),
+                });
+                let _ = publishers.push(SubjectID { value: 100 });
+                publishers
+            }),
+            subscribers: SubjectIDList::Total(Empty {}),
+            clients: ServiceIDList {
+                mask: BitArray::new(512),
+            },
+            servers: ServiceIDList { mask: servers },
+        },
+    )
+}
+
+fn info_response(
+    source: CanNodeId,
+    destination: CanNodeId,
+    time: u32,
+) -> ServiceTransfer<Vec<u8>, CanTransport> {
+    ServiceTransfer {
+        header: ServiceHeader {
+            timestamp: Microseconds32::from_ticks(time),
+            transfer_id: CanTransferId::default(),
+            priority: Priority::Optional,
+            service: get_info_1_0::SERVICE,
+            source,
+            destination,
+        },
+        loopback: false,
+        payload: serialize(&GetInfoResponse {
+            protocol_version: Version { major: 1, minor: 0 },
+            hardware_version: Version { major: 0, minor: 0 },
+            software_version: Version { major: 3, minor: 7 },
+            software_vcs_revision_id: 0,
+            unique_id: [0; 16],
+            name: heapless::Vec::from_slice(b"org.example.remote").unwrap(),
+            software_image_crc: heapless::Vec::new(),
+            certificate_of_authenticity: heapless::Vec::new(),
+        }),
+    }
+}
@@ -0,0 +1,139 @@
+//! Tests matching responses to pending requests and request timeouts
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+
+mod common;
+
+use canadensis::pending::PendingRequests;
+use canadensis::{Node, ServiceToken, TransferHandler};
+use canadensis_can::{CanNodeId, CanTransferId, CanTransport};
+use canadensis_core::time::{milliseconds, Microseconds32};
+use canadensis_core::transfer::{ServiceHeader, ServiceTransfer};
+use canadensis_core::Priority;
+use canadensis_data_types::uavcan::node::get_info_1_0::{self, GetInfoRequest, GetInfoResponse};
+use canadensis_data_types::uavcan::node::version_1_0::Version;
+use canadensis_encoding::Serialize;
+use common::{test_node, TestNode};
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// What a callback received: the software major version and latency, or a timeout
+type Outcome = Result<(u8, u32), CanTransferId>;
+
+#[test]
+fn responses_and_timeouts() {
+    let time = Cell::new(0);
+    let local_id = CanNodeId::try_from(1_u8).unwrap();
+    let remote_id = CanNodeId::try_from(42_u8).unwrap();
+    let mut node = test_node(&time, local_id);
+    let token = node
+        .start_sending_requests::<GetInfoRequest>(
+            get_info_1_0::SERVICE,
+            milliseconds(1000),
+            313,
+            Priority::Nominal,
+        )
+        .unwrap();
+    let mut pending = PendingRequests::new();
+    let outcomes: Rc<RefCell<Vec<Outcome>>> = Rc::default();
+
+    let first = send(&mut node, &mut pending, &token, remote_id, &outcomes);
+    time.set(100_000);
+    let second = send(&mut node, &mut pending, &token, remote_id, &outcomes);
+    assert_ne!(first, second);
+    assert_eq!(2, pending.len());
+
+    // A response to the second request
+    time.set(130_000);
+    let response = response_transfer(remote_id, local_id, second, 130_000);
+    assert!(pending.handler().handle_response(&mut node, &response));
+    assert_eq!(vec![Ok((3, 30_000))], *outcomes.borrow());
+    assert_eq!(1, pending.len());
+
+    // A second response with the same transfer ID, and a response from another node, do not
+    // match anything
+    assert!(!pending.handler().handle_response(&mut node, &response));
+    let other_node = CanNodeId::try_from(43_u8).unwrap();
+    let response = response_transfer(other_node, local_id, first, 130_000);
+    assert!(!pending.handler().handle_response(&mut node, &response));
+
+    // The first request times out
+    time.set(500_000);
+    pending.poll(&mut node);
+    assert_eq!(1, pending.len());
+    time.set(500_001);
+    pending.poll(&mut node);
+    assert!(pending.is_empty());
+    assert_eq!(vec![Ok((3, 30_000)), Err(first)], *outcomes.borrow());
+
+    // A cancelled request does not call its callback
+    let third = send(&mut node, &mut pending, &token, remote_id, &outcomes);
+    assert!(pending.cancel(get_info_1_0::SERVICE, &remote_id, &third));
+    time.set(2_000_000);
+    pending.poll(&mut node);
+    assert_eq!(2, outcomes.borrow().len());
+}
+
+/// Sends a GetInfo request and records the response or timeout in `outcomes`
+fn send<'c>(
+    node: &mut TestNode<'c>,
+    pending: &mut PendingRequests<TestNode<'c>>,
+    token: &ServiceToken<GetInfoRequest>,
+    destination: CanNodeId,
+    outcomes: &Rc<RefCell<Vec<Outcome>>>,
+) -> CanTransferId {
+    let outcomes = outcomes.clone();
+    pending
+        .send(
+            node,
+            token,
+            &GetInfoRequest {},
+            destination,
+            milliseconds(500),
+            move |result| {
+                outcomes.borrow_mut().push(match result {
+                    Ok(response) => {
+                        let info: GetInfoResponse = response.deserialize().unwrap();
+                        Ok((info.software_version.major, response.latency().ticks()))
+                    }
+                    Err(timeout) => Err(timeout.transfer_id),
+                })
+            },
+        )
+        .unwrap()
+}
+
+fn response_transfer(
+    source: CanNodeId,
+    destination: CanNodeId,
+    transfer_id: CanTransferId,
+    time: u32,
+) -> ServiceTransfer<Vec<u8>, CanTransport> {
+    let response = GetInfoResponse {
+        protocol_version: Version { major: 1, minor: 0 },
+        hardware_version: Version { major: 0, minor: 0 },
+        software_version: Version { major: 3, minor: 7 },
+        software_vcs_revision_id: 0,
+        unique_id: [0; 16],
+        name: heapless::Vec::from_slice(b"org.example.remote").unwrap(),
+        software_image_crc: heapless::Vec::new(),
+        certificate_of_authenticity: heapless::Vec::new(),
+    };
+    let mut payload = vec![0u8; response.size_bits().div_ceil(8)];
+    response.serialize_to_bytes(&mut payload);
+    ServiceTransfer {
+        header: ServiceHeader {
+            timestamp: Microseconds32::from_ticks(time),
+            transfer_id,
+            priority: Priority::Nominal,
+            service: get_info_1_0::SERVICE,
+            source,
+            destination,
+        },
+        loopback: false,
+        payload,
+    }
+}
@@ -0,0 +1,572 @@
+//!
+//! Tests of the replicated plug-and-play allocator on a simulated CAN bus
+//!
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+extern crate canadensis_sim;
+
+use canadensis::anonymous::AnonymousPublisher;
+use canadensis::core::time::{milliseconds, Clock, Microseconds32};
+use canadensis::core::transfer::ServiceTransfer;
+use canadensis::core::transport::{Receiver, Transmitter};
+use canadensis::core::Priority;
+use canadensis::encoding::{DataType, Deserialize};
+use canadensis::service::pnp::cluster::{LogEntry, PnpClusterService, Role};
+use canadensis::service::pnp::storage::{ClusterRecord, ClusterStorage};
+use canadensis::service::pnp::AllocationMessage;
+use canadensis::{Node, ServiceToken, TransferHandler};
+use canadensis_can::{CanNodeId, CanReceiver, CanTransmitter, CanTransport, Mtu};
+use canadensis_data_types::uavcan::node::id_1_0::ID;
+use canadensis_data_types::uavcan::pnp::cluster::append_entries_1_0::{
+    self, AppendEntriesRequest, AppendEntriesResponse,
+};
+use canadensis_data_types::uavcan::pnp::cluster::entry_1_0::Entry;
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_1_0::{self, NodeIDAllocationData};
+use canadensis_sim::can::{CanBus, CanBusConfig, SimCanDriver, SimCanNode};
+use canadensis_sim::{LinkId, SimClock, Simulation};
+use std::convert::TryFrom;
+
+struct Allocator {
+    node: SimCanNode,
+    cluster: PnpClusterService<SimCanNode, TestStorage>,
+    link: LinkId,
+    /// The number of times that poll returned a storage error
+    storage_errors: usize,
+}
+
+impl Allocator {
+    fn new(bus: &CanBus, node_id: u8) -> Self {
+        Self::with_storage(bus, node_id, TestStorage::default())
+    }
+
+    fn with_storage(bus: &CanBus, node_id: u8, storage: TestStorage) -> Self {
+        let mut node = bus.node(CanNodeId::try_from(node_id).unwrap());
+        let link = node.driver().link();
+        // NewError is only Debug if the node is
+        let cluster = PnpClusterService::new(&mut node, 3)
+            .unwrap_or_else(|_| panic!("Failed to start allocator"))
+            .with_storage(storage)
+            .unwrap();
+        Allocator {
+            node,
+            cluster,
+            link,
+            storage_errors: 0,
+        }
+    }
+
+    fn poll(&mut self) {
+        self.node.receive(&mut self.cluster.handler()).unwrap();
+        if self.cluster.poll(&mut self.node).is_err() {
+            self.storage_errors += 1;
+        }
+        self.node.flush().ok();
+    }
+
+    fn node_id(&self) -> u16 {
+        self.node.node_id().unwrap().into()
+    }
+}
+
+/// A node that sends version 1.0 allocation requests every 800 milliseconds
+struct Allocatee {
+    unique_id: [u8; 16],
+    publisher:
+        AnonymousPublisher<SimClock, NodeIDAllocationData, CanTransmitter<SimClock, SimCanDriver>>,
+    transmitter: CanTransmitter<SimClock, SimCanDriver>,
+    receiver: CanReceiver<SimClock, SimCanDriver>,
+    driver: SimCanDriver,
+    clock: SimClock,
+    next_request: Microseconds32,
+    node_id: Option<u16>,
+}
+
+impl Allocatee {
+    fn new(simulation: &Simulation, bus: &CanBus, unique_id: [u8; 16]) -> Self {
+        let mut driver = bus.attach();
+        let mut receiver = CanReceiver::new_anonymous();
+        receiver
+            .subscribe_message(
+                node_id_allocation_data_1_0::SUBJECT,
+                9,
+                milliseconds(1000),
+                &mut driver,
+            )
+            .unwrap();
+        Allocatee {
+            unique_id,
+            publisher: AnonymousPublisher::new(
+                node_id_allocation_data_1_0::SUBJECT,
+                Priority::Nominal,
+                milliseconds(1000),
+            ),
+            transmitter: CanTransmitter::new(Mtu::Can8),
+            receiver,
+            driver,
+            clock: simulation.clock(),
+            next_request: simulation.now(),
+            node_id: None,
+        }
+    }
+
+    fn poll(&mut self) {
+        while let Some(transfer) = self
+            .receiver
+            .receive(&mut self.clock, &mut self.driver)
+            .unwrap()
+        {
+            if transfer.header.source().is_none() {
+                continue;
+            }
+            let message = NodeIDAllocationData::deserialize_from_bytes(&transfer.payload).unwrap();
+            if AllocationMessage::<CanTransport>::matches_unique_id(&message, &self.unique_id) {
+                let node_id = AllocationMessage::<CanTransport>::node_id(&message);
+                self.node_id = node_id.map(u16::from);
+            }
+        }
+        let now = self.clock.now();
+        if self.node_id.is_none() && now >= self.next_request {
+            let request: NodeIDAllocationData =
+                AllocationMessage::<CanTransport>::with_unique_id(&self.unique_id);
+            self.publisher
+                .send(
+                    &request,
+                    &mut self.clock,
+                    &mut self.transmitter,
+                    &mut self.driver,
+                )
+                .unwrap();
+            self.next_request = now + milliseconds(800);
+        }
+        self.transmitter
+            .flush(&mut self.clock, &mut self.driver)
+            .unwrap();
+    }
+}
+
+/// Cluster storage in memory that can be made to fail
+#[derive(Default)]
+struct TestStorage {
+    records: Vec<ClusterRecord>,
+    fail: bool,
+}
+
+#[derive(Debug)]
+struct StorageFailed;
+
+impl ClusterStorage for TestStorage {
+    type Error = StorageFailed;
+
+    fn load_cluster<F>(&mut self, f: F) -> Result<(), Self::Error>
+    where
+        F: FnMut(ClusterRecord),
+    {
+        self.records.iter().copied().for_each(f);
+        Ok(())
+    }
+
+    fn store_cluster(&mut self, record: ClusterRecord) -> Result<(), Self::Error> {
+        if self.fail {
+            return Err(StorageFailed);
+        }
+        self.records.push(record);
+        Ok(())
+    }
+}
+
+fn run(
+    simulation: &mut Simulation,
+    allocators: &mut [Allocator],
+    allocatees: &mut [&mut Allocatee],
+    duration_ms: u32,
+) {
+    simulation.run_for(milliseconds(duration_ms), milliseconds(1), |_now| {
+        for allocator in allocators.iter_mut() {
+            allocator.poll();
+        }
+        for allocatee in allocatees.iter_mut() {
+            allocatee.poll();
+        }
+    });
+}
+
+/// Returns the index of the only leader among the allocators that are still connected
+fn leader(allocators: &[Allocator], connected: &[usize]) -> usize {
+    let leaders: Vec<usize> = connected
+        .iter()
+        .copied()
+        .filter(|&i| allocators[i].cluster.role() == Role::Leader)
+        .collect();
+    assert_eq!(1, leaders.len(), "Expected exactly one leader");
+    let leader = leaders[0];
+    let leader_id = CanNodeId::try_from(allocators[leader].node_id()).unwrap();
+    for &i in connected {
+        assert_eq!(Some(leader_id), allocators[i].cluster.leader());
+        assert_eq!(
+            allocators[leader].cluster.term(),
+            allocators[i].cluster.term()
+        );
+    }
+    leader
+}
+
+/// Returns the node ID that an allocator has committed for a version 1.0 request
+fn committed_node_id(allocator: &Allocator, unique_id: &[u8; 16]) -> Option<u16> {
+    let request: NodeIDAllocationData =
+        AllocationMessage::<CanTransport>::with_unique_id(unique_id);
+    let mut pseudo_unique_id = [0u8; 16];
+    pseudo_unique_id[10..].copy_from_slice(&request.unique_id_hash.to_le_bytes()[..6]);
+    allocator
+        .cluster
+        .allocations()
+        .find(|entry| entry.unique_id == pseudo_unique_id)
+        .map(|entry| entry.node_id)
+}
+
+fn start_cluster(simulation: &mut Simulation, bus: &CanBus) -> Vec<Allocator> {
+    let mut allocators: Vec<Allocator> = (1..=3).map(|id| Allocator::new(bus, id)).collect();
+    run(simulation, &mut allocators, &mut [], 10_000);
+    for allocator in &allocators {
+        assert_eq!(2, allocator.cluster.known_allocators().count());
+    }
+    allocators
+}
+
+#[test]
+fn leader_allocates_and_replicates() {
+    let mut simulation = Simulation::new(1);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let mut allocators = start_cluster(&mut simulation, &bus);
+    let leader = leader(&allocators, &[0, 1, 2]);
+
+    let unique_id = [0x5a; 16];
+    let mut allocatee = Allocatee::new(&simulation, &bus, unique_id);
+    run(
+        &mut simulation,
+        &mut allocators,
+        &mut [&mut allocatee],
+        5000,
+    );
+    // 126 and 127 are reserved for network maintenance tools
+    assert_eq!(Some(125), allocatee.node_id);
+    for allocator in &allocators {
+        assert_eq!(Some(125), committed_node_id(allocator, &unique_id));
+        // The leader added an entry for itself
+        let leader_id = allocators[leader].node_id();
+        assert!(allocator
+            .cluster
+            .allocations()
+            .any(|entry| entry.node_id == leader_id && entry.unique_id == [0; 16]));
+    }
+}
+
+#[test]
+fn allocation_continues_after_leader_fails() {
+    let mut simulation = Simulation::new(2);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let mut allocators = start_cluster(&mut simulation, &bus);
+    let old_leader = leader(&allocators, &[0, 1, 2]);
+    let old_term = allocators[old_leader].cluster.term();
+
+    let unique_id_a = [0xa0; 16];
+    let mut allocatee_a = Allocatee::new(&simulation, &bus, unique_id_a);
+    run(
+        &mut simulation,
+        &mut allocators,
+        &mut [&mut allocatee_a],
+        5000,
+    );
+    assert_eq!(Some(125), allocatee_a.node_id);
+
+    bus.set_bus_off(allocators[old_leader].link, true);
+    let connected: Vec<usize> = (0..3).filter(|&i| i != old_leader).collect();
+    run(&mut simulation, &mut allocators, &mut [], 10_000);
+    let new_leader = leader(&allocators, &connected);
+    assert!(allocators[new_leader].cluster.term() > old_term);
+
+    // The new leader knows about the earlier allocation and does not give the same node ID
+    // to a different node
+    let unique_id_b = [0xb0; 16];
+    let mut allocatee_b = Allocatee::new(&simulation, &bus, unique_id_b);
+    let mut allocatee_a = Allocatee::new(&simulation, &bus, unique_id_a);
+    run(
+        &mut simulation,
+        &mut allocators,
+        &mut [&mut allocatee_a, &mut allocatee_b],
+        5000,
+    );
+    assert_eq!(Some(125), allocatee_a.node_id);
+    assert_eq!(Some(124), allocatee_b.node_id);
+    for &i in &connected {
+        assert_eq!(Some(124), committed_node_id(&allocators[i], &unique_id_b));
+    }
+}
+
+#[test]
+fn storage_failure_prevents_votes_and_entries() {
+    let mut simulation = Simulation::new(3);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let failing = TestStorage {
+        records: Vec::new(),
+        fail: true,
+    };
+    let mut allocators = vec![
+        Allocator::new(&bus, 1),
+        Allocator::new(&bus, 2),
+        Allocator::with_storage(&bus, 3, failing),
+    ];
+    run(&mut simulation, &mut allocators, &mut [], 10_000);
+
+    // The allocator that can't store anything never moves to a new term, so it does not vote
+    // or accept entries, and it reports the errors
+    let leader = leader(&allocators, &[0, 1]);
+    assert_ne!(2, leader);
+    assert!(allocators[leader].cluster.term() > 0);
+    assert_eq!(0, allocators[2].cluster.term());
+    assert_eq!(None, allocators[2].cluster.leader());
+    assert_eq!(0, allocators[2].cluster.allocations().count());
+    assert!(allocators[2].storage_errors > 0);
+    assert_eq!(0, allocators[leader].storage_errors);
+}
+
+/// Records the responses to AppendEntries requests
+#[derive(Default)]
+struct AppendResponses(Vec<(u32, bool)>);
+
+impl TransferHandler<CanTransport> for AppendResponses {
+    fn handle_response<N: Node<Transport = CanTransport>>(
+        &mut self,
+        _node: &mut N,
+        transfer: &ServiceTransfer<Vec<u8>, CanTransport>,
+    ) -> bool {
+        let response = AppendEntriesResponse::deserialize_from_bytes(&transfer.payload).unwrap();
+        self.0.push((response.term, response.success));
+        true
+    }
+}
+
+#[test]
+fn conflicting_entries_are_replaced() {
+    let mut simulation = Simulation::new(4);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let mut follower = Allocator::new(&bus, 1);
+    let mut leader = bus.node(CanNodeId::try_from(2_u8).unwrap());
+    let token: ServiceToken<AppendEntriesRequest> = leader
+        .start_sending_requests(
+            append_entries_1_0::SERVICE,
+            milliseconds(1000),
+            AppendEntriesResponse::EXTENT_BYTES.unwrap() as usize,
+            Priority::Slow,
+        )
+        .unwrap_or_else(|_| panic!("Failed to start sending requests"));
+    let follower_id = CanNodeId::try_from(1_u8).unwrap();
+
+    let entry = |term, node_id| LogEntry {
+        term,
+        unique_id: [node_id as u8; 16],
+        node_id,
+    };
+    let mut append = |term, prev_log_index, prev_log_term, leader_commit, log_entry| {
+        let mut entries = heapless::Vec::new();
+        if let Some(log_entry) = log_entry {
+            let log_entry: LogEntry = log_entry;
+            entries
+                .push(Entry {
+                    term: log_entry.term,
+                    unique_id: log_entry.unique_id,
+                    node_id: ID {
+                        value: log_entry.node_id,
+                    },
+                })
+                .ok();
+        }
+        let request = AppendEntriesRequest {
+            term,
+            prev_log_term,
+            prev_log_index,
+            leader_commit,
+            entries,
+        };
+        leader.send_request(&token, &request, follower_id).unwrap();
+        leader.flush().unwrap();
+        let mut responses = AppendResponses::default();
+        simulation.run_for(milliseconds(10), milliseconds(1), |_now| {
+            follower.poll();
+            leader.receive(&mut responses).unwrap();
+        });
+        assert_eq!(1, responses.0.len());
+        responses.0[0]
+    };
+
+    // Two entries from term 1, and only the first is committed
+    assert_eq!((1, true), append(1, 0, 0, 0, Some(entry(1, 10))));
+    assert_eq!((1, true), append(1, 1, 1, 1, Some(entry(1, 11))));
+    // A leader of term 2 has a different entry at index 2
+    assert_eq!((2, true), append(2, 1, 1, 2, Some(entry(2, 12))));
+    // Entry 2 from term 1 was replaced
+    assert_eq!((2, false), append(2, 2, 1, 2, None));
+    assert_eq!((2, true), append(2, 2, 2, 2, None));
+    assert_eq!(
+        vec![entry(1, 10), entry(2, 12)],
+        follower.cluster.allocations().collect::<Vec<_>>()
+    );
+    // The stored log ends the same way
+    let stored_entries: Vec<(u16, LogEntry)> = follower
+        .cluster
+        .storage()
+        .records
+        .iter()
+        .filter_map(|record| match *record {
+            ClusterRecord::Entry { index, entry } => Some((index, entry)),
+            ClusterRecord::Term { .. } => None,
+        })
+        .collect();
+    assert_eq!(
+        vec![(1, entry(1, 10)), (2, entry(1, 11)), (2, entry(2, 12))],
+        stored_entries
+    );
+    assert_eq!(0, follower.storage_errors);
+}
+
+#[cfg(feature = "flash")]
+mod flash {
+    extern crate embedded_storage;
+
+    use canadensis::service::pnp::cluster::LogEntry;
+    use canadensis::service::pnp::storage::{
+        AllocationStorage, ClusterRecord, ClusterStorage, FlashStorage, RECORD_SIZE,
+    };
+    use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn flash_storage_keeps_cluster_state() {
+        let flash = RamFlash::new(4 * RECORD_SIZE);
+        let mut storage = FlashStorage::new(flash.clone(), 0, 4 * RECORD_SIZE as u32);
+        let entry = |term, node_id| LogEntry {
+            term,
+            unique_id: [node_id as u8; 16],
+            node_id,
+        };
+        storage
+            .store_cluster(ClusterRecord::Term {
+                term: 1,
+                voted_for: Some(3),
+            })
+            .unwrap();
+        storage
+            .store_cluster(ClusterRecord::Entry {
+                index: 1,
+                entry: entry(1, 10),
+            })
+            .unwrap();
+        storage
+            .store_cluster(ClusterRecord::Entry {
+                index: 2,
+                entry: entry(1, 11),
+            })
+            .unwrap();
+        // A conflicting entry replaces entry 2
+        storage
+            .store_cluster(ClusterRecord::Entry {
+                index: 2,
+                entry: entry(2, 12),
+            })
+            .unwrap();
+        // The region is full. This removes the records that are no longer needed.
+        storage
+            .store_cluster(ClusterRecord::Term {
+                term: 2,
+                voted_for: None,
+            })
+            .unwrap();
+
+        let mut storage = FlashStorage::new(flash.clone(), 0, 4 * RECORD_SIZE as u32);
+        let mut records = Vec::new();
+        storage.load_cluster(|record| records.push(record)).unwrap();
+        assert_eq!(
+            vec![
+                ClusterRecord::Term {
+                    term: 1,
+                    voted_for: Some(3)
+                },
+                ClusterRecord::Entry {
+                    index: 1,
+                    entry: entry(1, 10)
+                },
+                ClusterRecord::Entry {
+                    index: 2,
+                    entry: entry(2, 12)
+                },
+                ClusterRecord::Term {
+                    term: 2,
+                    voted_for: None
+                },
+            ],
+            records
+        );
+        // Cluster records are not allocations
+        let mut count = 0;
+        storage.load(|_, _| count += 1).unwrap();
+        assert_eq!(0, count);
+    }
+
+    /// NOR flash in memory, which can only change bits from 1 to 0 when writing
+    #[derive(Clone)]
+    struct RamFlash(Rc<RefCell<Vec<u8>>>);
+
+    impl RamFlash {
+        fn new(size: usize) -> Self {
+            RamFlash(Rc::new(RefCell::new(vec![0xff; size])))
+        }
+    }
+
+    impl ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let memory = self.0.borrow();
+            let source = memory
+                .get(offset as usize..offset as usize + bytes.len())
+                .ok_or(NorFlashErrorKind::OutOfBounds)?;
+            bytes.copy_from_slice(source);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.borrow().len()
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = RECORD_SIZE;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            let mut memory = self.0.borrow_mut();
+            memory
+                .get_mut(from as usize..to as usize)
+                .ok_or(NorFlashErrorKind::OutOfBounds)?
+                .fill(0xff);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let mut memory = self.0.borrow_mut();
+            let target = memory
+                .get_mut(offset as usize..offset as usize + bytes.len())
+                .ok_or(NorFlashErrorKind::OutOfBounds)?;
+            for (target, byte) in target.iter_mut().zip(bytes) {
+                *target &= byte;
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Tests the plug-and-play server with persistent allocation storage
+
+#![cfg(feature = "flash")]
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+extern crate embedded_storage;
+
+use canadensis::node::CoreNode;
+use canadensis::requester::TransferIdFixedMap;
+use canadensis::service::pnp::server::{Assignment, PnpServerService};
+use canadensis::service::pnp::storage::{
+    AllocationStorage, FlashStorage, FlashStorageError, RECORD_SIZE,
+};
+use canadensis::service::pnp::AllocationMessage;
+use canadensis::Node;
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanId, CanNodeId, CanReceiver, CanTransmitter, CanTransport, Frame, Mtu};
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{Clock, Microseconds32};
+use canadensis_core::OutOfMemoryError;
+use canadensis_data_types::uavcan::node::heartbeat_1_0;
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_1_0::{self, NodeIDAllocationData};
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::{Infallible, TryFrom};
+use std::rc::Rc;
+
+type TestNode = CoreNode<
+    ZeroClock,
+    CanTransmitter<ZeroClock, QueueDriver>,
+    CanReceiver<ZeroClock, QueueDriver>,
+    TransferIdFixedMap<CanTransport, 4>,
+    QueueDriver,
+    4,
+    4,
+>;
+
+/// The number of node IDs on Cyphal/CAN
+const NODE_IDS: usize = 127;
+
+type Server = PnpServerService<TestNode, NODE_IDS, FlashStorage<RamFlash>>;
+
+#[test]
+fn allocations_survive_restart() {
+    let flash = RamFlash::new(1024);
+    let unique_id_a = [0xa0; 16];
+    let unique_id_b = [0xb0; 16];
+
+    let (mut node, mut server) = start_server(&flash);
+    // Node 126 is already on the bus
+    push_heartbeat(&mut node, 126);
+    push_request(&mut node, &unique_id_a);
+    receive_all(&mut node, &mut server);
+    assert_eq!(Some(125), allocated_node_id(&server, &unique_id_a));
+    assert!(!node.driver().outgoing.is_empty());
+    drop(server);
+
+    // After a restart, the allocator has not seen the heartbeat from node 126 yet, but it knows
+    // that node 125 is allocated
+    let (mut node, mut server) = start_server(&flash);
+    assert_eq!(Some(125), allocated_node_id(&server, &unique_id_a));
+    push_request(&mut node, &unique_id_b);
+    push_request(&mut node, &unique_id_a);
+    receive_all(&mut node, &mut server);
+    assert_eq!(Some(126), allocated_node_id(&server, &unique_id_b));
+    assert_eq!(Some(125), allocated_node_id(&server, &unique_id_a));
+    assert_eq!(
+        2,
+        server
+            .assignments()
+            .filter(|(_, assignment)| assignment.is_assigned())
+            .count()
+    );
+}
+
+#[test]
+fn storage_failure_prevents_allocation() {
+    // Space for only one record
+    let flash = RamFlash::new(RECORD_SIZE);
+    let (mut node, mut server) = start_server(&flash);
+    push_request(&mut node, &[1; 16]);
+    receive_all(&mut node, &mut server);
+    assert_eq!(Some(126), allocated_node_id(&server, &[1; 16]));
+    node.driver_mut().outgoing.clear();
+
+    push_request(&mut node, &[2; 16]);
+    receive_all(&mut node, &mut server);
+    assert_eq!(None, allocated_node_id(&server, &[2; 16]));
+    assert!(node.driver().outgoing.is_empty());
+    assert!(matches!(
+        server.storage_mut().store(1, Assignment::Reserved),
+        Err(FlashStorageError::Full)
+    ));
+}
+
+#[test]
+fn flash_storage_skips_corrupt_records() {
+    let flash = RamFlash::new(4 * RECORD_SIZE);
+    let mut storage = FlashStorage::new(flash.clone(), 0, 4 * RECORD_SIZE as u32);
+    storage.store(3, Assignment::Assigned([3; 16])).unwrap();
+    storage.store(4, Assignment::AssignedHash(0x1234)).unwrap();
+    // Damage the first record
+    flash.0.borrow_mut()[5] = 0;
+    storage.store(3, Assignment::Unassigned).unwrap();
+
+    let mut storage = FlashStorage::new(flash.clone(), 0, 4 * RECORD_SIZE as u32);
+    let mut records = Vec::new();
+    storage
+        .load(|node_id, assignment| records.push((node_id, assignment)))
+        .unwrap();
+    assert_eq!(
+        vec![
+            (4, Assignment::AssignedHash(0x1234)),
+            (3, Assignment::Unassigned)
+        ],
+        records
+    );
+    // New records go after the existing ones
+    storage.store(5, Assignment::Reserved).unwrap();
+    assert!(matches!(
+        storage.store(6, Assignment::Reserved),
+        Err(FlashStorageError::Full)
+    ));
+
+    storage.erase().unwrap();
+    let mut count = 0;
+    storage.load(|_, _| count += 1).unwrap();
+    assert_eq!(0, count);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn file_storage() {
+    use canadensis::service::pnp::storage::FileStorage;
+    use std::io::Write;
+
+    let path = std::env::temp_dir().join(format!("canadensis_pnp_{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    {
+        let mut storage = FileStorage::open(&path).unwrap();
+        storage.store(10, Assignment::Assigned([10; 16])).unwrap();
+    }
+    // A partly written record
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[1, 2, 3])
+        .unwrap();
+    let mut storage = FileStorage::open(&path).unwrap();
+    let mut records = Vec::new();
+    storage
+        .load(|node_id, assignment| records.push((node_id, assignment)))
+        .unwrap();
+    assert_eq!(vec![(10, Assignment::Assigned([10; 16]))], records);
+    storage.store(11, Assignment::Reserved).unwrap();
+    drop(storage);
+
+    let mut storage = FileStorage::open(&path).unwrap();
+    let mut records = Vec::new();
+    storage
+        .load(|node_id, assignment| records.push((node_id, assignment)))
+        .unwrap();
+    assert_eq!(
+        vec![
+            (10, Assignment::Assigned([10; 16])),
+            (11, Assignment::Reserved)
+        ],
+        records
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn start_server(flash: &RamFlash) -> (TestNode, Server) {
+    let node_id = CanNodeId::try_from(1_u8).unwrap();
+    let mut node = CoreNode::new(
+        ZeroClock,
+        node_id,
+        CanTransmitter::new(Mtu::Can8),
+        CanReceiver::new(node_id),
+        QueueDriver::default(),
+    );
+    let size = flash.0.borrow().len() as u32;
+    // NewError is only Debug if the node is
+    let server = PnpServerService::new(&mut node)
+        .unwrap_or_else(|_| panic!("Failed to start server"))
+        .with_storage(FlashStorage::new(flash.clone(), 0, size))
+        .unwrap();
+    (node, server)
+}
+
+fn receive_all(node: &mut TestNode, server: &mut Server) {
+    while !node.driver().incoming.is_empty() {
+        node.receive(&mut server.handler()).unwrap();
+    }
+}
+
+/// Returns the node ID allocated to a unique ID, which sent a version 1.0 request
+fn allocated_node_id(server: &Server, unique_id: &[u8; 16]) -> Option<u16> {
+    let request: NodeIDAllocationData =
+        AllocationMessage::<CanTransport>::with_unique_id(unique_id);
+    server
+        .assignments()
+        .find(|(_, assignment)| *assignment == Assignment::AssignedHash(request.unique_id_hash))
+        .map(|(node_id, _)| u16::from(node_id))
+}
+
+/// Adds an anonymous version 1.0 allocation request
+fn push_request(node: &mut TestNode, unique_id: &[u8; 16]) {
+    let request: NodeIDAllocationData =
+        AllocationMessage::<CanTransport>::with_unique_id(unique_id);
+    let subject = u32::from(u16::from(node_id_allocation_data_1_0::SUBJECT));
+    let id = (4 << 26) | (1 << 24) | (3 << 21) | (subject << 8) | 0x55;
+    // 48-bit hash, empty node ID array, tail byte
+    let mut data = request.unique_id_hash.to_le_bytes()[..6].to_vec();
+    data.extend_from_slice(&[0, 0xe0]);
+    push_frame(node, id, &data);
+}
+
+fn push_heartbeat(node: &mut TestNode, source: u32) {
+    let subject = u32::from(u16::from(heartbeat_1_0::SUBJECT));
+    let id = (4 << 26) | (3 << 21) | (subject << 8) | source;
+    push_frame(node, id, &[0, 0, 0, 0, 0, 0, 0, 0xe0]);
+}
+
+fn push_frame(node: &mut TestNode, id: u32, data: &[u8]) {
+    node.driver_mut().incoming.push_back(Frame::new(
+        Microseconds32::from_ticks(0),
+        CanId::try_from(id).unwrap(),
+        data,
+    ));
+}
+
+/// NOR flash in memory, which can only change bits from 1 to 0 when writing
+#[derive(Clone)]
+struct RamFlash(Rc<RefCell<Vec<u8>>>);
+
+impl RamFlash {
+    fn new(size: usize) -> Self {
+        RamFlash(Rc::new(RefCell::new(vec![0xff; size])))
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let memory = self.0.borrow();
+        let source = memory
+            .get(offset as usize..offset as usize + bytes.len())
+            .ok_or(NorFlashErrorKind::OutOfBounds)?;
+        bytes.copy_from_slice(source);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.borrow().len()
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = RECORD_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let mut memory = self.0.borrow_mut();
+        memory
+            .get_mut(from as usize..to as usize)
+            .ok_or(NorFlashErrorKind::OutOfBounds)?
+            .fill(0xff);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let mut memory = self.0.borrow_mut();
+        let target = memory
+            .get_mut(offset as usize..offset as usize + bytes.len())
+            .ok_or(NorFlashErrorKind::OutOfBounds)?;
+        for (target, byte) in target.iter_mut().zip(bytes) {
+            *target &= byte;
+        }
+        Ok(())
+    }
+}
+
+/// A CAN driver that records outgoing frames and receives frames from a queue
+#[derive(Default)]
+struct QueueDriver {
+    outgoing: Vec<Frame>,
+    incoming: VecDeque<Frame>,
+}
+
+impl TransmitDriver<ZeroClock> for QueueDriver {
+    type Error = Infallible;
+
+    fn try_reserve(&mut self, _frames: usize) -> Result<(), OutOfMemoryError> {
+        Ok(())
+    }
+
+    fn transmit(
+        &mut self,
+        frame: Frame,
+        _clock: &mut ZeroClock,
+    ) -> canadensis::nb::Result<Option<Frame>, Self::Error> {
+        self.outgoing.push(frame);
+        Ok(None)
+    }
+
+    fn flush(&mut self, _clock: &mut ZeroClock) -> canadensis::nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ReceiveDriver<ZeroClock> for QueueDriver {
+    type Error = Infallible;
+
+    fn receive(&mut self, _clock: &mut ZeroClock) -> canadensis::nb::Result<Frame, Self::Error> {
+        self.incoming
+            .pop_front()
+            .ok_or(canadensis::nb::Error::WouldBlock)
+    }
+
+    fn apply_filters<S>(&mut self, _local_node: Option<CanNodeId>, _subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+    }
+
+    fn apply_accept_all(&mut self) {}
+}
+
+struct ZeroClock;
+
+impl Clock for ZeroClock {
+    fn now(&mut self) -> Microseconds32 {
+        Microseconds32::from_ticks(0)
+    }
+}
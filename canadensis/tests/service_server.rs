@@ -0,0 +1,208 @@
+//! Tests the generic service server with immediate and deferred responses
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+extern crate canadensis_encoding;
+
+use canadensis::node::CoreNode;
+use canadensis::requester::TransferIdFixedMap;
+use canadensis::service::server::{Reply, ServiceServer};
+use canadensis::{Node, ResponseToken};
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanId, CanNodeId, CanReceiver, CanTransmitter, CanTransport, Frame, Mtu};
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{milliseconds, Clock, Microseconds32};
+use canadensis_core::{OutOfMemoryError, ServiceId};
+use canadensis_data_types::uavcan::register::access_1_0::{self, AccessRequest, AccessResponse};
+use canadensis_data_types::uavcan::register::list_1_0::{self, ListRequest, ListResponse};
+use canadensis_data_types::uavcan::register::name_1_0::Name;
+use canadensis_encoding::Deserialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::{Infallible, TryFrom};
+use std::rc::Rc;
+
+type TestNode = CoreNode<
+    ZeroClock,
+    CanTransmitter<ZeroClock, QueueDriver>,
+    CanReceiver<ZeroClock, QueueDriver>,
+    TransferIdFixedMap<CanTransport, 4>,
+    QueueDriver,
+    4,
+    4,
+>;
+
+/// A register index and the token for responding to a list request
+type Deferred = (u16, ResponseToken<CanTransport>);
+
+#[test]
+fn immediate_response() {
+    let mut node = make_node();
+    let mut server = ServiceServer::new(
+        &mut node,
+        list_1_0::SERVICE,
+        2,
+        |request: ListRequest, call| {
+            assert_eq!(42, u16::from(call.header().source));
+            call.reply(register_name(request.index))
+        },
+    )
+    .unwrap();
+
+    push_request(&mut node, list_1_0::SERVICE, &[3, 0]);
+    node.receive(&mut server.handler()).unwrap();
+
+    let response: ListResponse = single_frame_response(&node.driver().outgoing[0]);
+    assert_eq!(b"r3", &response.name.name[..]);
+    assert_eq!(0, server.deserialize_errors());
+}
+
+#[test]
+fn deferred_response() {
+    let mut node = make_node();
+    let deferred: Rc<RefCell<Vec<Deferred>>> = Rc::default();
+    let mut server = {
+        let deferred = deferred.clone();
+        ServiceServer::<_, ListRequest, ListResponse>::new(
+            &mut node,
+            list_1_0::SERVICE,
+            2,
+            move |request: ListRequest, call| {
+                let (reply, token) = call.defer();
+                deferred.borrow_mut().push((request.index, token));
+                reply
+            },
+        )
+        .unwrap()
+    };
+
+    push_request(&mut node, list_1_0::SERVICE, &[9, 0]);
+    node.receive(&mut server.handler()).unwrap();
+    assert!(node.driver().outgoing.is_empty());
+
+    let (index, token) = deferred.borrow_mut().pop().unwrap();
+    node.send_response(token, milliseconds(1000), &register_name(index))
+        .unwrap();
+    let response: ListResponse = single_frame_response(&node.driver().outgoing[0]);
+    assert_eq!(b"r9", &response.name.name[..]);
+}
+
+#[test]
+fn invalid_request_and_other_service() {
+    let mut node = make_node();
+    let mut server = ServiceServer::new(
+        &mut node,
+        access_1_0::SERVICE,
+        515,
+        |_request: AccessRequest, _call| -> Reply<AccessResponse, CanTransport> {
+            panic!("Handler called")
+        },
+    )
+    .unwrap();
+    // Also subscribe to another service, which the server does not handle
+    node.subscribe_request(list_1_0::SERVICE, 2, milliseconds(1000))
+        .unwrap();
+
+    // Empty name, then a value union tag that is out of range
+    push_request(&mut node, access_1_0::SERVICE, &[0, 0xff]);
+    node.receive(&mut server.handler()).unwrap();
+    assert_eq!(1, server.deserialize_errors());
+
+    push_request(&mut node, list_1_0::SERVICE, &[0, 0]);
+    node.receive(&mut server.handler()).unwrap();
+    assert!(node.driver().outgoing.is_empty());
+    assert_eq!(1, server.deserialize_errors());
+}
+
+fn make_node() -> TestNode {
+    let local_id = CanNodeId::try_from(1_u8).unwrap();
+    CoreNode::new(
+        ZeroClock,
+        local_id,
+        CanTransmitter::new(Mtu::Can8),
+        CanReceiver::new(local_id),
+        QueueDriver::default(),
+    )
+}
+
+fn register_name(index: u16) -> ListResponse {
+    ListResponse {
+        name: Name {
+            name: heapless::Vec::from_slice(format!("r{}", index).as_bytes()).unwrap(),
+        },
+    }
+}
+
+/// Adds a single-frame request from node 42 to node 1, with transfer ID 0
+fn push_request(node: &mut TestNode, service: ServiceId, payload: &[u8]) {
+    let request_id =
+        (4 << 26) | (1 << 25) | (1 << 24) | (u32::from(u16::from(service)) << 14) | (1 << 7) | 42;
+    let mut data = payload.to_vec();
+    data.push(0xe0);
+    node.driver_mut().incoming.push_back(Frame::new(
+        Microseconds32::from_ticks(0),
+        CanId::try_from(request_id).unwrap(),
+        &data,
+    ));
+}
+
+/// Deserializes the payload of a response that fits into one frame
+fn single_frame_response<R: Deserialize>(frame: &Frame) -> R {
+    let data = frame.data();
+    R::deserialize_from_bytes(&data[..data.len() - 1]).unwrap()
+}
+
+/// A CAN driver that records outgoing frames and receives frames from a queue
+#[derive(Default)]
+struct QueueDriver {
+    outgoing: Vec<Frame>,
+    incoming: VecDeque<Frame>,
+}
+
+impl TransmitDriver<ZeroClock> for QueueDriver {
+    type Error = Infallible;
+
+    fn try_reserve(&mut self, _frames: usize) -> Result<(), OutOfMemoryError> {
+        Ok(())
+    }
+
+    fn transmit(
+        &mut self,
+        frame: Frame,
+        _clock: &mut ZeroClock,
+    ) -> canadensis::nb::Result<Option<Frame>, Self::Error> {
+        self.outgoing.push(frame);
+        Ok(None)
+    }
+
+    fn flush(&mut self, _clock: &mut ZeroClock) -> canadensis::nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl ReceiveDriver<ZeroClock> for QueueDriver {
+    type Error = Infallible;
+
+    fn receive(&mut self, _clock: &mut ZeroClock) -> canadensis::nb::Result<Frame, Self::Error> {
+        self.incoming
+            .pop_front()
+            .ok_or(canadensis::nb::Error::WouldBlock)
+    }
+
+    fn apply_filters<S>(&mut self, _local_node: Option<CanNodeId>, _subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+    }
+
+    fn apply_accept_all(&mut self) {}
+}
+
+struct ZeroClock;
+
+impl Clock for ZeroClock {
+    fn now(&mut self) -> Microseconds32 {
+        Microseconds32::from_ticks(0)
+    }
+}
@@ -2,6 +2,7 @@ extern crate itertools;
 extern crate num_integer;
 
 mod operator;
+mod text;
 
 use std::cmp::Ordering;
 use std::collections::BTreeSet;
@@ -9,6 +10,7 @@ use std::ops::{RangeToInclusive, Rem};
 use std::{iter, mem};
 
 use crate::operator::Operator;
+pub use crate::text::ParseBitLengthSetError;
 
 /// A non-empty set of possible lengths (in bits) for a data type
 ///
@@ -0,0 +1,257 @@
+//! A text form of bit length sets that keeps their structure
+//!
+//! Expanding a bit length set can take a lot of time and memory, so the text form describes the
+//! operators that make up a set instead of listing its values:
+//!
+//! * `{0,8,16}`: a set of fixed values
+//! * `pad(8,...)`: padding to a multiple of 8 bits
+//! * `cat(...,...)`: concatenation
+//! * `rep(3,...)`: 3 repetitions
+//! * `rep(..=3,...)`: 0 to 3 repetitions
+//! * `union(...,...)`: union
+//!
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use crate::operator::Operator;
+use crate::BitLengthSet;
+
+impl Display for BitLengthSet {
+    /// Formats this set in a form that [`from_str`](#method.from_str) can parse
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use canadensis_bit_length_set::bit_length;
+    /// let lengths = bit_length![8].concatenate([bit_length![16].repeat_range(..=3)]);
+    /// assert_eq!("cat({8},rep(..=3,{16}))", lengths.to_string());
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt_operator(&self.operator, f)
+    }
+}
+
+fn fmt_operator(operator: &Operator, f: &mut Formatter<'_>) -> fmt::Result {
+    match operator {
+        Operator::Leaf(values) => {
+            f.write_str("{")?;
+            for (i, value) in values.iter().enumerate() {
+                if i != 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{}", value)?;
+            }
+            f.write_str("}")
+        }
+        Operator::Padding { child, alignment } => {
+            write!(f, "pad({},", alignment)?;
+            fmt_operator(child, f)?;
+            f.write_str(")")
+        }
+        Operator::Concatenate { children } => fmt_children("cat", children, f),
+        Operator::Repeat { child, count } => {
+            write!(f, "rep({},", count)?;
+            fmt_operator(child, f)?;
+            f.write_str(")")
+        }
+        Operator::RangeRepeat { child, count } => {
+            write!(f, "rep(..={},", count.end)?;
+            fmt_operator(child, f)?;
+            f.write_str(")")
+        }
+        Operator::Union { children } => fmt_children("union", children, f),
+    }
+}
+
+fn fmt_children(name: &str, children: &[Operator], f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}(", name)?;
+    for (i, child) in children.iter().enumerate() {
+        if i != 0 {
+            f.write_str(",")?;
+        }
+        fmt_operator(child, f)?;
+    }
+    f.write_str(")")
+}
+
+impl FromStr for BitLengthSet {
+    type Err = ParseBitLengthSetError;
+
+    /// Parses a set from the form that [`Display`] produces
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use canadensis_bit_length_set::{bit_length, BitLengthSet};
+    /// let lengths: BitLengthSet = "pad(8,cat({1},{0,4}))".parse().unwrap();
+    /// assert_eq!(bit_length![8], lengths);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { rest: s };
+        let operator = parser.operator()?;
+        if parser.rest.is_empty() {
+            Ok(BitLengthSet { operator })
+        } else {
+            Err(ParseBitLengthSetError(()))
+        }
+    }
+}
+
+/// An error that occurs when parsing a bit length set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBitLengthSetError(());
+
+impl Display for ParseBitLengthSetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid bit length set")
+    }
+}
+
+impl Error for ParseBitLengthSetError {}
+
+struct Parser<'s> {
+    rest: &'s str,
+}
+
+impl Parser<'_> {
+    fn operator(&mut self) -> Result<Operator, ParseBitLengthSetError> {
+        if self.eat("{") {
+            let mut values = BTreeSet::new();
+            loop {
+                values.insert(self.number()?);
+                if self.eat("}") {
+                    return Ok(Operator::Leaf(values));
+                }
+                self.expect(",")?;
+            }
+        } else if self.eat("pad(") {
+            let alignment = self.number()?;
+            self.expect(",")?;
+            let child = Box::new(self.operator()?);
+            self.expect(")")?;
+            let alignment = u32::try_from(alignment).map_err(|_| ParseBitLengthSetError(()))?;
+            if alignment == 0 {
+                return Err(ParseBitLengthSetError(()));
+            }
+            Ok(Operator::Padding { child, alignment })
+        } else if self.eat("cat(") {
+            Ok(Operator::Concatenate {
+                children: self.children()?,
+            })
+        } else if self.eat("rep(") {
+            let range = self.eat("..=");
+            let count = self.number()?;
+            self.expect(",")?;
+            let child = Box::new(self.operator()?);
+            self.expect(")")?;
+            if range {
+                Ok(Operator::RangeRepeat {
+                    child,
+                    count: ..=count,
+                })
+            } else {
+                Ok(Operator::Repeat { child, count })
+            }
+        } else if self.eat("union(") {
+            Ok(Operator::Union {
+                children: self.children()?,
+            })
+        } else {
+            Err(ParseBitLengthSetError(()))
+        }
+    }
+
+    /// Parses one or more operators separated by commas, and the closing parenthesis
+    fn children(&mut self) -> Result<Vec<Operator>, ParseBitLengthSetError> {
+        let mut children = Vec::new();
+        loop {
+            children.push(self.operator()?);
+            if self.eat(")") {
+                return Ok(children);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, ParseBitLengthSetError> {
+        let length = self
+            .rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let (digits, rest) = self.rest.split_at(length);
+        self.rest = rest;
+        digits.parse().map_err(|_| ParseBitLengthSetError(()))
+    }
+
+    /// Removes a prefix from the remaining text and returns true, or returns false if the text
+    /// does not start with the prefix
+    fn eat(&mut self, prefix: &str) -> bool {
+        match self.rest.strip_prefix(prefix) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, prefix: &str) -> Result<(), ParseBitLengthSetError> {
+        if self.eat(prefix) {
+            Ok(())
+        } else {
+            Err(ParseBitLengthSetError(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{bit_length, BitLengthSet};
+
+    fn round_trip(set: BitLengthSet) {
+        let text = set.to_string();
+        let parsed: BitLengthSet = text.parse().unwrap();
+        assert!(
+            set.structural_equal(&parsed),
+            "{} changed when parsed",
+            text
+        );
+    }
+
+    #[test]
+    fn text_round_trip() {
+        round_trip(bit_length![0]);
+        round_trip(bit_length![1, 8, 3000]);
+        round_trip(
+            bit_length![8]
+                .concatenate([bit_length![16].repeat_range(..=3), bit_length![2]])
+                .pad_to_alignment(8),
+        );
+        round_trip(
+            bit_length![1]
+                .repeat(7)
+                .unite([bit_length![32, 64], bit_length![3].repeat_range(..=0)]),
+        );
+    }
+
+    #[test]
+    fn text_invalid() {
+        for text in [
+            "",
+            "{}",
+            "{1,}",
+            "{1",
+            "pad(0,{1})",
+            "cat()",
+            "rep(3,{1}",
+            "{1}x",
+            "union({1};{2})",
+        ] {
+            assert!(text.parse::<BitLengthSet>().is_err(), "{} was parsed", text);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::image::Image;
+
+/// Starts an application image
+///
+/// How to start an image depends on the microcontroller. Depending on the hardware, an
+/// implementation can jump to the image (like `CortexMBoot`) or swap the flash banks and reset.
+pub trait Boot {
+    /// Starts the application in `image`
+    ///
+    /// The image has a valid CRC.
+    fn boot(&mut self, image: &Image) -> !;
+}
+
+/// Starts images on a Cortex-M microcontroller by jumping to their vector tables
+///
+/// Each image must start with its vector table. This sets the main stack pointer to the first
+/// entry in the vector table and jumps to the reset handler. It does not set the vector table
+/// offset register, so the application should set it (for example, with the `set-vtor` feature
+/// of `cortex-m-rt`).
+#[cfg(feature = "cortex-m")]
+pub struct CortexMBoot {
+    /// The address where offset 0 of the flash is mapped
+    flash_address: u32,
+}
+
+#[cfg(feature = "cortex-m")]
+impl CortexMBoot {
+    /// Creates a boot hook
+    ///
+    /// * `flash_address`: The address where offset 0 of the flash is mapped
+    ///
+    /// # Safety
+    ///
+    /// `flash_address` must be the address of the flash that the bootloader writes images to,
+    /// and every image with a valid CRC must start with a valid vector table.
+    pub unsafe fn new(flash_address: u32) -> Self {
+        CortexMBoot { flash_address }
+    }
+}
+
+#[cfg(feature = "cortex-m")]
+impl Boot for CortexMBoot {
+    fn boot(&mut self, image: &Image) -> ! {
+        let vector_table = (self.flash_address + image.offset) as *const u32;
+        // The requirements of new() make this safe
+        unsafe { cortex_m::asm::bootload(vector_table) }
+    }
+}
@@ -0,0 +1,195 @@
+use core::mem;
+
+use canadensis_crc::AppDescriptor;
+use crc_any::CRCu64;
+use embedded_storage::nor_flash::NorFlash;
+
+/// The size of the buffer used to read images from flash
+pub(crate) const READ_CHUNK: usize = 256;
+
+// The offsets of the fields in an app descriptor that the bootloader uses, from the format in
+// the AppDescriptor documentation
+const DESCRIPTOR_CRC: u32 = 8;
+const DESCRIPTOR_IMAGE_SIZE: u32 = 16;
+const DESCRIPTOR_CRC_VALID: u32 = 20;
+
+/// A region of flash
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Region {
+    /// The offset of the region from the beginning of the flash
+    pub offset: u32,
+    /// The size of the region in bytes
+    pub size: u32,
+}
+
+/// One of the two flash banks that hold images
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Bank {
+    /// The first bank
+    A,
+    /// The second bank
+    B,
+}
+
+impl Bank {
+    /// Returns the bank that is not this bank
+    pub fn other(self) -> Bank {
+        match self {
+            Bank::A => Bank::B,
+            Bank::B => Bank::A,
+        }
+    }
+
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Bank::A => 0,
+            Bank::B => 1,
+        }
+    }
+}
+
+/// Where the bootloader stores images
+#[derive(Debug, Clone)]
+pub struct Layout {
+    /// The regions of flash that hold images, for bank A and bank B
+    ///
+    /// Each region must start and end on a flash erase boundary.
+    pub banks: [Region; 2],
+    /// The region of flash where the bootloader records the length and CRC of each image that
+    /// it has written
+    ///
+    /// This region must start and end on a flash erase boundary, and must not overlap either
+    /// bank.
+    pub metadata: Region,
+}
+
+impl Layout {
+    /// Returns the region for a bank
+    pub fn bank(&self, bank: Bank) -> Region {
+        self.banks[bank.index()]
+    }
+}
+
+/// An image in a bank that has a valid CRC
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Image {
+    /// The bank that contains the image
+    pub bank: Bank,
+    /// The offset of the image from the beginning of the flash
+    pub offset: u32,
+    /// The length of the image in bytes
+    pub length: u32,
+    /// The CRC of the image
+    pub crc: u64,
+}
+
+/// Reads an image from a bank and checks its CRC
+///
+/// The CRC is in the image's app descriptor, which is found by its signature. This returns
+/// `Ok(None)` if the image does not have exactly one app descriptor, the descriptor's valid flag
+/// is not set, the image size in the descriptor is not the length of the image, or the CRC does
+/// not match.
+pub(crate) fn check_image<F: NorFlash>(
+    flash: &mut F,
+    layout: &Layout,
+    bank: Bank,
+    length: u32,
+) -> Result<Option<Image>, F::Error> {
+    let region = layout.bank(bank);
+    if length > region.size {
+        return Ok(None);
+    }
+    let descriptor = match find_descriptor(flash, region.offset, length)? {
+        Some(descriptor) => descriptor,
+        None => return Ok(None),
+    };
+    let crc_range = descriptor + DESCRIPTOR_CRC..descriptor + DESCRIPTOR_CRC + 8;
+    let size_range = descriptor + DESCRIPTOR_IMAGE_SIZE..descriptor + DESCRIPTOR_IMAGE_SIZE + 4;
+
+    let mut stored_crc = [0u8; 8];
+    let mut stored_size = [0u8; 4];
+    let mut valid = 0u8;
+    let mut crc = CRCu64::crc64we();
+    for_each_chunk(flash, region.offset, length, |position, chunk| {
+        for (i, byte) in chunk.iter_mut().enumerate() {
+            let image_offset = position + i as u32;
+            if crc_range.contains(&image_offset) {
+                // The CRC is calculated with the CRC field set to zero
+                stored_crc[(image_offset - crc_range.start) as usize] = *byte;
+                *byte = 0;
+            } else if size_range.contains(&image_offset) {
+                stored_size[(image_offset - size_range.start) as usize] = *byte;
+            } else if image_offset == descriptor + DESCRIPTOR_CRC_VALID {
+                valid = *byte;
+            }
+        }
+        crc.digest(chunk);
+    })?;
+    // canadensis_write_crc pads the image with zeros to a multiple of 8 bytes
+    let padding = (8 - length % 8) % 8;
+    crc.digest(&[0u8; 8][..padding as usize]);
+
+    let crc = crc.get_crc();
+    if valid == 1
+        && u32::from_le_bytes(stored_size) == length
+        && crc == u64::from_le_bytes(stored_crc)
+    {
+        Ok(Some(Image {
+            bank,
+            offset: region.offset,
+            length,
+            crc,
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns the offset of the one app descriptor in an image, or None if the image has no
+/// descriptor or more than one
+///
+/// Descriptors are aligned to 8 bytes, so the signature is only checked at multiples of 8 from
+/// the beginning of the image.
+fn find_descriptor<F: NorFlash>(
+    flash: &mut F,
+    offset: u32,
+    length: u32,
+) -> Result<Option<u32>, F::Error> {
+    let mut found = None;
+    let mut count = 0;
+    for_each_chunk(flash, offset, length, |position, chunk| {
+        // Chunks start at multiples of 8, so an aligned signature is never split between them
+        for (i, window) in chunk
+            .chunks_exact(AppDescriptor::SIGNATURE.len())
+            .enumerate()
+        {
+            if window == AppDescriptor::SIGNATURE {
+                found = Some(position + (i * AppDescriptor::SIGNATURE.len()) as u32);
+                count += 1;
+            }
+        }
+    })?;
+    Ok(found.filter(|&descriptor| {
+        count == 1 && descriptor + mem::size_of::<AppDescriptor>() as u32 <= length
+    }))
+}
+
+/// Reads `length` bytes of flash starting at `offset` and calls `f` with the position of each
+/// chunk from `offset` and the chunk
+fn for_each_chunk<F, G>(flash: &mut F, offset: u32, length: u32, mut f: G) -> Result<(), F::Error>
+where
+    F: NorFlash,
+    G: FnMut(u32, &mut [u8]),
+{
+    let mut chunk = [0u8; READ_CHUNK];
+    let mut position = 0u32;
+    while position < length {
+        let used = (length - position).min(READ_CHUNK as u32) as usize;
+        // Reads must be a multiple of the read size, and the bank is big enough for that.
+        let read_length = used.next_multiple_of(F::READ_SIZE).min(READ_CHUNK);
+        flash.read(offset + position, &mut chunk[..read_length])?;
+        f(position, &mut chunk[..used]);
+        position += used as u32;
+    }
+    Ok(())
+}
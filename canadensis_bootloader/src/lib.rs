@@ -0,0 +1,368 @@
+//!
+//! # Canadensis bootloader
+//!
+//! This library implements a Cyphal bootloader that downloads software images and writes them to
+//! one of two flash banks.
+//!
+//! A [`Bootloader`] runs a node that reports `Mode::SOFTWARE_UPDATE` in its heartbeats and
+//! responds to `uavcan.node.GetInfo` requests. When it receives a `uavcan.node.ExecuteCommand`
+//! request with `COMMAND_BEGIN_SOFTWARE_UPDATE`, it reads the file named in the request from the
+//! node that sent the request using `uavcan.file.Read`. It writes the file to the bank that does
+//! not contain the current image, so the current image can still start if the update fails.
+//! After checking the CRC of the new image, the bootloader records that the new image is the one
+//! to start.
+//!
+//! ## Image CRCs
+//!
+//! Each image must contain one `canadensis_crc::AppDescriptor` that `canadensis_write_crc` has
+//! filled in. The bootloader finds the descriptor by its signature, and checks that its image
+//! size matches the length of the file and that its CRC matches the CRC of the image. The file
+//! that the bootloader downloads must contain exactly the image (for a .elf file, use
+//! `objcopy -O binary --gap-fill 0xff` after `canadensis_write_crc`).
+//!
+//! ## Starting the application
+//!
+//! How to start the application depends on the microcontroller. A [`Boot`] implementation
+//! starts an [`Image`]. With the `cortex-m` feature, `CortexMBoot` jumps to an image on a
+//! Cortex-M microcontroller. Other platforms can implement [`Boot`] to swap the flash banks or
+//! jump to the image in another way.
+//!
+//! ## Steps
+//!
+//! * Create a node with a node ID and call [`Bootloader::new`]
+//! * If the application has asked for an update (for example, with a value in memory that is
+//!   not cleared at reset), call [`Bootloader::begin_update`] with the node ID and path from the
+//!   application's `ExecuteCommand` request
+//! * Otherwise, call [`Bootloader::bootable_image`]. If it returns an image, start it with
+//!   [`Bootloader::boot`].
+//! * Call [`Bootloader::poll`] repeatedly until it returns an image, then start that image
+//!
+
+#![no_std]
+#![deny(missing_docs)]
+
+extern crate alloc;
+
+extern crate canadensis;
+extern crate canadensis_crc;
+extern crate canadensis_data_types;
+#[cfg(feature = "cortex-m")]
+extern crate cortex_m;
+extern crate crc_any;
+extern crate embedded_storage;
+extern crate heapless;
+
+mod boot;
+mod image;
+mod metadata;
+mod update;
+
+pub use crate::boot::Boot;
+#[cfg(feature = "cortex-m")]
+pub use crate::boot::CortexMBoot;
+pub use crate::image::{Bank, Image, Layout, Region};
+
+use core::fmt::{Debug, Formatter};
+
+use canadensis::core::time::{milliseconds, Microseconds32};
+use canadensis::core::transport::{Receiver, Transmitter, Transport};
+use canadensis::core::{Priority, ServiceSubscribeError};
+use canadensis::encoding::DataType;
+use canadensis::service::get_info::GetInfoService;
+use canadensis::service::heartbeat::HeartbeatService;
+use canadensis::{nb, Node, StartSendError, TransferHandler};
+use canadensis_data_types::uavcan::file::path_2_0::Path;
+use canadensis_data_types::uavcan::file::read_1_1::{self, ReadResponse};
+use canadensis_data_types::uavcan::node::execute_command_1_3::{self, ExecuteCommandRequest};
+use canadensis_data_types::uavcan::node::get_info_1_0::GetInfoResponse;
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::image::READ_CHUNK;
+use crate::metadata::RECORD_SIZE;
+use crate::update::Updater;
+
+type TxError<N> = <<N as Node>::Transmitter as Transmitter<<N as Node>::Clock>>::Error;
+type RxError<N> = <<N as Node>::Receiver as Receiver<<N as Node>::Clock>>::Error;
+
+/// A bootloader that updates images in two flash banks
+pub struct Bootloader<N: Node, F: NorFlash> {
+    node: N,
+    heartbeat: HeartbeatService<N>,
+    get_info: GetInfoService<N>,
+    updater: Updater<N, F>,
+    /// The time to run the next per-second tasks, or None before the first call to poll()
+    next_second: Option<Microseconds32>,
+}
+
+impl<N, F> Bootloader<N, F>
+where
+    N: Node,
+    F: NorFlash,
+    <N::Transport as Transport>::TransferId: PartialEq,
+{
+    /// Creates a bootloader
+    ///
+    /// * `node`: The node to use. This node must have a node ID.
+    /// * `flash`: The flash that contains the banks and the metadata region
+    /// * `layout`: The locations of the banks and the metadata region in `flash`
+    /// * `node_info`: The response to send to `uavcan.node.GetInfo` requests
+    ///
+    /// # Panics
+    ///
+    /// This function panics if a bank or the metadata region does not start and end on a flash
+    /// erase boundary, if the metadata region is too small for two records, or if the flash has
+    /// an unsupported read or write size.
+    pub fn new(
+        mut node: N,
+        flash: F,
+        layout: Layout,
+        node_info: GetInfoResponse,
+    ) -> Result<Self, NewError<N>> {
+        for region in layout
+            .banks
+            .iter()
+            .chain(core::iter::once(&layout.metadata))
+        {
+            assert_eq!(
+                region.offset as usize % F::ERASE_SIZE,
+                0,
+                "Offset not aligned"
+            );
+            assert_eq!(region.size as usize % F::ERASE_SIZE, 0, "Size not aligned");
+        }
+        assert!(
+            layout.metadata.size as usize >= RECORD_SIZE * 2,
+            "Metadata region too small"
+        );
+        assert_eq!(RECORD_SIZE % F::WRITE_SIZE, 0, "Unsupported write size");
+        assert_eq!(RECORD_SIZE % F::READ_SIZE, 0, "Unsupported read size");
+        assert_eq!(READ_CHUNK % F::READ_SIZE, 0, "Unsupported read size");
+
+        let mut heartbeat = HeartbeatService::new(&mut node).map_err(NewError::Publish)?;
+        heartbeat.set_mode(Mode {
+            value: Mode::SOFTWARE_UPDATE,
+        });
+        let get_info = GetInfoService::new(&mut node, node_info).map_err(NewError::Subscribe)?;
+        node.subscribe_request(
+            execute_command_1_3::SERVICE,
+            ExecuteCommandRequest::EXTENT_BYTES.unwrap() as usize,
+            milliseconds(1000),
+        )
+        .map_err(NewError::Subscribe)?;
+        let read_token = node
+            .start_sending_requests(
+                read_1_1::SERVICE,
+                milliseconds(1000),
+                ReadResponse::EXTENT_BYTES.unwrap() as usize,
+                Priority::Nominal.into(),
+            )
+            .map_err(NewError::Request)?;
+
+        Ok(Bootloader {
+            node,
+            heartbeat,
+            get_info,
+            updater: Updater::new(flash, layout, read_token),
+            next_second: None,
+        })
+    }
+
+    /// Returns the newest image that the bootloader has written and that still has a valid CRC
+    ///
+    /// If the newest image is damaged, this returns the image in the other bank if that image
+    /// is valid.
+    pub fn bootable_image(&mut self) -> Result<Option<Image>, F::Error> {
+        self.updater.bootable_image()
+    }
+
+    /// Starts downloading an image
+    ///
+    /// The bootloader calls this function when it receives a `uavcan.node.ExecuteCommand`
+    /// request with `COMMAND_BEGIN_SOFTWARE_UPDATE`. The application can call it to continue an
+    /// update that the application accepted before resetting into the bootloader.
+    ///
+    /// * `server`: The node to read the image file from
+    /// * `path`: The path of the image file
+    ///
+    /// If an image is already downloading, that download stops.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `path` is longer than 255 bytes.
+    pub fn begin_update(&mut self, server: <N::Transport as Transport>::NodeId, path: &[u8]) {
+        let path = Path {
+            path: heapless::Vec::from_slice(path).expect("Path too long"),
+        };
+        self.updater.begin(server, path);
+    }
+
+    /// Handles incoming transfers, continues any download, publishes a heartbeat once per
+    /// second, and sends outgoing frames
+    ///
+    /// * `now`: The current time
+    ///
+    /// This returns an image when an update has finished and the new image is ready to start.
+    pub fn poll(&mut self, now: Microseconds32) -> Result<Option<Image>, PollError<N>> {
+        self.node
+            .receive(&mut self.get_info.handler().chain(self.updater.handler()))
+            .map_err(PollError::Receive)?;
+
+        let image = match self.updater.poll(&mut self.node, now) {
+            Ok(image) => image,
+            // The request will be sent again on the next call
+            Err(nb::Error::WouldBlock) => None,
+            Err(nb::Error::Other(e)) => return Err(PollError::Transmit(e)),
+        };
+
+        let due = match self.next_second {
+            Some(next_second) => now >= next_second,
+            None => true,
+        };
+        if due {
+            self.next_second = Some(match self.next_second {
+                // Keep a steady rate unless the tasks are more than one second late
+                Some(next_second) if now < next_second + milliseconds(1000) => {
+                    next_second + milliseconds(1000)
+                }
+                _ => now + milliseconds(1000),
+            });
+            let health = match self.updater.state {
+                UpdateState::Failed(_) => Health::CAUTION,
+                _ => Health::NOMINAL,
+            };
+            self.heartbeat.set_health(Health { value: health });
+            match self.heartbeat.publish_heartbeat(&mut self.node) {
+                Ok(()) | Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(canadensis::PublishError::Transport(e))) => {
+                    return Err(PollError::Transmit(e))
+                }
+                Err(nb::Error::Other(canadensis::PublishError::NotPublishing)) => {}
+            }
+        }
+
+        match self.node.flush() {
+            Ok(()) | Err(nb::Error::WouldBlock) => Ok(image),
+            Err(nb::Error::Other(e)) => Err(PollError::Transmit(e)),
+        }
+    }
+
+    /// Stops the bootloader and starts an image
+    ///
+    /// This drops the node and the flash before calling `boot`, so that they can release any
+    /// peripherals that they use.
+    pub fn boot<B: Boot>(self, image: &Image, boot: &mut B) -> ! {
+        drop(self);
+        boot.boot(image)
+    }
+
+    /// Returns the state of the latest update
+    pub fn state(&self) -> &UpdateState<F::Error> {
+        &self.updater.state
+    }
+
+    /// Returns the layout of the flash
+    pub fn layout(&self) -> &Layout {
+        &self.updater.layout
+    }
+
+    /// Returns a reference to the node
+    pub fn node(&self) -> &N {
+        &self.node
+    }
+    /// Returns a mutable reference to the node
+    pub fn node_mut(&mut self) -> &mut N {
+        &mut self.node
+    }
+
+    /// Returns a reference to the flash
+    pub fn flash(&self) -> &F {
+        &self.updater.flash
+    }
+    /// Returns a mutable reference to the flash
+    pub fn flash_mut(&mut self) -> &mut F {
+        &mut self.updater.flash
+    }
+}
+
+/// The state of the latest update
+#[derive(Debug)]
+pub enum UpdateState<E> {
+    /// No update has started
+    Idle,
+    /// An image is downloading
+    Downloading {
+        /// The number of bytes received so far
+        received: u32,
+    },
+    /// The latest update failed
+    ///
+    /// While in this state, the bootloader reports `Health::CAUTION`. It still accepts requests
+    /// to begin an update.
+    Failed(UpdateError<E>),
+    /// The latest update finished, and this image is ready to start
+    Complete(Image),
+}
+
+/// Reasons that an update can fail
+#[derive(Debug)]
+pub enum UpdateError<E> {
+    /// The flash returned an error
+    Flash(E),
+    /// The file server returned an error (one of the `uavcan.file.Error` values)
+    File(u16),
+    /// The file server did not respond
+    Timeout,
+    /// The image is larger than a bank
+    TooLarge,
+    /// The image does not have a valid CRC
+    InvalidCrc,
+}
+
+/// An error from [`Bootloader::new`]
+pub enum NewError<N: Node> {
+    /// Heartbeats could not be published
+    Publish(StartSendError<TxError<N>>),
+    /// The bootloader could not subscribe to GetInfo or ExecuteCommand requests
+    Subscribe(ServiceSubscribeError<RxError<N>>),
+    /// The bootloader could not start sending file read requests
+    Request(StartSendError<RxError<N>>),
+}
+
+impl<N> Debug for NewError<N>
+where
+    N: Node,
+    TxError<N>: Debug,
+    RxError<N>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            NewError::Publish(e) => f.debug_tuple("Publish").field(e).finish(),
+            NewError::Subscribe(e) => f.debug_tuple("Subscribe").field(e).finish(),
+            NewError::Request(e) => f.debug_tuple("Request").field(e).finish(),
+        }
+    }
+}
+
+/// An error from [`Bootloader::poll`]
+pub enum PollError<N: Node> {
+    /// The receiver returned an error
+    Receive(RxError<N>),
+    /// The transmitter returned an error
+    Transmit(TxError<N>),
+}
+
+impl<N> Debug for PollError<N>
+where
+    N: Node,
+    TxError<N>: Debug,
+    RxError<N>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PollError::Receive(e) => f.debug_tuple("Receive").field(e).finish(),
+            PollError::Transmit(e) => f.debug_tuple("Transmit").field(e).finish(),
+        }
+    }
+}
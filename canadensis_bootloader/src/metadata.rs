@@ -0,0 +1,150 @@
+use core::convert::TryInto;
+
+use crc_any::CRCu64;
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::image::{Bank, Region};
+
+/// The size of a record in flash
+pub(crate) const RECORD_SIZE: usize = 32;
+/// The first four bytes of every record
+const MAGIC: [u8; 4] = *b"CBLM";
+
+/// A record of an image that the bootloader has written to a bank
+///
+/// Format:
+///
+/// * Bytes 0..4: Magic
+/// * Bytes 4..8: Sequence number (little-endian)
+/// * Byte 8: Bank (0 or 1)
+/// * Bytes 9..12: Zero
+/// * Bytes 12..16: Image length (little-endian)
+/// * Bytes 16..24: Image CRC (little-endian)
+/// * Bytes 24..32: CRC-64-WE of bytes 0..24 (little-endian)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Record {
+    /// A number that is greater than the sequence numbers of all earlier records
+    pub sequence: u32,
+    pub bank: Bank,
+    pub length: u32,
+    pub crc: u64,
+}
+
+impl Record {
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0u8; RECORD_SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[8] = self.bank.index() as u8;
+        bytes[12..16].copy_from_slice(&self.length.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.crc.to_le_bytes());
+        let check = record_crc(&bytes[..24]);
+        bytes[24..32].copy_from_slice(&check.to_le_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8; RECORD_SIZE]) -> Option<Record> {
+        if bytes[0..4] != MAGIC
+            || bytes[24..32] != record_crc(&bytes[..24]).to_le_bytes()
+            || bytes[9..12] != [0; 3]
+        {
+            return None;
+        }
+        let bank = match bytes[8] {
+            0 => Bank::A,
+            1 => Bank::B,
+            _ => return None,
+        };
+        Some(Record {
+            sequence: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            bank,
+            length: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+            crc: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+        })
+    }
+}
+
+fn record_crc(bytes: &[u8]) -> u64 {
+    let mut crc = CRCu64::crc64we();
+    crc.digest(bytes);
+    crc.get_crc()
+}
+
+/// The latest record for each bank
+#[derive(Debug, Default)]
+pub(crate) struct Latest {
+    pub banks: [Option<Record>; 2],
+    /// The offset of the first erased record in the region
+    end: u32,
+}
+
+impl Latest {
+    /// Returns the latest records, newest first
+    pub fn newest_first(&self) -> impl Iterator<Item = &Record> {
+        let [a, b] = &self.banks;
+        let (first, second) = match (a, b) {
+            (Some(a), Some(b)) if b.sequence > a.sequence => (Some(b), Some(a)),
+            _ => (a.as_ref(), b.as_ref()),
+        };
+        first.into_iter().chain(second)
+    }
+
+    fn next_sequence(&self) -> u32 {
+        self.newest_first()
+            .next()
+            .map(|record| record.sequence.wrapping_add(1))
+            .unwrap_or(0)
+    }
+}
+
+/// Reads each record until the first erased one and returns the latest record for each bank
+///
+/// Records that were only partly written (for example, because power was lost) are skipped.
+pub(crate) fn load<F: NorFlash>(flash: &mut F, region: Region) -> Result<Latest, F::Error> {
+    let mut latest = Latest::default();
+    let mut bytes = [0u8; RECORD_SIZE];
+    let mut position = 0;
+    while position + RECORD_SIZE as u32 <= region.size {
+        flash.read(region.offset + position, &mut bytes)?;
+        if bytes.iter().all(|&byte| byte == 0xff) {
+            break;
+        }
+        if let Some(record) = Record::decode(&bytes) {
+            let index = record.bank.index();
+            latest.banks[index] = Some(record);
+        }
+        position += RECORD_SIZE as u32;
+    }
+    latest.end = position;
+    Ok(latest)
+}
+
+/// Writes a record for an image in a bank
+///
+/// When the region is full, this erases it and writes back the latest record for the other bank
+/// before the new record. If power is lost during that time, the record for the other bank is
+/// lost.
+pub(crate) fn store<F: NorFlash>(
+    flash: &mut F,
+    region: Region,
+    bank: Bank,
+    length: u32,
+    crc: u64,
+) -> Result<(), F::Error> {
+    let mut latest = load(flash, region)?;
+    let record = Record {
+        sequence: latest.next_sequence(),
+        bank,
+        length,
+        crc,
+    };
+    if latest.end + RECORD_SIZE as u32 > region.size {
+        flash.erase(region.offset, region.offset + region.size)?;
+        latest.end = 0;
+        if let Some(other) = &latest.banks[bank.other().index()] {
+            flash.write(region.offset, &other.encode())?;
+            latest.end = RECORD_SIZE as u32;
+        }
+    }
+    flash.write(region.offset + latest.end, &record.encode())
+}
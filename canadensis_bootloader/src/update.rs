@@ -0,0 +1,318 @@
+use alloc::vec::Vec;
+
+use canadensis::core::time::{milliseconds, MicrosecondDuration32, Microseconds32};
+use canadensis::core::transfer::ServiceTransfer;
+use canadensis::core::transport::{Transmitter, Transport};
+use canadensis::encoding::Deserialize;
+use canadensis::{nb, Node, ResponseToken, ServiceToken, TransferHandler};
+use canadensis_data_types::uavcan::file::error_1_0::Error as FileError;
+use canadensis_data_types::uavcan::file::path_2_0::Path;
+use canadensis_data_types::uavcan::file::read_1_1::{self, ReadRequest, ReadResponse};
+use canadensis_data_types::uavcan::node::execute_command_1_3::{
+    self, ExecuteCommandRequest, ExecuteCommandResponse,
+};
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::image::{check_image, Bank, Image, Layout};
+use crate::{metadata, UpdateError, UpdateState};
+
+/// The time to wait for a file read response before sending the request again
+const READ_TIMEOUT: MicrosecondDuration32 = milliseconds(1000);
+/// The number of times to send a file read request before giving up
+const READ_ATTEMPTS: u8 = 5;
+/// The maximum number of bytes in a file read response
+const CHUNK_MAX: usize = 256;
+
+/// Downloads images and writes them to flash
+pub(crate) struct Updater<N: Node, F: NorFlash> {
+    pub flash: F,
+    pub layout: Layout,
+    read_token: ServiceToken<ReadRequest>,
+    pub state: UpdateState<F::Error>,
+    download: Option<Download<N::Transport>>,
+}
+
+/// The progress of an image download
+struct Download<T: Transport> {
+    /// The node that has the file
+    server: T::NodeId,
+    path: Path,
+    /// The bank that the image is written to
+    bank: Bank,
+    /// The number of bytes received from the server
+    received: u32,
+    /// The number of bytes that have been written to the bank
+    written: u32,
+    /// The number of bytes at the beginning of the bank that have been erased
+    erased: u32,
+    /// Received bytes that have not been written because they are less than the flash write
+    /// size
+    pending: [u8; CHUNK_MAX * 2],
+    pending_length: usize,
+    /// The transfer ID and timeout of the request that has been sent, if any
+    request: Option<(T::TransferId, Microseconds32)>,
+    /// The number of times the current request has been sent
+    attempts: u8,
+    /// A response to the current request that has not been handled yet
+    response: Option<ReadResponse>,
+}
+
+impl<N, F> Updater<N, F>
+where
+    N: Node,
+    F: NorFlash,
+    <N::Transport as Transport>::TransferId: PartialEq,
+{
+    pub fn new(flash: F, layout: Layout, read_token: ServiceToken<ReadRequest>) -> Self {
+        Updater {
+            flash,
+            layout,
+            read_token,
+            state: UpdateState::Idle,
+            download: None,
+        }
+    }
+
+    /// Returns the newest image with a valid CRC
+    pub fn bootable_image(&mut self) -> Result<Option<Image>, F::Error> {
+        let latest = metadata::load(&mut self.flash, self.layout.metadata)?;
+        for record in latest.newest_first() {
+            let image = check_image(&mut self.flash, &self.layout, record.bank, record.length)?;
+            if let Some(image) = image.filter(|image| image.crc == record.crc) {
+                return Ok(Some(image));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Starts downloading an image into the bank that does not contain the bootable image
+    ///
+    /// If an image is already downloading, that download stops.
+    pub fn begin(&mut self, server: <N::Transport as Transport>::NodeId, path: Path) {
+        self.download = None;
+        let bank = match self.bootable_image() {
+            Ok(Some(image)) => image.bank.other(),
+            Ok(None) => Bank::A,
+            Err(e) => {
+                self.state = UpdateState::Failed(UpdateError::Flash(e));
+                return;
+            }
+        };
+        self.state = UpdateState::Downloading { received: 0 };
+        self.download = Some(Download {
+            server,
+            path,
+            bank,
+            received: 0,
+            written: 0,
+            erased: 0,
+            pending: [0xff; CHUNK_MAX * 2],
+            pending_length: 0,
+            request: None,
+            attempts: 0,
+            response: None,
+        });
+    }
+
+    /// Handles a response and sends a request if needed
+    ///
+    /// This returns an image when an update finishes.
+    pub fn poll(
+        &mut self,
+        node: &mut N,
+        now: Microseconds32,
+    ) -> nb::Result<Option<Image>, <N::Transmitter as Transmitter<N::Clock>>::Error> {
+        let download = match &mut self.download {
+            Some(download) => download,
+            None => return Ok(None),
+        };
+        if let Some(response) = download.response.take() {
+            match self.handle_response(response) {
+                Ok(Some(image)) => {
+                    self.download = None;
+                    self.state = UpdateState::Complete(image.clone());
+                    return Ok(Some(image));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.download = None;
+                    self.state = UpdateState::Failed(e);
+                    return Ok(None);
+                }
+            }
+        }
+        let download = self.download.as_mut().expect("No download");
+
+        let due = match &download.request {
+            Some((_, deadline)) => now > *deadline,
+            None => true,
+        };
+        if !due {
+            return Ok(None);
+        }
+        if download.attempts == READ_ATTEMPTS {
+            self.download = None;
+            self.state = UpdateState::Failed(UpdateError::Timeout);
+            return Ok(None);
+        }
+        let request = ReadRequest {
+            offset: u64::from(download.received),
+            path: Path {
+                path: download.path.path.clone(),
+            },
+        };
+        let transfer_id = node.send_request(&self.read_token, &request, download.server.clone())?;
+        download.request = Some((transfer_id, now + READ_TIMEOUT));
+        download.attempts += 1;
+        Ok(None)
+    }
+
+    /// Writes the data from a response to flash, and checks the image if this is the end of
+    /// the file
+    fn handle_response(
+        &mut self,
+        response: ReadResponse,
+    ) -> Result<Option<Image>, UpdateError<F::Error>> {
+        if response.error.value != FileError::OK {
+            return Err(UpdateError::File(response.error.value));
+        }
+        let download = self.download.as_mut().expect("No download");
+        let bank = self.layout.bank(download.bank);
+        let data = &response.data.value;
+        let received = download.received + data.len() as u32;
+        if received > bank.size {
+            return Err(UpdateError::TooLarge);
+        }
+        download.pending[download.pending_length..][..data.len()].copy_from_slice(data);
+        download.pending_length += data.len();
+        download.received = received;
+        download.request = None;
+        download.attempts = 0;
+        self.state = UpdateState::Downloading { received };
+
+        let end_of_file = data.len() < CHUNK_MAX;
+        if end_of_file {
+            // Fill the rest of the last write with the erased value
+            let padded = download.pending_length.next_multiple_of(F::WRITE_SIZE);
+            download.pending[download.pending_length..padded].fill(0xff);
+            download.pending_length = padded;
+        }
+        let write_length = download.pending_length - download.pending_length % F::WRITE_SIZE;
+        if write_length != 0 {
+            let write_offset = download.written;
+            let write_end = write_offset + write_length as u32;
+            while download.erased < write_end {
+                let erase_end = download.erased + F::ERASE_SIZE as u32;
+                self.flash
+                    .erase(bank.offset + download.erased, bank.offset + erase_end)
+                    .map_err(UpdateError::Flash)?;
+                download.erased = erase_end;
+            }
+            self.flash
+                .write(
+                    bank.offset + write_offset,
+                    &download.pending[..write_length],
+                )
+                .map_err(UpdateError::Flash)?;
+            download
+                .pending
+                .copy_within(write_length..download.pending_length, 0);
+            download.pending_length -= write_length;
+            download.written = write_end;
+        }
+
+        if !end_of_file {
+            return Ok(None);
+        }
+        let length = download.received;
+        let bank = download.bank;
+        let image = check_image(&mut self.flash, &self.layout, bank, length)
+            .map_err(UpdateError::Flash)?
+            .ok_or(UpdateError::InvalidCrc)?;
+        metadata::store(
+            &mut self.flash,
+            self.layout.metadata,
+            bank,
+            length,
+            image.crc,
+        )
+        .map_err(UpdateError::Flash)?;
+        Ok(Some(image))
+    }
+
+    pub fn handler(&mut self) -> UpdateHandler<'_, N, F> {
+        UpdateHandler { updater: self }
+    }
+}
+
+/// Handles ExecuteCommand requests and file read responses
+pub(crate) struct UpdateHandler<'a, N: Node, F: NorFlash> {
+    updater: &'a mut Updater<N, F>,
+}
+
+impl<N, F> TransferHandler<N::Transport> for UpdateHandler<'_, N, F>
+where
+    N: Node,
+    F: NorFlash,
+    <N::Transport as Transport>::TransferId: PartialEq,
+{
+    fn handle_request<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        node: &mut N2,
+        token: ResponseToken<N2::Transport>,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        if transfer.header.service != execute_command_1_3::SERVICE {
+            return false;
+        }
+        let request = match ExecuteCommandRequest::deserialize_from_bytes(&transfer.payload) {
+            Ok(request) => request,
+            Err(_) => return true,
+        };
+        let status = if request.command != ExecuteCommandRequest::COMMAND_BEGIN_SOFTWARE_UPDATE {
+            ExecuteCommandResponse::STATUS_BAD_COMMAND
+        } else if request.parameter.is_empty() {
+            ExecuteCommandResponse::STATUS_BAD_PARAMETER
+        } else {
+            let path = Path {
+                path: request.parameter.clone(),
+            };
+            self.updater.begin(transfer.header.source.clone(), path);
+            match self.updater.state {
+                UpdateState::Downloading { .. } => ExecuteCommandResponse::STATUS_SUCCESS,
+                _ => ExecuteCommandResponse::STATUS_FAILURE,
+            }
+        };
+        let response = ExecuteCommandResponse {
+            status,
+            output: heapless::Vec::new(),
+        };
+        let _ = node.send_response(token, milliseconds(1000), &response);
+        true
+    }
+
+    fn handle_response<N2: Node<Transport = N::Transport>>(
+        &mut self,
+        _node: &mut N2,
+        transfer: &ServiceTransfer<Vec<u8>, N2::Transport>,
+    ) -> bool {
+        if transfer.header.service != read_1_1::SERVICE {
+            return false;
+        }
+        if let Some(download) = &mut self.updater.download {
+            let expected = match &download.request {
+                Some((transfer_id, _)) => {
+                    transfer.header.source == download.server
+                        && transfer.header.transfer_id == *transfer_id
+                }
+                None => false,
+            };
+            if expected {
+                if let Ok(response) = ReadResponse::deserialize_from_bytes(&transfer.payload) {
+                    download.response = Some(response);
+                }
+            }
+        }
+        true
+    }
+}
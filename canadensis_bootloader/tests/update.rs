@@ -0,0 +1,528 @@
+//!
+//! Tests of image updates on a simulated CAN bus
+//!
+
+extern crate canadensis;
+extern crate canadensis_bootloader;
+extern crate canadensis_can;
+extern crate canadensis_crc;
+extern crate canadensis_data_types;
+extern crate canadensis_sim;
+extern crate crc_any;
+extern crate embedded_storage;
+extern crate heapless;
+
+use canadensis::core::time::{milliseconds, Microseconds32};
+use canadensis::core::transfer::ServiceTransfer;
+use canadensis::core::Priority;
+use canadensis::encoding::{DataType, Deserialize};
+use canadensis::service::server::ServiceServer;
+use canadensis::{Node, ServiceToken, TransferHandler};
+use canadensis_bootloader::{
+    Bank, Boot, Bootloader, Image, Layout, Region, UpdateError, UpdateState,
+};
+use canadensis_can::{CanNodeId, CanTransport};
+use canadensis_crc::AppDescriptor;
+use canadensis_data_types::uavcan::file::error_1_0::Error as FileError;
+use canadensis_data_types::uavcan::file::read_1_1::{self, ReadRequest, ReadResponse};
+use canadensis_data_types::uavcan::node::execute_command_1_3::{
+    self, ExecuteCommandRequest, ExecuteCommandResponse,
+};
+use canadensis_data_types::uavcan::node::get_info_1_0::GetInfoResponse;
+use canadensis_data_types::uavcan::node::version_1_0::Version;
+use canadensis_data_types::uavcan::primitive::unstructured_1_0::Unstructured;
+use canadensis_sim::can::{CanBus, CanBusConfig, SimCanNode};
+use canadensis_sim::Simulation;
+use crc_any::CRCu64;
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+const BOOTLOADER_ID: u8 = 42;
+const SERVER_ID: u8 = 10;
+const BANK_SIZE: u32 = 8192;
+const ERASE_SIZE: usize = 1024;
+/// The offset of the app descriptor in each image
+const DESCRIPTOR_OFFSET: usize = 16;
+
+/// NOR flash in memory that can be shared with the test
+#[derive(Clone)]
+struct RamFlash(Rc<RefCell<Vec<u8>>>);
+
+impl RamFlash {
+    fn new(size: usize) -> Self {
+        RamFlash(Rc::new(RefCell::new(vec![0xff; size])))
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let memory = self.0.borrow();
+        let source = memory
+            .get(offset as usize..offset as usize + bytes.len())
+            .ok_or(NorFlashErrorKind::OutOfBounds)?;
+        bytes.copy_from_slice(source);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.borrow().len()
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 8;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        assert_eq!(from as usize % ERASE_SIZE, 0);
+        assert_eq!(to as usize % ERASE_SIZE, 0);
+        let mut memory = self.0.borrow_mut();
+        memory
+            .get_mut(from as usize..to as usize)
+            .ok_or(NorFlashErrorKind::OutOfBounds)?
+            .fill(0xff);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        assert_eq!(offset as usize % Self::WRITE_SIZE, 0);
+        assert_eq!(bytes.len() % Self::WRITE_SIZE, 0);
+        let mut memory = self.0.borrow_mut();
+        let target = memory
+            .get_mut(offset as usize..offset as usize + bytes.len())
+            .ok_or(NorFlashErrorKind::OutOfBounds)?;
+        for (target, byte) in target.iter_mut().zip(bytes) {
+            // Writing can only clear bits
+            assert_eq!(*target & byte, *byte, "Write to flash that was not erased");
+            *target = *byte;
+        }
+        Ok(())
+    }
+}
+
+fn layout() -> Layout {
+    Layout {
+        banks: [
+            Region {
+                offset: 0,
+                size: BANK_SIZE,
+            },
+            Region {
+                offset: BANK_SIZE,
+                size: BANK_SIZE,
+            },
+        ],
+        metadata: Region {
+            offset: BANK_SIZE * 2,
+            size: ERASE_SIZE as u32,
+        },
+    }
+}
+
+/// Creates an image with an app descriptor and a valid CRC, filled in the same way as
+/// canadensis_write_crc
+fn image(length: usize, seed: u8) -> Vec<u8> {
+    let mut image: Vec<u8> = (0..length)
+        .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+        .collect();
+    let descriptor = &mut image[DESCRIPTOR_OFFSET..][..40];
+    descriptor[..8].copy_from_slice(&AppDescriptor::SIGNATURE);
+    descriptor[8..16].fill(0);
+    descriptor[16..20].copy_from_slice(&(length as u32).to_le_bytes());
+    descriptor[20] = 1;
+    let mut padded = image.clone();
+    padded.resize(length.next_multiple_of(8), 0);
+    let mut crc = CRCu64::crc64we();
+    crc.digest(&padded);
+    image[DESCRIPTOR_OFFSET + 8..][..8].copy_from_slice(&crc.get_crc().to_le_bytes());
+    image
+}
+
+fn new_bootloader(bus: &CanBus, flash: RamFlash) -> Bootloader<SimCanNode, RamFlash> {
+    let node_info = GetInfoResponse {
+        protocol_version: Version { major: 1, minor: 0 },
+        hardware_version: Version { major: 0, minor: 0 },
+        software_version: Version { major: 0, minor: 0 },
+        software_vcs_revision_id: 0,
+        unique_id: [0; 16],
+        name: heapless::Vec::from_slice(b"org.example.bootloader").unwrap(),
+        software_image_crc: heapless::Vec::new(),
+        certificate_of_authenticity: heapless::Vec::new(),
+    };
+    // NewError is only Debug if the node is
+    Bootloader::new(
+        bus.node(CanNodeId::try_from(BOOTLOADER_ID).unwrap()),
+        flash,
+        layout(),
+        node_info,
+    )
+    .unwrap_or_else(|_| panic!("Failed to start bootloader"))
+}
+
+/// A node that serves one file and sends ExecuteCommand requests
+struct FileServer {
+    node: SimCanNode,
+    server: ServiceServer<SimCanNode, ReadRequest, ReadResponse>,
+    command: ServiceToken<ExecuteCommandRequest>,
+    /// The file that the server reads from
+    file: Rc<RefCell<Option<Vec<u8>>>>,
+    /// The status from the latest ExecuteCommand response
+    status: Option<u8>,
+}
+
+impl FileServer {
+    /// Creates a server for a file, which does not exist if `file` is None
+    fn new(bus: &CanBus, file: Option<Vec<u8>>) -> Self {
+        let mut node = bus.node(CanNodeId::try_from(SERVER_ID).unwrap());
+        let file = Rc::new(RefCell::new(file));
+        let server_file = file.clone();
+        let server = ServiceServer::new(
+            &mut node,
+            read_1_1::SERVICE,
+            ReadRequest::EXTENT_BYTES.unwrap() as usize,
+            move |request: ReadRequest, call| {
+                assert_eq!(&request.path.path[..], b"image.bin");
+                let response = match &*server_file.borrow() {
+                    Some(file) => {
+                        let start = (request.offset as usize).min(file.len());
+                        let end = (start + 256).min(file.len());
+                        ReadResponse {
+                            error: FileError {
+                                value: FileError::OK,
+                            },
+                            data: Unstructured {
+                                value: heapless::Vec::from_slice(&file[start..end]).unwrap(),
+                            },
+                        }
+                    }
+                    None => ReadResponse {
+                        error: FileError {
+                            value: FileError::NOT_FOUND,
+                        },
+                        data: Unstructured {
+                            value: heapless::Vec::new(),
+                        },
+                    },
+                };
+                call.reply(response)
+            },
+        )
+        .unwrap_or_else(|_| panic!("Failed to start file server"));
+        let command = node
+            .start_sending_requests(
+                execute_command_1_3::SERVICE,
+                milliseconds(1000),
+                ExecuteCommandResponse::EXTENT_BYTES.unwrap() as usize,
+                Priority::Nominal,
+            )
+            .unwrap_or_else(|_| panic!("Failed to start sending requests"));
+        FileServer {
+            node,
+            server,
+            command,
+            file,
+            status: None,
+        }
+    }
+
+    /// Replaces the file, or removes it if `file` is None
+    fn set_file(&mut self, file: Option<Vec<u8>>) {
+        *self.file.borrow_mut() = file;
+    }
+
+    fn begin_update(&mut self) {
+        let request = ExecuteCommandRequest {
+            command: ExecuteCommandRequest::COMMAND_BEGIN_SOFTWARE_UPDATE,
+            parameter: heapless::Vec::from_slice(b"image.bin").unwrap(),
+        };
+        self.node
+            .send_request(
+                &self.command,
+                &request,
+                CanNodeId::try_from(BOOTLOADER_ID).unwrap(),
+            )
+            .unwrap();
+    }
+
+    fn poll(&mut self) {
+        let mut status = None;
+        self.node
+            .receive(
+                &mut self
+                    .server
+                    .handler()
+                    .chain(CommandResponseHandler(&mut status)),
+            )
+            .unwrap();
+        if status.is_some() {
+            self.status = status;
+        }
+        self.node.flush().unwrap();
+    }
+}
+
+/// Records the status from ExecuteCommand responses
+struct CommandResponseHandler<'a>(&'a mut Option<u8>);
+
+impl TransferHandler<CanTransport> for CommandResponseHandler<'_> {
+    fn handle_response<N: Node<Transport = CanTransport>>(
+        &mut self,
+        _node: &mut N,
+        transfer: &ServiceTransfer<Vec<u8>, CanTransport>,
+    ) -> bool {
+        if transfer.header.service != execute_command_1_3::SERVICE {
+            return false;
+        }
+        let response = ExecuteCommandResponse::deserialize_from_bytes(&transfer.payload).unwrap();
+        *self.0 = Some(response.status);
+        true
+    }
+}
+
+/// Polls the bootloader and server until the bootloader returns an image or stops downloading
+fn run_update(
+    simulation: &mut Simulation,
+    bootloader: &mut Bootloader<SimCanNode, RamFlash>,
+    server: &mut FileServer,
+) -> Option<Image> {
+    let deadline: Microseconds32 = simulation.now() + milliseconds(30_000);
+    while simulation.now() < deadline {
+        server.poll();
+        let image = bootloader.poll(simulation.now()).unwrap();
+        if image.is_some() {
+            return image;
+        }
+        if let UpdateState::Failed(_) = bootloader.state() {
+            return None;
+        }
+        simulation.step(milliseconds(1));
+    }
+    panic!("Update did not finish");
+}
+
+fn new_network() -> (Simulation, CanBus) {
+    let mut simulation = Simulation::new(45);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    (simulation, bus)
+}
+
+fn bank_contents(flash: &RamFlash, image: &Image) -> Vec<u8> {
+    flash.0.borrow()[image.offset as usize..][..image.length as usize].to_vec()
+}
+
+#[test]
+fn update_writes_image_to_empty_flash() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash.clone());
+    assert_eq!(bootloader.bootable_image().unwrap(), None);
+
+    let file = image(1001, 7);
+    let mut server = FileServer::new(&bus, Some(file.clone()));
+    server.begin_update();
+    let image = run_update(&mut simulation, &mut bootloader, &mut server).unwrap();
+    assert_eq!(image.bank, Bank::A);
+    assert_eq!(image.offset, 0);
+    assert_eq!(image.length, 1001);
+    assert_eq!(bank_contents(&flash, &image), file);
+    assert_eq!(server.status, Some(ExecuteCommandResponse::STATUS_SUCCESS));
+    assert!(matches!(bootloader.state(), UpdateState::Complete(complete) if *complete == image));
+    assert_eq!(bootloader.bootable_image().unwrap(), Some(image.clone()));
+
+    // After a restart, the bootloader still finds the image
+    let mut bootloader = new_bootloader(&bus, flash);
+    assert_eq!(bootloader.bootable_image().unwrap(), Some(image));
+}
+
+#[test]
+fn updates_alternate_banks_and_bad_image_keeps_previous() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash.clone());
+
+    let mut server = FileServer::new(&bus, Some(image(3000, 1)));
+    server.begin_update();
+    let first = run_update(&mut simulation, &mut bootloader, &mut server).unwrap();
+    assert_eq!(first.bank, Bank::A);
+
+    // A file that is a multiple of the response size ends with an empty response
+    let second_file = image(512, 2);
+    server.set_file(Some(second_file.clone()));
+    server.begin_update();
+    let second = run_update(&mut simulation, &mut bootloader, &mut server).unwrap();
+    assert_eq!(second.bank, Bank::B);
+    assert_eq!(second.offset, BANK_SIZE);
+    assert_eq!(bank_contents(&flash, &second), second_file);
+    assert_eq!(bootloader.bootable_image().unwrap(), Some(second.clone()));
+
+    // An image with a bad CRC goes into bank A, but bank B is still bootable
+    let mut bad_file = image(2000, 3);
+    bad_file[1500] ^= 0x01;
+    server.set_file(Some(bad_file));
+    server.begin_update();
+    assert_eq!(
+        run_update(&mut simulation, &mut bootloader, &mut server),
+        None
+    );
+    assert!(matches!(
+        bootloader.state(),
+        UpdateState::Failed(UpdateError::InvalidCrc)
+    ));
+    assert_eq!(bootloader.bootable_image().unwrap(), Some(second));
+}
+
+#[test]
+fn damaged_newest_image_falls_back_to_other_bank() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash.clone());
+
+    let mut server = FileServer::new(&bus, Some(image(700, 4)));
+    server.begin_update();
+    let first = run_update(&mut simulation, &mut bootloader, &mut server).unwrap();
+    server.set_file(Some(image(900, 5)));
+    server.begin_update();
+    let second = run_update(&mut simulation, &mut bootloader, &mut server).unwrap();
+    assert_eq!(second.bank, Bank::B);
+
+    // Clear some bits in the newest image
+    flash.0.borrow_mut()[second.offset as usize + 100] &= 0x0f;
+    assert_eq!(bootloader.bootable_image().unwrap(), Some(first));
+}
+
+#[test]
+fn update_requested_by_application_with_missing_file() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash);
+    let mut server = FileServer::new(&bus, None);
+
+    bootloader.begin_update(CanNodeId::try_from(SERVER_ID).unwrap(), b"image.bin");
+    assert_eq!(
+        run_update(&mut simulation, &mut bootloader, &mut server),
+        None
+    );
+    assert!(matches!(
+        bootloader.state(),
+        UpdateState::Failed(UpdateError::File(FileError::NOT_FOUND))
+    ));
+}
+
+#[test]
+fn image_without_matching_descriptor_fails() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash);
+
+    // No descriptor
+    let mut no_descriptor = image(600, 9);
+    no_descriptor[DESCRIPTOR_OFFSET] ^= 0x01;
+    // Two descriptors
+    let mut two_descriptors = image(600, 10);
+    two_descriptors[64..72].copy_from_slice(&AppDescriptor::SIGNATURE);
+    // An image with a valid CRC, but the descriptor is for a longer image
+    let mut truncated = image(600, 11);
+    truncated.truncate(592);
+    for file in [no_descriptor, two_descriptors, truncated] {
+        let mut server = FileServer::new(&bus, Some(file));
+        server.begin_update();
+        assert_eq!(
+            run_update(&mut simulation, &mut bootloader, &mut server),
+            None
+        );
+        assert!(matches!(
+            bootloader.state(),
+            UpdateState::Failed(UpdateError::InvalidCrc)
+        ));
+    }
+    assert_eq!(bootloader.bootable_image().unwrap(), None);
+}
+
+/// A boot hook that panics with the bank of the image instead of starting it
+struct PanicBoot;
+
+impl Boot for PanicBoot {
+    fn boot(&mut self, image: &Image) -> ! {
+        panic!("Booting bank {:?}", image.bank);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Booting bank A")]
+fn boot_starts_bootable_image() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash);
+    let mut server = FileServer::new(&bus, Some(image(800, 12)));
+    server.begin_update();
+    let image = run_update(&mut simulation, &mut bootloader, &mut server).unwrap();
+    bootloader.boot(&image, &mut PanicBoot);
+}
+
+#[test]
+fn too_large_image_fails() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash);
+    let mut server = FileServer::new(&bus, Some(image(BANK_SIZE as usize + 1, 6)));
+
+    server.begin_update();
+    assert_eq!(
+        run_update(&mut simulation, &mut bootloader, &mut server),
+        None
+    );
+    assert!(matches!(
+        bootloader.state(),
+        UpdateState::Failed(UpdateError::TooLarge)
+    ));
+}
+
+#[test]
+fn metadata_region_is_compacted() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash);
+    let server_id = CanNodeId::try_from(SERVER_ID).unwrap();
+
+    // The metadata region has space for 32 records
+    let mut server = FileServer::new(&bus, None);
+    let mut latest = None;
+    for seed in 0..40 {
+        server.set_file(Some(image(300, seed)));
+        bootloader.begin_update(server_id, b"image.bin");
+        let image = run_update(&mut simulation, &mut bootloader, &mut server).unwrap();
+        if let Some(previous) = &latest {
+            let previous: &Image = previous;
+            assert_eq!(image.bank, previous.bank.other());
+        }
+        assert_eq!(bootloader.bootable_image().unwrap(), Some(image.clone()));
+        latest = Some(image);
+    }
+}
+
+#[test]
+fn unresponsive_server_times_out() {
+    let (mut simulation, bus) = new_network();
+    let flash = RamFlash::new(BANK_SIZE as usize * 2 + ERASE_SIZE);
+    let mut bootloader = new_bootloader(&bus, flash);
+    let mut server = FileServer::new(&bus, Some(image(300, 8)));
+
+    // No node has this ID
+    bootloader.begin_update(CanNodeId::try_from(99u8).unwrap(), b"image.bin");
+    assert_eq!(
+        run_update(&mut simulation, &mut bootloader, &mut server),
+        None
+    );
+    assert!(matches!(
+        bootloader.state(),
+        UpdateState::Failed(UpdateError::Timeout)
+    ));
+}
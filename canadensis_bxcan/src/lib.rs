@@ -24,13 +24,14 @@ pub mod pnp;
 
 pub use bxcan::OverrunError;
 
-use bxcan::filter::Mask32;
+use bxcan::filter::{ListEntry32, Mask32};
 use bxcan::{Can, ExtendedId, Fifo, FilterOwner, Instance, Mailbox};
 use canadensis::core::subscription::Subscription;
 use canadensis::core::time::{Clock, Microseconds32};
-use canadensis::core::OutOfMemoryError;
-use canadensis_can::driver::{optimize_filters, ReceiveDriver, TransmitDriver};
+use canadensis::core::{OutOfMemoryError, SubjectId};
+use canadensis_can::driver::{optimize_filter_banks, ReceiveDriver, TransmitDriver};
 use canadensis_can::{CanNodeId, Frame};
+use canadensis_filter_config::{BankConfig, HardwareFilters};
 use core::convert::{Infallible, TryFrom};
 use heapless::Deque;
 
@@ -46,6 +47,8 @@ where
     deadlines: DeadlineTracker,
     /// Copies of transmitted loopback frames that have not yet been received
     loopback_frames: Deque<Frame, LOOPBACK_CAPACITY>,
+    /// Returns true for subjects that should use FIFO 1
+    is_high_priority: fn(SubjectId) -> bool,
 }
 
 impl<N> BxCanDriver<N>
@@ -58,9 +61,21 @@ where
             can,
             deadlines: DeadlineTracker::new(),
             loopback_frames: Deque::new(),
+            is_high_priority: |_| false,
         }
     }
 
+    /// Sets a function that selects high-priority subjects
+    ///
+    /// Frames on high-priority subjects go to receive FIFO 1, and frames on other subjects and
+    /// services go to FIFO 0. This driver receives frames from FIFO 1 first, so a high-priority
+    /// frame does not wait behind other frames and is not lost if FIFO 0 overflows.
+    ///
+    /// The new setting takes effect the next time the filters are applied.
+    pub fn set_high_priority_subjects(&mut self, is_high_priority: fn(SubjectId) -> bool) {
+        self.is_high_priority = is_high_priority;
+    }
+
     /// Consumes this driver and returns its CAN object
     pub fn into_can(self) -> Can<N> {
         self.can
@@ -116,13 +131,17 @@ impl<N> BxCanDriver<N>
 where
     N: Instance + FilterOwner,
 {
-    /// Tries to receive a frame from the CAN bus
+    /// Tries to receive a frame from the CAN bus, checking the high-priority FIFO 1 before FIFO 0
     fn receive_from_bus<C: Clock>(
         &mut self,
         clock: &mut C,
     ) -> nb::Result<Frame, <Self as ReceiveDriver<C>>::Error> {
         loop {
-            match self.can.receive() {
+            let received = match self.can.rx1().receive() {
+                Err(nb::Error::WouldBlock) => self.can.rx0().receive(),
+                result => result,
+            };
+            match received {
                 Ok(frame) => {
                     if let Ok(frame) = bxcan_frame_to_cyphal(&frame, clock.now()) {
                         break Ok(frame);
@@ -218,18 +237,55 @@ where
     where
         S: IntoIterator<Item = Subscription>,
     {
+        let is_high_priority = self.is_high_priority;
         let mut filters = self.can.modify_filters();
-        let status = optimize_filters(
+        // The bxcan crate only supports standard IDs in 16-bit filters, so the 16-bit scale
+        // is not used.
+        let hardware = HardwareFilters {
+            banks: filters.num_banks().into(),
+            list_mode: true,
+            dual_16_bit: false,
+            high_priority_fifo: true,
+        };
+        let status = optimize_filter_banks(
             local_node,
             subscriptions,
-            filters.num_banks().into(),
-            |optimized| {
+            &hardware,
+            is_high_priority,
+            |banks| {
                 // Apply filters
                 filters.clear();
-                for (i, filter) in optimized.iter().enumerate() {
-                    let id = ExtendedId::new(filter.id()).unwrap();
-                    let mask = ExtendedId::new(filter.mask()).unwrap();
-                    filters.enable_bank(i as u8, Fifo::Fifo0, Mask32::frames_with_ext_id(id, mask));
+                for (i, bank) in banks.enumerate() {
+                    let fifo = if bank.high_priority {
+                        Fifo::Fifo1
+                    } else {
+                        Fifo::Fifo0
+                    };
+                    match bank.config {
+                        BankConfig::Mask(filter) => {
+                            let id = ExtendedId::new(filter.id()).unwrap();
+                            let mask = ExtendedId::new(filter.mask()).unwrap();
+                            filters.enable_bank(
+                                i as u8,
+                                fifo,
+                                Mask32::frames_with_ext_id(id, mask),
+                            );
+                        }
+                        BankConfig::List(id1, id2) => {
+                            // With only one ID, both entries are the same
+                            let id1 = ExtendedId::new(id1).unwrap();
+                            let id2 = id2.map(|id2| ExtendedId::new(id2).unwrap()).unwrap_or(id1);
+                            filters.enable_bank(
+                                i as u8,
+                                fifo,
+                                [
+                                    ListEntry32::data_frames_with_id(id1),
+                                    ListEntry32::data_frames_with_id(id2),
+                                ],
+                            );
+                        }
+                        BankConfig::DualMask16(..) => unreachable!("16-bit filters not enabled"),
+                    }
                 }
             },
         );
@@ -6,27 +6,26 @@ use crate::BxCanDriver;
 use bxcan::{Can, FilterOwner, Instance, OverrunError};
 use canadensis::core::time::Clock;
 use canadensis_can::queue::{SingleFrameQueue, SingleQueueDriver};
-use canadensis_can::{CanNodeId, CanReceiver, CanTransmitter, CanTransport, Error, Mtu};
-use canadensis_pnp_client::{AllocationMessage, PnpClient};
+use canadensis_can::{CanNodeId, CanReceiver, CanTransmitter, Error, Mtu};
+use canadensis_pnp_client::{PnpClient, PollError};
+use core::convert::Infallible;
 
 /// A plug-and-play node ID assignment client that uses a bxCAN peripheral
-pub struct BxCanPnpClient<C: Clock, M, I: Instance + FilterOwner> {
+pub struct BxCanPnpClient<C: Clock, I: Instance + FilterOwner> {
     /// A clock used to get the current time
     clock: C,
     /// The node ID allocation client
     pub client: PnpClient<
         C,
-        M,
         CanTransmitter<C, SingleQueueDriver<C, SingleFrameQueue, BxCanDriver<I>>>,
         CanReceiver<C, SingleQueueDriver<C, SingleFrameQueue, BxCanDriver<I>>>,
     >,
     driver: SingleQueueDriver<C, SingleFrameQueue, BxCanDriver<I>>,
 }
 
-impl<C, M, I> BxCanPnpClient<C, M, I>
+impl<C, I> BxCanPnpClient<C, I>
 where
     C: Clock,
-    M: AllocationMessage<CanTransport>,
     I: Instance + FilterOwner,
 {
     /// Creates a node ID allocation client
@@ -43,19 +42,13 @@ where
         })
     }
 
-    /// Publishes an ID allocation request, sending it onto the CAN bus
+    /// Handles incoming CAN frames and publishes an ID allocation request if one is due
     ///
-    /// This function returns an error if no transmit mailbox was available for the frame.
-    pub fn publish_request(&mut self) {
-        self.client.send_request(&mut self.clock, &mut self.driver)
-    }
-
-    /// Handles and parses incoming CAN frames, and returns a node ID if one was received
-    pub fn handle_incoming_frames(&mut self, clock: &mut C) -> Option<CanNodeId> {
-        match self.client.receive(clock, &mut self.driver) {
-            Ok(Some(id)) => Some(id),
-            Ok(None) | Err(_) => None,
-        }
+    /// This function returns a node ID if one was received.
+    pub fn poll(
+        &mut self,
+    ) -> Result<Option<CanNodeId>, PollError<Error<Infallible>, Error<OverrunError>>> {
+        self.client.poll(&mut self.clock, &mut self.driver)
     }
 
     /// Breaks up this client into its clock and CAN driver
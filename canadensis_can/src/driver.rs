@@ -3,10 +3,11 @@
 use crate::data::Frame;
 use crate::types::CanNodeId;
 use alloc::vec::Vec;
+use canadensis_core::statistics::TransportStatistics;
 use canadensis_core::subscription::Subscription;
 use canadensis_core::time::Clock;
 use canadensis_core::{nb, OutOfMemoryError, ServiceId, SubjectId};
-use canadensis_filter_config::{optimize, Filter};
+use canadensis_filter_config::{optimize, optimize_banks, Banks, Filter, HardwareFilters};
 use core::fmt::Debug;
 use fallible_collections::FallibleVec;
 
@@ -38,6 +39,23 @@ where
     /// Attempts to flush all frames out of any in-memory queues that may exist and transmit
     /// them
     fn flush(&mut self, clock: &mut C) -> nb::Result<(), Self::Error>;
+
+    /// Adds the numbers of frames that this driver has sent (`emitted`) and failed to send
+    /// (`errored`) on each network interface to `statistics`, starting with interface
+    /// `first_interface`
+    ///
+    /// This function returns the number of interfaces that this driver has counted frames on.
+    ///
+    /// The default implementation does nothing and returns 0, which means that this driver does
+    /// not count frames.
+    fn add_interface_statistics(
+        &self,
+        first_interface: usize,
+        statistics: &mut TransportStatistics,
+    ) -> usize {
+        let _ = (first_interface, statistics);
+        0
+    }
 }
 
 /// A CAN driver that can receive frames
@@ -72,6 +90,23 @@ where
         S: IntoIterator<Item = Subscription>;
     /// Sets up frame reception filters to accept all incoming frames
     fn apply_accept_all(&mut self);
+
+    /// Adds the numbers of frames that this driver has received (`received`) and failed to
+    /// receive (`errored`) on each network interface to `statistics`, starting with interface
+    /// `first_interface`
+    ///
+    /// This function returns the number of interfaces that this driver has counted frames on.
+    ///
+    /// The default implementation does nothing and returns 0, which means that this driver does
+    /// not count frames.
+    fn add_interface_statistics(
+        &self,
+        first_interface: usize,
+        statistics: &mut TransportStatistics,
+    ) -> usize {
+        let _ = (first_interface, statistics);
+        0
+    }
 }
 
 /// Creates a set of filters from the provided subscription, optimizes them to reduce the number
@@ -97,6 +132,38 @@ where
     Ok(())
 }
 
+/// Creates a set of filters from the provided subscriptions, arranges them in the filter banks of
+/// the hardware, and passes the resulting banks to a callback
+///
+/// Filters for subjects where `is_high_priority` returns true are marked as high priority, so
+/// their banks send frames to the high-priority FIFO if the hardware has one.
+pub fn optimize_filter_banks<F, S, P>(
+    local_node: Option<CanNodeId>,
+    subscriptions: S,
+    hardware: &HardwareFilters,
+    mut is_high_priority: P,
+    f: F,
+) -> Result<(), OutOfMemoryError>
+where
+    F: FnOnce(Banks<'_>),
+    S: IntoIterator<Item = Subscription>,
+    P: FnMut(SubjectId) -> bool,
+{
+    let mut filters: Vec<Filter> = Vec::new();
+    for subscription in subscriptions {
+        let high_priority = match subscription {
+            Subscription::Message(subject) => is_high_priority(subject),
+            Subscription::Request(_) | Subscription::Response(_) => false,
+        };
+        if let Some(mut filter) = make_filter(subscription, local_node) {
+            filter.set_high_priority(high_priority);
+            filters.try_push(filter)?;
+        }
+    }
+    f(optimize_banks(&mut filters, hardware));
+    Ok(())
+}
+
 /// Creates and returns a filter that matches the provided subscription, or None if the subscription
 /// is a request or response subscription and local_node is None.
 fn make_filter(subscription: Subscription, local_node: Option<CanNodeId>) -> Option<Filter> {
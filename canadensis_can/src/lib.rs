@@ -16,6 +16,7 @@ extern crate heapless;
 
 pub use crate::data::*;
 pub use crate::rx::CanReceiver;
+pub use crate::statistics::*;
 pub use crate::tx::CanTransmitter;
 pub use crate::types::*;
 
@@ -24,6 +25,7 @@ pub mod driver;
 pub mod queue;
 pub mod redundant;
 mod rx;
+mod statistics;
 mod tx;
 mod types;
 
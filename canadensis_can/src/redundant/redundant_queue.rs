@@ -1,5 +1,6 @@
 use crate::driver::TransmitDriver;
 use crate::Frame;
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::time::Clock;
 use canadensis_core::{nb, OutOfMemoryError};
 
@@ -10,6 +11,9 @@ use canadensis_core::{nb, OutOfMemoryError};
 ///
 /// Double-redundant drivers can be nested for use with triple-redundant transports.
 ///
+/// If the underlying drivers do not count frames, this driver counts the frames sent and failed
+/// on each of them. Driver 0 comes before driver 1 in the interface statistics.
+///
 pub struct RedundantDriver<D0, D1> {
     /// Driver 0
     driver0: D0,
@@ -19,6 +23,10 @@ pub struct RedundantDriver<D0, D1> {
     status0: Result<(), OutOfMemoryError>,
     /// Result of the last try_reserve() call on driver 1
     status1: Result<(), OutOfMemoryError>,
+    /// Frames sent and failed on driver 0
+    statistics0: IoStatistics,
+    /// Frames sent and failed on driver 1
+    statistics1: IoStatistics,
 }
 
 impl<D0, D1> RedundantDriver<D0, D1> {
@@ -29,6 +37,8 @@ impl<D0, D1> RedundantDriver<D0, D1> {
             driver1,
             status0: Ok(()),
             status1: Ok(()),
+            statistics0: IoStatistics::default(),
+            statistics1: IoStatistics::default(),
         }
     }
 }
@@ -69,6 +79,8 @@ where
         } else {
             Err(nb::Error::WouldBlock)
         };
+        count_frame(&mut self.statistics0, &push_status_0);
+        count_frame(&mut self.statistics1, &push_status_1);
         // This is successful if the frame got onto at least one queue.
         // If two frames were returned, send the first and ignore the second.
         match (push_status_0, push_status_1) {
@@ -99,6 +111,61 @@ where
             (_, Err(nb::Error::Other(e))) => Err(nb::Error::Other(RedundantError::Driver1(e))),
         }
     }
+
+    fn add_interface_statistics(
+        &self,
+        first_interface: usize,
+        statistics: &mut TransportStatistics,
+    ) -> usize {
+        let count0 = add_driver_statistics(
+            &self.driver0,
+            &self.statistics0,
+            first_interface,
+            statistics,
+        );
+        let count1 = add_driver_statistics(
+            &self.driver1,
+            &self.statistics1,
+            first_interface + count0,
+            statistics,
+        );
+        count0 + count1
+    }
+}
+
+/// Updates the frame counters of one driver after an attempt to send a frame
+///
+/// A frame that the driver did not have space for counts as an error.
+fn count_frame<T, E>(statistics: &mut IoStatistics, status: &nb::Result<T, E>) {
+    match status {
+        Ok(_) => statistics.emitted = statistics.emitted.wrapping_add(1),
+        Err(_) => statistics.errored = statistics.errored.wrapping_add(1),
+    }
+}
+
+/// Adds the interface statistics from a driver, or the statistics that a redundant driver
+/// recorded for it if it does not count frames, to `statistics`
+///
+/// This function returns the number of interfaces added.
+fn add_driver_statistics<C, D>(
+    driver: &D,
+    recorded: &IoStatistics,
+    first_interface: usize,
+    statistics: &mut TransportStatistics,
+) -> usize
+where
+    C: Clock,
+    D: TransmitDriver<C>,
+{
+    match driver.add_interface_statistics(first_interface, statistics) {
+        0 => {
+            if let Some(interface) = statistics.interface_mut(first_interface) {
+                interface.add(recorded);
+            }
+            1
+        }
+        count => count,
+    }
 }
 
 /// An error from a DoubleRedundantQueueDriver
@@ -17,7 +17,9 @@ use crate::data::{CanId, Frame};
 use crate::driver::ReceiveDriver;
 use crate::rx::session::SessionError;
 use crate::rx::subscription::{Subscription, SubscriptionError};
+use crate::statistics::RxStatistics;
 use crate::types::{CanNodeId, CanTransferId, CanTransport, Error};
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::time::{Clock, MicrosecondDuration32, Microseconds32};
 use canadensis_core::transfer::{Header, MessageHeader, ServiceHeader, Transfer};
 use canadensis_core::transport::Receiver;
@@ -44,6 +46,14 @@ pub struct CanReceiver<C, D> {
     /// Errors include failure to allocate memory (when handling incoming frames only), missing
     /// frames, and malformed frames.
     error_count: u64,
+    /// Counters of frames and transfers on all ports
+    statistics: RxStatistics,
+    /// Counters of frames and transfers from each source node that has sent a frame, in order of
+    /// increasing node ID
+    source_statistics: Vec<(CanNodeId, RxStatistics)>,
+    /// Subscription settings for ports that are subscribed automatically, if this receiver
+    /// accepts transfers on all ports
+    accept_all: Option<AcceptAll>,
     /// The driver that supplies incoming frames
     _driver: PhantomData<D>,
     /// The clock used to get the current time
@@ -225,6 +235,35 @@ where
             .iter()
             .map(|x| x.port_id().try_into().unwrap())
     }
+
+    /// Adds the transfer counters of this receiver and the frame counters from the driver
+    /// to `statistics`
+    ///
+    /// If the driver does not count frames, this function adds the numbers of frames that this
+    /// receiver has processed as interface 0. Frames with unexpected toggle bits and frames
+    /// from anonymous multi-frame transfers are counted as errors.
+    fn add_statistics(&self, driver: &D, statistics: &mut TransportStatistics) {
+        statistics.transfers.add(&IoStatistics {
+            emitted: 0,
+            received: self.transfer_count,
+            errored: self.error_count,
+        });
+        if driver.add_interface_statistics(0, statistics) == 0 {
+            if let Some(interface) = statistics.interface_mut(0) {
+                interface.add(&IoStatistics {
+                    emitted: 0,
+                    received: self
+                        .statistics
+                        .frames
+                        .wrapping_add(self.statistics.filter_false_positives),
+                    errored: self
+                        .statistics
+                        .toggle_errors
+                        .wrapping_add(self.statistics.anonymous_multi_frame),
+                });
+            }
+        }
+    }
 }
 
 impl<C, D> CanReceiver<C, D>
@@ -254,6 +293,9 @@ where
             id,
             transfer_count: 0,
             error_count: 0,
+            statistics: RxStatistics::default(),
+            source_statistics: Vec::new(),
+            accept_all: None,
             _driver: PhantomData,
             _clock: PhantomData,
         }
@@ -276,18 +318,39 @@ where
     ) -> Result<Option<Transfer<Vec<u8>, CanTransport>>, OutOfMemoryError> {
         // Part 1: basic frame checks
         let (frame_header, tail) = match Self::frame_sanity_check(&frame) {
-            Some(data) => data,
-            None => {
+            Ok(data) => data,
+            Err(FrameCheckError::Malformed) => {
                 // Can't use this frame
                 l0g::debug!("Frame failed sanity checks, ignoring");
                 self.increment_error_count();
                 return Ok(None);
             }
+            Err(FrameCheckError::AnonymousMultiFrame(header)) => {
+                l0g::debug!("Anonymous multi-frame transfer, ignoring");
+                self.increment_error_count();
+                let kind = TransferKind::from_header(&header);
+                if let Some(subscription) = self
+                    .subscriptions_for_kind(kind)
+                    .iter_mut()
+                    .find(|subscription| subscription.port_id() == header.port_id())
+                {
+                    let statistics = subscription.statistics_mut();
+                    statistics.anonymous_multi_frame =
+                        statistics.anonymous_multi_frame.wrapping_add(1);
+                }
+                self.statistics.anonymous_multi_frame =
+                    self.statistics.anonymous_multi_frame.wrapping_add(1);
+                return Ok(None);
+            }
         };
         // Check that the frame is actually destined for this node, and this node can handle services
         // Exception: Loopback frames came from this node and are always accepted
         if let Header::Request(service_header) | Header::Response(service_header) = &frame_header {
-            if !(frame.loopback() || self.can_accept_service(service_header)) {
+            if !(frame.loopback()
+                || self.accept_all.is_some()
+                || self.can_accept_service(service_header))
+            {
+                self.record_false_positive(service_header.source);
                 return Ok(None);
             }
         }
@@ -311,17 +374,48 @@ where
         tail: TailByte,
     ) -> Result<Option<Transfer<Vec<u8>, CanTransport>>, OutOfMemoryError> {
         let kind = TransferKind::from_header(&frame_header);
+        let source = frame_header.source().cloned();
+        let loopback = frame.loopback();
+        if let Some(accept_all) = self.accept_all.clone() {
+            let port_id = frame_header.port_id();
+            let subscribed = self
+                .subscriptions_for_kind(kind)
+                .iter()
+                .any(|subscription| subscription.port_id() == port_id);
+            if !subscribed {
+                self.subscribe(
+                    kind,
+                    port_id,
+                    accept_all.payload_size_max,
+                    accept_all.timeout,
+                )?;
+            }
+        }
         let subscriptions = self.subscriptions_for_kind(kind);
         if let Some(subscription) = subscriptions
             .iter_mut()
             .find(|subscription| subscription.port_id() == frame_header.port_id())
         {
-            match subscription.accept(frame, frame_header, tail) {
+            let statistics_before = *subscription.statistics();
+            let result = subscription.accept(frame, frame_header, tail);
+            let statistics_after = *subscription.statistics();
+            self.statistics
+                .add_change(&statistics_before, &statistics_after);
+            if let Some(source) = source {
+                if let Some(source_statistics) = self.source_statistics_mut(source) {
+                    source_statistics.add_change(&statistics_before, &statistics_after);
+                }
+            }
+
+            match result {
                 Ok(Some(transfer)) => {
                     self.increment_transfer_count();
                     Ok(Some(transfer))
                 }
                 Ok(None) => Ok(None),
+                // A frame with an unexpected toggle bit does not prevent the transfer from being
+                // received
+                Err(SubscriptionError::Session(SessionError::Toggle)) => Ok(None),
                 Err(e) => {
                     l0g::info!("Receiver accept error {:?}", e);
                     self.increment_error_count();
@@ -337,31 +431,69 @@ where
             }
         } else {
             // No subscription for this port, ignore frame
+            if !loopback {
+                if let Some(source) = source {
+                    self.record_false_positive(source);
+                } else {
+                    self.statistics.filter_false_positives =
+                        self.statistics.filter_false_positives.wrapping_add(1);
+                }
+            }
             Ok(None)
         }
     }
 
+    /// Records a frame that got through the driver's filters but that this receiver can't use
+    fn record_false_positive(&mut self, source: CanNodeId) {
+        self.statistics.filter_false_positives =
+            self.statistics.filter_false_positives.wrapping_add(1);
+        if let Some(statistics) = self.source_statistics_mut(source) {
+            statistics.filter_false_positives = statistics.filter_false_positives.wrapping_add(1);
+        }
+    }
+
+    /// Returns the statistics for a source node, creating them if necessary
+    ///
+    /// This function returns None if memory for the new statistics could not be allocated.
+    fn source_statistics_mut(&mut self, source: CanNodeId) -> Option<&mut RxStatistics> {
+        let index = match self
+            .source_statistics
+            .binary_search_by_key(&source, |(node, _)| *node)
+        {
+            Ok(index) => index,
+            Err(index) => {
+                self.source_statistics.try_reserve(1).ok()?;
+                self.source_statistics
+                    .insert(index, (source, RxStatistics::default()));
+                index
+            }
+        };
+        Some(&mut self.source_statistics[index].1)
+    }
+
     /// Runs basic sanity checks on an incoming frame. Returns the header and tail byte if the frame
     /// is valid.
-    fn frame_sanity_check(frame: &Frame) -> Option<(Header<CanTransport>, TailByte)> {
+    fn frame_sanity_check(
+        frame: &Frame,
+    ) -> Result<(Header<CanTransport>, TailByte), FrameCheckError> {
         // Frame must have a tail byte to be valid
-        let tail_byte = TailByte::parse(*frame.data().last()?);
+        let tail_byte = TailByte::parse(*frame.data().last().ok_or(FrameCheckError::Malformed)?);
 
-        let header = parse_can_id(frame.id(), frame.timestamp(), tail_byte.transfer_id).ok()?;
+        let header = parse_can_id(frame.id(), frame.timestamp(), tail_byte.transfer_id)
+            .map_err(|_| FrameCheckError::Malformed)?;
 
         // Additional header checks
         if let Header::Message(message_header) = &header {
             if message_header.source.is_none() {
                 // Anonymous message transfers must always fit into one frame
                 if !(tail_byte.toggle && tail_byte.start && tail_byte.end) {
-                    l0g::debug!("Anonymous multi-frame transfer, ignoring");
-                    return None;
+                    return Err(FrameCheckError::AnonymousMultiFrame(header));
                 }
             }
         }
 
         // OK
-        Some((header, tail_byte))
+        Ok((header, tail_byte))
     }
 
     fn subscribe(
@@ -412,6 +544,86 @@ where
         self.error_count
     }
 
+    /// Makes this receiver accept transfers on all ports, including service transfers addressed
+    /// to other nodes
+    ///
+    /// This is intended for tools that monitor a bus. When a frame arrives on a port that this
+    /// receiver is not subscribed to, the receiver subscribes to the port with the provided
+    /// maximum payload size and transfer-ID timeout. The driver is set to accept all frames.
+    ///
+    /// Service transfers from one node on the same port are reassembled in one session, even if
+    /// they have different destinations.
+    pub fn accept_all(
+        &mut self,
+        payload_size_max: usize,
+        timeout: MicrosecondDuration32,
+        driver: &mut D,
+    ) {
+        self.accept_all = Some(AcceptAll {
+            payload_size_max,
+            timeout,
+        });
+        self.apply_frame_filters(driver);
+    }
+
+    /// Returns the counters of frames and transfers on all ports
+    pub fn statistics(&self) -> RxStatistics {
+        self.statistics
+    }
+
+    /// Returns an iterator over the subscriptions of this receiver and the counters of frames
+    /// and transfers on each subscribed port
+    pub fn port_statistics(
+        &self,
+    ) -> impl Iterator<Item = (canadensis_core::subscription::Subscription, RxStatistics)> + '_
+    {
+        use canadensis_core::subscription::Subscription as CoreSubscription;
+        let messages = self.subscriptions_message.iter().map(|sub| {
+            (
+                CoreSubscription::Message(sub.port_id().try_into().unwrap()),
+                *sub.statistics(),
+            )
+        });
+        let requests = self.subscriptions_request.iter().map(|sub| {
+            (
+                CoreSubscription::Request(sub.port_id().try_into().unwrap()),
+                *sub.statistics(),
+            )
+        });
+        let responses = self.subscriptions_response.iter().map(|sub| {
+            (
+                CoreSubscription::Response(sub.port_id().try_into().unwrap()),
+                *sub.statistics(),
+            )
+        });
+        messages.chain(requests).chain(responses)
+    }
+
+    /// Returns an iterator over the nodes that have sent frames to this receiver and the
+    /// counters of frames and transfers from each node, in order of increasing node ID
+    ///
+    /// Frames from anonymous nodes are not included.
+    pub fn source_statistics(&self) -> impl Iterator<Item = (CanNodeId, RxStatistics)> + '_ {
+        self.source_statistics.iter().cloned()
+    }
+
+    /// Sets all counters of frames and transfers (including the transfer count and error count)
+    /// to zero
+    pub fn reset_statistics(&mut self) {
+        self.transfer_count = 0;
+        self.error_count = 0;
+        self.statistics = RxStatistics::default();
+        self.source_statistics.clear();
+        for subscription in self
+            .subscriptions_message
+            .iter_mut()
+            .chain(self.subscriptions_request.iter_mut())
+            .chain(self.subscriptions_response.iter_mut())
+        {
+            *subscription.statistics_mut() = RxStatistics::default();
+        }
+    }
+
     fn increment_transfer_count(&mut self) {
         self.transfer_count = self.transfer_count.wrapping_add(1)
     }
@@ -420,6 +632,10 @@ where
     }
 
     fn apply_frame_filters(&mut self, driver: &mut D) {
+        if self.accept_all.is_some() {
+            driver.apply_accept_all();
+            return;
+        }
         let message_subscriptions = self.subscriptions_message.iter().map(|sub| {
             canadensis_core::subscription::Subscription::Message(sub.port_id().try_into().unwrap())
         });
@@ -436,6 +652,24 @@ where
     }
 }
 
+/// Subscription settings for ports that a receiver subscribes to automatically
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct AcceptAll {
+    /// The maximum payload size for each port
+    payload_size_max: usize,
+    /// The transfer-ID timeout for each port
+    timeout: MicrosecondDuration32,
+}
+
+/// Reasons that a frame can't be processed
+enum FrameCheckError {
+    /// The frame has no tail byte or its CAN ID is not valid
+    Malformed,
+    /// The frame is part of a multi-frame anonymous transfer
+    AnonymousMultiFrame(Header<CanTransport>),
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum CanIdParseError {
@@ -64,11 +64,8 @@ impl Buildup {
         );
         let tail = TailByte::parse(*frame_data.last().unwrap());
         // Check tail byte
-        if tail.start != self.expect_start {
-            return Ok(None);
-        }
-        if tail.toggle != self.expect_toggle {
-            return Ok(None);
+        if tail.start != self.expect_start || tail.toggle != self.expect_toggle {
+            return Err(BuildupError::Toggle);
         }
         // Prepare for the next frame
         self.expect_start = false;
@@ -110,6 +107,10 @@ impl Buildup {
 pub enum BuildupError {
     OutOfMemory,
     Crc,
+    /// The frame had an unexpected start or toggle bit and was ignored
+    ///
+    /// This error does not prevent the transfer from being completed with later frames.
+    Toggle,
 }
 
 impl From<TryReserveError> for BuildupError {
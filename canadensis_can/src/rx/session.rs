@@ -96,8 +96,10 @@ impl Session {
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SessionError {
-    /// Reassembly failed because of an unexpected frame
-    Buildup,
+    /// Reassembly failed because the transfer CRC was not correct
+    Crc,
+    /// A frame had an unexpected start or toggle bit and was ignored, but the session can continue
+    Toggle,
     /// Memory allocation failed
     Memory(OutOfMemoryError),
 }
@@ -108,7 +110,11 @@ impl From<OutOfMemoryError> for SessionError {
     }
 }
 impl From<BuildupError> for SessionError {
-    fn from(_inner: BuildupError) -> Self {
-        SessionError::Buildup
+    fn from(inner: BuildupError) -> Self {
+        match inner {
+            BuildupError::OutOfMemory => SessionError::Memory(OutOfMemoryError),
+            BuildupError::Crc => SessionError::Crc,
+            BuildupError::Toggle => SessionError::Toggle,
+        }
     }
 }
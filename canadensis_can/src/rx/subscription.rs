@@ -1,5 +1,6 @@
 use crate::rx::session::{Session, SessionError};
 use crate::rx::TailByte;
+use crate::statistics::RxStatistics;
 use crate::types::{CanNodeId, Header, Transfer};
 use crate::{CanTransferId, Frame};
 use alloc::boxed::Box;
@@ -47,6 +48,8 @@ pub struct Subscription {
     port_id: PortId,
     /// State information from each possible node ID
     states: SessionStates,
+    /// Counters of frames and transfers on this port
+    statistics: RxStatistics,
 }
 
 impl Debug for Subscription {
@@ -56,6 +59,7 @@ impl Debug for Subscription {
             .field("payload_size_max", &self.payload_size_max)
             .field("port_id", &self.port_id)
             .field("states", &self.states)
+            .field("statistics", &self.statistics)
             .finish()
     }
 }
@@ -71,6 +75,7 @@ impl Subscription {
             payload_size_max,
             port_id,
             states: SessionStates::new(),
+            statistics: RxStatistics::default(),
         }
     }
 
@@ -81,11 +86,25 @@ impl Subscription {
         frame_header: Header,
         tail: TailByte,
     ) -> Result<Option<Transfer<Vec<u8>>>, SubscriptionError> {
-        if let Some(source_node) = frame_header.source().cloned() {
+        self.statistics.frames = self.statistics.frames.wrapping_add(1);
+        let result = if let Some(source_node) = frame_header.source().cloned() {
             self.checked_accept_non_anonymous(frame, frame_header, source_node, tail)
         } else {
             self.accept_anonymous(frame, frame_header)
-        }
+        };
+        let counter = match &result {
+            Ok(Some(_)) => &mut self.statistics.transfers,
+            Ok(None) => return result,
+            Err(SubscriptionError::NotStart)
+            | Err(SubscriptionError::Session(SessionError::Toggle)) => {
+                &mut self.statistics.toggle_errors
+            }
+            Err(SubscriptionError::Session(SessionError::Crc)) => &mut self.statistics.crc_errors,
+            Err(SubscriptionError::Session(SessionError::Memory(_)))
+            | Err(SubscriptionError::Memory(_)) => &mut self.statistics.out_of_memory,
+        };
+        *counter = counter.wrapping_add(1);
+        result
     }
 
     /// Performs transfer-ID checks before passing the frame further up the chain
@@ -97,14 +116,18 @@ impl Subscription {
         tail: TailByte,
     ) -> Result<Option<Transfer<Vec<u8>>>, SubscriptionError> {
         let expected_transfer_id = self.states.get(source).expected_transfer_id;
+        let previous_transfer_id = tail.transfer_id.increment() == expected_transfer_id;
         if tail.transfer_id == expected_transfer_id {
             self.accept_non_anonymous(frame, frame_header, source, tail)
-        } else if tail.transfer_id.increment() == expected_transfer_id
-            && !self.has_transfer_id_timed_out(source, frame.timestamp())
+        } else if previous_transfer_id && !self.has_transfer_id_timed_out(source, frame.timestamp())
         {
             // Drop frame, as we consider this to be a duplicate transfer.
             Ok(None)
         } else {
+            if previous_transfer_id {
+                self.statistics.transfer_id_timeouts =
+                    self.statistics.transfer_id_timeouts.wrapping_add(1);
+            }
             // Either the new frame has messed with the transfer ID that isn't a simple duplication
             // (e.g. they've reset the counter to some other value), or it uses the previous
             // transfer-ID but we've timed out. Therefore, reset the session and the expected transfer-ID.
@@ -213,8 +236,12 @@ impl Subscription {
                 Ok(Some(transfer))
             }
             Ok(None) => Ok(None),
+            Err(SessionError::Toggle) => {
+                // The frame was ignored, but the session can still be completed
+                Err(SessionError::Toggle.into())
+            }
             Err(e) => {
-                // This is either out-of-memory or an unexpected frame that invalidates the
+                // This is either out-of-memory or an incorrect CRC that invalidates the
                 // session.
                 self.states.get_mut(source).session = None;
                 Err(e.into())
@@ -247,6 +274,15 @@ impl Subscription {
     pub fn port_id(&self) -> PortId {
         self.port_id
     }
+
+    /// Returns the counters of frames and transfers on this port
+    pub fn statistics(&self) -> &RxStatistics {
+        &self.statistics
+    }
+    /// Returns a mutable reference to the counters of frames and transfers on this port
+    pub fn statistics_mut(&mut self) -> &mut RxStatistics {
+        &mut self.statistics
+    }
 }
 
 /// Errors that a subscription may encounter
@@ -0,0 +1,93 @@
+//!
+//! Counters of received and transmitted frames and transfers
+//!
+
+/// Counters of frames and transfers received on one port, from one node, or by a whole receiver
+///
+/// All counters wrap around when they reach their maximum values.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RxStatistics {
+    /// Frames that matched a subscription and were passed to transfer reassembly
+    pub frames: u64,
+    /// Transfers successfully reassembled
+    pub transfers: u64,
+    /// Frames that were ignored because their start or toggle bit did not have the expected value
+    ///
+    /// This includes duplicate frames and frames that arrived after the first frame of their
+    /// transfer was lost.
+    pub toggle_errors: u64,
+    /// Multi-frame transfers that were dropped because their transfer CRC was incorrect
+    pub crc_errors: u64,
+    /// Frames that had the same transfer ID as the previous transfer, but arrived after the
+    /// transfer-ID timeout and started a new transfer
+    pub transfer_id_timeouts: u64,
+    /// Frames that were dropped because memory for a transfer could not be allocated
+    pub out_of_memory: u64,
+    /// Frames that were dropped because they were part of an anonymous multi-frame transfer
+    pub anonymous_multi_frame: u64,
+    /// Frames that got through the driver's frame filters but did not match any subscription
+    ///
+    /// This includes service transfers addressed to other nodes. Loopback frames are not counted.
+    pub filter_false_positives: u64,
+}
+
+impl RxStatistics {
+    /// Adds the difference between two sets of counters (`after - before`) to these counters
+    pub(crate) fn add_change(&mut self, before: &RxStatistics, after: &RxStatistics) {
+        fn add(total: &mut u64, before: u64, after: u64) {
+            *total = total.wrapping_add(after.wrapping_sub(before));
+        }
+        add(&mut self.frames, before.frames, after.frames);
+        add(&mut self.transfers, before.transfers, after.transfers);
+        add(
+            &mut self.toggle_errors,
+            before.toggle_errors,
+            after.toggle_errors,
+        );
+        add(&mut self.crc_errors, before.crc_errors, after.crc_errors);
+        add(
+            &mut self.transfer_id_timeouts,
+            before.transfer_id_timeouts,
+            after.transfer_id_timeouts,
+        );
+        add(
+            &mut self.out_of_memory,
+            before.out_of_memory,
+            after.out_of_memory,
+        );
+        add(
+            &mut self.anonymous_multi_frame,
+            before.anonymous_multi_frame,
+            after.anonymous_multi_frame,
+        );
+        add(
+            &mut self.filter_false_positives,
+            before.filter_false_positives,
+            after.filter_false_positives,
+        );
+    }
+}
+
+/// Counters of transfers and frames sent by a transmitter
+///
+/// All counters wrap around when they reach their maximum values.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TxStatistics {
+    /// Transfers that were completely handed over to the driver
+    pub transfers: u64,
+    /// Frames that were handed over to the driver
+    pub frames: u64,
+    /// Transfers whose deadline had already passed when they were pushed
+    ///
+    /// These transfers are still passed to the driver. Frames that expire later, while they are
+    /// waiting in a driver's queue, are not counted.
+    pub deadline_expired: u64,
+    /// Transfers that were dropped (completely or partially) because the driver did not have
+    /// space for all their frames
+    pub queue_full: u64,
+    /// Lower-priority frames that the driver removed from its queue to make space for
+    /// higher-priority frames
+    pub displaced_frames: u64,
+}
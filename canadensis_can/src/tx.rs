@@ -7,12 +7,14 @@ use core::iter;
 use core::marker::PhantomData;
 
 use canadensis_core::nb;
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::time::{Clock, Microseconds32};
 use canadensis_core::transfer::{Header, ServiceHeader, Transfer};
 use canadensis_core::transport::Transmitter;
 
 use crate::data::Frame;
 use crate::driver::TransmitDriver;
+use crate::statistics::TxStatistics;
 use crate::tx::breakdown::Breakdown;
 use crate::types::{CanNodeId, CanTransport, Error};
 use crate::{CanId, Mtu};
@@ -26,11 +28,11 @@ mod tx_test;
 pub struct CanTransmitter<C, D> {
     /// Transport MTU (including the tail byte)
     mtu: usize,
-    /// Number of transfers successfully transmitted
+    /// Counters of transfers and frames
     ///
-    /// Success means that the frames were placed into the frame queue successfully. CAN bus errors
-    /// are ignored.
-    transfer_count: u64,
+    /// The transfer counter counts transfers whose frames were placed into the frame queue
+    /// successfully. CAN bus errors are ignored.
+    statistics: TxStatistics,
     /// Number of transfers that could not be transmitted
     ///
     /// A failure to allocate memory is considered an error. CAN bus errors are ignored.
@@ -70,12 +72,20 @@ where
             payload: transfer.payload.as_ref(),
         };
 
+        if clock.now() > transfer.header.timestamp() {
+            // The driver decides what to do with frames that have missed their deadline
+            self.statistics.deadline_expired = self.statistics.deadline_expired.wrapping_add(1);
+        }
+
         match self.push_inner(transfer, clock, driver) {
             Ok(()) => {
-                self.transfer_count = self.transfer_count.wrapping_add(1);
+                self.statistics.transfers = self.statistics.transfers.wrapping_add(1);
                 Ok(())
             }
             Err(e) => {
+                if let nb::Error::WouldBlock | nb::Error::Other(Error::Memory(_)) = e {
+                    self.statistics.queue_full = self.statistics.queue_full.wrapping_add(1);
+                }
                 self.error_count = self.error_count.wrapping_add(1);
                 Err(e)
             }
@@ -90,6 +100,24 @@ where
         // Subtract 1 for the tail byte
         self.mtu - 1
     }
+
+    /// Adds the transfer counters of this transmitter and the frame counters from the driver
+    /// to `statistics`
+    ///
+    /// If the driver does not count frames, this function adds the number of frames that this
+    /// transmitter has handed over to the driver as interface 0.
+    fn add_statistics(&self, driver: &D, statistics: &mut TransportStatistics) {
+        statistics.transfers.add(&IoStatistics {
+            emitted: self.statistics.transfers,
+            received: 0,
+            errored: self.error_count,
+        });
+        if driver.add_interface_statistics(0, statistics) == 0 {
+            if let Some(interface) = statistics.interface_mut(0) {
+                interface.emitted = interface.emitted.wrapping_add(self.statistics.frames);
+            }
+        }
+    }
 }
 
 impl<C, D> CanTransmitter<C, D>
@@ -103,7 +131,7 @@ where
     pub fn new(mtu: Mtu) -> Self {
         CanTransmitter {
             mtu: mtu as usize,
-            transfer_count: 0,
+            statistics: TxStatistics::default(),
             error_count: 0,
             _clock: PhantomData,
             _driver: PhantomData,
@@ -194,7 +222,8 @@ where
 
     /// Creates a frame and sends it to the driver to be transmitted
     ///
-    /// If the driver returns a removed lower-priority frame, this function discards it.
+    /// If the driver returns a removed lower-priority frame, this function discards it and counts
+    /// it as displaced.
     fn push_frame(
         &mut self,
         timestamp: Microseconds32,
@@ -206,8 +235,13 @@ where
     ) -> nb::Result<(), D::Error> {
         let mut frame = Frame::new(timestamp, id, data);
         frame.set_loopback(loopback);
+        let displaced = driver.transmit(frame, clock)?;
+        self.statistics.frames = self.statistics.frames.wrapping_add(1);
         // If a lower-priority frame was removed, drop it
-        driver.transmit(frame, clock).map(drop)
+        if displaced.is_some() {
+            self.statistics.displaced_frames = self.statistics.displaced_frames.wrapping_add(1);
+        }
+        Ok(())
     }
 
     /// Returns the number of transfers successfully transmitted
@@ -216,7 +250,7 @@ where
     ///  are ignored.
     #[inline]
     pub fn transfer_count(&self) -> u64 {
-        self.transfer_count
+        self.statistics.transfers
     }
 
     /// Returns the number of transfers that could not be transmitted
@@ -226,6 +260,17 @@ where
     pub fn error_count(&self) -> u64 {
         self.error_count
     }
+
+    /// Returns the counters of transfers and frames sent by this transmitter
+    pub fn statistics(&self) -> TxStatistics {
+        self.statistics
+    }
+
+    /// Sets all counters of transfers and frames (including the error count) to zero
+    pub fn reset_statistics(&mut self) {
+        self.statistics = TxStatistics::default();
+        self.error_count = 0;
+    }
 }
 
 fn make_can_id(header: &Header<CanTransport>, payload: &[u8]) -> CanId {
@@ -11,7 +11,7 @@ use std::collections::{HashMap, VecDeque};
 use std::iter;
 
 use canadensis_can::driver::ReceiveDriver;
-use canadensis_can::{CanId, CanNodeId, CanReceiver, Frame, FRAME_CAPACITY};
+use canadensis_can::{CanId, CanNodeId, CanReceiver, Frame, RxStatistics, FRAME_CAPACITY};
 use canadensis_core::nb;
 use canadensis_core::subscription::Subscription;
 use canadensis_core::time::{milliseconds, Clock, MicrosecondDuration32, Microseconds32};
@@ -950,6 +950,55 @@ fn test_ignore_request_to_other_node() {
     assert_eq!(transfer, None);
 }
 
+#[test]
+fn test_accept_all() {
+    let mut driver = StubDriver::default();
+    let mut rx = CanReceiver::new(43u8.try_into().unwrap());
+    rx.accept_all(7, duration(0), &mut driver);
+
+    // A heartbeat without a subscription
+    driver.push(Frame::new(
+        instant(302),
+        0x107d552a.try_into().unwrap(),
+        &[0x00, 0x00, 0x00, 0x00, 0x04, 0x78, 0x68, 0xe0],
+    ));
+    // A request going to node 42
+    driver.push(Frame::new(
+        instant(302),
+        0x136b957b.try_into().unwrap(),
+        &[0xe1],
+    ));
+    let clock = ClockOwner::default();
+    clock.set_ticks(302);
+
+    let heartbeat = rx
+        .receive(&mut clock.make_clock(), &mut driver)
+        .unwrap()
+        .expect("Didn't get a heartbeat");
+    match heartbeat.header {
+        Header::Message(header) => assert_eq!(SubjectId::try_from(7509).unwrap(), header.subject),
+        _ => panic!("Expected a message"),
+    }
+    assert_eq!(vec![0x00, 0x00, 0x00, 0x00, 0x04, 0x78, 0x68], heartbeat.payload);
+
+    let request = rx
+        .receive(&mut clock.make_clock(), &mut driver)
+        .unwrap()
+        .expect("Didn't get a request");
+    match request.header {
+        Header::Request(header) => {
+            assert_eq!(ServiceId::try_from(430).unwrap(), header.service);
+            assert_eq!(CanNodeId::try_from(42u8).unwrap(), header.destination);
+            assert_eq!(CanNodeId::try_from(123u8).unwrap(), header.source);
+        }
+        _ => panic!("Expected a request"),
+    }
+    assert_eq!(
+        vec![SubjectId::try_from(7509).unwrap()],
+        rx.subscribers().collect::<Vec<_>>()
+    );
+}
+
 #[test]
 fn test_message_payload_too_large_single_frame() {
     let mut driver = StubDriver::default();
@@ -2025,6 +2074,171 @@ fn test_stressed_concurrent_subscriptions() {
     }
 }
 
+#[test]
+#[allow(clippy::unusual_byte_groupings)]
+fn receive_statistics() {
+    let mut driver = StubDriver::default();
+    let local_id = CanNodeId::try_from(7u8).unwrap();
+    let remote_id = CanNodeId::try_from(120u8).unwrap();
+    let mut rx: CanReceiver<StubClock, StubDriver> = CanReceiver::new(local_id);
+    let subject = SubjectId::try_from(8003).unwrap();
+    rx.subscribe_message(subject, 12, milliseconds(1000), &mut driver)
+        .unwrap();
+    let frame_id: u32 = 0b101_00011_1111101000011_0_1111000;
+    let clock = ClockOwner::default();
+
+    let transfer_frames = |time: Microseconds32, transfer_id: u8, first_byte: u8| {
+        vec![
+            Frame::new(
+                time,
+                frame_id.try_into().unwrap(),
+                &[
+                    first_byte,
+                    0x10,
+                    0x09,
+                    0xff,
+                    0xae,
+                    0x69,
+                    0xa2,
+                    tail(true, false, true, transfer_id),
+                ],
+            ),
+            Frame::new(
+                time,
+                frame_id.try_into().unwrap(),
+                &[
+                    0x01,
+                    0x10,
+                    0x13,
+                    0x22,
+                    0x99,
+                    0xaa,
+                    0xed,
+                    tail(false, false, false, transfer_id),
+                ],
+            ),
+            Frame::new(
+                time,
+                frame_id.try_into().unwrap(),
+                &[
+                    0x00,
+                    0x20,
+                    0x41,
+                    0x44,
+                    0x7a,
+                    0x69,
+                    tail(false, true, true, transfer_id),
+                ],
+            ),
+        ]
+    };
+    let mut receive_all = |driver: &mut StubDriver, time: u32| {
+        clock.set_ticks(time);
+        let mut transfers = 0;
+        while !driver.frames.is_empty() {
+            if rx
+                .receive(&mut clock.make_clock(), driver)
+                .unwrap()
+                .is_some()
+            {
+                transfers += 1;
+            }
+        }
+        transfers
+    };
+
+    // Every frame duplicated: the duplicates of the first two frames have unexpected start and
+    // toggle bits, and the duplicate of the last frame is a duplicate transfer
+    for frame in transfer_frames(instant(10), 3, 0x30) {
+        driver.push(frame.clone());
+        driver.push(frame);
+    }
+    assert_eq!(1, receive_all(&mut driver, 10));
+    // Incorrect CRC
+    for frame in transfer_frames(instant(20), 4, 0x31) {
+        driver.push(frame);
+    }
+    assert_eq!(0, receive_all(&mut driver, 20));
+    // The previous transfer ID again, after the transfer-ID timeout
+    for frame in transfer_frames(instant(2_000_000), 3, 0x30) {
+        driver.push(frame);
+    }
+    assert_eq!(1, receive_all(&mut driver, 2_000_000));
+    // A heartbeat, which this receiver is not subscribed to
+    driver.push(Frame::new(
+        instant(2_000_010),
+        0x107d552a.try_into().unwrap(),
+        &[0x00, 0x00, 0x00, 0x00, 0x04, 0x78, 0x68, 0xe0],
+    ));
+    // The first frame of an anonymous multi-frame transfer
+    driver.push(Frame::new(
+        instant(2_000_020),
+        (frame_id | 1 << 24).try_into().unwrap(),
+        &[
+            0x30,
+            0x10,
+            0x09,
+            0xff,
+            0xae,
+            0x69,
+            0xa2,
+            tail(true, false, true, 5),
+        ],
+    ));
+    assert_eq!(0, receive_all(&mut driver, 2_000_020));
+
+    let port_expected = RxStatistics {
+        frames: 12,
+        transfers: 2,
+        toggle_errors: 2,
+        crc_errors: 1,
+        transfer_id_timeouts: 1,
+        out_of_memory: 0,
+        anonymous_multi_frame: 1,
+        filter_false_positives: 0,
+    };
+    assert_eq!(
+        vec![(Subscription::Message(subject), port_expected)],
+        rx.port_statistics().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        RxStatistics {
+            filter_false_positives: 1,
+            ..port_expected
+        },
+        rx.statistics()
+    );
+    assert_eq!(
+        vec![
+            (
+                CanNodeId::try_from(42u8).unwrap(),
+                RxStatistics {
+                    filter_false_positives: 1,
+                    ..RxStatistics::default()
+                }
+            ),
+            (
+                remote_id,
+                RxStatistics {
+                    anonymous_multi_frame: 0,
+                    ..port_expected
+                }
+            ),
+        ],
+        rx.source_statistics().collect::<Vec<_>>()
+    );
+    assert_eq!(2, rx.transfer_count());
+    assert_eq!(2, rx.error_count());
+
+    rx.reset_statistics();
+    assert_eq!(RxStatistics::default(), rx.statistics());
+    assert_eq!(0, rx.source_statistics().count());
+    assert_eq!(
+        vec![(Subscription::Message(subject), RxStatistics::default())],
+        rx.port_statistics().collect::<Vec<_>>()
+    );
+}
+
 fn delay_frame(frame: Frame, delay: MicrosecondDuration32) -> Frame {
     Frame::new(frame.timestamp() + delay, frame.id(), frame.data())
 }
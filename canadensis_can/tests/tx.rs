@@ -10,7 +10,9 @@ use std::collections::VecDeque;
 use std::convert::Infallible;
 
 use canadensis_can::driver::TransmitDriver;
-use canadensis_can::{CanId, CanNodeId, CanTransferId, CanTransmitter, Frame, Mtu};
+use canadensis_can::redundant::RedundantDriver;
+use canadensis_can::{CanId, CanNodeId, CanTransferId, CanTransmitter, Frame, Mtu, TxStatistics};
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::time::{Clock, Microseconds32};
 use canadensis_core::transfer::*;
 use canadensis_core::transport::Transmitter;
@@ -258,6 +260,118 @@ fn test_array() {
     assert_eq!(None, driver.pop_frame());
 }
 
+#[test]
+fn test_statistics() {
+    let heartbeat = |deadline: u32| Transfer {
+        header: Header::Message(MessageHeader {
+            timestamp: instant(deadline),
+            transfer_id: CanTransferId::try_from(0).unwrap(),
+            priority: Priority::Nominal,
+            subject: SubjectId::try_from(7509).unwrap(),
+            source: Some(CanNodeId::try_from(42u8).unwrap()),
+        }),
+        loopback: false,
+        payload: &[0x00, 0x00, 0x00, 0x00, 0x04, 0x78, 0x68],
+    };
+    let mut tx = CanTransmitter::new(Mtu::Can8);
+
+    let mut driver = LimitedDriver {
+        reserve_ok: true,
+        displace: false,
+    };
+    tx.push(heartbeat(100), &mut FixedClock(100), &mut driver)
+        .unwrap();
+    // Deadline has passed, but the transfer is still sent
+    tx.push(heartbeat(100), &mut FixedClock(101), &mut driver)
+        .unwrap();
+    // No space in the queue
+    driver.reserve_ok = false;
+    assert!(tx
+        .push(heartbeat(100), &mut FixedClock(0), &mut driver)
+        .is_err());
+    // The driver removes one frame to make space for another
+    driver.reserve_ok = true;
+    driver.displace = true;
+    tx.push(heartbeat(100), &mut FixedClock(0), &mut driver)
+        .unwrap();
+
+    assert_eq!(
+        TxStatistics {
+            transfers: 3,
+            frames: 3,
+            deadline_expired: 1,
+            queue_full: 1,
+            displaced_frames: 1,
+        },
+        tx.statistics()
+    );
+    assert_eq!(3, tx.transfer_count());
+    assert_eq!(1, tx.error_count());
+    tx.reset_statistics();
+    assert_eq!(TxStatistics::default(), tx.statistics());
+}
+
+#[test]
+fn test_redundant_statistics() {
+    let heartbeat = Transfer {
+        header: Header::Message(MessageHeader {
+            timestamp: instant(100),
+            transfer_id: CanTransferId::try_from(0).unwrap(),
+            priority: Priority::Nominal,
+            subject: SubjectId::try_from(7509).unwrap(),
+            source: Some(CanNodeId::try_from(42u8).unwrap()),
+        }),
+        loopback: false,
+        payload: &[0x00, 0x00, 0x00, 0x00, 0x04, 0x78, 0x68],
+    };
+    let mut tx = CanTransmitter::new(Mtu::Can8);
+    // Interface 1 is always full
+    let mut driver = RedundantDriver::new(
+        RedundantDriver::new(
+            MockDriver::default(),
+            LimitedDriver {
+                reserve_ok: false,
+                displace: false,
+            },
+        ),
+        MockDriver::default(),
+    );
+    tx.push(heartbeat.clone(), &mut ZeroClock, &mut driver)
+        .unwrap();
+    tx.push(heartbeat, &mut ZeroClock, &mut driver).unwrap();
+
+    let mut statistics = TransportStatistics::default();
+    tx.add_statistics(&driver, &mut statistics);
+    assert_eq!(
+        IoStatistics {
+            emitted: 2,
+            received: 0,
+            errored: 0,
+        },
+        statistics.transfers
+    );
+    assert_eq!(
+        [
+            IoStatistics {
+                emitted: 2,
+                received: 0,
+                errored: 0,
+            },
+            IoStatistics {
+                emitted: 0,
+                received: 0,
+                errored: 2,
+            },
+            IoStatistics {
+                emitted: 2,
+                received: 0,
+                errored: 0,
+            },
+        ],
+        statistics.interfaces.as_slice()
+    );
+}
+
 /// A simple driver that uses a `VecDeque`
 ///
 /// This does not keep the frames in order by priority, but it is correct as long as it is used for
@@ -307,3 +421,51 @@ impl Clock for ZeroClock {
         Microseconds32::from_ticks(0)
     }
 }
+
+/// A clock that always returns the same time
+struct FixedClock(u32);
+
+impl Clock for FixedClock {
+    fn now(&mut self) -> Microseconds32 {
+        Microseconds32::from_ticks(self.0)
+    }
+}
+
+/// A driver that discards frames, and can pretend to be full
+struct LimitedDriver {
+    /// If try_reserve() succeeds
+    reserve_ok: bool,
+    /// If transmit() reports that it removed a lower-priority frame
+    displace: bool,
+}
+
+impl<C> TransmitDriver<C> for LimitedDriver
+where
+    C: Clock,
+{
+    type Error = Infallible;
+
+    fn try_reserve(&mut self, _frames: usize) -> Result<(), OutOfMemoryError> {
+        if self.reserve_ok {
+            Ok(())
+        } else {
+            Err(OutOfMemoryError)
+        }
+    }
+
+    fn transmit(
+        &mut self,
+        frame: Frame,
+        _clock: &mut C,
+    ) -> canadensis_core::nb::Result<Option<Frame>, Self::Error> {
+        if self.displace {
+            Ok(Some(frame))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn flush(&mut self, _clock: &mut C) -> canadensis_core::nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
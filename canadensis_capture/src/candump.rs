@@ -0,0 +1,293 @@
+//!
+//! Log files in the format written by `candump -l` and read by `canplayer`
+//!
+//! Each line has a timestamp in seconds, an interface name, and a frame:
+//!
+//! ```text
+//! (1700000000.123456) can0 107D552A#0000000000000000E0
+//! (1700000000.124002) can0 123#DEADBEEF
+//! (1700000000.125311) can1 10606A2B##1112233445566778899AABB
+//! ```
+//!
+//! Classic CAN frames use one `#` and CAN FD frames use `##` followed by one hexadecimal digit of
+//! flags. Remote frames have `R` instead of data.
+//!
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use canadensis_can::{CanId, Frame, FRAME_CAPACITY};
+use canadensis_core::time::Microseconds32;
+
+use crate::Error;
+
+/// The ID of a recorded frame
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RecordId {
+    /// An 11-bit standard ID
+    Standard(u16),
+    /// A 29-bit extended ID
+    Extended(u32),
+    /// An error frame, with the error class bits
+    Error(u32),
+}
+
+/// Bit set in the ID of an error frame in candump logs and SocketCAN
+const ERROR_FLAG: u32 = 0x2000_0000;
+/// Largest 11-bit ID
+const STANDARD_ID_MAX: u32 = 0x7ff;
+/// Largest 29-bit ID
+const EXTENDED_ID_MAX: u32 = 0x1fff_ffff;
+
+/// A CAN or CAN FD frame from a recording
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CanRecord {
+    /// The time when the frame was received, relative to some epoch (usually the Unix epoch)
+    pub timestamp: Duration,
+    /// The name of the interface that received the frame
+    pub interface: String,
+    /// The frame ID
+    pub id: RecordId,
+    /// True if this is a remote transmission request frame
+    pub remote: bool,
+    /// For CAN FD frames, the CAN FD flags (bit 0: bit rate switch, bit 1: error state indicator)
+    ///
+    /// This is None for classic CAN frames.
+    pub fd_flags: Option<u8>,
+    /// The frame data
+    pub data: Vec<u8>,
+}
+
+impl CanRecord {
+    /// Creates a record from a Cyphal/CAN frame
+    ///
+    /// Frames with more than 8 bytes of data are recorded as CAN FD frames.
+    pub fn from_frame(timestamp: Duration, interface: &str, frame: &Frame) -> Self {
+        CanRecord {
+            timestamp,
+            interface: interface.to_owned(),
+            id: RecordId::Extended(frame.id().into()),
+            remote: false,
+            fd_flags: if frame.data().len() > 8 {
+                Some(0)
+            } else {
+                None
+            },
+            data: frame.data().to_vec(),
+        }
+    }
+
+    /// Converts this record into a Cyphal/CAN frame with the provided timestamp
+    ///
+    /// This function returns None if this record is not an extended data frame, or if its data
+    /// does not fit into a [`Frame`].
+    pub fn to_frame(&self, timestamp: Microseconds32) -> Option<Frame> {
+        match self.id {
+            RecordId::Extended(id) if !self.remote && self.data.len() <= FRAME_CAPACITY => {
+                let id = CanId::try_from(id).ok()?;
+                Some(Frame::new(timestamp, id, &self.data))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Formats this record as one line (without a line ending) of a candump log
+impl fmt::Display for CanRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}.{:06}) {} ",
+            self.timestamp.as_secs(),
+            self.timestamp.subsec_micros(),
+            self.interface
+        )?;
+        match self.id {
+            RecordId::Standard(id) => write!(f, "{:03X}", id)?,
+            RecordId::Extended(id) => write!(f, "{:08X}", id)?,
+            RecordId::Error(class) => write!(f, "{:08X}", class | ERROR_FLAG)?,
+        }
+        match self.fd_flags {
+            Some(flags) => write!(f, "##{:X}", flags & 0xf)?,
+            None => f.write_str("#")?,
+        }
+        if self.remote {
+            f.write_str("R")?;
+        }
+        for byte in &self.data {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses one line of a candump log
+///
+/// This function returns Ok(None) if the line is empty.
+pub fn parse_line(line: &str) -> Result<Option<CanRecord>, &'static str> {
+    let mut parts = line.split_whitespace();
+    let timestamp = match parts.next() {
+        Some(timestamp) => parse_timestamp(timestamp)?,
+        None => return Ok(None),
+    };
+    let interface = parts.next().ok_or("Missing interface name")?;
+    let frame = parts.next().ok_or("Missing frame")?;
+    // Anything after the frame (like the R/T direction flag from `candump -x`) is ignored
+
+    let (id_text, rest) = frame.split_once('#').ok_or("Missing # in frame")?;
+    let id = u32::from_str_radix(id_text, 16).map_err(|_| "Invalid frame ID")?;
+    let id = match id_text.len() {
+        3 if id <= STANDARD_ID_MAX => RecordId::Standard(id as u16),
+        8 if id & ERROR_FLAG != 0 => RecordId::Error(id & !ERROR_FLAG),
+        8 if id <= EXTENDED_ID_MAX => RecordId::Extended(id),
+        _ => return Err("Invalid frame ID"),
+    };
+
+    let (fd_flags, data_text) = match rest.strip_prefix('#') {
+        Some(fd_rest) => {
+            let mut chars = fd_rest.chars();
+            let flags = chars
+                .next()
+                .and_then(|flags| flags.to_digit(16))
+                .ok_or("Missing CAN FD flags")?;
+            (Some(flags as u8), chars.as_str())
+        }
+        None => (None, rest),
+    };
+    let (remote, data_text) = match data_text.strip_prefix('R') {
+        Some(_) if fd_flags.is_none() => (true, ""),
+        Some(_) => return Err("CAN FD frames cannot be remote frames"),
+        None => (false, data_text),
+    };
+    let data = parse_hex(data_text)?;
+    let data_max = if fd_flags.is_some() { 64 } else { 8 };
+    if data.len() > data_max {
+        return Err("Too much data for a frame");
+    }
+
+    Ok(Some(CanRecord {
+        timestamp,
+        interface: interface.to_owned(),
+        id,
+        remote,
+        fd_flags,
+        data,
+    }))
+}
+
+/// Parses a timestamp like `(1700000000.123456)`
+fn parse_timestamp(text: &str) -> Result<Duration, &'static str> {
+    const INVALID: &str = "Invalid timestamp";
+    let text = text
+        .strip_prefix('(')
+        .and_then(|text| text.strip_suffix(')'))
+        .ok_or(INVALID)?;
+    let (seconds, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let seconds: u64 = seconds.parse().map_err(|_| INVALID)?;
+    if fraction.len() > 9 || !fraction.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(INVALID);
+    }
+    let mut nanoseconds: u32 = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse().map_err(|_| INVALID)?
+    };
+    for _ in fraction.len()..9 {
+        nanoseconds *= 10;
+    }
+    Ok(Duration::new(seconds, nanoseconds))
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, &'static str> {
+    // Some tools separate bytes with dots
+    let digits: Vec<u8> = text.bytes().filter(|&c| c != b'.').collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("Odd number of hexadecimal digits");
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or("Invalid hexadecimal data")
+        })
+        .collect()
+}
+
+/// Reads frames from a candump log
+///
+/// This is an iterator that returns one record for each frame in the log.
+pub struct CandumpReader<R> {
+    input: R,
+    line_number: usize,
+    line: String,
+}
+
+impl<R: BufRead> CandumpReader<R> {
+    /// Creates a reader
+    pub fn new(input: R) -> Self {
+        CandumpReader {
+            input,
+            line_number: 0,
+            line: String::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CandumpReader<R> {
+    type Item = Result<CanRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.input.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(Error::Io(e))),
+            }
+            self.line_number += 1;
+            match parse_line(&self.line) {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => { /* Empty line, keep going */ }
+                Err(reason) => {
+                    return Some(Err(Error::Candump {
+                        line: self.line_number,
+                        reason,
+                    }))
+                }
+            }
+        }
+    }
+}
+
+/// Writes frames to a candump log
+pub struct CandumpWriter<W> {
+    output: W,
+}
+
+impl<W: Write> CandumpWriter<W> {
+    /// Creates a writer
+    pub fn new(output: W) -> Self {
+        CandumpWriter { output }
+    }
+
+    /// Writes a record as one line
+    pub fn write(&mut self, record: &CanRecord) -> Result<(), Error> {
+        writeln!(self.output, "{}", record)?;
+        Ok(())
+    }
+
+    /// Flushes the output
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Returns the output
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
@@ -0,0 +1,69 @@
+//!
+//! # Cyphal traffic captures
+//!
+//! This library reads and writes recordings of Cyphal traffic and replays them into the receivers
+//! from `canadensis_can` and `canadensis_udp`.
+//!
+//! Supported formats:
+//! * Log files from `candump -l` ([`candump`])
+//! * pcap and pcapng files with SocketCAN frames or IPv4 UDP packets ([`pcap`])
+//!
+//! The [`replay`] module has drivers that provide the frames or packets from a recording to a
+//! receiver, and a clock that follows the timestamps in the recording. They can be used with
+//! a `CoreNode` to run the same `TransferHandler`s that run on a real node, without any hardware.
+//!
+
+#![deny(missing_docs)]
+
+extern crate canadensis_can;
+extern crate canadensis_core;
+extern crate canadensis_udp;
+extern crate nb;
+
+pub mod candump;
+pub mod pcap;
+pub mod replay;
+
+use std::fmt;
+use std::io;
+
+/// An error that can occur when reading or writing a capture
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing the underlying file failed
+    Io(io::Error),
+    /// A line of a candump log could not be parsed
+    Candump {
+        /// The line number, starting at 1
+        line: usize,
+        /// A description of the problem
+        reason: &'static str,
+    },
+    /// A pcap or pcapng file has an invalid structure
+    Pcap(&'static str),
+}
+
+impl From<io::Error> for Error {
+    fn from(inner: io::Error) -> Self {
+        Error::Io(inner)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(inner) => write!(f, "{}", inner),
+            Error::Candump { line, reason } => write!(f, "candump log line {}: {}", line, reason),
+            Error::Pcap(reason) => write!(f, "Invalid pcap file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(inner) => Some(inner),
+            _ => None,
+        }
+    }
+}
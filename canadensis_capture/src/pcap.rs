@@ -0,0 +1,816 @@
+//!
+//! pcap and pcapng files
+//!
+//! [`PcapReader`] reads packets from both classic pcap files and pcapng files. The packets can
+//! be converted into CAN frames (for captures from SocketCAN interfaces) or UDP datagrams
+//! (for captures from Ethernet, Linux cooked, raw IP, or loopback interfaces).
+//!
+//! [`PcapWriter`] writes classic pcap files and [`PcapngWriter`] writes pcapng files.
+//!
+
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+
+use crate::candump::{CanRecord, RecordId};
+use crate::Error;
+
+/// BSD loopback encapsulation, with a 4-byte address family in the byte order of the
+/// capturing host
+pub const LINKTYPE_NULL: u32 = 0;
+/// Ethernet
+pub const LINKTYPE_ETHERNET: u32 = 1;
+/// Raw IPv4 or IPv6 packets
+pub const LINKTYPE_RAW: u32 = 101;
+/// Linux cooked capture, version 1 (used when capturing on the "any" interface)
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+/// SocketCAN CAN and CAN FD frames
+pub const LINKTYPE_CAN_SOCKETCAN: u32 = 227;
+/// Raw IPv4 packets
+pub const LINKTYPE_IPV4: u32 = 228;
+/// Linux cooked capture, version 2
+pub const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// Classic pcap magic number for microsecond timestamps
+const PCAP_MAGIC_MICROSECONDS: u32 = 0xa1b2_c3d4;
+/// Classic pcap magic number for nanosecond timestamps
+const PCAP_MAGIC_NANOSECONDS: u32 = 0xa1b2_3c4d;
+/// pcapng section header block type
+const BLOCK_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+/// pcapng interface description block type
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+/// pcapng simple packet block type
+const BLOCK_SIMPLE_PACKET: u32 = 0x0000_0003;
+/// pcapng enhanced packet block type
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+/// pcapng byte-order magic number
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+/// pcapng option code for the end of the options
+const OPTION_END: u16 = 0;
+/// pcapng interface option code for the interface name
+const OPTION_IF_NAME: u16 = 2;
+/// pcapng interface option code for the timestamp resolution
+const OPTION_IF_TSRESOL: u16 = 9;
+/// The largest pcapng block that this reader will accept
+const BLOCK_LENGTH_MAX: usize = 16 * 1024 * 1024;
+/// The maximum packet length written in file headers
+const SNAPLEN: u32 = 262_144;
+
+/// SocketCAN flag for extended frame IDs
+const SOCKETCAN_EFF_FLAG: u32 = 0x8000_0000;
+/// SocketCAN flag for remote frames
+const SOCKETCAN_RTR_FLAG: u32 = 0x4000_0000;
+/// SocketCAN flag for error frames
+const SOCKETCAN_ERR_FLAG: u32 = 0x2000_0000;
+/// SocketCAN CAN FD flag that marks a CAN FD frame
+const CANFD_FDF: u8 = 0x04;
+/// Size of a SocketCAN classic CAN frame
+const CAN_MTU: usize = 16;
+/// Size of a SocketCAN CAN FD frame
+const CANFD_MTU: usize = 72;
+/// SocketCAN frame header size
+const SOCKETCAN_HEADER_SIZE: usize = 8;
+
+/// Ethernet type for IPv4
+const ETHERTYPE_IPV4: u16 = 0x0800;
+/// Ethernet types for VLAN tags
+const ETHERTYPE_VLAN: [u16; 2] = [0x8100, 0x88a8];
+/// IP protocol number for UDP
+const IP_PROTOCOL_UDP: u8 = 17;
+
+/// A packet from a capture file
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Packet {
+    /// The time when the packet was captured, relative to the Unix epoch
+    pub timestamp: Duration,
+    /// The link-layer header type (one of the `LINKTYPE_` constants)
+    pub link_type: u32,
+    /// The index of the interface that captured this packet (always zero for classic pcap files)
+    pub interface: u32,
+    /// The packet data, starting with the link-layer header
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    /// Converts this packet into a CAN frame record
+    ///
+    /// This function returns None if the link type is not [`LINKTYPE_CAN_SOCKETCAN`] or the
+    /// packet is too short.
+    pub fn to_can_record(&self, interface: &str) -> Option<CanRecord> {
+        if self.link_type != LINKTYPE_CAN_SOCKETCAN || self.data.len() < SOCKETCAN_HEADER_SIZE {
+            return None;
+        }
+        let raw_id = u32::from_be_bytes(self.data[0..4].try_into().unwrap());
+        let flags = self.data[5];
+        let body = &self.data[SOCKETCAN_HEADER_SIZE..];
+        let length = usize::from(self.data[4]).min(body.len());
+        let id = if raw_id & SOCKETCAN_ERR_FLAG != 0 {
+            RecordId::Error(raw_id & 0x1fff_ffff)
+        } else if raw_id & SOCKETCAN_EFF_FLAG != 0 {
+            RecordId::Extended(raw_id & 0x1fff_ffff)
+        } else {
+            RecordId::Standard((raw_id & 0x7ff) as u16)
+        };
+        let fd = flags & CANFD_FDF != 0 || self.data.len() > CAN_MTU;
+        Some(CanRecord {
+            timestamp: self.timestamp,
+            interface: interface.to_owned(),
+            id,
+            remote: !fd && raw_id & SOCKETCAN_RTR_FLAG != 0,
+            fd_flags: if fd { Some(flags & !CANFD_FDF) } else { None },
+            data: body[..length].to_vec(),
+        })
+    }
+
+    /// Converts this packet into a UDP datagram
+    ///
+    /// This function returns None if the link type is not supported or the packet does not
+    /// contain an unfragmented IPv4 UDP datagram.
+    pub fn to_udp_datagram(&self) -> Option<UdpDatagram> {
+        let ip_packet = match self.link_type {
+            LINKTYPE_RAW | LINKTYPE_IPV4 => &self.data[..],
+            LINKTYPE_ETHERNET => {
+                let mut offset = 12;
+                let mut ethertype = read_u16_be(&self.data, offset)?;
+                while ETHERTYPE_VLAN.contains(&ethertype) {
+                    offset += 4;
+                    ethertype = read_u16_be(&self.data, offset)?;
+                }
+                if ethertype != ETHERTYPE_IPV4 {
+                    return None;
+                }
+                self.data.get(offset + 2..)?
+            }
+            LINKTYPE_LINUX_SLL => {
+                if read_u16_be(&self.data, 14)? != ETHERTYPE_IPV4 {
+                    return None;
+                }
+                self.data.get(16..)?
+            }
+            LINKTYPE_LINUX_SLL2 => {
+                if read_u16_be(&self.data, 0)? != ETHERTYPE_IPV4 {
+                    return None;
+                }
+                self.data.get(20..)?
+            }
+            LINKTYPE_NULL => {
+                // AF_INET is 2 on all platforms
+                let family = self.data.get(0..4)?;
+                if family != [2, 0, 0, 0] && family != [0, 0, 0, 2] {
+                    return None;
+                }
+                &self.data[4..]
+            }
+            _ => return None,
+        };
+        parse_ipv4_udp(ip_packet).map(|(source, destination, payload)| UdpDatagram {
+            timestamp: self.timestamp,
+            source,
+            destination,
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// A UDP datagram from a capture
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UdpDatagram {
+    /// The time when the datagram was captured
+    pub timestamp: Duration,
+    /// The source address and port
+    pub source: SocketAddrV4,
+    /// The destination address and port
+    pub destination: SocketAddrV4,
+    /// The UDP payload
+    pub payload: Vec<u8>,
+}
+
+impl UdpDatagram {
+    /// Encodes this datagram as an IPv4 packet, for a capture with link type
+    /// [`LINKTYPE_IPV4`] or [`LINKTYPE_RAW`]
+    ///
+    /// The UDP checksum is set to zero, which means that it is not used.
+    pub fn to_ipv4_packet(&self) -> Vec<u8> {
+        let udp_length = 8 + self.payload.len();
+        let total_length = 20 + udp_length;
+        let mut packet = Vec::with_capacity(total_length);
+        // IPv4 header: version 4, 5 words, don't fragment, TTL 16
+        packet.extend_from_slice(&[0x45, 0x00]);
+        packet.extend_from_slice(&(total_length as u16).to_be_bytes());
+        packet.extend_from_slice(&[0x00, 0x00, 0x40, 0x00, 16, IP_PROTOCOL_UDP, 0x00, 0x00]);
+        packet.extend_from_slice(&self.source.ip().octets());
+        packet.extend_from_slice(&self.destination.ip().octets());
+        let checksum = ipv4_checksum(&packet);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        // UDP header
+        packet.extend_from_slice(&self.source.port().to_be_bytes());
+        packet.extend_from_slice(&self.destination.port().to_be_bytes());
+        packet.extend_from_slice(&(udp_length as u16).to_be_bytes());
+        packet.extend_from_slice(&[0x00, 0x00]);
+        packet.extend_from_slice(&self.payload);
+        packet
+    }
+}
+
+/// Encodes a frame in the format used for captures with link type [`LINKTYPE_CAN_SOCKETCAN`]
+pub fn encode_socketcan(record: &CanRecord) -> Vec<u8> {
+    let raw_id = match record.id {
+        RecordId::Standard(id) => u32::from(id),
+        RecordId::Extended(id) => id | SOCKETCAN_EFF_FLAG,
+        RecordId::Error(class) => class | SOCKETCAN_ERR_FLAG,
+    };
+    let raw_id = if record.remote {
+        raw_id | SOCKETCAN_RTR_FLAG
+    } else {
+        raw_id
+    };
+    let (size, flags) = match record.fd_flags {
+        Some(flags) => (CANFD_MTU, flags | CANFD_FDF),
+        None => (CAN_MTU, 0),
+    };
+    let mut encoded = vec![0u8; size];
+    encoded[0..4].copy_from_slice(&raw_id.to_be_bytes());
+    let length = record.data.len().min(size - SOCKETCAN_HEADER_SIZE);
+    encoded[4] = length as u8;
+    encoded[5] = flags;
+    encoded[SOCKETCAN_HEADER_SIZE..][..length].copy_from_slice(&record.data[..length]);
+    encoded
+}
+
+fn parse_ipv4_udp(packet: &[u8]) -> Option<(SocketAddrV4, SocketAddrV4, &[u8])> {
+    let first = *packet.first()?;
+    if first >> 4 != 4 {
+        return None;
+    }
+    let header_length = usize::from(first & 0xf) * 4;
+    let total_length = usize::from(read_u16_be(packet, 2)?);
+    if header_length < 20 || total_length < header_length || packet.len() < total_length {
+        return None;
+    }
+    // Fragments can't be handled without reassembly
+    if read_u16_be(packet, 6)? & 0x3fff != 0 || packet[9] != IP_PROTOCOL_UDP {
+        return None;
+    }
+    let source_ip = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let destination_ip = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+    let udp = &packet[header_length..total_length];
+    let udp_length = usize::from(read_u16_be(udp, 4)?);
+    if udp_length < 8 || udp_length > udp.len() {
+        return None;
+    }
+    Some((
+        SocketAddrV4::new(source_ip, read_u16_be(udp, 0)?),
+        SocketAddrV4::new(destination_ip, read_u16_be(udp, 2)?),
+        &udp[8..udp_length],
+    ))
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], pair[1]])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn read_u16_be(bytes: &[u8], offset: usize) -> Option<u16> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Byte order of a capture file
+#[derive(Debug, Copy, Clone)]
+struct ByteOrder {
+    big_endian: bool,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8], offset: usize) -> u16 {
+        let bytes: [u8; 2] = bytes[offset..offset + 2].try_into().unwrap();
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+    fn u32(self, bytes: &[u8], offset: usize) -> u32 {
+        let bytes: [u8; 4] = bytes[offset..offset + 4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+/// An interface described in a pcapng file
+#[derive(Debug)]
+struct Interface {
+    link_type: u32,
+    name: Option<String>,
+    /// Timestamp units per second
+    units_per_second: u64,
+}
+
+enum Format {
+    Pcap {
+        order: ByteOrder,
+        nanoseconds: bool,
+        link_type: u32,
+    },
+    Pcapng {
+        order: ByteOrder,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// Reads packets from a pcap or pcapng file
+///
+/// This is an iterator that returns each packet in the file.
+pub struct PcapReader<R> {
+    input: R,
+    format: Format,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Creates a reader and reads the file header
+    ///
+    /// The file format (pcap or pcapng) is detected automatically.
+    pub fn new(mut input: R) -> Result<Self, Error> {
+        let mut start = [0u8; 4];
+        input.read_exact(&mut start)?;
+        let magic_le = u32::from_le_bytes(start);
+        let magic_be = u32::from_be_bytes(start);
+        if magic_le == BLOCK_SECTION_HEADER {
+            let mut reader = PcapReader {
+                input,
+                format: Format::Pcapng {
+                    order: ByteOrder { big_endian: false },
+                    interfaces: Vec::new(),
+                },
+            };
+            reader.read_section_header()?;
+            return Ok(reader);
+        }
+        let (order, nanoseconds) = match (magic_le, magic_be) {
+            (PCAP_MAGIC_MICROSECONDS, _) => (ByteOrder { big_endian: false }, false),
+            (PCAP_MAGIC_NANOSECONDS, _) => (ByteOrder { big_endian: false }, true),
+            (_, PCAP_MAGIC_MICROSECONDS) => (ByteOrder { big_endian: true }, false),
+            (_, PCAP_MAGIC_NANOSECONDS) => (ByteOrder { big_endian: true }, true),
+            _ => return Err(Error::Pcap("Unknown file format")),
+        };
+        let mut header = [0u8; 20];
+        input.read_exact(&mut header)?;
+        // The upper bits of the link type field have other information
+        let link_type = order.u32(&header, 16) & 0xffff;
+        Ok(PcapReader {
+            input,
+            format: Format::Pcap {
+                order,
+                nanoseconds,
+                link_type,
+            },
+        })
+    }
+
+    /// Returns the name of an interface, if the file has one
+    ///
+    /// Only pcapng files have interface names.
+    pub fn interface_name(&self, interface: u32) -> Option<&str> {
+        match &self.format {
+            Format::Pcap { .. } => None,
+            Format::Pcapng { interfaces, .. } => interfaces
+                .get(interface as usize)
+                .and_then(|interface| interface.name.as_deref()),
+        }
+    }
+
+    /// Converts this reader into an iterator over the CAN frames in the file
+    ///
+    /// Packets with other link types are skipped. If the file does not have an interface name,
+    /// the records have interface names like `can0`, based on the interface index.
+    pub fn can_records(self) -> CanRecords<R> {
+        CanRecords(self)
+    }
+
+    /// Converts this reader into an iterator over the UDP datagrams in the file
+    ///
+    /// Packets that are not unfragmented IPv4 UDP datagrams are skipped.
+    pub fn udp_datagrams(self) -> UdpDatagrams<R> {
+        UdpDatagrams(self)
+    }
+
+    /// Reads the rest of a section header block after the block type
+    fn read_section_header(&mut self) -> Result<(), Error> {
+        let mut start = [0u8; 8];
+        self.input.read_exact(&mut start)?;
+        let order = if u32::from_le_bytes(start[4..8].try_into().unwrap()) == BYTE_ORDER_MAGIC {
+            ByteOrder { big_endian: false }
+        } else if u32::from_be_bytes(start[4..8].try_into().unwrap()) == BYTE_ORDER_MAGIC {
+            ByteOrder { big_endian: true }
+        } else {
+            return Err(Error::Pcap("Invalid byte-order magic"));
+        };
+        let length = block_length(order.u32(&start, 0))?;
+        // Skip the rest of the block (version, section length, options, and length)
+        let mut rest = vec![0u8; length - 12];
+        self.input.read_exact(&mut rest)?;
+        self.format = Format::Pcapng {
+            order,
+            interfaces: Vec::new(),
+        };
+        Ok(())
+    }
+
+    fn read_packet(&mut self) -> Result<Option<Packet>, Error> {
+        match &mut self.format {
+            Format::Pcap {
+                order,
+                nanoseconds,
+                link_type,
+            } => {
+                let mut header = [0u8; 16];
+                if !read_exact_or_eof(&mut self.input, &mut header)? {
+                    return Ok(None);
+                }
+                let seconds = order.u32(&header, 0);
+                let fraction = order.u32(&header, 4);
+                let length = order.u32(&header, 8) as usize;
+                if length > BLOCK_LENGTH_MAX {
+                    return Err(Error::Pcap("Packet too long"));
+                }
+                let mut data = vec![0u8; length];
+                self.input.read_exact(&mut data)?;
+                let nanoseconds = if *nanoseconds {
+                    fraction
+                } else {
+                    fraction.saturating_mul(1000)
+                };
+                Ok(Some(Packet {
+                    timestamp: Duration::new(u64::from(seconds), nanoseconds.min(999_999_999)),
+                    link_type: *link_type,
+                    interface: 0,
+                    data,
+                }))
+            }
+            Format::Pcapng { .. } => self.read_pcapng_packet(),
+        }
+    }
+
+    fn read_pcapng_packet(&mut self) -> Result<Option<Packet>, Error> {
+        loop {
+            let order = match &self.format {
+                Format::Pcapng { order, .. } => *order,
+                Format::Pcap { .. } => unreachable!("Not a pcapng file"),
+            };
+            let mut block_type = [0u8; 4];
+            if !read_exact_or_eof(&mut self.input, &mut block_type)? {
+                return Ok(None);
+            }
+            let block_type = order.u32(&block_type, 0);
+            if block_type == BLOCK_SECTION_HEADER {
+                // A new section, which may have a different byte order
+                self.read_section_header()?;
+                continue;
+            }
+            let mut length = [0u8; 4];
+            self.input.read_exact(&mut length)?;
+            let length = block_length(order.u32(&length, 0))?;
+            // Read the body and the trailing length
+            let mut body = vec![0u8; length - 8];
+            self.input.read_exact(&mut body)?;
+            let body = &body[..body.len() - 4];
+
+            let interfaces = match &mut self.format {
+                Format::Pcapng { interfaces, .. } => interfaces,
+                Format::Pcap { .. } => unreachable!("Not a pcapng file"),
+            };
+            match block_type {
+                BLOCK_INTERFACE_DESCRIPTION => {
+                    interfaces.push(parse_interface(order, body)?);
+                }
+                BLOCK_ENHANCED_PACKET => {
+                    if body.len() < 20 {
+                        return Err(Error::Pcap("Enhanced packet block too short"));
+                    }
+                    let interface_index = order.u32(body, 0);
+                    let interface = interfaces
+                        .get(interface_index as usize)
+                        .ok_or(Error::Pcap("Packet refers to an unknown interface"))?;
+                    let timestamp =
+                        u64::from(order.u32(body, 4)) << 32 | u64::from(order.u32(body, 8));
+                    let captured_length = order.u32(body, 12) as usize;
+                    let data = body
+                        .get(20..20 + captured_length)
+                        .ok_or(Error::Pcap("Packet data too long"))?;
+                    return Ok(Some(Packet {
+                        timestamp: timestamp_to_duration(timestamp, interface.units_per_second),
+                        link_type: interface.link_type,
+                        interface: interface_index,
+                        data: data.to_vec(),
+                    }));
+                }
+                BLOCK_SIMPLE_PACKET => {
+                    let interface = interfaces
+                        .first()
+                        .ok_or(Error::Pcap("Packet refers to an unknown interface"))?;
+                    if body.len() < 4 {
+                        return Err(Error::Pcap("Simple packet block too short"));
+                    }
+                    let original_length = order.u32(body, 0) as usize;
+                    let data = &body[4..];
+                    // Simple packet blocks do not have timestamps
+                    return Ok(Some(Packet {
+                        timestamp: Duration::from_secs(0),
+                        link_type: interface.link_type,
+                        interface: 0,
+                        data: data[..original_length.min(data.len())].to_vec(),
+                    }));
+                }
+                _ => { /* Ignore other blocks */ }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<Packet, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_packet().transpose()
+    }
+}
+
+/// An iterator over the CAN frames in a capture
+///
+/// This is returned by [`PcapReader::can_records`].
+pub struct CanRecords<R>(PcapReader<R>);
+
+impl<R: Read> Iterator for CanRecords<R> {
+    type Item = Result<CanRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let packet = match self.0.next()? {
+                Ok(packet) => packet,
+                Err(e) => return Some(Err(e)),
+            };
+            let interface = match self.0.interface_name(packet.interface) {
+                Some(name) => name.to_owned(),
+                None => format!("can{}", packet.interface),
+            };
+            if let Some(record) = packet.to_can_record(&interface) {
+                return Some(Ok(record));
+            }
+        }
+    }
+}
+
+/// An iterator over the UDP datagrams in a capture
+///
+/// This is returned by [`PcapReader::udp_datagrams`].
+pub struct UdpDatagrams<R>(PcapReader<R>);
+
+impl<R: Read> Iterator for UdpDatagrams<R> {
+    type Item = Result<UdpDatagram, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.0.next()? {
+                Ok(packet) => {
+                    if let Some(datagram) = packet.to_udp_datagram() {
+                        return Some(Ok(datagram));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+fn parse_interface(order: ByteOrder, body: &[u8]) -> Result<Interface, Error> {
+    if body.len() < 8 {
+        return Err(Error::Pcap("Interface description block too short"));
+    }
+    let mut interface = Interface {
+        link_type: u32::from(order.u16(body, 0)),
+        name: None,
+        units_per_second: 1_000_000,
+    };
+    let mut options = &body[8..];
+    while options.len() >= 4 {
+        let code = order.u16(options, 0);
+        let length = usize::from(order.u16(options, 2));
+        let value = options
+            .get(4..4 + length)
+            .ok_or(Error::Pcap("Option too long"))?;
+        match code {
+            OPTION_END => break,
+            OPTION_IF_NAME => {
+                interface.name = Some(String::from_utf8_lossy(value).into_owned());
+            }
+            OPTION_IF_TSRESOL => {
+                let resolution = *value.first().ok_or(Error::Pcap("Empty if_tsresol"))?;
+                let exponent = u32::from(resolution & 0x7f);
+                let units_per_second = if resolution & 0x80 == 0 {
+                    10u64.checked_pow(exponent)
+                } else {
+                    2u64.checked_pow(exponent)
+                };
+                interface.units_per_second = units_per_second
+                    .filter(|&units| units != 0)
+                    .ok_or(Error::Pcap("Invalid if_tsresol"))?;
+            }
+            _ => {}
+        }
+        // Values are padded to 4 bytes
+        let padded_length = (length + 3) & !3;
+        options = options.get(4 + padded_length..).unwrap_or(&[]);
+    }
+    Ok(interface)
+}
+
+fn timestamp_to_duration(timestamp: u64, units_per_second: u64) -> Duration {
+    let seconds = timestamp / units_per_second;
+    let fraction = timestamp % units_per_second;
+    let nanoseconds = u128::from(fraction) * 1_000_000_000 / u128::from(units_per_second);
+    Duration::new(seconds, nanoseconds as u32)
+}
+
+/// Checks that a pcapng block length is valid and converts it to a usize
+fn block_length(length: u32) -> Result<usize, Error> {
+    let length = length as usize;
+    if length < 12 || !length.is_multiple_of(4) || length > BLOCK_LENGTH_MAX {
+        Err(Error::Pcap("Invalid block length"))
+    } else {
+        Ok(length)
+    }
+}
+
+/// Fills a buffer, or returns false if the input ended before any bytes were read
+fn read_exact_or_eof<R: Read>(input: &mut R, buffer: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Writes packets to a classic pcap file with microsecond timestamps
+///
+/// All packets in a pcap file have the same link type.
+pub struct PcapWriter<W> {
+    output: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Creates a writer and writes the file header
+    pub fn new(mut output: W, link_type: u32) -> Result<Self, Error> {
+        output.write_all(&PCAP_MAGIC_MICROSECONDS.to_le_bytes())?;
+        // Version 2.4
+        output.write_all(&2u16.to_le_bytes())?;
+        output.write_all(&4u16.to_le_bytes())?;
+        // Time zone and timestamp accuracy (unused)
+        output.write_all(&[0; 8])?;
+        output.write_all(&SNAPLEN.to_le_bytes())?;
+        output.write_all(&link_type.to_le_bytes())?;
+        Ok(PcapWriter { output })
+    }
+
+    /// Writes a packet
+    ///
+    /// The data must start with the link-layer header for the link type of this file.
+    pub fn write_packet(&mut self, timestamp: Duration, data: &[u8]) -> Result<(), Error> {
+        let length = data.len() as u32;
+        self.output
+            .write_all(&(timestamp.as_secs() as u32).to_le_bytes())?;
+        self.output
+            .write_all(&timestamp.subsec_micros().to_le_bytes())?;
+        self.output.write_all(&length.to_le_bytes())?;
+        self.output.write_all(&length.to_le_bytes())?;
+        self.output.write_all(data)?;
+        Ok(())
+    }
+
+    /// Flushes the output
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Returns the output
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+/// Writes packets to a pcapng file with microsecond timestamps
+///
+/// A pcapng file can have several interfaces with different link types.
+pub struct PcapngWriter<W> {
+    output: W,
+    interfaces: u32,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Creates a writer and writes the section header
+    pub fn new(output: W) -> Result<Self, Error> {
+        let mut writer = PcapngWriter {
+            output,
+            interfaces: 0,
+        };
+        let mut body = Vec::with_capacity(16);
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        // Version 1.0
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // Section length not specified
+        body.extend_from_slice(&u64::MAX.to_le_bytes());
+        writer.write_block(BLOCK_SECTION_HEADER, &body)?;
+        Ok(writer)
+    }
+
+    /// Adds an interface and returns its index
+    pub fn add_interface(&mut self, link_type: u32, name: Option<&str>) -> Result<u32, Error> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(link_type as u16).to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&SNAPLEN.to_le_bytes());
+        if let Some(name) = name {
+            body.extend_from_slice(&OPTION_IF_NAME.to_le_bytes());
+            body.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            body.extend_from_slice(name.as_bytes());
+            pad(&mut body);
+            body.extend_from_slice(&OPTION_END.to_le_bytes());
+            body.extend_from_slice(&0u16.to_le_bytes());
+        }
+        self.write_block(BLOCK_INTERFACE_DESCRIPTION, &body)?;
+        let index = self.interfaces;
+        self.interfaces += 1;
+        Ok(index)
+    }
+
+    /// Writes a packet captured on an interface
+    ///
+    /// The data must start with the link-layer header for the link type of the interface.
+    pub fn write_packet(
+        &mut self,
+        interface: u32,
+        timestamp: Duration,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        if interface >= self.interfaces {
+            return Err(Error::Pcap("Unknown interface"));
+        }
+        let microseconds = timestamp.as_micros() as u64;
+        let length = data.len() as u32;
+        let mut body = Vec::with_capacity(20 + data.len() + 3);
+        body.extend_from_slice(&interface.to_le_bytes());
+        body.extend_from_slice(&((microseconds >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(microseconds as u32).to_le_bytes());
+        body.extend_from_slice(&length.to_le_bytes());
+        body.extend_from_slice(&length.to_le_bytes());
+        body.extend_from_slice(data);
+        pad(&mut body);
+        self.write_block(BLOCK_ENHANCED_PACKET, &body)
+    }
+
+    /// Flushes the output
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Returns the output
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    /// Writes a block with a body that has a length that is a multiple of 4
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> Result<(), Error> {
+        let length = (body.len() + 12) as u32;
+        self.output.write_all(&block_type.to_le_bytes())?;
+        self.output.write_all(&length.to_le_bytes())?;
+        self.output.write_all(body)?;
+        self.output.write_all(&length.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Adds zero bytes to the end of a block body to make its length a multiple of 4
+fn pad(body: &mut Vec<u8>) {
+    while !body.len().is_multiple_of(4) {
+        body.push(0);
+    }
+}
@@ -0,0 +1,294 @@
+//!
+//! Drivers that replay recorded frames and packets into receivers
+//!
+//! A [`CanReplay`] is a CAN driver that can be used with a `CanReceiver`, and a [`UdpReplay`]
+//! is a socket that can be used with a `UdpReceiver`. Both also accept outgoing frames or
+//! packets and keep them, so a complete `CoreNode` can run on a recording and its responses can
+//! be checked.
+//!
+//! The timestamps of replayed frames and the time from [`ReplayClock`] start at zero at the
+//! first frame in the recording and follow the recorded timestamps after that. Frames must be
+//! replayed with the clock from the driver that provides them.
+//!
+
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::rc::Rc;
+use std::time::Duration;
+
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanNodeId, Frame};
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{Clock, Microseconds32};
+use canadensis_core::OutOfMemoryError;
+use canadensis_udp::driver::UdpSocket;
+
+use crate::candump::CanRecord;
+use crate::pcap::UdpDatagram;
+use crate::Error;
+
+/// A clock that returns the time of the most recently replayed frame or packet
+///
+/// Clones of a clock share the same time.
+#[derive(Debug, Clone)]
+pub struct ReplayClock(Rc<Cell<Microseconds32>>);
+
+impl Default for ReplayClock {
+    fn default() -> Self {
+        ReplayClock(Rc::new(Cell::new(Microseconds32::from_ticks(0))))
+    }
+}
+
+impl Clock for ReplayClock {
+    fn now(&mut self) -> Microseconds32 {
+        self.0.get()
+    }
+}
+
+/// Converts recorded timestamps into times relative to the first recorded frame
+#[derive(Debug, Default)]
+struct Timeline {
+    clock: ReplayClock,
+    start: Option<Duration>,
+    current: Duration,
+}
+
+impl Timeline {
+    /// Sets the clock to a recorded timestamp and returns the new time
+    fn advance(&mut self, timestamp: Duration) -> Microseconds32 {
+        let start = *self.start.get_or_insert(timestamp);
+        self.current = timestamp;
+        // Time wraps around after about 71 minutes, like any other Microseconds32 clock
+        let time = Microseconds32::from_ticks(timestamp.saturating_sub(start).as_micros() as u32);
+        self.clock.0.set(time);
+        time
+    }
+}
+
+/// A CAN driver that receives frames from a recording and keeps transmitted frames
+///
+/// Records that are not Cyphal/CAN frames (standard-ID, remote, and error frames, and frames
+/// that are too long for [`Frame`]) are skipped.
+///
+/// When all records have been replayed, [`ReceiveDriver::receive`] returns
+/// `Err(nb::Error::WouldBlock)` and [`CanReplay::is_finished`] returns true.
+pub struct CanReplay<I> {
+    records: I,
+    timeline: Timeline,
+    finished: bool,
+    transmitted: Vec<Frame>,
+}
+
+impl<I> CanReplay<I>
+where
+    I: Iterator<Item = Result<CanRecord, Error>>,
+{
+    /// Creates a driver that replays records from an iterator
+    ///
+    /// [`CandumpReader`](crate::candump::CandumpReader) and
+    /// [`CanRecords`](crate::pcap::CanRecords) provide suitable iterators.
+    pub fn new(records: I) -> Self {
+        CanReplay {
+            records,
+            timeline: Timeline::default(),
+            finished: false,
+            transmitted: Vec::new(),
+        }
+    }
+
+    /// Returns a clock that follows the timestamps of the replayed frames
+    pub fn clock(&self) -> ReplayClock {
+        self.timeline.clock.clone()
+    }
+
+    /// Returns true if all records have been replayed
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns the frames that have been transmitted through this driver
+    pub fn transmitted(&self) -> &[Frame] {
+        &self.transmitted
+    }
+
+    /// Removes and returns the frames that have been transmitted through this driver
+    pub fn take_transmitted(&mut self) -> Vec<Frame> {
+        std::mem::take(&mut self.transmitted)
+    }
+}
+
+impl<I> ReceiveDriver<ReplayClock> for CanReplay<I>
+where
+    I: Iterator<Item = Result<CanRecord, Error>>,
+{
+    type Error = Error;
+
+    fn receive(&mut self, _clock: &mut ReplayClock) -> nb::Result<Frame, Self::Error> {
+        loop {
+            match self.records.next() {
+                Some(Ok(record)) => {
+                    let time = self.timeline.advance(record.timestamp);
+                    if let Some(frame) = record.to_frame(time) {
+                        return Ok(frame);
+                    }
+                }
+                Some(Err(e)) => return Err(nb::Error::Other(e)),
+                None => {
+                    self.finished = true;
+                    return Err(nb::Error::WouldBlock);
+                }
+            }
+        }
+    }
+
+    fn apply_filters<S>(&mut self, _local_node: Option<CanNodeId>, _subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+        // The receiver ignores frames that do not match its subscriptions
+    }
+
+    fn apply_accept_all(&mut self) {}
+}
+
+impl<I> TransmitDriver<ReplayClock> for CanReplay<I> {
+    type Error = Infallible;
+
+    fn try_reserve(&mut self, frames: usize) -> Result<(), OutOfMemoryError> {
+        self.transmitted
+            .try_reserve(frames)
+            .map_err(|_| OutOfMemoryError)
+    }
+
+    fn transmit(
+        &mut self,
+        frame: Frame,
+        _clock: &mut ReplayClock,
+    ) -> nb::Result<Option<Frame>, Self::Error> {
+        self.transmitted.push(frame);
+        Ok(None)
+    }
+
+    fn flush(&mut self, _clock: &mut ReplayClock) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A UDP socket that receives packets from a recording and keeps sent packets
+///
+/// Only datagrams sent to the port that the socket is bound to are received. The socket does
+/// not check the destination addresses, so it receives packets sent to all multicast groups.
+///
+/// When all datagrams have been replayed, [`UdpSocket::recv`] returns
+/// `Err(nb::Error::WouldBlock)` and [`UdpReplay::is_finished`] returns true.
+pub struct UdpReplay<I> {
+    datagrams: I,
+    port: u16,
+    timeline: Timeline,
+    finished: bool,
+    sent: Vec<UdpDatagram>,
+}
+
+impl<I> UdpReplay<I>
+where
+    I: Iterator<Item = Result<UdpDatagram, Error>>,
+{
+    /// Creates a socket that replays datagrams from an iterator
+    ///
+    /// `port` is the local port that the socket acts like it is bound to, usually
+    /// `canadensis_udp::DEFAULT_PORT`.
+    ///
+    /// [`UdpDatagrams`](crate::pcap::UdpDatagrams) provides a suitable iterator.
+    pub fn new(datagrams: I, port: u16) -> Self {
+        UdpReplay {
+            datagrams,
+            port,
+            timeline: Timeline::default(),
+            finished: false,
+            sent: Vec::new(),
+        }
+    }
+
+    /// Returns a clock that follows the timestamps of the replayed datagrams
+    pub fn clock(&self) -> ReplayClock {
+        self.timeline.clock.clone()
+    }
+
+    /// Returns true if all datagrams have been replayed
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns the datagrams that have been sent through this socket
+    ///
+    /// The timestamp of each datagram is the timestamp of the replayed datagram that was most
+    /// recently received when it was sent.
+    pub fn sent(&self) -> &[UdpDatagram] {
+        &self.sent
+    }
+
+    /// Removes and returns the datagrams that have been sent through this socket
+    pub fn take_sent(&mut self) -> Vec<UdpDatagram> {
+        std::mem::take(&mut self.sent)
+    }
+}
+
+impl<I> UdpSocket for UdpReplay<I>
+where
+    I: Iterator<Item = Result<UdpDatagram, Error>>,
+{
+    type Error = Error;
+
+    fn local_addr(&self) -> Result<SocketAddrV4, Self::Error> {
+        Ok(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, self.port))
+    }
+
+    fn join_multicast_v4(
+        &mut self,
+        _multiaddr: &Ipv4Addr,
+        _interface: &Ipv4Addr,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn leave_multicast_v4(
+        &mut self,
+        _multiaddr: &Ipv4Addr,
+        _interface: &Ipv4Addr,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn send_to(&mut self, data: &[u8], destination: SocketAddrV4) -> Result<usize, Self::Error> {
+        self.sent.push(UdpDatagram {
+            timestamp: self.timeline.current,
+            source: SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, self.port),
+            destination,
+            payload: data.to_vec(),
+        });
+        Ok(data.len())
+    }
+
+    fn recv(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+        loop {
+            match self.datagrams.next() {
+                Some(Ok(datagram)) => {
+                    if datagram.destination.port() != self.port {
+                        continue;
+                    }
+                    self.timeline.advance(datagram.timestamp);
+                    // Like a real socket, discard any bytes that do not fit into the buffer
+                    let length = datagram.payload.len().min(buffer.len());
+                    buffer[..length].copy_from_slice(&datagram.payload[..length]);
+                    return Ok(length);
+                }
+                Some(Err(e)) => return Err(nb::Error::Other(e)),
+                None => {
+                    self.finished = true;
+                    return Err(nb::Error::WouldBlock);
+                }
+            }
+        }
+    }
+}
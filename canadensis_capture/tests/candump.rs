@@ -0,0 +1,202 @@
+//!
+//! Tests reading and writing candump logs and replaying them into a node
+//!
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_capture;
+extern crate canadensis_core;
+
+use std::convert::TryFrom;
+use std::io::Cursor;
+use std::time::Duration;
+
+use canadensis::node::CoreNode;
+use canadensis::requester::TransferIdFixedMap;
+use canadensis::{Node, TransferHandler};
+use canadensis_can::{CanNodeId, CanReceiver, CanTransferId, CanTransmitter, CanTransport, Mtu};
+use canadensis_capture::candump::{parse_line, CanRecord, CandumpReader, CandumpWriter, RecordId};
+use canadensis_capture::replay::{CanReplay, ReplayClock};
+use canadensis_capture::Error;
+use canadensis_core::time::{milliseconds, Clock, Microseconds32};
+use canadensis_core::transfer::{Header, MessageHeader, MessageTransfer, Transfer};
+use canadensis_core::transport::Transmitter;
+use canadensis_core::{Priority, SubjectId};
+
+#[test]
+fn parse_lines() {
+    let record = parse_line("(1700000000.123456) can0 107D552A#00000000047868E0\n")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        CanRecord {
+            timestamp: Duration::new(1_700_000_000, 123_456_000),
+            interface: "can0".into(),
+            id: RecordId::Extended(0x107d552a),
+            remote: false,
+            fd_flags: None,
+            data: vec![0x00, 0x00, 0x00, 0x00, 0x04, 0x78, 0x68, 0xe0],
+        },
+        record
+    );
+
+    let record = parse_line("(0.5) vcan1 123#R").unwrap().unwrap();
+    assert_eq!(RecordId::Standard(0x123), record.id);
+    assert!(record.remote);
+    assert_eq!(Duration::from_millis(500), record.timestamp);
+
+    let record = parse_line("(1.000001) can0 10606A2B##1112233445566778899AABB R")
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(1), record.fd_flags);
+    assert_eq!(
+        vec![0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb],
+        record.data
+    );
+
+    let record = parse_line("(1.0) can0 20000080#0000000000000000")
+        .unwrap()
+        .unwrap();
+    assert_eq!(RecordId::Error(0x80), record.id);
+
+    assert_eq!(None, parse_line("  \n").unwrap());
+    assert!(parse_line("(1.0) can0").is_err());
+    assert!(parse_line("(1.0) can0 12345#00").is_err());
+    assert!(parse_line("(1.0) can0 123#0").is_err());
+    assert!(parse_line("(1.0) can0 123#000000000000000000").is_err());
+}
+
+#[test]
+fn format_lines() {
+    for line in [
+        "(1700000000.123456) can0 107D552A#00000000047868E0",
+        "(0.500000) vcan1 123#R",
+        "(1.000001) can0 10606A2B##1112233445566778899AABB",
+        "(1.000000) can0 20000080#0000000000000000",
+        "(2.000000) can0 7FF#",
+    ]
+    .iter()
+    {
+        let record = parse_line(line).unwrap().unwrap();
+        assert_eq!(*line, record.to_string());
+    }
+}
+
+#[test]
+fn reader_reports_line_numbers() {
+    let log = "(1.0) can0 123#00\n\n(2.0) can0 123#0Z\n";
+    let results: Vec<Result<CanRecord, Error>> = CandumpReader::new(Cursor::new(log)).collect();
+    assert_eq!(2, results.len());
+    assert!(results[0].is_ok());
+    match &results[1] {
+        Err(Error::Candump { line, .. }) => assert_eq!(3, *line),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+type ReplayNode<I> = CoreNode<
+    ReplayClock,
+    CanTransmitter<ReplayClock, CanReplay<I>>,
+    CanReceiver<ReplayClock, CanReplay<I>>,
+    TransferIdFixedMap<CanTransport, 4>,
+    CanReplay<I>,
+    4,
+    4,
+>;
+
+/// Records all received messages
+#[derive(Default)]
+struct MessageCollector {
+    messages: Vec<(Microseconds32, Option<CanNodeId>, Vec<u8>)>,
+}
+
+impl TransferHandler<CanTransport> for MessageCollector {
+    fn handle_message<N: Node<Transport = CanTransport>>(
+        &mut self,
+        _node: &mut N,
+        transfer: &MessageTransfer<Vec<u8>, CanTransport>,
+    ) -> bool {
+        self.messages.push((
+            transfer.header.timestamp,
+            transfer.header.source,
+            transfer.payload.clone(),
+        ));
+        true
+    }
+}
+
+#[test]
+fn replay_into_node() {
+    let subject = SubjectId::try_from(100).unwrap();
+    let source = CanNodeId::try_from(42u8).unwrap();
+    let payload: Vec<u8> = (0..20).collect();
+
+    // Generate the frames of a multi-frame transfer
+    let mut driver = CanReplay::new(std::iter::empty());
+    let mut clock = driver.clock();
+    let mut transmitter = CanTransmitter::new(Mtu::Can8);
+    transmitter
+        .push(
+            Transfer {
+                header: Header::Message(MessageHeader {
+                    timestamp: Microseconds32::from_ticks(0),
+                    transfer_id: CanTransferId::try_from(3).unwrap(),
+                    priority: Priority::Nominal,
+                    subject,
+                    source: Some(source),
+                }),
+                loopback: false,
+                payload: &payload,
+            },
+            &mut clock,
+            &mut driver,
+        )
+        .unwrap();
+    let frames = driver.take_transmitted();
+    assert_eq!(4, frames.len());
+
+    // Write a log with a frame with a standard ID between the Cyphal frames
+    let mut writer = CandumpWriter::new(Vec::new());
+    let start = Duration::new(1_700_000_000, 0);
+    for (i, frame) in frames.iter().enumerate() {
+        let timestamp = start + Duration::from_millis(10 * i as u64);
+        writer
+            .write(&CanRecord::from_frame(timestamp, "can0", frame))
+            .unwrap();
+        writer
+            .write(&CanRecord {
+                timestamp,
+                interface: "can0".into(),
+                id: RecordId::Standard(0x7ff),
+                remote: false,
+                fd_flags: None,
+                data: vec![1, 2, 3],
+            })
+            .unwrap();
+    }
+    let log = writer.into_inner();
+
+    // Replay it
+    let driver = CanReplay::new(CandumpReader::new(Cursor::new(log)));
+    let local_id = CanNodeId::try_from(1u8).unwrap();
+    let mut node: ReplayNode<_> = CoreNode::new(
+        driver.clock(),
+        local_id,
+        CanTransmitter::new(Mtu::Can8),
+        CanReceiver::new(local_id),
+        driver,
+    );
+    node.subscribe_message(subject, 64, milliseconds(1000))
+        .unwrap();
+    let mut collector = MessageCollector::default();
+    while !node.driver().is_finished() {
+        node.receive(&mut collector).unwrap();
+    }
+
+    // The transfer timestamp is the time of the first frame, relative to the start of the log
+    assert_eq!(
+        vec![(Microseconds32::from_ticks(0), Some(source), payload)],
+        collector.messages
+    );
+    assert_eq!(Microseconds32::from_ticks(30_000), node.clock_mut().now());
+}
@@ -0,0 +1,211 @@
+//!
+//! Tests reading and writing pcap and pcapng files and replaying UDP packets into a receiver
+//!
+
+extern crate canadensis_capture;
+extern crate canadensis_core;
+extern crate canadensis_udp;
+
+use std::convert::TryFrom;
+use std::io::Cursor;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+
+use canadensis_capture::candump::{CanRecord, RecordId};
+use canadensis_capture::pcap::{
+    encode_socketcan, Packet, PcapReader, PcapWriter, PcapngWriter, UdpDatagram,
+    LINKTYPE_CAN_SOCKETCAN, LINKTYPE_ETHERNET, LINKTYPE_IPV4,
+};
+use canadensis_capture::replay::{ReplayClock, UdpReplay};
+use canadensis_core::session::SessionDynamicMap;
+use canadensis_core::time::{milliseconds, Microseconds32};
+use canadensis_core::transport::Receiver;
+use canadensis_core::SubjectId;
+use canadensis_udp::{UdpNodeId, UdpReceiver, UdpSessionData, UdpTransferId, DEFAULT_PORT};
+
+fn can_records() -> Vec<CanRecord> {
+    vec![
+        CanRecord {
+            timestamp: Duration::new(1_700_000_000, 123_456_000),
+            interface: "vcan0".into(),
+            id: RecordId::Extended(0x107d552a),
+            remote: false,
+            fd_flags: None,
+            data: vec![0x00, 0x00, 0x00, 0x00, 0x04, 0x78, 0x68, 0xe0],
+        },
+        CanRecord {
+            timestamp: Duration::new(1_700_000_001, 0),
+            interface: "vcan0".into(),
+            id: RecordId::Standard(0x123),
+            remote: true,
+            fd_flags: None,
+            data: vec![],
+        },
+        CanRecord {
+            timestamp: Duration::new(1_700_000_002, 1_000),
+            interface: "vcan0".into(),
+            id: RecordId::Extended(0x10606a2b),
+            remote: false,
+            fd_flags: Some(1),
+            data: (0..12).collect(),
+        },
+    ]
+}
+
+#[test]
+fn pcap_can_round_trip() {
+    let records = can_records();
+    let mut writer = PcapWriter::new(Vec::new(), LINKTYPE_CAN_SOCKETCAN).unwrap();
+    for record in &records {
+        writer
+            .write_packet(record.timestamp, &encode_socketcan(record))
+            .unwrap();
+    }
+    let file = writer.into_inner();
+
+    let read: Vec<CanRecord> = PcapReader::new(Cursor::new(file))
+        .unwrap()
+        .can_records()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    // Classic pcap files do not have interface names
+    let expected: Vec<CanRecord> = records
+        .into_iter()
+        .map(|record| CanRecord {
+            interface: "can0".into(),
+            ..record
+        })
+        .collect();
+    assert_eq!(expected, read);
+}
+
+#[test]
+fn pcapng_can_round_trip() {
+    let records = can_records();
+    let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+    let interface = writer
+        .add_interface(LINKTYPE_CAN_SOCKETCAN, Some("vcan0"))
+        .unwrap();
+    for record in &records {
+        writer
+            .write_packet(interface, record.timestamp, &encode_socketcan(record))
+            .unwrap();
+    }
+    let file = writer.into_inner();
+
+    let read: Vec<CanRecord> = PcapReader::new(Cursor::new(file))
+        .unwrap()
+        .can_records()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(records, read);
+}
+
+#[test]
+fn ethernet_udp_packet() {
+    let datagram = UdpDatagram {
+        timestamp: Duration::from_secs(3),
+        source: SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 10), 50000),
+        destination: SocketAddrV4::new(Ipv4Addr::new(239, 0, 4, 68), DEFAULT_PORT),
+        payload: vec![1, 2, 3, 4, 5],
+    };
+    // Destination MAC, source MAC, VLAN tag, and IPv4 ethertype
+    let mut data = vec![0x01, 0x00, 0x5e, 0x00, 0x04, 0x44];
+    data.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    data.extend_from_slice(&[0x81, 0x00, 0x00, 0x05, 0x08, 0x00]);
+    data.extend_from_slice(&datagram.to_ipv4_packet());
+    let packet = Packet {
+        timestamp: datagram.timestamp,
+        link_type: LINKTYPE_ETHERNET,
+        interface: 0,
+        data,
+    };
+    assert_eq!(Some(datagram), packet.to_udp_datagram());
+}
+
+/// A message with a 16-byte payload in three frames, from the Cyphal/UDP receiver tests
+const THREE_FRAME_TRANSFER: [&[u8]; 3] = [
+    &[
+        0x01, 0x06, 0xe3, 0xf0, 0xff, 0xff, 0x44, 0x04, 0xfb, 0xe7, 0x48, 0x32, 0xdf, 0xa7, 0xa8,
+        0xfa, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc9, 0x72, 0xe3, 0x39, 0x5a, 0xbe, 0x93, 0xa0,
+        0x00, 0x92,
+    ],
+    &[
+        0x01, 0x06, 0xe3, 0xf0, 0xff, 0xff, 0x44, 0x04, 0xfb, 0xe7, 0x48, 0x32, 0xdf, 0xa7, 0xa8,
+        0xfa, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x8c, 0xd2, 0x92, 0xff, 0x00, 0x00, 0x01,
+    ],
+    &[
+        0x01, 0x06, 0xe3, 0xf0, 0xff, 0xff, 0x44, 0x04, 0xfb, 0xe7, 0x48, 0x32, 0xdf, 0xa7, 0xa8,
+        0xfa, 0x02, 0x00, 0x00, 0x80, 0x00, 0x00, 0x79, 0x68, 0x6f, 0x77, 0x6f, 0xad, 0xb3, 0xf1,
+        0xbf,
+    ],
+];
+
+#[test]
+fn replay_udp() {
+    let source = SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 50000);
+    let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+    let interface = writer.add_interface(LINKTYPE_IPV4, None).unwrap();
+    for (i, payload) in THREE_FRAME_TRANSFER.iter().enumerate() {
+        let timestamp = Duration::new(1_700_000_000, 0) + Duration::from_millis(i as u64);
+        let datagram = UdpDatagram {
+            timestamp,
+            source,
+            destination: SocketAddrV4::new(Ipv4Addr::new(239, 0, 4, 68), DEFAULT_PORT),
+            payload: payload.to_vec(),
+        };
+        writer
+            .write_packet(interface, timestamp, &datagram.to_ipv4_packet())
+            .unwrap();
+        // Another packet to a different port, which is ignored
+        let other = UdpDatagram {
+            destination: SocketAddrV4::new(Ipv4Addr::new(239, 0, 4, 68), 53),
+            ..datagram
+        };
+        writer
+            .write_packet(interface, timestamp, &other.to_ipv4_packet())
+            .unwrap();
+    }
+    let file = writer.into_inner();
+
+    let datagrams = PcapReader::new(Cursor::new(file)).unwrap().udp_datagrams();
+    let mut socket = UdpReplay::new(datagrams, DEFAULT_PORT);
+    let mut clock = socket.clock();
+    let mut receiver = UdpReceiver::<
+        ReplayClock,
+        SessionDynamicMap<UdpNodeId, UdpTransferId, UdpSessionData>,
+        _,
+        1472,
+    >::new(None, Ipv4Addr::LOCALHOST);
+    receiver
+        .subscribe_message(
+            SubjectId::try_from(0x444).unwrap(),
+            64,
+            milliseconds(1000),
+            &mut socket,
+        )
+        .unwrap();
+
+    let mut transfers = Vec::new();
+    while !socket.is_finished() {
+        if let Some(transfer) = receiver.receive(&mut clock, &mut socket).unwrap() {
+            transfers.push(transfer);
+        }
+    }
+    assert_eq!(1, transfers.len());
+    assert_eq!(
+        vec![
+            0xe3, 0x39, 0x5a, 0xbe, 0x93, 0xa0, 0x00, 0x92, 0x92, 0xff, 0x00, 0x00, 0x01, 0x6f,
+            0x77, 0x6f,
+        ],
+        transfers[0].payload
+    );
+    assert_eq!(
+        Microseconds32::from_ticks(0),
+        transfers[0].header.timestamp()
+    );
+    assert_eq!(
+        Some(&UdpNodeId::try_from(0xf0e3).unwrap()),
+        transfers[0].header.source()
+    );
+}
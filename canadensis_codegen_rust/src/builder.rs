@@ -0,0 +1,292 @@
+//!
+//! Code generation from build scripts
+//!
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use canadensis_dsdl_frontend::cache::CompileCache;
+use canadensis_dsdl_frontend::Package;
+
+use crate::error::BuildError;
+use crate::{generate_code_with_options, report, GenerateOptions};
+
+/// Generates Rust code from DSDL files in a build script
+///
+/// The builder compiles the DSDL packages, writes the generated module into `OUT_DIR`,
+/// formats it with rustfmt (if rustfmt is installed), and tells Cargo to run the build script
+/// again when any DSDL file changes.
+///
+/// The builder also keeps a cache of compiled types next to the output file. If no DSDL file and
+/// no builder setting has changed since the last run, the output file is left alone. Otherwise,
+/// only the DSDL files that have changed (and the files that depend on them) are compiled again.
+///
+/// # Examples
+///
+/// In `build.rs`:
+///
+/// ```no_run
+/// canadensis_codegen_rust::Builder::new()
+///     .package("dsdl")
+///     .package("public_regulated_data_types")
+///     .external("uavcan", "canadensis_data_types::uavcan")
+///     .external("reg", "canadensis_data_types::reg")
+///     .derive("Debug")
+///     .out_file("dsdl.rs")
+///     .generate()
+///     .expect("Failed to generate code from DSDL");
+/// ```
+///
+/// In the crate:
+///
+/// ```ignore
+/// include!(concat!(env!("OUT_DIR"), "/dsdl.rs"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    /// Root directories of DSDL packages
+    packages: Vec<PathBuf>,
+    /// Map from DSDL package names to Rust module paths
+    external_packages: BTreeMap<Vec<String>, Vec<String>>,
+    options: GenerateOptions,
+    /// Output file path, relative to OUT_DIR
+    out_file: PathBuf,
+    rustfmt: bool,
+    emit_cargo_directives: bool,
+}
+
+impl Builder {
+    /// Creates a builder with no packages
+    ///
+    /// By default, the output file is `dsdl.rs`, the code is formatted with rustfmt, and
+    /// the builder prints directives for Cargo.
+    pub fn new() -> Self {
+        Builder {
+            packages: Vec::new(),
+            external_packages: BTreeMap::new(),
+            options: GenerateOptions::new(),
+            out_file: PathBuf::from("dsdl.rs"),
+            rustfmt: true,
+            emit_cargo_directives: true,
+        }
+    }
+
+    /// Adds a directory of DSDL files
+    ///
+    /// The directory should contain one or more root namespace folders, like the root of the
+    /// public regulated data types repository.
+    pub fn package<P>(mut self, root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.packages.push(root.into());
+        self
+    }
+
+    /// Marks a DSDL package as external
+    ///
+    /// No code will be generated for types in the DSDL package `cyphal_package` (like `uavcan` or
+    /// `uavcan.node`) or its subpackages. Instead, the generated code will refer to types in the
+    /// Rust module `rust_module` (like `canadensis_data_types::uavcan`).
+    pub fn external(mut self, cyphal_package: &str, rust_module: &str) -> Self {
+        self.external_packages.insert(
+            cyphal_package.split('.').map(String::from).collect(),
+            rust_module
+                .trim_start_matches("::")
+                .split("::")
+                .map(String::from)
+                .collect(),
+        );
+        self
+    }
+
+    /// Adds a trait to derive on every generated struct and enum
+    ///
+    /// See [`GenerateOptions::derive`] for details.
+    pub fn derive<S>(mut self, path: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.options = self.options.derive(path);
+        self
+    }
+
+    /// Enables or disables generating view types
+    ///
+    /// See [`GenerateOptions::views`] for details.
+    pub fn views(mut self, views: bool) -> Self {
+        self.options = self.options.views(views);
+        self
+    }
+
+    /// Enables or disables using `alloc::vec::Vec` for variable-length arrays
+    ///
+    /// See [`GenerateOptions::alloc_vec`] for details.
+    pub fn alloc_vec(mut self, alloc_vec: bool) -> Self {
+        self.options = self.options.alloc_vec(alloc_vec);
+        self
+    }
+
+    /// Replaces all the code generation options
+    pub fn options(mut self, options: GenerateOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the path of the output file
+    ///
+    /// A relative path is relative to the `OUT_DIR` directory that Cargo provides to build
+    /// scripts.
+    pub fn out_file<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.out_file = path.into();
+        self
+    }
+
+    /// Enables or disables formatting the generated code with rustfmt
+    ///
+    /// If formatting is enabled but rustfmt is not installed, the code is left unformatted.
+    pub fn rustfmt(mut self, rustfmt: bool) -> Self {
+        self.rustfmt = rustfmt;
+        self
+    }
+
+    /// Enables or disables printing `cargo:rerun-if-changed` and `cargo:warning` directives
+    ///
+    /// This should be disabled when the builder is not used in a build script.
+    pub fn emit_cargo_directives(mut self, emit: bool) -> Self {
+        self.emit_cargo_directives = emit;
+        self
+    }
+
+    /// Compiles the DSDL and writes the generated code
+    ///
+    /// On success, this function returns the path to the output file.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the output path is relative and `OUT_DIR` is not set,
+    /// if the DSDL could not be read or compiled, if code could not be generated, or if the
+    /// output file could not be written. DSDL errors contain a report of the problem.
+    pub fn generate(&self) -> Result<PathBuf, BuildError> {
+        let out_path = if self.out_file.is_absolute() {
+            self.out_file.clone()
+        } else {
+            let out_dir = std::env::var_os("OUT_DIR").ok_or(BuildError::OutDir)?;
+            PathBuf::from(out_dir).join(&self.out_file)
+        };
+        let cache_path = sibling_path(&out_path, ".cache");
+        let stamp_path = sibling_path(&out_path, ".stamp");
+
+        let mut package = Package::new();
+        for root in &self.packages {
+            // Watching the directory catches added and removed files
+            self.emit(&format!("rerun-if-changed={}", root.display()));
+            package
+                .add_files(root)
+                .map_err(|e| BuildError::Dsdl(report::render(&e.to_diagnostic())))?;
+        }
+        for path in package.source_paths() {
+            self.emit(&format!("rerun-if-changed={}", path.display()));
+        }
+
+        // If the DSDL and settings are the same as last time and the output is still there,
+        // there's nothing to do.
+        let mut cache = CompileCache::load(&cache_path)?;
+        let stamp = self.stamp();
+        let up_to_date = out_path.is_file()
+            && read_optional(&stamp_path)?.as_deref() == Some(stamp.as_str())
+            && !cache.is_empty()
+            && package
+                .changes(&cache)
+                .map_err(|e| BuildError::Dsdl(report::render(&e.to_diagnostic())))?
+                .is_empty();
+        if up_to_date {
+            return Ok(out_path);
+        }
+
+        let (compiled, _changes) = package
+            .compile_with_cache(&mut cache)
+            .map_err(|e| BuildError::Dsdl(report::render(&e.to_diagnostic())))?;
+        for warning in compiled.warnings() {
+            self.emit(&format!("warning={}", warning));
+        }
+        let generated =
+            generate_code_with_options(&compiled, &self.external_packages, &self.options)?;
+
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&out_path, generated.to_string())?;
+        if self.rustfmt {
+            self.run_rustfmt(&out_path);
+        }
+        cache.save(&cache_path)?;
+        fs::write(&stamp_path, stamp)?;
+        Ok(out_path)
+    }
+
+    /// Prints a directive for Cargo, if enabled
+    fn emit(&self, directive: &str) {
+        if self.emit_cargo_directives {
+            println!("cargo:{}", directive);
+        }
+    }
+
+    /// Formats a file with rustfmt, if rustfmt is available
+    ///
+    /// Formatting problems are reported as warnings because the unformatted code is still usable.
+    fn run_rustfmt(&self, path: &Path) {
+        let rustfmt = std::env::var_os("RUSTFMT").unwrap_or_else(|| OsString::from("rustfmt"));
+        match Command::new(rustfmt)
+            .arg("--edition")
+            .arg("2018")
+            .arg(path)
+            .status()
+        {
+            Ok(status) if status.success() => {}
+            Ok(status) => self.emit(&format!("warning=rustfmt failed: {}", status)),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => self.emit(&format!("warning=Failed to run rustfmt: {}", e)),
+        }
+    }
+
+    /// Returns a description of the settings that affect the output
+    fn stamp(&self) -> String {
+        format!(
+            "{}\n{:?}\n{:?}\n{}\n",
+            env!("CARGO_PKG_VERSION"),
+            self.external_packages,
+            self.options,
+            self.rustfmt
+        )
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+/// Returns a path with the same name as `path` with a suffix appended
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Reads a file, returning None if it does not exist
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
@@ -35,3 +35,19 @@ pub enum Error {
         inner: Box<dyn std::error::Error>,
     },
 }
+
+/// Errors that can occur when generating code with a [`Builder`](crate::Builder)
+#[derive(thiserror::Error, Debug)]
+pub enum BuildError {
+    #[error("OUT_DIR is not set, so the output file path must be absolute")]
+    OutDir,
+    /// A DSDL file could not be read or compiled
+    ///
+    /// The string contains a human-readable report of the problem.
+    #[error("Failed to compile DSDL:\n{0}")]
+    Dsdl(String),
+    #[error("Failed to generate code")]
+    Generate(#[from] Error),
+    #[error("Input/output error")]
+    Io(#[from] std::io::Error),
+}
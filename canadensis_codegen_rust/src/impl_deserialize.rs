@@ -25,9 +25,11 @@ impl Display for ImplementDeserialize<'_, '_> {
 
         match &self.ty.kind {
             GeneratedTypeKind::Struct(gstruct) => {
-                deserialize_struct(f, &self.ty.name, gstruct, self.zero_copy)?
+                deserialize_struct(f, &self.ty.name, gstruct, self.zero_copy, self.ty.alloc_vec)?
+            }
+            GeneratedTypeKind::Enum(genum) => {
+                deserialize_enum(f, &self.ty.name, genum, self.ty.alloc_vec)?
             }
-            GeneratedTypeKind::Enum(genum) => deserialize_enum(f, &self.ty.name, genum)?,
         }
 
         // End function
@@ -43,6 +45,7 @@ fn deserialize_struct(
     name: &RustTypeName,
     gstruct: &GeneratedStruct,
     zero_copy: bool,
+    alloc_vec: bool,
 ) -> Result {
     if zero_copy {
         writeln!(f, "Ok(Self::deserialize_zero_copy(cursor))")
@@ -64,7 +67,8 @@ fn deserialize_struct(
                         f,
                         "{} }},",
                         ReadUnalignedField {
-                            ty: field.cyphal_ty
+                            ty: field.cyphal_ty,
+                            alloc_vec,
                         }
                     )?;
                 }
@@ -80,7 +84,12 @@ fn deserialize_struct(
     }
 }
 
-fn deserialize_enum(f: &mut Formatter<'_>, name: &RustTypeName, genum: &GeneratedEnum) -> Result {
+fn deserialize_enum(
+    f: &mut Formatter<'_>,
+    name: &RustTypeName,
+    genum: &GeneratedEnum,
+    alloc_vec: bool,
+) -> Result {
     // Match on the discriminant
     writeln!(
         f,
@@ -100,7 +109,10 @@ fn deserialize_enum(f: &mut Formatter<'_>, name: &RustTypeName, genum: &Generate
                 "Ok({}::{}({{ {} }}))",
                 name.type_name,
                 variant.name,
-                ReadUnalignedField { ty: &ty.cyphal_ty }
+                ReadUnalignedField {
+                    ty: &ty.cyphal_ty,
+                    alloc_vec,
+                }
             )?;
         } else {
             // Variant with no data
@@ -121,8 +133,10 @@ fn deserialize_enum(f: &mut Formatter<'_>, name: &RustTypeName, genum: &Generate
     Ok(())
 }
 
-struct ReadUnalignedField<'t> {
-    ty: &'t ResolvedType,
+pub(crate) struct ReadUnalignedField<'t> {
+    pub ty: &'t ResolvedType,
+    /// If variable-length arrays are `alloc::vec::Vec`s instead of `heapless::Vec`s
+    pub alloc_vec: bool,
 }
 
 impl Display for ReadUnalignedField<'_> {
@@ -166,6 +180,7 @@ impl Display for ReadUnalignedField<'_> {
             ResolvedType::VariableArray { inner, max_len } => {
                 // Read and check the length
                 // Create a heapless::Vec (its element type and capacity will be inferred)
+                // or an alloc::vec::Vec with the required capacity
                 // Read and push elements
                 let length_bits = match &self.ty.implicit_field() {
                     Some(ImplicitField::ArrayLength { bits }) => *bits,
@@ -174,15 +189,24 @@ impl Display for ReadUnalignedField<'_> {
                 writeln!(f, "let length = {};", CallRead { bits: length_bits })?;
                 writeln!(f, "if length <= {} {{", *max_len)?;
 
-                writeln!(f, "let mut elements = ::heapless::Vec::new();")?;
-                writeln!(f, "for _ in 0..length {{")?;
+                if self.alloc_vec {
+                    writeln!(
+                        f,
+                        "let mut elements = ::alloc::vec::Vec::with_capacity(length);"
+                    )?;
+                    writeln!(f, "for _ in 0..length {{")?;
+                    writeln!(f, "elements.push({});", ReadUnalignedScalar { ty: inner })?;
+                } else {
+                    writeln!(f, "let mut elements = ::heapless::Vec::new();")?;
+                    writeln!(f, "for _ in 0..length {{")?;
 
-                // Don't use unwrap() because that requires inner to implement Debug
-                writeln!(
-                    f,
-                    "let _ = elements.push({});",
-                    ReadUnalignedScalar { ty: inner }
-                )?;
+                    // Don't use unwrap() because that requires inner to implement Debug
+                    writeln!(
+                        f,
+                        "let _ = elements.push({});",
+                        ReadUnalignedScalar { ty: inner }
+                    )?;
+                }
 
                 // End for
                 writeln!(f, "}}")?;
@@ -228,8 +252,8 @@ impl Display for ReadUnalignedScalar<'_> {
     }
 }
 
-struct CallReadAligned {
-    bits: u8,
+pub(crate) struct CallReadAligned {
+    pub bits: u8,
 }
 
 impl Display for CallReadAligned {
@@ -247,8 +271,8 @@ impl Display for CallReadAligned {
     }
 }
 
-struct CallRead {
-    bits: u8,
+pub(crate) struct CallRead {
+    pub bits: u8,
 }
 
 impl Display for CallRead {
@@ -1,6 +1,7 @@
 use crate::size_bits::SizeBitsExpr;
 use crate::{
-    round_up_integer_size, GeneratedField, GeneratedType, GeneratedTypeKind, GeneratedVariant,
+    array_expr, round_up_integer_size, GeneratedField, GeneratedType, GeneratedTypeKind,
+    GeneratedVariant,
 };
 use canadensis_dsdl_frontend::types::{
     ImplicitField, PrimitiveType, ResolvedScalarType, ResolvedType,
@@ -43,7 +44,7 @@ impl Display for ImplementSerialize<'_, '_> {
                     )?;
                 } else {
                     for field in &gstruct.fields {
-                        Display::fmt(&SerializeField(field), f)?;
+                        Display::fmt(&SerializeField(field, self.ty.alloc_vec), f)?;
                     }
                 }
             }
@@ -77,7 +78,7 @@ impl Display for ImplementSerialize<'_, '_> {
                         )?;
                     }
                     // Write the content of this variant
-                    Display::fmt(&SerializeVariant(variant), f)?;
+                    Display::fmt(&SerializeVariant(variant, self.ty.alloc_vec), f)?;
 
                     // End match arm
                     writeln!(f, "}}")?;
@@ -102,16 +103,20 @@ fn have_aligned_write_functions(bits: u8) -> bool {
     matches!(bits, 8 | 16 | 32 | 64)
 }
 
-struct SerializeField<'f, 'c>(&'f GeneratedField<'c>);
+/// Serializes a field
+///
+/// The second value is true if variable-length arrays are `alloc::vec::Vec`s.
+struct SerializeField<'f, 'c>(&'f GeneratedField<'c>, bool);
 
 impl Display for SerializeField<'_, '_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match &self.0 {
             GeneratedField::Data(data) => {
+                let field_expr = array_expr(&format!("self.{}", data.name), data.cyphal_ty, self.1);
                 if data.always_aligned {
                     Display::fmt(
                         &WriteAlignedField {
-                            field_expr: &format!("self.{}", data.name),
+                            field_expr: &field_expr,
                             ty: data.cyphal_ty,
                         },
                         f,
@@ -119,7 +124,7 @@ impl Display for SerializeField<'_, '_> {
                 } else {
                     Display::fmt(
                         &WriteUnalignedField {
-                            field_expr: &format!("self.{}", data.name),
+                            field_expr: &field_expr,
                             ty: data.cyphal_ty,
                         },
                         f,
@@ -131,14 +136,17 @@ impl Display for SerializeField<'_, '_> {
     }
 }
 
-struct SerializeVariant<'v, 'c>(&'v GeneratedVariant<'c>);
+/// Serializes the data in a variant
+///
+/// The second value is true if variable-length arrays are `alloc::vec::Vec`s.
+struct SerializeVariant<'v, 'c>(&'v GeneratedVariant<'c>, bool);
 
 impl Display for SerializeVariant<'_, '_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         if let Some(ty) = &self.0.ty {
             Display::fmt(
                 &WriteVariant {
-                    field_expr: "inner",
+                    field_expr: &array_expr("inner", &ty.cyphal_ty, self.1),
                     ty: &ty.cyphal_ty,
                 },
                 f,
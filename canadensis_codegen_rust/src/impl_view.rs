@@ -0,0 +1,332 @@
+//! Generates a view type that reads fields from serialized bytes on demand
+
+use canadensis_dsdl_frontend::compiled::Extent;
+use canadensis_dsdl_frontend::types::{ImplicitField, ResolvedScalarType, ResolvedType};
+use heck::ToSnakeCase;
+use std::fmt::{Display, Formatter, Result};
+
+use crate::impl_deserialize::{CallRead, CallReadAligned, ReadUnalignedField};
+use crate::{GeneratedEnum, GeneratedField, GeneratedStruct, GeneratedType, GeneratedTypeKind};
+
+/// Names of view functions that fields and variants can't use
+const RESERVED_NAMES: [&str; 4] = ["new", "bytes", "deserialize", "discriminant"];
+
+/// Defines a `[Name]View` struct and its functions
+pub(crate) struct ImplementView<'t, 'c>(pub &'t GeneratedType<'c>);
+
+impl Display for ImplementView<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let type_name = &self.0.name.type_name;
+        let view_name = format!("{}View", type_name);
+
+        writeln!(
+            f,
+            "/// A view of a serialized `{}` that reads each field only when it is requested",
+            self.0.cyphal_name
+        )?;
+        writeln!(f, "///")?;
+        writeln!(
+            f,
+            "/// Reading a field from a view skips over the fields before it without deserializing"
+        )?;
+        writeln!(
+            f,
+            "/// them. To read all the fields, [`deserialize`]({}::deserialize) is faster.",
+            view_name
+        )?;
+        writeln!(f, "#[derive(Debug, Clone, Copy)]")?;
+        if self.0.deprecated {
+            writeln!(f, "#[deprecated]")?;
+        }
+        writeln!(f, "pub struct {}<'b> {{ bytes: &'b [u8] }}", view_name)?;
+
+        writeln!(f, "#[allow(deprecated)]")?;
+        writeln!(f, "impl<'b> {}<'b> {{", view_name)?;
+        writeln!(f, "/// Creates a view of serialized bytes")?;
+        writeln!(
+            f,
+            "pub fn new(bytes: &'b [u8]) -> Self {{ {} {{ bytes }} }}",
+            view_name
+        )?;
+        writeln!(f, "/// Returns the serialized bytes")?;
+        writeln!(f, "pub fn bytes(&self) -> &'b [u8] {{ self.bytes }}")?;
+        writeln!(f, "/// Deserializes the complete object")?;
+        writeln!(f, "pub fn deserialize(&self) -> ::core::result::Result<{}, ::canadensis_encoding::DeserializeError> {{", type_name)?;
+        writeln!(
+            f,
+            "<{} as ::canadensis_encoding::Deserialize>::deserialize_from_bytes(self.bytes)",
+            type_name
+        )?;
+        writeln!(f, "}}")?;
+
+        match &self.0.kind {
+            GeneratedTypeKind::Struct(gstruct) => view_struct(f, gstruct, self.0.alloc_vec)?,
+            GeneratedTypeKind::Enum(genum) => view_enum(f, genum, self.0.alloc_vec)?,
+        }
+
+        // End impl
+        writeln!(f, "}}")
+    }
+}
+
+fn view_struct(f: &mut Formatter<'_>, gstruct: &GeneratedStruct, alloc_vec: bool) -> Result {
+    for (i, field) in gstruct.fields.iter().enumerate() {
+        let field = match field {
+            GeneratedField::Data(field) => field,
+            GeneratedField::Padding(_) => continue,
+        };
+        let function_name = function_name(&field.name, "field");
+        let skip = SkipFields {
+            fields: &gstruct.fields[..i],
+        };
+
+        // Each function uses `cursor: &mut ReadCursor` like Deserialize::deserialize, so the same
+        // code can read the field
+        writeln!(f, "/// Reads the `{}` field", field.name)?;
+        writeln!(f, "pub fn {}(&self) -> ::core::result::Result<{}, ::canadensis_encoding::DeserializeError> {{", function_name, field.ty)?;
+        writeln!(
+            f,
+            "let cursor = &mut ::canadensis_encoding::ReadCursor::new(self.bytes);"
+        )?;
+        write!(f, "{}", skip)?;
+        writeln!(
+            f,
+            "Ok({{ {} }})",
+            ReadUnalignedField {
+                ty: field.cyphal_ty,
+                alloc_vec,
+            }
+        )?;
+        writeln!(f, "}}")?;
+
+        if let Some(view_ty) = &field.view_ty {
+            writeln!(f, "/// Returns a view of the `{}` field", field.name)?;
+            writeln!(f, "pub fn {}_view(&self) -> ::core::result::Result<{}<'b>, ::canadensis_encoding::DeserializeError> {{", field.name, view_ty)?;
+            writeln!(
+                f,
+                "let cursor = &mut ::canadensis_encoding::ReadCursor::new(self.bytes);"
+            )?;
+            write!(f, "{}", skip)?;
+            writeln!(
+                f,
+                "Ok({}::new(cursor.read_composite_bytes::<{}>()?))",
+                view_ty, field.ty
+            )?;
+            writeln!(f, "}}")?;
+        }
+    }
+    Ok(())
+}
+
+fn view_enum(f: &mut Formatter<'_>, genum: &GeneratedEnum, alloc_vec: bool) -> Result {
+    let read_discriminant = CallReadAligned {
+        bits: genum.discriminant_bits,
+    };
+    writeln!(
+        f,
+        "/// Reads the discriminant (union tag), which identifies the active variant"
+    )?;
+    writeln!(f, "pub fn discriminant(&self) -> u32 {{")?;
+    writeln!(
+        f,
+        "let cursor = &mut ::canadensis_encoding::ReadCursor::new(self.bytes);"
+    )?;
+    writeln!(f, "{}", read_discriminant)?;
+    writeln!(f, "}}")?;
+
+    for variant in &genum.variants {
+        let ty = match &variant.ty {
+            Some(ty) => ty,
+            None => continue,
+        };
+        let snake_name = variant.name.to_snake_case();
+        let function_name = function_name(&snake_name, "variant");
+
+        writeln!(
+            f,
+            "/// Reads the `{}` variant, or returns None if a different variant is active",
+            variant.name
+        )?;
+        writeln!(f, "pub fn {}(&self) -> ::core::result::Result<::core::option::Option<{}>, ::canadensis_encoding::DeserializeError> {{", function_name, ty.rust_name)?;
+        writeln!(
+            f,
+            "let cursor = &mut ::canadensis_encoding::ReadCursor::new(self.bytes);"
+        )?;
+        writeln!(f, "let discriminant: u32 = {};", read_discriminant)?;
+        writeln!(
+            f,
+            "if discriminant != {} {{ return Ok(None); }}",
+            variant.discriminant
+        )?;
+        writeln!(
+            f,
+            "Ok(Some({{ {} }}))",
+            ReadUnalignedField {
+                ty: &ty.cyphal_ty,
+                alloc_vec,
+            }
+        )?;
+        writeln!(f, "}}")?;
+
+        if let Some(view_name) = &ty.view_name {
+            writeln!(
+                f,
+                "/// Returns a view of the `{}` variant, or None if a different variant is active",
+                variant.name
+            )?;
+            writeln!(f, "pub fn {}_view(&self) -> ::core::result::Result<::core::option::Option<{}<'b>>, ::canadensis_encoding::DeserializeError> {{", snake_name, view_name)?;
+            writeln!(
+                f,
+                "let cursor = &mut ::canadensis_encoding::ReadCursor::new(self.bytes);"
+            )?;
+            writeln!(f, "let discriminant: u32 = {};", read_discriminant)?;
+            writeln!(
+                f,
+                "if discriminant != {} {{ return Ok(None); }}",
+                variant.discriminant
+            )?;
+            writeln!(
+                f,
+                "Ok(Some({}::new(cursor.read_composite_bytes::<{}>()?)))",
+                view_name, ty.rust_name
+            )?;
+            writeln!(f, "}}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of the function that reads a field or variant, adding a suffix if the name
+/// is already used by another view function
+fn function_name(name: &str, suffix: &str) -> String {
+    if RESERVED_NAMES.contains(&name) {
+        format!("{}_{}", name, suffix)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Advances a cursor past some fields
+///
+/// Consecutive fields with fixed sizes are skipped with one function call. Other fields
+/// are skipped using their length fields and delimiter headers where possible. Only sealed
+/// composite types with variable lengths need to be deserialized.
+struct SkipFields<'f, 'c> {
+    fields: &'f [GeneratedField<'c>],
+}
+
+impl Display for SkipFields<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        // Bits that can be skipped without any alignment
+        let mut pending_bits = 0u64;
+        for field in self.fields {
+            match field {
+                GeneratedField::Padding(bits) => pending_bits += u64::from(*bits),
+                GeneratedField::Data(field) => {
+                    if let Some(bits) = unaligned_fixed_size(field.cyphal_ty) {
+                        pending_bits += bits;
+                        continue;
+                    }
+                    skip_bits(f, &mut pending_bits)?;
+                    skip_aligned_field(f, field.cyphal_ty, &field.scalar_ty)?;
+                }
+            }
+        }
+        skip_bits(f, &mut pending_bits)
+    }
+}
+
+/// Writes a call to skip any pending bits, and then sets the number of pending bits to zero
+fn skip_bits(f: &mut Formatter<'_>, pending_bits: &mut u64) -> Result {
+    if *pending_bits != 0 {
+        writeln!(f, "cursor.skip_bits({});", *pending_bits)?;
+        *pending_bits = 0;
+    }
+    Ok(())
+}
+
+/// Writes code to skip a field that contains composite types or has a variable length
+///
+/// `scalar_ty` is the Rust type of the field, or of each element if the field is an array.
+fn skip_aligned_field(f: &mut Formatter<'_>, ty: &ResolvedType, scalar_ty: &str) -> Result {
+    match ty {
+        ResolvedType::Scalar(scalar) => skip_composites(f, scalar, scalar_ty, Some(1)),
+        ResolvedType::FixedArray { inner, len } => skip_composites(f, inner, scalar_ty, Some(*len)),
+        ResolvedType::VariableArray { inner, max_len } => {
+            let length_bits = match ty.implicit_field() {
+                Some(ImplicitField::ArrayLength { bits }) => bits,
+                _ => unreachable!("Variable-length array does not have a length field"),
+            };
+            writeln!(f, "{{")?;
+            writeln!(f, "let length: usize = {};", CallRead { bits: length_bits })?;
+            writeln!(
+                f,
+                "if length > {} {{ return Err(::canadensis_encoding::DeserializeError::ArrayLength); }}",
+                max_len
+            )?;
+            match inner {
+                ResolvedScalarType::Composite { .. } => skip_composites(f, inner, scalar_ty, None)?,
+                ResolvedScalarType::Primitive(_) | ResolvedScalarType::Void { .. } => writeln!(
+                    f,
+                    "cursor.skip_bits(length * {});",
+                    inner.size().min_value()
+                )?,
+            }
+            writeln!(f, "}}")
+        }
+    }
+}
+
+/// Writes code to skip some composite objects
+///
+/// `count` is the number of objects, or None to skip the number of objects in a variable
+/// named `length`.
+///
+/// Each object is aligned to 8 bits. A sealed fixed-size composite type has a size that is
+/// a multiple of 8 bits, so any number of them can be skipped with one alignment and one
+/// function call.
+fn skip_composites(
+    f: &mut Formatter<'_>,
+    scalar: &ResolvedScalarType,
+    scalar_ty: &str,
+    count: Option<u64>,
+) -> Result {
+    let size = scalar.size();
+    match (scalar.extent(), count) {
+        (Extent::Sealed, Some(count)) if size.is_fixed_size() => {
+            writeln!(f, "cursor.align_to_8_bits();")?;
+            writeln!(f, "cursor.skip_bits({});", count * size.min_value())
+        }
+        (Extent::Sealed, None) if size.is_fixed_size() => {
+            // With zero elements, there is no alignment
+            writeln!(f, "if length != 0 {{")?;
+            writeln!(f, "cursor.align_to_8_bits();")?;
+            writeln!(f, "cursor.skip_bits(length * {});", size.min_value())?;
+            writeln!(f, "}}")
+        }
+        (_, Some(1)) => writeln!(f, "cursor.skip_composite::<{}>()?;", scalar_ty),
+        (_, Some(count)) => writeln!(
+            f,
+            "for _ in 0..{} {{ cursor.skip_composite::<{}>()?; }}",
+            count, scalar_ty
+        ),
+        (_, None) => writeln!(
+            f,
+            "for _ in 0..length {{ cursor.skip_composite::<{}>()?; }}",
+            scalar_ty
+        ),
+    }
+}
+
+/// If a field has a fixed size and contains no composite types (which need alignment),
+/// this function returns its size in bits
+fn unaligned_fixed_size(ty: &ResolvedType) -> Option<u64> {
+    match ty.scalar() {
+        ResolvedScalarType::Composite { .. } => None,
+        ResolvedScalarType::Primitive(_) | ResolvedScalarType::Void { .. } => match ty {
+            ResolvedType::Scalar(scalar) => Some(scalar.size().min_value()),
+            ResolvedType::FixedArray { inner, len } => Some(inner.size().min_value() * len),
+            ResolvedType::VariableArray { .. } => None,
+        },
+    }
+}
@@ -20,17 +20,21 @@ use canadensis_dsdl_frontend::constants::Constants;
 use canadensis_dsdl_frontend::types::{PrimitiveType, ResolvedScalarType, ResolvedType};
 use canadensis_dsdl_frontend::TypeKey;
 
+pub use crate::builder::Builder;
 use crate::error::EnumError;
-pub use crate::error::{Error, Result};
+pub use crate::error::{BuildError, Error, Result};
 use crate::module_tree::ModuleTree;
 use crate::struct_as_enum::{generate_enum_from_struct, has_enum_directive};
 
+mod builder;
 mod error;
 mod impl_constants;
 mod impl_data_type;
 mod impl_deserialize;
 mod impl_serialize;
+mod impl_view;
 mod module_tree;
+pub mod report;
 mod size_bits;
 mod struct_as_enum;
 
@@ -47,6 +51,74 @@ canadensis_encoding = "0.3.0"
     )
 }
 
+/// Options that change the generated code
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct GenerateOptions {
+    /// Additional traits to derive on every generated struct and enum
+    derives: Vec<String>,
+    /// Generate a view type for every generated struct and enum
+    views: bool,
+    /// Use `alloc::vec::Vec` instead of `heapless::Vec` for variable-length arrays
+    alloc_vec: bool,
+}
+
+impl GenerateOptions {
+    /// Creates a set of options that generates the default code
+    pub fn new() -> Self {
+        GenerateOptions::default()
+    }
+
+    /// Adds a trait to derive on every generated struct and enum
+    ///
+    /// The trait should be a path that is valid anywhere in the generated code, like
+    /// `Debug` or `::serde::Serialize`.
+    ///
+    /// Zero-copy types are `#[repr(C, packed)]`. Deriving `Debug`, `PartialEq`, or `Hash` on them
+    /// requires all their fields to implement `Copy`, so those derives should be used along with
+    /// `Clone` and `Copy`.
+    pub fn derive<S>(mut self, path: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.derives.push(path.into());
+        self
+    }
+
+    /// Enables or disables generating view types
+    ///
+    /// For each type `Name`, this generates a `NameView<'b>` type that wraps the serialized
+    /// bytes of a `Name` and has a function that reads each field (or for unions, the
+    /// discriminant and each variant). Each function reads only the parts of the bytes that it
+    /// needs, so reading one field from a large message is much cheaper than deserializing
+    /// the whole message.
+    ///
+    /// If a field has a composite type that is not in an external package, the view also has
+    /// a function that returns a view of that field.
+    pub fn views(mut self, views: bool) -> Self {
+        self.views = views;
+        self
+    }
+
+    /// Enables or disables using `alloc::vec::Vec` for variable-length arrays
+    ///
+    /// By default, variable-length arrays are `heapless::Vec`s with a capacity equal to the
+    /// maximum length, which makes some types very large. With this option, they are
+    /// `alloc::vec::Vec`s that hold only the elements that are present.
+    ///
+    /// Deserializing an array that is longer than its maximum length returns an error.
+    /// If an array is longer than its maximum length, serializing it writes only the first
+    /// elements, up to the maximum length.
+    ///
+    /// Variable-length arrays of `bool` are still stored in `BitArray`s.
+    ///
+    /// The generated code refers to `::alloc`, so the crate that contains it needs
+    /// an `extern crate alloc;` declaration.
+    pub fn alloc_vec(mut self, alloc_vec: bool) -> Self {
+        self.alloc_vec = alloc_vec;
+        self
+    }
+}
+
 /// Generates a Rust module from the provided package of DSDL
 ///
 /// `external_packages` is a map from DSDL package names to Rust module paths. A DSDL type in
@@ -55,6 +127,17 @@ canadensis_encoding = "0.3.0"
 pub fn generate_code<'c>(
     package: &'c CompiledPackage,
     external_packages: &BTreeMap<Vec<String>, Vec<String>>,
+) -> Result<GeneratedModule<'c>> {
+    generate_code_with_options(package, external_packages, &GenerateOptions::default())
+}
+
+/// Generates a Rust module from the provided package of DSDL, with some options
+///
+/// `external_packages` has the same meaning as in [`generate_code`].
+pub fn generate_code_with_options<'c>(
+    package: &'c CompiledPackage,
+    external_packages: &BTreeMap<Vec<String>, Vec<String>>,
+    options: &GenerateOptions,
 ) -> Result<GeneratedModule<'c>> {
     let mut generated_types = Vec::new();
 
@@ -69,6 +152,15 @@ pub fn generate_code<'c>(
             )?;
         }
     }
+    for item in &mut generated_types {
+        if let GeneratedItem::Type(ty) = item {
+            ty.derives = options.derives.clone();
+            ty.views = options.views;
+            if options.alloc_vec {
+                ty.use_alloc_vec();
+            }
+        }
+    }
     let tree: ModuleTree = generated_types.into_iter().collect();
     Ok(GeneratedModule { tree })
 }
@@ -287,6 +379,12 @@ struct GeneratedType<'c> {
     constants: Constants,
     deprecated: bool,
     comments: &'c str,
+    /// Additional traits to derive
+    derives: Vec<String>,
+    /// If a view type should be generated
+    views: bool,
+    /// If variable-length arrays use `alloc::vec::Vec`
+    alloc_vec: bool,
 }
 
 enum GeneratedTypeKind<'c> {
@@ -396,6 +494,34 @@ impl<'c> GeneratedType<'c> {
             constants,
             deprecated,
             comments,
+            derives: Vec::new(),
+            views: false,
+            alloc_vec: false,
+        }
+    }
+
+    /// Changes the types of variable-length array fields and variants to `alloc::vec::Vec`
+    fn use_alloc_vec(&mut self) {
+        self.alloc_vec = true;
+        match &mut self.kind {
+            GeneratedTypeKind::Struct(gstruct) => {
+                for field in &mut gstruct.fields {
+                    if let GeneratedField::Data(field) = field {
+                        if let Some(ty) = alloc_vec_type(field.cyphal_ty, &field.scalar_ty) {
+                            field.ty = ty;
+                        }
+                    }
+                }
+            }
+            GeneratedTypeKind::Enum(genum) => {
+                for variant in &mut genum.variants {
+                    if let Some(ty) = &mut variant.ty {
+                        if let Some(rust_name) = alloc_vec_type(&ty.cyphal_ty, &ty.scalar_name) {
+                            ty.rust_name = rust_name;
+                        }
+                    }
+                }
+            }
         }
     }
 
@@ -445,6 +571,10 @@ enum GeneratedField<'c> {
 struct GeneratedDataField<'c> {
     name: String,
     ty: String,
+    /// The Rust type of this field, or of each element if this field is an array
+    scalar_ty: String,
+    /// The Rust view type of this field, if this field has a non-external composite type
+    view_ty: Option<String>,
     cyphal_ty: &'c ResolvedType,
     always_aligned: bool,
     comments: &'c str,
@@ -518,6 +648,8 @@ impl<'c> GeneratedField<'c> {
         GeneratedField::Data(GeneratedDataField {
             name: make_rust_identifier(name),
             ty: to_rust_type(ty, external_packages),
+            scalar_ty: scalar_to_rust_type(ty.scalar(), external_packages),
+            view_ty: to_rust_view_type(ty, external_packages),
             cyphal_ty: ty,
             always_aligned,
             comments,
@@ -555,6 +687,8 @@ impl<'c> GeneratedVariant<'c> {
             name: make_rust_identifier(name).to_upper_camel_case(),
             ty: ty.map(|ty| ReferencedType {
                 rust_name: to_rust_type(&ty, external_packages),
+                scalar_name: scalar_to_rust_type(ty.scalar(), external_packages),
+                view_name: to_rust_view_type(&ty, external_packages),
                 cyphal_ty: ty,
             }),
             comments,
@@ -575,6 +709,10 @@ enum MessageRole {
 /// The type of a field or variant
 struct ReferencedType {
     rust_name: String,
+    /// The Rust type, or the type of each element if this is an array
+    scalar_name: String,
+    /// The Rust view type, if this is a non-external composite type
+    view_name: Option<String>,
     cyphal_ty: ResolvedType,
 }
 
@@ -613,6 +751,63 @@ fn to_rust_type(
     }
 }
 
+/// If a type is a variable-length array that can be an `alloc::vec::Vec`, this function returns
+/// the Rust Vec type
+///
+/// `scalar` is the Rust type of each element.
+fn alloc_vec_type(ty: &ResolvedType, scalar: &str) -> Option<String> {
+    match ty {
+        ResolvedType::VariableArray {
+            inner: ResolvedScalarType::Primitive(PrimitiveType::Boolean),
+            ..
+        } => None,
+        ResolvedType::VariableArray { .. } => Some(format!("::alloc::vec::Vec<{}>", scalar)),
+        ResolvedType::Scalar(_) | ResolvedType::FixedArray { .. } => None,
+    }
+}
+
+/// Returns an expression for the elements of a field or variant that are serialized
+///
+/// An `alloc::vec::Vec` can be longer than the maximum length of its array. Only the first
+/// elements, up to the maximum length, are serialized and counted in the size. Writing the lower
+/// bits of a longer length would make the serialized data invalid.
+///
+/// For other types, this returns `expr` unchanged.
+pub(crate) fn array_expr(expr: &str, ty: &ResolvedType, alloc_vec: bool) -> String {
+    match ty {
+        // Arrays of bool are still BitArrays
+        ResolvedType::VariableArray {
+            inner: ResolvedScalarType::Primitive(PrimitiveType::Boolean),
+            ..
+        } => expr.to_owned(),
+        ResolvedType::VariableArray { max_len, .. } if alloc_vec => {
+            format!("&{}[..{}.len().min({})]", expr, expr, max_len)
+        }
+        _ => expr.to_owned(),
+    }
+}
+
+/// Returns the name of the view type for a composite type that is not external
+///
+/// This returns None for array, primitive, and external composite types. Views of external types
+/// may not have been generated.
+fn to_rust_view_type(
+    ty: &ResolvedType,
+    external_packages: &BTreeMap<Vec<String>, Vec<String>>,
+) -> Option<String> {
+    match ty {
+        ResolvedType::Scalar(ResolvedScalarType::Composite { key, .. }) => {
+            let name = RustTypeName::for_message_type(key, external_packages);
+            if name.internal {
+                Some(format!("{}View", name))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
 fn scalar_to_rust_type(
     scalar: &ResolvedScalarType,
     external_packages: &BTreeMap<Vec<String>, Vec<String>>,
@@ -742,6 +937,7 @@ mod fmt_impl {
     use crate::impl_data_type::ImplementDataType;
     use crate::impl_deserialize::ImplementDeserialize;
     use crate::impl_serialize::ImplementSerialize;
+    use crate::impl_view::ImplementView;
     use crate::{
         write_doc_comments, GeneratedItem, GeneratedModule, GeneratedTypeKind, GeneratedVariant,
     };
@@ -788,6 +984,10 @@ mod fmt_impl {
                 writeln!(f, "#[repr(C, packed)]")?;
             }
 
+            if !self.derives.is_empty() {
+                writeln!(f, "#[derive({})]", self.derives.join(", "))?;
+            }
+
             if self.deprecated {
                 writeln!(f, "#[deprecated]")?;
             }
@@ -828,6 +1028,10 @@ mod fmt_impl {
                 f,
             )?;
 
+            if self.views {
+                Display::fmt(&ImplementView(self), f)?;
+            }
+
             if supports_zero_copy {
                 // Add some assertions about the type size and field layout
                 writeln!(f, "#[test] fn test_layout() {{")?;
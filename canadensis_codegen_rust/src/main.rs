@@ -2,8 +2,10 @@ extern crate canadensis_codegen_rust;
 extern crate canadensis_dsdl_frontend;
 extern crate clap;
 
+use canadensis_codegen_rust::{report, GenerateOptions};
+use canadensis_dsdl_frontend::diagnostic::Diagnostic;
 use canadensis_dsdl_frontend::Package;
-use clap::{value_parser, Arg, Command};
+use clap::{value_parser, Arg, ArgAction, Command};
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
@@ -28,28 +30,40 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
             output_file: output_path,
             external_packages,
             rustfmt,
+            views,
+            alloc_vec,
+            message_format,
         } => {
             let mut package = Package::new();
             for path in input_folders {
-                package.add_files(path)?;
+                if let Err(e) = package.add_files(path) {
+                    message_format.report(&e.to_diagnostic());
+                    return Err(Box::new(StringError("Could not read DSDL files".into())));
+                }
             }
             let package = match package.compile_with_warnings() {
                 Ok(package) => package,
                 Err((e, warnings)) => {
                     for warning in warnings {
-                        eprintln!("Warning: {}", warning);
+                        message_format.report(&warning.to_diagnostic());
                     }
-                    return Err(e.into());
+                    message_format.report(&e.to_diagnostic());
+                    return Err(Box::new(StringError("Could not compile DSDL".into())));
                 }
             };
 
             // Report warnings
             for warning in package.warnings() {
-                eprintln!("Warning: {}", warning);
+                message_format.report(&warning.to_diagnostic());
             }
 
             // Generate code
-            let generated = canadensis_codegen_rust::generate_code(&package, &external_packages)?;
+            let options = GenerateOptions::new().views(views).alloc_vec(alloc_vec);
+            let generated = canadensis_codegen_rust::generate_code_with_options(
+                &package,
+                &external_packages,
+                &options,
+            )?;
 
             let mut output_file = BufWriter::new(File::create(&output_path)?);
             writeln!(output_file, "{}", generated)?;
@@ -103,10 +117,36 @@ enum Args {
         external_packages: BTreeMap<Vec<String>, Vec<String>>,
         /// Run rustfmt on the generated code
         rustfmt: bool,
+        /// Generate view types
+        views: bool,
+        /// Use alloc::vec::Vec for variable-length arrays
+        alloc_vec: bool,
+        /// The format used to report DSDL errors and warnings
+        message_format: MessageFormat,
     },
     PrintDependencies,
 }
 
+/// Formats for DSDL errors and warnings
+#[derive(Debug, Clone, Copy)]
+enum MessageFormat {
+    /// Human-readable reports with source snippets
+    Human,
+    /// One JSON object per line
+    Json,
+}
+
+impl MessageFormat {
+    /// Writes a diagnostic to standard error (for human-readable output) or standard output
+    /// (for JSON)
+    fn report(&self, diagnostic: &Diagnostic) {
+        match self {
+            MessageFormat::Human => eprintln!("{}", report::render(diagnostic)),
+            MessageFormat::Json => println!("{}", report::to_json(diagnostic)),
+        }
+    }
+}
+
 fn get_args() -> Args {
     let app = clap::Command::new("canadensis_generate_code")
         .version(clap::crate_version!())
@@ -142,6 +182,22 @@ fn get_args() -> Args {
                 .long("rustfmt")
                 .num_args(0)
                 .help("Run rustfmt to format the generated code")
+        )
+            .arg(Arg::new("views")
+                .long("views")
+                .action(ArgAction::SetTrue)
+                .help("Generate a view type for each data type, which reads fields from serialized bytes without deserializing everything")
+        )
+            .arg(Arg::new("alloc_vec")
+                .long("alloc-vec")
+                .action(ArgAction::SetTrue)
+                .help("Use alloc::vec::Vec instead of heapless::Vec for variable-length arrays. The generated code requires `extern crate alloc;`.")
+        )
+            .arg(Arg::new("message_format")
+                .long("message-format")
+                .value_parser(["human", "json"])
+                .default_value("human")
+                .help("The format of DSDL errors and warnings. JSON messages are written to standard output, one per line.")
         ))
         .subcommand(Command::new("print-dependencies")
             .about("Prints the packages that the generated code depends on (for use in Cargo.toml)"));
@@ -165,6 +221,15 @@ fn get_args() -> Args {
                 })
                 .unwrap_or_else(BTreeMap::new),
             rustfmt: matches.contains_id("rustfmt"),
+            views: matches.get_flag("views"),
+            alloc_vec: matches.get_flag("alloc_vec"),
+            message_format: match matches
+                .get_one::<String>("message_format")
+                .map(String::as_str)
+            {
+                Some("json") => MessageFormat::Json,
+                _ => MessageFormat::Human,
+            },
         },
         Some(("print-dependencies", _)) => Args::PrintDependencies,
         _ => panic!("Unrecognized Subcommand"),
@@ -0,0 +1,245 @@
+//!
+//! Formatting of DSDL errors and warnings for people and for tools
+//!
+
+use std::fmt::Write;
+use std::fs;
+
+use canadensis_dsdl_frontend::diagnostic::{Diagnostic, SourceSpan, Suggestion};
+
+/// Formats a diagnostic in the style of rustc, with the relevant line of the source file
+/// and a marker under the problematic text
+///
+/// If the diagnostic refers to a file, this function tries to read the file to show the source
+/// text. If the file can't be read, the report contains only the location.
+pub fn render(diagnostic: &Diagnostic) -> String {
+    let source = diagnostic
+        .path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok());
+    render_with_source(diagnostic, source.as_deref())
+}
+
+/// Formats a diagnostic in the style of rustc, using the provided text as the content of the
+/// file that the diagnostic refers to
+pub fn render_with_source(diagnostic: &Diagnostic, source: Option<&str>) -> String {
+    let mut report = String::new();
+    writeln!(report, "{}: {}", diagnostic.severity, diagnostic.message).unwrap();
+
+    let snippet_line = diagnostic.span.as_ref().and_then(|span| {
+        source
+            .and_then(|source| source.lines().nth(span.start.line - 1))
+            .map(|line| (span, line))
+    });
+    // Width of the gutter that holds line numbers
+    let gutter = match &snippet_line {
+        Some((span, _)) => span.start.line.to_string().len(),
+        None => 0,
+    };
+    let indent = " ".repeat(gutter);
+
+    if let Some(location) = location(diagnostic) {
+        writeln!(report, "{}--> {}", indent, location).unwrap();
+    }
+    if let Some((span, line)) = snippet_line {
+        let marker_start = span.start.column - 1;
+        let line_length = line.chars().count();
+        let marker_end = if span.end.line == span.start.line {
+            span.end.column - 1
+        } else {
+            line_length
+        };
+        let marker_length = marker_end.saturating_sub(marker_start).max(1);
+        writeln!(report, "{} |", indent).unwrap();
+        writeln!(report, "{} | {}", span.start.line, line).unwrap();
+        writeln!(
+            report,
+            "{} | {}{}",
+            indent,
+            " ".repeat(marker_start),
+            "^".repeat(marker_length)
+        )
+        .unwrap();
+    }
+    if !diagnostic.notes.is_empty() || diagnostic.suggestion.is_some() {
+        writeln!(report, "{} |", indent).unwrap();
+    }
+    for note in &diagnostic.notes {
+        writeln!(report, "{} = note: {}", indent, note).unwrap();
+    }
+    if let Some(suggestion) = &diagnostic.suggestion {
+        writeln!(
+            report,
+            "{} = help: consider using `{}`",
+            indent, suggestion.replacement
+        )
+        .unwrap();
+    }
+    report
+}
+
+/// Returns the file or type name and line/column that a diagnostic refers to
+fn location(diagnostic: &Diagnostic) -> Option<String> {
+    let file = match (&diagnostic.path, &diagnostic.key) {
+        (Some(path), _) => path.display().to_string(),
+        (None, Some(key)) => key.to_string(),
+        (None, None) => return None,
+    };
+    Some(match &diagnostic.span {
+        Some(span) => format!("{}:{}", file, span.start),
+        None => file,
+    })
+}
+
+/// Formats a diagnostic as a single-line JSON object
+///
+/// The object has these fields:
+/// * `severity`: `"error"` or `"warning"`
+/// * `message`: The description of the problem
+/// * `type`: The name and version of the DSDL type with the problem, or null
+/// * `path`: The path to the file with the problem, or null
+/// * `span`: The location in the file (see below), or null
+/// * `suggestion`: An object with a `replacement` string and a `span` (which may be null),
+///   or null
+/// * `notes`: An array of strings with additional information
+///
+/// Each span contains `start` and `end` objects, which have one-based `line` and `column` fields,
+/// and the zero-based `start_byte` and `end_byte` offsets. The end of a span is exclusive.
+pub fn to_json(diagnostic: &Diagnostic) -> String {
+    let mut json = String::new();
+    json.push('{');
+    write!(
+        json,
+        "\"severity\":{},\"message\":{},\"type\":",
+        json_string(diagnostic.severity.as_str()),
+        json_string(&diagnostic.message)
+    )
+    .unwrap();
+    match &diagnostic.key {
+        Some(key) => json.push_str(&json_string(&key.to_string())),
+        None => json.push_str("null"),
+    }
+    json.push_str(",\"path\":");
+    match &diagnostic.path {
+        Some(path) => json.push_str(&json_string(&path.display().to_string())),
+        None => json.push_str("null"),
+    }
+    json.push_str(",\"span\":");
+    write_span(&mut json, diagnostic.span.as_ref());
+    json.push_str(",\"suggestion\":");
+    match &diagnostic.suggestion {
+        Some(Suggestion { span, replacement }) => {
+            write!(
+                json,
+                "{{\"replacement\":{},\"span\":",
+                json_string(replacement)
+            )
+            .unwrap();
+            write_span(&mut json, span.as_ref());
+            json.push('}');
+        }
+        None => json.push_str("null"),
+    }
+    json.push_str(",\"notes\":[");
+    for (i, note) in diagnostic.notes.iter().enumerate() {
+        if i != 0 {
+            json.push(',');
+        }
+        json.push_str(&json_string(note));
+    }
+    json.push_str("]}");
+    json
+}
+
+fn write_span(json: &mut String, span: Option<&SourceSpan>) {
+    match span {
+        Some(span) => write!(
+            json,
+            "{{\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}},\"start_byte\":{},\"end_byte\":{}}}",
+            span.start.line,
+            span.start.column,
+            span.end.line,
+            span.end.column,
+            span.start_byte,
+            span.end_byte
+        )
+        .unwrap(),
+        None => json.push_str("null"),
+    }
+}
+
+/// Converts a string into a quoted and escaped JSON string
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{json_string, render_with_source, to_json};
+    use canadensis_dsdl_frontend::Package;
+
+    fn warning_diagnostic() -> canadensis_dsdl_frontend::diagnostic::Diagnostic {
+        let mut package = Package::new();
+        package
+            .add_string(
+                None,
+                "test.Names.1.0".parse().unwrap(),
+                "uint8 fieldName\n@sealed\n".into(),
+            )
+            .unwrap();
+        let package = package.compile().unwrap();
+        let warning = package.warnings().iter().next().unwrap();
+        warning.to_diagnostic()
+    }
+
+    #[test]
+    fn render_warning() {
+        let diagnostic = warning_diagnostic();
+        let expected = "\
+warning: In type test.Names.1.0, the field or variant \"fieldName\" should have a snake_case name like \"field_name\"
+ --> test.Names.1.0:1:7
+  |
+1 | uint8 fieldName
+  |       ^^^^^^^^^
+  |
+  = help: consider using `field_name`
+";
+        assert_eq!(
+            expected,
+            render_with_source(&diagnostic, Some("uint8 fieldName\n@sealed\n"))
+        );
+    }
+
+    #[test]
+    fn warning_json() {
+        let diagnostic = warning_diagnostic();
+        let expected = concat!(
+            r#"{"severity":"warning","#,
+            r#""message":"In type test.Names.1.0, the field or variant \"fieldName\" should have a snake_case name like \"field_name\"","#,
+            r#""type":"test.Names.1.0","path":null,"#,
+            r#""span":{"start":{"line":1,"column":7},"end":{"line":1,"column":16},"start_byte":6,"end_byte":15},"#,
+            r#""suggestion":{"replacement":"field_name","span":{"start":{"line":1,"column":7},"end":{"line":1,"column":16},"start_byte":6,"end_byte":15}},"#,
+            r#""notes":[]}"#
+        );
+        assert_eq!(expected, to_json(&diagnostic));
+    }
+
+    #[test]
+    fn json_escape() {
+        assert_eq!(r#""a\"b\\c\n\u0001""#, json_string("a\"b\\c\n\u{1}"));
+    }
+}
@@ -1,6 +1,6 @@
 //! Generates an expression that calculates the size (in bits) of a data type
 
-use crate::{GeneratedField, GeneratedType, GeneratedTypeKind};
+use crate::{array_expr, GeneratedField, GeneratedType, GeneratedTypeKind};
 use canadensis_dsdl_frontend::compiled::Extent;
 use canadensis_dsdl_frontend::types::{
     ImplicitField, PrimitiveType, ResolvedScalarType, ResolvedType,
@@ -34,7 +34,11 @@ fn write_complex_size_expression(f: &mut Formatter, ty: &GeneratedType) -> Resul
                         Display::fmt(
                             &WriteFieldSize {
                                 ty: field.cyphal_ty,
-                                expr: &format!("self.{}", field.name),
+                                expr: &array_expr(
+                                    &format!("self.{}", field.name),
+                                    field.cyphal_ty,
+                                    ty.alloc_vec,
+                                ),
                             },
                             f,
                         )?;
@@ -54,11 +58,11 @@ fn write_complex_size_expression(f: &mut Formatter, ty: &GeneratedType) -> Resul
             for variant in genum.variants.iter() {
                 // Match arm (inner value is called `inner`)
                 writeln!(f, "{}::{}(inner) => {{", ty.name.type_name, variant.name)?;
-                if let Some(ty) = &variant.ty {
+                if let Some(variant_ty) = &variant.ty {
                     Display::fmt(
                         &WriteFieldSize {
-                            ty: &ty.cyphal_ty,
-                            expr: "inner",
+                            ty: &variant_ty.cyphal_ty,
+                            expr: &array_expr("inner", &variant_ty.cyphal_ty, ty.alloc_vec),
                         },
                         f,
                     )?;
@@ -0,0 +1,57 @@
+extern crate canadensis_codegen_rust;
+
+use canadensis_codegen_rust::{BuildError, Builder};
+use std::fs;
+use std::path::PathBuf;
+
+/// Generates code with a builder, checks that a second run with the same settings does not
+/// rewrite the output, and checks that changing the settings does rewrite it
+#[test]
+fn builder_skips_unchanged_dsdl() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("builder_skips_unchanged_dsdl");
+    let _ = fs::remove_dir_all(&out_dir);
+    let dsdl = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../canadensis_dsdl_frontend/tests/split_namespace/part1");
+    let builder = Builder::new()
+        .package(&dsdl)
+        .derive("Debug")
+        .out_file(out_dir.join("dsdl.rs"))
+        .rustfmt(false)
+        .emit_cargo_directives(false);
+
+    let out_path = builder.generate()?;
+    let generated = fs::read_to_string(&out_path)?;
+    assert!(generated.contains("#[derive(Debug)]"));
+
+    // Remove the content to detect if the file gets written again
+    fs::write(&out_path, "")?;
+    builder.generate()?;
+    assert_eq!("", fs::read_to_string(&out_path)?);
+
+    // Different settings
+    builder.clone().derive("Clone").generate()?;
+    assert!(fs::read_to_string(&out_path)?.contains("#[derive(Debug, Clone)]"));
+
+    fs::remove_dir_all(&out_dir)?;
+    Ok(())
+}
+
+/// Checks that a DSDL error is reported with its location
+#[test]
+fn builder_reports_dsdl_error() {
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("builder_reports_dsdl_error");
+    let dsdl = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../canadensis_dsdl_frontend/tests/compile_fail/duplicate_fields");
+    let status = Builder::new()
+        .package(dsdl)
+        .out_file(out_dir.join("dsdl.rs"))
+        .emit_cargo_directives(false)
+        .generate();
+    match status {
+        Err(BuildError::Dsdl(report)) => {
+            assert!(report.starts_with("error: A field named a already exists"));
+            assert!(report.contains("DuplicateFields.1.0.uavcan:4:1"));
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
@@ -14,6 +14,7 @@ pub extern crate nb;
 pub mod crc;
 mod error;
 pub mod session;
+pub mod statistics;
 pub mod subscription;
 pub mod time;
 pub mod transfer;
@@ -0,0 +1,59 @@
+//!
+//! Transport-independent counters of sent and received transfers and frames
+//!
+
+use heapless::Vec;
+
+/// The maximum number of redundant network interfaces that statistics can be reported for
+///
+/// This matches the maximum in `uavcan.node.GetTransportStatistics.0.1`.
+pub const MAX_INTERFACES: usize = 3;
+
+/// Counters of transfers or frames that were sent, received, or failed
+///
+/// All counters wrap around when they reach their maximum values.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IoStatistics {
+    /// Transfers or frames successfully sent
+    pub emitted: u64,
+    /// Transfers or frames successfully received
+    pub received: u64,
+    /// Transfers or frames that could not be sent or received because of an error
+    pub errored: u64,
+}
+
+impl IoStatistics {
+    /// Adds the values of other counters to these counters
+    pub fn add(&mut self, other: &IoStatistics) {
+        self.emitted = self.emitted.wrapping_add(other.emitted);
+        self.received = self.received.wrapping_add(other.received);
+        self.errored = self.errored.wrapping_add(other.errored);
+    }
+}
+
+/// Statistics about all transfers and frames handled by a transmitter and receiver
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TransportStatistics {
+    /// Counters of transfers
+    pub transfers: IoStatistics,
+    /// Counters of frames on each network interface
+    ///
+    /// This is empty if the transport and driver do not count frames.
+    pub interfaces: Vec<IoStatistics, MAX_INTERFACES>,
+}
+
+impl TransportStatistics {
+    /// Returns a mutable reference to the counters for a network interface
+    ///
+    /// If `index` is not less than the number of interfaces, this function adds empty counters
+    /// for this interface and all interfaces before it.
+    ///
+    /// This function returns None if `index` is not less than [`MAX_INTERFACES`].
+    pub fn interface_mut(&mut self, index: usize) -> Option<&mut IoStatistics> {
+        while self.interfaces.len() <= index {
+            self.interfaces.push(IoStatistics::default()).ok()?;
+        }
+        self.interfaces.get_mut(index)
+    }
+}
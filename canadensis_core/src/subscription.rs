@@ -262,7 +262,7 @@ impl<S> Default for DynamicSubscriptionManager<S> {
 }
 
 /// Information about something that a receiver/node is subscribed to
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Subscription {
     /// A message subscription, for messages with the specified subject ID
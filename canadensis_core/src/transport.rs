@@ -1,6 +1,7 @@
 //! Transport layer traits
 
 use crate::error::{OutOfMemoryError, ServiceSubscribeError};
+use crate::statistics::TransportStatistics;
 use crate::time::{Clock, MicrosecondDuration32};
 use crate::transfer::Transfer;
 use crate::{ServiceId, SubjectId};
@@ -77,6 +78,17 @@ where
     /// For example, Cyphal/CAN over classic CAN can transfer up to 7 bytes per frame (the eighth
     /// byte is used up by the tail byte), so it would return 7.
     fn mtu(&self) -> usize;
+
+    /// Adds the numbers of transfers and frames that this transmitter has sent or failed to send
+    /// to `statistics`
+    ///
+    /// `driver` is the driver that this transmitter uses. Frame counters may come from the driver,
+    /// if it counts frames for each network interface.
+    ///
+    /// The default implementation does nothing.
+    fn add_statistics(&self, driver: &Self::Driver, statistics: &mut TransportStatistics) {
+        let _ = (driver, statistics);
+    }
 }
 
 /// A receiver that can assemble incoming frames into transfers
@@ -200,6 +212,17 @@ where
 
     /// Returns an iterator over all servers of this Receiver
     fn servers(&self) -> impl Iterator<Item = ServiceId>;
+
+    /// Adds the numbers of transfers and frames that this receiver has received or failed to
+    /// receive to `statistics`
+    ///
+    /// `driver` is the driver that this receiver uses. Frame counters may come from the driver,
+    /// if it counts frames for each network interface.
+    ///
+    /// The default implementation does nothing.
+    fn add_statistics(&self, driver: &Self::Driver, statistics: &mut TransportStatistics) {
+        let _ = (driver, statistics);
+    }
 }
 
 /// Required operations for a transfer ID
@@ -10,10 +10,28 @@
 //!       binary and clear the CRC.
 //! * Load the .elf file onto the target microcontroller and run it as usual
 //!
+//! # App descriptors
+//!
+//! An application can also include an [`AppDescriptor`], which holds the CRC along with the
+//! image size, software version, version control revision, and build time. To add one, use the
+//! [`app_descriptor`] macro in the application:
+//!
+//! ```
+//! canadensis_crc::app_descriptor!();
+//!
+//! fn software_version() -> (u8, u8) {
+//!     CANADENSIS_APP_DESCRIPTOR.software_version()
+//! }
+//! ```
+//!
+//! `canadensis_write_crc` fills in the descriptor in a .elf file, a raw .bin file, or an Intel HEX
+//! file. In a .elf file, the image is the content of the loadable segments, not the whole file.
+//!
 
 #![no_std]
 #![deny(missing_docs)]
 
+use core::mem;
 use core::ptr;
 
 /// The CRC of this compiled image
@@ -39,3 +57,160 @@ pub fn get_crc() -> Option<u64> {
         None
     }
 }
+
+/// Information about a software image, filled in by `canadensis_write_crc`
+///
+/// The application sets the software version when it is compiled. `canadensis_write_crc` sets
+/// everything else.
+///
+/// Format (40 bytes, all integers little-endian):
+///
+/// * Bytes 0..8: [`AppDescriptor::SIGNATURE`]
+/// * Bytes 8..16: CRC-64-WE of the image
+/// * Bytes 16..20: Image size in bytes
+/// * Byte 20: 1 if the CRC and other fields set by `canadensis_write_crc` are valid
+/// * Byte 21: Software major version
+/// * Byte 22: Software minor version
+/// * Byte 23: Zero
+/// * Bytes 24..32: Version control revision ID, or zero if unknown
+/// * Bytes 32..40: Build time in seconds since the Unix epoch
+///
+/// The CRC covers the whole image (padded with zeros to a multiple of 8 bytes) with the CRC
+/// field set to zero and the valid flag set to 1. The image is the data that gets programmed into
+/// flash, from the lowest address to the highest, with any gaps filled with 0xff:
+///
+/// * For a raw binary, the image is the whole file.
+/// * For an Intel HEX file, the image is the data in all data records.
+/// * For a .elf file, the image is the content of the loadable segments at their physical
+///   addresses. The ELF headers, symbols, and debug information are not part of the image.
+///
+/// `canadensis_bootloader` finds the descriptor by its signature and checks the image size and
+/// CRC, so the file that the bootloader receives must contain exactly the image. For example, a
+/// raw binary made from a stamped .elf file must start at the lowest loadable address and fill
+/// gaps with 0xff (`objcopy -O binary --gap-fill 0xff`).
+#[repr(C, align(8))]
+pub struct AppDescriptor {
+    signature: [u8; 8],
+    crc: u64,
+    image_size: u32,
+    crc_valid: u8,
+    version_major: u8,
+    version_minor: u8,
+    _reserved: u8,
+    vcs_revision: u64,
+    build_timestamp: u64,
+}
+
+// canadensis_write_crc depends on this size
+const _: () = assert!(mem::size_of::<AppDescriptor>() == 40);
+
+impl AppDescriptor {
+    /// The bytes at the beginning of every descriptor, which `canadensis_write_crc` uses to find
+    /// the descriptor in a raw binary
+    pub const SIGNATURE: [u8; 8] = *b"CnDsApDs";
+
+    /// Creates a descriptor for an image with the provided software version
+    ///
+    /// The other fields will be filled in by `canadensis_write_crc`.
+    pub const fn new(version_major: u8, version_minor: u8) -> Self {
+        AppDescriptor {
+            signature: Self::SIGNATURE,
+            crc: 0,
+            image_size: 0,
+            crc_valid: 0,
+            version_major,
+            version_minor,
+            _reserved: 0,
+            vcs_revision: 0,
+            build_timestamp: 0,
+        }
+    }
+
+    /// Returns the CRC of the image, if it has been set
+    pub fn crc(&self) -> Option<u64> {
+        self.if_valid(|descriptor| u64::from_le(read(&descriptor.crc)))
+    }
+
+    /// Returns the size of the image in bytes, if it has been set
+    pub fn image_size(&self) -> Option<u32> {
+        self.if_valid(|descriptor| u32::from_le(read(&descriptor.image_size)))
+    }
+
+    /// Returns the software major and minor version
+    pub fn software_version(&self) -> (u8, u8) {
+        (read(&self.version_major), read(&self.version_minor))
+    }
+
+    /// Returns the version control revision ID (for example, the first 64 bits of a git commit
+    /// hash), or zero if it is not known
+    pub fn vcs_revision(&self) -> u64 {
+        self.if_valid(|descriptor| u64::from_le(read(&descriptor.vcs_revision)))
+            .unwrap_or(0)
+    }
+
+    /// Returns the time when the image was built in seconds since the Unix epoch, if it has been
+    /// set
+    pub fn build_timestamp(&self) -> Option<u64> {
+        self.if_valid(|descriptor| u64::from_le(read(&descriptor.build_timestamp)))
+    }
+
+    fn if_valid<T>(&self, f: impl FnOnce(&Self) -> T) -> Option<T> {
+        if read(&self.crc_valid) == 1 {
+            Some(f(self))
+        } else {
+            None
+        }
+    }
+}
+
+/// Reads a value that `canadensis_write_crc` may have changed after compilation
+///
+/// If this is not a volatile read, the compiler can replace it with the value from the
+/// descriptor's initializer.
+fn read<T: Copy>(value: &T) -> T {
+    unsafe { ptr::read_volatile(value) }
+}
+
+/// Defines a static [`AppDescriptor`] called `CANADENSIS_APP_DESCRIPTOR`
+///
+/// With no arguments, the software version comes from the major and minor version in the
+/// Cargo.toml file of the crate that uses this macro. The version can also be specified
+/// explicitly: `app_descriptor!(1, 2)`.
+///
+/// `canadensis_write_crc` finds the descriptor in a .elf file using the symbol name, so there can
+/// be only one descriptor in an application.
+#[macro_export]
+macro_rules! app_descriptor {
+    () => {
+        $crate::app_descriptor!(
+            $crate::parse_version_component(env!("CARGO_PKG_VERSION_MAJOR")),
+            $crate::parse_version_component(env!("CARGO_PKG_VERSION_MINOR"))
+        );
+    };
+    ($major:expr, $minor:expr) => {
+        #[no_mangle]
+        #[used]
+        pub static CANADENSIS_APP_DESCRIPTOR: $crate::AppDescriptor =
+            $crate::AppDescriptor::new($major, $minor);
+    };
+}
+
+/// Parses a version number that must fit in a u8
+#[doc(hidden)]
+pub const fn parse_version_component(text: &str) -> u8 {
+    let bytes = text.as_bytes();
+    assert!(!bytes.is_empty(), "Empty version number");
+    let mut value: u32 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let digit = bytes[i];
+        assert!(digit.is_ascii_digit(), "Version number is not a number");
+        value = value * 10 + (digit - b'0') as u32;
+        assert!(
+            value <= u8::MAX as u32,
+            "Version number is greater than 255"
+        );
+        i += 1;
+    }
+    value as u8
+}
@@ -0,0 +1,390 @@
+//!
+//! A cache of compiled DSDL types
+//!
+//! A [`CompileCache`] stores each compiled type, keyed by a hash of the content of its DSDL file
+//! and the hashes of all the types that it depends on (directly or indirectly). On the next run,
+//! [`Package::compile_with_cache`](crate::Package::compile_with_cache) reads the types whose file
+//! and dependencies have not changed from the cache, and only parses and evaluates the others.
+//!
+//! [`Package::changes`](crate::Package::changes) compares the current files with a cache without
+//! compiling anything. This lets a build script skip compilation and code generation completely
+//! when no type has changed, and find out exactly which types have changed when some have.
+//!
+//! A type is considered modified if its file content has changed, or if any type that it
+//! depends on (directly or indirectly) has been added, removed, or modified.
+//!
+//! Warnings are only reported for types that are compiled, not for types read from the cache.
+//!
+
+mod format;
+
+use crate::compiled::package::CompiledPackage;
+use crate::compiled::{CompiledDsdl, DsdlKind};
+use crate::package::DsdlFile;
+use crate::TypeKey;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::{fs, io};
+
+/// The first line of a cache file
+///
+/// This includes the version of this library, so that a cache written by a different version
+/// (which may compile types differently) is ignored.
+const HEADER: &str = concat!("canadensis_dsdl_frontend cache ", env!("CARGO_PKG_VERSION"));
+
+/// Compiled types, with the hashes of the DSDL files that they were compiled from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileCache {
+    entries: BTreeMap<TypeKey, CacheEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CacheEntry {
+    /// Hash of the DSDL text
+    source_hash: u64,
+    /// The other types that this type refers to
+    dependencies: BTreeSet<TypeKey>,
+    /// Hashes of the DSDL text of all types that this type depends on, directly or indirectly
+    dependency_hashes: BTreeMap<TypeKey, u64>,
+    /// The compiled type, in the form that the `format` module reads and writes
+    compiled: String,
+}
+
+impl CacheEntry {
+    /// Returns true if the DSDL file of this type and the files of all its dependencies still
+    /// have the recorded hashes
+    fn is_current(&self, key: &TypeKey, source_hashes: &BTreeMap<TypeKey, u64>) -> bool {
+        source_hashes.get(key) == Some(&self.source_hash)
+            && self
+                .dependency_hashes
+                .iter()
+                .all(|(dependency, hash)| source_hashes.get(dependency) == Some(hash))
+    }
+}
+
+impl CompileCache {
+    /// Creates an empty cache
+    ///
+    /// With an empty cache, all types are reported as added.
+    pub fn new() -> Self {
+        CompileCache::default()
+    }
+
+    /// Reads a cache from a file
+    ///
+    /// If the file does not exist, was written by a different version of this library, or
+    /// has invalid content, this function returns an empty cache.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the file exists but could not be read.
+    pub fn load<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(CompileCache::parse(&text).unwrap_or_default()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(CompileCache::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes this cache to a file, replacing the file if it already exists
+    pub fn save<P>(&self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        fs::write(path, self.to_text())
+    }
+
+    /// Returns true if this cache has no types
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reads the types whose DSDL files and dependencies have not changed
+    ///
+    /// This function returns the types and the other types that each one refers to. Types that
+    /// are not returned need to be compiled.
+    pub(crate) fn load_types(
+        &self,
+        files: &BTreeMap<TypeKey, DsdlFile>,
+        source_hashes: &BTreeMap<TypeKey, u64>,
+    ) -> (
+        BTreeMap<TypeKey, CompiledDsdl>,
+        BTreeMap<TypeKey, BTreeSet<TypeKey>>,
+    ) {
+        let mut loaded = BTreeMap::new();
+        for key in files.keys() {
+            self.load_type(key, files, source_hashes, &mut loaded);
+        }
+        let types: BTreeMap<TypeKey, CompiledDsdl> = loaded
+            .into_iter()
+            .filter_map(|(key, dsdl)| Some((key, dsdl?)))
+            .collect();
+        let dependencies = types
+            .keys()
+            .map(|key| (key.clone(), self.entries[key].dependencies.clone()))
+            .collect();
+        (types, dependencies)
+    }
+
+    /// Reads one type, after reading the types that it refers to, and returns true if it was
+    /// read
+    ///
+    /// `loaded` holds the types that have already been looked up, with None for types that could
+    /// not be read from the cache.
+    fn load_type(
+        &self,
+        key: &TypeKey,
+        files: &BTreeMap<TypeKey, DsdlFile>,
+        source_hashes: &BTreeMap<TypeKey, u64>,
+        loaded: &mut BTreeMap<TypeKey, Option<CompiledDsdl>>,
+    ) -> bool {
+        if let Some(dsdl) = loaded.get(key) {
+            return dsdl.is_some();
+        }
+        // Until this type has been read, treat it as unavailable. This stops an (invalid) cyclic
+        // dependency from recursing forever.
+        loaded.insert(key.clone(), None);
+        let (entry, file) = match (self.entries.get(key), files.get(key)) {
+            (Some(entry), Some(file)) if entry.is_current(key, source_hashes) => (entry, file),
+            _ => return false,
+        };
+        for dependency in &entry.dependencies {
+            if !self.load_type(dependency, files, source_hashes, loaded) {
+                return false;
+            }
+        }
+        let dsdl = format::decode(&entry.compiled, file.fixed_port_id(), |key| {
+            match loaded.get(key) {
+                Some(Some(CompiledDsdl {
+                    kind: DsdlKind::Message(message),
+                    ..
+                })) => Some(message.clone()),
+                _ => None,
+            }
+        });
+        let found = dsdl.is_some();
+        loaded.insert(key.clone(), dsdl);
+        found
+    }
+
+    /// Replaces the content of this cache with the types in a package and the hashes of their
+    /// sources
+    pub(crate) fn update(
+        &mut self,
+        source_hashes: &BTreeMap<TypeKey, u64>,
+        package: &CompiledPackage,
+    ) {
+        self.entries = package
+            .iter()
+            .filter_map(|(key, dsdl)| {
+                let dependencies = package.dependencies(key).cloned().unwrap_or_default();
+                // Find all direct and indirect dependencies
+                let mut dependency_hashes = BTreeMap::new();
+                let mut pending: Vec<&TypeKey> = dependencies.iter().collect();
+                while let Some(dependency) = pending.pop() {
+                    if !dependency_hashes.contains_key(dependency) {
+                        dependency_hashes
+                            .insert(dependency.clone(), *source_hashes.get(dependency)?);
+                        pending.extend(package.dependencies(dependency).into_iter().flatten());
+                    }
+                }
+                let entry = CacheEntry {
+                    source_hash: *source_hashes.get(key)?,
+                    dependencies,
+                    dependency_hashes,
+                    compiled: format::encode(dsdl)?,
+                };
+                Some((key.clone(), entry))
+            })
+            .collect();
+    }
+
+    /// Compares the cached types with the current hashes of the source files
+    pub(crate) fn changes(&self, source_hashes: &BTreeMap<TypeKey, u64>) -> Changes {
+        let mut changes = Changes::default();
+        for key in source_hashes.keys() {
+            match self.entries.get(key) {
+                None => {
+                    changes.added.insert(key.clone());
+                }
+                Some(entry) => {
+                    if !entry.is_current(key, source_hashes) {
+                        changes.modified.insert(key.clone());
+                    }
+                }
+            }
+        }
+        changes.removed = self
+            .entries
+            .keys()
+            .filter(|key| !source_hashes.contains_key(key))
+            .cloned()
+            .collect();
+        changes
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        text.push_str(HEADER);
+        text.push('\n');
+        for (key, entry) in &self.entries {
+            write!(
+                text,
+                "{} {:016x} {}",
+                key,
+                entry.source_hash,
+                entry.dependencies.len()
+            )
+            .unwrap();
+            for dependency in &entry.dependencies {
+                write!(text, " {}", dependency).unwrap();
+            }
+            write!(text, " {}", entry.dependency_hashes.len()).unwrap();
+            for (dependency, hash) in &entry.dependency_hashes {
+                write!(text, " {} {:016x}", dependency, hash).unwrap();
+            }
+            writeln!(text, " {}", entry.compiled).unwrap();
+        }
+        text
+    }
+
+    /// Parses the text of a cache file, returning None if the text is not valid
+    fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+        let mut entries = BTreeMap::new();
+        for line in lines {
+            let mut rest = line;
+            let mut next = || {
+                let (token, after) = rest.split_once(' ')?;
+                rest = after;
+                Some(token)
+            };
+            let key: TypeKey = next()?.parse().ok()?;
+            let source_hash = u64::from_str_radix(next()?, 16).ok()?;
+            let dependency_count: usize = next()?.parse().ok()?;
+            let dependencies = (0..dependency_count)
+                .map(|_| next()?.parse().ok())
+                .collect::<Option<BTreeSet<TypeKey>>>()?;
+            let dependency_hash_count: usize = next()?.parse().ok()?;
+            let dependency_hashes = (0..dependency_hash_count)
+                .map(|_| {
+                    let dependency: TypeKey = next()?.parse().ok()?;
+                    let hash = u64::from_str_radix(next()?, 16).ok()?;
+                    Some((dependency, hash))
+                })
+                .collect::<Option<BTreeMap<TypeKey, u64>>>()?;
+            entries.insert(
+                key,
+                CacheEntry {
+                    source_hash,
+                    dependencies,
+                    dependency_hashes,
+                    compiled: rest.to_owned(),
+                },
+            );
+        }
+        Some(CompileCache { entries })
+    }
+}
+
+/// Types that have changed since a cache was last updated
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    added: BTreeSet<TypeKey>,
+    modified: BTreeSet<TypeKey>,
+    removed: BTreeSet<TypeKey>,
+}
+
+impl Changes {
+    /// Returns true if no types have been added, modified, or removed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+    /// Returns the types that are not in the cache
+    pub fn added(&self) -> &BTreeSet<TypeKey> {
+        &self.added
+    }
+    /// Returns the types that are in the cache, but have different content or depend on other
+    /// types that have changed
+    pub fn modified(&self) -> &BTreeSet<TypeKey> {
+        &self.modified
+    }
+    /// Returns the types that are in the cache, but are no longer in the package
+    pub fn removed(&self) -> &BTreeSet<TypeKey> {
+        &self.removed
+    }
+}
+
+/// Calculates a 64-bit FNV-1a hash of some bytes
+///
+/// This hash is stable across Rust versions and platforms, unlike the standard library hashers.
+pub(crate) fn hash_source(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_source, CacheEntry, CompileCache};
+    use crate::TypeKey;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn key(s: &str) -> TypeKey {
+        s.parse().unwrap()
+    }
+
+    fn keys(strings: &[&str]) -> BTreeSet<TypeKey> {
+        strings.iter().map(|s| key(s)).collect()
+    }
+
+    fn entry(source_hash: u64, dependency_hashes: &[(&str, u64)]) -> CacheEntry {
+        CacheEntry {
+            source_hash,
+            dependencies: dependency_hashes.iter().map(|(s, _)| key(s)).collect(),
+            dependency_hashes: dependency_hashes
+                .iter()
+                .map(|&(s, hash)| (key(s), hash))
+                .collect(),
+            compiled: "message 0 sealed {0} 0 \" struct 0".into(),
+        }
+    }
+
+    #[test]
+    fn test_hash_source() {
+        assert_eq!(0xcbf2_9ce4_8422_2325, hash_source(b""));
+        assert_eq!(0xaf63_dc4c_8601_ec8c, hash_source(b"a"));
+    }
+
+    #[test]
+    fn dependency_change_propagates() {
+        let mut cache = CompileCache::new();
+        cache
+            .entries
+            .insert(key("test.A.1.0"), entry(1, &[("test.B.1.0", 2)]));
+        cache.entries.insert(key("test.B.1.0"), entry(2, &[]));
+        cache.entries.insert(key("test.C.1.0"), entry(3, &[]));
+        // Round trip through text
+        let text = cache.to_text();
+        let cache = CompileCache::parse(&text).unwrap();
+        assert_eq!(text, cache.to_text());
+
+        let mut hashes = BTreeMap::new();
+        hashes.insert(key("test.A.1.0"), 1);
+        hashes.insert(key("test.B.1.0"), 20);
+        hashes.insert(key("test.D.1.0"), 4);
+        let changes = cache.changes(&hashes);
+        assert_eq!(&keys(&["test.D.1.0"]), changes.added());
+        assert_eq!(&keys(&["test.A.1.0", "test.B.1.0"]), changes.modified());
+        assert_eq!(&keys(&["test.C.1.0"]), changes.removed());
+    }
+}
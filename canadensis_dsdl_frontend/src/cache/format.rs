@@ -0,0 +1,511 @@
+//!
+//! The text form of compiled types in a cache file
+//!
+//! A compiled type is written as a sequence of tokens separated by spaces, with no line breaks.
+//! Strings are escaped so that they do not contain spaces or line breaks.
+//!
+//! A field or variant with a composite type only stores the key of that type. When a type is
+//! read back, the messages of its dependencies must already be available.
+//!
+
+use crate::compiled::{
+    CompiledDsdl, DsdlKind, Extent, Field, FieldKind, Message, MessageKind, Struct, Union, Variant,
+};
+use crate::constants::{Constant, ConstantValue, Constants};
+use crate::types::string::StringValue;
+use crate::types::{PrimitiveType, ResolvedScalarType, ResolvedType, Value};
+use crate::TypeKey;
+use canadensis_dsdl_parser::num_bigint::BigInt;
+use canadensis_dsdl_parser::CastMode;
+use half::f16;
+use num_rational::BigRational;
+use std::fmt::{Display, Write as _};
+use std::str::{FromStr, Split};
+
+/// Converts a compiled type into text
+///
+/// This function returns None if the type contains a constant value that can't be stored.
+/// The fixed port ID is not stored.
+pub(crate) fn encode(dsdl: &CompiledDsdl) -> Option<String> {
+    let mut encoder = Encoder(String::new());
+    match &dsdl.kind {
+        DsdlKind::Message(message) => {
+            encoder.token("message");
+            encoder.message(message)?;
+        }
+        DsdlKind::Service { request, response } => {
+            encoder.token("service");
+            encoder.message(request)?;
+            encoder.message(response)?;
+        }
+    }
+    Some(encoder.0)
+}
+
+/// Reads a compiled type from text
+///
+/// `resolve` returns the message for a composite type key. This function returns None if the
+/// text is not valid or a composite type can't be resolved.
+pub(crate) fn decode<F>(text: &str, fixed_port_id: Option<u32>, resolve: F) -> Option<CompiledDsdl>
+where
+    F: Fn(&TypeKey) -> Option<Message>,
+{
+    let mut decoder = Decoder {
+        tokens: text.split(' '),
+        resolve,
+    };
+    let kind = match decoder.next()? {
+        "message" => DsdlKind::Message(decoder.message()?),
+        "service" => DsdlKind::Service {
+            request: decoder.message()?,
+            response: decoder.message()?,
+        },
+        _ => return None,
+    };
+    if decoder.tokens.next().is_some() {
+        return None;
+    }
+    Some(CompiledDsdl {
+        fixed_port_id,
+        kind,
+    })
+}
+
+struct Encoder(String);
+
+impl Encoder {
+    fn token<T: Display>(&mut self, token: T) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        write!(self.0, "{}", token).unwrap();
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.token(u8::from(value));
+    }
+
+    /// Writes a string, starting with `"` so that an empty string is still a token
+    fn string(&mut self, value: &str) {
+        self.token('"');
+        for c in value.chars() {
+            match c {
+                '\\' => self.0.push_str(r"\\"),
+                ' ' => self.0.push_str(r"\s"),
+                '\n' => self.0.push_str(r"\n"),
+                '\r' => self.0.push_str(r"\r"),
+                _ => self.0.push(c),
+            }
+        }
+    }
+
+    fn message(&mut self, message: &Message) -> Option<()> {
+        self.bool(message.deprecated);
+        match message.extent {
+            Extent::Sealed => self.token("sealed"),
+            Extent::Delimited(bits) => {
+                self.token("delimited");
+                self.token(bits);
+            }
+        }
+        self.token(&message.bit_length);
+        self.constants(&message.constants)?;
+        self.string(&message.comments);
+        match &message.kind {
+            MessageKind::Struct(Struct { fields }) => {
+                self.token("struct");
+                self.token(fields.len());
+                for field in fields {
+                    self.bool(field.always_aligned);
+                    self.string(&field.comments);
+                    self.token(field.end_offset);
+                    match &field.kind {
+                        FieldKind::Padding(bits) => {
+                            self.token("padding");
+                            self.token(bits);
+                        }
+                        FieldKind::Data { ty, name } => {
+                            self.token("data");
+                            self.token(name);
+                            self.resolved_type(ty);
+                        }
+                    }
+                }
+            }
+            MessageKind::Union(union) => {
+                self.token("union");
+                self.token(union.discriminant_bits);
+                self.token(union.variants.len());
+                for variant in &union.variants {
+                    self.token(&variant.name);
+                    self.string(&variant.comments);
+                    self.token(variant.end_offset);
+                    self.resolved_type(&variant.ty);
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn constants(&mut self, constants: &Constants) -> Option<()> {
+        self.token(constants.iter().count());
+        for (name, constant) in constants {
+            self.token(name);
+            self.primitive_type(&constant.ty);
+            match &constant.dsdl_value {
+                Value::Rational(value) => {
+                    self.token("rational");
+                    self.token(value.numer());
+                    self.token(value.denom());
+                }
+                Value::String(value) => {
+                    self.token("string");
+                    self.string(value);
+                }
+                Value::Boolean(value) => {
+                    self.token("boolean");
+                    self.bool(*value);
+                }
+                // A constant can't have any of these values
+                Value::Set(_) | Value::BitLengthSet(_) | Value::Type(_) => return None,
+            }
+            match &constant.value {
+                ConstantValue::Boolean(value) => {
+                    self.token("bool");
+                    self.bool(*value);
+                }
+                ConstantValue::Int(value) => {
+                    self.token("int");
+                    self.token(value);
+                }
+                ConstantValue::Float16(value) => {
+                    self.token("float16");
+                    self.token(value.to_bits());
+                }
+                ConstantValue::Float32(value) => {
+                    self.token("float32");
+                    self.token(value.to_bits());
+                }
+                ConstantValue::Float64(value) => {
+                    self.token("float64");
+                    self.token(value.to_bits());
+                }
+            }
+            self.string(&constant.comments);
+            self.token(constant.end_offset);
+        }
+        Some(())
+    }
+
+    fn resolved_type(&mut self, ty: &ResolvedType) {
+        match ty {
+            ResolvedType::Scalar(scalar) => {
+                self.token("scalar");
+                self.scalar_type(scalar);
+            }
+            ResolvedType::FixedArray { inner, len } => {
+                self.token("fixed");
+                self.token(len);
+                self.scalar_type(inner);
+            }
+            ResolvedType::VariableArray { inner, max_len } => {
+                self.token("variable");
+                self.token(max_len);
+                self.scalar_type(inner);
+            }
+        }
+    }
+
+    fn scalar_type(&mut self, ty: &ResolvedScalarType) {
+        match ty {
+            ResolvedScalarType::Composite { key, .. } => {
+                self.token("composite");
+                self.token(key);
+            }
+            ResolvedScalarType::Primitive(primitive) => self.primitive_type(primitive),
+            ResolvedScalarType::Void { bits } => {
+                self.token("void");
+                self.token(bits);
+            }
+        }
+    }
+
+    fn primitive_type(&mut self, ty: &PrimitiveType) {
+        match ty {
+            PrimitiveType::Boolean => self.token("bool"),
+            PrimitiveType::Utf8 => self.token("utf8"),
+            PrimitiveType::Byte => self.token("byte"),
+            PrimitiveType::Int { bits } => {
+                self.token("int");
+                self.token(bits);
+            }
+            PrimitiveType::UInt { bits, mode } => {
+                self.token("uint");
+                self.token(bits);
+                self.cast_mode(mode);
+            }
+            PrimitiveType::Float16 { mode } => {
+                self.token("float16");
+                self.cast_mode(mode);
+            }
+            PrimitiveType::Float32 { mode } => {
+                self.token("float32");
+                self.cast_mode(mode);
+            }
+            PrimitiveType::Float64 { mode } => {
+                self.token("float64");
+                self.cast_mode(mode);
+            }
+        }
+    }
+
+    fn cast_mode(&mut self, mode: &CastMode) {
+        match mode {
+            CastMode::Saturated => self.token("saturated"),
+            CastMode::Truncated => self.token("truncated"),
+        }
+    }
+}
+
+struct Decoder<'t, F> {
+    tokens: Split<'t, char>,
+    resolve: F,
+}
+
+impl<'t, F> Decoder<'t, F>
+where
+    F: Fn(&TypeKey) -> Option<Message>,
+{
+    fn next(&mut self) -> Option<&'t str> {
+        self.tokens.next()
+    }
+
+    fn parse<T: FromStr>(&mut self) -> Option<T> {
+        self.next()?.parse().ok()
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.next()? {
+            "0" => Some(false),
+            "1" => Some(true),
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let escaped = self.next()?.strip_prefix('"')?;
+        let mut value = String::with_capacity(escaped.len());
+        let mut chars = escaped.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                value.push(match chars.next()? {
+                    '\\' => '\\',
+                    's' => ' ',
+                    'n' => '\n',
+                    'r' => '\r',
+                    _ => return None,
+                });
+            } else {
+                value.push(c);
+            }
+        }
+        Some(value)
+    }
+
+    fn message(&mut self) -> Option<Message> {
+        let deprecated = self.bool()?;
+        let extent = match self.next()? {
+            "sealed" => Extent::Sealed,
+            "delimited" => Extent::Delimited(self.parse()?),
+            _ => return None,
+        };
+        let bit_length = self.parse()?;
+        let constants = self.constants()?;
+        let comments = self.string()?;
+        let kind = match self.next()? {
+            "struct" => {
+                let count: usize = self.parse()?;
+                let fields = (0..count)
+                    .map(|_| self.field())
+                    .collect::<Option<Vec<Field>>>()?;
+                MessageKind::Struct(Struct { fields })
+            }
+            "union" => {
+                let discriminant_bits = self.parse()?;
+                let count: usize = self.parse()?;
+                let variants = (0..count)
+                    .map(|_| self.variant())
+                    .collect::<Option<Vec<Variant>>>()?;
+                MessageKind::Union(Union {
+                    discriminant_bits,
+                    variants,
+                })
+            }
+            _ => return None,
+        };
+        Some(Message {
+            deprecated,
+            extent,
+            kind,
+            bit_length,
+            constants,
+            comments,
+        })
+    }
+
+    fn field(&mut self) -> Option<Field> {
+        let always_aligned = self.bool()?;
+        let comments = self.string()?;
+        let end_offset = self.parse()?;
+        let kind = match self.next()? {
+            "padding" => FieldKind::Padding(self.parse()?),
+            "data" => {
+                let name = self.next()?.to_owned();
+                let ty = self.resolved_type()?;
+                FieldKind::Data { ty, name }
+            }
+            _ => return None,
+        };
+        Some(Field {
+            kind,
+            always_aligned,
+            comments,
+            end_offset,
+        })
+    }
+
+    fn variant(&mut self) -> Option<Variant> {
+        let name = self.next()?.to_owned();
+        let comments = self.string()?;
+        let end_offset = self.parse()?;
+        let ty = self.resolved_type()?;
+        Some(Variant {
+            ty,
+            name,
+            comments,
+            end_offset,
+        })
+    }
+
+    fn constants(&mut self) -> Option<Constants> {
+        let count: usize = self.parse()?;
+        let mut constants = Constants::default();
+        for _ in 0..count {
+            let name = self.next()?.to_owned();
+            let ty = self.primitive_type()?;
+            let dsdl_value = match self.next()? {
+                "rational" => {
+                    let numer: BigInt = self.parse()?;
+                    let denom: BigInt = self.parse()?;
+                    if denom == BigInt::from(0) {
+                        return None;
+                    }
+                    Value::Rational(BigRational::new(numer, denom))
+                }
+                "string" => Value::String(StringValue::from(self.string()?)),
+                "boolean" => Value::Boolean(self.bool()?),
+                _ => return None,
+            };
+            let value = match self.next()? {
+                "bool" => ConstantValue::Boolean(self.bool()?),
+                "int" => ConstantValue::Int(self.parse()?),
+                "float16" => ConstantValue::Float16(f16::from_bits(self.parse()?)),
+                "float32" => ConstantValue::Float32(f32::from_bits(self.parse()?)),
+                "float64" => ConstantValue::Float64(f64::from_bits(self.parse()?)),
+                _ => return None,
+            };
+            let comments = self.string()?;
+            let end_offset = self.parse()?;
+            constants.insert(
+                name,
+                Constant {
+                    ty,
+                    dsdl_value,
+                    value,
+                    comments,
+                    end_offset,
+                },
+            );
+        }
+        Some(constants)
+    }
+
+    fn resolved_type(&mut self) -> Option<ResolvedType> {
+        match self.next()? {
+            "scalar" => Some(ResolvedType::Scalar(self.scalar_type()?)),
+            "fixed" => {
+                let len = self.parse()?;
+                Some(ResolvedType::FixedArray {
+                    inner: self.scalar_type()?,
+                    len,
+                })
+            }
+            "variable" => {
+                let max_len = self.parse()?;
+                Some(ResolvedType::VariableArray {
+                    inner: self.scalar_type()?,
+                    max_len,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn scalar_type(&mut self) -> Option<ResolvedScalarType> {
+        match self.next()? {
+            "composite" => {
+                let key: TypeKey = self.parse()?;
+                let inner = Box::new((self.resolve)(&key)?);
+                Some(ResolvedScalarType::Composite { key, inner })
+            }
+            "void" => Some(ResolvedScalarType::Void {
+                bits: self.parse()?,
+            }),
+            other => Some(ResolvedScalarType::Primitive(
+                self.primitive_type_named(other)?,
+            )),
+        }
+    }
+
+    fn primitive_type(&mut self) -> Option<PrimitiveType> {
+        let name = self.next()?;
+        self.primitive_type_named(name)
+    }
+
+    /// Reads the rest of a primitive type after its name
+    fn primitive_type_named(&mut self, name: &str) -> Option<PrimitiveType> {
+        match name {
+            "bool" => Some(PrimitiveType::Boolean),
+            "utf8" => Some(PrimitiveType::Utf8),
+            "byte" => Some(PrimitiveType::Byte),
+            "int" => Some(PrimitiveType::Int {
+                bits: self.parse()?,
+            }),
+            "uint" => {
+                let bits = self.parse()?;
+                Some(PrimitiveType::UInt {
+                    bits,
+                    mode: self.cast_mode()?,
+                })
+            }
+            "float16" => Some(PrimitiveType::Float16 {
+                mode: self.cast_mode()?,
+            }),
+            "float32" => Some(PrimitiveType::Float32 {
+                mode: self.cast_mode()?,
+            }),
+            "float64" => Some(PrimitiveType::Float64 {
+                mode: self.cast_mode()?,
+            }),
+            _ => None,
+        }
+    }
+
+    fn cast_mode(&mut self) -> Option<CastMode> {
+        match self.next()? {
+            "saturated" => Some(CastMode::Saturated),
+            "truncated" => Some(CastMode::Truncated),
+            _ => None,
+        }
+    }
+}
@@ -2,6 +2,7 @@ use crate::compiled::{
     CompiledDsdl, DsdlKind, Extent, Field, Message, MessageKind, Struct, Union, Variant,
 };
 use crate::constants::Constants;
+use crate::diagnostic::SourceSpan;
 use crate::error::Error;
 use crate::package::DsdlFile;
 use crate::type_key::{TypeFullName, TypeKey};
@@ -14,7 +15,7 @@ use canadensis_bit_length_set::BitLengthSet;
 use canadensis_dsdl_parser::{Identifier, Span, Statement};
 use once_cell::sync::Lazy;
 use std::collections::btree_map::Entry;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryInto;
 use std::mem;
 use std::path::PathBuf;
@@ -33,9 +34,22 @@ static BIT_LENGTH_ZERO: Lazy<BitLengthSet> = Lazy::new(|| BitLengthSet::single(0
 /// This function returns the compiled DSDL or an error. In either case, it also returns
 /// a set of warnings.
 pub(crate) fn compile(files: BTreeMap<TypeKey, DsdlFile>) -> CompileOutput {
+    compile_with_done(files, BTreeMap::new(), BTreeMap::new())
+}
+
+/// Compiles a set of files that may refer to types that have already been compiled
+///
+/// `done` contains the types that have already been compiled, and `dependencies` contains the
+/// other types that each of them refers to. These are included in the output.
+pub(crate) fn compile_with_done(
+    files: BTreeMap<TypeKey, DsdlFile>,
+    done: BTreeMap<TypeKey, CompiledDsdl>,
+    dependencies: BTreeMap<TypeKey, BTreeSet<TypeKey>>,
+) -> CompileOutput {
     let context = PersistentContext {
         pending: files,
-        done: BTreeMap::new(),
+        done,
+        dependencies,
         warnings: Warnings::new(),
     };
     context.compile()
@@ -45,6 +59,8 @@ pub(crate) fn compile(files: BTreeMap<TypeKey, DsdlFile>) -> CompileOutput {
 pub(crate) struct CompileOutput {
     /// The compiled DSDL, or an error that prevented compilation
     pub dsdl: Result<BTreeMap<TypeKey, CompiledDsdl>, Box<Error>>,
+    /// The other types that each compiled type refers to
+    pub dependencies: BTreeMap<TypeKey, BTreeSet<TypeKey>>,
     /// Any warnings reported while compiling
     pub warnings: Warnings,
 }
@@ -128,17 +144,17 @@ impl<'p> CompileContext<'p> {
     /// type.
     pub fn type_by_key(&mut self, key: TypeKey) -> Result<(TypeKey, &CompiledDsdl), Box<Error>> {
         // Look in the current package if the package is not specified
-        if key.name().path().is_empty() {
-            let canonical_key = TypeKey::new(
+        let canonical_key = if key.name().path().is_empty() {
+            TypeKey::new(
                 TypeFullName::new(self.current_file.path.clone(), key.name().name().to_owned()),
                 key.version().clone(),
-            );
-            let result = self.persistent.type_by_key(&canonical_key)?;
-            Ok((canonical_key, result))
+            )
         } else {
-            let result = self.persistent.type_by_key(&key)?;
-            Ok((key, result))
-        }
+            key
+        };
+        self.current_file.dependencies.insert(canonical_key.clone());
+        let result = self.persistent.type_by_key(&canonical_key)?;
+        Ok((canonical_key, result))
     }
 
     /// Handles a @union directive
@@ -233,6 +249,8 @@ struct PersistentContext {
     pending: BTreeMap<TypeKey, DsdlFile>,
     /// Files that have been compiled
     done: BTreeMap<TypeKey, CompiledDsdl>,
+    /// The other types that each compiled type refers to
+    dependencies: BTreeMap<TypeKey, BTreeSet<TypeKey>>,
     /// Any reported warnings
     warnings: Warnings,
 }
@@ -249,6 +267,7 @@ impl PersistentContext {
                 Err(e) => {
                     return CompileOutput {
                         dsdl: Err(e),
+                        dependencies: self.dependencies,
                         warnings: self.warnings,
                     }
                 }
@@ -256,6 +275,7 @@ impl PersistentContext {
         }
         CompileOutput {
             dsdl: Ok(self.done),
+            dependencies: self.dependencies,
             warnings: self.warnings,
         }
     }
@@ -276,7 +296,8 @@ impl PersistentContext {
         key: &TypeKey,
         input: DsdlFile,
     ) -> Result<CompiledDsdl, Box<Error>> {
-        self.warnings.check_pre_compile(key);
+        let path = input.path();
+        self.warnings.check_pre_compile(key, path);
 
         // Create a new state for this file
         let mut state = FileState::new(key.name().path());
@@ -287,7 +308,9 @@ impl PersistentContext {
         for statement in ast.statements {
             match statement {
                 Statement::Directive { name, value } => {
-                    evaluate_directive(&mut ctx(self, &mut state), name, value)?
+                    let span = name.span;
+                    evaluate_directive(&mut ctx(self, &mut state), name, value)
+                        .map_err(locate(span))?
                 }
                 Statement::Constant { ty, name, value } => {
                     if state.constants.contains_key(name.name) {
@@ -297,14 +320,20 @@ impl PersistentContext {
                             name.name
                         ));
                     }
+                    self.warnings.check_constant_name(key, path, &name);
                     let name_str = name.name;
+                    let span = name.span;
                     let new_constant =
-                        Constant::evaluate(&mut ctx(self, &mut state), ty, name, value)?;
+                        Constant::evaluate(&mut ctx(self, &mut state), ty, name, value)
+                            .map_err(locate(span))?;
                     state.constants.insert(name_str.to_owned(), new_constant);
                 }
                 Statement::Field { ty, name, span } => {
-                    let ty = convert_type(&mut ctx(self, &mut state), ty)?;
-                    let ty = ty.resolve(&mut ctx(self, &mut state), span)?;
+                    self.warnings.check_field_name(key, path, &name);
+                    let ty = convert_type(&mut ctx(self, &mut state), ty).map_err(locate(span))?;
+                    let ty = ty
+                        .resolve(&mut ctx(self, &mut state), span)
+                        .map_err(locate(span))?;
                     let ty_alignment = ty.alignment();
                     let ty_length = ty.size();
 
@@ -324,7 +353,7 @@ impl PersistentContext {
                     };
 
                     // If the field type is deprecated, this type must also be deprecated.
-                    check_deprecated_in_non_deprecated(key, &state, &ty)?;
+                    check_deprecated_in_non_deprecated(key, &state, &ty).map_err(locate(span))?;
 
                     state.add_field(ty, name, total_length, total_alignment, span)?;
                 }
@@ -337,11 +366,10 @@ impl PersistentContext {
                 Statement::Comment(comment) => state.handle_comment(comment.as_str())?,
             }
         }
+        self.dependencies
+            .insert(key.clone(), mem::take(&mut state.dependencies));
         // End of file, check that everything is here
-        let compiled = state.finish(ast.eof_span, input.fixed_port_id())?;
-
-        self.warnings.check_post_compile(key, &compiled);
-        Ok(compiled)
+        state.finish(ast.eof_span, input.fixed_port_id())
     }
 
     /// Looks up a composite type by its name and version
@@ -398,6 +426,16 @@ fn check_deprecated_in_non_deprecated(
     }
 }
 
+/// Returns a function that attaches the location of a span to an error, if the error does not
+/// already have a location
+fn locate(span: Span<'_>) -> impl Fn(Box<Error>) -> Box<Error> {
+    let span = SourceSpan::from_span(span);
+    move |e| match &*e {
+        Error::Compile(_) | Error::Located { .. } => e,
+        _ => Box::new(Error::Located { span, inner: e }),
+    }
+}
+
 trait MapExt {
     /// Checks if this map contains a mapping for the specified key, using case-sensitive comparison
     fn contains_key_case_sensitive(&self, key: &TypeKey) -> bool {
@@ -434,6 +472,8 @@ struct FileState {
     state: Option<State>,
     /// Documentation comments from the top of the file
     comments: String,
+    /// Other types that this type refers to
+    dependencies: BTreeSet<TypeKey>,
 }
 
 impl Default for FileState {
@@ -444,6 +484,7 @@ impl Default for FileState {
             deprecated: false,
             state: Some(State::Message),
             comments: String::new(),
+            dependencies: BTreeSet::new(),
         }
     }
 }
@@ -105,13 +105,13 @@ pub struct Union {
 #[derive(Debug, Clone)]
 pub struct Field {
     /// The kind of this field
-    kind: FieldKind,
+    pub(crate) kind: FieldKind,
     /// True if this field is always aligned to a multiple of 8 bits
-    always_aligned: bool,
+    pub(crate) always_aligned: bool,
     /// Documentation comments
-    comments: String,
+    pub(crate) comments: String,
     /// The offset, in bytes into the file, of the end of this field definition
-    end_offset: usize,
+    pub(crate) end_offset: usize,
 }
 
 impl Field {
@@ -187,13 +187,13 @@ impl Field {
 #[derive(Debug, Clone)]
 pub struct Variant {
     /// The type of this variant
-    ty: ResolvedType,
+    pub(crate) ty: ResolvedType,
     /// The name of this variant
-    name: String,
+    pub(crate) name: String,
     /// Documentation comments
-    comments: String,
+    pub(crate) comments: String,
     /// The offset, in bytes into the file, of the end of this variant definition
-    end_offset: usize,
+    pub(crate) end_offset: usize,
 }
 
 impl Variant {
@@ -4,20 +4,30 @@ use crate::compiled::CompiledDsdl;
 use crate::type_key::TypeKey;
 use crate::warning::Warnings;
 use std::collections::btree_map;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 /// A package of types compiled from DSDL files
 #[derive(Debug)]
 pub struct CompiledPackage {
     /// Each compiled DSDL type
     types: BTreeMap<TypeKey, CompiledDsdl>,
+    /// The other types that each type refers to
+    dependencies: BTreeMap<TypeKey, BTreeSet<TypeKey>>,
     /// Warnings reported while compiling
     warnings: Warnings,
 }
 
 impl CompiledPackage {
-    pub(crate) fn new(types: BTreeMap<TypeKey, CompiledDsdl>, warnings: Warnings) -> Self {
-        CompiledPackage { types, warnings }
+    pub(crate) fn new(
+        types: BTreeMap<TypeKey, CompiledDsdl>,
+        dependencies: BTreeMap<TypeKey, BTreeSet<TypeKey>>,
+        warnings: Warnings,
+    ) -> Self {
+        CompiledPackage {
+            types,
+            dependencies,
+            warnings,
+        }
     }
 
     /// Returns a reference to the type with the provided key
@@ -40,6 +50,15 @@ impl CompiledPackage {
         Iter(self.types.iter())
     }
 
+    /// Returns the other types that the type with the provided key directly refers to, as field
+    /// types or in expressions
+    ///
+    /// This function returns None if this package does not contain a type with the provided key.
+    #[inline]
+    pub fn dependencies(&self, key: &TypeKey) -> Option<&BTreeSet<TypeKey>> {
+        self.dependencies.get(key)
+    }
+
     /// Returns the warnings reported while compiling
     #[inline]
     pub fn warnings(&self) -> &Warnings {
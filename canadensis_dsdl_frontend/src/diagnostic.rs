@@ -0,0 +1,167 @@
+//!
+//! Structured descriptions of errors and warnings, with source locations
+//!
+//! A [`Diagnostic`] contains everything an editor or a command-line tool needs to point a user
+//! to the problem: the file, the line and column span, and an optional suggested replacement.
+//!
+
+use crate::TypeKey;
+use canadensis_dsdl_parser::Span;
+use std::fmt;
+use std::path::PathBuf;
+
+/// The severity of a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// A problem that prevented compilation
+    Error,
+    /// A non-fatal problem
+    Warning,
+}
+
+impl Severity {
+    /// Returns the lowercase name of this severity, like `error` or `warning`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A line and column in a source file
+///
+/// Both values start at 1. Columns are counted in characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineColumn {
+    /// The line number
+    pub line: usize,
+    /// The column number
+    pub column: usize,
+}
+
+impl fmt::Display for LineColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A range of text in a source file
+///
+/// The end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceSpan {
+    /// The line and column where this span starts
+    pub start: LineColumn,
+    /// The line and column immediately after the end of this span
+    pub end: LineColumn,
+    /// The byte offset where this span starts
+    pub start_byte: usize,
+    /// The byte offset immediately after the end of this span
+    pub end_byte: usize,
+}
+
+impl SourceSpan {
+    /// Creates a source span from a parser span
+    pub(crate) fn from_span(span: Span<'_>) -> Self {
+        let (start_line, start_column) = span.start_pos().line_col();
+        let (end_line, end_column) = span.end_pos().line_col();
+        SourceSpan {
+            start: LineColumn {
+                line: start_line,
+                column: start_column,
+            },
+            end: LineColumn {
+                line: end_line,
+                column: end_column,
+            },
+            start_byte: span.start(),
+            end_byte: span.end(),
+        }
+    }
+
+    /// Creates a source span from the location of a parser error
+    pub(crate) fn from_parse_error(error: &canadensis_dsdl_parser::Error) -> Self {
+        let ((start_line, start_column), (end_line, end_column)) = error.line_col();
+        let bytes = error.byte_range();
+        SourceSpan {
+            start: LineColumn {
+                line: start_line,
+                column: start_column,
+            },
+            end: LineColumn {
+                line: end_line,
+                column: end_column,
+            },
+            start_byte: bytes.start,
+            end_byte: bytes.end,
+        }
+    }
+}
+
+/// A suggested change that may fix the problem that a diagnostic describes
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Suggestion {
+    /// The text to replace
+    ///
+    /// If this is None, the suggestion can't be applied automatically.
+    pub span: Option<SourceSpan>,
+    /// The text to put in place of the span
+    pub replacement: String,
+}
+
+/// An error or warning with source location information
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The severity
+    pub severity: Severity,
+    /// A description of the problem, without location information
+    pub message: String,
+    /// The type that the problem was found in, if known
+    pub key: Option<TypeKey>,
+    /// The path to the file that the problem was found in
+    ///
+    /// This is None if the problem is not associated with a file, or the DSDL was provided as
+    /// a string.
+    pub path: Option<PathBuf>,
+    /// The location of the problem in the file
+    pub span: Option<SourceSpan>,
+    /// A change that may fix the problem
+    pub suggestion: Option<Suggestion>,
+    /// Additional information, like which other types depend on the type with the problem
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic with a message and no location
+    pub(crate) fn new(severity: Severity, message: String) -> Self {
+        Diagnostic {
+            severity,
+            message,
+            key: None,
+            path: None,
+            span: None,
+            suggestion: None,
+            notes: vec![],
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+        if let Some(path) = &self.path {
+            write!(f, " at {}", path.display())?;
+            if let Some(span) = &self.span {
+                write!(f, ":{}", span.start)?;
+            }
+        }
+        Ok(())
+    }
+}
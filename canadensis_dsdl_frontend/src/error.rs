@@ -1,3 +1,4 @@
+use crate::diagnostic::{Diagnostic, Severity, SourceSpan};
 use crate::package::TYPE_NAME_LENGTH_MAX;
 use crate::type_key::TypeKey;
 use std::io;
@@ -54,6 +55,15 @@ pub enum Error {
         #[source]
         inner: Box<Error>,
     },
+    /// An error caused by a particular part of a file
+    ///
+    /// This attaches a location to errors that do not have one, like [`Error::UnknownType`].
+    #[error("Error at line {}, column {}", .span.start.line, .span.start.column)]
+    Located {
+        span: SourceSpan,
+        #[source]
+        inner: Box<Error>,
+    },
     #[error("Invalid DSDL")]
     Compile(
         #[from]
@@ -69,3 +79,103 @@ pub enum Error {
     #[error("Input/output error")]
     Io(#[from] io::Error),
 }
+
+impl Error {
+    /// Converts this error into a diagnostic with the path, location, and message of the
+    /// innermost problem
+    ///
+    /// If the problem is in a type that another type depends on, the diagnostic has notes
+    /// describing the types that depend on it.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(Severity::Error, String::new());
+        let mut current = self;
+        loop {
+            match current {
+                Error::CompileFile { key, path, inner } => {
+                    if let Some(dependent) = diagnostic.key.take() {
+                        diagnostic.notes.push(dependent_note(
+                            &dependent,
+                            diagnostic.path.take(),
+                            diagnostic.span.take(),
+                        ));
+                    }
+                    diagnostic.key = Some(key.clone());
+                    diagnostic.path = path.clone();
+                    current = inner;
+                }
+                Error::Located { span, inner } => {
+                    diagnostic.span = Some(*span);
+                    current = inner;
+                }
+                Error::Compile(inner) => {
+                    diagnostic.message = inner.message();
+                    diagnostic.span = Some(SourceSpan::from_parse_error(inner));
+                    break;
+                }
+                other => {
+                    diagnostic.message = message_with_sources(other);
+                    if let Some(path) = other.path() {
+                        diagnostic.path = Some(path.clone());
+                    }
+                    if diagnostic.key.is_none() {
+                        diagnostic.key = other.key().cloned();
+                    }
+                    break;
+                }
+            }
+        }
+        diagnostic
+    }
+
+    /// Returns the path that this error directly refers to, if any
+    fn path(&self) -> Option<&PathBuf> {
+        match self {
+            Error::WalkDir { root, .. } => Some(root),
+            Error::NotDirectory(path)
+            | Error::FileRead { path, .. }
+            | Error::PathUtf8(path)
+            | Error::FileName(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the type that this error directly refers to, if any
+    fn key(&self) -> Option<&TypeKey> {
+        match self {
+            Error::NameKeyword { key, .. }
+            | Error::NameInvalidIdentifier { key, .. }
+            | Error::TypeNameLength { key, .. } => Some(key),
+            Error::VersionZero(key) | Error::TypeNotInNamespace(key) => Some(key),
+            Error::DuplicateKey { new, .. } => Some(new),
+            Error::DeprecatedInNonDeprecated { outer, .. } => Some(outer),
+            _ => None,
+        }
+    }
+}
+
+/// Formats an error and all its sources on one line
+fn message_with_sources(error: &Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(inner) = source {
+        message.push_str(": ");
+        message.push_str(&inner.to_string());
+        source = inner.source();
+    }
+    message
+}
+
+/// Creates a note explaining that a type with an error is used by another type
+fn dependent_note(dependent: &TypeKey, path: Option<PathBuf>, span: Option<SourceSpan>) -> String {
+    match (path, span) {
+        (Some(path), Some(span)) => format!(
+            "required by {} at {}:{}",
+            dependent,
+            path.display(),
+            span.start
+        ),
+        (Some(path), None) => format!("required by {} at {}", dependent, path.display()),
+        (None, Some(span)) => format!("required by {} at {}", dependent, span.start),
+        (None, None) => format!("required by {}", dependent),
+    }
+}
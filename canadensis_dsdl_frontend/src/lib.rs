@@ -19,9 +19,11 @@ macro_rules! span_error {
     };
 }
 
+pub mod cache;
 pub(crate) mod compile;
 pub mod compiled;
 pub mod constants;
+pub mod diagnostic;
 pub(crate) mod error;
 pub(crate) mod operators;
 mod package;
@@ -1,3 +1,4 @@
+use crate::cache::{hash_source, Changes, CompileCache};
 use crate::compile::CompileOutput;
 use crate::compiled::package::CompiledPackage;
 use crate::error::Error;
@@ -178,14 +179,85 @@ impl Package {
         match crate::compile::compile(self.files) {
             CompileOutput {
                 dsdl: Ok(types),
+                dependencies,
                 warnings,
-            } => Ok(CompiledPackage::new(types, warnings)),
+            } => Ok(CompiledPackage::new(types, dependencies, warnings)),
             CompileOutput {
                 dsdl: Err(e),
                 warnings,
+                ..
             } => Err((e, warnings)),
         }
     }
+
+    /// Compares the files in this package with a cache, without compiling anything
+    ///
+    /// If the returned changes are empty, code generated from the last compilation that updated
+    /// the cache is still valid.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if any DSDL file could not be read.
+    pub fn changes(&self, cache: &CompileCache) -> Result<Changes, Box<Error>> {
+        Ok(cache.changes(&self.source_hashes()?))
+    }
+
+    /// Compiles all input files that were previously added, using a cache of compiled types,
+    /// and updates the cache with the new types
+    ///
+    /// Types whose DSDL files and dependencies have not changed since the cache was last updated
+    /// are read from the cache. Only the other types are parsed and evaluated. Warnings are only
+    /// reported for the types that were compiled.
+    ///
+    /// This function returns the compiled package and the changes since the cache was last
+    /// updated. If compilation fails, the cache is not modified.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if any DSDL file could not be read, if any DSDL file has
+    /// invalid content, or an `@assert` directive fails.
+    pub fn compile_with_cache(
+        self,
+        cache: &mut CompileCache,
+    ) -> Result<(CompiledPackage, Changes), Box<Error>> {
+        let source_hashes = self.source_hashes()?;
+        let changes = cache.changes(&source_hashes);
+        let (cached_types, cached_dependencies) = cache.load_types(&self.files, &source_hashes);
+        let mut files = self.files;
+        files.retain(|key, _| !cached_types.contains_key(key));
+        let package = match crate::compile::compile_with_done(
+            files,
+            cached_types,
+            cached_dependencies,
+        ) {
+            CompileOutput {
+                dsdl: Ok(types),
+                dependencies,
+                warnings,
+            } => CompiledPackage::new(types, dependencies, warnings),
+            CompileOutput { dsdl: Err(e), .. } => return Err(e),
+        };
+        cache.update(&source_hashes, &package);
+        Ok((package, changes))
+    }
+
+    /// Returns the paths of all files in this package
+    ///
+    /// Types that were added with [`add_string`](Self::add_string) do not have paths.
+    pub fn source_paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.values().filter_map(DsdlFile::path)
+    }
+
+    /// Reads each file and returns a hash of its content
+    fn source_hashes(&self) -> Result<BTreeMap<TypeKey, u64>, Box<Error>> {
+        self.files
+            .iter()
+            .map(|(key, file)| {
+                let text = file.read()?;
+                Ok((key.clone(), hash_source(text.as_bytes())))
+            })
+            .collect()
+    }
 }
 
 /// Checks that the provided key has a package and does not contain any reserved keywords,
@@ -5,7 +5,7 @@ pub(crate) mod directive;
 pub(crate) mod expression;
 pub(crate) mod keywords;
 pub(crate) mod set;
-mod string;
+pub(crate) mod string;
 
 use crate::compile::CompileContext;
 use crate::compiled::{DsdlKind, Extent, Message};
@@ -14,18 +14,18 @@ use std::ops::Range;
 #[derive(Debug, Clone)]
 pub struct Constant {
     /// The declared type of the constant
-    ty: PrimitiveType,
+    pub(crate) ty: PrimitiveType,
     /// The value of the constant, used to evaluate DSDL expressions
     ///
     /// The type of this value may not be equal to its declared type.
-    dsdl_value: Value,
+    pub(crate) dsdl_value: Value,
     /// The possibly-simplified value of this constant that is exposed to client code in other
     /// crates and can be used to generate code
-    value: ConstantValue,
+    pub(crate) value: ConstantValue,
     /// Documentation comments for this constant
-    comments: String,
+    pub(crate) comments: String,
     /// The offset, in bytes into the file, of the end of this constant definition
-    end_offset: usize,
+    pub(crate) end_offset: usize,
 }
 
 impl Constant {
@@ -1,20 +1,77 @@
 use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 
+use canadensis_dsdl_parser::Identifier;
 use heck::{ToShoutySnakeCase, ToSnakeCase, ToUpperCamelCase};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use crate::compiled::{CompiledDsdl, DsdlKind, Message, MessageKind};
+use crate::diagnostic::{Diagnostic, Severity, SourceSpan, Suggestion};
 use crate::TypeKey;
 
 /// A non-fatal warning encountered while processing DSDL
+///
+/// Warnings are ordered by file and location.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Warning(WarningKind);
+pub struct Warning {
+    /// The file that caused this warning, if it came from a file
+    path: Option<PathBuf>,
+    /// The location of the problem in the file
+    span: Option<SourceSpan>,
+    kind: WarningKind,
+}
+
+impl Warning {
+    /// Returns the path to the file that caused this warning
+    ///
+    /// This is None if the warning applies to a whole package, or the DSDL was provided as
+    /// a string.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the location of the problem in the file
+    ///
+    /// This is None if the warning is about the name of a type or package, which comes from the
+    /// file path instead of the file content.
+    pub fn span(&self) -> Option<&SourceSpan> {
+        self.span.as_ref()
+    }
+
+    /// Returns a suggested replacement for the text that caused this warning
+    pub fn suggestion(&self) -> Suggestion {
+        let replacement = match &self.kind {
+            WarningKind::PackageCase { suggestion, .. }
+            | WarningKind::FieldCase { suggestion, .. }
+            | WarningKind::ConstantCase { suggestion, .. }
+            | WarningKind::TypeNameCase { suggestion, .. } => suggestion.clone(),
+        };
+        Suggestion {
+            span: self.span,
+            replacement,
+        }
+    }
+
+    /// Converts this warning into a diagnostic
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(Severity::Warning, self.kind.to_string());
+        diagnostic.key = match &self.kind {
+            WarningKind::PackageCase { .. } => None,
+            WarningKind::FieldCase { key, .. }
+            | WarningKind::ConstantCase { key, .. }
+            | WarningKind::TypeNameCase { key, .. } => Some(key.clone()),
+        };
+        diagnostic.path = self.path.clone();
+        diagnostic.span = self.span;
+        diagnostic.suggestion = Some(self.suggestion());
+        diagnostic
+    }
+}
 
 impl std::fmt::Display for Warning {
     #[inline]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Display::fmt(&self.0, f)
+        std::fmt::Display::fmt(&self.kind, f)
     }
 }
 
@@ -124,83 +181,91 @@ impl Warnings {
 
     /// Inserts a warning, or does nothing if this set of warnings already contains the provided
     /// warning
-    fn insert(&mut self, kind: WarningKind) {
-        self.warnings.insert(Warning(kind));
+    fn insert(&mut self, path: Option<&Path>, span: Option<SourceSpan>, kind: WarningKind) {
+        self.warnings.insert(Warning {
+            path: path.map(PathBuf::from),
+            span,
+            kind,
+        });
     }
 
     /// Checks for warnings on a type before it is compiled and adds any warnings to this collection
-    pub(crate) fn check_pre_compile(&mut self, key: &TypeKey) {
+    ///
+    /// `path` is the path to the file that defines the type, if it came from a file.
+    pub(crate) fn check_pre_compile(&mut self, key: &TypeKey, path: Option<&Path>) {
         // All package segments should be snake_case
         for part in key.name().path() {
             if !is_permissive_snake_case(part) {
                 let suggestion = part.to_snake_case();
-                self.insert(WarningKind::PackageCase {
-                    name: part.to_owned(),
-                    suggestion,
-                })
+                // Package warnings are not associated with a file, so that each package
+                // gets only one warning.
+                self.insert(
+                    None,
+                    None,
+                    WarningKind::PackageCase {
+                        name: part.to_owned(),
+                        suggestion,
+                    },
+                )
             }
         }
         // Type name should be UpperCamelCase
         let actual_name = key.name().name();
         if !is_permissive_upper_camel_case(actual_name) {
             let suggestion = actual_name.to_upper_camel_case();
-            self.insert(WarningKind::TypeNameCase {
-                key: key.clone(),
-                suggestion,
-            })
+            self.insert(
+                path,
+                None,
+                WarningKind::TypeNameCase {
+                    key: key.clone(),
+                    suggestion,
+                },
+            )
         }
     }
 
-    /// Checks for warnings on a compiled type and adds any warnings to this collection
-    pub(crate) fn check_post_compile(&mut self, key: &TypeKey, dsdl: &CompiledDsdl) {
-        match &dsdl.kind {
-            DsdlKind::Message(message) => self.check_message(key, message),
-            DsdlKind::Service { request, response } => {
-                self.check_message(key, request);
-                self.check_message(key, response);
-            }
+    /// Checks the name of a field or union variant and adds any warnings to this collection
+    pub(crate) fn check_field_name(
+        &mut self,
+        key: &TypeKey,
+        path: Option<&Path>,
+        name: &Identifier<'_>,
+    ) {
+        // Should contain _, a-z, or 0-9
+        let snake_case = is_permissive_snake_case(name.name);
+        if !snake_case {
+            let suggested = name.name.to_snake_case();
+            self.insert(
+                path,
+                Some(SourceSpan::from_span(name.span)),
+                WarningKind::FieldCase {
+                    key: key.to_owned(),
+                    name: name.name.to_owned(),
+                    suggestion: suggested,
+                },
+            )
         }
     }
 
-    fn check_message(&mut self, key: &TypeKey, message: &Message) {
-        // Fields should be snake_case
-        match &message.kind {
-            MessageKind::Struct(struct_data) => {
-                for field in &struct_data.fields {
-                    if let Some(name) = field.name() {
-                        self.check_field_name(key, name);
-                    }
-                }
-            }
-            MessageKind::Union(union_data) => {
-                for variant in &union_data.variants {
-                    self.check_field_name(key, variant.name());
-                }
-            }
-        }
+    /// Checks the name of a constant and adds any warnings to this collection
+    pub(crate) fn check_constant_name(
+        &mut self,
+        key: &TypeKey,
+        path: Option<&Path>,
+        name: &Identifier<'_>,
+    ) {
         // Constants should be SCREAMING_SNAKE_CASE
-        for (name, _) in &message.constants {
-            let suggestion = name.to_shouty_snake_case();
-            if name != &suggestion {
-                self.insert(WarningKind::ConstantCase {
+        let suggestion = name.name.to_shouty_snake_case();
+        if name.name != suggestion {
+            self.insert(
+                path,
+                Some(SourceSpan::from_span(name.span)),
+                WarningKind::ConstantCase {
                     key: key.to_owned(),
-                    name: name.to_owned(),
+                    name: name.name.to_owned(),
                     suggestion,
-                })
-            }
-        }
-    }
-
-    fn check_field_name(&mut self, key: &TypeKey, name: &str) {
-        // Should contain _, a-z, or 0-9
-        let snake_case = is_permissive_snake_case(name);
-        if !snake_case {
-            let suggested = name.to_snake_case();
-            self.insert(WarningKind::FieldCase {
-                key: key.to_owned(),
-                name: name.to_owned(),
-                suggestion: suggested,
-            })
+                },
+            )
         }
     }
 
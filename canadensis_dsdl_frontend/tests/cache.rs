@@ -0,0 +1,144 @@
+extern crate canadensis_dsdl_frontend;
+
+use canadensis_dsdl_frontend::cache::CompileCache;
+use canadensis_dsdl_frontend::{Package, TypeKey};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+fn keys(strings: &[&str]) -> BTreeSet<TypeKey> {
+    strings.iter().map(|s| s.parse().unwrap()).collect()
+}
+
+fn load_package(root: &Path) -> Package {
+    let mut package = Package::new();
+    package.add_files(root).unwrap();
+    package
+}
+
+/// Compiles a package, modifies a type that another type depends on, and checks that the cache
+/// reports both types as modified
+#[test]
+fn cache_detects_dependency_change() -> io::Result<()> {
+    let test_dir =
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cache_detects_dependency_change");
+    let _ = fs::remove_dir_all(&test_dir);
+    let package_dir = test_dir.join("changes_test");
+    fs::create_dir_all(&package_dir)?;
+    fs::write(
+        package_dir.join("Inner.1.0.dsdl"),
+        "uint8 MAX = 3\nuint8 value\n@sealed\n",
+    )?;
+    fs::write(
+        package_dir.join("Outer.1.0.dsdl"),
+        "Inner.1.0 inner\n@sealed\n",
+    )?;
+    fs::write(
+        package_dir.join("UsesConstant.1.0.dsdl"),
+        "uint8 SIZE = Inner.1.0.MAX\n@sealed\n",
+    )?;
+    fs::write(
+        package_dir.join("Unrelated.1.0.dsdl"),
+        "bool flag\n@sealed\n",
+    )?;
+    let cache_path = test_dir.join("dsdl.cache");
+
+    // First compilation: everything is new
+    let mut cache = CompileCache::load(&cache_path)?;
+    assert!(cache.is_empty());
+    let (_, changes) = load_package(&test_dir)
+        .compile_with_cache(&mut cache)
+        .unwrap();
+    assert_eq!(
+        &keys(&[
+            "changes_test.UsesConstant.1.0",
+            "changes_test.Inner.1.0",
+            "changes_test.Outer.1.0",
+            "changes_test.Unrelated.1.0"
+        ]),
+        changes.added()
+    );
+    cache.save(&cache_path)?;
+
+    // Nothing changed
+    let cache = CompileCache::load(&cache_path)?;
+    let package = load_package(&test_dir);
+    assert_eq!(4, package.source_paths().count());
+    assert!(package.changes(&cache).unwrap().is_empty());
+
+    // Change the inner type. The outer type and the type with a constant that refers to the
+    // inner type also change.
+    fs::write(
+        package_dir.join("Inner.1.0.dsdl"),
+        "uint8 MAX = 3\nuint16 value\n@sealed\n",
+    )?;
+    fs::remove_file(package_dir.join("Unrelated.1.0.dsdl"))?;
+    let changes = load_package(&test_dir).changes(&cache).unwrap();
+    assert!(changes.added().is_empty());
+    assert_eq!(
+        &keys(&[
+            "changes_test.UsesConstant.1.0",
+            "changes_test.Inner.1.0",
+            "changes_test.Outer.1.0"
+        ]),
+        changes.modified()
+    );
+    assert_eq!(&keys(&["changes_test.Unrelated.1.0"]), changes.removed());
+
+    fs::remove_dir_all(&test_dir)
+}
+
+/// Compiles a package with a cache, then again with the same files, and checks that the second
+/// compilation reads every type from the cache
+#[test]
+fn cache_reuses_compiled_types() -> io::Result<()> {
+    let test_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cache_reuses_compiled_types");
+    let _ = fs::remove_dir_all(&test_dir);
+    fs::create_dir_all(&test_dir)?;
+    let cache_path = test_dir.join("dsdl.cache");
+    let dsdl_root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/simple_dsdl");
+
+    let load = || {
+        let mut package = load_package(&dsdl_root);
+        // This field name causes a warning
+        package
+            .add_string(
+                None,
+                "canadensis.Warning.1.0".parse().unwrap(),
+                "bool Flag\n@sealed\n".into(),
+            )
+            .unwrap();
+        package
+    };
+    let expected = describe_types(load().compile().unwrap());
+
+    let mut cache = CompileCache::load(&cache_path)?;
+    let (package, _) = load().compile_with_cache(&mut cache).unwrap();
+    assert!(!package.warnings().is_empty());
+    assert_eq!(expected, describe_types(package));
+    cache.save(&cache_path)?;
+
+    let mut cache = CompileCache::load(&cache_path)?;
+    let (package, changes) = load().compile_with_cache(&mut cache).unwrap();
+    assert!(changes.is_empty());
+    // The types were not compiled again, so there are no warnings
+    assert!(package.warnings().is_empty());
+    assert_eq!(expected, describe_types(package));
+
+    fs::remove_dir_all(&test_dir)
+}
+
+/// Returns the debug representation and dependencies of each type in a package
+fn describe_types(
+    package: canadensis_dsdl_frontend::compiled::package::CompiledPackage,
+) -> BTreeMap<TypeKey, (String, BTreeSet<TypeKey>)> {
+    package
+        .iter()
+        .map(|(key, dsdl)| {
+            let dependencies = package.dependencies(key).unwrap().clone();
+            (key.clone(), (format!("{:?}", dsdl), dependencies))
+        })
+        .collect()
+}
@@ -6,7 +6,6 @@ extern crate canadensis_dsdl_frontend;
 use canadensis_dsdl_frontend::compiled::package::CompiledPackage;
 use canadensis_dsdl_frontend::compiled::DsdlKind;
 use canadensis_dsdl_frontend::{Error, Package, TypeKey};
-use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::{fs, io};
@@ -56,9 +55,11 @@ fn test_cycle() {
             // Failed to compile one of the two files
             // |- Failed to compile the other one of the two files
             //    |- Couldn't find the type in the outermost file
-            match &*e {
-                Error::CompileFile { inner, .. } => match inner.deref() {
-                    Error::CompileFile { inner, .. } => match inner.deref() {
+            // Each file's error may be wrapped in an Error::Located with the location of the field
+            // that caused it.
+            match skip_located(&e) {
+                Error::CompileFile { inner, .. } => match skip_located(inner) {
+                    Error::CompileFile { inner, .. } => match skip_located(inner) {
                         Error::UnknownType(_) => { /* OK */ }
                         _ => panic!("Unexpected error {:#?}", e),
                     },
@@ -70,6 +71,14 @@ fn test_cycle() {
     }
 }
 
+/// Returns the error inside any `Error::Located` wrappers
+fn skip_located(mut error: &Error) -> &Error {
+    while let Error::Located { inner, .. } = error {
+        error = inner;
+    }
+    error
+}
+
 /// Checks that when compiling a deprecated service type, both the request and response are marked
 /// as deprecated
 #[test]
@@ -0,0 +1,105 @@
+extern crate canadensis_dsdl_frontend;
+
+use canadensis_dsdl_frontend::diagnostic::{LineColumn, Severity};
+use canadensis_dsdl_frontend::{Package, TypeKey};
+use std::path::PathBuf;
+
+fn key(s: &str) -> TypeKey {
+    s.parse().expect("Invalid type key")
+}
+
+/// Checks that an error caused by the content of a file has the path and location
+#[test]
+fn error_from_file_has_location() {
+    let mut package = Package::new();
+    package
+        .add_files(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/compile_fail/duplicate_fields"),
+        )
+        .unwrap();
+    let error = package.compile().unwrap_err();
+    let diagnostic = error.to_diagnostic();
+
+    assert_eq!(Severity::Error, diagnostic.severity);
+    assert_eq!("A field named a already exists", diagnostic.message);
+    assert_eq!(Some(key("canadensis.DuplicateFields.1.0")), diagnostic.key);
+    assert!(diagnostic
+        .path
+        .unwrap()
+        .ends_with("canadensis/DuplicateFields.1.0.uavcan"));
+    let span = diagnostic.span.unwrap();
+    assert_eq!(LineColumn { line: 4, column: 1 }, span.start);
+}
+
+/// Checks that an error without its own location gets the location of the statement that
+/// caused it, and that the type that uses the type with the error is noted
+#[test]
+fn unknown_type_has_location_and_dependent() {
+    let mut package = Package::new();
+    package
+        .add_string(
+            None,
+            key("test.Outer.1.0"),
+            "test.Part.1.0 part\n@sealed\n".into(),
+        )
+        .unwrap();
+    package
+        .add_string(
+            None,
+            key("test.Part.1.0"),
+            "uint8 a\ntest.Missing.1.0 missing\n@sealed\n".into(),
+        )
+        .unwrap();
+    let diagnostic = package.compile().unwrap_err().to_diagnostic();
+
+    assert_eq!("Type test.Missing.1.0 not found", diagnostic.message);
+    assert_eq!(Some(key("test.Part.1.0")), diagnostic.key);
+    assert_eq!(None, diagnostic.path);
+    assert_eq!(
+        LineColumn { line: 2, column: 1 },
+        diagnostic.span.unwrap().start
+    );
+    assert_eq!(
+        vec!["required by test.Outer.1.0 at 1:1".to_owned()],
+        diagnostic.notes
+    );
+}
+
+/// Checks that warnings about field and constant names have locations and suggestions
+#[test]
+fn warnings_have_locations_and_suggestions() {
+    let mut package = Package::new();
+    package
+        .add_string(
+            None,
+            key("test.Names.1.0"),
+            "uint8 lowerConstant = 3\nuint8 fieldName\n@sealed\n".into(),
+        )
+        .unwrap();
+    let compiled = package.compile().unwrap();
+    let diagnostics: Vec<_> = compiled
+        .warnings()
+        .iter()
+        .map(|warning| warning.to_diagnostic())
+        .collect();
+    assert_eq!(2, diagnostics.len());
+
+    let constant = &diagnostics[0];
+    assert_eq!(Severity::Warning, constant.severity);
+    let span = constant.span.unwrap();
+    assert_eq!(LineColumn { line: 1, column: 7 }, span.start);
+    assert_eq!(
+        LineColumn {
+            line: 1,
+            column: 20
+        },
+        span.end
+    );
+    let suggestion = constant.suggestion.as_ref().unwrap();
+    assert_eq!("LOWER_CONSTANT", suggestion.replacement);
+    assert_eq!(Some(span), suggestion.span);
+
+    let field = &diagnostics[1];
+    assert_eq!(LineColumn { line: 2, column: 7 }, field.span.unwrap().start);
+    assert_eq!("field_name", field.suggestion.as_ref().unwrap().replacement);
+}
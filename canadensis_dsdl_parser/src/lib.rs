@@ -15,7 +15,8 @@ use pest::Parser;
 
 pub use crate::ast::types::*;
 
-use pest::error::ErrorVariant;
+use pest::error::{ErrorVariant, InputLocation, LineColLocation};
+use std::ops::Range;
 /// A range of characters in the input text
 ///
 pub use pest::Span;
@@ -57,6 +58,34 @@ where
     )))
 }
 
+impl Error {
+    /// Returns the message describing this error, without any location information
+    pub fn message(&self) -> String {
+        self.0.variant.message().into_owned()
+    }
+
+    /// Returns the one-based line and column numbers of the start and end of the text that caused
+    /// this error
+    ///
+    /// If the error refers to a single position instead of a span, the start and end are equal.
+    pub fn line_col(&self) -> ((usize, usize), (usize, usize)) {
+        match self.0.line_col {
+            LineColLocation::Pos(pos) => (pos, pos),
+            LineColLocation::Span(start, end) => (start, end),
+        }
+    }
+
+    /// Returns the byte offsets of the start and end of the text that caused this error
+    ///
+    /// If the error refers to a single position instead of a span, the start and end are equal.
+    pub fn byte_range(&self) -> Range<usize> {
+        match self.0.location {
+            InputLocation::Pos(pos) => pos..pos,
+            InputLocation::Span((start, end)) => start..end,
+        }
+    }
+}
+
 mod error_impl {
     use super::Error;
     use std::fmt;
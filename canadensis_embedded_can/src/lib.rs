@@ -0,0 +1,398 @@
+#![no_std]
+#![deny(missing_docs)]
+
+//!
+//! # Canadensis compatibility for embedded-can drivers
+//!
+//! This library provides a driver that works with any CAN peripheral driver that implements
+//! [`embedded_can::nb::Can`], and functions that convert between `embedded_can` frames and
+//! `canadensis_can` frames.
+//!
+//! The `embedded_can` traits do not provide any way to configure hardware filters or to remove
+//! a frame from a transmit buffer after its deadline has passed, so [`EmbeddedCanDriver`] can
+//! optionally do both in software. See [`EmbeddedCanDriver::set_deadline_tracking`] and
+//! [`EmbeddedCanDriver::set_software_filtering`].
+//!
+
+extern crate alloc;
+
+extern crate canadensis_can;
+extern crate canadensis_core;
+extern crate canadensis_filter_config;
+extern crate embedded_can;
+extern crate heapless;
+
+use alloc::vec::Vec;
+use canadensis_can::driver::{optimize_filters, ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanId, CanNodeId, Frame, FRAME_CAPACITY};
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{Clock, Microseconds32};
+use canadensis_core::{nb, OutOfMemoryError};
+use canadensis_filter_config::Filter;
+use core::convert::TryFrom;
+use embedded_can::nb::Can;
+use embedded_can::{ExtendedId, Id};
+use heapless::Deque;
+
+/// Maximum number of loopback frames that can be stored
+const LOOPBACK_CAPACITY: usize = 2;
+
+/// Maximum number of transmitted frames that can be remembered for deadline tracking
+///
+/// This should be at least the number of transmit buffers in the CAN peripheral.
+const TRACKED_CAPACITY: usize = 8;
+
+/// A CAN driver that wraps a CAN peripheral driver that implements [`embedded_can::nb::Can`]
+///
+/// Frames are sent and received with extended IDs. Frames with standard IDs and remote frames
+/// are ignored when received. They may belong to other protocols on the same bus, so they are
+/// not counted as errors.
+///
+/// # Deadline tracking
+///
+/// When all transmit buffers are full, the peripheral driver may remove a lower-priority frame
+/// from a transmit buffer to make space for a new frame. With deadline tracking enabled, this
+/// driver remembers the last few frames that it sent, so it can return a removed frame with
+/// its original deadline and loopback flag. A removed frame whose deadline has passed is dropped.
+///
+/// Without deadline tracking, or if a removed frame is older than the remembered frames, a removed
+/// frame gets the deadline of the frame that replaced it.
+///
+/// Deadline tracking does not remove frames from the transmit buffers when their deadlines pass,
+/// because `embedded_can` does not provide a way to do that.
+///
+/// # Software filtering
+///
+/// With software filtering enabled, this driver discards received frames that do not match
+/// the filters from the last call to `apply_filters`. This is useful when the peripheral does
+/// not have hardware filters, or they have not been configured.
+pub struct EmbeddedCanDriver<D> {
+    can: D,
+    /// If removed frames should be matched with the frames that were sent
+    deadline_tracking: bool,
+    /// Copies of recently sent frames, oldest first, if deadline tracking is enabled
+    sent_frames: Deque<Frame, TRACKED_CAPACITY>,
+    /// If received frames should be checked against `filters`
+    software_filtering: bool,
+    /// The filters for received frames, or None to accept all frames
+    filters: Option<Vec<Filter>>,
+    /// Copies of transmitted loopback frames that have not yet been received
+    loopback_frames: Deque<Frame, LOOPBACK_CAPACITY>,
+    /// Frames sent, and frames that could not be sent or missed their deadlines
+    transmit_statistics: IoStatistics,
+    /// Frames received, and receive errors and frames that could not be used
+    receive_statistics: IoStatistics,
+}
+
+impl<D> EmbeddedCanDriver<D>
+where
+    D: Can,
+{
+    /// Creates a driver with deadline tracking and software filtering disabled
+    pub fn new(can: D) -> Self {
+        EmbeddedCanDriver {
+            can,
+            deadline_tracking: false,
+            sent_frames: Deque::new(),
+            software_filtering: false,
+            filters: None,
+            loopback_frames: Deque::new(),
+            transmit_statistics: IoStatistics::default(),
+            receive_statistics: IoStatistics::default(),
+        }
+    }
+
+    /// Enables or disables deadline tracking for frames that the peripheral driver removes from
+    /// its transmit buffers
+    pub fn set_deadline_tracking(&mut self, deadline_tracking: bool) {
+        self.deadline_tracking = deadline_tracking;
+        if !deadline_tracking {
+            self.sent_frames.clear();
+        }
+    }
+
+    /// Enables or disables filtering of received frames in software
+    ///
+    /// The filters come from the most recent call to `apply_filters` or `apply_accept_all`,
+    /// even if that call was made while software filtering was disabled.
+    pub fn set_software_filtering(&mut self, software_filtering: bool) {
+        self.software_filtering = software_filtering;
+    }
+
+    /// Consumes this driver and returns its CAN object
+    pub fn into_can(self) -> D {
+        self.can
+    }
+
+    /// Returns a reference to the CAN driver
+    pub fn can(&self) -> &D {
+        &self.can
+    }
+    /// Returns a mutable reference to the CAN driver
+    pub fn can_mut(&mut self) -> &mut D {
+        &mut self.can
+    }
+
+    /// Returns true if at least one loopback frame is ready to receive
+    pub fn loopback_frame_waiting(&self) -> bool {
+        !self.loopback_frames.is_empty()
+    }
+
+    /// Converts a frame that the peripheral driver removed from a transmit buffer
+    ///
+    /// replacement: The frame that replaced the removed frame
+    fn removed_frame(
+        &mut self,
+        removed: &D::Frame,
+        replacement: &Frame,
+        now: Microseconds32,
+    ) -> Option<Frame> {
+        if self.deadline_tracking {
+            let converted = embedded_frame_to_cyphal(removed, now).ok()?;
+            let tracked = self
+                .sent_frames
+                .iter()
+                .position(|sent| sent.id() == converted.id() && sent.data() == converted.data())
+                .and_then(|index| remove_at(&mut self.sent_frames, index));
+            if let Some(tracked) = tracked {
+                return if tracked.timestamp() >= now {
+                    Some(tracked)
+                } else {
+                    // Missed its deadline while waiting in the transmit buffer
+                    self.transmit_statistics.errored =
+                        self.transmit_statistics.errored.wrapping_add(1);
+                    None
+                };
+            }
+            // The frame is no longer in the history, so its deadline is unknown
+        }
+        embedded_frame_to_cyphal(removed, replacement.timestamp()).ok()
+    }
+
+    /// Returns true if a received frame should be returned
+    fn accepts(&self, frame: &Frame) -> bool {
+        match (&self.filters, self.software_filtering) {
+            (Some(filters), true) => {
+                let id = u32::from(frame.id());
+                filters.iter().any(|filter| filter.accepts(id))
+            }
+            _ => true,
+        }
+    }
+
+    /// Tries to receive a frame from the loopback queue
+    fn receive_loopback(&mut self) -> nb::Result<Frame, D::Error> {
+        match self.loopback_frames.pop_front() {
+            Some(frame) => Ok(frame),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+impl<C, D> TransmitDriver<C> for EmbeddedCanDriver<D>
+where
+    C: Clock,
+    D: Can,
+{
+    type Error = D::Error;
+
+    fn try_reserve(&mut self, frames: usize) -> Result<(), OutOfMemoryError> {
+        if frames == 1 {
+            // There's likely space for at least one frame
+            Ok(())
+        } else {
+            // However, there is no in-memory queue.
+            Err(OutOfMemoryError)
+        }
+    }
+
+    fn transmit(&mut self, frame: Frame, clock: &mut C) -> nb::Result<Option<Frame>, Self::Error> {
+        let now = clock.now();
+        // Drop this frame if its deadline has passed
+        if frame.timestamp() < now {
+            self.transmit_statistics.errored = self.transmit_statistics.errored.wrapping_add(1);
+            return Ok(None);
+        }
+        let embedded_frame: D::Frame = match cyphal_frame_to_embedded(&frame) {
+            Some(embedded_frame) => embedded_frame,
+            None => {
+                // The peripheral driver can't represent this frame (for example, it has more than
+                // 8 bytes of data and the peripheral only supports classic CAN)
+                self.transmit_statistics.errored = self.transmit_statistics.errored.wrapping_add(1);
+                return Ok(None);
+            }
+        };
+        let removed = match self.can.transmit(&embedded_frame) {
+            Ok(removed) => removed,
+            Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+            Err(nb::Error::Other(e)) => {
+                self.transmit_statistics.errored = self.transmit_statistics.errored.wrapping_add(1);
+                return Err(nb::Error::Other(e));
+            }
+        };
+        self.transmit_statistics.emitted = self.transmit_statistics.emitted.wrapping_add(1);
+        let removed = removed.and_then(|removed| self.removed_frame(&removed, &frame, now));
+        if self.deadline_tracking {
+            if self.sent_frames.is_full() {
+                // The oldest frame has most likely been transmitted already
+                self.sent_frames.pop_front();
+            }
+            let _ = self.sent_frames.push_back(frame.clone());
+        }
+        if frame.loopback() {
+            // Loopback frame successfully sent; store a copy with its timestamp set to
+            // the time just before it was sent
+            let mut loopback_frame = frame;
+            loopback_frame.set_timestamp(now);
+            // If the loopback queue is full, drop this frame
+            let _ = self.loopback_frames.push_back(loopback_frame);
+        }
+        Ok(removed)
+    }
+
+    fn flush(&mut self, _clock: &mut C) -> nb::Result<(), Self::Error> {
+        // The hardware does this automatically
+        Ok(())
+    }
+
+    fn add_interface_statistics(
+        &self,
+        first_interface: usize,
+        statistics: &mut TransportStatistics,
+    ) -> usize {
+        if let Some(interface) = statistics.interface_mut(first_interface) {
+            interface.add(&self.transmit_statistics);
+        }
+        1
+    }
+}
+
+impl<C, D> ReceiveDriver<C> for EmbeddedCanDriver<D>
+where
+    C: Clock,
+    D: Can,
+{
+    type Error = D::Error;
+
+    /// Tries to receive a frame from the peripheral driver or the loopback frame queue
+    ///
+    /// If both loopback and non-loopback frames are waiting, this function returns a non-loopback
+    /// frame.
+    fn receive(&mut self, clock: &mut C) -> nb::Result<Frame, Self::Error> {
+        loop {
+            let statistics = &mut self.receive_statistics;
+            let embedded_frame = match self.can.receive() {
+                Ok(frame) => frame,
+                // No frames waiting from the bus. Try the loopback queue.
+                Err(nb::Error::WouldBlock) => break self.receive_loopback(),
+                Err(nb::Error::Other(e)) => {
+                    statistics.errored = statistics.errored.wrapping_add(1);
+                    break Err(nb::Error::Other(e));
+                }
+            };
+            statistics.received = statistics.received.wrapping_add(1);
+            if !embedded_can::Frame::is_extended(&embedded_frame)
+                || embedded_can::Frame::is_remote_frame(&embedded_frame)
+            {
+                // Not a Cyphal frame. Try to receive another frame.
+                continue;
+            }
+            match embedded_frame_to_cyphal(&embedded_frame, clock.now()) {
+                Ok(frame) => {
+                    if self.accepts(&frame) {
+                        break Ok(frame);
+                    }
+                }
+                Err(_) => {
+                    // The frame has an invalid ID or is too long. Try to receive another
+                    // frame.
+                    statistics.errored = statistics.errored.wrapping_add(1);
+                }
+            }
+        }
+    }
+
+    fn apply_filters<S>(&mut self, local_node: Option<CanNodeId>, subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+        // Software filters have no limit, so the filters are not merged.
+        let mut filters = self.filters.take().unwrap_or_default();
+        filters.clear();
+        let mut stored = Ok(());
+        let status = optimize_filters(local_node, subscriptions, usize::MAX, |optimized| {
+            stored = filters
+                .try_reserve(optimized.len())
+                .map(|()| filters.extend_from_slice(optimized));
+        });
+        self.filters = if status.is_ok() && stored.is_ok() {
+            Some(filters)
+        } else {
+            // Not enough memory to store the filters. Just accept all frames.
+            None
+        };
+    }
+
+    fn apply_accept_all(&mut self) {
+        self.filters = None;
+    }
+
+    fn add_interface_statistics(
+        &self,
+        first_interface: usize,
+        statistics: &mut TransportStatistics,
+    ) -> usize {
+        if let Some(interface) = statistics.interface_mut(first_interface) {
+            interface.add(&self.receive_statistics);
+        }
+        1
+    }
+}
+
+/// Removes and returns the frame at an index in a deque, keeping the other frames in order
+fn remove_at(frames: &mut Deque<Frame, TRACKED_CAPACITY>, index: usize) -> Option<Frame> {
+    frames.make_contiguous().get_mut(index..)?.rotate_left(1);
+    frames.pop_back()
+}
+
+/// Converts a Canadensis frame into an `embedded_can` frame with an extended ID
+///
+/// This function returns None if the frame type does not support the length of the frame's
+/// data (for example, if the frame has more than 8 bytes of data and the frame type only
+/// supports classic CAN).
+pub fn cyphal_frame_to_embedded<F>(frame: &Frame) -> Option<F>
+where
+    F: embedded_can::Frame,
+{
+    // Every CanId is a valid extended ID
+    let id = ExtendedId::new(frame.id().into())?;
+    F::new(id, frame.data())
+}
+
+/// Converts an `embedded_can` frame into a Canadensis frame
+///
+/// This function returns an error if the frame does not have an extended ID, is a remote frame,
+/// has an ID with an invalid format, or has more than [`FRAME_CAPACITY`] bytes of data.
+pub fn embedded_frame_to_cyphal<F>(
+    frame: &F,
+    timestamp: Microseconds32,
+) -> Result<Frame, InvalidFrameFormat>
+where
+    F: embedded_can::Frame,
+{
+    let id_bits = match frame.id() {
+        Id::Extended(extended_id) => extended_id.as_raw(),
+        Id::Standard(_) => return Err(InvalidFrameFormat),
+    };
+    if frame.is_remote_frame() || frame.data().len() > FRAME_CAPACITY {
+        return Err(InvalidFrameFormat);
+    }
+    let cyphal_id = CanId::try_from(id_bits).map_err(|_| InvalidFrameFormat)?;
+    Ok(Frame::new(timestamp, cyphal_id, frame.data()))
+}
+
+/// An error indicating that a frame did not have the correct format for use with Cyphal
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidFrameFormat;
@@ -0,0 +1,367 @@
+//!
+//! Tests for EmbeddedCanDriver with a mock CAN peripheral
+//!
+
+extern crate canadensis_can;
+extern crate canadensis_core;
+extern crate canadensis_embedded_can;
+extern crate embedded_can;
+
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanId, Frame};
+use canadensis_core::nb;
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{Clock, Microseconds32};
+use canadensis_core::SubjectId;
+use canadensis_embedded_can::{
+    cyphal_frame_to_embedded, embedded_frame_to_cyphal, EmbeddedCanDriver,
+};
+use core::convert::TryFrom;
+use embedded_can::{ErrorKind, ExtendedId, Id, StandardId};
+use std::collections::VecDeque;
+
+/// A classic CAN frame
+#[derive(Debug, Clone, PartialEq)]
+struct MockFrame {
+    id: Id,
+    data: Vec<u8>,
+    remote: bool,
+}
+
+impl embedded_can::Frame for MockFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() <= 8 {
+            Some(MockFrame {
+                id: id.into(),
+                data: data.to_vec(),
+                remote: false,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc <= 8 {
+            Some(MockFrame {
+                id: id.into(),
+                data: Vec::new(),
+                remote: true,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.id, Id::Extended(_))
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        self.remote
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn dlc(&self) -> usize {
+        self.data.len()
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// A CAN peripheral with two transmit buffers that are never emptied
+#[derive(Default)]
+struct MockCan {
+    transmit_buffers: Vec<MockFrame>,
+    received: VecDeque<MockFrame>,
+}
+
+impl MockCan {
+    fn raw_id(frame: &MockFrame) -> u32 {
+        match frame.id {
+            Id::Extended(id) => id.as_raw(),
+            Id::Standard(id) => u32::from(id.as_raw()) << 18,
+        }
+    }
+}
+
+impl embedded_can::nb::Can for MockCan {
+    type Frame = MockFrame;
+    type Error = ErrorKind;
+
+    fn transmit(&mut self, frame: &MockFrame) -> nb::Result<Option<MockFrame>, ErrorKind> {
+        if self.transmit_buffers.len() < 2 {
+            self.transmit_buffers.push(frame.clone());
+            return Ok(None);
+        }
+        // Replace the lowest-priority frame if it has a lower priority than the new frame
+        let (index, lowest) = self
+            .transmit_buffers
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, queued)| MockCan::raw_id(queued))
+            .unwrap();
+        if MockCan::raw_id(lowest) > MockCan::raw_id(frame) {
+            Ok(Some(std::mem::replace(
+                &mut self.transmit_buffers[index],
+                frame.clone(),
+            )))
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    fn receive(&mut self) -> nb::Result<MockFrame, ErrorKind> {
+        self.received.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+struct StubClock {
+    ticks: u32,
+}
+
+impl Clock for StubClock {
+    fn now(&mut self) -> Microseconds32 {
+        Microseconds32::from_ticks(self.ticks)
+    }
+}
+
+fn instant(ticks: u32) -> Microseconds32 {
+    Microseconds32::from_ticks(ticks)
+}
+
+/// Returns a frame with a message ID on a subject
+fn message_frame(deadline: u32, subject: u16, data: &[u8]) -> Frame {
+    let id = (4 << 26) | (3 << 21) | (u32::from(subject) << 8) | 42;
+    Frame::new(instant(deadline), CanId::try_from(id).unwrap(), data)
+}
+
+#[test]
+fn frame_conversion() {
+    let frame = message_frame(10, 7509, &[1, 2, 3, 0xe0]);
+    let embedded: MockFrame = cyphal_frame_to_embedded(&frame).unwrap();
+    assert_eq!(
+        embedded.id,
+        Id::Extended(ExtendedId::new(frame.id().into()).unwrap())
+    );
+    assert_eq!(embedded.data, frame.data());
+    assert_eq!(
+        embedded_frame_to_cyphal(&embedded, instant(10)).unwrap(),
+        frame
+    );
+
+    // Too long for a classic CAN frame (only possible with the can-fd feature of canadensis_can)
+    if canadensis_can::FRAME_CAPACITY > 8 {
+        let long_frame = message_frame(10, 7509, &[0; 9]);
+        assert!(cyphal_frame_to_embedded::<MockFrame>(&long_frame).is_none());
+    }
+
+    // Standard ID and remote frames
+    let standard = MockFrame {
+        id: Id::Standard(StandardId::new(0x12).unwrap()),
+        data: vec![0xe0],
+        remote: false,
+    };
+    assert!(embedded_frame_to_cyphal(&standard, instant(0)).is_err());
+    let remote = MockFrame {
+        remote: true,
+        ..embedded
+    };
+    assert!(embedded_frame_to_cyphal(&remote, instant(0)).is_err());
+}
+
+#[test]
+fn removed_frames() {
+    for &deadline_tracking in &[false, true] {
+        let mut clock = StubClock { ticks: 0 };
+        let mut driver = EmbeddedCanDriver::new(MockCan::default());
+        driver.set_deadline_tracking(deadline_tracking);
+
+        let mut low_priority = message_frame(100, 1000, &[0xe0]);
+        low_priority.set_loopback(true);
+        let medium_priority = message_frame(200, 500, &[0xe1]);
+        let high_priority = message_frame(300, 10, &[0xe2]);
+        assert_eq!(driver.transmit(low_priority.clone(), &mut clock), Ok(None));
+        assert_eq!(driver.transmit(medium_priority, &mut clock), Ok(None));
+        let removed = driver.transmit(high_priority, &mut clock).unwrap().unwrap();
+        assert_eq!(removed.id(), low_priority.id());
+        assert_eq!(removed.data(), low_priority.data());
+        if deadline_tracking {
+            // The removed frame keeps its deadline and loopback flag
+            assert_eq!(removed, low_priority);
+        } else {
+            assert_eq!(removed.timestamp(), instant(300));
+            assert!(!removed.loopback());
+        }
+    }
+}
+
+#[test]
+fn removed_frame_after_deadline() {
+    let mut clock = StubClock { ticks: 0 };
+    let mut driver = EmbeddedCanDriver::new(MockCan::default());
+    driver.set_deadline_tracking(true);
+
+    assert_eq!(
+        driver.transmit(message_frame(100, 1000, &[0xe0]), &mut clock),
+        Ok(None)
+    );
+    assert_eq!(
+        driver.transmit(message_frame(200, 500, &[0xe1]), &mut clock),
+        Ok(None)
+    );
+    clock.ticks = 150;
+    // The frame that the hardware removes has missed its deadline, so it is not returned
+    assert_eq!(
+        driver.transmit(message_frame(300, 10, &[0xe2]), &mut clock),
+        Ok(None)
+    );
+    // A frame that has missed its deadline is not sent
+    clock.ticks = 400;
+    assert_eq!(
+        driver.transmit(message_frame(300, 1, &[0xe3]), &mut clock),
+        Ok(None)
+    );
+    assert_eq!(driver.can().transmit_buffers.len(), 2);
+}
+
+#[test]
+fn removed_frame_not_tracked() {
+    let mut clock = StubClock { ticks: 0 };
+    let mut driver = EmbeddedCanDriver::new(MockCan::default());
+    driver.set_deadline_tracking(true);
+
+    let mut low_priority = message_frame(100, 1000, &[0xe0]);
+    low_priority.set_loopback(true);
+    assert_eq!(driver.transmit(low_priority.clone(), &mut clock), Ok(None));
+    // Send enough other frames that the first frame is forgotten
+    for subject in 0..8 {
+        let frame = message_frame(100, subject, &[0xe1]);
+        assert_eq!(driver.transmit(frame, &mut clock), Ok(None));
+        // The frame has been sent
+        driver.can_mut().transmit_buffers.pop();
+    }
+    assert_eq!(
+        driver.transmit(message_frame(200, 500, &[0xe2]), &mut clock),
+        Ok(None)
+    );
+    // The removed frame is still returned, with the deadline of the frame that replaced it
+    let removed = driver
+        .transmit(message_frame(300, 10, &[0xe3]), &mut clock)
+        .unwrap()
+        .unwrap();
+    assert_eq!(removed.id(), low_priority.id());
+    assert_eq!(removed.data(), low_priority.data());
+    assert_eq!(removed.timestamp(), instant(300));
+    assert!(!removed.loopback());
+}
+
+#[test]
+fn frame_too_long() {
+    // Only possible with the can-fd feature of canadensis_can
+    if canadensis_can::FRAME_CAPACITY <= 8 {
+        return;
+    }
+    let mut clock = StubClock { ticks: 0 };
+    let mut driver = EmbeddedCanDriver::new(MockCan::default());
+    // MockFrame only supports classic CAN, so this frame is counted as an error and dropped
+    assert_eq!(
+        driver.transmit(message_frame(100, 10, &[0; 12]), &mut clock),
+        Ok(None)
+    );
+    assert!(driver.can().transmit_buffers.is_empty());
+    let mut statistics = TransportStatistics::default();
+    TransmitDriver::<StubClock>::add_interface_statistics(&driver, 0, &mut statistics);
+    assert_eq!(
+        [IoStatistics {
+            emitted: 0,
+            received: 0,
+            errored: 1,
+        }],
+        statistics.interfaces.as_slice()
+    );
+}
+
+#[test]
+fn software_filtering() {
+    let mut clock = StubClock { ticks: 0 };
+    let mut can = MockCan::default();
+    for &subject in &[10, 11, 10] {
+        let frame = message_frame(0, subject, &[0xe0]);
+        can.received
+            .push_back(cyphal_frame_to_embedded(&frame).unwrap());
+    }
+    // A frame with a standard ID, which is always ignored
+    can.received.push_back(MockFrame {
+        id: Id::Standard(StandardId::new(0x12).unwrap()),
+        data: vec![0xe0],
+        remote: false,
+    });
+    let mut driver = EmbeddedCanDriver::new(can);
+    driver.set_software_filtering(true);
+    ReceiveDriver::<StubClock>::apply_filters(
+        &mut driver,
+        None,
+        [Subscription::Message(SubjectId::try_from(10).unwrap())],
+    );
+
+    let mut received_subjects = Vec::new();
+    while let Ok(frame) = driver.receive(&mut clock) {
+        received_subjects.push((u32::from(frame.id()) >> 8) & 0x1fff);
+    }
+    assert_eq!(received_subjects, [10, 10]);
+
+    // Without software filtering, all frames with extended IDs are received
+    for &subject in &[10, 11] {
+        let frame = message_frame(0, subject, &[0xe0]);
+        driver
+            .can_mut()
+            .received
+            .push_back(cyphal_frame_to_embedded(&frame).unwrap());
+    }
+    driver.set_software_filtering(false);
+    let mut received_subjects = Vec::new();
+    while let Ok(frame) = driver.receive(&mut clock) {
+        received_subjects.push((u32::from(frame.id()) >> 8) & 0x1fff);
+    }
+    assert_eq!(received_subjects, [10, 11]);
+}
+
+#[test]
+fn non_cyphal_frames_are_not_errors() {
+    let mut clock = StubClock { ticks: 0 };
+    let mut can = MockCan::default();
+    can.received.push_back(MockFrame {
+        id: Id::Standard(StandardId::new(0x12).unwrap()),
+        data: vec![0xe0],
+        remote: false,
+    });
+    can.received.push_back(MockFrame {
+        id: Id::Extended(ExtendedId::new(0x1000).unwrap()),
+        data: vec![0],
+        remote: true,
+    });
+    let frame = message_frame(0, 10, &[0xe0]);
+    can.received
+        .push_back(cyphal_frame_to_embedded(&frame).unwrap());
+    let mut driver = EmbeddedCanDriver::new(can);
+
+    let received = driver.receive(&mut clock).unwrap();
+    assert_eq!(received.id(), frame.id());
+    let mut statistics = TransportStatistics::default();
+    ReceiveDriver::<StubClock>::add_interface_statistics(&driver, 0, &mut statistics);
+    assert_eq!(
+        [IoStatistics {
+            emitted: 0,
+            received: 3,
+            errored: 0,
+        }],
+        statistics.interfaces.as_slice()
+    );
+}
@@ -301,6 +301,68 @@ impl<'b> ReadCursor<'b> {
         status
     }
 
+    /// Skips over a composite object without keeping its value
+    ///
+    /// If T is delimited, this function reads only the delimiter header. If T is sealed, its
+    /// length is not known without reading it, so this function deserializes and discards it.
+    ///
+    /// Like [`read_composite()`](#method.read_composite), this function skips any padding before
+    /// and after the object.
+    pub fn skip_composite<T>(&mut self) -> Result<(), DeserializeError>
+    where
+        T: Deserialize,
+    {
+        self.align_to_8_bits();
+        let status = if T::EXTENT_BYTES.is_some() {
+            let composite_length_bytes = self.read_aligned_u32() as usize;
+            if composite_length_bytes > self.bytes.len() {
+                Err(DeserializeError::DelimitedLength)
+            } else {
+                self.advance_bytes(composite_length_bytes);
+                Ok(())
+            }
+        } else {
+            T::deserialize(self).map(drop)
+        };
+        self.align_to_8_bits();
+        status
+    }
+
+    /// Returns the serialized bytes of a composite object without deserializing it
+    ///
+    /// If T is delimited, this function returns exactly the bytes of the object and skips this
+    /// cursor past them. If T is sealed, its length is not known without reading it, so this
+    /// function returns all the remaining bytes and skips this cursor to the end.
+    ///
+    /// This function skips any padding before the object.
+    pub fn read_composite_bytes<T>(&mut self) -> Result<&'b [u8], DeserializeError>
+    where
+        T: Deserialize,
+    {
+        self.align_to_8_bits();
+        let composite_length_bytes = if T::EXTENT_BYTES.is_some() {
+            let composite_length_bytes = self.read_aligned_u32() as usize;
+            if composite_length_bytes > self.bytes.len() {
+                return Err(DeserializeError::DelimitedLength);
+            }
+            composite_length_bytes
+        } else {
+            self.bytes.len()
+        };
+        let (composite_bytes, rest) = self.bytes.split_at(composite_length_bytes);
+        self.bytes = rest;
+        Ok(composite_bytes)
+    }
+
+    /// Advances the cursor to skip any number of bits
+    ///
+    /// This is equivalent to calling the `skip_N` functions, but the number of bits does not
+    /// need to be known at compile time.
+    #[inline]
+    pub fn skip_bits(&mut self, bits: usize) {
+        self.advance_bits(bits)
+    }
+
     /// Reads a boolean value (1 bit)
     pub fn read_bool(&mut self) -> bool {
         self.read_u1() == 1
@@ -634,3 +634,35 @@ fn deserialize_new_version() {
     let deserialized = A11::deserialize_from_bytes(&expected_bytes).unwrap();
     assert_eq!(a, deserialized);
 }
+
+#[test]
+fn skip_and_read_composite_bytes() {
+    let bytes: [u8; 28] = [
+        0x01, 0x17, 0x00, 0x00, 0x00, 0x02, 0x04, 0x00, 0x00, 0x00, 0x02, 0x01, 0x02, 0x00, 0x03,
+        0x00, 0x00, 0x00, 0x01, 0x03, 0x04, 0x01, 0x02, 0x00, 0x00, 0x00, 0x05, 0x06,
+    ];
+    // Skip the union tag, then the delimited B without deserializing it
+    let mut cursor = ReadCursor::new(&bytes);
+    cursor.skip_bits(8);
+    cursor.skip_composite::<BDelimited>().unwrap();
+    assert_eq!(Some(&[][..]), cursor.as_bytes());
+
+    // Get the bytes of the delimited B and read only its second length field
+    let mut cursor = ReadCursor::new(&bytes);
+    cursor.skip_bits(8);
+    let b_bytes = cursor.read_composite_bytes::<BDelimited>().unwrap();
+    assert_eq!(&bytes[5..], b_bytes);
+    let mut b_cursor = ReadCursor::new(b_bytes);
+    b_cursor.skip_bits(8);
+    b_cursor.skip_composite::<CVariable>().unwrap();
+    b_cursor.skip_composite::<CVariable>().unwrap();
+    assert_eq!(1, b_cursor.read_aligned_u8());
+
+    // A delimiter header that is longer than the remaining bytes
+    let mut cursor = ReadCursor::new(&bytes[..20]);
+    cursor.skip_bits(8);
+    assert!(matches!(
+        cursor.skip_composite::<BDelimited>(),
+        Err(DeserializeError::DelimitedLength)
+    ));
+}
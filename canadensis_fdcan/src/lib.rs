@@ -0,0 +1,475 @@
+#![no_std]
+#![deny(missing_docs)]
+
+//!
+//! # Canadensis compatibility for FDCAN CAN controllers
+//!
+//! This library provides a driver that makes it easier to use `canadensis` with the FDCAN
+//! controllers found on STM32G0, G4, L5, and H7 microcontrollers. It supports CAN FD frames with
+//! up to 64 bytes of data and bit rate switching.
+//!
+//! The fdcan crate needs to know which kind of microcontroller it is running on. The default
+//! `fdcan_g0_g4_l5` feature selects STM32G0, G4, and L5 microcontrollers. For STM32H7
+//! microcontrollers, disable the default features and enable the `fdcan_h7` feature.
+//!
+//! # Setup
+//!
+//! Configure the bit timing and frame transmission mode of the peripheral, then pass it to
+//! [`FdCanDriver::new`] in configuration mode:
+//!
+//! ```ignore
+//! let mut can = can.into_config_mode();
+//! can.set_nominal_bit_timing(nominal_bit_timing);
+//! can.set_data_bit_timing(data_bit_timing);
+//! can.set_frame_transmit(FrameTransmissionConfig::AllowFdCanAndBRS);
+//! let driver = FdCanDriver::new(can);
+//! ```
+//!
+//! Use [`Mtu::CanFd64`](canadensis_can::Mtu::CanFd64) when creating the `CanTransmitter` to send
+//! transfers in CAN FD frames.
+//!
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_filter_config;
+extern crate fdcan;
+extern crate heapless;
+extern crate nb;
+
+use canadensis::core::subscription::Subscription;
+use canadensis::core::time::{Clock, Microseconds32};
+use canadensis::core::{OutOfMemoryError, SubjectId};
+use canadensis_can::driver::{optimize_filter_banks, ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanNodeId, Frame, FRAME_CAPACITY};
+use canadensis_filter_config::{BankConfig, HardwareFilters};
+use core::convert::{Infallible, TryFrom};
+use fdcan::config::GlobalFilter;
+use fdcan::filter::{Action, ExtendedFilter, FilterType, EXTENDED_FILTER_MAX};
+use fdcan::frame::{FrameFormat, RxFrameInfo, TxFrameHeader};
+use fdcan::id::{ExtendedId, Id};
+use fdcan::{ConfigMode, FdCan, Instance, Mailbox, NormalOperationMode, ReceiveOverrun};
+use heapless::Deque;
+
+/// Maximum number of loopback frames that can be stored
+const LOOPBACK_CAPACITY: usize = 2;
+
+/// The transmit buffers of an FDCAN peripheral
+const MAILBOXES: [Mailbox; 3] = [Mailbox::_0, Mailbox::_1, Mailbox::_2];
+
+/// A CAN driver that wraps an FDCAN device and keeps track of deadlines for queued frames
+///
+/// Frames with more than 8 bytes of data are sent as CAN FD frames, with bit rate switching
+/// unless it has been disabled using [`set_bit_rate_switching`](Self::set_bit_rate_switching).
+/// Other frames are sent as classic CAN frames. If the peripheral is configured for classic CAN
+/// only, it sends all frames as classic CAN frames.
+pub struct FdCanDriver<I>
+where
+    I: Instance,
+{
+    can: FdCan<I, NormalOperationMode>,
+    deadlines: DeadlineTracker,
+    /// Copies of transmitted loopback frames that have not yet been received
+    loopback_frames: Deque<Frame, LOOPBACK_CAPACITY>,
+    /// A frame that was received along with a FIFO overrun, which will be returned after the
+    /// overrun error
+    frame_after_overrun: Option<Frame>,
+    /// Returns true for subjects that should use FIFO 1
+    is_high_priority: fn(SubjectId) -> bool,
+    /// If CAN FD frames should be sent with bit rate switching
+    bit_rate_switching: bool,
+}
+
+impl<I> FdCanDriver<I>
+where
+    I: Instance,
+{
+    /// Creates a CAN driver and puts the peripheral into normal operation mode
+    ///
+    /// This configures the peripheral to reject all frames that do not match a filter. Until
+    /// the filters are applied, no frames will be received.
+    pub fn new(mut can: FdCan<I, ConfigMode>) -> Self {
+        can.set_global_filter(GlobalFilter::reject_all());
+        can.set_extended_filters(&[ExtendedFilter::disable(); EXTENDED_FILTER_MAX as usize]);
+        FdCanDriver {
+            can: can.into_normal(),
+            deadlines: DeadlineTracker::new(),
+            loopback_frames: Deque::new(),
+            frame_after_overrun: None,
+            is_high_priority: |_| false,
+            bit_rate_switching: true,
+        }
+    }
+
+    /// Sets a function that selects high-priority subjects
+    ///
+    /// Frames on high-priority subjects go to receive FIFO 1, and frames on other subjects and
+    /// services go to FIFO 0. This driver receives frames from FIFO 1 first, so a high-priority
+    /// frame does not wait behind other frames and is not lost if FIFO 0 overflows.
+    ///
+    /// The new setting takes effect the next time the filters are applied.
+    pub fn set_high_priority_subjects(&mut self, is_high_priority: fn(SubjectId) -> bool) {
+        self.is_high_priority = is_high_priority;
+    }
+
+    /// Enables or disables bit rate switching for CAN FD frames
+    ///
+    /// Bit rate switching is enabled by default. It only has an effect if the peripheral has
+    /// been configured to allow it.
+    pub fn set_bit_rate_switching(&mut self, bit_rate_switching: bool) {
+        self.bit_rate_switching = bit_rate_switching;
+    }
+
+    /// Consumes this driver and returns its CAN object
+    pub fn into_can(self) -> FdCan<I, NormalOperationMode> {
+        self.can
+    }
+
+    /// Returns a reference to the CAN driver
+    pub fn can(&self) -> &FdCan<I, NormalOperationMode> {
+        &self.can
+    }
+    /// Returns a mutable reference to the CAN driver
+    pub fn can_mut(&mut self) -> &mut FdCan<I, NormalOperationMode> {
+        &mut self.can
+    }
+
+    /// Returns true if at least one loopback frame is ready to receive
+    pub fn loopback_frame_waiting(&self) -> bool {
+        !self.loopback_frames.is_empty()
+    }
+
+    /// Tries to transmit a frame, and assumes that the frame's deadline has not passed
+    ///
+    /// If all transmit buffers are full and one of them holds a frame with a lower priority than
+    /// the new frame, that frame is removed and returned.
+    fn transmit_inner(&mut self, frame: &Frame) -> nb::Result<Option<Frame>, Infallible> {
+        let mut removed_frame = None;
+        if tx_queue_status::<I>().full {
+            // Every buffer holds a frame that this driver sent
+            let (mailbox, queued_id) = self
+                .deadlines
+                .lowest_priority()
+                .map(|(mailbox, queued_frame)| (mailbox, queued_frame.id()))
+                .ok_or(nb::Error::WouldBlock)?;
+            if queued_id <= frame.id() {
+                return Err(nb::Error::WouldBlock);
+            }
+            let aborted = self.can.abort(mailbox);
+            let queued_frame = self.deadlines.take(mailbox);
+            if aborted {
+                removed_frame = queued_frame;
+            }
+            // Otherwise, the frame was transmitted before it could be aborted.
+        }
+        // The queue now has at least one free buffer, and the new frame will go into the
+        // buffer at the put index.
+        let mailbox = tx_queue_status::<I>().put_index;
+        match self.can.transmit(self.tx_header(frame), frame.data()) {
+            Ok(_) => {
+                self.deadlines.replace(mailbox, frame.clone());
+                Ok(removed_frame)
+            }
+            Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+            Err(nb::Error::Other(infallible)) => match infallible {},
+        }
+    }
+
+    fn tx_header(&self, frame: &Frame) -> TxFrameHeader {
+        let len = frame.data().len() as u8;
+        let fd = len > 8;
+        TxFrameHeader {
+            len,
+            frame_format: if fd {
+                FrameFormat::Fdcan
+            } else {
+                FrameFormat::Standard
+            },
+            id: ExtendedId::new(frame.id().into()).unwrap().into(),
+            bit_rate_switching: fd && self.bit_rate_switching,
+            marker: None,
+        }
+    }
+
+    /// Tries to receive a frame from the CAN bus, checking the high-priority FIFO 1 before FIFO 0
+    fn receive_from_bus<C: Clock>(&mut self, clock: &mut C) -> nb::Result<Frame, OverrunError> {
+        if let Some(frame) = self.frame_after_overrun.take() {
+            return Ok(frame);
+        }
+        let mut buffer = [0u8; FRAME_CAPACITY];
+        loop {
+            let received = match self.can.receive1(&mut buffer) {
+                Err(nb::Error::WouldBlock) => self.can.receive0(&mut buffer),
+                result => result,
+            };
+            let (info, overrun) = match received {
+                Ok(ReceiveOverrun::NoOverrun(info)) => (info, false),
+                Ok(ReceiveOverrun::Overrun(info)) => (info, true),
+                Err(nb::Error::WouldBlock) => break Err(nb::Error::WouldBlock),
+                Err(nb::Error::Other(infallible)) => match infallible {},
+            };
+            match fdcan_frame_to_cyphal(&info, &buffer, clock.now()) {
+                Ok(frame) if overrun => {
+                    // Report the overrun now and return this frame next time
+                    self.frame_after_overrun = Some(frame);
+                    break Err(nb::Error::Other(OverrunError));
+                }
+                Ok(frame) => break Ok(frame),
+                Err(_) if overrun => break Err(nb::Error::Other(OverrunError)),
+                // Otherwise the frame is remote or has a standard ID, not compatible with
+                // Cyphal. Try to receive another frame.
+                Err(_) => {}
+            }
+        }
+    }
+    /// Tries to receive a frame from the loopback queue
+    fn receive_loopback(&mut self) -> nb::Result<Frame, OverrunError> {
+        match self.loopback_frames.pop_front() {
+            Some(frame) => Ok(frame),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    /// Aborts transmission for all frames placed in transmit buffers that have missed their
+    /// transmit deadlines
+    ///
+    /// now: The current time
+    fn clean_expired_frames(&mut self, now: Microseconds32) {
+        for &mailbox in MAILBOXES.iter() {
+            if let Some(deadline) = self.deadlines.get(mailbox) {
+                if now > deadline {
+                    // Deadline has passed, abort transmission
+                    // Ignore if the buffer is really empty or the frame has been transmitted.
+                    self.can.abort(mailbox);
+                    self.deadlines.take(mailbox);
+                }
+            }
+        }
+    }
+}
+
+impl<C, I> TransmitDriver<C> for FdCanDriver<I>
+where
+    C: Clock,
+    I: Instance,
+{
+    type Error = Infallible;
+
+    fn try_reserve(&mut self, frames: usize) -> Result<(), OutOfMemoryError> {
+        if frames == 1 {
+            // There's likely space for at least one frame
+            Ok(())
+        } else {
+            // However, there is no in-memory queue.
+            Err(OutOfMemoryError)
+        }
+    }
+
+    fn transmit(&mut self, frame: Frame, clock: &mut C) -> nb::Result<Option<Frame>, Self::Error> {
+        let now = clock.now();
+        self.clean_expired_frames(now);
+        // Check that the frame's deadline has not passed
+        let deadline = frame.timestamp();
+        if deadline >= now {
+            // Deadline is now or in the future. Continue to transmit.
+            let transmit_status = self.transmit_inner(&frame);
+            if transmit_status.is_ok() && frame.loopback() {
+                // Loopback frame successfully sent; store a copy with its timestamp set to
+                // the time just before it was sent
+                let mut loopback_frame = frame;
+                loopback_frame.set_timestamp(now);
+                // If the loopback queue is full, drop this frame
+                let _ = self.loopback_frames.push_back(loopback_frame);
+            }
+            transmit_status
+        } else {
+            // Deadline passed, ignore frame
+            Ok(None)
+        }
+    }
+
+    fn flush(&mut self, _clock: &mut C) -> nb::Result<(), Self::Error> {
+        // The hardware does this automatically
+        Ok(())
+    }
+}
+
+impl<C, I> ReceiveDriver<C> for FdCanDriver<I>
+where
+    C: Clock,
+    I: Instance,
+{
+    type Error = OverrunError;
+
+    /// Tries to receive a frame from the receive FIFOs or the loopback frame queue
+    ///
+    /// If both loopback and non-loopback frames are waiting, this function returns a non-loopback
+    /// frame.
+    ///
+    /// If a receive FIFO has overflowed, this function returns an error. The next call returns
+    /// the frame that was received after the overflow.
+    fn receive(&mut self, clock: &mut C) -> nb::Result<Frame, Self::Error> {
+        match self.receive_from_bus(clock) {
+            Ok(frame) => Ok(frame),
+            Err(nb::Error::Other(e)) => Err(nb::Error::Other(e)),
+            // No frames waiting from the bus. Try the loopback queue.
+            Err(nb::Error::WouldBlock) => self.receive_loopback(),
+        }
+    }
+
+    fn apply_filters<S>(&mut self, local_node: Option<CanNodeId>, subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+        let mut elements = [ExtendedFilter::disable(); EXTENDED_FILTER_MAX as usize];
+        // Each extended filter element holds one mask filter or two IDs (dual ID mode).
+        let hardware = HardwareFilters {
+            banks: elements.len(),
+            list_mode: true,
+            dual_16_bit: false,
+            high_priority_fifo: true,
+        };
+        let status = optimize_filter_banks(
+            local_node,
+            subscriptions,
+            &hardware,
+            self.is_high_priority,
+            |banks| {
+                for (element, bank) in elements.iter_mut().zip(banks) {
+                    let action = if bank.high_priority {
+                        Action::StoreInFifo1
+                    } else {
+                        Action::StoreInFifo0
+                    };
+                    let filter = match bank.config {
+                        BankConfig::Mask(filter) => FilterType::BitMask {
+                            filter: filter.id(),
+                            mask: filter.mask(),
+                        },
+                        BankConfig::List(id1, id2) => {
+                            let id1 = ExtendedId::new(id1).unwrap();
+                            match id2 {
+                                Some(id2) => {
+                                    FilterType::DedicatedDual(id1, ExtendedId::new(id2).unwrap())
+                                }
+                                None => FilterType::DedicatedSingle(id1),
+                            }
+                        }
+                        BankConfig::DualMask16(..) => unreachable!("16-bit filters not enabled"),
+                    };
+                    *element = ExtendedFilter { filter, action };
+                }
+            },
+        );
+        if status.is_err() {
+            // Not enough memory to apply the ideal filters. Just accept all frames.
+            elements = [ExtendedFilter::disable(); EXTENDED_FILTER_MAX as usize];
+            elements[0] = ExtendedFilter::accept_all_into_fifo0();
+        }
+        self.can.set_extended_filters(&elements);
+    }
+
+    fn apply_accept_all(&mut self) {
+        let mut elements = [ExtendedFilter::disable(); EXTENDED_FILTER_MAX as usize];
+        elements[0] = ExtendedFilter::accept_all_into_fifo0();
+        self.can.set_extended_filters(&elements);
+    }
+}
+
+/// The state of the transmit queue
+struct TxQueueStatus {
+    /// True if all transmit buffers hold pending frames
+    full: bool,
+    /// The buffer that the next frame will be placed in, if the queue is not full
+    put_index: Mailbox,
+}
+
+/// Reads the state of the transmit queue
+///
+/// The fdcan crate does not report which buffer a new frame is placed in, so this reads the
+/// transmit FIFO/queue status register directly.
+fn tx_queue_status<I: Instance>() -> TxQueueStatus {
+    // Safety: The driver owns the peripheral, and this only reads a status register.
+    let status = unsafe { (*I::REGISTERS).txfqs.read() };
+    TxQueueStatus {
+        full: status.tfqf().bit(),
+        put_index: match status.tfqpi().bits() {
+            0 => Mailbox::_0,
+            1 => Mailbox::_1,
+            _ => Mailbox::_2,
+        },
+    }
+}
+
+/// Keeps track of the frame (and its deadline) in each CAN transmit buffer
+///
+/// A frame stays here after it has been transmitted, until another frame replaces it or its
+/// deadline passes.
+#[derive(Default)]
+struct DeadlineTracker {
+    frames: [Option<Frame>; 3],
+}
+
+impl DeadlineTracker {
+    /// Creates a deadline tracker with no frames
+    fn new() -> Self {
+        DeadlineTracker::default()
+    }
+    /// Returns the deadline for a buffer
+    fn get(&self, mailbox: Mailbox) -> Option<Microseconds32> {
+        self.frames[mailbox as usize]
+            .as_ref()
+            .map(|frame| frame.timestamp())
+    }
+    /// Stores a frame for a buffer and returns the previous frame in that buffer, if any
+    fn replace(&mut self, mailbox: Mailbox, frame: Frame) -> Option<Frame> {
+        self.frames[mailbox as usize].replace(frame)
+    }
+    /// Removes and returns the frame for a buffer
+    fn take(&mut self, mailbox: Mailbox) -> Option<Frame> {
+        self.frames[mailbox as usize].take()
+    }
+    /// Returns the buffer with the lowest-priority frame (the frame with the greatest ID)
+    fn lowest_priority(&self) -> Option<(Mailbox, &Frame)> {
+        MAILBOXES
+            .iter()
+            .filter_map(|&mailbox| {
+                self.frames[mailbox as usize]
+                    .as_ref()
+                    .map(|frame| (mailbox, frame))
+            })
+            .max_by_key(|(_, frame)| frame.id())
+    }
+}
+
+/// Converts a received FDCAN frame into a Canadensis frame
+///
+/// This function returns an error if the frame does not have an extended ID, has an ID with an
+/// invalid format, or is a remote frame.
+fn fdcan_frame_to_cyphal(
+    info: &RxFrameInfo,
+    buffer: &[u8],
+    timestamp: Microseconds32,
+) -> Result<Frame, InvalidFrameFormat> {
+    let id_bits = match info.id {
+        Id::Extended(extended_id) => extended_id.as_raw(),
+        Id::Standard(_) => return Err(InvalidFrameFormat),
+    };
+    if info.rtr {
+        return Err(InvalidFrameFormat);
+    }
+    let cyphal_id = canadensis_can::CanId::try_from(id_bits).map_err(|_| InvalidFrameFormat)?;
+    let data = buffer
+        .get(..usize::from(info.len))
+        .ok_or(InvalidFrameFormat)?;
+    Ok(Frame::new(timestamp, cyphal_id, data))
+}
+
+/// An error indicating that a receive FIFO overflowed and at least one frame was lost
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OverrunError;
+
+/// An error indicating that a frame did not have the correct format for use with Cyphal
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidFrameFormat;
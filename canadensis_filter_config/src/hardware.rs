@@ -0,0 +1,304 @@
+//!
+//! Filter configuration for CAN controllers with filter banks that support several modes
+//!
+
+use crate::{compact, merge, Filter, EXTENDED_ID_MASK};
+
+/// The bits of an extended CAN ID that a 16-bit filter can compare (bits 28..15)
+const UPPER_14_BITS: u32 = 0x1fff_8000;
+
+/// The acceptance filters of a CAN controller
+///
+/// The controller has some number of filter banks. Each bank can hold one mask filter, or
+/// (if the controller supports it) two exact-match list entries or two 16-bit mask filters.
+///
+/// For example, an STM32 bxCAN controller has 14 or 28 banks, list mode, the 16-bit filter
+/// scale, and two receive FIFOs. An STM32 FDCAN controller has a number of extended filter
+/// elements that can each hold one mask filter or two exact IDs (dual ID mode), and two
+/// receive FIFOs.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HardwareFilters {
+    /// The number of filter banks
+    pub banks: usize,
+    /// If each bank can hold two extended IDs that must match exactly, instead of one mask
+    /// filter
+    pub list_mode: bool,
+    /// If each bank can hold two mask filters that compare only the 14 most significant bits of
+    /// an extended ID (bits 28..15), like the 16-bit filter scale of bxCAN
+    pub dual_16_bit: bool,
+    /// If the controller has a second receive FIFO, which will be used for high-priority frames
+    pub high_priority_fifo: bool,
+}
+
+/// The configuration of one filter bank
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BankConfig {
+    /// One filter that compares the bits of an extended ID selected by its mask
+    Mask(Filter),
+    /// One or two extended IDs that must match exactly
+    List(u32, Option<u32>),
+    /// One or two filters with masks that select only bits 28..15 of an extended ID
+    DualMask16(Filter, Option<Filter>),
+}
+
+/// A filter bank and the receive FIFO that it sends frames to
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FilterBank {
+    /// The filters in this bank
+    pub config: BankConfig,
+    /// True if this bank should send frames to the high-priority FIFO
+    ///
+    /// This is always false if the hardware does not have a high-priority FIFO.
+    pub high_priority: bool,
+}
+
+/// The ways that a filter can be placed in a bank
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Kind {
+    /// Two list entries per bank
+    List = 0,
+    /// Two 16-bit masks per bank
+    DualMask16 = 1,
+    /// One mask per bank
+    Mask = 2,
+}
+
+impl HardwareFilters {
+    fn kind(&self, filter: &Filter) -> Kind {
+        if self.list_mode && filter.mask() == EXTENDED_ID_MASK {
+            Kind::List
+        } else if self.dual_16_bit && (filter.mask() & !UPPER_14_BITS) == 0 {
+            Kind::DualMask16
+        } else {
+            Kind::Mask
+        }
+    }
+
+    /// Returns 1 if a filter goes to the high-priority FIFO, or 0 otherwise
+    fn group(&self, filter: &Filter) -> usize {
+        (self.high_priority_fifo && filter.is_high_priority()).into()
+    }
+}
+
+/// The number of filters of each kind in each group
+#[derive(Copy, Clone)]
+struct Counts([[usize; 3]; 2]);
+
+impl Counts {
+    fn add(&mut self, hardware: &HardwareFilters, filter: &Filter) {
+        self.0[hardware.group(filter)][hardware.kind(filter) as usize] += 1;
+    }
+    fn remove(&mut self, hardware: &HardwareFilters, filter: &Filter) {
+        self.0[hardware.group(filter)][hardware.kind(filter) as usize] -= 1;
+    }
+    /// Returns the number of banks needed to hold all the filters
+    fn banks(&self) -> usize {
+        self.0
+            .iter()
+            .map(|group| {
+                group[Kind::List as usize].div_ceil(2)
+                    + group[Kind::DualMask16 as usize].div_ceil(2)
+                    + group[Kind::Mask as usize]
+            })
+            .sum()
+    }
+}
+
+/// A change that reduces the number of banks needed
+#[derive(Copy, Clone)]
+enum Step {
+    Merge(usize, usize),
+    Truncate(usize),
+    TruncatePair(usize, usize),
+}
+
+/// Returns a filter that compares only bits 28..15
+fn truncate(filter: &Filter) -> Filter {
+    let mut truncated = Filter::new(filter.mask() & UPPER_14_BITS, filter.id());
+    truncated.set_high_priority(filter.is_high_priority());
+    truncated
+}
+
+/// Combines a slice of ideal filters into filter banks that will accept a superset of the message
+/// IDs of the ideal filters
+///
+/// Like [`optimize`](crate::optimize), this merges filters until they fit in the hardware.
+/// Exact-match filters use list entries where the hardware supports them, and other filters may
+/// be shortened to 16 bits when that is more precise than merging. High-priority filters
+/// (see [`Filter::set_high_priority`]) are never merged with other filters, and their banks
+/// send frames to the high-priority FIFO.
+///
+/// The returned banks use filters from a sub-slice of `ideal_filters`.
+///
+/// If the hardware has no banks, this function returns no banks. If the hardware has only one
+/// bank, all filters go to the normal FIFO.
+pub fn optimize_banks<'f>(
+    ideal_filters: &'f mut [Filter],
+    hardware: &HardwareFilters,
+) -> Banks<'f> {
+    let mut hardware = hardware.clone();
+    if hardware.banks < 2 {
+        // The two groups of filters need at least one bank each
+        hardware.high_priority_fifo = false;
+    }
+    if hardware.banks == 0 {
+        return Banks {
+            filters: &[],
+            hardware,
+        };
+    }
+    let filters = ideal_filters;
+
+    let mut counts = Counts([[0; 3]; 2]);
+    for filter in filters.iter() {
+        counts.add(&hardware, filter);
+    }
+    while counts.banks() > hardware.banks {
+        let step = best_step(filters, &hardware, &counts);
+        match step {
+            Step::Merge(i, j) => {
+                counts.remove(&hardware, &filters[i]);
+                counts.remove(&hardware, &filters[j]);
+                filters[i] = merge(&filters[i], &filters[j]);
+                filters[j].invalidate();
+                counts.add(&hardware, &filters[i]);
+            }
+            Step::Truncate(i) => {
+                counts.remove(&hardware, &filters[i]);
+                filters[i] = truncate(&filters[i]);
+                counts.add(&hardware, &filters[i]);
+            }
+            Step::TruncatePair(i, j) => {
+                for index in [i, j] {
+                    counts.remove(&hardware, &filters[index]);
+                    filters[index] = truncate(&filters[index]);
+                    counts.add(&hardware, &filters[index]);
+                }
+            }
+        }
+    }
+
+    compact(filters);
+    let valid_count = filters
+        .iter()
+        .position(|filter| !filter.is_valid())
+        .unwrap_or(filters.len());
+    let filters = &mut filters[..valid_count];
+    sort_for_banks(filters, &hardware);
+    Banks { filters, hardware }
+}
+
+/// Finds the change to the filters that reduces the number of banks needed and keeps the most
+/// precise filters
+///
+/// If no change reduces the number of banks needed, this returns the most precise merge.
+fn best_step(filters: &[Filter], hardware: &HardwareFilters, counts: &Counts) -> Step {
+    let banks = counts.banks();
+    // The best step, whether it reduces the number of banks, and its rank
+    let mut best: Option<(Step, bool, u32)> = None;
+    let mut consider = |step: Step, new_counts: &Counts, rank: u32| {
+        let reduces = new_counts.banks() < banks;
+        let better = match best {
+            Some((_, best_reduces, best_rank)) => (reduces, rank) >= (best_reduces, best_rank),
+            None => true,
+        };
+        if better {
+            best = Some((step, reduces, rank));
+        }
+    };
+
+    for (i, filter1) in filters.iter().enumerate() {
+        if !filter1.is_valid() {
+            continue;
+        }
+        let truncated1 = truncate(filter1);
+        let can_truncate1 = hardware.dual_16_bit && hardware.kind(filter1) == Kind::Mask;
+        if can_truncate1 {
+            let mut new_counts = *counts;
+            new_counts.remove(hardware, filter1);
+            new_counts.add(hardware, &truncated1);
+            // Only useful if it fills the empty half of a bank
+            if new_counts.banks() < banks {
+                consider(Step::Truncate(i), &new_counts, truncated1.rank());
+            }
+        }
+        for (j, filter2) in filters.iter().enumerate().skip(i + 1) {
+            if !filter2.is_valid() || hardware.group(filter1) != hardware.group(filter2) {
+                continue;
+            }
+            let merged = merge(filter1, filter2);
+            let mut new_counts = *counts;
+            new_counts.remove(hardware, filter1);
+            new_counts.remove(hardware, filter2);
+            new_counts.add(hardware, &merged);
+            consider(Step::Merge(i, j), &new_counts, merged.rank());
+
+            if can_truncate1 && hardware.kind(filter2) == Kind::Mask {
+                let truncated2 = truncate(filter2);
+                let mut new_counts = *counts;
+                new_counts.remove(hardware, filter1);
+                new_counts.remove(hardware, filter2);
+                new_counts.add(hardware, &truncated1);
+                new_counts.add(hardware, &truncated2);
+                if new_counts.banks() < banks {
+                    let rank = truncated1.rank().min(truncated2.rank());
+                    consider(Step::TruncatePair(i, j), &new_counts, rank);
+                }
+            }
+        }
+    }
+    // There are always at least two valid filters in one group when more banks are needed
+    // than available, so there is at least one possible merge.
+    best.expect("No filters to merge").0
+}
+
+/// Sorts filters so that high-priority filters come first, and filters of the same kind in the
+/// same group are next to each other
+fn sort_for_banks(filters: &mut [Filter], hardware: &HardwareFilters) {
+    // Like compact(), this uses insertion sort to keep the code small.
+    let key = |filter: &Filter| (1 - hardware.group(filter), hardware.kind(filter) as usize);
+    for i in 1..filters.len() {
+        let mut j = i;
+        while j != 0 && key(&filters[j - 1]) > key(&filters[j]) {
+            filters.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
+
+/// An iterator over optimized filter banks
+///
+/// This is returned by [`optimize_banks`].
+#[derive(Debug, Clone)]
+pub struct Banks<'f> {
+    filters: &'f [Filter],
+    hardware: HardwareFilters,
+}
+
+impl Iterator for Banks<'_> {
+    type Item = FilterBank;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (first, rest) = self.filters.split_first()?;
+        let kind = self.hardware.kind(first);
+        let group = self.hardware.group(first);
+        let second = rest.first().filter(|second| {
+            kind != Kind::Mask
+                && self.hardware.kind(second) == kind
+                && self.hardware.group(second) == group
+        });
+        self.filters = &rest[usize::from(second.is_some())..];
+        let config = match kind {
+            Kind::List => BankConfig::List(first.id(), second.map(Filter::id)),
+            Kind::DualMask16 => BankConfig::DualMask16(first.clone(), second.cloned()),
+            Kind::Mask => BankConfig::Mask(first.clone()),
+        };
+        Some(FilterBank {
+            config,
+            high_priority: group == 1,
+        })
+    }
+}
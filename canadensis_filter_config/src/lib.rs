@@ -40,16 +40,31 @@
 //! ```
 //!
 //! 3. Apply the resulting filters to the CAN hardware
+//!
+//! ## Filter banks
+//!
+//! Many CAN controllers have filter banks that can hold one mask filter, two exact IDs, or
+//! two shorter mask filters, and can send accepted frames to one of two receive FIFOs.
+//! [`optimize_banks`] uses a description of the hardware ([`HardwareFilters`]) to produce
+//! a configuration for each bank. It puts exact-match filters in list entries and keeps
+//! high-priority filters (see [`Filter::set_high_priority`]) in banks that use a separate FIFO.
 
 #![no_std]
 #![deny(missing_docs)]
 
+mod hardware;
+
+pub use crate::hardware::{optimize_banks, BankConfig, Banks, FilterBank, HardwareFilters};
+
 /// Mask of allowed extended CAN IDs
 const EXTENDED_ID_MASK: u32 = 0x1fff_ffff;
 
 /// Bit 31, used to mark a filter as valid
 const VALID_BIT: u32 = 0x8000_0000;
 
+/// Bit 30, used to mark a filter as high priority
+const HIGH_PRIORITY_BIT: u32 = 0x4000_0000;
+
 /// A generic mask-based filter for extended CAN IDs
 ///
 /// A filter will accept a message if (message_id & filter.mask) == (filter.id & filter.mask).
@@ -94,6 +109,23 @@ impl Filter {
         self.id_and_valid & EXTENDED_ID_MASK
     }
 
+    /// Marks this filter as high priority or not high priority
+    ///
+    /// [`optimize_banks`] assigns high-priority filters to a separate receive FIFO, if the
+    /// hardware has one. [`optimize`] ignores this setting.
+    pub fn set_high_priority(&mut self, high_priority: bool) {
+        if high_priority {
+            self.id_and_valid |= HIGH_PRIORITY_BIT;
+        } else {
+            self.id_and_valid &= !HIGH_PRIORITY_BIT;
+        }
+    }
+    /// Returns true if this filter is high priority
+    #[inline]
+    pub fn is_high_priority(&self) -> bool {
+        (self.id_and_valid & HIGH_PRIORITY_BIT) != 0
+    }
+
     /// Returns true if this filter is valid
     fn is_valid(&self) -> bool {
         (self.id_and_valid & VALID_BIT) != 0
@@ -125,6 +157,7 @@ mod debug_impl {
         fn fmt(&self, f: &mut Formatter<'_>) -> Result {
             f.debug_struct("Filter")
                 .field("valid", &self.is_valid())
+                .field("high_priority", &self.is_high_priority())
                 .field("mask", &DebugHex(self.mask()))
                 .field("id", &DebugHex(self.id()))
                 .finish()
@@ -136,8 +169,9 @@ mod debug_impl {
         fn format(&self, f: defmt::Formatter) {
             defmt::write!(
                 f,
-                "Filter {{ valid: {}, mask: {}, id: {}",
+                "Filter {{ valid: {}, high_priority: {}, mask: {}, id: {}",
                 self.is_valid(),
+                self.is_high_priority(),
                 DebugHex(self.mask()),
                 DebugHex(self.id())
             )
@@ -165,9 +199,13 @@ fn merge_masks(a: &Filter, b: &Filter) -> u32 {
 
 /// Merges two filters, producing a new filter that accepts the union of the IDs accepted by the
 /// two input filters (and possibly more IDs)
+///
+/// The new filter is high priority if either input filter is high priority.
 fn merge(a: &Filter, b: &Filter) -> Filter {
     let mask = merge_masks(a, b);
-    Filter::new(mask, a.id() & mask)
+    let mut merged = Filter::new(mask, a.id() & mask);
+    merged.set_high_priority(a.is_high_priority() || b.is_high_priority());
+    merged
 }
 
 /// Combines a slice of ideal filters down to max_filters filters that will accept a superset
@@ -0,0 +1,150 @@
+extern crate canadensis_filter_config;
+
+use canadensis_filter_config::{optimize_banks, BankConfig, Filter, FilterBank, HardwareFilters};
+
+const IDS: [u32; 14] = [
+    0x024F2EC8, 0x197060BA, 0x1F8FC4EB, 0x176DA287, 0x12D60349, 0x1470C4D0, 0x1CD159CA, 0x063D5425,
+    0x10338C76, 0x0EA4AD64, 0x0525E1BB, 0x00942DEF, 0x0, 0x1fffffff,
+];
+
+fn hardware(banks: usize, list_mode: bool, dual_16_bit: bool) -> HardwareFilters {
+    HardwareFilters {
+        banks,
+        list_mode,
+        dual_16_bit,
+        high_priority_fifo: true,
+    }
+}
+
+fn bank_accepts(bank: &FilterBank, id: u32) -> bool {
+    match &bank.config {
+        BankConfig::Mask(filter) => filter.accepts(id),
+        BankConfig::List(id1, id2) => id == *id1 || Some(id) == *id2,
+        BankConfig::DualMask16(filter1, filter2) => {
+            filter1.accepts(id) || filter2.as_ref().is_some_and(|filter| filter.accepts(id))
+        }
+    }
+}
+
+/// Checks that the banks fit in the hardware, and that each ID is accepted by a bank that sends
+/// frames to the correct FIFO
+fn check(banks: &[FilterBank], hardware: &HardwareFilters, ids: &[(u32, bool)]) {
+    assert!(banks.len() <= hardware.banks);
+    for bank in banks {
+        match &bank.config {
+            BankConfig::Mask(_) => {}
+            BankConfig::List(..) => assert!(hardware.list_mode),
+            BankConfig::DualMask16(filter1, filter2) => {
+                assert!(hardware.dual_16_bit);
+                for filter in Some(filter1).into_iter().chain(filter2) {
+                    assert_eq!(filter.mask() & 0x7fff, 0);
+                }
+            }
+        }
+    }
+    for &(id, high_priority) in ids {
+        let expect_high_priority = high_priority && hardware.high_priority_fifo;
+        assert!(
+            banks
+                .iter()
+                .any(|bank| bank.high_priority == expect_high_priority && bank_accepts(bank, id)),
+            "No bank accepts {:#010x}: {:#?}",
+            id,
+            banks
+        );
+    }
+}
+
+fn optimize_ids(ids: &[(u32, bool)], hardware: &HardwareFilters) -> Vec<FilterBank> {
+    let mut filters: Vec<Filter> = ids
+        .iter()
+        .map(|&(id, high_priority)| {
+            let mut filter = Filter::exact_match(id);
+            filter.set_high_priority(high_priority);
+            filter
+        })
+        .collect();
+    optimize_banks(&mut filters, hardware).collect()
+}
+
+#[test]
+fn exact_ids_in_list_banks() {
+    let ids: Vec<(u32, bool)> = IDS[..6].iter().map(|&id| (id, false)).collect();
+    let hardware = hardware(3, true, false);
+    let banks = optimize_ids(&ids, &hardware);
+    check(&banks, &hardware, &ids);
+    // No merging is needed, so every bank holds two exact IDs
+    assert_eq!(banks.len(), 3);
+    for bank in &banks {
+        assert!(matches!(bank.config, BankConfig::List(_, Some(_))));
+    }
+
+    // Without list mode, the IDs are merged into three mask filters
+    let hardware = self::hardware(3, false, false);
+    let banks = optimize_ids(&ids, &hardware);
+    check(&banks, &hardware, &ids);
+    assert_eq!(banks.len(), 3);
+    assert!(banks
+        .iter()
+        .all(|bank| matches!(bank.config, BankConfig::Mask(_))));
+}
+
+#[test]
+fn high_priority_fifo() {
+    let ids: Vec<(u32, bool)> = IDS
+        .iter()
+        .enumerate()
+        .map(|(i, &id)| (id, i % 4 == 0))
+        .collect();
+    for banks in 0..=IDS.len() + 1 {
+        for &list_mode in &[false, true] {
+            for &dual_16_bit in &[false, true] {
+                let hardware = hardware(banks, list_mode, dual_16_bit);
+                let banks = optimize_ids(&ids, &hardware);
+                if hardware.banks == 0 {
+                    assert!(banks.is_empty());
+                    continue;
+                }
+                if hardware.banks == 1 {
+                    // Everything goes to the normal FIFO
+                    assert!(banks.iter().all(|bank| !bank.high_priority));
+                    let hardware = HardwareFilters {
+                        high_priority_fifo: false,
+                        ..hardware
+                    };
+                    check(&banks, &hardware, &ids);
+                } else {
+                    check(&banks, &hardware, &ids);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn dual_16_bit_for_subjects() {
+    // Filters like the ones that canadensis_can makes for subjects, which ignore the priority
+    // and source node ID
+    let subjects = [10u32, 900, 2000, 4000, 7509, 7510, 8000, 8100];
+    let mask = 0b0_0010_1001_1111_1111_1111_1000_0000;
+    let mut filters: Vec<Filter> = subjects
+        .iter()
+        .map(|subject| {
+            Filter::new(
+                mask,
+                0b0_0000_0110_0000_0000_0000_0000_0000 | (subject << 8),
+            )
+        })
+        .collect();
+    let hardware = hardware(2, false, true);
+    let banks: Vec<FilterBank> = optimize_banks(&mut filters, &hardware).collect();
+    assert!(banks.len() <= 2);
+    for subject in subjects {
+        for priority in 0..8 {
+            for source in [0, 1, 127] {
+                let id = (priority << 26) | (3 << 21) | (subject << 8) | source;
+                assert!(banks.iter().any(|bank| bank_accepts(bank, id)));
+            }
+        }
+    }
+}
@@ -12,6 +12,7 @@ extern crate socketcan;
 
 use canadensis_can::driver::{optimize_filters, ReceiveDriver, TransmitDriver};
 use canadensis_can::{CanNodeId, Frame};
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::subscription::Subscription;
 use canadensis_core::time::{Clock, Microseconds32};
 use canadensis_core::{nb, OutOfMemoryError};
@@ -25,12 +26,20 @@ use std::os::fd::AsRawFd;
 /// An adapter between SocketCAN and the canadensis frame format
 pub struct LinuxCan<S: Socket> {
     socket: S,
+    /// Frames sent, and frames that could not be sent or missed their deadlines
+    transmit_statistics: IoStatistics,
+    /// Frames received, and read errors and frames that were too long
+    receive_statistics: IoStatistics,
 }
 
 impl<S: Socket> LinuxCan<S> {
     /// Creates a Linux CAN adapter around a SocketCAN socket
     pub fn new(socket: S) -> Self {
-        LinuxCan { socket }
+        LinuxCan {
+            socket,
+            transmit_statistics: IoStatistics::default(),
+            receive_statistics: IoStatistics::default(),
+        }
     }
 }
 
@@ -54,6 +63,7 @@ where
         let now = clock.now();
         if frame.timestamp() < now {
             log::warn!("Dropping frame that has missed its deadline");
+            self.transmit_statistics.errored = self.transmit_statistics.errored.wrapping_add(1);
             return Ok(None);
         }
         let socketcan_frame = S::FrameType::new(
@@ -63,13 +73,18 @@ where
             frame.data(),
         )
         .expect("Invalid frame format");
+        let statistics = &mut self.transmit_statistics;
         self.socket
             .write_frame_insist(&socketcan_frame)
-            .map(|()| None)
+            .map(|()| {
+                statistics.emitted = statistics.emitted.wrapping_add(1);
+                None
+            })
             .map_err(|e| {
                 if e.kind() == ErrorKind::WouldBlock {
                     nb::Error::WouldBlock
                 } else {
+                    statistics.errored = statistics.errored.wrapping_add(1);
                     nb::Error::Other(e)
                 }
             })
@@ -79,6 +94,17 @@ where
         // Presumably this happens automatically
         Ok(())
     }
+
+    fn add_interface_statistics(
+        &self,
+        first_interface: usize,
+        statistics: &mut TransportStatistics,
+    ) -> usize {
+        if let Some(interface) = statistics.interface_mut(first_interface) {
+            interface.add(&self.transmit_statistics);
+        }
+        1
+    }
 }
 
 impl<SK: Socket + SocketOptions> ReceiveDriver<SystemClock> for LinuxCan<SK>
@@ -89,13 +115,16 @@ where
 
     fn receive(&mut self, clock: &mut SystemClock) -> nb::Result<Frame, Self::Error> {
         loop {
+            let statistics = &mut self.receive_statistics;
             let socketcan_frame = self.socket.read_frame().map_err(|e| {
                 if e.kind() == ErrorKind::WouldBlock {
                     nb::Error::WouldBlock
                 } else {
+                    statistics.errored = statistics.errored.wrapping_add(1);
                     nb::Error::Other(e)
                 }
             })?;
+            statistics.received = statistics.received.wrapping_add(1);
             if socketcan_frame.data().len() <= canadensis_can::FRAME_CAPACITY {
                 let raw_id = match socketcan_frame.id() {
                     Id::Standard(_) => continue,
@@ -112,6 +141,7 @@ where
                     "Ignoring a frame {} bytes long, which is too large",
                     socketcan_frame.data().len()
                 );
+                statistics.errored = statistics.errored.wrapping_add(1);
             }
         }
     }
@@ -133,6 +163,17 @@ where
     fn apply_accept_all(&mut self) {
         self.socket.set_filter_accept_all().unwrap();
     }
+
+    fn add_interface_statistics(
+        &self,
+        first_interface: usize,
+        statistics: &mut TransportStatistics,
+    ) -> usize {
+        if let Some(interface) = statistics.interface_mut(first_interface) {
+            interface.add(&self.receive_statistics);
+        }
+        1
+    }
 }
 
 impl<S: Socket> AsRawFd for LinuxCan<S> {
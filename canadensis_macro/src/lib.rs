@@ -5,12 +5,18 @@ extern crate proc_macro;
 mod input;
 
 use crate::input::{Input, ParsedString, Statement};
-use canadensis_dsdl_frontend::{Package, TypeKey};
+use canadensis_codegen_rust::GenerateOptions;
+use canadensis_dsdl_frontend::cache::CompileCache;
+use canadensis_dsdl_frontend::compiled::package::CompiledPackage;
+use canadensis_dsdl_frontend::{Error, Package, TypeKey};
 use proc_macro::TokenStream;
-use proc_macro2::{Ident, Span, TokenTree};
+use proc_macro2::{Delimiter, Ident, Span, TokenTree};
 use std::collections::btree_map::Entry;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::BTreeMap;
 use std::env;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use syn::spanned::Spanned;
 
 /// Parses one or more DSDL definitions and expands into the corresponding Rust code
@@ -40,7 +46,8 @@ fn types_from_dsdl_inner(
                 "package" => eval_package_function(&mut package, arguments)?,
                 "make_external" => eval_make_external_function(&mut external_packages, arguments)?,
                 "generate" => {
-                    let parsed_code = eval_generate_function(package, &external_packages)
+                    let options = eval_generate_options(arguments)?;
+                    let parsed_code = eval_generate_function(package, &external_packages, &options)
                         .map_err(|e| make_error(name.span(), e))?;
                     output.extend(parsed_code);
                     break;
@@ -71,12 +78,13 @@ fn types_from_dsdl_inner(
 fn eval_generate_function(
     package: Package,
     external_packages: &BTreeMap<Vec<String>, Vec<String>>,
+    options: &GenerateOptions,
 ) -> Result<proc_macro2::TokenStream, String> {
-    let compiled = package
-        .compile()
+    let compiled = compile_with_cache(package)
         .map_err(|e| format!("Failed to compile DSDL: {}", ErrorChain(e)))?;
-    let code = canadensis_codegen_rust::generate_code(&compiled, external_packages)
-        .map_err(|e| format!("Failed to generate code from DSDL: {}", ErrorChain(e)))?;
+    let code =
+        canadensis_codegen_rust::generate_code_with_options(&compiled, external_packages, options)
+            .map_err(|e| format!("Failed to generate code from DSDL: {}", ErrorChain(e)))?;
     let code_string = code.to_string();
     let parsed_code: proc_macro2::TokenStream = code_string
         .parse()
@@ -84,6 +92,77 @@ fn eval_generate_function(
     Ok(parsed_code)
 }
 
+/// Compiles a package, using a cache of compiled types in `OUT_DIR` if the crate that uses
+/// this macro has a build script
+///
+/// Errors reading or writing the cache are ignored, because the cache is only an optimization.
+fn compile_with_cache(package: Package) -> Result<CompiledPackage, Box<Error>> {
+    let cache_path = match env::var_os("OUT_DIR") {
+        Some(out_dir) => {
+            // Each set of files gets its own cache, so that several uses of this macro in one
+            // crate do not replace each other's cache
+            let mut hasher = DefaultHasher::new();
+            for path in package.source_paths() {
+                path.hash(&mut hasher);
+            }
+            PathBuf::from(out_dir).join(format!("canadensis_macro-{:016x}.cache", hasher.finish()))
+        }
+        None => return package.compile(),
+    };
+    let mut cache = CompileCache::load(&cache_path).unwrap_or_default();
+    let (compiled, _changes) = package.compile_with_cache(&mut cache)?;
+    let _ = cache.save(&cache_path);
+    Ok(compiled)
+}
+
+/// Parses the optional arguments to the generate function, which look like
+/// `{ option: value, option: value }`
+fn eval_generate_options(
+    arguments: proc_macro2::TokenStream,
+) -> Result<GenerateOptions, proc_macro2::TokenStream> {
+    let mut options = GenerateOptions::new();
+    let mut iter = arguments.into_iter();
+    let group = match iter.next() {
+        None => return Ok(options),
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => group,
+        Some(other) => return Err(make_error(other.span(), "Expected {")),
+    };
+    if let Some(other) = iter.next() {
+        return Err(make_error(other.span(), "Unexpected argument after }"));
+    }
+
+    let mut iter = group.stream().into_iter();
+    while let Some(tree) = iter.next() {
+        let name = match tree {
+            TokenTree::Ident(name) => name,
+            other => return Err(make_error(other.span(), "Expected an option name")),
+        };
+        match iter.next() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ':' => {}
+            _ => return Err(make_error(name.span(), "Expected : after option name")),
+        }
+        let value = match iter.next() {
+            Some(TokenTree::Ident(value)) if value == "true" => true,
+            Some(TokenTree::Ident(value)) if value == "false" => false,
+            Some(other) => return Err(make_error(other.span(), "Expected true or false")),
+            None => return Err(make_error(name.span(), "Expected a value after :")),
+        };
+        match &*name.to_string() {
+            "views" => options = options.views(value),
+            "alloc_vec" => options = options.alloc_vec(value),
+            // These options are accepted for compatibility, but have no effect
+            "allow_utf8_and_byte" | "allow_saturated_bool" => {}
+            _ => return Err(make_error(name.span(), "Unrecognized option")),
+        }
+        match iter.next() {
+            None => break,
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => {}
+            Some(other) => return Err(make_error(other.span(), "Expected comma after value")),
+        }
+    }
+    Ok(options)
+}
+
 fn eval_package_function(
     package: &mut Package,
     arguments: proc_macro2::TokenStream,
@@ -0,0 +1,118 @@
+extern crate alloc;
+extern crate canadensis_encoding;
+extern crate canadensis_macro;
+
+use canadensis_encoding::{Deserialize, DeserializeError, Serialize};
+use canadensis_macro::types_from_dsdl;
+
+types_from_dsdl! {
+    type "heap.Text.1.0" { r#"
+uint8[<=256] value
+@sealed
+    "#}
+    type "heap.Read.1.0" { r#"
+uint40 offset
+@sealed
+---
+uint16 error
+Text.1.0 data
+bool[<=4] flags
+Text.1.0[<=3] more
+@sealed
+    "#}
+    type "heap.Value.1.0" { r#"
+@union
+uint16[<=4] integers
+Text.1.0 text
+@sealed
+    "#}
+    generate({
+        alloc_vec: true,
+        views: true,
+    })
+}
+
+use heap::read_1_0::{ReadResponse, ReadResponseView};
+use heap::text_1_0::Text;
+use heap::value_1_0::Value;
+
+fn serialize<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![0u8; value.size_bits().div_ceil(8)];
+    value.serialize_to_bytes(&mut bytes);
+    bytes
+}
+
+#[test]
+fn vec_round_trip() -> Result<(), DeserializeError> {
+    let response = ReadResponse {
+        error: 3,
+        data: Text {
+            value: b"hello".to_vec(),
+        },
+        flags: canadensis_encoding::bits::BitArray::new(2),
+        more: vec![
+            Text { value: vec![1, 2] },
+            Text {
+                value: vec![0; 256],
+            },
+        ],
+    };
+    // The array capacity is not stored inline
+    assert!(core::mem::size_of::<ReadResponse>() < 100);
+
+    let bytes = serialize(&response);
+    let decoded = ReadResponse::deserialize_from_bytes(&bytes)?;
+    assert_eq!(3, decoded.error);
+    assert_eq!(b"hello", &decoded.data.value[..]);
+    assert_eq!(2, decoded.more.len());
+    assert_eq!(256, decoded.more[1].value.len());
+    assert_eq!(
+        response.more[1].value,
+        ReadResponseView::new(&bytes).more()?[1].value
+    );
+
+    let value = Value::Integers(vec![1, 2, 3]);
+    let bytes = serialize(&value);
+    match Value::deserialize_from_bytes(&bytes)? {
+        Value::Integers(integers) => assert_eq!(vec![1, 2, 3], integers),
+        Value::Text(_) => panic!("Wrong variant"),
+    }
+    Ok(())
+}
+
+#[test]
+fn deserialize_too_long() {
+    // Length 5, but the maximum is 4
+    let bytes = [0, 5];
+    assert!(matches!(
+        Value::deserialize_from_bytes(&bytes),
+        Err(DeserializeError::ArrayLength)
+    ));
+}
+
+#[test]
+fn serialize_too_long() -> Result<(), DeserializeError> {
+    // Length 5, but the maximum is 4. Only the first 4 elements are serialized.
+    let value = Value::Integers(vec![1, 2, 3, 4, 5]);
+    let bytes = serialize(&value);
+    assert_eq!(2 + 4 * 2, bytes.len());
+    match Value::deserialize_from_bytes(&bytes)? {
+        Value::Integers(integers) => assert_eq!(vec![1, 2, 3, 4], integers),
+        Value::Text(_) => panic!("Wrong variant"),
+    }
+
+    let response = ReadResponse {
+        error: 0,
+        data: Text {
+            value: vec![7; 300],
+        },
+        flags: canadensis_encoding::bits::BitArray::new(1),
+        more: (0..4).map(|i| Text { value: vec![i] }).collect(),
+    };
+    let bytes = serialize(&response);
+    let decoded = ReadResponse::deserialize_from_bytes(&bytes)?;
+    assert_eq!(vec![7; 256], decoded.data.value);
+    assert_eq!(3, decoded.more.len());
+    assert_eq!(vec![2], decoded.more[2].value);
+    Ok(())
+}
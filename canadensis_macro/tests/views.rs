@@ -0,0 +1,158 @@
+extern crate canadensis_encoding;
+extern crate canadensis_macro;
+extern crate heapless;
+
+use canadensis_encoding::{DeserializeError, Serialize};
+use canadensis_macro::types_from_dsdl;
+
+types_from_dsdl! {
+    type "views.Timestamp.1.0" { r#"
+uint56 microsecond
+@sealed
+    "#}
+    type "views.Empty.1.0" { r#"
+@sealed
+    "#}
+    type "views.Text.1.0" { r#"
+uint8[<=16] value
+@sealed
+    "#}
+    type "views.Delimited.1.0" { r#"
+uint8 a
+@extent 8 * 8
+    "#}
+    type "views.Value.1.0" { r#"
+@union
+Empty.1.0 empty
+uint16[<=4] integers
+Text.1.0 text
+@sealed
+    "#}
+    type "views.Response.1.0" { r#"
+Timestamp.1.0 timestamp
+bool mutable
+bool persistent
+void6
+Value.1.0 value
+@sealed
+    "#}
+    type "views.Record.1.0" { r#"
+uint3 flags
+Text.1.0[<=2] names
+Delimited.1.0 extra
+Timestamp.1.0[2] times
+uint7[<=5] small
+bool[<=9] bits
+Timestamp.1.0[<=2] more_times
+float32 last
+@extent 128 * 8
+    "#}
+    generate({
+        views: true,
+    })
+}
+
+use views::record_1_0::{Record, RecordView};
+use views::response_1_0::{Response, ResponseView};
+use views::text_1_0::Text;
+use views::timestamp_1_0::Timestamp;
+use views::value_1_0::Value;
+
+/// Serializes a value into a buffer that is large enough for all the test types
+///
+/// Any bytes after the end of the value are zero, so reading them is the same as reading beyond
+/// the end of the transfer.
+fn serialize<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![0u8; 128];
+    value.serialize_to_bytes(&mut bytes);
+    bytes
+}
+
+fn text(s: &str) -> Text {
+    Text {
+        value: heapless::Vec::from_slice(s.as_bytes()).unwrap(),
+    }
+}
+
+#[test]
+fn union_discriminant() -> Result<(), DeserializeError> {
+    let response = Response {
+        timestamp: Timestamp {
+            microsecond: 0x1234_5678,
+        },
+        mutable: true,
+        persistent: false,
+        value: Value::Text(text("hello")),
+    };
+    let bytes = serialize(&response);
+    let view = ResponseView::new(&bytes);
+
+    assert!(view.mutable()?);
+    assert!(!view.persistent()?);
+    assert_eq!(0x1234_5678, view.timestamp_view()?.microsecond()?);
+    let value = view.value_view()?;
+    assert_eq!(2, value.discriminant());
+    assert!(value.integers()?.is_none());
+    assert!(value.empty_view()?.is_none());
+    assert_eq!(&b"hello"[..], &value.text()?.unwrap().value[..]);
+    assert_eq!(&b"hello"[..], &value.text_view()?.unwrap().value()?[..]);
+    assert_eq!(&b"hello"[..], &view.deserialize()?.value_text().value[..]);
+    Ok(())
+}
+
+#[test]
+fn skip_variable_fields() -> Result<(), DeserializeError> {
+    let mut names = heapless::Vec::new();
+    let _ = names.push(text("one"));
+    let _ = names.push(text("three"));
+    let record = Record {
+        flags: 5,
+        names,
+        extra: views::delimited_1_0::Delimited { a: 99 },
+        times: [Timestamp { microsecond: 1 }, Timestamp { microsecond: 2 }],
+        small: heapless::Vec::from_slice(&[1, 2, 127]).unwrap(),
+        bits: {
+            let mut bits = canadensis_encoding::bits::BitArray::new(3);
+            bits.set(2, true);
+            bits
+        },
+        more_times: heapless::Vec::new(),
+        last: 1.5,
+    };
+    let bytes = serialize(&record);
+    let view = RecordView::new(&bytes);
+
+    assert_eq!(5, view.flags()?);
+    assert_eq!(2, view.names()?.len());
+    assert_eq!(&b"three"[..], &view.names()?[1].value[..]);
+    assert_eq!(99, view.extra_view()?.a()?);
+    assert_eq!(2, view.times()?[1].microsecond);
+    assert_eq!(&[1, 2, 127][..], &view.small()?[..]);
+    assert_eq!(3, view.bits()?.len());
+    assert!(view.bits()?.get(2));
+    assert!(view.more_times()?.is_empty());
+    assert_eq!(1.5, view.last()?);
+    Ok(())
+}
+
+#[test]
+fn invalid_array_length() {
+    // The length of names is 3, which is more than the maximum
+    let bytes = [3u8 << 3];
+    let view = RecordView::new(&bytes);
+    assert_eq!(0, view.flags().unwrap());
+    assert!(matches!(view.last(), Err(DeserializeError::ArrayLength)));
+}
+
+trait ValueExt {
+    fn value_text(&self) -> &Text;
+}
+
+impl ValueExt for Response {
+    fn value_text(&self) -> &Text {
+        match &self.value {
+            Value::Text(text) => text,
+            _ => panic!("Not text"),
+        }
+    }
+}
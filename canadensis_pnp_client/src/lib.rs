@@ -1,7 +1,15 @@
 //!
 //! # Canadensis plug-and-play client
 //!
-//! This library implements the Cyphal plug-and-play node ID allocation protocol.
+//! This library implements the allocatee side of the Cyphal plug-and-play node ID allocation
+//! protocol.
+//!
+//! A [`PnpClient`] waits for a random initial delay, then publishes allocation requests at random
+//! intervals until an allocator responds with a node ID. The random values are derived from the
+//! unique ID, so nodes that start at the same time do not send their requests at the same times.
+//!
+//! The client uses `uavcan.pnp.NodeIDAllocationData` version 2.0 if a request of that version
+//! fits into one frame of the transport, and version 1.0 otherwise.
 //!
 
 #![no_std]
@@ -12,94 +20,216 @@ extern crate alloc;
 extern crate canadensis;
 extern crate canadensis_data_types;
 extern crate canadensis_filter_config;
-extern crate crc_any;
-extern crate heapless;
+extern crate num_traits;
 
-use canadensis::anonymous::AnonymousPublisher;
+use alloc::vec::Vec;
+use canadensis::anonymous::{AnonymousPublishError, AnonymousPublisher};
+use canadensis::core::nb;
 use canadensis::core::time::{milliseconds, Clock};
+use canadensis::core::transfer::Transfer;
 use canadensis::core::transport::{Receiver, Transmitter, Transport};
-use canadensis::core::{Priority, SubjectId};
-use canadensis::encoding::{Deserialize, Message, Serialize};
-use canadensis_data_types::uavcan::pnp::node_id_allocation_data_1_0::{self, NodeIDAllocationData};
-use core::convert::TryFrom;
-use core::marker::PhantomData;
-use crc_any::CRCu64;
+use canadensis::core::Priority;
+use canadensis::encoding::Serialize;
+use canadensis::service::pnp::RequestTimer;
+use canadensis_data_types::uavcan::pnp::{
+    node_id_allocation_data_1_0, node_id_allocation_data_2_0,
+};
+use num_traits::Bounded;
+
+pub use canadensis::service::pnp::AllocationMessage;
+
+type MessageV1 = node_id_allocation_data_1_0::NodeIDAllocationData;
+type MessageV2 = node_id_allocation_data_2_0::NodeIDAllocationData;
+
+/// The result of [`PnpClient::poll`]
+type PollResult<P, TE, RE> = Result<Option<<P as Transport>::NodeId>, PollError<TE, RE>>;
 
 /// A plug-and-play allocation client that can be used to find a node ID
-pub struct PnpClient<C: Clock, M, T: Transmitter<C>, R: Receiver<C>> {
+pub struct PnpClient<C: Clock, T: Transmitter<C>, R: Receiver<C>> {
     /// The unique ID of this node
     unique_id: [u8; 16],
-    /// Publisher used to send messages
-    publisher: AnonymousPublisher<C, M, T>,
+    /// The node ID to request, if any
+    preferred_node_id: Option<<T::Transport as Transport>::NodeId>,
+    /// Publisher used to send messages of the selected version
+    publisher: Publisher<C, T>,
     /// Transmitter used along with the publisher to send messages
     transmitter: T,
     /// Receiver used to receive messages
     receiver: R,
-    _message: PhantomData<M>,
+    /// Decides when to send requests
+    timer: RequestTimer,
+    /// The allocated node ID
+    node_id: Option<<T::Transport as Transport>::NodeId>,
 }
 
-impl<C, M, T, R, P> PnpClient<C, M, T, R>
+impl<C, T, R, P> PnpClient<C, T, R>
 where
     C: Clock,
-    M: AllocationMessage<P>,
     T: Transmitter<C, Transport = P>,
     R: Receiver<C, Transport = P>,
     P: Transport,
+    MessageV1: AllocationMessage<P>,
+    MessageV2: AllocationMessage<P>,
 {
-    /// Creates a new plug-and-play client
+    /// Creates a new plug-and-play client and subscribes to allocation messages
     ///
     /// * `unique_id`: The unique ID of this node
+    ///
+    /// The client uses version 2.0 of the allocation message if a request fits into one frame
+    /// with the transmitter's MTU, and version 1.0 otherwise.
     pub fn new(
         transmitter: T,
         mut receiver: R,
         unique_id: [u8; 16],
         driver: &mut R::Driver,
     ) -> Result<Self, R::Error> {
-        receiver.subscribe_message(M::SUBJECT, 9, milliseconds(1000), driver)?;
+        let publisher = if <MessageV2 as AllocationMessage<P>>::with_unique_id(&unique_id)
+            .size_bits()
+            .div_ceil(8)
+            <= transmitter.mtu()
+        {
+            receiver.subscribe_message(
+                <MessageV2 as AllocationMessage<P>>::SUBJECT,
+                <MessageV2 as AllocationMessage<P>>::PAYLOAD_SIZE_MAX,
+                milliseconds(1000),
+                driver,
+            )?;
+            Publisher::V2(AnonymousPublisher::new(
+                <MessageV2 as AllocationMessage<P>>::SUBJECT,
+                Priority::Nominal.into(),
+                milliseconds(1000),
+            ))
+        } else {
+            receiver.subscribe_message(
+                <MessageV1 as AllocationMessage<P>>::SUBJECT,
+                <MessageV1 as AllocationMessage<P>>::PAYLOAD_SIZE_MAX,
+                milliseconds(1000),
+                driver,
+            )?;
+            Publisher::V1(AnonymousPublisher::new(
+                <MessageV1 as AllocationMessage<P>>::SUBJECT,
+                Priority::Nominal.into(),
+                milliseconds(1000),
+            ))
+        };
 
         Ok(PnpClient {
             unique_id,
-            publisher: AnonymousPublisher::new(
-                M::SUBJECT,
-                Priority::Nominal.into(),
-                milliseconds(1000),
-            ),
+            preferred_node_id: None,
+            publisher,
             transmitter,
             receiver,
-            _message: PhantomData,
+            timer: RequestTimer::new(&unique_id),
+            node_id: None,
         })
     }
 
-    /// Creates an outgoing node ID allocation message and gives it to the transmitter
-    pub fn send_request(&mut self, clock: &mut C, driver: &mut T::Driver) {
-        let message = M::with_unique_id(&self.unique_id);
-        let status = self
-            .publisher
-            .send(&message, clock, &mut self.transmitter, driver);
-        match status {
-            Ok(()) => {}
-            Err(_) => panic!("Can't fit transfer into one frame"),
+    /// Sets a node ID that this client will ask the allocator for
+    ///
+    /// The allocator may assign a different node ID. Only version 2.0 requests contain a preferred
+    /// node ID. Without a preferred node ID, version 2.0 requests ask for the highest node ID.
+    pub fn with_preferred_node_id(mut self, node_id: P::NodeId) -> Self {
+        self.preferred_node_id = Some(node_id);
+        self
+    }
+
+    /// Returns the version of the allocation message that this client uses
+    pub fn version(&self) -> MessageVersion {
+        match self.publisher {
+            Publisher::V1(_) => MessageVersion::V1,
+            Publisher::V2(_) => MessageVersion::V2,
         }
     }
 
-    /// Handles an incoming frame and checks if it provides an ID for this node
+    /// Handles incoming frames, sends an allocation request if one is due, and flushes the
+    /// transmitter
     ///
-    /// This function returns the node ID if one was assigned.
-    pub fn receive(
+    /// The first call to this function starts the initial delay before the first request.
+    ///
+    /// This function returns the node ID if one has been allocated. After that, it always
+    /// returns the same node ID and does not send or receive anything.
+    pub fn poll(
         &mut self,
         clock: &mut C,
-        driver: &mut R::Driver,
-    ) -> Result<Option<P::NodeId>, R::Error> {
-        if let Some(transfer_in) = self.receiver.receive(clock, driver)? {
-            if let Ok(message) = M::deserialize_from_bytes(&transfer_in.payload) {
-                if message.matches_unique_id(&self.unique_id) {
-                    if let Some(node_id) = message.node_id() {
-                        return Ok(Some(node_id));
-                    }
+        driver: &mut T::Driver,
+    ) -> PollResult<P, T::Error, R::Error>
+    where
+        R: Receiver<C, Driver = T::Driver>,
+    {
+        if let Some(node_id) = &self.node_id {
+            return Ok(Some(node_id.clone()));
+        }
+        let mut received_message = false;
+        while let Some(transfer) = self
+            .receiver
+            .receive(clock, driver)
+            .map_err(PollError::Receive)?
+        {
+            if let Some(node_id) = self.allocated_node_id(&transfer) {
+                self.node_id = Some(node_id.clone());
+                return Ok(Some(node_id));
+            }
+            received_message = true;
+        }
+
+        let now = clock.now();
+        if received_message && self.version() == MessageVersion::V1 {
+            // With version 1.0, any allocation message (from another allocatee or for another
+            // node) restarts the request timer
+            self.timer.restart(now);
+        }
+        if self.timer.is_due(now) {
+            match self.send_request(clock, driver) {
+                Ok(()) => self.timer.restart(now),
+                // Try again on the next poll
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(AnonymousPublishError::Length)) => {
+                    return Err(PollError::Length)
+                }
+                Err(nb::Error::Other(AnonymousPublishError::Transport(e))) => {
+                    return Err(PollError::Transmit(e))
                 }
             }
         }
-        Ok(None)
+        match self.transmitter.flush(clock, driver) {
+            Ok(()) | Err(nb::Error::WouldBlock) => Ok(None),
+            Err(nb::Error::Other(e)) => Err(PollError::Transmit(e)),
+        }
+    }
+
+    /// Creates an allocation request and gives it to the transmitter
+    fn send_request(
+        &mut self,
+        clock: &mut C,
+        driver: &mut T::Driver,
+    ) -> nb::Result<(), AnonymousPublishError<T::Error>> {
+        match &mut self.publisher {
+            Publisher::V1(publisher) => {
+                // Version 1.0 requests never contain a node ID
+                let message = <MessageV1 as AllocationMessage<P>>::with_unique_id(&self.unique_id);
+                publisher.send(&message, clock, &mut self.transmitter, driver)
+            }
+            Publisher::V2(publisher) => {
+                // Without a preference, request the highest node ID
+                let node_id = self
+                    .preferred_node_id
+                    .clone()
+                    .unwrap_or_else(P::NodeId::max_value);
+                let message = <MessageV2 as AllocationMessage<P>>::with_unique_id(&self.unique_id)
+                    .with_node_id(node_id);
+                publisher.send(&message, clock, &mut self.transmitter, driver)
+            }
+        }
+    }
+
+    /// Returns the node ID that an incoming transfer allocates to this node, if any
+    fn allocated_node_id(&self, transfer: &Transfer<Vec<u8>, P>) -> Option<P::NodeId> {
+        // Requests from other allocatees are anonymous. Only allocators send responses.
+        transfer.header.source()?;
+        match self.publisher {
+            Publisher::V1(_) => response_node_id::<MessageV1, P>(&self.unique_id, transfer),
+            Publisher::V2(_) => response_node_id::<MessageV2, P>(&self.unique_id, transfer),
+        }
     }
 
     /// Returns a reference to the transmitter
@@ -120,56 +250,46 @@ where
     }
 }
 
-/// A node ID allocation message
-///
-/// This is currently implemented for `uavcan.pnp.NodeIdAllocationData` version 1.0. In the future,
-/// it may also be implemented for version 2.0 of that data type.
-pub trait AllocationMessage<T: Transport>: Message + Serialize + Deserialize {
-    /// The fixed subject ID for this message
-    const SUBJECT: SubjectId;
-
-    /// Creates a message with the provided unique ID and no allocated node ID
-    ///
-    /// The message must fit into one frame of the transport that is being used.
-    fn with_unique_id(id: &[u8; 16]) -> Self;
-
-    /// Determines if this message matches the provided unique ID
-    fn matches_unique_id(&self, id: &[u8; 16]) -> bool;
-
-    /// Returns the allocated node ID in this message, if one is specified
-    fn node_id(&self) -> Option<T::NodeId>;
+/// A version of the `uavcan.pnp.NodeIDAllocationData` message
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MessageVersion {
+    /// Version 1.0, which contains a 48-bit hash of the unique ID and fits into one Classic CAN
+    /// frame
+    V1,
+    /// Version 2.0, which contains the full unique ID
+    V2,
 }
 
-impl<T: Transport> AllocationMessage<T> for NodeIDAllocationData {
-    const SUBJECT: SubjectId = node_id_allocation_data_1_0::SUBJECT;
-
-    fn with_unique_id(id: &[u8; 16]) -> Self {
-        let id_hash = crc_64we_48_bits(id);
-        NodeIDAllocationData {
-            unique_id_hash: id_hash,
-            allocated_node_id: heapless::Vec::new(),
-        }
-    }
-
-    fn matches_unique_id(&self, id: &[u8; 16]) -> bool {
-        let id_hash = crc_64we_48_bits(id);
-        self.unique_id_hash == id_hash
-    }
+/// Errors that can occur when polling a plug-and-play client
+#[derive(Debug)]
+pub enum PollError<T, R> {
+    /// The allocation request was too long to fit into one frame
+    Length,
+    /// The transmitter returned an error
+    Transmit(T),
+    /// The receiver returned an error
+    Receive(R),
+}
 
-    fn node_id(&self) -> Option<T::NodeId> {
-        self.allocated_node_id.iter().next().and_then(|id| {
-            // The message may allow a wider range of node IDs than the transport allows.
-            // If the ID is too large, return None.
-            T::NodeId::try_from(id.value).ok()
-        })
-    }
+/// An anonymous publisher for the selected message version
+enum Publisher<C: Clock, T: Transmitter<C>> {
+    V1(AnonymousPublisher<C, MessageV1, T>),
+    V2(AnonymousPublisher<C, MessageV2, T>),
 }
 
-/// Calculates a CRC-64WE hash of the provided ID and returns the less significant 48 bits of the
-/// result
-fn crc_64we_48_bits(id: &[u8; 16]) -> u64 {
-    let mut crc = CRCu64::crc64we();
-    crc.digest(id);
-    let value = crc.get_crc();
-    value & 0x0000_ffff_ffff_ffff
+/// Deserializes an allocation message and returns its node ID if it matches the unique ID
+fn response_node_id<M, P>(
+    unique_id: &[u8; 16],
+    transfer: &Transfer<Vec<u8>, P>,
+) -> Option<P::NodeId>
+where
+    M: AllocationMessage<P>,
+    P: Transport,
+{
+    let message = M::deserialize_from_bytes(&transfer.payload).ok()?;
+    if message.matches_unique_id(unique_id) {
+        message.node_id()
+    } else {
+        None
+    }
 }
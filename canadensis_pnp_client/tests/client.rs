@@ -0,0 +1,253 @@
+//!
+//! Tests of the plug-and-play client on simulated networks
+//!
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_data_types;
+extern crate canadensis_pnp_client;
+extern crate canadensis_serial;
+extern crate canadensis_sim;
+
+use std::convert::TryFrom;
+
+use canadensis::core::subscription::DynamicSubscriptionManager;
+use canadensis::core::time::{milliseconds, Microseconds32};
+use canadensis::core::transfer::MessageTransfer;
+use canadensis::core::transport::Transport;
+use canadensis::core::{Priority, SubjectId};
+use canadensis::encoding::Deserialize;
+use canadensis::{Node, TransferHandler};
+use canadensis_can::{CanNodeId, CanReceiver, CanTransmitter, CanTransport, Mtu};
+use canadensis_data_types::uavcan::pnp::{
+    node_id_allocation_data_1_0, node_id_allocation_data_2_0,
+};
+use canadensis_pnp_client::{AllocationMessage, MessageVersion, PnpClient};
+use canadensis_serial::{
+    SerialNodeId, SerialReceiver, SerialTransmitter, SerialTransport, Subscription,
+};
+use canadensis_sim::can::{CanBus, CanBusConfig, SimCanNode};
+use canadensis_sim::serial::SerialLinkConfig;
+use canadensis_sim::Simulation;
+
+type MessageV1 = node_id_allocation_data_1_0::NodeIDAllocationData;
+type MessageV2 = node_id_allocation_data_2_0::NodeIDAllocationData;
+
+/// Records the arrival times and payloads of allocation requests, which are anonymous
+#[derive(Default)]
+struct RequestCollector {
+    requests: Vec<(Microseconds32, Vec<u8>)>,
+}
+
+impl<T: Transport> TransferHandler<T> for RequestCollector {
+    fn handle_message<N: Node<Transport = T>>(
+        &mut self,
+        _node: &mut N,
+        transfer: &MessageTransfer<Vec<u8>, T>,
+    ) -> bool {
+        if transfer.header.source.is_none() {
+            self.requests
+                .push((transfer.header.timestamp, transfer.payload.clone()));
+        }
+        true
+    }
+}
+
+fn make_can_allocator(bus: &CanBus) -> SimCanNode {
+    let mut node = bus.node(CanNodeId::try_from(10u8).unwrap());
+    start_allocator(&mut node, node_id_allocation_data_1_0::SUBJECT, 9);
+    node
+}
+
+fn start_allocator<N: Node>(node: &mut N, subject: SubjectId, payload_size_max: usize) {
+    node.start_publishing(subject, milliseconds(1000), Priority::Nominal.into())
+        .unwrap();
+    node.subscribe_message(subject, payload_size_max, milliseconds(1000))
+        .unwrap();
+}
+
+/// Checks that the first request was sent within one second, and the intervals between
+/// requests are between 600 milliseconds and one second
+///
+/// The limits include one millisecond for the simulation step.
+fn check_timing(requests: &[(Microseconds32, Vec<u8>)]) {
+    assert!(requests.len() >= 4);
+    assert!(requests[0].0.ticks() <= 1_001_000);
+    for pair in requests.windows(2) {
+        let interval = (pair[1].0 - pair[0].0).to_millis();
+        assert!((600..=1001).contains(&interval), "Interval {}", interval);
+    }
+}
+
+#[test]
+fn allocation_on_classic_can() {
+    let mut simulation = Simulation::new(1);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let unique_id = [0x5a; 16];
+    let mut driver = bus.attach();
+    let mut client = PnpClient::new(
+        CanTransmitter::new(Mtu::Can8),
+        CanReceiver::new_anonymous(),
+        unique_id,
+        &mut driver,
+    )
+    .unwrap()
+    .with_preferred_node_id(CanNodeId::try_from(5u8).unwrap());
+    assert_eq!(MessageVersion::V1, client.version());
+    let mut allocator = make_can_allocator(&bus);
+    let mut collector = RequestCollector::default();
+    let mut clock = simulation.clock();
+
+    simulation.run_for(milliseconds(5000), milliseconds(1), |_now| {
+        assert_eq!(None, client.poll(&mut clock, &mut driver).unwrap());
+        allocator.receive(&mut collector).unwrap();
+    });
+    check_timing(&collector.requests);
+    for (_, payload) in &collector.requests {
+        // A 48-bit hash and an empty node ID array. Version 1.0 requests do not contain the
+        // preferred node ID.
+        assert_eq!(7, payload.len());
+        let request = MessageV1::deserialize_from_bytes(payload).unwrap();
+        assert!(AllocationMessage::<CanTransport>::matches_unique_id(
+            &request, &unique_id
+        ));
+    }
+
+    let allocated_id = CanNodeId::try_from(9u8).unwrap();
+    let response = <MessageV1 as AllocationMessage<CanTransport>>::with_node_id(
+        AllocationMessage::<CanTransport>::with_unique_id(&unique_id),
+        allocated_id,
+    );
+    allocator
+        .publish(node_id_allocation_data_1_0::SUBJECT, &response)
+        .unwrap();
+    allocator.flush().unwrap();
+    let mut allocated = None;
+    simulation.run_for(milliseconds(10), milliseconds(1), |_now| {
+        if let Some(node_id) = client.poll(&mut clock, &mut driver).unwrap() {
+            allocated = Some(node_id);
+        }
+    });
+    assert_eq!(Some(allocated_id), allocated);
+}
+
+#[test]
+fn version_1_allocation_messages_restart_request_timer() {
+    let mut simulation = Simulation::new(1);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let mut driver = bus.attach();
+    let mut client = PnpClient::new(
+        CanTransmitter::new(Mtu::Can8),
+        CanReceiver::new_anonymous(),
+        [0x5a; 16],
+        &mut driver,
+    )
+    .unwrap();
+    let mut allocator = make_can_allocator(&bus);
+    let mut collector = RequestCollector::default();
+    let mut clock = simulation.clock();
+
+    // An allocation for another node every 500 milliseconds, which is less than the minimum
+    // request interval
+    let other_node = <MessageV1 as AllocationMessage<CanTransport>>::with_node_id(
+        AllocationMessage::<CanTransport>::with_unique_id(&[0x33; 16]),
+        CanNodeId::try_from(20u8).unwrap(),
+    );
+    simulation.run_for(milliseconds(5000), milliseconds(1), |now| {
+        if now.ticks() % 500_000 == 0 {
+            allocator
+                .publish(node_id_allocation_data_1_0::SUBJECT, &other_node)
+                .unwrap();
+            allocator.flush().unwrap();
+        }
+        assert_eq!(None, client.poll(&mut clock, &mut driver).unwrap());
+        allocator.receive(&mut collector).unwrap();
+    });
+    assert!(collector.requests.is_empty());
+}
+
+#[test]
+fn request_times_depend_on_unique_id() {
+    // With version 1.0, the requests from each client would restart the request timer of the
+    // other client, so each client runs alone.
+    let times: Vec<Vec<Microseconds32>> = [[1; 16], [2; 16]]
+        .iter()
+        .map(|unique_id| {
+            let mut simulation = Simulation::new(1);
+            let bus = simulation.add_can_bus(CanBusConfig::default());
+            let mut driver = bus.attach();
+            let mut client = PnpClient::new(
+                CanTransmitter::new(Mtu::Can8),
+                CanReceiver::new_anonymous(),
+                *unique_id,
+                &mut driver,
+            )
+            .unwrap();
+            let mut allocator = make_can_allocator(&bus);
+            let mut collector = RequestCollector::default();
+            let mut clock = simulation.clock();
+
+            simulation.run_for(milliseconds(5000), milliseconds(1), |_now| {
+                assert_eq!(None, client.poll(&mut clock, &mut driver).unwrap());
+                allocator.receive(&mut collector).unwrap();
+            });
+            check_timing(&collector.requests);
+            collector.requests.iter().map(|(time, _)| *time).collect()
+        })
+        .collect();
+    assert_ne!(times[0][0], times[1][0]);
+}
+
+#[test]
+fn allocation_on_serial_with_preferred_node_id() {
+    let mut simulation = Simulation::new(1);
+    let link = simulation.add_serial_link(SerialLinkConfig::default());
+    let (mut port, allocator_port) = link.ports();
+    let unique_id = [0xc3; 16];
+    let preferred_id = SerialNodeId::try_from(5u16).unwrap();
+    let mut client = PnpClient::new(
+        SerialTransmitter::<_, 256>::new(),
+        SerialReceiver::<_, _, DynamicSubscriptionManager<Subscription>>::new_anonymous(),
+        unique_id,
+        &mut port,
+    )
+    .unwrap()
+    .with_preferred_node_id(preferred_id);
+    // Serial transfers have no size limit, so the client uses the version with the full
+    // unique ID
+    assert_eq!(MessageVersion::V2, client.version());
+
+    let mut allocator = allocator_port.into_node(SerialNodeId::try_from(10u16).unwrap());
+    start_allocator(&mut allocator, node_id_allocation_data_2_0::SUBJECT, 18);
+    let mut collector = RequestCollector::default();
+    let mut clock = simulation.clock();
+
+    simulation.run_for(milliseconds(5000), milliseconds(1), |_now| {
+        assert_eq!(None, client.poll(&mut clock, &mut port).unwrap());
+        allocator.receive(&mut collector).unwrap();
+    });
+    check_timing(&collector.requests);
+    for (_, payload) in &collector.requests {
+        let request = MessageV2::deserialize_from_bytes(payload).unwrap();
+        assert_eq!(unique_id, request.unique_id);
+        assert_eq!(5, { request.node_id.value });
+    }
+
+    // The allocator assigns a different node ID
+    let allocated_id = SerialNodeId::try_from(7u16).unwrap();
+    let response = <MessageV2 as AllocationMessage<SerialTransport>>::with_node_id(
+        AllocationMessage::<SerialTransport>::with_unique_id(&unique_id),
+        allocated_id,
+    );
+    allocator
+        .publish(node_id_allocation_data_2_0::SUBJECT, &response)
+        .unwrap();
+    allocator.flush().unwrap();
+    let mut allocated = None;
+    simulation.run_for(milliseconds(100), milliseconds(1), |_now| {
+        if let Some(node_id) = client.poll(&mut clock, &mut port).unwrap() {
+            allocated = Some(node_id);
+        }
+    });
+    assert_eq!(Some(allocated_id), allocated);
+}
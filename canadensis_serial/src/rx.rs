@@ -5,6 +5,7 @@ use core::mem;
 use fallible_collections::TryHashMap;
 
 use canadensis_core::crc::CrcTracker;
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::subscription::SubscriptionManager;
 use canadensis_core::time::{Clock, MicrosecondDuration32, Microseconds32};
 use canadensis_core::transfer::{Header, Transfer};
@@ -24,6 +25,8 @@ pub struct SerialReceiver<C, D, S> {
     state: State,
     node_id: Option<SerialNodeId>,
     subscriptions: S,
+    /// Transfers received and failed
+    statistics: IoStatistics,
     _driver: PhantomData<D>,
     _clock: PhantomData<C>,
 }
@@ -39,6 +42,7 @@ where
             state: State::Idle,
             node_id: Some(node_id),
             subscriptions: S::default(),
+            statistics: IoStatistics::default(),
             _driver: PhantomData,
             _clock: PhantomData,
         }
@@ -48,6 +52,7 @@ where
             state: State::Idle,
             node_id: None,
             subscriptions: S::default(),
+            statistics: IoStatistics::default(),
             _driver: PhantomData,
             _clock: PhantomData,
         }
@@ -220,12 +225,21 @@ where
         loop {
             match driver.receive_byte() {
                 Ok(byte) => match self.handle_byte(byte, clock.now()) {
-                    Ok(Some(transfer)) => break Ok(Some(transfer)),
+                    Ok(Some(transfer)) => {
+                        self.statistics.received = self.statistics.received.wrapping_add(1);
+                        break Ok(Some(transfer));
+                    }
                     Ok(None) => { /* Keep going and try another byte */ }
-                    Err(e) => break Err(e),
+                    Err(e) => {
+                        self.statistics.errored = self.statistics.errored.wrapping_add(1);
+                        break Err(e);
+                    }
                 },
                 Err(nb::Error::WouldBlock) => break Ok(None),
-                Err(nb::Error::Other(e)) => break Err(Error::Driver(e)),
+                Err(nb::Error::Other(e)) => {
+                    self.statistics.errored = self.statistics.errored.wrapping_add(1);
+                    break Err(Error::Driver(e));
+                }
             }
         }
     }
@@ -297,6 +311,16 @@ where
     fn servers(&self) -> impl Iterator<Item = ServiceId> {
         self.subscriptions.servers()
     }
+
+    /// Adds the numbers of transfers received and failed to `statistics`
+    ///
+    /// Each transfer is sent in one frame, so the same numbers are added as interface 0.
+    fn add_statistics(&self, _driver: &D, statistics: &mut TransportStatistics) {
+        statistics.transfers.add(&self.statistics);
+        if let Some(interface) = statistics.interface_mut(0) {
+            interface.add(&self.statistics);
+        }
+    }
 }
 
 impl<C, D, S> SerialReceiver<C, D, S>
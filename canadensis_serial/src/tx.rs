@@ -4,6 +4,7 @@ use core::marker::PhantomData;
 use heapless::Deque;
 use zerocopy::IntoBytes;
 
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::time::Clock;
 use canadensis_core::transfer::Transfer;
 use canadensis_core::transport::Transmitter;
@@ -27,6 +28,8 @@ const DELIMITER: u8 = 0x0;
 pub struct SerialTransmitter<D, const C: usize> {
     /// Queue of outgoing bytes
     queue: TransmitQueue<C>,
+    /// Transfers placed in the queue and transfers that did not fit
+    statistics: IoStatistics,
     _driver: PhantomData<D>,
 }
 
@@ -34,32 +37,21 @@ impl<D, const C: usize> SerialTransmitter<D, C> {
     pub fn new() -> Self {
         SerialTransmitter {
             queue: TransmitQueue::new(),
+            statistics: IoStatistics::default(),
             _driver: PhantomData,
         }
     }
 }
 
-impl<D, const C: usize> Default for SerialTransmitter<D, C> {
-    fn default() -> Self {
-        SerialTransmitter::new()
-    }
-}
-
-impl<L, D, const C: usize> Transmitter<L> for SerialTransmitter<D, C>
+impl<D, const C: usize> SerialTransmitter<D, C>
 where
-    L: Clock,
     D: TransmitDriver,
 {
-    type Transport = SerialTransport;
-    type Driver = D;
-    type Error = Error<D::Error>;
-
-    fn push<A>(
+    /// Escapes a transfer and adds it to the queue
+    fn push_inner<A>(
         &mut self,
-        transfer: Transfer<A, Self::Transport>,
-        _clock: &mut L,
-        _driver: &mut D,
-    ) -> nb::Result<(), Self::Error>
+        transfer: Transfer<A, SerialTransport>,
+    ) -> nb::Result<(), Error<D::Error>>
     where
         A: AsRef<[u8]>,
     {
@@ -103,6 +95,40 @@ where
 
         Ok(())
     }
+}
+
+impl<D, const C: usize> Default for SerialTransmitter<D, C> {
+    fn default() -> Self {
+        SerialTransmitter::new()
+    }
+}
+
+impl<L, D, const C: usize> Transmitter<L> for SerialTransmitter<D, C>
+where
+    L: Clock,
+    D: TransmitDriver,
+{
+    type Transport = SerialTransport;
+    type Driver = D;
+    type Error = Error<D::Error>;
+
+    fn push<A>(
+        &mut self,
+        transfer: Transfer<A, Self::Transport>,
+        _clock: &mut L,
+        _driver: &mut D,
+    ) -> nb::Result<(), Self::Error>
+    where
+        A: AsRef<[u8]>,
+    {
+        let result = self.push_inner(transfer);
+        if result.is_ok() {
+            self.statistics.emitted = self.statistics.emitted.wrapping_add(1);
+        } else {
+            self.statistics.errored = self.statistics.errored.wrapping_add(1);
+        }
+        result
+    }
 
     fn flush(&mut self, _clock: &mut L, driver: &mut D) -> nb::Result<(), Self::Error> {
         while let Some(byte) = self.queue.pop_front() {
@@ -128,6 +154,16 @@ where
         // Virtually unlimited
         usize::MAX
     }
+
+    /// Adds the numbers of transfers queued and dropped to `statistics`
+    ///
+    /// Each transfer is sent in one frame, so the same numbers are added as interface 0.
+    fn add_statistics(&self, _driver: &D, statistics: &mut TransportStatistics) {
+        statistics.transfers.add(&self.statistics);
+        if let Some(interface) = statistics.interface_mut(0) {
+            interface.add(&self.statistics);
+        }
+    }
 }
 
 /// A queue of bytes to be transmitted
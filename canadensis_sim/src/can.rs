@@ -0,0 +1,320 @@
+//!
+//! A simulated CAN bus
+//!
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use canadensis::node::CoreNode;
+use canadensis::requester::TransferIdFixedMap;
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanNodeId, CanReceiver, CanTransmitter, CanTransport, Frame, Mtu};
+use canadensis_core::subscription::Subscription;
+use canadensis_core::time::{MicrosecondDuration32, Microseconds32};
+use canadensis_core::OutOfMemoryError;
+
+use crate::fault::{LinkFaults, Rng};
+use crate::{Faults, LinkDown, LinkId, Network, SimClock};
+
+/// The settings for a simulated CAN bus
+#[derive(Debug, Clone)]
+pub struct CanBusConfig {
+    /// The bit rate, in bits per second
+    ///
+    /// Default: 1 Mbit/s
+    pub bit_rate: u32,
+    /// The number of frames that each driver can hold while waiting to send them
+    ///
+    /// Default: 64
+    pub tx_queue_capacity: usize,
+}
+
+impl Default for CanBusConfig {
+    fn default() -> Self {
+        CanBusConfig {
+            bit_rate: 1_000_000,
+            tx_queue_capacity: 64,
+        }
+    }
+}
+
+/// A node on a simulated CAN bus, with room for 8 publishers and 8 requesters
+pub type SimCanNode = CoreNode<
+    SimClock,
+    CanTransmitter<SimClock, SimCanDriver>,
+    CanReceiver<SimClock, SimCanDriver>,
+    TransferIdFixedMap<CanTransport, 8>,
+    SimCanDriver,
+    8,
+    8,
+>;
+
+/// A simulated CAN bus
+///
+/// Frames are sent one at a time. When more than one frame is waiting, the frame with the
+/// lowest CAN ID wins arbitration (frames with the same ID are sent in the order they were
+/// transmitted). Each frame takes as long to send as an extended-ID frame with the same
+/// length and an average amount of bit stuffing would take at the configured bit rate.
+///
+/// A frame that is still waiting when its deadline passes is discarded.
+///
+/// Clones of a bus refer to the same bus.
+#[derive(Clone)]
+pub struct CanBus(Rc<RefCell<BusState>>);
+
+struct BusState {
+    config: CanBusConfig,
+    clock: SimClock,
+    rng: Rng,
+    links: Vec<CanLink>,
+    /// The number of frames that have been transmitted, used to break ties in arbitration
+    sequence: u64,
+    /// The frame that is currently being sent, the index of the link that sent it, and the
+    /// time when it will be completely sent
+    in_flight: Option<(Frame, usize, Microseconds32)>,
+    /// The time when the bus becomes idle
+    busy_until: Microseconds32,
+    /// The time of the previous step
+    last_run: Microseconds32,
+}
+
+#[derive(Default)]
+struct CanLink {
+    /// Frames waiting to be sent, with their sequence numbers
+    tx: VecDeque<(u64, Frame)>,
+    rx: VecDeque<Frame>,
+    faults: LinkFaults<Frame>,
+    bus_off: bool,
+}
+
+impl CanBus {
+    pub(crate) fn new(config: CanBusConfig, clock: SimClock, rng: Rng) -> Self {
+        CanBus(Rc::new(RefCell::new(BusState {
+            config,
+            clock,
+            rng,
+            links: Vec::new(),
+            sequence: 0,
+            in_flight: None,
+            busy_until: Microseconds32::from_ticks(0),
+            last_run: Microseconds32::from_ticks(0),
+        })))
+    }
+
+    /// Connects a new node to this bus and returns its driver
+    pub fn attach(&self) -> SimCanDriver {
+        let mut state = self.0.borrow_mut();
+        state.links.push(CanLink::default());
+        SimCanDriver {
+            bus: self.0.clone(),
+            link: LinkId(state.links.len() - 1),
+        }
+    }
+
+    /// Connects a new node with the provided ID to this bus
+    ///
+    /// The node uses the simulation clock and classic CAN frames. Its link ID is available
+    /// from `node.driver().link()`.
+    pub fn node(&self, id: CanNodeId) -> SimCanNode {
+        let clock = self.0.borrow().clock.clone();
+        CoreNode::new(
+            clock,
+            id,
+            CanTransmitter::new(Mtu::Can8),
+            CanReceiver::new(id),
+            self.attach(),
+        )
+    }
+
+    /// Sets the faults that a link applies to the frames it receives
+    pub fn set_faults(&self, link: LinkId, faults: Faults) {
+        self.0.borrow_mut().links[link.0].faults.faults = faults;
+    }
+
+    /// Puts a link into or takes a link out of the bus-off state
+    ///
+    /// When a link goes into the bus-off state, it discards all frames that it was going to send
+    /// and has not yet received. While it is in the bus-off state, it does not receive anything
+    /// and its driver returns [`LinkDown`] errors when transmitting.
+    pub fn set_bus_off(&self, link: LinkId, bus_off: bool) {
+        let mut state = self.0.borrow_mut();
+        let link = &mut state.links[link.0];
+        link.bus_off = bus_off;
+        if bus_off {
+            link.tx.clear();
+            link.rx.clear();
+            link.faults.clear();
+        }
+    }
+}
+
+impl BusState {
+    /// Returns the time needed to send a frame
+    fn frame_duration(&self, frame: &Frame) -> MicrosecondDuration32 {
+        // Extended-ID frame overhead, including the interframe space
+        let bits = 67 + 8 * frame.data().len() as u64;
+        // Average bit stuffing
+        let bits = bits + bits / 5;
+        let micros = (bits * 1_000_000).div_ceil(u64::from(self.config.bit_rate));
+        MicrosecondDuration32::from_ticks(micros as u32)
+    }
+
+    /// Removes and returns the frame that wins arbitration, and the index of its link
+    ///
+    /// Frames with deadlines before `now` are discarded.
+    fn arbitrate(&mut self, now: Microseconds32) -> Option<(Frame, usize)> {
+        for link in &mut self.links {
+            link.tx.retain(|(_, frame)| frame.timestamp() >= now);
+        }
+        let (index, position) = self
+            .links
+            .iter()
+            .enumerate()
+            .filter(|(_, link)| !link.bus_off)
+            .flat_map(|(index, link)| {
+                link.tx
+                    .iter()
+                    .enumerate()
+                    .map(move |(position, (sequence, frame))| {
+                        ((u32::from(frame.id()), *sequence), index, position)
+                    })
+            })
+            .min()
+            .map(|(_, index, position)| (index, position))?;
+        let (_, frame) = self.links[index].tx.remove(position)?;
+        Some((frame, index))
+    }
+
+    /// Delivers a frame to all links that are not in the bus-off state
+    fn deliver(&mut self, mut frame: Frame, sender: usize, time: Microseconds32) {
+        frame.set_timestamp(time);
+        let rng = &mut self.rng;
+        for (index, link) in self.links.iter_mut().enumerate() {
+            if link.bus_off {
+                continue;
+            }
+            if index == sender {
+                // The sender receives its own frame only if it asked for loopback
+                if frame.loopback() {
+                    link.rx.push_back(frame.clone());
+                }
+            } else {
+                let mut received = frame.clone();
+                received.set_loopback(false);
+                link.faults.deliver(received, rng, &mut link.rx);
+            }
+        }
+    }
+}
+
+impl Network for CanBus {
+    fn run_until(&self, now: Microseconds32) {
+        let mut state = self.0.borrow_mut();
+        let state = &mut *state;
+        loop {
+            if let Some((_, _, end)) = &state.in_flight {
+                if *end > now {
+                    break;
+                }
+                let (frame, sender, end) = state.in_flight.take().unwrap();
+                state.deliver(frame, sender, end);
+            }
+            // Frames that were transmitted during the previous step are ready to send at the
+            // beginning of the step
+            let start = if state.busy_until > state.last_run {
+                state.busy_until
+            } else {
+                state.last_run
+            };
+            if start > now {
+                break;
+            }
+            match state.arbitrate(start) {
+                Some((frame, sender)) => {
+                    let end = start + state.frame_duration(&frame);
+                    state.busy_until = end;
+                    state.in_flight = Some((frame, sender, end));
+                }
+                None => break,
+            }
+        }
+        state.last_run = now;
+        for link in &mut state.links {
+            link.faults.end_step(&mut link.rx);
+        }
+    }
+}
+
+/// A driver that connects a node to a simulated CAN bus
+pub struct SimCanDriver {
+    bus: Rc<RefCell<BusState>>,
+    link: LinkId,
+}
+
+impl SimCanDriver {
+    /// Returns the identifier of the link between this driver and the bus
+    pub fn link(&self) -> LinkId {
+        self.link
+    }
+}
+
+impl TransmitDriver<SimClock> for SimCanDriver {
+    type Error = LinkDown;
+
+    fn try_reserve(&mut self, frames: usize) -> Result<(), OutOfMemoryError> {
+        let state = self.bus.borrow();
+        let queued = state.links[self.link.0].tx.len();
+        if queued + frames <= state.config.tx_queue_capacity {
+            Ok(())
+        } else {
+            Err(OutOfMemoryError)
+        }
+    }
+
+    fn transmit(
+        &mut self,
+        frame: Frame,
+        _clock: &mut SimClock,
+    ) -> nb::Result<Option<Frame>, Self::Error> {
+        let mut state = self.bus.borrow_mut();
+        let capacity = state.config.tx_queue_capacity;
+        let sequence = state.sequence;
+        let link = &mut state.links[self.link.0];
+        if link.bus_off {
+            return Err(nb::Error::Other(LinkDown));
+        }
+        if link.tx.len() >= capacity {
+            return Err(nb::Error::WouldBlock);
+        }
+        link.tx.push_back((sequence, frame));
+        state.sequence += 1;
+        Ok(None)
+    }
+
+    fn flush(&mut self, _clock: &mut SimClock) -> nb::Result<(), Self::Error> {
+        // Frames are sent when the simulation steps
+        Ok(())
+    }
+}
+
+impl ReceiveDriver<SimClock> for SimCanDriver {
+    type Error = Infallible;
+
+    fn receive(&mut self, _clock: &mut SimClock) -> nb::Result<Frame, Self::Error> {
+        self.bus.borrow_mut().links[self.link.0]
+            .rx
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+
+    fn apply_filters<S>(&mut self, _local_node: Option<CanNodeId>, _subscriptions: S)
+    where
+        S: IntoIterator<Item = Subscription>,
+    {
+        // The receiver ignores frames that do not match its subscriptions
+    }
+
+    fn apply_accept_all(&mut self) {}
+}
@@ -0,0 +1,34 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use canadensis_core::time::{Clock, Microseconds32};
+
+/// A clock that returns the simulated time
+///
+/// All clones of a clock share the same time. The time changes only when the
+/// [`Simulation`](crate::Simulation) steps.
+#[derive(Debug, Clone)]
+pub struct SimClock(Rc<Cell<Microseconds32>>);
+
+impl SimClock {
+    /// Returns the current simulated time
+    pub fn time(&self) -> Microseconds32 {
+        self.0.get()
+    }
+
+    pub(crate) fn set_time(&self, time: Microseconds32) {
+        self.0.set(time)
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        SimClock(Rc::new(Cell::new(Microseconds32::from_ticks(0))))
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&mut self) -> Microseconds32 {
+        self.0.get()
+    }
+}
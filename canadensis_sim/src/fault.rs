@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use canadensis_can::Frame;
+
+/// Random faults that a link applies to the frames, packets, or bytes that it receives
+///
+/// Each field is a probability from 0.0 (never) to 1.0 (always). The default has no faults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Faults {
+    /// The probability that an item is lost
+    pub loss: f64,
+    /// The probability that an item is delivered twice
+    pub duplication: f64,
+    /// The probability that an item is delivered after the next item (or at the end of the
+    /// next step, if nothing else arrives first)
+    pub reorder: f64,
+    /// The probability that one bit of an item is inverted
+    ///
+    /// A real CAN controller would detect most bit errors with the CRC and retransmit the
+    /// frame. The simulated bus delivers corrupted frames, so this tests the transport CRC
+    /// (for multi-frame transfers) and the deserialization code.
+    pub bit_error: f64,
+}
+
+/// A SplitMix64 pseudo-random number generator
+///
+/// This is not suitable for anything except simulations, but it is fast and its output is the
+/// same on every platform.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns true with the provided probability
+    ///
+    /// This does not use any random numbers if the probability is zero or less, so a link with
+    /// no faults does not change the faults on other links.
+    pub fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            false
+        } else {
+            // 53 random bits, the precision of an f64
+            let value = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+            value < probability
+        }
+    }
+
+    /// Returns a random number less than `limit`
+    ///
+    /// `limit` must not be zero.
+    pub fn below(&mut self, limit: usize) -> usize {
+        (self.next_u64() % limit as u64) as usize
+    }
+}
+
+/// Something that can have a bit error
+pub(crate) trait Corrupt {
+    /// Inverts one random bit
+    fn corrupt(&mut self, rng: &mut Rng);
+}
+
+impl Corrupt for u8 {
+    fn corrupt(&mut self, rng: &mut Rng) {
+        *self ^= 1 << rng.below(8);
+    }
+}
+
+impl Corrupt for Vec<u8> {
+    fn corrupt(&mut self, rng: &mut Rng) {
+        if !self.is_empty() {
+            let index = rng.below(self.len());
+            self[index].corrupt(rng);
+        }
+    }
+}
+
+impl Corrupt for Frame {
+    fn corrupt(&mut self, rng: &mut Rng) {
+        let mut data = self.data().to_vec();
+        data.corrupt(rng);
+        let mut corrupted = Frame::new(self.timestamp(), self.id(), &data);
+        corrupted.set_loopback(self.loopback());
+        *self = corrupted;
+    }
+}
+
+/// The faults on one link, and an item that was held back to reorder it
+#[derive(Debug)]
+pub(crate) struct LinkFaults<T> {
+    pub faults: Faults,
+    /// An item that will be delivered after the next item, and true if it was held back
+    /// before the current step
+    held: Option<(T, bool)>,
+}
+
+impl<T> Default for LinkFaults<T> {
+    fn default() -> Self {
+        LinkFaults {
+            faults: Faults::default(),
+            held: None,
+        }
+    }
+}
+
+impl<T> LinkFaults<T>
+where
+    T: Clone + Corrupt,
+{
+    /// Applies random faults to an item and adds the results to `out`
+    pub fn deliver(&mut self, mut item: T, rng: &mut Rng, out: &mut VecDeque<T>) {
+        if rng.chance(self.faults.loss) {
+            return;
+        }
+        if rng.chance(self.faults.bit_error) {
+            item.corrupt(rng);
+        }
+        let copies = if rng.chance(self.faults.duplication) {
+            2
+        } else {
+            1
+        };
+        if self.held.is_none() && rng.chance(self.faults.reorder) {
+            self.held = Some((item, false));
+            return;
+        }
+        for _ in 1..copies {
+            out.push_back(item.clone());
+        }
+        out.push_back(item);
+        if let Some((held, _)) = self.held.take() {
+            out.push_back(held);
+        }
+    }
+
+    /// Called at the end of each step
+    ///
+    /// This delivers an item that was held back during the previous step.
+    pub fn end_step(&mut self, out: &mut VecDeque<T>) {
+        match self.held.take() {
+            Some((held, true)) => out.push_back(held),
+            Some((held, false)) => self.held = Some((held, true)),
+            None => {}
+        }
+    }
+
+    /// Discards any item that was held back
+    pub fn clear(&mut self) {
+        self.held = None;
+    }
+}
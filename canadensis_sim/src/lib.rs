@@ -0,0 +1,176 @@
+//!
+//! # Simulated Cyphal networks
+//!
+//! This library connects many nodes through simulated networks, so that tests can check how
+//! nodes work together (for example, plug-and-play node ID allocation, failover, and timeouts)
+//! without any hardware.
+//!
+//! A [`Simulation`] has a virtual clock and any number of networks:
+//! * [`CanBus`](can::CanBus): a CAN bus with arbitration by CAN ID and a configurable bit rate
+//! * [`UdpNetwork`](udp::UdpNetwork): an IPv4 network with multicast groups and a configurable
+//!   latency
+//! * [`SerialLink`](serial::SerialLink): a point-to-point serial link with a configurable baud
+//!   rate
+//!
+//! Each node is attached to a network through a driver (or socket) that the network provides.
+//! The drivers implement the same traits as real drivers, so they work with the normal
+//! transmitters, receivers, and nodes.
+//!
+//! Each link between a node and a network can drop, duplicate, reorder, and corrupt what it
+//! receives ([`Faults`]), and a link can be disconnected (bus-off for CAN).
+//!
+//! ## Determinism
+//!
+//! Simulations do not depend on the real time or on any global state. Random faults come from a
+//! pseudo-random generator seeded from the seed passed to [`Simulation::new`]. Two simulations
+//! with the same seed and the same nodes that do the same things produce the same results.
+//!
+//! ## Stepping
+//!
+//! Time passes only when [`Simulation::step`] is called. Each step advances the clock and then
+//! delivers everything that the networks finished sending by the new time. Nodes should be
+//! polled between steps:
+//!
+//! ```ignore
+//! let mut simulation = Simulation::new(1);
+//! let bus = simulation.add_can_bus(CanBusConfig::default());
+//! // ... create nodes with bus.node(id), or with bus.attach() and simulation.clock()
+//! simulation.run_for(milliseconds(10_000), milliseconds(1), |_now| {
+//!     for node in &mut nodes {
+//!         node.receive(&mut handler).unwrap();
+//!     }
+//! });
+//! ```
+//!
+//! The time wraps around like any other `Microseconds32` clock, so a simulation should not run
+//! for more than about 35 minutes of simulated time.
+//!
+
+#![deny(missing_docs)]
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_core;
+extern crate canadensis_serial;
+extern crate canadensis_udp;
+extern crate nb;
+
+pub mod can;
+mod clock;
+mod fault;
+pub mod serial;
+pub mod udp;
+
+pub use crate::clock::SimClock;
+pub use crate::fault::Faults;
+
+use canadensis_core::time::{MicrosecondDuration32, Microseconds32};
+
+use crate::can::{CanBus, CanBusConfig};
+use crate::fault::Rng;
+use crate::serial::{SerialLink, SerialLinkConfig};
+use crate::udp::{UdpNetwork, UdpNetworkConfig};
+
+/// Identifies one link between a node and a network
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct LinkId(usize);
+
+/// The error returned when a node tries to send through a link that is disconnected
+/// (or, for CAN, in the bus-off state)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LinkDown;
+
+/// Something that delivers frames, packets, or bytes when time passes
+trait Network {
+    /// Delivers everything that finishes sending at or before `now`
+    fn run_until(&self, now: Microseconds32);
+}
+
+/// A virtual clock and a set of simulated networks
+pub struct Simulation {
+    clock: SimClock,
+    seed: u64,
+    networks: Vec<Box<dyn Network>>,
+}
+
+impl Simulation {
+    /// Creates a simulation with no networks and the time set to zero
+    ///
+    /// `seed` controls the random faults on all networks.
+    pub fn new(seed: u64) -> Self {
+        Simulation {
+            clock: SimClock::default(),
+            seed,
+            networks: Vec::new(),
+        }
+    }
+
+    /// Returns a clock that follows the simulated time
+    pub fn clock(&self) -> SimClock {
+        self.clock.clone()
+    }
+
+    /// Returns the current simulated time
+    pub fn now(&self) -> Microseconds32 {
+        self.clock.time()
+    }
+
+    /// Adds a CAN bus
+    pub fn add_can_bus(&mut self, config: CanBusConfig) -> CanBus {
+        let bus = CanBus::new(config, self.clock(), self.next_rng());
+        self.networks.push(Box::new(bus.clone()));
+        bus
+    }
+
+    /// Adds a UDP network
+    pub fn add_udp_network(&mut self, config: UdpNetworkConfig) -> UdpNetwork {
+        let network = UdpNetwork::new(config, self.clock(), self.next_rng());
+        self.networks.push(Box::new(network.clone()));
+        network
+    }
+
+    /// Adds a serial link between two ports
+    pub fn add_serial_link(&mut self, config: SerialLinkConfig) -> SerialLink {
+        let link = SerialLink::new(config, self.clock(), self.next_rng());
+        self.networks.push(Box::new(link.clone()));
+        link
+    }
+
+    /// Advances the time and delivers everything that the networks finish sending by the
+    /// new time
+    pub fn step(&mut self, duration: MicrosecondDuration32) {
+        let now = self.clock.time() + duration;
+        self.clock.set_time(now);
+        for network in &self.networks {
+            network.run_until(now);
+        }
+    }
+
+    /// Repeatedly calls `poll` and then advances the time by `step`, until `duration` has
+    /// passed
+    ///
+    /// `poll` gets the current time. It should poll all nodes, and may change faults or
+    /// disconnect links.
+    pub fn run_for<F>(
+        &mut self,
+        duration: MicrosecondDuration32,
+        step: MicrosecondDuration32,
+        mut poll: F,
+    ) where
+        F: FnMut(Microseconds32),
+    {
+        let end = self.now() + duration;
+        while self.now() < end {
+            poll(self.now());
+            self.step(step);
+        }
+    }
+
+    /// Creates a random number generator for a new network
+    ///
+    /// Each network has its own generator, so the faults on one network do not depend on how
+    /// much traffic the other networks have.
+    fn next_rng(&self) -> Rng {
+        Rng::new(self.seed ^ (self.networks.len() as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+}
@@ -0,0 +1,230 @@
+//!
+//! A simulated point-to-point serial link
+//!
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use canadensis::node::CoreNode;
+use canadensis::requester::TransferIdFixedMap;
+use canadensis_core::subscription::DynamicSubscriptionManager;
+use canadensis_core::time::{MicrosecondDuration32, Microseconds32};
+use canadensis_serial::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_serial::{
+    SerialNodeId, SerialReceiver, SerialTransmitter, SerialTransport, Subscription,
+};
+
+use crate::fault::{LinkFaults, Rng};
+use crate::{Faults, LinkDown, LinkId, Network, SimClock};
+
+/// The settings for a simulated serial link
+#[derive(Debug, Clone)]
+pub struct SerialLinkConfig {
+    /// The baud rate, in bits per second
+    ///
+    /// Each byte takes 10 bits (with one start bit and one stop bit), rounded up to a whole
+    /// number of microseconds.
+    ///
+    /// Default: 115200
+    pub baud_rate: u32,
+    /// The number of bytes that each port can hold while waiting to send them
+    ///
+    /// Default: 1024
+    pub tx_buffer_capacity: usize,
+}
+
+impl Default for SerialLinkConfig {
+    fn default() -> Self {
+        SerialLinkConfig {
+            baud_rate: 115200,
+            tx_buffer_capacity: 1024,
+        }
+    }
+}
+
+/// A node on a simulated serial link, with room for 8 publishers and 8 requesters
+pub type SimSerialNode = CoreNode<
+    SimClock,
+    SerialTransmitter<SimSerialPort, 256>,
+    SerialReceiver<SimClock, SimSerialPort, DynamicSubscriptionManager<Subscription>>,
+    TransferIdFixedMap<SerialTransport, 8>,
+    SimSerialPort,
+    8,
+    8,
+>;
+
+/// A simulated serial link between two ports
+///
+/// The two directions are independent, so both ports can send at the same time.
+///
+/// Clones of a link refer to the same link.
+#[derive(Clone)]
+pub struct SerialLink(Rc<RefCell<LinkState>>);
+
+struct LinkState {
+    config: SerialLinkConfig,
+    clock: SimClock,
+    rng: Rng,
+    ends: [End; 2],
+    /// The time of the previous step
+    last_run: Microseconds32,
+}
+
+#[derive(Default)]
+struct End {
+    tx: VecDeque<u8>,
+    rx: VecDeque<u8>,
+    faults: LinkFaults<u8>,
+    /// The time when the previous byte sent from this end was completely sent, or when the
+    /// next byte started sending
+    busy_until: Option<Microseconds32>,
+    /// True if some bytes were not sent by the end of the previous step
+    backlogged: bool,
+    disconnected: bool,
+}
+
+impl SerialLink {
+    pub(crate) fn new(config: SerialLinkConfig, clock: SimClock, rng: Rng) -> Self {
+        SerialLink(Rc::new(RefCell::new(LinkState {
+            config,
+            clock,
+            rng,
+            ends: [End::default(), End::default()],
+            last_run: Microseconds32::from_ticks(0),
+        })))
+    }
+
+    /// Returns the ports at the two ends of this link
+    ///
+    /// The link IDs of the ports are `LinkId` values that can be used with
+    /// [`set_faults`](SerialLink::set_faults) and [`set_disconnected`](SerialLink::set_disconnected).
+    pub fn ports(&self) -> (SimSerialPort, SimSerialPort) {
+        (
+            SimSerialPort {
+                link: self.0.clone(),
+                end: 0,
+            },
+            SimSerialPort {
+                link: self.0.clone(),
+                end: 1,
+            },
+        )
+    }
+
+    /// Sets the faults that a port applies to the bytes it receives
+    pub fn set_faults(&self, port: LinkId, faults: Faults) {
+        self.0.borrow_mut().ends[port.0].faults.faults = faults;
+    }
+
+    /// Disconnects a port from the link or reconnects it
+    ///
+    /// When a port is disconnected, it discards all bytes that it was going to send and has
+    /// not yet received. While it is disconnected, it does not receive anything and it returns
+    /// [`LinkDown`] errors when sending.
+    pub fn set_disconnected(&self, port: LinkId, disconnected: bool) {
+        let mut state = self.0.borrow_mut();
+        let end = &mut state.ends[port.0];
+        end.disconnected = disconnected;
+        if disconnected {
+            end.tx.clear();
+            end.rx.clear();
+            end.faults.clear();
+        }
+    }
+}
+
+impl Network for SerialLink {
+    fn run_until(&self, now: Microseconds32) {
+        let mut state = self.0.borrow_mut();
+        let state = &mut *state;
+        let byte_micros = 10_000_000_u32.div_ceil(state.config.baud_rate);
+        let byte_time = MicrosecondDuration32::from_ticks(byte_micros.max(1));
+        for sender_index in 0..2 {
+            let (first, second) = state.ends.split_at_mut(1);
+            let (sender, receiver) = if sender_index == 0 {
+                (&mut first[0], &mut second[0])
+            } else {
+                (&mut second[0], &mut first[0])
+            };
+            // Bytes that were sent during the previous step are ready to send at the beginning
+            // of the step, or when the bytes before them have been sent
+            let mut time = match sender.busy_until {
+                Some(busy_until) if sender.backlogged || busy_until > state.last_run => busy_until,
+                _ => state.last_run,
+            };
+            while !sender.tx.is_empty() && time + byte_time <= now {
+                time += byte_time;
+                let byte = sender.tx.pop_front().unwrap();
+                if !receiver.disconnected {
+                    receiver
+                        .faults
+                        .deliver(byte, &mut state.rng, &mut receiver.rx);
+                }
+            }
+            sender.busy_until = Some(time);
+            sender.backlogged = !sender.tx.is_empty();
+        }
+        state.last_run = now;
+        for end in &mut state.ends {
+            end.faults.end_step(&mut end.rx);
+        }
+    }
+}
+
+/// One end of a simulated serial link
+pub struct SimSerialPort {
+    link: Rc<RefCell<LinkState>>,
+    end: usize,
+}
+
+impl SimSerialPort {
+    /// Returns the identifier of this end of the link
+    pub fn link(&self) -> LinkId {
+        LinkId(self.end)
+    }
+
+    /// Creates a node with the provided ID that sends and receives through this port
+    ///
+    /// The node uses the simulation clock.
+    pub fn into_node(self, id: SerialNodeId) -> SimSerialNode {
+        let clock = self.link.borrow().clock.clone();
+        CoreNode::new(
+            clock,
+            id,
+            SerialTransmitter::new(),
+            SerialReceiver::new(id),
+            self,
+        )
+    }
+}
+
+impl TransmitDriver for SimSerialPort {
+    type Error = LinkDown;
+
+    fn send_byte(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        let mut state = self.link.borrow_mut();
+        let capacity = state.config.tx_buffer_capacity;
+        let end = &mut state.ends[self.end];
+        if end.disconnected {
+            return Err(nb::Error::Other(LinkDown));
+        }
+        if end.tx.len() >= capacity {
+            return Err(nb::Error::WouldBlock);
+        }
+        end.tx.push_back(byte);
+        Ok(())
+    }
+}
+
+impl ReceiveDriver for SimSerialPort {
+    type Error = Infallible;
+
+    fn receive_byte(&mut self) -> nb::Result<u8, Self::Error> {
+        self.link.borrow_mut().ends[self.end]
+            .rx
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+}
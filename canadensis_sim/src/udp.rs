@@ -0,0 +1,260 @@
+//!
+//! A simulated IPv4 network for Cyphal/UDP
+//!
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, VecDeque};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::rc::Rc;
+
+use canadensis::node::CoreNode;
+use canadensis::requester::TransferIdFixedMap;
+use canadensis_core::session::SessionDynamicMap;
+use canadensis_core::time::{MicrosecondDuration32, Microseconds32};
+use canadensis_udp::driver::UdpSocket;
+use canadensis_udp::{
+    UdpNodeId, UdpReceiver, UdpSessionData, UdpTransferId, UdpTransmitter, UdpTransport,
+    DEFAULT_PORT,
+};
+
+use crate::fault::{LinkFaults, Rng};
+use crate::{Faults, LinkDown, LinkId, Network, SimClock};
+
+/// The settings for a simulated UDP network
+#[derive(Debug, Clone)]
+pub struct UdpNetworkConfig {
+    /// The time between sending a packet and receiving it
+    ///
+    /// Default: 100 microseconds
+    pub latency: MicrosecondDuration32,
+}
+
+impl Default for UdpNetworkConfig {
+    fn default() -> Self {
+        UdpNetworkConfig {
+            latency: MicrosecondDuration32::from_ticks(100),
+        }
+    }
+}
+
+/// The MTU of the nodes that [`UdpNetwork::node`] creates
+pub const SIM_UDP_MTU: usize = 1200;
+
+/// A node on a simulated UDP network, with room for 8 publishers and 8 requesters
+pub type SimUdpNode = CoreNode<
+    SimClock,
+    UdpTransmitter<SimUdpSocket, SIM_UDP_MTU>,
+    UdpReceiver<
+        SimClock,
+        SessionDynamicMap<UdpNodeId, UdpTransferId, UdpSessionData>,
+        SimUdpSocket,
+        SIM_UDP_MTU,
+    >,
+    TransferIdFixedMap<UdpTransport, 8>,
+    SimUdpSocket,
+    8,
+    8,
+>;
+
+/// A simulated IPv4 network
+///
+/// A packet sent to a multicast address is received by all other sockets bound to the
+/// destination port that have joined the multicast group. A packet sent to any other address
+/// is received by all other sockets bound to that address and port. Sockets do not receive
+/// packets that they send.
+///
+/// Clones of a network refer to the same network.
+#[derive(Clone)]
+pub struct UdpNetwork(Rc<RefCell<NetworkState>>);
+
+struct NetworkState {
+    config: UdpNetworkConfig,
+    clock: SimClock,
+    rng: Rng,
+    sockets: Vec<SocketState>,
+    /// Packets that have been sent and not yet received, in the order they will arrive
+    in_flight: VecDeque<InFlight>,
+}
+
+struct SocketState {
+    address: SocketAddrV4,
+    groups: BTreeSet<Ipv4Addr>,
+    rx: VecDeque<Vec<u8>>,
+    faults: LinkFaults<Vec<u8>>,
+    down: bool,
+}
+
+struct InFlight {
+    arrival: Microseconds32,
+    sender: usize,
+    destination: SocketAddrV4,
+    data: Vec<u8>,
+}
+
+impl UdpNetwork {
+    pub(crate) fn new(config: UdpNetworkConfig, clock: SimClock, rng: Rng) -> Self {
+        UdpNetwork(Rc::new(RefCell::new(NetworkState {
+            config,
+            clock,
+            rng,
+            sockets: Vec::new(),
+            in_flight: VecDeque::new(),
+        })))
+    }
+
+    /// Creates a socket bound to an address and port on this network
+    ///
+    /// For Cyphal/UDP, the port is usually `canadensis_udp::DEFAULT_PORT`. Each node should
+    /// have its own address.
+    pub fn attach(&self, address: Ipv4Addr, port: u16) -> SimUdpSocket {
+        let mut state = self.0.borrow_mut();
+        state.sockets.push(SocketState {
+            address: SocketAddrV4::new(address, port),
+            groups: BTreeSet::new(),
+            rx: VecDeque::new(),
+            faults: LinkFaults::default(),
+            down: false,
+        });
+        SimUdpSocket {
+            network: self.0.clone(),
+            link: LinkId(state.sockets.len() - 1),
+        }
+    }
+
+    /// Connects a new node with the provided ID and IP address to this network
+    ///
+    /// The node uses the simulation clock and the default Cyphal/UDP port.
+    pub fn node(&self, id: UdpNodeId, address: Ipv4Addr) -> SimUdpNode {
+        let clock = self.0.borrow().clock.clone();
+        CoreNode::new(
+            clock,
+            id,
+            UdpTransmitter::new(DEFAULT_PORT),
+            UdpReceiver::new(Some(id), address),
+            self.attach(address, DEFAULT_PORT),
+        )
+    }
+
+    /// Sets the faults that a link applies to the packets it receives
+    pub fn set_faults(&self, link: LinkId, faults: Faults) {
+        self.0.borrow_mut().sockets[link.0].faults.faults = faults;
+    }
+
+    /// Disconnects a link from the network or reconnects it
+    ///
+    /// When a link is disconnected, it discards all packets that it has not yet received.
+    /// While it is disconnected, it does not receive anything and its socket returns
+    /// [`LinkDown`] errors when sending.
+    pub fn set_link_down(&self, link: LinkId, down: bool) {
+        let mut state = self.0.borrow_mut();
+        let socket = &mut state.sockets[link.0];
+        socket.down = down;
+        if down {
+            socket.rx.clear();
+            socket.faults.clear();
+        }
+    }
+}
+
+impl Network for UdpNetwork {
+    fn run_until(&self, now: Microseconds32) {
+        let mut state = self.0.borrow_mut();
+        let state = &mut *state;
+        while state
+            .in_flight
+            .front()
+            .map(|packet| packet.arrival <= now)
+            .unwrap_or(false)
+        {
+            let packet = state.in_flight.pop_front().unwrap();
+            let multicast = packet.destination.ip().is_multicast();
+            let rng = &mut state.rng;
+            for (index, socket) in state.sockets.iter_mut().enumerate() {
+                let matches = socket.address.port() == packet.destination.port()
+                    && if multicast {
+                        socket.groups.contains(packet.destination.ip())
+                    } else {
+                        socket.address.ip() == packet.destination.ip()
+                    };
+                if index != packet.sender && !socket.down && matches {
+                    socket
+                        .faults
+                        .deliver(packet.data.clone(), rng, &mut socket.rx);
+                }
+            }
+        }
+        for socket in &mut state.sockets {
+            socket.faults.end_step(&mut socket.rx);
+        }
+    }
+}
+
+/// A socket that connects a node to a simulated UDP network
+pub struct SimUdpSocket {
+    network: Rc<RefCell<NetworkState>>,
+    link: LinkId,
+}
+
+impl SimUdpSocket {
+    /// Returns the identifier of the link between this socket and the network
+    pub fn link(&self) -> LinkId {
+        self.link
+    }
+}
+
+impl UdpSocket for SimUdpSocket {
+    type Error = LinkDown;
+
+    fn local_addr(&self) -> Result<SocketAddrV4, Self::Error> {
+        Ok(self.network.borrow().sockets[self.link.0].address)
+    }
+
+    fn join_multicast_v4(
+        &mut self,
+        multiaddr: &Ipv4Addr,
+        _interface: &Ipv4Addr,
+    ) -> Result<(), Self::Error> {
+        self.network.borrow_mut().sockets[self.link.0]
+            .groups
+            .insert(*multiaddr);
+        Ok(())
+    }
+
+    fn leave_multicast_v4(
+        &mut self,
+        multiaddr: &Ipv4Addr,
+        _interface: &Ipv4Addr,
+    ) -> Result<(), Self::Error> {
+        self.network.borrow_mut().sockets[self.link.0]
+            .groups
+            .remove(multiaddr);
+        Ok(())
+    }
+
+    fn send_to(&mut self, data: &[u8], destination: SocketAddrV4) -> Result<usize, Self::Error> {
+        let mut state = self.network.borrow_mut();
+        if state.sockets[self.link.0].down {
+            return Err(LinkDown);
+        }
+        let arrival = state.clock.time() + state.config.latency;
+        state.in_flight.push_back(InFlight {
+            arrival,
+            sender: self.link.0,
+            destination,
+            data: data.to_vec(),
+        });
+        Ok(data.len())
+    }
+
+    fn recv(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+        let mut state = self.network.borrow_mut();
+        let data = state.sockets[self.link.0]
+            .rx
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)?;
+        // Like a real socket, discard any bytes that do not fit into the buffer
+        let length = data.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&data[..length]);
+        Ok(length)
+    }
+}
@@ -0,0 +1,254 @@
+//!
+//! Tests of the simulated CAN bus
+//!
+
+extern crate canadensis;
+extern crate canadensis_can;
+extern crate canadensis_core;
+extern crate canadensis_data_types;
+extern crate canadensis_sim;
+
+use std::convert::TryFrom;
+
+use canadensis::core::transport::Transport;
+use canadensis::{Node, TransferHandler};
+use canadensis_can::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_can::{CanId, CanNodeId, CanTransport, Frame};
+use canadensis_core::time::{milliseconds, MicrosecondDuration32, Microseconds32};
+use canadensis_core::transfer::MessageTransfer;
+use canadensis_core::{nb, Priority};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use canadensis_sim::can::{CanBus, CanBusConfig, SimCanDriver, SimCanNode};
+use canadensis_sim::{Faults, LinkDown, SimClock, Simulation};
+
+fn make_node(bus: &CanBus, id: u8) -> SimCanNode {
+    let mut node = bus.node(CanNodeId::try_from(id).unwrap());
+    node.start_publishing(
+        heartbeat_1_0::SUBJECT,
+        milliseconds(1000),
+        Priority::Nominal,
+    )
+    .unwrap();
+    node.subscribe_message(heartbeat_1_0::SUBJECT, 7, milliseconds(1000))
+        .unwrap();
+    node
+}
+
+/// Records the sources of all received messages
+struct SourceCollector<T: Transport> {
+    sources: Vec<Option<T::NodeId>>,
+}
+
+impl<T: Transport> TransferHandler<T> for SourceCollector<T> {
+    fn handle_message<N: Node<Transport = T>>(
+        &mut self,
+        _node: &mut N,
+        transfer: &MessageTransfer<Vec<u8>, T>,
+    ) -> bool {
+        self.sources.push(transfer.header.source.clone());
+        true
+    }
+}
+
+/// Runs three nodes that each publish a heartbeat every 100 milliseconds for one second, and
+/// returns the sources of the heartbeats that each node received
+fn run_heartbeats(simulation: &mut Simulation, bus: &CanBus) -> Vec<Vec<Option<CanNodeId>>> {
+    let mut nodes: Vec<SimCanNode> = (1..=3).map(|id| make_node(bus, id)).collect();
+    let mut collectors: Vec<SourceCollector<CanTransport>> = (0..3)
+        .map(|_| SourceCollector {
+            sources: Vec::new(),
+        })
+        .collect();
+    simulation.run_for(milliseconds(1000), milliseconds(1), |now| {
+        for (node, collector) in nodes.iter_mut().zip(collectors.iter_mut()) {
+            node.receive(collector).unwrap();
+            if now.ticks() % 100_000 == 0 {
+                let heartbeat = Heartbeat {
+                    uptime: now.ticks() / 1_000_000,
+                    health: Health { value: 0 },
+                    mode: Mode { value: 0 },
+                    vendor_specific_status_code: 0,
+                };
+                node.publish(heartbeat_1_0::SUBJECT, &heartbeat).unwrap();
+            }
+            node.flush().unwrap();
+        }
+    });
+    collectors
+        .into_iter()
+        .map(|collector| collector.sources)
+        .collect()
+}
+
+#[test]
+fn heartbeats_between_nodes() {
+    let mut simulation = Simulation::new(1);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let received = run_heartbeats(&mut simulation, &bus);
+    for (i, sources) in received.iter().enumerate() {
+        let own_id = CanNodeId::try_from(i as u8 + 1).unwrap();
+        assert_eq!(20, sources.len());
+        assert!(sources.iter().all(|source| *source != Some(own_id)));
+    }
+    // Lower node IDs win arbitration when nodes publish at the same time
+    assert_eq!(
+        vec![
+            Some(CanNodeId::try_from(2u8).unwrap()),
+            Some(CanNodeId::try_from(3u8).unwrap())
+        ],
+        received[0][..2]
+    );
+}
+
+#[test]
+fn lossy_link() {
+    let mut simulation = Simulation::new(1);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let mut sender = bus.attach();
+    let mut lossy = bus.attach();
+    let mut reliable = bus.attach();
+    bus.set_faults(
+        lossy.link(),
+        Faults {
+            loss: 1.0,
+            ..Faults::default()
+        },
+    );
+    let mut clock = simulation.clock();
+    for i in 0..10u8 {
+        sender
+            .transmit(frame(0x100, &[i], 10_000), &mut clock)
+            .unwrap();
+    }
+    simulation.step(milliseconds(2));
+    assert_eq!(10, receive_all(&mut reliable, &mut clock).len());
+    assert!(receive_all(&mut lossy, &mut clock).is_empty());
+}
+
+#[test]
+fn arbitration_and_timing() {
+    let mut simulation = Simulation::new(1);
+    let bus = simulation.add_can_bus(CanBusConfig {
+        bit_rate: 125_000,
+        ..CanBusConfig::default()
+    });
+    let mut first = bus.attach();
+    let mut second = bus.attach();
+    let mut receiver = bus.attach();
+    let mut clock = simulation.clock();
+    first
+        .transmit(frame(0x300, &[0; 8], 100_000), &mut clock)
+        .unwrap();
+    first
+        .transmit(frame(0x100, &[1; 8], 100_000), &mut clock)
+        .unwrap();
+    second
+        .transmit(frame(0x200, &[2; 8], 100_000), &mut clock)
+        .unwrap();
+    // This deadline passes before the bus is free
+    second
+        .transmit(frame(0x400, &[3; 8], 1_000), &mut clock)
+        .unwrap();
+
+    // An 8-byte frame takes 1256 microseconds at 125 kbit/s
+    simulation.step(milliseconds(1));
+    assert!(receive_all(&mut receiver, &mut clock).is_empty());
+    simulation.step(milliseconds(9));
+    let received = receive_all(&mut receiver, &mut clock);
+    let ids: Vec<u32> = received.iter().map(|frame| u32::from(frame.id())).collect();
+    assert_eq!(vec![0x100, 0x200, 0x300], ids);
+    let timestamps: Vec<u32> = received
+        .iter()
+        .map(|frame| frame.timestamp().ticks())
+        .collect();
+    assert_eq!(vec![1256, 2512, 3768], timestamps);
+}
+
+#[test]
+fn bus_off() {
+    let mut simulation = Simulation::new(1);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let mut sender = bus.attach();
+    let mut receiver = bus.attach();
+    let mut clock = simulation.clock();
+    sender
+        .transmit(frame(0x100, &[0], 10_000), &mut clock)
+        .unwrap();
+    bus.set_bus_off(sender.link(), true);
+    assert_eq!(
+        Err(nb::Error::Other(LinkDown)),
+        sender.transmit(frame(0x100, &[1], 10_000), &mut clock)
+    );
+    simulation.step(milliseconds(1));
+    assert!(receive_all(&mut receiver, &mut clock).is_empty());
+
+    bus.set_bus_off(sender.link(), false);
+    sender
+        .transmit(frame(0x100, &[2], 10_000), &mut clock)
+        .unwrap();
+    simulation.step(milliseconds(1));
+    let received = receive_all(&mut receiver, &mut clock);
+    assert_eq!(1, received.len());
+    assert_eq!(&[2], received[0].data());
+}
+
+/// Sends frames through a bus with all kinds of faults and returns the received frames
+fn faulty_run(seed: u64) -> Vec<Frame> {
+    let mut simulation = Simulation::new(seed);
+    let bus = simulation.add_can_bus(CanBusConfig::default());
+    let mut sender = bus.attach();
+    let mut receiver = bus.attach();
+    bus.set_faults(
+        receiver.link(),
+        Faults {
+            loss: 0.2,
+            duplication: 0.2,
+            reorder: 0.2,
+            bit_error: 0.2,
+        },
+    );
+    let mut clock = simulation.clock();
+    let mut received = Vec::new();
+    for i in 0..200u8 {
+        let deadline = simulation.now().ticks() + 10_000;
+        sender
+            .transmit(frame(0x100, &[i, 0, 0, 0], deadline), &mut clock)
+            .unwrap();
+        simulation.step(MicrosecondDuration32::from_ticks(200));
+        received.extend(receive_all(&mut receiver, &mut clock));
+    }
+    received
+}
+
+#[test]
+fn deterministic_faults() {
+    let run1 = faulty_run(37);
+    let run2 = faulty_run(37);
+    let other_seed = faulty_run(38);
+    assert_eq!(run1, run2);
+    assert_ne!(run1, other_seed);
+
+    // Some frames should be lost, duplicated, reordered, or corrupted
+    let expected: Vec<Vec<u8>> = (0..200u8).map(|i| vec![i, 0, 0, 0]).collect();
+    let actual: Vec<Vec<u8>> = run1.iter().map(|frame| frame.data().to_vec()).collect();
+    assert_ne!(expected, actual);
+    assert!(actual.len() > 100);
+}
+
+fn frame(id: u32, data: &[u8], deadline: u32) -> Frame {
+    Frame::new(
+        Microseconds32::from_ticks(deadline),
+        CanId::try_from(id).unwrap(),
+        data,
+    )
+}
+
+fn receive_all(driver: &mut SimCanDriver, clock: &mut SimClock) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    while let Ok(frame) = driver.receive(clock) {
+        frames.push(frame);
+    }
+    frames
+}
@@ -0,0 +1,126 @@
+//!
+//! Tests of the simulated serial link
+//!
+
+extern crate canadensis;
+extern crate canadensis_core;
+extern crate canadensis_data_types;
+extern crate canadensis_serial;
+extern crate canadensis_sim;
+
+use std::convert::TryFrom;
+
+use canadensis::{Node, TransferHandler};
+use canadensis_core::time::{milliseconds, MicrosecondDuration32};
+use canadensis_core::transfer::MessageTransfer;
+use canadensis_core::{nb, Priority};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use canadensis_serial::driver::{ReceiveDriver, TransmitDriver};
+use canadensis_serial::{SerialNodeId, SerialTransport};
+use canadensis_sim::serial::{SerialLinkConfig, SimSerialNode, SimSerialPort};
+use canadensis_sim::{Faults, LinkDown, Simulation};
+
+fn make_node(port: SimSerialPort, id: u16) -> SimSerialNode {
+    let mut node = port.into_node(SerialNodeId::try_from(id).unwrap());
+    node.start_publishing(
+        heartbeat_1_0::SUBJECT,
+        milliseconds(1000),
+        Priority::Nominal,
+    )
+    .unwrap();
+    node.subscribe_message(heartbeat_1_0::SUBJECT, 7, milliseconds(1000))
+        .unwrap();
+    node
+}
+
+/// Records the uptimes of all received heartbeats
+#[derive(Default)]
+struct UptimeCollector {
+    uptimes: Vec<u32>,
+}
+
+impl TransferHandler<SerialTransport> for UptimeCollector {
+    fn handle_message<N: Node<Transport = SerialTransport>>(
+        &mut self,
+        _node: &mut N,
+        transfer: &MessageTransfer<Vec<u8>, SerialTransport>,
+    ) -> bool {
+        let uptime = u32::from_le_bytes([
+            transfer.payload[0],
+            transfer.payload[1],
+            transfer.payload[2],
+            transfer.payload[3],
+        ]);
+        self.uptimes.push(uptime);
+        true
+    }
+}
+
+#[test]
+fn heartbeats_over_link() {
+    let mut simulation = Simulation::new(1);
+    let link = simulation.add_serial_link(SerialLinkConfig::default());
+    let (port_a, port_b) = link.ports();
+    let mut node_a = make_node(port_a, 1);
+    let mut node_b = make_node(port_b, 2);
+    let mut collector_a = UptimeCollector::default();
+    let mut collector_b = UptimeCollector::default();
+
+    simulation.run_for(milliseconds(1000), milliseconds(1), |now| {
+        node_a.receive(&mut collector_a).unwrap();
+        node_b.receive(&mut collector_b).unwrap();
+        if now.ticks() % 100_000 == 0 {
+            let uptime = now.ticks() / 100_000;
+            for node in [&mut node_a, &mut node_b].iter_mut() {
+                let heartbeat = Heartbeat {
+                    uptime,
+                    health: Health { value: 0 },
+                    mode: Mode { value: 0 },
+                    vendor_specific_status_code: 0,
+                };
+                node.publish(heartbeat_1_0::SUBJECT, &heartbeat).unwrap();
+            }
+        }
+        for node in [&mut node_a, &mut node_b].iter_mut() {
+            match node.flush() {
+                Ok(()) | Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(e)) => panic!("{:?}", e),
+            }
+        }
+    });
+    let expected: Vec<u32> = (0..10).collect();
+    assert_eq!(expected, collector_a.uptimes);
+    assert_eq!(expected, collector_b.uptimes);
+}
+
+#[test]
+fn byte_timing_and_faults() {
+    let mut simulation = Simulation::new(1);
+    let link = simulation.add_serial_link(SerialLinkConfig {
+        baud_rate: 100_000,
+        ..SerialLinkConfig::default()
+    });
+    let (mut port_a, mut port_b) = link.ports();
+    link.set_faults(
+        port_b.link(),
+        Faults {
+            bit_error: 1.0,
+            ..Faults::default()
+        },
+    );
+    port_a.send_byte(0x00).unwrap();
+    port_a.send_byte(0x00).unwrap();
+
+    // One byte takes 100 microseconds
+    simulation.step(MicrosecondDuration32::from_ticks(150));
+    let byte = port_b.receive_byte().unwrap();
+    assert_eq!(1, byte.count_ones());
+    assert!(port_b.receive_byte().is_err());
+    simulation.step(MicrosecondDuration32::from_ticks(50));
+    assert_eq!(1, port_b.receive_byte().unwrap().count_ones());
+
+    link.set_disconnected(port_a.link(), true);
+    assert_eq!(Err(nb::Error::Other(LinkDown)), port_a.send_byte(0));
+}
@@ -0,0 +1,139 @@
+//!
+//! Tests of the simulated UDP network
+//!
+
+extern crate canadensis;
+extern crate canadensis_core;
+extern crate canadensis_data_types;
+extern crate canadensis_sim;
+extern crate canadensis_udp;
+
+use std::convert::TryFrom;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use canadensis::{Node, TransferHandler};
+use canadensis_core::time::{milliseconds, MicrosecondDuration32};
+use canadensis_core::transfer::MessageTransfer;
+use canadensis_core::Priority;
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use canadensis_sim::udp::{SimUdpNode, UdpNetwork, UdpNetworkConfig};
+use canadensis_sim::{LinkDown, Simulation};
+use canadensis_udp::driver::UdpSocket;
+use canadensis_udp::{UdpNodeId, UdpTransport, DEFAULT_PORT};
+
+fn make_node(network: &UdpNetwork, id: u16) -> SimUdpNode {
+    let address = Ipv4Addr::new(10, 0, 0, id as u8);
+    let mut node = network.node(UdpNodeId::try_from(id).unwrap(), address);
+    node.start_publishing(
+        heartbeat_1_0::SUBJECT,
+        milliseconds(1000),
+        Priority::Nominal,
+    )
+    .unwrap();
+    node.subscribe_message(heartbeat_1_0::SUBJECT, 7, milliseconds(1000))
+        .unwrap();
+    node
+}
+
+/// Records the sources of all received messages
+#[derive(Default)]
+struct SourceCollector {
+    sources: Vec<Option<UdpNodeId>>,
+}
+
+impl TransferHandler<UdpTransport> for SourceCollector {
+    fn handle_message<N: Node<Transport = UdpTransport>>(
+        &mut self,
+        _node: &mut N,
+        transfer: &MessageTransfer<Vec<u8>, UdpTransport>,
+    ) -> bool {
+        self.sources.push(transfer.header.source);
+        true
+    }
+}
+
+#[test]
+fn heartbeats_between_nodes() {
+    let mut simulation = Simulation::new(1);
+    let network = simulation.add_udp_network(UdpNetworkConfig::default());
+    let mut publisher = make_node(&network, 1);
+    let mut subscriber = make_node(&network, 2);
+    let mut collector = SourceCollector::default();
+    let mut publisher_collector = SourceCollector::default();
+
+    simulation.run_for(milliseconds(1000), milliseconds(1), |now| {
+        if now.ticks() % 100_000 == 0 {
+            let heartbeat = Heartbeat {
+                uptime: now.ticks() / 1_000_000,
+                health: Health { value: 0 },
+                mode: Mode { value: 0 },
+                vendor_specific_status_code: 0,
+            };
+            publisher
+                .publish(heartbeat_1_0::SUBJECT, &heartbeat)
+                .unwrap();
+        }
+        publisher.receive(&mut publisher_collector).unwrap();
+        subscriber.receive(&mut collector).unwrap();
+    });
+    assert_eq!(
+        vec![Some(UdpNodeId::try_from(1).unwrap()); 10],
+        collector.sources
+    );
+    // A socket does not receive its own packets
+    assert!(publisher_collector.sources.is_empty());
+}
+
+#[test]
+fn multicast_latency_and_link_down() {
+    let mut simulation = Simulation::new(1);
+    let network = simulation.add_udp_network(UdpNetworkConfig {
+        latency: MicrosecondDuration32::from_ticks(500),
+    });
+    let group = Ipv4Addr::new(239, 0, 0, 10);
+    let mut sender = network.attach(Ipv4Addr::new(10, 0, 0, 1), DEFAULT_PORT);
+    let mut member = network.attach(Ipv4Addr::new(10, 0, 0, 2), DEFAULT_PORT);
+    let mut other_port = network.attach(Ipv4Addr::new(10, 0, 0, 3), 1234);
+    let mut non_member = network.attach(Ipv4Addr::new(10, 0, 0, 4), DEFAULT_PORT);
+    member
+        .join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+        .unwrap();
+    other_port
+        .join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+        .unwrap();
+
+    let destination = SocketAddrV4::new(group, DEFAULT_PORT);
+    sender.send_to(&[1, 2, 3], destination).unwrap();
+    // Unicast to the non-member
+    sender
+        .send_to(
+            &[4],
+            SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 4), DEFAULT_PORT),
+        )
+        .unwrap();
+
+    let mut buffer = [0u8; 2];
+    simulation.step(MicrosecondDuration32::from_ticks(499));
+    assert!(member.recv(&mut buffer).is_err());
+    simulation.step(MicrosecondDuration32::from_ticks(1));
+    // The packet is truncated to fit into the buffer
+    assert_eq!(Ok(2), member.recv(&mut buffer));
+    assert_eq!([1, 2], buffer);
+    assert!(member.recv(&mut buffer).is_err());
+    assert!(other_port.recv(&mut buffer).is_err());
+    assert_eq!(Ok(1), non_member.recv(&mut buffer));
+    assert_eq!(4, buffer[0]);
+    assert!(non_member.recv(&mut buffer).is_err());
+
+    network.set_link_down(sender.link(), true);
+    assert_eq!(Err(LinkDown), sender.send_to(&[5], destination));
+    network.set_link_down(sender.link(), false);
+    member
+        .leave_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+        .unwrap();
+    sender.send_to(&[6], destination).unwrap();
+    simulation.step(milliseconds(1));
+    assert!(member.recv(&mut buffer).is_err());
+}
@@ -0,0 +1,377 @@
+//!
+//! Decoding of payloads with data types that have fixed port IDs
+//!
+
+use std::fmt::Write;
+
+use canadensis_core::{ServiceId, SubjectId};
+use canadensis_data_types::uavcan::diagnostic::record_1_1::{self, Record};
+use canadensis_data_types::uavcan::diagnostic::severity_1_0::Severity;
+use canadensis_data_types::uavcan::node::execute_command_1_3::{
+    self, ExecuteCommandRequest, ExecuteCommandResponse,
+};
+use canadensis_data_types::uavcan::node::get_info_1_0::{self, GetInfoRequest, GetInfoResponse};
+use canadensis_data_types::uavcan::node::get_transport_statistics_0_1::{
+    self, GetTransportStatisticsRequest, GetTransportStatisticsResponse,
+};
+use canadensis_data_types::uavcan::node::health_1_0::Health;
+use canadensis_data_types::uavcan::node::heartbeat_1_0::{self, Heartbeat};
+use canadensis_data_types::uavcan::node::io_statistics_0_1::IOStatistics;
+use canadensis_data_types::uavcan::node::mode_1_0::Mode;
+use canadensis_data_types::uavcan::node::port::list_1_0::{self as port_list_1_0, List};
+use canadensis_data_types::uavcan::node::port::service_id_list_1_0::ServiceIDList;
+use canadensis_data_types::uavcan::node::port::subject_id_list_1_0::SubjectIDList;
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_1_0::{
+    self, NodeIDAllocationData as NodeIDAllocationData1,
+};
+use canadensis_data_types::uavcan::pnp::node_id_allocation_data_2_0::{
+    self, NodeIDAllocationData as NodeIDAllocationData2,
+};
+use canadensis_data_types::uavcan::register::access_1_0::{self, AccessRequest, AccessResponse};
+use canadensis_data_types::uavcan::register::list_1_0::{
+    self as register_list_1_0, ListRequest, ListResponse,
+};
+use canadensis_data_types::uavcan::time::synchronization_1_0::{self, Synchronization};
+use canadensis_encoding::{Deserialize, DeserializeError};
+
+/// A payload decoded using a known data type
+pub struct Decoded {
+    /// The full name of the data type
+    pub type_name: &'static str,
+    /// A description of the payload, or an error if the payload could not be deserialized
+    pub text: Result<String, DeserializeError>,
+}
+
+/// Decodes a message payload, if the subject is the fixed subject ID of a known data type
+pub fn decode_message(subject: SubjectId, payload: &[u8]) -> Option<Decoded> {
+    if subject == heartbeat_1_0::SUBJECT {
+        Some(decode::<Heartbeat>(
+            "uavcan.node.Heartbeat.1.0",
+            payload,
+            format_heartbeat,
+        ))
+    } else if subject == port_list_1_0::SUBJECT {
+        Some(decode::<List>(
+            "uavcan.node.port.List.1.0",
+            payload,
+            format_port_list,
+        ))
+    } else if subject == record_1_1::SUBJECT {
+        Some(decode::<Record>(
+            "uavcan.diagnostic.Record.1.1",
+            payload,
+            format_record,
+        ))
+    } else if subject == synchronization_1_0::SUBJECT {
+        Some(decode::<Synchronization>(
+            "uavcan.time.Synchronization.1.0",
+            payload,
+            |sync| {
+                format!(
+                    "previous_transmission={} us",
+                    sync.previous_transmission_timestamp_microsecond
+                )
+            },
+        ))
+    } else if subject == node_id_allocation_data_1_0::SUBJECT {
+        Some(decode::<NodeIDAllocationData1>(
+            "uavcan.pnp.NodeIDAllocationData.1.0",
+            payload,
+            |data| match data.allocated_node_id.first() {
+                Some(id) => format!(
+                    "unique_id_hash={:#014x} allocated_node_id={}",
+                    data.unique_id_hash,
+                    { id.value }
+                ),
+                None => format!("unique_id_hash={:#014x}", data.unique_id_hash),
+            },
+        ))
+    } else if subject == node_id_allocation_data_2_0::SUBJECT {
+        Some(decode::<NodeIDAllocationData2>(
+            "uavcan.pnp.NodeIDAllocationData.2.0",
+            payload,
+            |data| {
+                format!(
+                    "node_id={} unique_id={}",
+                    { data.node_id.value },
+                    hex(&data.unique_id)
+                )
+            },
+        ))
+    } else {
+        None
+    }
+}
+
+/// Decodes a service request payload, if the service is the fixed service ID of a known data
+/// type
+pub fn decode_request(service: ServiceId, payload: &[u8]) -> Option<Decoded> {
+    if service == get_info_1_0::SERVICE {
+        Some(decode::<GetInfoRequest>(
+            "uavcan.node.GetInfo.1.0",
+            payload,
+            |_| String::new(),
+        ))
+    } else if service == execute_command_1_3::SERVICE {
+        Some(decode::<ExecuteCommandRequest>(
+            "uavcan.node.ExecuteCommand.1.3",
+            payload,
+            format_command_request,
+        ))
+    } else if service == get_transport_statistics_0_1::SERVICE {
+        Some(decode::<GetTransportStatisticsRequest>(
+            "uavcan.node.GetTransportStatistics.0.1",
+            payload,
+            |_| String::new(),
+        ))
+    } else if service == register_list_1_0::SERVICE {
+        Some(decode::<ListRequest>(
+            "uavcan.register.List.1.0",
+            payload,
+            |request| format!("index={}", { request.index }),
+        ))
+    } else if service == access_1_0::SERVICE {
+        Some(decode::<AccessRequest>(
+            "uavcan.register.Access.1.0",
+            payload,
+            |request| format!("name={:?}", String::from_utf8_lossy(&request.name.name)),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Decodes a service response payload, if the service is the fixed service ID of a known data
+/// type
+pub fn decode_response(service: ServiceId, payload: &[u8]) -> Option<Decoded> {
+    if service == get_info_1_0::SERVICE {
+        Some(decode::<GetInfoResponse>(
+            "uavcan.node.GetInfo.1.0",
+            payload,
+            format_info,
+        ))
+    } else if service == execute_command_1_3::SERVICE {
+        Some(decode::<ExecuteCommandResponse>(
+            "uavcan.node.ExecuteCommand.1.3",
+            payload,
+            format_command_response,
+        ))
+    } else if service == get_transport_statistics_0_1::SERVICE {
+        Some(decode::<GetTransportStatisticsResponse>(
+            "uavcan.node.GetTransportStatistics.0.1",
+            payload,
+            format_transport_statistics,
+        ))
+    } else if service == register_list_1_0::SERVICE {
+        Some(decode::<ListResponse>(
+            "uavcan.register.List.1.0",
+            payload,
+            |response| format!("name={:?}", String::from_utf8_lossy(&response.name.name)),
+        ))
+    } else if service == access_1_0::SERVICE {
+        Some(decode::<AccessResponse>(
+            "uavcan.register.Access.1.0",
+            payload,
+            |response| {
+                format!(
+                    "mutable={} persistent={}",
+                    response.mutable, response.persistent
+                )
+            },
+        ))
+    } else {
+        None
+    }
+}
+
+fn decode<T>(type_name: &'static str, payload: &[u8], format: fn(&T) -> String) -> Decoded
+where
+    T: Deserialize,
+{
+    Decoded {
+        type_name,
+        text: T::deserialize_from_bytes(payload).map(|value| format(&value)),
+    }
+}
+
+fn format_heartbeat(heartbeat: &Heartbeat) -> String {
+    let health = match heartbeat.health.value {
+        Health::NOMINAL => "NOMINAL",
+        Health::ADVISORY => "ADVISORY",
+        Health::CAUTION => "CAUTION",
+        Health::WARNING => "WARNING",
+        _ => "?",
+    };
+    let mode = match heartbeat.mode.value {
+        Mode::OPERATIONAL => "OPERATIONAL",
+        Mode::INITIALIZATION => "INITIALIZATION",
+        Mode::MAINTENANCE => "MAINTENANCE",
+        Mode::SOFTWARE_UPDATE => "SOFTWARE_UPDATE",
+        _ => "?",
+    };
+    format!(
+        "uptime={} health={} mode={} vssc={}",
+        heartbeat.uptime, health, mode, heartbeat.vendor_specific_status_code
+    )
+}
+
+fn format_port_list(list: &List) -> String {
+    format!(
+        "publishers={} subscribers={} clients={} servers={}",
+        format_subjects(&list.publishers),
+        format_subjects(&list.subscribers),
+        format_services(&list.clients),
+        format_services(&list.servers)
+    )
+}
+
+fn format_subjects(subjects: &SubjectIDList) -> String {
+    let ids: Vec<usize> = match subjects {
+        SubjectIDList::SparseList(list) => list.iter().map(|id| usize::from(id.value)).collect(),
+        SubjectIDList::Mask(mask) => (0..mask.len()).filter(|&i| mask.get(i)).collect(),
+        SubjectIDList::Total(_) => return String::from("all"),
+    };
+    format_ids(&ids)
+}
+
+fn format_services(services: &ServiceIDList) -> String {
+    let ids: Vec<usize> = (0..services.mask.len())
+        .filter(|&i| services.mask.get(i))
+        .collect();
+    format_ids(&ids)
+}
+
+fn format_ids(ids: &[usize]) -> String {
+    let ids: Vec<String> = ids.iter().map(ToString::to_string).collect();
+    format!("[{}]", ids.join(","))
+}
+
+fn format_record(record: &Record) -> String {
+    let severity = match record.severity.value {
+        Severity::TRACE => "TRACE",
+        Severity::DEBUG => "DEBUG",
+        Severity::INFO => "INFO",
+        Severity::NOTICE => "NOTICE",
+        Severity::WARNING => "WARNING",
+        Severity::ERROR => "ERROR",
+        Severity::CRITICAL => "CRITICAL",
+        Severity::ALERT => "ALERT",
+        _ => "?",
+    };
+    format!("[{}] {:?}", severity, String::from_utf8_lossy(&record.text))
+}
+
+fn format_info(info: &GetInfoResponse) -> String {
+    format!(
+        "name={:?} protocol={}.{} hardware={}.{} software={}.{} vcs={:#x} unique_id={}",
+        String::from_utf8_lossy(&info.name),
+        info.protocol_version.major,
+        info.protocol_version.minor,
+        info.hardware_version.major,
+        info.hardware_version.minor,
+        info.software_version.major,
+        info.software_version.minor,
+        info.software_vcs_revision_id,
+        hex(&info.unique_id)
+    )
+}
+
+fn format_command_request(request: &ExecuteCommandRequest) -> String {
+    let command = match request.command {
+        ExecuteCommandRequest::COMMAND_RESTART => "RESTART",
+        ExecuteCommandRequest::COMMAND_POWER_OFF => "POWER_OFF",
+        ExecuteCommandRequest::COMMAND_BEGIN_SOFTWARE_UPDATE => "BEGIN_SOFTWARE_UPDATE",
+        ExecuteCommandRequest::COMMAND_FACTORY_RESET => "FACTORY_RESET",
+        ExecuteCommandRequest::COMMAND_EMERGENCY_STOP => "EMERGENCY_STOP",
+        ExecuteCommandRequest::COMMAND_STORE_PERSISTENT_STATES => "STORE_PERSISTENT_STATES",
+        ExecuteCommandRequest::COMMAND_IDENTIFY => "IDENTIFY",
+        _ => "vendor-specific",
+    };
+    format!(
+        "command={}({}) parameter={:?}",
+        command,
+        request.command,
+        String::from_utf8_lossy(&request.parameter)
+    )
+}
+
+fn format_command_response(response: &ExecuteCommandResponse) -> String {
+    let status = match response.status {
+        ExecuteCommandResponse::STATUS_SUCCESS => "SUCCESS",
+        ExecuteCommandResponse::STATUS_FAILURE => "FAILURE",
+        ExecuteCommandResponse::STATUS_NOT_AUTHORIZED => "NOT_AUTHORIZED",
+        ExecuteCommandResponse::STATUS_BAD_COMMAND => "BAD_COMMAND",
+        ExecuteCommandResponse::STATUS_BAD_PARAMETER => "BAD_PARAMETER",
+        ExecuteCommandResponse::STATUS_BAD_STATE => "BAD_STATE",
+        ExecuteCommandResponse::STATUS_INTERNAL_ERROR => "INTERNAL_ERROR",
+        _ => "?",
+    };
+    format!(
+        "status={}({}) output={:?}",
+        status,
+        response.status,
+        String::from_utf8_lossy(&response.output)
+    )
+}
+
+fn format_transport_statistics(response: &GetTransportStatisticsResponse) -> String {
+    let mut text = format!(
+        "transfers={}",
+        format_io_statistics(&response.transfer_statistics)
+    );
+    for (i, interface) in response.network_interface_statistics.iter().enumerate() {
+        let _ = write!(text, " interface{}={}", i, format_io_statistics(interface));
+    }
+    text
+}
+
+fn format_io_statistics(statistics: &IOStatistics) -> String {
+    format!(
+        "(emitted {}, received {}, errored {})",
+        statistics.num_emitted, statistics.num_received, statistics.num_errored
+    )
+}
+
+/// Formats bytes as a string of hexadecimal digits
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut text, byte| {
+        let _ = write!(text, "{:02x}", byte);
+        text
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use canadensis_core::SubjectId;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn heartbeat() {
+        let decoded = decode_message(
+            heartbeat_1_0::SUBJECT,
+            &[0x64, 0x00, 0x00, 0x00, 0x01, 0x01, 0x07],
+        )
+        .unwrap();
+        assert_eq!("uavcan.node.Heartbeat.1.0", decoded.type_name);
+        assert_eq!(
+            "uptime=100 health=ADVISORY mode=INITIALIZATION vssc=7",
+            decoded.text.unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_subject() {
+        assert!(decode_message(SubjectId::try_from(100).unwrap(), &[]).is_none());
+    }
+
+    #[test]
+    fn record() {
+        let decoded = decode_message(
+            record_1_1::SUBJECT,
+            &[0, 0, 0, 0, 0, 0, 0, 4, 2, b'h', b'i'],
+        )
+        .unwrap();
+        assert_eq!("[WARNING] \"hi\"", decoded.text.unwrap());
+    }
+}
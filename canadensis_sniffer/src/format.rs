@@ -0,0 +1,89 @@
+//!
+//! Printing of transfers
+//!
+
+use std::fmt::{Debug, Display};
+
+use canadensis_core::transfer::{Header, Transfer};
+use canadensis_core::transport::Transport;
+
+use crate::decode::{self, Decoded};
+
+/// Formats a transfer as one line with the header fields, followed by a second line with the
+/// decoded payload or the payload bytes
+pub fn format_transfer<T>(transfer: &Transfer<Vec<u8>, T>) -> String
+where
+    T: Transport,
+    T::NodeId: Display,
+    T::TransferId: Display,
+    T::Priority: Debug,
+{
+    let header = format_header(&transfer.header);
+    let decoded = match &transfer.header {
+        Header::Message(header) => decode::decode_message(header.subject, &transfer.payload),
+        Header::Request(header) => decode::decode_request(header.service, &transfer.payload),
+        Header::Response(header) => decode::decode_response(header.service, &transfer.payload),
+    };
+    let payload = match decoded {
+        Some(Decoded {
+            type_name,
+            text: Ok(text),
+        }) => format!("{} {}", type_name, text),
+        Some(Decoded {
+            type_name,
+            text: Err(e),
+        }) => format!(
+            "{} (invalid: {:?}) {}",
+            type_name,
+            e,
+            decode::hex(&transfer.payload)
+        ),
+        None => format!(
+            "{} bytes: {}",
+            transfer.payload.len(),
+            decode::hex(&transfer.payload)
+        ),
+    };
+    format!("{}\n    {}", header, payload)
+}
+
+/// Formats the fields of a transfer header
+fn format_header<T>(header: &Header<T>) -> String
+where
+    T: Transport,
+    T::NodeId: Display,
+    T::TransferId: Display,
+    T::Priority: Debug,
+{
+    let time = f64::from(header.timestamp().ticks()) / 1e6;
+    let (kind, port, destination) = match header {
+        Header::Message(header) => ("message", header.subject.to_string(), None),
+        Header::Request(header) => (
+            "request",
+            header.service.to_string(),
+            Some(&header.destination),
+        ),
+        Header::Response(header) => (
+            "response",
+            header.service.to_string(),
+            Some(&header.destination),
+        ),
+    };
+    let source = header
+        .source()
+        .map(ToString::to_string)
+        .unwrap_or_else(|| String::from("anon"));
+    let route = match destination {
+        Some(destination) => format!("{} -> {}", source, destination),
+        None => source,
+    };
+    format!(
+        "{:>12.6} {:<8} {:>4} {:<12} {:?} tid={}",
+        time,
+        kind,
+        port,
+        route,
+        header.priority(),
+        header.transfer_id()
+    )
+}
@@ -0,0 +1,311 @@
+//!
+//! Prints all Cyphal transfers on a SocketCAN interface or a Cyphal/UDP network
+//!
+//! Usage:
+//! * `canadensis_sniffer can <interface>`
+//! * `canadensis_sniffer udp [--subject <subject-id>]... [--node <node-id>]...`
+//! * `canadensis_sniffer candump <log-file>`
+//! * `canadensis_sniffer pcap <pcap-or-pcapng-file> [--port <udp-port>]`
+//!
+//! The sniffer does not send anything. Transfers with data types that have fixed port IDs and
+//! are part of the standard `uavcan` namespace are decoded. Other payloads are printed as
+//! hexadecimal bytes.
+//!
+//! When reading a recording, the printed times are relative to the first frame or packet.
+//!
+
+extern crate canadensis_can;
+extern crate canadensis_capture;
+extern crate canadensis_core;
+extern crate canadensis_data_types;
+extern crate canadensis_encoding;
+extern crate canadensis_linux;
+extern crate canadensis_udp;
+extern crate clap;
+extern crate socketcan;
+
+mod decode;
+mod format;
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use canadensis_can::CanReceiver;
+use canadensis_capture::candump::CandumpReader;
+use canadensis_capture::pcap::{PcapReader, LINKTYPE_CAN_SOCKETCAN};
+use canadensis_capture::replay::{CanReplay, ReplayClock, UdpReplay};
+use canadensis_core::session::SessionDynamicMap;
+use canadensis_core::time::{Clock, MicrosecondDuration32};
+use canadensis_core::transport::Receiver;
+use canadensis_core::SubjectId;
+use canadensis_data_types::uavcan::diagnostic::record_1_1;
+use canadensis_data_types::uavcan::node::heartbeat_1_0;
+use canadensis_data_types::uavcan::node::port::list_1_0;
+use canadensis_data_types::uavcan::pnp::{
+    node_id_allocation_data_1_0, node_id_allocation_data_2_0,
+};
+use canadensis_data_types::uavcan::time::synchronization_1_0;
+use canadensis_linux::{LinuxCan, SystemClock};
+use canadensis_udp::driver::{StdUdpSocket, UdpSocket};
+use canadensis_udp::{Address, UdpNodeId, UdpReceiver, UdpSessionData, UdpTransferId};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use socketcan::Socket;
+
+use crate::format::format_transfer;
+
+/// The maximum size of a Cyphal/UDP packet, including the header
+const UDP_MTU: usize = 1472;
+
+/// The maximum time to wait for a frame or packet from a live network
+///
+/// Receiving blocks for up to this long, so the sniffer does not spin while the network is idle.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The subjects that the UDP sniffer listens to if no subjects are specified
+const DEFAULT_SUBJECTS: [SubjectId; 6] = [
+    heartbeat_1_0::SUBJECT,
+    list_1_0::SUBJECT,
+    record_1_1::SUBJECT,
+    synchronization_1_0::SUBJECT,
+    node_id_allocation_data_1_0::SUBJECT,
+    node_id_allocation_data_2_0::SUBJECT,
+];
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(-1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = get_args();
+    let payload_size_max = *matches.get_one::<usize>("max_payload").unwrap();
+    let timeout =
+        MicrosecondDuration32::from_ticks(*matches.get_one::<u32>("timeout_ms").unwrap() * 1000);
+
+    match matches.subcommand() {
+        Some(("can", matches)) => {
+            let interface = matches.get_one::<String>("interface").unwrap();
+            let socket = socketcan::CanSocket::open(interface)?;
+            socket.set_read_timeout(READ_TIMEOUT)?;
+            let mut can = LinuxCan::new(socket);
+            let mut receiver = CanReceiver::new_anonymous();
+            receiver.accept_all(payload_size_max, timeout, &mut can);
+            sniff(receiver, can, SystemClock::new(), |_| false)
+        }
+        Some(("udp", matches)) => {
+            let interface = *matches.get_one::<Ipv4Addr>("interface").unwrap();
+            let port = *matches.get_one::<u16>("port").unwrap();
+            let subjects: Vec<SubjectId> = matches
+                .get_many::<SubjectId>("subject")
+                .map(|subjects| subjects.copied().collect())
+                .unwrap_or_else(|| DEFAULT_SUBJECTS.to_vec());
+            let nodes: Vec<UdpNodeId> = matches
+                .get_many::<UdpNodeId>("node")
+                .map(|nodes| nodes.copied().collect())
+                .unwrap_or_default();
+
+            // For loopback multicast to work, the socket needs to bind to the unspecified address
+            let mut socket = StdUdpSocket::bind(Ipv4Addr::UNSPECIFIED, port)?;
+            socket.set_read_timeout(Some(READ_TIMEOUT))?;
+            let groups = subjects
+                .into_iter()
+                .map(Address::Multicast)
+                .chain(nodes.into_iter().map(Address::Node));
+            for group in groups {
+                socket.join_multicast_v4(&group.into(), &interface)?;
+            }
+            let mut receiver = UdpReceiver::<
+                SystemClock,
+                SessionDynamicMap<UdpNodeId, UdpTransferId, UdpSessionData>,
+                StdUdpSocket,
+                UDP_MTU,
+            >::new(None, interface);
+            receiver.accept_all(payload_size_max, timeout);
+            sniff(receiver, socket, SystemClock::new(), |_| false)
+        }
+        Some(("candump", matches)) => {
+            let path = matches.get_one::<PathBuf>("file").unwrap();
+            let records = CandumpReader::new(BufReader::new(File::open(path)?));
+            let mut replay = CanReplay::new(records);
+            let clock = replay.clock();
+            let mut receiver = CanReceiver::new_anonymous();
+            receiver.accept_all(payload_size_max, timeout, &mut replay);
+            sniff(receiver, replay, clock, CanReplay::is_finished)
+        }
+        Some(("pcap", matches)) => {
+            let path = matches.get_one::<PathBuf>("file").unwrap();
+            let port = *matches.get_one::<u16>("port").unwrap();
+            let mut packets = PcapReader::new(BufReader::new(File::open(path)?))?.peekable();
+            let is_can = matches!(
+                packets.peek(),
+                Some(Ok(packet)) if packet.link_type == LINKTYPE_CAN_SOCKETCAN
+            );
+            if is_can {
+                let records = packets.filter_map(|packet| match packet {
+                    Ok(packet) => packet.to_can_record("can").map(Ok),
+                    Err(e) => Some(Err(e)),
+                });
+                let mut replay = CanReplay::new(records);
+                let clock = replay.clock();
+                let mut receiver = CanReceiver::new_anonymous();
+                receiver.accept_all(payload_size_max, timeout, &mut replay);
+                sniff(receiver, replay, clock, CanReplay::is_finished)
+            } else {
+                let datagrams = packets.filter_map(|packet| match packet {
+                    Ok(packet) => packet.to_udp_datagram().map(Ok),
+                    Err(e) => Some(Err(e)),
+                });
+                let replay = UdpReplay::new(datagrams, port);
+                let clock = replay.clock();
+                let mut receiver = UdpReceiver::<
+                    ReplayClock,
+                    SessionDynamicMap<UdpNodeId, UdpTransferId, UdpSessionData>,
+                    _,
+                    UDP_MTU,
+                >::new(None, Ipv4Addr::UNSPECIFIED);
+                receiver.accept_all(payload_size_max, timeout);
+                sniff(receiver, replay, clock, UdpReplay::is_finished)
+            }
+        }
+        _ => unreachable!("Subcommand required"),
+    }
+}
+
+/// Receives and prints transfers until `finished` returns true
+fn sniff<C, R, F>(
+    mut receiver: R,
+    mut driver: R::Driver,
+    mut clock: C,
+    finished: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    C: Clock,
+    R: Receiver<C>,
+    F: Fn(&R::Driver) -> bool,
+    <R::Transport as canadensis_core::transport::Transport>::NodeId: std::fmt::Display,
+    <R::Transport as canadensis_core::transport::Transport>::TransferId: std::fmt::Display,
+{
+    while !finished(&driver) {
+        match receiver.receive(&mut clock, &mut driver) {
+            Ok(Some(transfer)) => println!("{}", format_transfer(&transfer)),
+            Ok(None) => {}
+            Err(e) => eprintln!("Receive error: {:?}", e),
+        }
+    }
+    Ok(())
+}
+
+fn get_args() -> ArgMatches {
+    Command::new("canadensis_sniffer")
+        .version(clap::crate_version!())
+        .about("Prints all Cyphal transfers on a SocketCAN interface or a Cyphal/UDP network")
+        .subcommand_required(true)
+        .arg(
+            Arg::new("max_payload")
+                .long("max-payload")
+                .global(true)
+                .value_parser(value_parser!(usize))
+                .default_value("1024")
+                .help("The maximum number of payload bytes to reassemble for each transfer"),
+        )
+        .arg(
+            Arg::new("timeout_ms")
+                .long("timeout")
+                .global(true)
+                .value_parser(value_parser!(u32).range(1..=4_000_000))
+                .default_value("2000")
+                .help("The transfer-ID timeout in milliseconds"),
+        )
+        .subcommand(
+            Command::new("can")
+                .about("Receives transfers from a SocketCAN interface")
+                .arg(
+                    Arg::new("interface")
+                        .index(1)
+                        .required(true)
+                        .help("The name of the CAN interface, like can0 or vcan0"),
+                ),
+        )
+        .subcommand(
+            Command::new("udp")
+                .about("Receives transfers from Cyphal/UDP multicast groups")
+                .arg(
+                    Arg::new("subject")
+                        .long("subject")
+                        .action(ArgAction::Append)
+                        .value_parser(parse_subject)
+                        .help(
+                            "A subject ID to receive messages on. If no subjects are specified, \
+                             the standard subjects with fixed IDs are used.",
+                        ),
+                )
+                .arg(
+                    Arg::new("node")
+                        .long("node")
+                        .action(ArgAction::Append)
+                        .value_parser(parse_node)
+                        .help("A node ID to receive service requests and responses sent to"),
+                )
+                .arg(
+                    Arg::new("port")
+                        .long("port")
+                        .value_parser(value_parser!(u16))
+                        .default_value("9382")
+                        .help("The UDP port to listen on"),
+                )
+                .arg(
+                    Arg::new("interface")
+                        .long("interface")
+                        .value_parser(value_parser!(Ipv4Addr))
+                        .default_value("127.0.0.1")
+                        .help("The address of the network interface to join multicast groups on"),
+                ),
+        )
+        .subcommand(
+            Command::new("candump")
+                .about("Reads transfers from a log file written by candump -l")
+                .arg(
+                    Arg::new("file")
+                        .index(1)
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("The log file to read"),
+                ),
+        )
+        .subcommand(
+            Command::new("pcap")
+                .about("Reads transfers from a pcap or pcapng file with SocketCAN frames or UDP packets")
+                .arg(
+                    Arg::new("file")
+                        .index(1)
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("The capture file to read"),
+                )
+                .arg(
+                    Arg::new("port")
+                        .long("port")
+                        .value_parser(value_parser!(u16))
+                        .default_value("9382")
+                        .help("The destination UDP port of Cyphal/UDP packets"),
+                ),
+        )
+        .get_matches()
+}
+
+fn parse_subject(value: &str) -> Result<SubjectId, String> {
+    let value: u16 = value.parse().map_err(|e| format!("{}", e))?;
+    SubjectId::try_from(value).map_err(|_| String::from("Subject ID out of range"))
+}
+
+fn parse_node(value: &str) -> Result<UdpNodeId, String> {
+    let value: u16 = value.parse().map_err(|e| format!("{}", e))?;
+    UdpNodeId::try_from(value).map_err(|_| String::from("Node ID out of range"))
+}
@@ -76,6 +76,13 @@ mod std_socket {
             )))?;
             Ok(StdUdpSocket(socket.into()))
         }
+
+        /// Sets the maximum time that a receive operation waits for a packet
+        ///
+        /// The default timeout is 1 millisecond.
+        pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+            self.0.set_read_timeout(timeout)
+        }
     }
 
     impl UdpSocket for StdUdpSocket {
@@ -37,6 +37,7 @@ use canadensis_core::{OutOfMemoryError, Priority};
 use canadensis_header::{NodeId16, TransferId64};
 use core::fmt::Debug;
 
+pub use crate::address::Address;
 pub use crate::rx::{UdpReceiver, UdpSessionData};
 pub use crate::tx::UdpTransmitter;
 
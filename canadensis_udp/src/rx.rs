@@ -7,6 +7,7 @@ use zerocopy::FromBytes;
 
 use canadensis_core::crc::CrcTracker;
 use canadensis_core::session::{ActiveSession, Session, SessionTracker};
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::time::{Clock, MicrosecondDuration32, Microseconds32};
 use canadensis_core::transfer::{Header, MessageHeader, ServiceHeader, Transfer};
 use canadensis_core::transport::Receiver;
@@ -30,6 +31,13 @@ pub struct UdpReceiver<C, T, S, const MTU: usize> {
     node_id: Option<UdpNodeId>,
     /// The IP address of the local interface that the socket is bound to
     local_address: Ipv4Addr,
+    /// Transfers received and failed
+    transfers: IoStatistics,
+    /// Packets received and packets that were malformed or could not be read
+    frames: IoStatistics,
+    /// Subscription settings for ports that are subscribed automatically, if this receiver
+    /// accepts transfers on all ports
+    accept_all: Option<AcceptAll>,
     _socket: PhantomData<S>,
     _session_tracker: PhantomData<T>,
     _clock: PhantomData<C>,
@@ -46,6 +54,9 @@ where
             subscriptions: Subscriptions::new(),
             node_id,
             local_address: interface_address,
+            transfers: IoStatistics::default(),
+            frames: IoStatistics::default(),
+            accept_all: None,
             _socket: PhantomData,
             _session_tracker: PhantomData,
             _clock: PhantomData,
@@ -68,9 +79,11 @@ where
         let bytes_received = socket.recv(&mut buffer).map_err(Error::Socket)?;
         let buffer = &buffer[..bytes_received];
         let frame_time = clock.now();
+        self.frames.received = self.frames.received.wrapping_add(1);
 
         if bytes_received < MIN_PACKET_SIZE {
             // Ignore packet
+            self.frames.errored = self.frames.errored.wrapping_add(1);
             return Ok(None);
         }
         // Check header validity, ignore frames with invalid headers
@@ -79,9 +92,17 @@ where
             .and_then(|(header, _)| UdpHeader::try_from(header).ok());
         let header: UdpHeader = match header {
             Some(header) => header,
-            None => return Ok(None),
+            None => {
+                self.frames.errored = self.frames.errored.wrapping_add(1);
+                return Ok(None);
+            }
         };
         let bytes_after_header = &buffer[canadensis_header::SIZE..];
+        if let Some(accept_all) = &self.accept_all {
+            self.subscriptions.subscribe_if_absent(&header.data_specifier, || {
+                Subscription::new(accept_all.payload_size_max, accept_all.timeout)
+            });
+        }
 
         // Look for a matching subscription
         match header.data_specifier {
@@ -122,6 +143,21 @@ where
         Ok(None)
     }
 
+    /// Makes this receiver accept transfers on all ports
+    ///
+    /// This is intended for tools that monitor a network. When a packet arrives on a port that
+    /// this receiver is not subscribed to, the receiver subscribes to the port with the provided
+    /// maximum payload size and transfer-ID timeout.
+    ///
+    /// This function does not join any multicast groups. The socket only receives packets sent to
+    /// the groups that it has joined.
+    pub fn accept_all(&mut self, payload_size_max: usize, timeout: MicrosecondDuration32) {
+        self.accept_all = Some(AcceptAll {
+            payload_size_max,
+            timeout,
+        });
+    }
+
     /// Call this function before adding a service subscription
     /// to join the multicast group if necessary
     fn service_subscribe_check_multicast(&mut self, socket: &mut S) -> Result<(), S::Error> {
@@ -167,14 +203,23 @@ where
         // Loop until all incoming packets have been read
         loop {
             match self.accept_inner(clock, socket) {
-                Ok(Some(transfer)) => break Ok(Some(transfer)),
+                Ok(Some(transfer)) => {
+                    self.transfers.received = self.transfers.received.wrapping_add(1);
+                    break Ok(Some(transfer));
+                }
                 Ok(None) => { /* Keep going and try to read another packet */ }
                 Err(Error::Socket(nb::Error::WouldBlock)) => {
                     // Can't read any more
                     break Ok(None);
                 }
-                Err(Error::Memory(e)) => break Err(Error::Memory(e)),
-                Err(Error::Socket(nb::Error::Other(e))) => break Err(Error::Socket(e)),
+                Err(Error::Memory(e)) => {
+                    self.transfers.errored = self.transfers.errored.wrapping_add(1);
+                    break Err(Error::Memory(e));
+                }
+                Err(Error::Socket(nb::Error::Other(e))) => {
+                    self.frames.errored = self.frames.errored.wrapping_add(1);
+                    break Err(Error::Socket(e));
+                }
             }
         }
     }
@@ -256,6 +301,24 @@ where
     fn servers(&self) -> impl Iterator<Item = ServiceId> {
         self.subscriptions.servers()
     }
+
+    /// Adds the numbers of transfers and packets received to `statistics`
+    ///
+    /// Packets are counted as interface 0.
+    fn add_statistics(&self, _socket: &S, statistics: &mut TransportStatistics) {
+        statistics.transfers.add(&self.transfers);
+        if let Some(interface) = statistics.interface_mut(0) {
+            interface.add(&self.frames);
+        }
+    }
+}
+
+/// Subscription settings for ports that a receiver subscribes to automatically
+struct AcceptAll {
+    /// The maximum payload size for each port
+    payload_size_max: usize,
+    /// The transfer-ID timeout for each port
+    timeout: MicrosecondDuration32,
 }
 
 pub struct Subscription<T> {
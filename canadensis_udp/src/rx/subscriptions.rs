@@ -3,6 +3,7 @@
 use alloc::collections::BTreeMap;
 
 use canadensis_core::{ServiceId, SubjectId};
+use canadensis_header::DataSpecifier;
 
 use crate::rx::Subscription;
 
@@ -68,6 +69,27 @@ impl<T> Subscriptions<T> {
         self.response.get_mut(&service)
     }
 
+    /// Stores a subscription for the port that a data specifier refers to, if that port does not
+    /// have a subscription
+    pub fn subscribe_if_absent<F>(&mut self, data_specifier: &DataSpecifier, make_subscription: F)
+    where
+        F: FnOnce() -> Subscription<T>,
+    {
+        match *data_specifier {
+            DataSpecifier::Subject { subject, .. } => {
+                self.message.entry(subject).or_insert_with(make_subscription);
+            }
+            DataSpecifier::ServiceRequest { service, .. } => {
+                self.request.entry(service).or_insert_with(make_subscription);
+            }
+            DataSpecifier::ServiceResponse { service, .. } => {
+                self.response
+                    .entry(service)
+                    .or_insert_with(make_subscription);
+            }
+        }
+    }
+
     /// Returns true if any request or response subscriptions exist
     pub fn any_service_subscriptions(&self) -> bool {
         !(self.request.is_empty() && self.response.is_empty())
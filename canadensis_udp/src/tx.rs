@@ -4,6 +4,7 @@ use core::marker::PhantomData;
 use core::net::SocketAddrV4;
 
 use canadensis_core::nb;
+use canadensis_core::statistics::{IoStatistics, TransportStatistics};
 use canadensis_core::time::{Clock, Microseconds32};
 use canadensis_core::transfer::{Header, Transfer};
 use canadensis_core::transport::Transmitter;
@@ -18,6 +19,10 @@ mod breakdown;
 
 pub struct UdpTransmitter<S, const MTU: usize> {
     destination_port: u16,
+    /// Transfers sent and failed
+    transfers: IoStatistics,
+    /// Packets sent, and packets that could not be sent or missed their deadlines
+    frames: IoStatistics,
     _socket: PhantomData<S>,
 }
 impl<S, const MTU: usize> UdpTransmitter<S, MTU>
@@ -39,6 +44,8 @@ where
 
         UdpTransmitter {
             destination_port,
+            transfers: IoStatistics::default(),
+            frames: IoStatistics::default(),
             _socket: PhantomData,
         }
     }
@@ -72,9 +79,14 @@ where
     {
         for frame in breakdown {
             if frame.deadline > clock.now() {
-                socket.send_to(&frame.data, destination_address)?;
+                if let Err(e) = socket.send_to(&frame.data, destination_address) {
+                    self.frames.errored = self.frames.errored.wrapping_add(1);
+                    return Err(e);
+                }
+                self.frames.emitted = self.frames.emitted.wrapping_add(1);
             } else {
                 l0g::trace!("Discarding outgoing frame because its deadline has passed");
+                self.frames.errored = self.frames.errored.wrapping_add(1);
             }
         }
         Ok(())
@@ -143,16 +155,24 @@ where
                 (header_base, dest_addr)
             }
         };
-        self.push_inner(
+        let result = self.push_inner(
             header_base,
             SocketAddrV4::new(dest_address.into(), self.destination_port),
             deadline,
             transfer.payload.as_ref(),
             clock,
             socket,
-        )
-        .map_err(Error::Socket)
-        .map_err(nb::Error::Other)
+        );
+        match result {
+            Ok(()) => {
+                self.transfers.emitted = self.transfers.emitted.wrapping_add(1);
+                Ok(())
+            }
+            Err(e) => {
+                self.transfers.errored = self.transfers.errored.wrapping_add(1);
+                Err(nb::Error::Other(Error::Socket(e)))
+            }
+        }
     }
 
     fn flush(
@@ -169,6 +189,16 @@ where
         // Subtract to get the maximum number of payload bytes per frame
         MTU - canadensis_header::SIZE - TRANSFER_CRC_SIZE
     }
+
+    /// Adds the numbers of transfers and packets sent to `statistics`
+    ///
+    /// Packets are counted as interface 0.
+    fn add_statistics(&self, _socket: &S, statistics: &mut TransportStatistics) {
+        statistics.transfers.add(&self.transfers);
+        if let Some(interface) = statistics.interface_mut(0) {
+            interface.add(&self.frames);
+        }
+    }
 }
 
 #[derive(Eq, PartialEq, Debug)]
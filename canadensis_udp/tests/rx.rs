@@ -289,6 +289,50 @@ fn multi_frame_duplicate() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[test]
+fn accept_all() -> Result<(), Box<dyn Error>> {
+    init_test_logging();
+    let mut rx: UdpReceiver<
+        StubClock,
+        SessionDynamicMap<UdpNodeId, UdpTransferId, UdpSessionData>,
+        StdUdpSocket,
+        1472,
+    > = UdpReceiver::new(None, Ipv4Addr::LOCALHOST);
+    rx.accept_all(64, milliseconds(1000));
+
+    // Loopback using two UDP sockets
+    // Use OS-assigned ephemeral ports.
+    let mut transmit_socket = StdUdpSocket::bind(Ipv4Addr::LOCALHOST, 0).unwrap();
+    let mut receive_socket = StdUdpSocket::bind(Ipv4Addr::UNSPECIFIED, 0).unwrap();
+    let receive_port = receive_socket.local_addr()?.port();
+    let loopback_destination = SocketAddrV4::new(Ipv4Addr::LOCALHOST, receive_port);
+
+    for frame in VALID_THREE_FRAME_TRANSFER {
+        transmit_socket.send_to(frame, loopback_destination)?;
+    }
+
+    let mut clock = StubClock::default();
+    clock.set_ticks(109932);
+    let transfer = rx.receive(&mut clock, &mut receive_socket).unwrap();
+    let subject = SubjectId::try_from(1092).unwrap();
+    assert_eq!(
+        transfer,
+        Some(Transfer {
+            header: Header::Message(MessageHeader {
+                timestamp: Microseconds32::from_ticks(109932),
+                transfer_id: UdpTransferId::try_from(0xfaa8a7df3248e7fb).unwrap(),
+                priority: Priority::Slow,
+                subject,
+                source: Some(UdpNodeId::try_from(0xf0e3).unwrap())
+            }),
+            loopback: false,
+            payload: VALID_THREE_FRAME_TRANSFER_PAYLOAD.to_vec(),
+        })
+    );
+    assert_eq!(vec![subject], rx.subscribers().collect::<Vec<_>>());
+    Ok(())
+}
+
 #[derive(Default)]
 struct StubClock {
     ticks: u32,
@@ -0,0 +1,266 @@
+//!
+//! Finding the bytes of an ELF file that are programmed into flash
+//!
+//! A programmer writes the content of each loadable segment at the segment's physical (load)
+//! address. Other parts of the file, like the headers, symbol table, and debug information, are
+//! never programmed. The CRC and image size in an app descriptor must cover only the programmed
+//! bytes, so that the application and bootloader can check them.
+//!
+
+use std::convert::TryFrom;
+use std::error::Error;
+
+use object::elf::{FileHeader32, FileHeader64, PT_LOAD};
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::{Endianness, FileKind};
+
+use crate::ihex::{FlatImage, GAP_FILL};
+use crate::StringError;
+
+/// The largest span of addresses that the loadable segments can cover
+///
+/// The image is stored in memory with the gaps between segments filled in, so segments that are
+/// far apart (for example, flash and external memory) would need an unreasonable amount of
+/// memory. An app descriptor can't describe images larger than this either.
+const IMAGE_SIZE_MAX: u64 = 256 * 1024 * 1024;
+
+/// A loadable segment that has some content in the file
+struct Segment {
+    /// The offset of the content from the beginning of the file
+    file_offset: usize,
+    /// The physical address where the content is programmed
+    address: u64,
+    /// The length of the content in the file
+    length: usize,
+}
+
+/// The loadable segments of an ELF file
+pub struct ElfSegments {
+    /// Segments with content, in the order they appear in the program header table
+    segments: Vec<Segment>,
+}
+
+impl ElfSegments {
+    /// Reads the program headers of an ELF file
+    pub fn parse(contents: &[u8]) -> Result<ElfSegments, Box<dyn Error>> {
+        match FileKind::parse(contents)? {
+            FileKind::Elf32 => ElfSegments::parse_with::<FileHeader32<Endianness>>(contents),
+            FileKind::Elf64 => ElfSegments::parse_with::<FileHeader64<Endianness>>(contents),
+            _ => Err(Box::new(StringError("Not an ELF file".into()))),
+        }
+    }
+
+    fn parse_with<H>(contents: &[u8]) -> Result<ElfSegments, Box<dyn Error>>
+    where
+        H: FileHeader<Endian = Endianness>,
+    {
+        let header = H::parse(contents)?;
+        let endian = header.endian()?;
+        let mut segments = Vec::new();
+        for program_header in header.program_headers(endian, contents)? {
+            let length: u64 = program_header.p_filesz(endian).into();
+            if program_header.p_type(endian) != PT_LOAD || length == 0 {
+                continue;
+            }
+            let file_offset: u64 = program_header.p_offset(endian).into();
+            let too_large = || StringError("ELF segment is too large".into());
+            segments.push(Segment {
+                file_offset: usize::try_from(file_offset).map_err(|_| too_large())?,
+                address: program_header.p_paddr(endian).into(),
+                length: usize::try_from(length).map_err(|_| too_large())?,
+            });
+        }
+        Ok(ElfSegments { segments })
+    }
+
+    /// Returns the content of all loadable segments, from the lowest physical address to the
+    /// highest
+    pub fn to_image(&self, contents: &[u8]) -> Result<FlatImage, StringError> {
+        let mut ranges = Vec::with_capacity(self.segments.len());
+        for segment in &self.segments {
+            let end = segment
+                .address
+                .checked_add(segment.length as u64)
+                .ok_or_else(|| StringError("ELF segment address is too large".into()))?;
+            ranges.push((segment.address, end));
+        }
+        let (start, end) = ranges
+            .into_iter()
+            .reduce(|(start1, end1), (start2, end2)| (start1.min(start2), end1.max(end2)))
+            .ok_or_else(|| StringError("ELF file has no loadable segments".into()))?;
+        let base = u32::try_from(start)
+            .map_err(|_| StringError("ELF segment address is too large".into()))?;
+        if end > u64::from(u32::MAX) + 1 {
+            return Err(StringError(
+                "ELF segment extends past the end of the 32-bit address space".into(),
+            ));
+        }
+        if end - start > IMAGE_SIZE_MAX {
+            return Err(StringError(format!(
+                "ELF segments span {:#x} bytes, more than the maximum of {:#x}",
+                end - start,
+                IMAGE_SIZE_MAX
+            )));
+        }
+        let length = (end - start) as usize;
+        let mut bytes = vec![GAP_FILL; length];
+        for segment in &self.segments {
+            let data = contents
+                .get(segment.file_offset..)
+                .and_then(|data| data.get(..segment.length))
+                .ok_or_else(|| {
+                    StringError("ELF segment extends past the end of the file".into())
+                })?;
+            let offset = (segment.address - start) as usize;
+            bytes[offset..][..segment.length].copy_from_slice(data);
+        }
+        Ok(FlatImage { base, bytes })
+    }
+
+    /// Converts an offset in the file into an offset in an image (which must have come from
+    /// `to_image`)
+    ///
+    /// This returns None if the offset is not in a loadable segment.
+    pub fn image_offset(&self, image: &FlatImage, file_offset: usize) -> Option<usize> {
+        self.segments
+            .iter()
+            .find(|segment| {
+                file_offset >= segment.file_offset
+                    && file_offset < segment.file_offset + segment.length
+            })
+            .map(|segment| {
+                (segment.address - u64::from(image.base)) as usize
+                    + (file_offset - segment.file_offset)
+            })
+    }
+
+    /// Copies bytes from an image (which must have come from `to_image`) back into the segments
+    /// in the file
+    pub fn update(&self, image: &FlatImage, contents: &mut [u8]) {
+        for segment in &self.segments {
+            let offset = (segment.address - u64::from(image.base)) as usize;
+            contents[segment.file_offset..][..segment.length]
+                .copy_from_slice(&image.bytes[offset..][..segment.length]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns a little-endian 32-bit ELF file with the provided program headers
+    /// (type, file offset, virtual address, physical address, file size) and content
+    fn elf32(program_headers: &[(u32, u32, u32, u32, u32)], content: &[u8]) -> Vec<u8> {
+        let mut file = b"\x7fELF\x01\x01\x01".to_vec();
+        file.resize(16, 0);
+        let header_fields: [&[u8]; 13] = [
+            &2u16.to_le_bytes(),  // e_type (executable)
+            &40u16.to_le_bytes(), // e_machine (ARM)
+            &1u32.to_le_bytes(),  // e_version
+            &0u32.to_le_bytes(),  // e_entry
+            &52u32.to_le_bytes(), // e_phoff
+            &0u32.to_le_bytes(),  // e_shoff
+            &0u32.to_le_bytes(),  // e_flags
+            &52u16.to_le_bytes(), // e_ehsize
+            &32u16.to_le_bytes(), // e_phentsize
+            &(program_headers.len() as u16).to_le_bytes(),
+            &40u16.to_le_bytes(), // e_shentsize
+            &0u16.to_le_bytes(),  // e_shnum
+            &0u16.to_le_bytes(),  // e_shstrndx
+        ];
+        for field in header_fields.iter() {
+            file.extend_from_slice(field);
+        }
+        for &(p_type, offset, virtual_address, physical_address, size) in program_headers {
+            for field in [
+                p_type,
+                offset,
+                virtual_address,
+                physical_address,
+                size,
+                size,
+                0,
+                4,
+            ] {
+                file.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        file.extend_from_slice(content);
+        file
+    }
+
+    #[test]
+    fn loadable_segments() {
+        // Program headers end at 52 + 3 * 32 = 148
+        let content: Vec<u8> = (0..24).collect();
+        let mut file = elf32(
+            &[
+                // Code in flash
+                (PT_LOAD, 148, 0x0800_0000, 0x0800_0000, 16),
+                // Not loaded
+                (4, 148, 0, 0, 24),
+                // Initialized data, loaded into flash after a gap and copied into RAM
+                (PT_LOAD, 164, 0x2000_0000, 0x0800_0014, 8),
+            ],
+            &content,
+        );
+        let segments = ElfSegments::parse(&file).unwrap();
+        let mut image = segments.to_image(&file).unwrap();
+        assert_eq!(image.base, 0x0800_0000);
+        assert_eq!(
+            image.bytes,
+            [
+                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 0xff, 0xff, 0xff, 0xff, 16,
+                17, 18, 19, 20, 21, 22, 23
+            ]
+        );
+
+        assert_eq!(segments.image_offset(&image, 150), Some(2));
+        assert_eq!(segments.image_offset(&image, 165), Some(21));
+        assert_eq!(segments.image_offset(&image, 100), None);
+        assert_eq!(segments.image_offset(&image, 172), None);
+
+        image.bytes[2] = 0xaa;
+        image.bytes[21] = 0xbb;
+        segments.update(&image, &mut file);
+        assert_eq!(file[150], 0xaa);
+        assert_eq!(file[165], 0xbb);
+    }
+
+    #[test]
+    fn segments_too_far_apart() {
+        let content = [0u8; 8];
+        // Flash and external memory 1 GiB apart
+        let file = elf32(
+            &[
+                (PT_LOAD, 116, 0x0800_0000, 0x0800_0000, 4),
+                (PT_LOAD, 120, 0x4800_0000, 0x4800_0000, 4),
+            ],
+            &content,
+        );
+        let segments = ElfSegments::parse(&file).unwrap();
+        assert!(segments.to_image(&file).is_err());
+    }
+
+    #[test]
+    fn segment_past_address_space() {
+        let content = [0u8; 8];
+        let file = elf32(&[(PT_LOAD, 84, 0xffff_fffc, 0xffff_fffc, 8)], &content);
+        let segments = ElfSegments::parse(&file).unwrap();
+        assert!(segments.to_image(&file).is_err());
+    }
+
+    #[test]
+    fn segment_end_overflows() {
+        // Only possible with 64-bit addresses
+        let segments = ElfSegments {
+            segments: vec![Segment {
+                file_offset: 0,
+                address: u64::MAX - 3,
+                length: 8,
+            }],
+        };
+        assert!(segments.to_image(&[0u8; 8]).is_err());
+    }
+}
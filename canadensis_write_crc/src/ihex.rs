@@ -0,0 +1,246 @@
+//!
+//! Reading and writing Intel HEX files
+//!
+//! Only the contents of data records are changed. All other records, and the order and
+//! addresses of data records, stay the same.
+//!
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use crate::StringError;
+
+const RECORD_DATA: u8 = 0x00;
+const RECORD_END_OF_FILE: u8 = 0x01;
+const RECORD_EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const RECORD_EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+
+/// The value of bytes that are not in any data record, which is the value of erased flash
+pub const GAP_FILL: u8 = 0xff;
+
+/// An Intel HEX file
+pub struct HexFile {
+    lines: Vec<Line>,
+    line_ending: &'static str,
+}
+
+enum Line {
+    /// A data record
+    Data {
+        /// The address field of the record
+        offset: u16,
+        /// The address of the first byte, including any extended address
+        address: u32,
+        data: Vec<u8>,
+    },
+    /// Any other record or line, which is written back unchanged
+    Other(String),
+}
+
+/// The bytes of a HEX file or the loadable segments of an ELF file, with gaps filled with
+/// [`GAP_FILL`]
+pub struct FlatImage {
+    /// The address of the first byte
+    pub base: u32,
+    pub bytes: Vec<u8>,
+}
+
+impl HexFile {
+    /// Parses the text of a HEX file
+    pub fn parse(text: &str) -> Result<HexFile, StringError> {
+        let line_ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut lines = Vec::new();
+        let mut extended_address = 0u32;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                lines.push(Line::Other(String::new()));
+                continue;
+            }
+            let record =
+                parse_record(line).map_err(|e| StringError(format!("Line {}: {}", i + 1, e.0)))?;
+            match record.kind {
+                RECORD_DATA => {
+                    lines.push(Line::Data {
+                        offset: record.offset,
+                        address: extended_address.wrapping_add(u32::from(record.offset)),
+                        data: record.data,
+                    });
+                    continue;
+                }
+                RECORD_EXTENDED_SEGMENT_ADDRESS | RECORD_EXTENDED_LINEAR_ADDRESS => {
+                    let value = match record.data[..] {
+                        [high, low] => u32::from(u16::from_be_bytes([high, low])),
+                        _ => {
+                            return Err(StringError(format!(
+                                "Line {}: Extended address record does not have 2 bytes",
+                                i + 1
+                            )))
+                        }
+                    };
+                    extended_address = if record.kind == RECORD_EXTENDED_LINEAR_ADDRESS {
+                        value << 16
+                    } else {
+                        value << 4
+                    };
+                }
+                _ => {}
+            }
+            lines.push(Line::Other(line.to_owned()));
+            if record.kind == RECORD_END_OF_FILE {
+                break;
+            }
+        }
+        Ok(HexFile { lines, line_ending })
+    }
+
+    /// Returns the bytes from all data records, from the lowest address to the highest
+    pub fn to_image(&self) -> Result<FlatImage, StringError> {
+        let ranges = self
+            .data()
+            .map(|(address, data)| (u64::from(address), u64::from(address) + data.len() as u64));
+        let (start, end) = ranges
+            .reduce(|(start1, end1), (start2, end2)| (start1.min(start2), end1.max(end2)))
+            .ok_or_else(|| StringError("HEX file has no data".into()))?;
+        let length = usize::try_from(end - start)
+            .map_err(|_| StringError("HEX file data is too large".into()))?;
+        let mut bytes = vec![GAP_FILL; length];
+        for (address, data) in self.data() {
+            let offset = (u64::from(address) - start) as usize;
+            bytes[offset..][..data.len()].copy_from_slice(data);
+        }
+        Ok(FlatImage {
+            base: start as u32,
+            bytes,
+        })
+    }
+
+    /// Copies bytes from an image (which must have come from `to_image`) back into the data
+    /// records
+    pub fn update(&mut self, image: &FlatImage) {
+        for line in &mut self.lines {
+            if let Line::Data { address, data, .. } = line {
+                let offset = (*address - image.base) as usize;
+                let length = data.len();
+                data.copy_from_slice(&image.bytes[offset..][..length]);
+            }
+        }
+    }
+
+    /// Returns the text of this file
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Data { offset, data, .. } => {
+                    let [offset_high, offset_low] = offset.to_be_bytes();
+                    let mut record = vec![data.len() as u8, offset_high, offset_low, RECORD_DATA];
+                    record.extend_from_slice(data);
+                    let checksum = record
+                        .iter()
+                        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+                        .wrapping_neg();
+                    record.push(checksum);
+                    text.push(':');
+                    for byte in record {
+                        write!(text, "{:02X}", byte).unwrap();
+                    }
+                }
+                Line::Other(line) => text.push_str(line),
+            }
+            text.push_str(self.line_ending);
+        }
+        text
+    }
+
+    fn data(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Data { address, data, .. } => Some((*address, &data[..])),
+            Line::Other(_) => None,
+        })
+    }
+}
+
+struct Record {
+    kind: u8,
+    offset: u16,
+    data: Vec<u8>,
+}
+
+fn parse_record(line: &str) -> Result<Record, StringError> {
+    let hex = line
+        .strip_prefix(':')
+        .ok_or_else(|| StringError("Record does not start with ':'".into()))?;
+    if !hex.is_ascii() {
+        return Err(StringError("Record is not hexadecimal".into()));
+    }
+    if hex.len() % 2 != 0 {
+        return Err(StringError("Record has an odd number of digits".into()));
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|e| StringError(format!("Invalid hexadecimal: {}", e)))?;
+    if bytes.len() < 5 || bytes.len() != usize::from(bytes[0]) + 5 {
+        return Err(StringError(
+            "Record length does not match its byte count".into(),
+        ));
+    }
+    if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+        return Err(StringError("Incorrect checksum".into()));
+    }
+    Ok(Record {
+        kind: bytes[3],
+        offset: u16::from_be_bytes([bytes[1], bytes[2]]),
+        data: bytes[4..bytes.len() - 1].to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: &str = ":020000040800F2\r\n\
+                           :0400000001020304F2\r\n\
+                           :02000800AABB91\r\n\
+                           :04000005080001C12D\r\n\
+                           :00000001FF\r\n";
+
+    #[test]
+    fn round_trip() {
+        let file = HexFile::parse(EXAMPLE).unwrap();
+        assert_eq!(file.to_text(), EXAMPLE);
+    }
+
+    #[test]
+    fn image_with_gap() {
+        let mut file = HexFile::parse(EXAMPLE).unwrap();
+        let mut image = file.to_image().unwrap();
+        assert_eq!(image.base, 0x0800_0000);
+        assert_eq!(
+            image.bytes,
+            [1, 2, 3, 4, 0xff, 0xff, 0xff, 0xff, 0xaa, 0xbb]
+        );
+
+        image.bytes[1] = 0x20;
+        image.bytes[9] = 0xcc;
+        file.update(&image);
+        assert_eq!(
+            file.to_text(),
+            ":020000040800F2\r\n\
+             :0400000001200304D4\r\n\
+             :02000800AACC80\r\n\
+             :04000005080001C12D\r\n\
+             :00000001FF\r\n"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(HexFile::parse(":0400000001020304F3\n").is_err());
+        assert!(HexFile::parse(":04000000010203F2\n").is_err());
+        assert!(HexFile::parse("0400000001020304F2\n").is_err());
+        assert!(HexFile::parse(":00000001FF\n").unwrap().to_image().is_err());
+    }
+}
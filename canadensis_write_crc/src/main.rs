@@ -1,62 +1,194 @@
 //!
 //! # Canadensis CRC writer
 //!
-//! This application modifies an ELF binary file that uses the `canadensis_crc` library. It
+//! This application modifies a binary file that uses the `canadensis_crc` library. It
 //! calculates the CRC of the binary and writes the CRC value so that the `canadensis_crc`
 //! functions will return the correct value.
 //!
+//! If the binary contains an app descriptor (from `canadensis_crc::app_descriptor!()`), this
+//! also fills in the image size, version control revision, and build time.
+//!
+//! ## File formats
+//!
+//! * ELF with an app descriptor: The CRC and image size cover the content of the loadable
+//!   segments, placed at their physical addresses from the lowest address to the highest, with
+//!   any gaps filled with 0xff. These are the bytes that a programmer writes into flash. The app
+//!   descriptor is found using the symbol table.
+//! * ELF without an app descriptor: The CRC covers the whole file. The `CANADENSIS_CRC` and
+//!   `CANADENSIS_CRC_VALID` symbols are found using the symbol table.
+//! * Raw binary (for example, from `objcopy -O binary`): The CRC covers the whole file. The file
+//!   must contain an app descriptor, which is found by searching for its signature.
+//! * Intel HEX (files ending in `.hex` or `.ihex`): The CRC covers the data from the lowest
+//!   address to the highest address, with any gaps between records filled with 0xff (the value
+//!   of erased flash). The app descriptor is found by searching for its signature.
+//!
 //! ## Usage
 //!
-//! `canadensis_write_crc file-path`
+//! `canadensis_write_crc [--vcs-revision hex] [--software-version major.minor] file-path`
+//!
+//! The build time is the value of the `SOURCE_DATE_EPOCH` environment variable if it is set,
+//! or the current time otherwise.
+//!
+//! `canadensis_write_crc verify file-path` checks the CRC without changing the file. It exits
+//! with status 1 if the CRC is missing or incorrect.
 //!
 
+extern crate canadensis_crc;
+extern crate clap;
 extern crate crc_any;
 extern crate object;
 
+mod elf;
+mod ihex;
+
+use crate::elf::ElfSegments;
+use crate::ihex::{FlatImage, HexFile};
+use canadensis_crc::AppDescriptor;
+use clap::{value_parser, Arg, ArgMatches, Command};
 use crc_any::CRCu64;
 use object::read::{File, Object};
 use object::{ObjectSection, ObjectSymbol};
 use std::borrow::Cow;
+use std::convert::{TryFrom, TryInto};
 use std::error::Error;
+use std::ffi::OsStr;
 use std::fs;
-use std::path::PathBuf;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{env, iter};
 
+/// The first bytes of every ELF file
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+/// The name of the symbol that `canadensis_crc::app_descriptor!()` defines
+const DESCRIPTOR_SYMBOL: &str = "CANADENSIS_APP_DESCRIPTOR";
+
+/// Offsets of app descriptor fields from the beginning of the descriptor
+///
+/// These match the format described in the `AppDescriptor` documentation.
+mod descriptor_offsets {
+    pub const CRC: usize = 8;
+    pub const IMAGE_SIZE: usize = 16;
+    pub const CRC_VALID: usize = 20;
+    pub const VERSION_MAJOR: usize = 21;
+    pub const VERSION_MINOR: usize = 22;
+    pub const VCS_REVISION: usize = 24;
+    pub const BUILD_TIMESTAMP: usize = 32;
+}
+
 fn main() {
-    match run() {
-        Ok(()) => {}
-        Err(e) => {
-            eprintln!("Failed to add CRC: {}", e);
-            process::exit(-1);
-        }
+    let matches = get_args();
+    match matches.subcommand() {
+        Some(("verify", matches)) => match verify(matches) {
+            Ok(true) => {}
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                eprintln!("Failed to verify CRC: {}", e);
+                process::exit(-1);
+            }
+        },
+        _ => match run(&matches) {
+            Ok(()) => {}
+            Err(e) => {
+                eprintln!("Failed to add CRC: {}", e);
+                process::exit(-1);
+            }
+        },
     }
 }
 
-fn run() -> Result<(), Box<dyn Error>> {
-    let input_path = get_input_path();
-    let mut binary: Vec<u8> = fs::read(&input_path)?;
-
-    let offsets = find_file_offsets(&binary)?;
+fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let input_path = matches.get_one::<PathBuf>("file").unwrap();
+    let mut file = InputFile::read(input_path)?;
+    let location = find_crc_location(&file)?;
+    let image = file.image_mut();
 
-    // Set the CRC to 0 but CRC valid to true
-    clear_crc(&mut binary, offsets.crc);
-    write_crc_valid(&mut binary, offsets.crc_valid);
+    match location.descriptor {
+        Some(offset) => {
+            let fields = DescriptorFields {
+                software_version: matches.get_one::<(u8, u8)>("software_version").copied(),
+                vcs_revision: matches.get_one::<u64>("vcs_revision").copied().unwrap_or(0),
+                build_timestamp: build_timestamp()?,
+            };
+            let crc = write_descriptor(image, offset, &fields)?;
+            println!("App descriptor at offset {:#x}", offset);
+            println!("Image size = {} bytes", image.len());
+            println!("VCS revision = {:#018x}", fields.vcs_revision);
+            println!("Build timestamp = {}", fields.build_timestamp);
+            println!("CRC = {:#x}", crc);
+        }
+        None => {
+            // Set the CRC to 0 but CRC valid to true
+            clear_crc(image, location.crc as u64);
+            write_crc_valid(image, location.crc_valid as u64);
 
-    // Calculate CRC of binary with CRC set to 0 but CRC valid set to true
-    let crc = crc_64_we(&binary);
-    println!("CRC = {:#x}", crc);
+            // Calculate CRC of binary with CRC set to 0 but CRC valid set to true
+            let crc = crc_64_we(image);
+            println!("CRC = {:#x}", crc);
 
-    write_crc(&mut binary, offsets.crc, crc);
+            write_crc(image, location.crc as u64, crc);
+        }
+    }
 
     // Overwrite: Create a temporary file, then rename
     let temporary_path = input_path.with_extension("tmp");
-    fs::write(&temporary_path, binary)?;
+    fs::write(&temporary_path, file.into_contents())?;
+    fs::set_permissions(&temporary_path, fs::metadata(input_path)?.permissions())?;
     fs::rename(temporary_path, input_path)?;
 
     Ok(())
 }
 
+/// Recalculates the CRC of an image and compares it to the CRC in the image
+///
+/// This returns false if the CRC is not valid.
+fn verify(matches: &ArgMatches) -> Result<bool, Box<dyn Error>> {
+    let input_path = matches.get_one::<PathBuf>("file").unwrap();
+    let file = InputFile::read(input_path)?;
+    let location = find_crc_location(&file)?;
+    match check_crc(file.image(), &location) {
+        Ok(crc) => {
+            println!("CRC = {:#x} (OK)", crc);
+            Ok(true)
+        }
+        Err(e) => {
+            println!("{}", e);
+            Ok(false)
+        }
+    }
+}
+
+/// Checks the CRC of an image and returns the CRC if it is correct
+fn check_crc(image: &[u8], location: &CrcLocation) -> Result<u64, StringError> {
+    if image[location.crc_valid] != 1 {
+        return Err(StringError("CRC has not been written".into()));
+    }
+    if let Some(offset) = location.descriptor {
+        let size_offset = offset + descriptor_offsets::IMAGE_SIZE;
+        let size = u32::from_le_bytes(image[size_offset..][..4].try_into().unwrap());
+        if size as usize != image.len() {
+            return Err(StringError(format!(
+                "Image size mismatch: app descriptor has {} bytes, image has {} bytes",
+                size,
+                image.len()
+            )));
+        }
+    }
+    let stored = u64::from_le_bytes(image[location.crc..][..8].try_into().unwrap());
+    let mut image = image.to_vec();
+    clear_crc(&mut image, location.crc as u64);
+    let calculated = crc_64_we(&image);
+    if stored == calculated {
+        Ok(calculated)
+    } else {
+        Err(StringError(format!(
+            "CRC mismatch: image has {:#x}, calculated {:#x}",
+            stored, calculated
+        )))
+    }
+}
+
 fn clear_crc(binary: &mut [u8], offset: u64) {
     binary[offset as usize..][..8].copy_from_slice(&[0u8; 8]);
 }
@@ -71,17 +203,219 @@ fn write_crc_valid(binary: &mut [u8], offset: u64) {
     binary[offset as usize] = 1;
 }
 
-struct FileOffsets {
-    pub crc: u64,
-    pub crc_valid: u64,
+/// A file that contains an image
+enum InputFile {
+    /// An ELF file without an app descriptor, where the image is the whole file
+    Elf(Vec<u8>),
+    /// An ELF file with an app descriptor, where the image is the content of the loadable
+    /// segments from the lowest physical address to the highest
+    ElfWithDescriptor(Vec<u8>, ElfSegments, FlatImage),
+    /// A raw binary file, where the image is the whole file
+    Raw(Vec<u8>),
+    /// An Intel HEX file, where the image is the data from the lowest address to the highest
+    /// address
+    IntelHex(HexFile, FlatImage),
+}
+
+impl InputFile {
+    /// Reads a file
+    ///
+    /// Files with the extension `.hex` or `.ihex` are Intel HEX files. Other files are ELF files
+    /// if they start with the ELF magic number, or raw binary files otherwise.
+    fn read(path: &Path) -> Result<InputFile, Box<dyn Error>> {
+        let contents = fs::read(path)?;
+        let is_hex = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("hex") || extension.eq_ignore_ascii_case("ihex")
+            });
+        if is_hex {
+            let text = String::from_utf8(contents)
+                .map_err(|_| StringError("HEX file is not valid text".into()))?;
+            let file = HexFile::parse(&text)?;
+            let image = file.to_image()?;
+            println!(
+                "HEX file data from {:#010x} to {:#010x}",
+                image.base,
+                u64::from(image.base) + image.bytes.len() as u64
+            );
+            Ok(InputFile::IntelHex(file, image))
+        } else if contents.starts_with(ELF_MAGIC) {
+            let has_descriptor = File::parse(&contents[..])?
+                .symbols()
+                .any(|symbol| symbol.name() == Ok(DESCRIPTOR_SYMBOL));
+            if has_descriptor {
+                let segments = ElfSegments::parse(&contents)?;
+                let image = segments.to_image(&contents)?;
+                println!(
+                    "ELF loadable segments from {:#010x} to {:#010x}",
+                    image.base,
+                    u64::from(image.base) + image.bytes.len() as u64
+                );
+                Ok(InputFile::ElfWithDescriptor(contents, segments, image))
+            } else {
+                Ok(InputFile::Elf(contents))
+            }
+        } else {
+            Ok(InputFile::Raw(contents))
+        }
+    }
+
+    /// Returns the bytes that the CRC covers
+    fn image(&self) -> &[u8] {
+        match self {
+            InputFile::Elf(contents) | InputFile::Raw(contents) => contents,
+            InputFile::ElfWithDescriptor(_, _, image) | InputFile::IntelHex(_, image) => {
+                &image.bytes
+            }
+        }
+    }
+
+    fn image_mut(&mut self) -> &mut [u8] {
+        match self {
+            InputFile::Elf(contents) | InputFile::Raw(contents) => contents,
+            InputFile::ElfWithDescriptor(_, _, image) | InputFile::IntelHex(_, image) => {
+                &mut image.bytes
+            }
+        }
+    }
+
+    /// Returns the contents of the file with any changes to the image
+    fn into_contents(self) -> Vec<u8> {
+        match self {
+            InputFile::Elf(contents) | InputFile::Raw(contents) => contents,
+            InputFile::ElfWithDescriptor(mut contents, segments, image) => {
+                segments.update(&image, &mut contents);
+                contents
+            }
+            InputFile::IntelHex(mut file, image) => {
+                file.update(&image);
+                file.to_text().into_bytes()
+            }
+        }
+    }
+}
+
+/// The offsets of the CRC and CRC valid flag from the beginning of an image
+struct CrcLocation {
+    crc: usize,
+    crc_valid: usize,
+    /// The offset of the app descriptor that contains the CRC, if any
+    descriptor: Option<usize>,
+}
+
+impl CrcLocation {
+    fn in_descriptor(offset: usize) -> Self {
+        CrcLocation {
+            crc: offset + descriptor_offsets::CRC,
+            crc_valid: offset + descriptor_offsets::CRC_VALID,
+            descriptor: Some(offset),
+        }
+    }
+}
+
+/// Finds the CRC in a file
+///
+/// In an ELF file, this uses the app descriptor symbol or the `CANADENSIS_CRC` and
+/// `CANADENSIS_CRC_VALID` symbols. In other files, this searches for the app descriptor signature.
+///
+/// The returned offsets are relative to the beginning of the image, not the file.
+fn find_crc_location(file: &InputFile) -> Result<CrcLocation, Box<dyn Error>> {
+    let location = match file {
+        InputFile::Elf(binary) => {
+            let in_object = File::parse(&binary[..])?;
+            CrcLocation {
+                crc: find_symbol_file_offset(&in_object, "CANADENSIS_CRC")? as usize,
+                crc_valid: find_symbol_file_offset(&in_object, "CANADENSIS_CRC_VALID")? as usize,
+                descriptor: None,
+            }
+        }
+        InputFile::ElfWithDescriptor(binary, segments, image) => {
+            let in_object = File::parse(&binary[..])?;
+            let file_offset = find_symbol_file_offset(&in_object, DESCRIPTOR_SYMBOL)?;
+            let offset = segments
+                .image_offset(image, file_offset as usize)
+                .ok_or_else(|| StringError("App descriptor is not in a loadable segment".into()))?;
+            CrcLocation::in_descriptor(offset)
+        }
+        InputFile::Raw(_) | InputFile::IntelHex(..) => {
+            CrcLocation::in_descriptor(find_descriptor_signature(file.image())?)
+        }
+    };
+    let end = (location.crc + 8).max(location.crc_valid + 1);
+    let descriptor_end = location
+        .descriptor
+        .map_or(0, |offset| offset + mem::size_of::<AppDescriptor>());
+    if end.max(descriptor_end) > file.image().len() {
+        return Err(Box::new(StringError(
+            "CRC extends past the end of the image".into(),
+        )));
+    }
+    Ok(location)
+}
+
+/// The values that this tool writes into an app descriptor, other than the image size and CRC
+struct DescriptorFields {
+    /// A software version that replaces the version in the descriptor
+    software_version: Option<(u8, u8)>,
+    vcs_revision: u64,
+    build_timestamp: u64,
+}
+
+/// Returns the offset of the one app descriptor signature in an image
+fn find_descriptor_signature(image: &[u8]) -> Result<usize, StringError> {
+    let mut matches = image
+        .windows(AppDescriptor::SIGNATURE.len())
+        .enumerate()
+        .filter(|(_, window)| *window == AppDescriptor::SIGNATURE)
+        .map(|(offset, _)| offset);
+    match (matches.next(), matches.next()) {
+        (Some(offset), None) => Ok(offset),
+        (Some(_), Some(_)) => Err(StringError(
+            "App descriptor signature found more than once".into(),
+        )),
+        (None, _) => Err(StringError(
+            "Not an ELF file, and no app descriptor signature found".into(),
+        )),
+    }
 }
 
-fn find_file_offsets(binary: &[u8]) -> Result<FileOffsets, Box<dyn Error>> {
-    let in_object = File::parse(binary)?;
-    Ok(FileOffsets {
-        crc: find_symbol_file_offset(&in_object, "CANADENSIS_CRC")?,
-        crc_valid: find_symbol_file_offset(&in_object, "CANADENSIS_CRC_VALID")?,
-    })
+/// Fills in the app descriptor at the provided offset and returns the CRC
+fn write_descriptor(
+    binary: &mut [u8],
+    offset: usize,
+    fields: &DescriptorFields,
+) -> Result<u64, StringError> {
+    use self::descriptor_offsets::*;
+
+    let image_size = u32::try_from(binary.len())
+        .map_err(|_| StringError("Binary is too large for an app descriptor".into()))?;
+    let descriptor = offset
+        .checked_add(mem::size_of::<AppDescriptor>())
+        .and_then(|end| binary.get_mut(offset..end))
+        .ok_or_else(|| StringError("App descriptor extends past the end of the file".into()))?;
+    if descriptor[..AppDescriptor::SIGNATURE.len()] != AppDescriptor::SIGNATURE {
+        return Err(StringError(format!(
+            "No app descriptor signature at offset {:#x}",
+            offset
+        )));
+    }
+
+    descriptor[CRC..][..8].copy_from_slice(&[0u8; 8]);
+    descriptor[IMAGE_SIZE..][..4].copy_from_slice(&image_size.to_le_bytes());
+    descriptor[CRC_VALID] = 1;
+    if let Some((major, minor)) = fields.software_version {
+        descriptor[VERSION_MAJOR] = major;
+        descriptor[VERSION_MINOR] = minor;
+    }
+    descriptor[VCS_REVISION..][..8].copy_from_slice(&fields.vcs_revision.to_le_bytes());
+    descriptor[BUILD_TIMESTAMP..][..8].copy_from_slice(&fields.build_timestamp.to_le_bytes());
+
+    // Calculate CRC of binary with CRC set to 0 but CRC valid set to true
+    let crc = crc_64_we(binary);
+    write_crc(binary, (offset + CRC) as u64, crc);
+    Ok(crc)
 }
 
 /// Finds a symbol with the provided name and returns the offset from the start of the binary
@@ -128,13 +462,75 @@ impl std::fmt::Display for StringError {
 
 impl Error for StringError {}
 
-fn get_input_path() -> PathBuf {
-    match env::args_os().nth(1) {
-        Some(path) => PathBuf::from(path),
-        None => {
-            eprintln!("Usage: canadensis_write_crc binary-path");
-            process::exit(-1);
-        }
+fn get_args() -> ArgMatches {
+    Command::new("canadensis_write_crc")
+        .version(clap::crate_version!())
+        .about("Calculates and writes the CRC and app descriptor of a software image")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .arg(
+            Arg::new("file")
+                .index(1)
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .help("The ELF, raw binary, or Intel HEX file to modify"),
+        )
+        .arg(
+            Arg::new("vcs_revision")
+                .long("vcs-revision")
+                .value_parser(parse_vcs_revision)
+                .help(
+                    "The version control revision to write into the app descriptor, in \
+                     hexadecimal. Only the first 16 digits of a longer value (like a git commit \
+                     hash) are used.",
+                ),
+        )
+        .arg(
+            Arg::new("software_version")
+                .long("software-version")
+                .value_parser(parse_software_version)
+                .help(
+                    "The software version (major.minor) to write into the app descriptor, \
+                     replacing the version that the application was compiled with",
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Checks the CRC of a software image without changing it")
+                .arg(
+                    Arg::new("file")
+                        .index(1)
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("The ELF, raw binary, or Intel HEX file to check"),
+                ),
+        )
+        .get_matches()
+}
+
+fn parse_vcs_revision(value: &str) -> Result<u64, String> {
+    let digits = value.get(..16).unwrap_or(value);
+    u64::from_str_radix(digits, 16).map_err(|e| format!("{}", e))
+}
+
+fn parse_software_version(value: &str) -> Result<(u8, u8), String> {
+    let (major, minor) = value
+        .split_once('.')
+        .ok_or_else(|| String::from("Expected major.minor"))?;
+    let major = major.parse().map_err(|e| format!("{}", e))?;
+    let minor = minor.parse().map_err(|e| format!("{}", e))?;
+    Ok((major, minor))
+}
+
+/// Returns the build time in seconds since the Unix epoch
+///
+/// For reproducible builds, this uses the `SOURCE_DATE_EPOCH` environment variable if it is set.
+fn build_timestamp() -> Result<u64, Box<dyn Error>> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| Box::new(StringError(format!("Invalid SOURCE_DATE_EPOCH: {}", e))).into()),
+        Err(_) => Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
     }
 }
 
@@ -161,3 +557,153 @@ fn pad_to_8_bytes(data: &[u8]) -> Cow<'_, [u8]> {
         Cow::Owned(data)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn image_with_descriptor(descriptor_offset: usize, length: usize) -> Vec<u8> {
+        let mut binary: Vec<u8> = (0..length).map(|i| i as u8).collect();
+        let descriptor = &mut binary[descriptor_offset..][..mem::size_of::<AppDescriptor>()];
+        descriptor.fill(0);
+        descriptor[..8].copy_from_slice(&AppDescriptor::SIGNATURE);
+        descriptor[descriptor_offsets::VERSION_MAJOR] = 3;
+        descriptor[descriptor_offsets::VERSION_MINOR] = 7;
+        binary
+    }
+
+    #[test]
+    fn raw_binary_descriptor() {
+        let mut binary = image_with_descriptor(100, 301);
+        assert_eq!(find_descriptor_signature(&binary).unwrap(), 100);
+        let fields = DescriptorFields {
+            software_version: None,
+            vcs_revision: 0x0123_4567_89ab_cdef,
+            build_timestamp: 1_700_000_000,
+        };
+        let crc = write_descriptor(&mut binary, 100, &fields).unwrap();
+
+        let descriptor = &binary[100..140];
+        assert_eq!(&descriptor[8..16], &crc.to_le_bytes());
+        assert_eq!(&descriptor[16..20], &301u32.to_le_bytes());
+        assert_eq!(&descriptor[20..24], &[1, 3, 7, 0]);
+        assert_eq!(&descriptor[24..32], &0x0123_4567_89ab_cdefu64.to_le_bytes());
+        assert_eq!(&descriptor[32..40], &1_700_000_000u64.to_le_bytes());
+
+        // The CRC covers the image with the CRC field set to zero
+        let mut check = binary.clone();
+        check[108..116].fill(0);
+        assert_eq!(crc_64_we(&check), crc);
+    }
+
+    #[test]
+    fn software_version_override() {
+        let mut binary = image_with_descriptor(0, 64);
+        let fields = DescriptorFields {
+            software_version: Some((10, 20)),
+            vcs_revision: 0,
+            build_timestamp: 0,
+        };
+        write_descriptor(&mut binary, 0, &fields).unwrap();
+        assert_eq!(&binary[21..23], &[10, 20]);
+    }
+
+    #[test]
+    fn raw_binary_signature_errors() {
+        let binary = vec![0u8; 64];
+        assert!(find_descriptor_signature(&binary).is_err());
+        let mut binary = image_with_descriptor(0, 128);
+        binary[64..72].copy_from_slice(&AppDescriptor::SIGNATURE);
+        assert!(find_descriptor_signature(&binary).is_err());
+        // Descriptor truncated by the end of the file
+        let mut binary = vec![0u8; 64];
+        binary[40..48].copy_from_slice(&AppDescriptor::SIGNATURE);
+        let fields = DescriptorFields {
+            software_version: None,
+            vcs_revision: 0,
+            build_timestamp: 0,
+        };
+        assert!(write_descriptor(&mut binary, 40, &fields).is_err());
+    }
+
+    #[test]
+    fn verify_raw_binary() {
+        let fields = DescriptorFields {
+            software_version: None,
+            vcs_revision: 0,
+            build_timestamp: 0,
+        };
+        let mut file = InputFile::Raw(image_with_descriptor(16, 203));
+        let location = find_crc_location(&file).unwrap();
+        assert!(check_crc(file.image(), &location).is_err());
+
+        let crc = write_descriptor(file.image_mut(), 16, &fields).unwrap();
+        assert_eq!(check_crc(file.image(), &location).unwrap(), crc);
+
+        file.image_mut()[200] ^= 1;
+        assert!(check_crc(file.image(), &location).is_err());
+        file.image_mut()[200] ^= 1;
+
+        // Image size in the descriptor does not match
+        let mut longer = file.image().to_vec();
+        longer.extend_from_slice(&[0; 8]);
+        assert!(check_crc(&longer, &location).is_err());
+    }
+
+    #[test]
+    fn intel_hex() {
+        // 176 bytes at 0x08000000 with the descriptor at offset 128, and no records for bytes
+        // 64..96
+        let image = image_with_descriptor(128, 176);
+        let mut text = String::from(":020000040800F2\n");
+        for (i, chunk) in image.chunks(16).enumerate() {
+            if i == 4 || i == 5 {
+                continue;
+            }
+            let mut record = vec![16u8, 0, (i * 16) as u8, 0];
+            record.extend_from_slice(chunk);
+            record.push(
+                record
+                    .iter()
+                    .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+                    .wrapping_neg(),
+            );
+            text.push(':');
+            text.extend(record.iter().map(|byte| format!("{:02X}", byte)));
+            text.push('\n');
+        }
+        text.push_str(":00000001FF\n");
+
+        let hex = HexFile::parse(&text).unwrap();
+        let flat = hex.to_image().unwrap();
+        assert_eq!(flat.base, 0x0800_0000);
+        assert_eq!(flat.bytes.len(), 176);
+        assert!(flat.bytes[64..96]
+            .iter()
+            .all(|&byte| byte == ihex::GAP_FILL));
+        let mut file = InputFile::IntelHex(hex, flat);
+        let location = find_crc_location(&file).unwrap();
+        assert_eq!(location.descriptor, Some(128));
+        let fields = DescriptorFields {
+            software_version: None,
+            vcs_revision: 1,
+            build_timestamp: 2,
+        };
+        let crc = write_descriptor(file.image_mut(), 128, &fields).unwrap();
+
+        let written = String::from_utf8(file.into_contents()).unwrap();
+        assert_eq!(written.lines().count(), text.lines().count());
+        let flat = HexFile::parse(&written).unwrap().to_image().unwrap();
+        assert_eq!(check_crc(&flat.bytes, &location).unwrap(), crc);
+    }
+
+    #[test]
+    fn vcs_revision() {
+        assert_eq!(parse_vcs_revision("abc1234"), Ok(0xabc1234));
+        assert_eq!(
+            parse_vcs_revision("0123456789abcdef0123456789abcdef01234567"),
+            Ok(0x0123_4567_89ab_cdef)
+        );
+        assert!(parse_vcs_revision("xyz").is_err());
+    }
+}